@@ -1,14 +1,26 @@
-use arrow_array::{Float32Array, StringArray};
+use arrow_array::{
+    ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator, StringArray,
+};
+use base64::Engine;
+use fastembed::{
+    InitOptionsUserDefined, Pooling, TextEmbedding, TokenizerFiles, UserDefinedEmbeddingModel,
+};
 use futures::StreamExt;
-use lancedb::query::{ExecutableQuery, QueryBase};
+use lancedb::arrow::arrow_schema::DataType;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use regex::Regex;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use tauri::{path::BaseDirectory, AppHandle, Emitter, Manager};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use tauri::{path::BaseDirectory, AppHandle, Emitter, Manager, Runtime};
+use tauri_plugin_opener::OpenerExt;
+use tracing::{debug, error, info, warn, Instrument};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // ==========================================
 // 1. 提示词 (Prompts)
@@ -80,6 +92,68 @@ const EXECUTOR_PROMPT: &str = r#"
 }
 "#;
 
+const SUGGESTION_PROMPT: &str = r#"
+你是法律检索顾问。用户的问题在本地法条库中没有检索到相关度足够的法条，请帮助用户改写搜索词。
+
+用户问题："{user_query}"
+
+要求：
+1. 给出 2-3 个改写后的搜索词，使用法律条文中常见的规范表述（例如将“被辞退”改为“解除劳动合同 经济补偿”）。
+2. 每个搜索词简短，不超过 20 个字。
+3. 不要回答问题本身。
+
+输出格式（仅 JSON 数组）：
+["搜索词1", "搜索词2"]
+"#;
+
+const QUERY_CLASSIFY_PROMPT: &str = r#"
+判断下面的法律检索输入属于哪一类，只输出类别名，不要解释：
+- citation：引用某部法规的具体条文，如“民法典第一千零四十三条”
+- law_name：查找某部法规本身，如“劳动合同法”“Civil Code”
+- semantic：描述问题或情形，需要按语义检索相关条文
+
+输入："{query}"
+"#;
+
+const NATIONAL_LOCAL_PROMPT: &str = r#"
+你是法律检索助手。下面是就同一问题检索到的全国性规定与{region}地方规定，请比较两者的差异。
+只依据给出的条文，不要补充条文以外的内容；每个比较点都要注明依据的条文编号。
+
+问题："{query}"
+
+全国性规定：
+{national}
+
+地方规定：
+{local}
+
+输出格式（仅 JSON）：
+{
+  "summary": "一两句话概括地方规定与全国性规定的主要差异；没有实质差异时说明一致",
+  "points": [
+    {
+      "aspect": "比较的事项，如“产假天数”",
+      "national": "全国性规定的内容",
+      "local": "地方规定的内容",
+      "national_ids": ["条文编号"],
+      "local_ids": ["条文编号"],
+      "divergent": true
+    }
+  ]
+}
+"#;
+
+const FOLDER_SUGGEST_PROMPT: &str = r#"
+用户刚收藏了一条法条，请从下面的收藏文件夹中选出最适合存放它的一个。
+只输出文件夹编号；都不合适时输出 0，不要解释。
+
+法条：《{law_name}》{article_number}（{category}）
+{content}
+
+文件夹：
+{folders}
+"#;
+
 // ==========================================
 // 2. 数据结构
 // ==========================================
@@ -88,6 +162,20 @@ pub struct UserFolder {
     id: i32,
     name: String,
     created_at: String,
+    // 上级文件夹，顶层为 None
+    parent_id: Option<i32>,
+}
+
+// 收藏时推荐的文件夹；只作提示，由用户点选后才加入
+#[derive(Serialize, Debug, Clone)]
+pub struct FolderSuggestion {
+    folder_id: i32,
+    folder_name: String,
+    // 0~1，越高越可信
+    confidence: f32,
+    reason: String,
+    // "rules"（按已有收藏打分）或 "ai"（大模型分类）
+    source: &'static str,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -105,12 +193,429 @@ pub struct AppSettings {
     pub chat_top_k: usize,
     #[serde(default = "default_max_loops")]
     pub max_agent_loops: i32,
+    // 为空时沿用内置默认值（深度模式 0.4，其余 0.3）
+    #[serde(default)]
+    pub chat_temperature: Option<f32>,
+    #[serde(default)]
+    pub chat_max_tokens: Option<u32>,
+    #[serde(default)]
+    pub chat_top_p: Option<f32>,
+    // 自定义提示词模版，需包含 {context} 占位符
+    #[serde(default)]
+    pub custom_deep_prompt: Option<String>,
+    #[serde(default)]
+    pub custom_simple_prompt: Option<String>,
+    // 部分企业网关不支持 stream=true，可强制走非流式
+    #[serde(default = "default_true")]
+    pub chat_streaming_enabled: bool,
+    // "openai" (默认)、"anthropic" 或 "ollama"
+    #[serde(default = "default_api_format")]
+    pub chat_api_format: String,
+    // "openai" (默认) 或 "ollama"
+    #[serde(default = "default_api_format")]
+    pub embedding_api_format: String,
+    // "api" (默认，走 HTTP 接口) 或 "local" (数据目录下的 ONNX 模型，完全离线)
+    #[serde(default = "default_embedding_provider")]
+    pub embedding_provider: String,
+    // 系统提示词（含上下文）的字数上限，0 表示不限制
+    #[serde(default = "default_context_char_budget")]
+    pub chat_context_char_budget: usize,
+    // 缓存最近几次对话的上下文用于“重新生成”，0 表示不缓存
+    #[serde(default = "default_chat_context_cache_size")]
+    pub chat_context_cache_size: usize,
+    // 检索结果全部不相关时拒绝作答，只给出搜索建议
+    #[serde(default)]
+    pub strict_grounding: bool,
+    // 出站请求代理，如 http://127.0.0.1:7890；为空时沿用系统环境变量
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    // 不走代理的主机，逗号分隔；localhost 与 127.0.0.1 始终直连
+    #[serde(default)]
+    pub no_proxy_hosts: Option<String>,
+    // 额外信任的 CA 证书（PEM，可含多张），用于企业网关重新签发 HTTPS 证书的环境；
+    // 系统证书库中的证书始终信任
+    #[serde(default)]
+    pub extra_ca_cert_path: Option<String>,
+    // 不校验 HTTPS 证书。仅作排查手段，开启后诊断中持续警告
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    #[serde(default = "default_embedding_timeout_secs")]
+    pub embedding_timeout_secs: u64,
+    // 流式输出时作为首包等待及两次数据包之间的最长间隔
+    #[serde(default = "default_chat_timeout_secs")]
+    pub chat_timeout_secs: u64,
+    // "error" / "warn" / "info" (默认) / "debug" / "trace"，保存后立即生效
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    // 日志中记录原始查询文本；默认只记录长度，避免案情细节落盘
+    #[serde(default)]
+    pub log_query_text: bool,
+    // 明文保存 API Key 到 settings.json（便携模式或系统无凭据管理器时使用）
+    #[serde(default)]
+    pub plaintext_api_keys: bool,
+    // 最近一次应用的模型服务配置方案名称
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    // 设置文件结构版本，缺省为 0（引入版本号之前写入的文件）
+    #[serde(default)]
+    pub settings_version: u32,
+    // 法规数据更新清单 (manifest JSON) 地址，为空时不检查更新
+    #[serde(default)]
+    pub content_update_url: Option<String>,
+    // 自定义引用格式模板，键为 "brief" / "full" / "markdown"，
+    // 可用占位符 {law_name} {short_name} {article} {content}
+    #[serde(default)]
+    pub citation_templates: HashMap<String, String>,
+    // 搜索历史最多保留的条数，写入时清理超出部分
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
+    // 缓存最近的检索结果（只缓存条文 ID 与距离），重复搜索时跳过嵌入与向量检索
+    #[serde(default = "default_true")]
+    pub enable_search_cache: bool,
+    // 记录本地使用统计（搜索、收藏、对话），数据只保存在 user_data.db
+    #[serde(default = "default_true")]
+    pub enable_usage_stats: bool,
+    // 启动时在后台发送一次嵌入请求，让 Ollama 等服务提前加载模型
+    #[serde(default)]
+    pub warmup_on_start: bool,
+    // 搜索结果列表中条文内容的预览字数，0 表示返回完整内容
+    #[serde(default)]
+    pub search_preview_chars: usize,
+    // 本地 HTTP API，只监听 127.0.0.1，请求需带 Authorization: Bearer <token>
+    #[serde(default)]
+    pub api_server_enabled: bool,
+    #[serde(default = "default_api_server_port")]
+    pub api_server_port: u16,
+    // 为空时在首次启动服务时自动生成
+    #[serde(default)]
+    pub api_server_token: String,
+    // 允许跨域访问的来源，如 http://localhost:3000；为空时不返回任何 CORS 头
+    #[serde(default)]
+    pub api_server_cors_origins: Vec<String>,
+    // 呼出快速查询窗口的全局快捷键，如 CommandOrControl+Shift+L；为空时不注册
+    #[serde(default = "default_quick_search_shortcut")]
+    pub quick_search_shortcut: String,
+    // 监听剪贴板中的法条引用并提示条文内容；剪贴板内容只在内存中比对，不落盘
+    #[serde(default)]
+    pub enable_clipboard_watch: bool,
+    // 收藏复习：开启后新收藏的条文自动加入复习计划
+    #[serde(default)]
+    pub enable_review_mode: bool,
+    // 工作区最多收集的条文数
+    #[serde(default = "default_workspace_limit")]
+    pub workspace_limit: usize,
+    // 检索前把口语说法替换为法律用语（内置规则 + 用户规则），引用与法规名查询不受影响
+    #[serde(default)]
+    pub enable_query_rewrite_rules: bool,
+    // 附加数据源（如合同范本库），与默认数据目录一同检索
+    #[serde(default)]
+    pub data_sources: Vec<DataSource>,
+    // content.db 列名映射，用于列名与标准格式不同的第三方数据集；未指定的列自动识别
+    #[serde(default)]
+    pub content_schema_map: ContentSchemaMap,
+    // 预读检索结果中法规全文的内存缓存上限（MB），0 表示不缓存
+    #[serde(default = "default_full_text_cache_mb")]
+    pub full_text_cache_mb: usize,
+    // 低内存模式：不预读、不缓存全文
+    #[serde(default)]
+    pub low_memory_mode: bool,
+}
+
+// 键为标准列名（如 law_name），值为数据集中的实际列名（如 title）
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ContentSchemaMap {
+    #[serde(default)]
+    pub chunks: HashMap<String, String>,
+    #[serde(default)]
+    pub full_texts: HashMap<String, String>,
+}
+
+// 某张表识别出的列映射；mapped 为 (标准列, 实际列)，defaulted 为缺失后按空串填充的可选列
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TableSchemaMapping {
+    pub table: String,
+    pub mapped: Vec<(String, String)>,
+    pub defaulted: Vec<String>,
+    pub missing_required: Vec<String>,
+}
+
+// 附加数据源：目录结构与数据目录相同（content.db + law_db.lancedb），
+// 相对路径的解析方式同 custom_data_path
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DataSource {
+    pub name: String,
+    pub path: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+// 数据源列表中的一项；primary 为默认数据目录（不能停用或删除）
+#[derive(Serialize, Debug, Clone)]
+pub struct DataSourceInfo {
+    pub name: String,
+    pub path: String,
+    pub enabled: bool,
+    pub primary: bool,
+    pub reachable: bool,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_embedding_timeout_secs() -> u64 {
+    30
+}
+
+fn default_chat_timeout_secs() -> u64 {
+    120
+}
+
+fn default_chat_context_cache_size() -> usize {
+    5
+}
+
+fn default_quick_search_shortcut() -> String {
+    "CommandOrControl+Shift+L".to_string()
+}
+
+fn default_api_server_port() -> u16 {
+    17860
+}
+
+fn default_history_limit() -> usize {
+    50
+}
+
+fn default_workspace_limit() -> usize {
+    50
+}
+
+fn default_full_text_cache_mb() -> usize {
+    32
+}
+
+fn default_context_char_budget() -> usize {
+    12000
+}
+
+fn default_api_format() -> String {
+    "openai".to_string()
+}
+
+fn default_embedding_provider() -> String {
+    "api".to_string()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 fn default_max_loops() -> i32 {
     5
 }
 
+// 导入设置的结果：逐字段报告，单个字段有误不影响其余字段
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SettingsImportReport {
+    pub applied: Vec<String>,
+    pub rejected: Vec<RejectedSetting>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RejectedSetting {
+    pub field: String,
+    pub reason: String,
+}
+
+// --- 首次运行诊断 ---
+// 单项检查：status 为 "ok" / "warn" / "fail"，field 指向需要修改的设置项
+#[derive(Serialize, Debug, Clone)]
+pub struct DiagnosticCheck {
+    pub code: String,
+    pub status: String,
+    pub message: String,
+    pub field: Option<String>,
+}
+
+impl DiagnosticCheck {
+    fn new(code: &str, status: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            status: status.to_string(),
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    fn ok(code: &str, message: impl Into<String>) -> Self {
+        Self::new(code, "ok", message)
+    }
+
+    fn warn(code: &str, message: impl Into<String>) -> Self {
+        Self::new(code, "warn", message)
+    }
+
+    fn fail(code: &str, message: impl Into<String>) -> Self {
+        Self::new(code, "fail", message)
+    }
+
+    fn with_field(mut self, field: &str) -> Self {
+        self.field = Some(field.to_string());
+        self
+    }
+
+    fn is_fail(&self) -> bool {
+        self.status == "fail"
+    }
+}
+
+// 安装模式信息；pending_mode 与 mode 不同时表示重启后才会生效
+#[derive(Serialize, Debug, Clone)]
+pub struct InstallModeInfo {
+    pub mode: String,
+    pub pending_mode: String,
+    pub settings_path: String,
+    pub user_db_path: String,
+}
+
+// data-path-changed 事件：切换后实际生效的数据目录
+#[derive(Serialize, Debug, Clone)]
+pub struct DataPathChangedEvent {
+    pub data_dir: String,
+    pub is_default: bool,
+}
+
+// data-dir-unreachable 事件：自定义数据目录无法访问（如网络盘断开），暂时使用默认目录
+#[derive(Serialize, Debug, Clone)]
+pub struct DataDirUnreachableEvent {
+    pub configured_path: String,
+    pub fallback_dir: String,
+}
+
+// 回退状态；reported 为 true 表示已经提示过前端，路径恢复或更换前不再重复提示
+#[derive(Debug, Clone)]
+pub struct DataDirFallback {
+    pub configured_path: PathBuf,
+    pub reported: bool,
+}
+
+// 实际使用的各个路径，供诊断页显示；custom_data_path 为解析相对路径后的自定义目录
+#[derive(Serialize, Debug, Clone)]
+pub struct EffectivePaths {
+    pub data_dir: String,
+    pub is_default: bool,
+    pub custom_data_path: Option<String>,
+    pub custom_data_path_reachable: bool,
+    pub settings_path: String,
+    pub user_db_path: String,
+    pub log_dir: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SetupDiagnostics {
+    // "portable" 或 "standard"
+    pub install_mode: String,
+    pub data_dir: String,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+// 模型服务配置方案：保存嵌入与对话服务相关字段，便于在多套服务之间切换
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SettingsProfile {
+    pub name: String,
+    pub embedding_provider: String,
+    pub embedding_base_url: String,
+    pub embedding_api_key: String,
+    pub embedding_model: String,
+    pub embedding_api_format: String,
+    pub chat_base_url: String,
+    pub chat_api_key: String,
+    pub chat_model: String,
+    pub chat_api_format: String,
+}
+
+impl SettingsProfile {
+    fn from_settings(name: &str, settings: &AppSettings) -> Self {
+        Self {
+            name: name.to_string(),
+            embedding_provider: settings.embedding_provider.clone(),
+            embedding_base_url: settings.embedding_base_url.clone(),
+            embedding_api_key: settings.embedding_api_key.clone(),
+            embedding_model: settings.embedding_model.clone(),
+            embedding_api_format: settings.embedding_api_format.clone(),
+            chat_base_url: settings.chat_base_url.clone(),
+            chat_api_key: settings.chat_api_key.clone(),
+            chat_model: settings.chat_model.clone(),
+            chat_api_format: settings.chat_api_format.clone(),
+        }
+    }
+
+    fn apply_to(self, settings: &mut AppSettings) {
+        settings.embedding_provider = self.embedding_provider;
+        settings.embedding_base_url = self.embedding_base_url;
+        settings.embedding_api_key = self.embedding_api_key;
+        settings.embedding_model = self.embedding_model;
+        settings.embedding_api_format = self.embedding_api_format;
+        settings.chat_base_url = self.chat_base_url;
+        settings.chat_api_key = self.chat_api_key;
+        settings.chat_model = self.chat_model;
+        settings.chat_api_format = self.chat_api_format;
+        settings.active_profile = Some(self.name);
+    }
+
+    // 方案中的 Key 与主设置一样存入凭据管理器，账户名带上方案名区分
+    fn key_accounts(name: &str) -> [String; 2] {
+        API_KEY_ACCOUNTS.map(|field| format!("profile:{}:{}", name, field))
+    }
+
+    fn key_fields(&mut self) -> [(String, &mut String); 2] {
+        let [embedding_account, chat_account] = Self::key_accounts(&self.name);
+        [
+            (embedding_account, &mut self.embedding_api_key),
+            (chat_account, &mut self.chat_api_key),
+        ]
+    }
+}
+
+// 设置校验结果：severity 为 "error" 时拒绝保存，"warning" 仅提示
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub severity: String,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn error(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            severity: "error".to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            severity: "warning".to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.severity == "error"
+    }
+}
+
+// 单次调用的采样参数覆盖（如“更详细地重新生成”）
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChatOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
@@ -126,15 +631,63 @@ impl Default for AppSettings {
             chat_model: "qwen3".to_string(),
             chat_top_k: 5,
             max_agent_loops: 5,
+            chat_temperature: None,
+            chat_max_tokens: None,
+            chat_top_p: None,
+            custom_deep_prompt: None,
+            custom_simple_prompt: None,
+            chat_streaming_enabled: true,
+            chat_api_format: default_api_format(),
+            embedding_api_format: default_api_format(),
+            embedding_provider: default_embedding_provider(),
+            chat_context_char_budget: default_context_char_budget(),
+            chat_context_cache_size: default_chat_context_cache_size(),
+            strict_grounding: false,
+            http_proxy: None,
+            no_proxy_hosts: None,
+            extra_ca_cert_path: None,
+            accept_invalid_certs: false,
+            embedding_timeout_secs: default_embedding_timeout_secs(),
+            chat_timeout_secs: default_chat_timeout_secs(),
+            log_level: default_log_level(),
+            log_query_text: false,
+            plaintext_api_keys: false,
+            active_profile: None,
+            settings_version: CURRENT_SETTINGS_VERSION,
+            content_update_url: None,
+            citation_templates: HashMap::new(),
+            history_limit: default_history_limit(),
+            enable_search_cache: true,
+            enable_usage_stats: true,
+            warmup_on_start: false,
+            search_preview_chars: 0,
+            api_server_enabled: false,
+            api_server_port: default_api_server_port(),
+            api_server_token: String::new(),
+            api_server_cors_origins: Vec::new(),
+            quick_search_shortcut: default_quick_search_shortcut(),
+            enable_clipboard_watch: false,
+            enable_review_mode: false,
+            workspace_limit: default_workspace_limit(),
+            enable_query_rewrite_rules: false,
+            data_sources: Vec::new(),
+            content_schema_map: ContentSchemaMap::default(),
+            full_text_cache_mb: default_full_text_cache_mb(),
+            low_memory_mode: false,
         }
     }
 }
 
-#[derive(Serialize, Debug)]
-struct LawNameSuggestion {
+#[derive(Serialize, Debug, Clone)]
+pub struct LawNameSuggestion {
     name: String,
     region: String,
     category: String,
+    // 英文名：来自法规库的 law_name_en 列，库中没有时取用户填写的译名
+    name_en: Option<String>,
+    // 所属的附加数据源，默认数据目录为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -150,6 +703,127 @@ pub struct LawChunk {
     pub article_number: String,
     region: String,
     source_file: String,
+    // 列表视图只带内容预览时为 true，完整内容通过 get_chunk_content 获取
+    #[serde(default)]
+    pub content_truncated: bool,
+    // 所属的附加数据源；默认数据目录中的条文为空。全文、预览等按它找到对应的数据库
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_name: Option<String>,
+}
+
+// 检索与收藏命令返回的内容形式：full 为完整内容，preview 截到句末并标记 content_truncated，
+// 展开时再调用 get_chunk_content / get_favorite_content。不传时沿用 search_preview_chars 设置
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentMode {
+    Full,
+    Preview,
+}
+
+// 条文对比结果中的一段：相邻的同类改动已合并
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DiffHunk {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+// 术语释义的来源：释义表（随法规库提供或用户导入）、条文中的定义句、语义检索
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionSource {
+    Curated,
+    Pattern,
+    Vector,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionConfidence {
+    High,
+    Medium,
+    Low,
+}
+
+// chunk 为给出定义的条文；释义表条目未注明出处时为空
+#[derive(Serialize, Debug, Clone)]
+pub struct TermDefinition {
+    pub term: String,
+    pub definition: String,
+    pub source: DefinitionSource,
+    pub confidence: DefinitionConfidence,
+    pub chunk: Option<LawChunk>,
+}
+
+// 法规中的一条定义句；definition 为包含定义的整句
+#[derive(Serialize, Debug, Clone)]
+pub struct DefinedTerm {
+    pub term: String,
+    pub definition: String,
+    pub article_number: String,
+    pub chunk_id: String,
+}
+
+// 释义导入文件中的一条
+#[derive(Deserialize, Debug)]
+struct TermDefinitionEntry {
+    term: String,
+    definition: String,
+    #[serde(default)]
+    law_name: Option<String>,
+    #[serde(default)]
+    article_number: Option<String>,
+}
+
+// 收藏复习卡片；masked_content 中本条条号替换为空格线，翻面前不显示 article_number
+#[derive(Serialize, Debug, Clone)]
+pub struct ReviewCard {
+    law_id: String,
+    law_name: String,
+    article_number: String,
+    content: String,
+    masked_content: String,
+    note: Option<String>,
+    schedule: ReviewSchedule,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ReviewSchedule {
+    ease: f64,
+    interval_days: f64,
+    repetitions: u32,
+    // 下次复习时间（Unix 秒）
+    due_at: i64,
+}
+
+// 全文阅读时的高亮批注；偏移为全文的 UTF-16 位置，与前端 JS 字符串下标一致
+#[derive(Serialize, Debug, Clone)]
+pub struct Annotation {
+    id: i64,
+    law_name: String,
+    start_offset: usize,
+    end_offset: usize,
+    // 创建时选中的原文，法规文本变化后据此重新定位
+    quote: String,
+    color: String,
+    note: Option<String>,
+    created_at: String,
+    // 文本变化后找不到原文，偏移已不可信
+    orphaned: bool,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct AnnotationReconcileReport {
+    checked: usize,
+    relocated: usize,
+    orphaned: Vec<i64>,
 }
 
 // 用户收藏结构体
@@ -161,1372 +835,16144 @@ pub struct UserFavorite {
     article_number: String,
     content: String,
     created_at: String,
-    tags: Option<String>,
+    tags: Vec<String>,
+    // 所在的第一个文件夹，兼容只认单个文件夹的调用方；未分类为 None
     folder_id: Option<i32>,
+    // 一条收藏可以同时放在多个文件夹中
+    folder_ids: Vec<i32>,
+    // 从导出文件导入、且本地法规库中没有对应条文时为 true，内容以导出文件为准
+    from_export: bool,
+    // 用户笔记 (Markdown)
+    note: Option<String>,
+    // 最近一次时效检查发现与当前法规库不一致（已修改或已删除）
+    stale: bool,
+    // 刷新内容前收藏时的原文
+    original_content: Option<String>,
+    // 按 preview 返回且内容被截断时为 true，完整内容通过 get_favorite_content 获取
+    #[serde(default)]
+    content_truncated: bool,
 }
 
+// 整部法规收藏，打开时走 get_full_text；与条文收藏相互独立
 #[derive(Serialize, Deserialize, Debug)]
-pub struct SearchHistoryItem {
+pub struct LawFavorite {
     id: i32,
-    query: String,
-    timestamp: i64,
+    law_name: String,
+    category: String,
+    region: String,
+    created_at: String,
+    folder_id: Option<i32>,
+    note: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CustomTemplate {
-    id: i32,
-    name: String,
-    content: String,
+// 收藏时效检查：status 为 "changed" (条文已修改) 或 "missing" (法规库中已找不到)
+#[derive(Serialize, Debug)]
+pub struct FavoriteFreshness {
+    pub law_id: String,
+    pub law_name: String,
+    pub article_number: String,
+    pub status: String,
+    pub current_content: Option<String>,
 }
 
+// 收藏导出文件：收藏按文件夹名称关联，导入时按名称重建文件夹
+const FAVORITES_EXPORT_FORMAT: &str = "lawvault-favorites";
+// v2 起包含整部法规收藏 (laws)，v3 起条文收藏可属于多个文件夹 (folders)
+const FAVORITES_EXPORT_VERSION: u32 = 3;
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct DraftMaterial {
-    id: i32,
-    law_id: String,
-    law_name: String,
-    article_number: String,
-    content: String,
-    added_at: String,
+pub struct FavoritesExport {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    pub folders: Vec<String>,
+    pub favorites: Vec<ExportedFavorite>,
+    #[serde(default)]
+    pub laws: Vec<ExportedLawFavorite>,
 }
 
-pub struct AppState {
-    pub settings: Mutex<AppSettings>,
-    pub settings_path: PathBuf,
-    pub app_data_dir: PathBuf,
-    // 存储 user_data.db 的路径，方便后续连接
-    pub user_db_path: PathBuf,
-    pub chat_tasks: Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>,
-    pub agent_abort_flags: Mutex<HashMap<String, Arc<AtomicBool>>>,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportedLawFavorite {
+    pub law_name: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
 }
 
-// --- Agent 相关结构 ---
-#[derive(Serialize, Clone, Debug)]
-pub struct AgentUpdateEvent {
-    pub step_type: String,
-    pub todo_list: Vec<String>,
-    pub completed_log: Vec<CompletedTask>,
-    pub current_task: Option<String>,
-    pub thought: Option<String>,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportedFavorite {
+    pub law_id: String,
+    pub law_name: String,
+    pub article_number: String,
+    pub content: String,
+    #[serde(default, deserialize_with = "deserialize_lenient_tags")]
+    pub tags: Vec<String>,
+    // 第一个文件夹，供 v2 及更早的版本读取
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
 }
 
-#[derive(Serialize, Clone, Debug)]
-pub struct CompletedTask {
-    pub task: String,
-    pub thought: String,
+// classify_and_search 的结果：按识别出的查询类型返回不同的结果
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClassifiedSearch {
+    Citation {
+        results: Vec<LawChunk>,
+    },
+    LawName {
+        results: Vec<LawNameSuggestion>,
+    },
+    // 嵌入服务不可用、结果来自关键词匹配时 degraded_reason 为原因
+    Semantic {
+        results: Vec<LawChunk>,
+        degraded_reason: Option<String>,
+    },
 }
 
-#[derive(Deserialize)]
-struct ExecutorResponse {
-    thought: String,
-    new_todo_list: Vec<String>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryKind {
+    Citation,
+    LawName,
+    Semantic,
 }
 
-// ==========================================
-// 3. 辅助函数
-// ==========================================
+// 手工编辑或其他工具生成的导出文件中，tags 可能是 null、逗号分隔的字符串或数组
+fn deserialize_lenient_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawTags {
+        List(Vec<Option<String>>),
+        Text(String),
+    }
 
-// 连接 content.db (法条库)
-fn connect_sqlite(data_dir: &std::path::Path) -> Result<Connection, String> {
-    let db_path_buf = data_dir.join("content.db");
-    let mut path_str = db_path_buf.to_string_lossy().to_string();
+    Ok(match Option::<RawTags>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(RawTags::List(tags)) => normalize_tags(tags.into_iter().flatten().collect()),
+        Some(RawTags::Text(text)) => parse_tags(Some(text)),
+    })
+}
 
-    #[cfg(windows)]
-    {
-        if path_str.starts_with(r"\\?\") {
-            path_str = path_str[4..].to_string();
-        }
-    }
+#[derive(Serialize, Debug)]
+pub struct FavoritesPage {
+    pub items: Vec<UserFavorite>,
+    // 当前筛选条件下的总数
+    pub total: usize,
+}
 
-    Connection::open(path_str).map_err(|e| format!("SQLite connect error: {}", e))
+// 批量操作结果：skipped 为未生效的条文（已收藏 / 未收藏）
+#[derive(Serialize, Debug, Default)]
+pub struct BulkResult {
+    pub affected: usize,
+    pub skipped: Vec<String>,
 }
 
-// 连接 user_data.db (用户库)
-fn connect_user_db(db_path: &PathBuf) -> Result<Connection, String> {
-    let conn = Connection::open(db_path).map_err(|e| format!("无法打开用户数据库: {}", e))?;
-    conn.execute("CREATE TABLE IF NOT EXISTS favorite_folders (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, created_at DATETIME DEFAULT CURRENT_TIMESTAMP)", []).map_err(|e| e.to_string())?;
-    conn.execute("CREATE TABLE IF NOT EXISTS favorites (id INTEGER PRIMARY KEY AUTOINCREMENT, law_id TEXT UNIQUE, law_name TEXT, article_number TEXT, content TEXT, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, tags TEXT)", []).map_err(|e| e.to_string())?;
+// 使用统计
+#[derive(Serialize, Debug)]
+pub struct DailyCount {
+    pub day: String,
+    pub count: usize,
+}
 
-    let column_exists: bool = conn
-        .prepare("PRAGMA table_info(favorites)")
-        .map_err(|e| e.to_string())?
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "folder_id")
-        })
-        .map_err(|e| e.to_string())?
-        .any(|res| res.unwrap_or(false));
-    if !column_exists {
-        conn.execute("ALTER TABLE favorites ADD COLUMN folder_id INTEGER", [])
-            .map_err(|e| e.to_string())?;
-    }
+#[derive(Serialize, Debug)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: usize,
+}
 
-    conn.execute("CREATE TABLE IF NOT EXISTS search_history (id INTEGER PRIMARY KEY AUTOINCREMENT, query TEXT UNIQUE, timestamp INTEGER)", []).map_err(|e| e.to_string())?;
+#[derive(Serialize, Debug)]
+pub struct UsageStats {
+    pub searches_per_day: Vec<DailyCount>,
+    // 检索结果中出现次数最多的法规
+    pub top_laws: Vec<NamedCount>,
+    // 重复搜索最多的查询
+    pub top_queries: Vec<NamedCount>,
+}
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS draft_materials (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            law_id TEXT UNIQUE,
-            law_name TEXT,
-            article_number TEXT,
-            content TEXT,
-            added_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )
-    .map_err(|e| e.to_string())?;
+#[derive(Serialize, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS custom_templates (
+#[derive(Serialize, Debug, Default)]
+pub struct FavoritesImportReport {
+    pub imported: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    // 本地已有同一条文但内容不同（merge 时保留本地版本）
+    pub conflicts: Vec<String>,
+    // 本地法规库中找不到的条文，已按导出文件内容导入
+    pub missing_locally: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchHistoryItem {
+    id: i32,
+    query: String,
+    timestamp: i64,
+    // "search" / "agent"；旧版本前端写入的记录为空
+    search_type: Option<String>,
+    result_count: Option<i64>,
+    // 置顶的记录不受条数上限清理
+    pinned: bool,
+    // 搜索次数
+    use_count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CustomTemplate {
+    id: i32,
+    name: String,
+    content: String,
+}
+
+// 普通搜索的结果；rewritten_query 为按改写规则实际检索的文本，未改写时为空
+#[derive(Serialize, Debug)]
+pub struct SearchLawResponse {
+    pub results: Vec<LawChunk>,
+    pub rewritten_query: Option<String>,
+    // 嵌入服务不可用、改用关键词检索时为 true，degraded_reason 为原因
+    pub degraded: bool,
+    pub degraded_reason: Option<String>,
+    // 结果来自检索缓存，未请求嵌入服务
+    pub cache_hit: bool,
+    // 已被更新的搜索请求取代，results 为空，前端应忽略本次响应
+    pub stale: bool,
+}
+
+// 全国性规定与地方规定对照：两栏检索结果按名次配对，comparison 为大模型生成的差异比较，
+// 未启用 AI 对话或生成失败时为空
+#[derive(Serialize, Debug)]
+pub struct NationalLocalComparison {
+    pub query: String,
+    pub region: String,
+    pub national: Vec<LawChunk>,
+    pub local: Vec<LawChunk>,
+    pub pairs: Vec<ProvisionPair>,
+    pub comparison: Option<ProvisionComparison>,
+    // 任一侧改用关键词检索时为原因
+    pub degraded_reason: Option<String>,
+}
+
+// 同一名次的全国性条文与地方条文，一侧结果较少时另一侧为空
+#[derive(Serialize, Debug)]
+pub struct ProvisionPair {
+    pub national_id: Option<String>,
+    pub local_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ProvisionComparison {
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub points: Vec<ComparisonPoint>,
+}
+
+// 一个比较点；*_ids 只保留本次检索结果中的条文编号
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComparisonPoint {
+    #[serde(default)]
+    pub aspect: String,
+    #[serde(default)]
+    pub national: String,
+    #[serde(default)]
+    pub local: String,
+    #[serde(default)]
+    pub national_ids: Vec<String>,
+    #[serde(default)]
+    pub local_ids: Vec<String>,
+    #[serde(default)]
+    pub divergent: bool,
+}
+
+// 检索所用的配置快照：设置副本与解析后的数据目录。
+// Agent 在开始时取一次，整个运行期间的检索都用它，中途修改设置不会混入另一个数据库的结果
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub settings: AppSettings,
+    pub data_dir: PathBuf,
+}
+
+impl SearchConfig {
+    fn capture(state: &AppState) -> Self {
+        let settings = snapshot_settings(state);
+        let data_dir = resolve_data_dir(state, &settings);
+        Self { settings, data_dir }
+    }
+
+    fn embedding(&self) -> EmbeddingConfig {
+        EmbeddingConfig::from_settings(&self.settings, &self.data_dir)
+    }
+}
+
+impl SearchLawResponse {
+    fn superseded() -> Self {
+        Self {
+            results: Vec::new(),
+            rewritten_query: None,
+            degraded: false,
+            degraded_reason: None,
+            cache_hit: false,
+            stale: true,
+        }
+    }
+}
+
+// 一次检索的结果；degraded 为改用关键词检索的原因，cache_hit 表示向量检索结果来自检索缓存
+#[derive(Debug)]
+pub struct SearchOutcome {
+    pub results: Vec<LawChunk>,
+    pub degraded: Option<String>,
+    pub cache_hit: bool,
+}
+
+// embedding-unavailable 事件：嵌入服务连不上，检索暂时改用关键词匹配
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingUnavailableEvent {
+    pub reason: String,
+}
+
+// 嵌入服务中断的记录；reported 为 true 表示已经提示过前端，恢复前不再重复提示
+#[derive(Debug, Clone)]
+pub struct EmbeddingOutage {
+    pub reason: String,
+    pub reported: bool,
+}
+
+// probe_embedding_service 的结果
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingProbeResult {
+    pub dimension: usize,
+    pub latency_ms: u64,
+}
+
+// 查询改写规则；to 为空表示删除该词（停用词）。内置规则没有 id，不能删除，
+// 用户添加同名规则即可覆盖
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryRewriteRule {
+    pub id: Option<i64>,
+    pub from: String,
+    pub to: String,
+    pub builtin: bool,
+}
+
+// 工作区变化（workspace-changed 事件），各窗口据此更新计数
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkspaceChangedEvent {
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DraftMaterial {
+    id: i32,
+    law_id: String,
+    law_name: String,
+    article_number: String,
+    content: String,
+    added_at: String,
+}
+
+pub struct AppState {
+    pub settings: RwLock<AppSettings>,
+    pub settings_path: PathBuf,
+    pub app_data_dir: PathBuf,
+    // 存储 user_data.db 的路径，方便后续连接
+    pub user_db_path: PathBuf,
+    pub chat_tasks: Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>,
+    pub agent_abort_flags: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    // 最近几次对话的上下文，供 regenerate_chat 复用
+    pub chat_contexts: Mutex<HashMap<String, CachedChatContext>>,
+    // 按代理设置构建的共享 HTTP 客户端，保存设置时重建
+    pub http_client: Mutex<reqwest::Client>,
+    // 嵌入维度与向量库是否一致，首次检索时校验，保存设置后清空
+    pub embedding_dimension_check: Mutex<Option<Result<(), AppError>>>,
+    // 向量库记录的嵌入模型与当前设置是否一致，清空时机同上
+    pub index_meta_check: Mutex<Option<IndexMetaCheck>>,
+    // 滚动日志所在目录（与 settings.json 同级的 logs/）
+    pub log_dir: PathBuf,
+    // 启动阶段的问题（如设置文件损坏），前端加载后拉取提示
+    pub startup_warnings: Mutex<Vec<String>>,
+    // settings.json 位于 exe 旁时为便携模式
+    pub portable_mode: bool,
+    pub exe_dir: PathBuf,
+    pub app_config_dir: PathBuf,
+    // 法规库统计（含向量库目录大小，网络盘上遍历较慢），切换数据目录或数据变动后清空
+    pub content_stats_cache: Mutex<Option<ContentStats>>,
+    // 最近的检索结果，数据目录、嵌入模型或法规数据变化时清空
+    pub search_result_cache: Mutex<HashMap<SearchCacheKey, CachedSearch>>,
+    // content.db / user_data.db 连接池，首次使用时建立连接
+    pub content_pool: Arc<SqlitePool>,
+    pub user_pool: Arc<SqlitePool>,
+    // 已打开的向量表及其数据目录，数据目录或向量数据变化时清空
+    pub vector_table: Mutex<Option<(PathBuf, lancedb::Table)>>,
+    // 正在执行的检索，相同查询的后续请求等待其结果而不是重复请求嵌入服务
+    pub search_in_flight: Mutex<HashMap<SearchCacheKey, InFlightSender>>,
+    // 前端最新一次搜索的请求编号，较早的请求完成后不再写入历史
+    pub latest_search_request: AtomicU64,
+    // 正在运行的本地 HTTP API 服务
+    pub api_server: Mutex<Option<ApiServerHandle>>,
+    // 通过链接启动程序时的跳转目标，前端初始化完成后取走
+    pub pending_navigation: Mutex<Option<NavigateTarget>>,
+    // 剪贴板监听线程，设置关闭或后台使用电池时停止
+    pub clipboard_watcher: Mutex<Option<ClipboardWatcher>>,
+    // 主窗口是否在前台，用于判断后台省电
+    pub main_window_focused: AtomicBool,
+    // 按法规缓存的定义句，法规数据变化时清空
+    pub defined_terms_cache: Mutex<HashMap<String, Vec<DefinedTerm>>>,
+    // 自定义数据目录无法访问、暂时回退到默认目录时的记录
+    pub data_dir_fallback: Mutex<Option<DataDirFallback>>,
+    // 附加数据源已打开的向量表，按目录缓存
+    pub source_vector_tables: Mutex<HashMap<PathBuf, lancedb::Table>>,
+    // 嵌入服务连接失败、检索降级为关键词匹配时的记录，恢复连接后清空
+    pub embedding_outage: Mutex<Option<EmbeddingOutage>>,
+    // 检索后预读的法规全文，打开阅读视图时直接返回；切换数据目录或数据变动后清空
+    pub full_text_cache: Mutex<HashMap<FullTextCacheKey, CachedFullText>>,
+    // 各命令的调用次数、失败次数与耗时
+    pub metrics: Arc<MetricsRegistry>,
+}
+
+// 法规全文 PDF 导出选项
+#[derive(Deserialize, Debug, Clone)]
+pub struct PdfOptions {
+    // 正文字号（磅），默认 11
+    #[serde(default = "default_pdf_font_size")]
+    pub font_size: f32,
+    // 标题下显示发布日期
+    #[serde(default = "default_true")]
+    pub include_publish_date: bool,
+    // 标题下额外的一行说明，如案号或“仅供参考”
+    #[serde(default)]
+    pub cover_line: Option<String>,
+    // 指定中文字体文件（.ttf/.otf），为空时自动查找
+    #[serde(default)]
+    pub font_path: Option<String>,
+}
+
+fn default_pdf_font_size() -> f32 {
+    11.0
+}
+
+// lawvault:// 链接解析后的跳转目标，随 navigate 事件发给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NavigateTarget {
+    Article { chunk: LawChunk },
+    Law { law: LawNameSuggestion },
+    Search { query: String },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeepLinkError {
+    url: String,
+    message: String,
+}
+
+pub struct ApiServerHandle {
+    port: u16,
+    shutdown: tokio::sync::oneshot::Sender<()>,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+pub struct ClipboardWatcher {
+    stop: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+// 剪贴板中识别到的引用及本地库中对应的条文
+#[derive(Serialize, Debug, Clone)]
+pub struct CitationDetectedEvent {
+    citation: String,
+    chunk: LawChunk,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiServerStatus {
+    running: bool,
+    port: Option<u16>,
+    token: String,
+}
+
+pub type InFlightSender = tokio::sync::watch::Sender<Option<Result<Vec<(String, f32)>, AppError>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchCacheKey {
+    pub query: String,
+    pub filter_region: Option<String>,
+    pub embedding_model: String,
+    pub top_k: usize,
+    // Agent 运行期间切换了数据目录时，旧目录的结果不能混入新目录的缓存
+    pub data_dir: PathBuf,
+}
+
+// 按向量距离排好序的 (chunk_id, distance)，使用时再从 content.db 补全内容
+#[derive(Debug, Clone)]
+pub struct CachedSearch {
+    pub hits: Vec<(String, f32)>,
+    pub used_at: Instant,
+}
+
+// --- 命令耗时统计 ---
+// 只保存在内存中，供诊断面板查看或复制到问题反馈，不会上传
+
+// 每个命令保留的耗时样本数，写满后循环覆盖最早的样本
+const METRICS_RESERVOIR_SIZE: usize = 256;
+
+#[derive(Default)]
+pub struct CommandMetrics {
+    count: u64,
+    error_count: u64,
+    // 耗时样本（微秒）
+    samples: Vec<u64>,
+    next_sample: usize,
+}
+
+impl CommandMetrics {
+    fn push_sample(&mut self, elapsed: Duration, failed: bool) {
+        let micros = elapsed.as_micros() as u64;
+        if self.samples.len() < METRICS_RESERVOIR_SIZE {
+            self.samples.push(micros);
+        } else {
+            self.samples[self.next_sample] = micros;
+        }
+        self.next_sample = (self.next_sample + 1) % METRICS_RESERVOIR_SIZE;
+        if failed {
+            self.error_count += 1;
+        }
+    }
+}
+
+pub struct MetricsRegistry {
+    commands: Mutex<HashMap<String, CommandMetrics>>,
+    since: Mutex<Instant>,
+}
+
+impl MetricsRegistry {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            commands: Mutex::new(HashMap::new()),
+            since: Mutex::new(Instant::now()),
+        })
+    }
+
+    // 每个命令执行完毕时记录一次，failed 取自命令返回的 Result
+    fn record(&self, command: &str, elapsed: Duration, failed: bool) {
+        let mut commands = self.commands.lock_or_recover();
+        let entry = commands.entry(command.to_string()).or_default();
+        entry.count += 1;
+        entry.push_sample(elapsed, failed);
+    }
+
+    fn snapshot(&self) -> MetricsReport {
+        let commands = self.commands.lock_or_recover();
+        let mut report: Vec<CommandMetricsSnapshot> = commands
+            .iter()
+            .map(|(name, metrics)| {
+                let mut sorted = metrics.samples.clone();
+                sorted.sort_unstable();
+                let percentile = |q: f64| {
+                    if sorted.is_empty() {
+                        return 0.0;
+                    }
+                    let index = ((sorted.len() - 1) as f64 * q).round() as usize;
+                    sorted[index] as f64 / 1000.0
+                };
+                CommandMetricsSnapshot {
+                    command: name.clone(),
+                    count: metrics.count,
+                    error_count: metrics.error_count,
+                    p50_ms: percentile(0.5),
+                    p95_ms: percentile(0.95),
+                    max_ms: sorted.last().map_or(0.0, |&m| m as f64 / 1000.0),
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.command.cmp(&b.command))
+        });
+        MetricsReport {
+            since_secs: self.since.lock_or_recover().elapsed().as_secs(),
+            commands: report,
+        }
+    }
+
+    fn reset(&self) {
+        self.commands.lock_or_recover().clear();
+        *self.since.lock_or_recover() = Instant::now();
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandMetricsSnapshot {
+    pub command: String,
+    pub count: u64,
+    // 按命令返回的 Result 判断；不返回 Result 的命令恒为 0
+    pub error_count: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricsReport {
+    // 距启动或上次重置的秒数
+    pub since_secs: u64,
+    pub commands: Vec<CommandMetricsSnapshot>,
+}
+
+// 全文缓存的键：(数据目录, 法规名)。按实际目录区分，切换数据目录后不会取到旧目录的全文
+pub type FullTextCacheKey = (PathBuf, String);
+
+pub struct CachedFullText {
+    pub text: String,
+    pub used_at: Instant,
+}
+
+// --- 错误类型 ---
+// 命令返回给前端的结构化错误：code 供前端区分处理（重试、离线提示等），
+// message 为面向用户的中文提示，detail 保留底层错误原文便于排查
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ErrorInfo {
+    pub message: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    Network(ErrorInfo),
+    EmbeddingApi(ErrorInfo),
+    ChatApi(ErrorInfo),
+    VectorDb(ErrorInfo),
+    ContentDb(ErrorInfo),
+    UserDb(ErrorInfo),
+    Validation(ErrorInfo),
+    NotFound(ErrorInfo),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Network(_) => "NETWORK",
+            AppError::EmbeddingApi(_) => "EMBEDDING_API",
+            AppError::ChatApi(_) => "CHAT_API",
+            AppError::VectorDb(_) => "VECTOR_DB",
+            AppError::ContentDb(_) => "CONTENT_DB",
+            AppError::UserDb(_) => "USER_DB",
+            AppError::Validation(_) => "VALIDATION",
+            AppError::NotFound(_) => "NOT_FOUND",
+        }
+    }
+
+    fn info(&self) -> &ErrorInfo {
+        match self {
+            AppError::Network(info)
+            | AppError::EmbeddingApi(info)
+            | AppError::ChatApi(info)
+            | AppError::VectorDb(info)
+            | AppError::ContentDb(info)
+            | AppError::UserDb(info)
+            | AppError::Validation(info)
+            | AppError::NotFound(info) => info,
+        }
+    }
+
+    fn info_mut(&mut self) -> &mut ErrorInfo {
+        match self {
+            AppError::Network(info)
+            | AppError::EmbeddingApi(info)
+            | AppError::ChatApi(info)
+            | AppError::VectorDb(info)
+            | AppError::ContentDb(info)
+            | AppError::UserDb(info)
+            | AppError::Validation(info)
+            | AppError::NotFound(info) => info,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.info().message
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.info().detail.as_deref()
+    }
+
+    pub fn with_detail(mut self, detail: impl std::fmt::Display) -> Self {
+        self.info_mut().detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        AppError::Network(ErrorInfo::new(message))
+    }
+
+    pub fn embedding_api(message: impl Into<String>) -> Self {
+        AppError::EmbeddingApi(ErrorInfo::new(message))
+    }
+
+    pub fn chat_api(message: impl Into<String>) -> Self {
+        AppError::ChatApi(ErrorInfo::new(message))
+    }
+
+    pub fn vector_db(message: impl Into<String>) -> Self {
+        AppError::VectorDb(ErrorInfo::new(message))
+    }
+
+    pub fn content_db(message: impl Into<String>) -> Self {
+        AppError::ContentDb(ErrorInfo::new(message))
+    }
+
+    pub fn user_db(message: impl Into<String>) -> Self {
+        AppError::UserDb(ErrorInfo::new(message))
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation(ErrorInfo::new(message))
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound(ErrorInfo::new(message))
+    }
+
+    // 底层库错误的包装：统一提示语 + 原始错误
+    pub fn vector_db_error(e: impl std::fmt::Display) -> Self {
+        Self::vector_db("向量数据库读取失败").with_detail(e)
+    }
+
+    pub fn content_db_error(e: impl std::fmt::Display) -> Self {
+        Self::content_db("法条数据库读取失败").with_detail(e)
+    }
+
+    pub fn user_db_error(e: impl std::fmt::Display) -> Self {
+        Self::user_db("用户数据读写失败").with_detail(e)
+    }
+}
+
+impl ErrorInfo {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            detail: None,
+        }
+    }
+}
+
+// 日志输出格式固定为 "提示语: 原始错误"
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.detail() {
+            Some(detail) => write!(f, "{}: {}", self.message(), detail),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// 本地 HTTP API 按错误类型返回状态码，响应体与前端收到的 { code, message, detail } 一致
+impl axum::response::IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        use axum::http::StatusCode;
+        let status = match self {
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Network(_) | AppError::EmbeddingApi(_) | AppError::ChatApi(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, axum::Json(self)).into_response()
+    }
+}
+
+// 序列化为 { code, message, detail }
+impl Serialize for AppError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("detail", &self.detail())?;
+        state.end()
+    }
+}
+
+// 仍返回 String 的命令可以直接用 ? 传递 AppError
+impl From<AppError> for String {
+    fn from(e: AppError) -> Self {
+        e.to_string()
+    }
+}
+
+// --- Agent 相关结构 ---
+#[derive(Serialize, Clone, Debug)]
+pub struct AgentUpdateEvent {
+    pub step_type: String,
+    pub todo_list: Vec<String>,
+    pub completed_log: Vec<CompletedTask>,
+    pub current_task: Option<String>,
+    pub thought: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CompletedTask {
+    pub task: String,
+    pub thought: String,
+}
+
+#[derive(Deserialize)]
+struct ExecutorResponse {
+    thought: String,
+    new_todo_list: Vec<String>,
+}
+
+// --- 本地嵌入模型 ---
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelDownloadProgress {
+    pub file: String,
+    pub file_index: usize,
+    pub file_count: usize,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LocalModelStatus {
+    pub available: bool,
+    pub path: String,
+    pub dimension: Option<usize>,
+    pub missing_files: Vec<String>,
+}
+
+// --- 法规数据更新 ---
+// 更新清单：url 指向 zip 包，包内根目录为 content.db 与 law_db.lancedb
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContentManifest {
+    pub version: String,
+    pub url: String,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    // 构建向量库所用的嵌入模型与维度；更新包自带 index_meta.json 时以其为准
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub embedding_dimension: Option<usize>,
+}
+
+// 向量库的构建信息，存于 law_db.lancedb/index_meta.json，随向量库一起替换
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexMeta {
+    pub embedding_model: String,
+    pub dimension: usize,
+    pub metric: String,
+    pub built_at: String,
+}
+
+// 向量库与当前嵌入模型不一致（index-model-mismatch 事件）
+#[derive(Serialize, Debug, Clone)]
+pub struct IndexModelMismatch {
+    pub index_model: String,
+    pub configured_model: String,
+    pub built_at: String,
+}
+
+// 首次检索时的校验结果；reported 为 true 表示已经提示过前端
+#[derive(Debug, Clone)]
+pub struct IndexMetaCheck {
+    pub mismatch: Option<IndexModelMismatch>,
+    pub reported: bool,
+}
+
+// 与 content.db 同目录的版本文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContentVersion {
+    pub version: String,
+    pub installed_at: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ContentUpdateInfo {
+    pub current_version: Option<String>,
+    pub latest_version: String,
+    pub size: u64,
+    pub published_at: Option<String>,
+    pub notes: Option<String>,
+    pub has_update: bool,
+}
+
+// stage: "download" / "verify" / "extract" / "install" / "done"
+#[derive(Serialize, Clone, Debug)]
+pub struct ContentUpdateProgress {
+    pub stage: String,
+    pub downloaded: u64,
+    pub total: u64,
+}
+
+// --- 用户导入法规 ---
+#[derive(Deserialize, Debug, Clone)]
+pub struct LawImportMeta {
+    // 为空时取文件名
+    #[serde(default)]
+    pub law_name: Option<String>,
+    pub category: String,
+    pub region: String,
+    pub publish_date: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportedLaw {
+    pub law_name: String,
+    pub category: String,
+    pub region: String,
+    pub publish_date: String,
+    pub article_count: usize,
+    pub imported_at: String,
+}
+
+// law-import-progress 事件；stage: "parsed" / "embedding" / "saving" / "done"
+#[derive(Serialize, Clone, Debug)]
+pub struct LawImportProgress {
+    pub law_name: String,
+    pub stage: String,
+    pub total: usize,
+    pub embedded: usize,
+}
+
+// 按条切分后的一条法条
+struct ParsedArticle {
+    part: String,
+    chapter: String,
+    article_number: String,
+    content: String,
+}
+
+// --- 法规浏览 ---
+#[derive(Serialize, Debug, Clone)]
+pub struct LawBrowseItem {
+    pub law_name: String,
+    pub category: String,
+    pub region: String,
+    pub publish_date: Option<String>,
+    pub article_count: usize,
+    // 整部法规是否已收藏
+    pub is_favorite: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LawBrowsePage {
+    pub total: usize,
+    pub items: Vec<LawBrowseItem>,
+}
+
+// --- 法规目录与全文 ---
+// level: 1 编 / 2 章 / 3 节；first_article 为该标题下的第一条
+#[derive(Serialize, Debug, Clone)]
+pub struct TocNode {
+    pub title: String,
+    pub level: u8,
+    pub first_article: Option<String>,
+    pub chunk_id: Option<String>,
+    pub children: Vec<TocNode>,
+}
+
+// 默认返回全文字符串；structured 模式返回按原文顺序排列的条文列表
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum FullText {
+    Raw(String),
+    Structured(Vec<LawChunk>),
+}
+
+// 全文内关键词检索的一处命中；start/end 为全文中的字节偏移
+#[derive(Serialize, Debug, Clone)]
+pub struct FullTextMatch {
+    pub start: usize,
+    pub end: usize,
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+    pub article_number: Option<String>,
+    pub chunk_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FullTextSearchResult {
+    pub law_name: String,
+    pub matches: Vec<FullTextMatch>,
+    // 命中数超过上限时只返回前 FULL_TEXT_MATCH_LIMIT 处
+    pub truncated: bool,
+}
+
+// --- 数据库统计 ---
+#[derive(Serialize, Debug, Clone)]
+pub struct StatGroup {
+    pub name: String,
+    pub law_count: usize,
+    pub chunk_count: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ContentStats {
+    pub law_count: usize,
+    pub chunk_count: usize,
+    pub full_text_count: usize,
+    pub earliest_publish_date: Option<String>,
+    pub latest_publish_date: Option<String>,
+    pub by_category: Vec<StatGroup>,
+    pub by_region: Vec<StatGroup>,
+    pub content_db_bytes: u64,
+    pub vector_db_bytes: u64,
+}
+
+// 收藏与历史计数每次实时查询，不进缓存
+#[derive(Serialize, Debug, Clone)]
+pub struct DbStats {
+    #[serde(flatten)]
+    pub content: ContentStats,
+    pub favorite_count: usize,
+    pub history_count: usize,
+}
+
+// 数据一致性检查结果；列表最多返回 INTEGRITY_SAMPLE_LIMIT 条，数量字段为实际总数
+#[derive(Serialize, Debug)]
+pub struct IntegrityReport {
+    pub vector_count: usize,
+    pub chunk_count: usize,
+    pub orphan_vector_count: usize,
+    pub orphan_vectors: Vec<String>,
+    pub missing_vector_count: usize,
+    // 缺少向量、需要重新嵌入的条文
+    pub chunks_missing_vectors: Vec<String>,
+    pub laws_without_chunks: Vec<String>,
+    pub repaired_vectors: usize,
+}
+
+// --- 向量库重建 ---
+// vector-rebuild-progress 事件
+#[derive(Serialize, Clone, Debug)]
+pub struct VectorRebuildProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub percent: f32,
+    pub eta_secs: Option<u64>,
+    pub current_law: String,
+}
+
+// --- 数据库状态 ---
+// 各项检查互不影响，某项失败时其余字段照常填写；*_error 为该项失败的原文
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DbStatus {
+    pub data_dir: String,
+    pub data_dir_exists: bool,
+    pub lancedb_present: bool,
+    pub table_openable: bool,
+    pub row_count: Option<usize>,
+    pub vector_error: Option<String>,
+    pub content_db_present: bool,
+    pub chunks_count: Option<i64>,
+    pub content_error: Option<String>,
+    // 表结构问题，如缺少列、向量表没有向量列
+    pub schema_issues: Vec<String>,
+    pub ready: bool,
+}
+
+// --- 检索评测 ---
+// 评测用例：查询与期望命中的条文；用户文件为同样字段的 JSON 数组
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BenchmarkCase {
+    pub query: String,
+    pub law_name: String,
+    pub article_number: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchmarkCaseResult {
+    pub query: String,
+    pub expected: String,
+    // 期望条文在结果中的名次（从 1 开始），未命中为 None
+    pub rank: Option<usize>,
+    // 检索出错时的原因，该用例计为未命中
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RetrievalBenchmarkReport {
+    pub embedding_provider: String,
+    pub embedding_model: String,
+    pub top_k: usize,
+    pub total: usize,
+    pub hit_at_5: f32,
+    pub hit_at_10: f32,
+    pub mrr: f32,
+    pub elapsed_ms: u64,
+    pub cases: Vec<BenchmarkCaseResult>,
+    // 写入的 markdown 摘要路径，写入失败时为 None
+    pub report_path: Option<String>,
+}
+
+// retrieval-benchmark-progress 事件
+#[derive(Serialize, Clone, Debug)]
+pub struct BenchmarkProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub current_query: String,
+}
+
+// --- 条文互引索引 ---
+// cross-ref-progress 事件
+#[derive(Serialize, Clone, Debug)]
+pub struct CrossRefProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub current_law: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CrossRefIndexStats {
+    // 本次新扫描的法规数，已扫描过的法规不会重复处理
+    pub scanned_laws: usize,
+    pub new_edges: usize,
+    // 之前无法解析、本次找到目标条文的引用（目标法规后来才导入）
+    pub resolved_pending: usize,
+    // 库中仍找不到目标条文的引用
+    pub unresolved: usize,
+}
+
+// 断点文件：记录已写入新表的最后一行 rowid，模型配置不变时可续跑
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct RebuildCheckpoint {
+    fingerprint: String,
+    last_rowid: i64,
+}
+
+// --- Chat 上下文与引用 ---
+// 前端传入的上下文条文，兼容 LawChunk (id) 与 DraftMaterial (law_id)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatContextChunk {
+    #[serde(alias = "law_id")]
+    pub id: String,
+    pub law_name: String,
+    pub article_number: String,
+    pub content: String,
+    // 检索时的向量距离；素材库条文没有距离，视为相关
+    #[serde(default, alias = "_distance")]
+    pub distance: Option<f32>,
+}
+
+// 严格依据模式下检索结果均不相关时的回应 ({event_id}-ungrounded)
+#[derive(Serialize, Clone, Debug)]
+pub struct UngroundedEvent {
+    pub message: String,
+    pub suggestions: Vec<String>,
+}
+
+// 一次对话调用的完整输入，缓存后可在不重新检索的情况下重新生成
+#[derive(Clone, Debug)]
+pub struct ChatRequest {
+    pub query: String,
+    pub mode: String,
+    pub chunks: Vec<ChatContextChunk>,
+    pub options: ChatOptions,
+    pub style_hint: Option<String>,
+}
+
+pub struct CachedChatContext {
+    pub request: ChatRequest,
+    pub created_at: Instant,
+}
+
+// 回答中的 [n] 编号引用
+#[derive(Serialize, Clone, Debug)]
+pub struct ChatReference {
+    pub number: usize,
+    pub chunk_id: Option<String>,
+    pub law_name: Option<String>,
+    pub article_number: Option<String>,
+    pub valid: bool,
+}
+
+// 回答正文中的《XX法》第X条引用，start/end 为 UTF-16 偏移，前端可直接 slice
+#[derive(Serialize, Clone, Debug)]
+pub struct ChatCitation {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub law_name: String,
+    pub article_number: String,
+    pub exists: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChatCitationsEvent {
+    pub references: Vec<ChatReference>,
+    pub citations: Vec<ChatCitation>,
+}
+
+// --- 引用核对 ---
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationCheckStatus {
+    Found,
+    ArticleMissing,
+    LawNotFound,
+}
+
+// start/end 为 UTF-16 偏移；matched_law_name 为库中匹配到的完整法名，
+// chunk 仅在 found 时给出，便于逐条核对原文
+#[derive(Serialize, Debug, Clone)]
+pub struct CitationCheck {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub law_name: String,
+    pub article_number: String,
+    pub status: CitationCheckStatus,
+    pub matched_law_name: Option<String>,
+    pub chunk: Option<LawChunk>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CitationCheckReport {
+    pub citations: Vec<CitationCheck>,
+    pub found: usize,
+    pub article_missing: usize,
+    pub law_not_found: usize,
+}
+
+// --- 设置变更事件 (settings-changed) ---
+// 只携带变更的字段名，前端按需重新拉取设置
+#[derive(Serialize, Clone, Debug)]
+pub struct SettingsChangedEvent {
+    pub changed_fields: Vec<String>,
+}
+
+// --- Chat 流结束事件 ({event_id}-done) ---
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ChatDoneEvent {
+    pub finish_reason: Option<String>,
+    pub total_chars: usize,
+    pub usage: Option<serde_json::Value>,
+    // 实际纳入上下文 / 因字数预算被丢弃的条文数
+    pub included_chunks: usize,
+    pub dropped_chunks: usize,
+}
+
+// ==========================================
+// 3. 辅助函数
+// ==========================================
+
+// 交给 rusqlite / lancedb 的路径统一经过这里：去掉 Windows 扩展长度前缀
+// (\\?\C:\... -> C:\...，\\?\UNC\server\share -> \\server\share)，两者都不识别该前缀
+fn normalize_db_path(p: &std::path::Path) -> String {
+    let path_str = p.to_string_lossy();
+    if cfg!(windows) {
+        return strip_extended_length_prefix(&path_str);
+    }
+    path_str.into_owned()
+}
+
+fn strip_extended_length_prefix(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", rest);
+    }
+    path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+}
+
+// 连接 content.db (法条库)，按当前设置中的 content_schema_map 映射列名
+fn connect_sqlite(state: &AppState, data_dir: &std::path::Path) -> Result<Connection, AppError> {
+    let schema = state
+        .settings
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .content_schema_map
+        .clone();
+    open_content_db(data_dir, &schema)
+}
+
+// 不依赖 AppState 的连接方式，供后台线程与测试使用
+#[tracing::instrument(level = "debug", skip_all)]
+fn open_content_db(
+    data_dir: &std::path::Path,
+    schema: &ContentSchemaMap,
+) -> Result<Connection, AppError> {
+    let conn = Connection::open(normalize_db_path(&data_dir.join("content.db")))
+        .map_err(AppError::content_db_error)?;
+    if let Err(e) = apply_content_schema(&conn, schema) {
+        warn!("Failed to map content.db schema in {:?}: {}", data_dir, e);
+    }
+    Ok(conn)
+}
+
+// --- content.db 列映射 ---
+// 第三方数据集的列名可能不同（如 title 代替 law_name、没有 part 列）。
+// 列名不标准时在连接上建同名临时视图，把实际列映射为标准列名；SQLite 先查找 temp 中的对象，
+// 其余查询无需改动。映射后的数据集只读，导入法规等写操作不可用
+
+// (标准列, 是否必需, 常见别名)
+type SchemaSpec = &'static [(&'static str, bool, &'static [&'static str])];
+
+const CHUNKS_SCHEMA: SchemaSpec = &[
+    ("id", true, &["chunk_id", "uid", "uuid"]),
+    (
+        "content",
+        true,
+        &["text", "article_content", "body", "article_text"],
+    ),
+    ("law_name", true, &["title", "name", "law_title", "law"]),
+    (
+        "category",
+        false,
+        &["type", "law_type", "level", "law_level"],
+    ),
+    (
+        "region",
+        false,
+        &["area", "province", "location", "jurisdiction"],
+    ),
+    (
+        "publish_date",
+        false,
+        &["date", "pub_date", "release_date", "promulgation_date"],
+    ),
+    ("part", false, &["book", "part_name"]),
+    ("chapter", false, &["chapter_name", "chapter_title"]),
+    (
+        "article_number",
+        true,
+        &["article", "article_no", "article_num", "clause"],
+    ),
+];
+
+const FULL_TEXTS_SCHEMA: SchemaSpec = &[
+    ("law_name", true, &["title", "name", "law_title", "law"]),
+    (
+        "region",
+        false,
+        &["area", "province", "location", "jurisdiction"],
+    ),
+    (
+        "category",
+        false,
+        &["type", "law_type", "level", "law_level"],
+    ),
+    ("full_text", true, &["content", "text", "body"]),
+];
+
+// 只看 main 中的原表，不受已建临时视图影响；表不存在时返回空
+fn main_table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare(&format!("PRAGMA main.table_info({})", table))?
+        .query_map([], |row| row.get(1))?
+        .collect()
+}
+
+fn map_table_schema(
+    table: &str,
+    actual: &[String],
+    spec: SchemaSpec,
+    overrides: &HashMap<String, String>,
+) -> TableSchemaMapping {
+    let has = |name: &str| actual.iter().any(|c| c.eq_ignore_ascii_case(name));
+    let mut mapping = TableSchemaMapping {
+        table: table.to_string(),
+        ..Default::default()
+    };
+    let mut used: Vec<&str> = Vec::new();
+    for (column, required, aliases) in spec {
+        let source = overrides
+            .get(*column)
+            .map(String::as_str)
+            .filter(|name| has(name))
+            .or_else(|| has(column).then_some(*column))
+            .or_else(|| {
+                aliases
+                    .iter()
+                    .copied()
+                    .find(|alias| has(alias) && !used.contains(alias))
+            });
+        match source {
+            Some(source) => {
+                used.push(source);
+                if source != *column {
+                    mapping
+                        .mapped
+                        .push((column.to_string(), source.to_string()));
+                }
+            }
+            None if *required => mapping.missing_required.push(column.to_string()),
+            None => mapping.defaulted.push(column.to_string()),
+        }
+    }
+    mapping
+}
+
+// 识别 chunks 与 full_texts 的列映射，overrides 为设置中的显式映射；不存在的表不列出
+fn detect_content_schema(
+    conn: &Connection,
+    overrides: &ContentSchemaMap,
+) -> rusqlite::Result<Vec<TableSchemaMapping>> {
+    let mut mappings = Vec::new();
+    for (table, spec, table_overrides) in [
+        ("chunks", CHUNKS_SCHEMA, &overrides.chunks),
+        ("full_texts", FULL_TEXTS_SCHEMA, &overrides.full_texts),
+    ] {
+        let actual = main_table_columns(conn, table)?;
+        if !actual.is_empty() {
+            mappings.push(map_table_schema(table, &actual, spec, table_overrides));
+        }
+    }
+    Ok(mappings)
+}
+
+// 设置变化后已打开的连接由 invalidate_content_caches 清理，新连接按新映射建视图
+fn apply_content_schema(conn: &Connection, overrides: &ContentSchemaMap) -> rusqlite::Result<()> {
+    for mapping in detect_content_schema(conn, overrides)? {
+        if mapping.mapped.is_empty() && mapping.defaulted.is_empty() {
+            continue;
+        }
+        if !mapping.missing_required.is_empty() {
+            warn!(
+                "content.db table {} lacks required columns {:?}, leaving it unmapped",
+                mapping.table, mapping.missing_required
+            );
+            continue;
+        }
+        let spec = if mapping.table == "chunks" {
+            CHUNKS_SCHEMA
+        } else {
+            FULL_TEXTS_SCHEMA
+        };
+        let actual = main_table_columns(conn, &mapping.table)?;
+        let mut select: Vec<String> = spec
+            .iter()
+            .map(|(column, _, _)| {
+                if mapping.defaulted.iter().any(|c| c == column) {
+                    format!("'' AS \"{}\"", column)
+                } else {
+                    let source = mapping
+                        .mapped
+                        .iter()
+                        .find(|(c, _)| c == column)
+                        .map_or(*column, |(_, source)| source.as_str());
+                    format!("\"{}\" AS \"{}\"", source, column)
+                }
+            })
+            .collect();
+        // 其余列原样带上（如 law_name_en、publish_date_sort），rowid 供重建向量库分批读取
+        let consumed = |name: &str| {
+            spec.iter().any(|(c, _, _)| c.eq_ignore_ascii_case(name))
+                || mapping
+                    .mapped
+                    .iter()
+                    .any(|(_, source)| source.eq_ignore_ascii_case(name))
+        };
+        select.extend(
+            actual
+                .iter()
+                .filter(|name| !consumed(name))
+                .map(|name| format!("\"{}\"", name)),
+        );
+        select.push("rowid AS rowid".to_string());
+        conn.execute_batch(&format!(
+            "CREATE TEMP VIEW IF NOT EXISTS {table} AS SELECT {} FROM main.{table}",
+            select.join(", "),
+            table = mapping.table
+        ))?;
+        debug!(
+            "Mapped content.db table {}: {:?}, defaulted {:?}",
+            mapping.table, mapping.mapped, mapping.defaulted
+        );
+    }
+    Ok(())
+}
+
+// 诊断用：描述 content.db 的列映射情况
+fn check_content_schema(
+    data_dir: &std::path::Path,
+    overrides: &ContentSchemaMap,
+) -> Option<DiagnosticCheck> {
+    let path = data_dir.join("content.db");
+    if !path.exists() {
+        return None;
+    }
+    let conn = Connection::open(normalize_db_path(&path)).ok()?;
+    let mappings = match detect_content_schema(&conn, overrides) {
+        Ok(mappings) => mappings,
+        Err(e) => {
+            return Some(DiagnosticCheck::warn(
+                "CONTENT_SCHEMA",
+                format!("无法读取 content.db 表结构: {}", e),
+            ))
+        }
+    };
+    let mut notes = Vec::new();
+    let mut missing = Vec::new();
+    for mapping in &mappings {
+        notes.extend(
+            mapping
+                .mapped
+                .iter()
+                .map(|(column, source)| format!("{}.{} ← {}", mapping.table, column, source)),
+        );
+        notes.extend(
+            mapping
+                .defaulted
+                .iter()
+                .map(|column| format!("{}.{} 缺失（按空值处理）", mapping.table, column)),
+        );
+        missing.extend(
+            mapping
+                .missing_required
+                .iter()
+                .map(|column| format!("{}.{}", mapping.table, column)),
+        );
+    }
+    let check = if !missing.is_empty() {
+        DiagnosticCheck::fail(
+            "CONTENT_SCHEMA",
+            format!(
+                "content.db 缺少必需的列 {}，请在 content_schema_map 中指定对应列名",
+                missing.join("、")
+            ),
+        )
+        .with_field("content_schema_map")
+    } else if notes.is_empty() {
+        DiagnosticCheck::ok("CONTENT_SCHEMA", "content.db 表结构为标准格式")
+    } else {
+        DiagnosticCheck::warn(
+            "CONTENT_SCHEMA",
+            format!("content.db 列名已映射（数据只读）：{}", notes.join("；")),
+        )
+        .with_field("content_schema_map")
+    };
+    Some(check)
+}
+
+// 简单的 SQLite 连接池：空闲连接按路径复用，取不到时直接新建，不会阻塞等待
+pub struct SqlitePool {
+    idle: Mutex<Vec<(PathBuf, Connection)>>,
+}
+
+const SQLITE_POOL_MAX_IDLE: usize = 4;
+
+impl SqlitePool {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    // 取与 path 相同的空闲连接，没有时用 open 新建。其他路径的空闲连接不受影响，
+    // 切换数据目录后旧路径的连接仍占着空闲位，直到 clear 清空
+    fn get(
+        self: &Arc<Self>,
+        path: &std::path::Path,
+        open: impl FnOnce(&std::path::Path) -> Result<Connection, AppError>,
+    ) -> Result<PooledConnection, AppError> {
+        let reused = {
+            let mut idle = self.idle.lock_or_recover();
+            idle.iter()
+                .position(|(p, _)| p == path)
+                .map(|i| idle.swap_remove(i).1)
+        };
+        let conn = match reused {
+            Some(conn) => conn,
+            None => open(path)?,
+        };
+        Ok(PooledConnection {
+            pool: Arc::clone(self),
+            path: path.to_path_buf(),
+            conn: Some(conn),
+        })
+    }
+
+    // 替换数据文件前调用，避免空闲连接占用旧文件
+    fn clear(&self) {
+        self.idle.lock_or_recover().clear();
+    }
+}
+
+// 离开作用域时归还连接池
+pub struct PooledConnection {
+    pool: Arc<SqlitePool>,
+    path: PathBuf,
+    conn: Option<Connection>,
+}
+
+impl std::ops::Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("pooled connection already released")
+    }
+}
+
+impl std::ops::DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn
+            .as_mut()
+            .expect("pooled connection already released")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // 未结束的事务不放回池中
+        if !conn.is_autocommit() {
+            return;
+        }
+        let mut idle = self.pool.idle.lock_or_recover();
+        if idle.len() < SQLITE_POOL_MAX_IDLE {
+            idle.push((std::mem::take(&mut self.path), conn));
+        }
+    }
+}
+
+// 当前数据目录下的 content.db 连接
+fn content_db(state: &AppState) -> Result<PooledConnection, AppError> {
+    state
+        .content_pool
+        .get(&get_effective_data_dir(state), |dir| {
+            connect_sqlite(state, dir)
+        })
+}
+
+// user_data.db 连接，迁移已在启动时完成
+fn user_db(state: &AppState) -> Result<PooledConnection, AppError> {
+    state.user_pool.get(&state.user_db_path, open_user_db)
+}
+
+fn table_has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let exists = conn
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| {
+            let name: String = row.get(1)?;
+            Ok(name == column)
+        })?
+        .any(|res| res.unwrap_or(false));
+    Ok(exists)
+}
+
+// 旧版本用户库缺少的列在连接时补上
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), AppError> {
+    let column_exists = table_has_column(conn, table, column).map_err(AppError::user_db_error)?;
+    if !column_exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )
+        .map_err(AppError::user_db_error)?;
+    }
+    Ok(())
+}
+
+// 打开 user_data.db (用户库)，不做建表迁移
+#[tracing::instrument(level = "debug", skip_all)]
+fn open_user_db(db_path: &std::path::Path) -> Result<Connection, AppError> {
+    let conn = Connection::open(normalize_db_path(db_path))
+        .map_err(|e| AppError::user_db("无法打开用户数据库").with_detail(e))?;
+    // 收藏与历史可能同时写入：等待锁释放而不是立即报 SQLITE_BUSY
+    conn.busy_timeout(Duration::from_millis(USER_DB_BUSY_TIMEOUT_MS))
+        .map_err(AppError::user_db_error)?;
+    // 网络盘等不支持 WAL 的文件系统上保持默认日志模式
+    if let Err(e) = conn.pragma_update(None, "journal_mode", "WAL") {
+        warn!("Failed to enable WAL for user DB: {}", e);
+    }
+    Ok(conn)
+}
+
+const USER_DB_BUSY_TIMEOUT_MS: u64 = 5000;
+const USER_DB_BUSY_RETRIES: u64 = 3;
+
+fn is_busy_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+// 用户库写入：busy_timeout 之后仍然被锁时稍等再试，最终失败给出明确提示
+fn retry_on_busy<T>(mut op: impl FnMut() -> rusqlite::Result<T>) -> Result<T, AppError> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_busy_error(&e) && attempt < USER_DB_BUSY_RETRIES => {
+                attempt += 1;
+                warn!(
+                    "User DB busy, retrying ({}/{})",
+                    attempt, USER_DB_BUSY_RETRIES
+                );
+                std::thread::sleep(Duration::from_millis(100 * attempt));
+            }
+            Err(e) if is_busy_error(&e) => {
+                return Err(AppError::user_db("用户数据库正忙，请稍后重试").with_detail(e))
+            }
+            Err(e) => return Err(AppError::user_db_error(e)),
+        }
+    }
+}
+
+// 打开用户库并执行建表迁移；正常运行时只在启动时调用一次，之后走 user_db 连接池
+fn connect_user_db(db_path: &std::path::Path) -> Result<Connection, AppError> {
+    let conn = open_user_db(db_path)?;
+    migrate_user_db(&conn)?;
+    Ok(conn)
+}
+
+fn migrate_favorite_folder_links(conn: &Connection) -> Result<(), AppError> {
+    let legacy: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM favorites WHERE folder_id IS NOT NULL",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(AppError::user_db_error)?;
+    if legacy == 0 {
+        return Ok(());
+    }
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(AppError::user_db_error)?;
+    tx.execute_batch(
+        "INSERT OR IGNORE INTO favorite_folder_links (law_id, folder_id, sort_order, created_at)
+            SELECT law_id, folder_id, sort_order, created_at FROM favorites
+            WHERE folder_id IN (SELECT id FROM favorite_folders);
+        UPDATE favorites SET folder_id = NULL WHERE folder_id IS NOT NULL;",
+    )
+    .map_err(AppError::user_db_error)?;
+    tx.commit().map_err(AppError::user_db_error)?;
+    info!("Migrated {} favorites to folder links", legacy);
+    Ok(())
+}
+
+fn migrate_user_db(conn: &Connection) -> Result<(), AppError> {
+    conn.execute("CREATE TABLE IF NOT EXISTS favorite_folders (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, created_at DATETIME DEFAULT CURRENT_TIMESTAMP)", []).map_err(AppError::user_db_error)?;
+    conn.execute("CREATE TABLE IF NOT EXISTS favorites (id INTEGER PRIMARY KEY AUTOINCREMENT, law_id TEXT UNIQUE, law_name TEXT, article_number TEXT, content TEXT, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, tags TEXT)", []).map_err(AppError::user_db_error)?;
+
+    conn.execute("CREATE TABLE IF NOT EXISTS search_history (id INTEGER PRIMARY KEY AUTOINCREMENT, query TEXT UNIQUE, timestamp INTEGER)", []).map_err(AppError::user_db_error)?;
+
+    ensure_column(conn, "favorites", "folder_id", "INTEGER")?;
+    ensure_column(conn, "favorites", "note", "TEXT")?;
+    ensure_column(conn, "favorite_folders", "parent_id", "INTEGER")?;
+    ensure_column(conn, "favorites", "stale", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "favorites", "original_content", "TEXT")?;
+    ensure_column(conn, "favorites", "sort_order", "REAL")?;
+    // 收藏时条文的分类，用于推荐文件夹；旧收藏为空
+    ensure_column(conn, "favorites", "category", "TEXT")?;
+    ensure_column(conn, "search_history", "search_type", "TEXT")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS query_rewrite_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            from_text TEXT NOT NULL UNIQUE,
+            to_text TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    // 工作区：研究过程中跨多次检索收集的条文，决定收藏或提问前的暂存区
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS workspace_items (
+            chunk_id TEXT PRIMARY KEY,
+            chunk TEXT NOT NULL,
+            added_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+    ensure_column(conn, "search_history", "result_count", "INTEGER")?;
+    ensure_column(conn, "search_history", "pinned", "INTEGER DEFAULT 0")?;
+    ensure_column(
+        conn,
+        "search_history",
+        "use_count",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+    ensure_column(
+        conn,
+        "favorites",
+        "from_export",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    migrate_legacy_tags(conn)?;
+
+    // 用户手动调整过顺序的文件夹（未分类记为 -1）；新收藏总会分配 sort_order，
+    // 只有出现在这里的文件夹导出时才按手动顺序，其余按法规层级排列
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS favorite_manual_order (
+            folder_id INTEGER PRIMARY KEY,
+            reordered_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TRIGGER IF NOT EXISTS trg_favorite_folders_delete_manual_order
+        AFTER DELETE ON favorite_folders
+        BEGIN
+            DELETE FROM favorite_manual_order WHERE folder_id = OLD.id;
+        END;",
+    )
+    .map_err(AppError::user_db_error)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS favorite_laws (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            law_name TEXT UNIQUE,
+            category TEXT,
+            region TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            folder_id INTEGER,
+            note TEXT
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS draft_materials (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            law_id TEXT UNIQUE,
+            law_name TEXT,
+            article_number TEXT,
+            content TEXT,
+            added_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS custom_templates (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             name TEXT UNIQUE,
             content TEXT
         )",
         [],
     )
-    .map_err(|e| e.to_string())?;
+    .map_err(AppError::user_db_error)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings_profiles (
+            name TEXT PRIMARY KEY,
+            data TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    // event_type: search / agent / retrieved (检索结果中的法规) / favorite / chat
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS activity_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_type TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            law_name TEXT,
+            query TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_activity_type_time ON activity_log (event_type, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_activity_law_name ON activity_log (law_name);",
+    )
+    .map_err(AppError::user_db_error)?;
+
+    // 收藏与文件夹多对多；未分类的收藏没有任何关联。
+    // 旧版本的 favorites.folder_id 迁移为关联后清空，之后不再写入该列
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS favorite_folder_links (
+            law_id TEXT NOT NULL,
+            folder_id INTEGER NOT NULL,
+            sort_order REAL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (law_id, folder_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_favorite_folder_links_folder ON favorite_folder_links (folder_id);
+        CREATE TRIGGER IF NOT EXISTS trg_favorites_delete_links AFTER DELETE ON favorites
+        BEGIN
+            DELETE FROM favorite_folder_links WHERE law_id = OLD.law_id;
+        END;",
+    )
+    .map_err(AppError::user_db_error)?;
+    migrate_favorite_folder_links(conn)?;
+
+    // 用户填写的法规英文名，法规库没有 law_name_en 列或该列为空时使用
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS law_name_translations (
+            law_name TEXT PRIMARY KEY,
+            english TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    // 收藏删除时复习计划随之删除，所有删除收藏的入口都不必单独处理
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS review_state (
+            law_id TEXT PRIMARY KEY,
+            ease REAL NOT NULL DEFAULT 2.5,
+            interval_days REAL NOT NULL DEFAULT 0,
+            repetitions INTEGER NOT NULL DEFAULT 0,
+            due_at INTEGER NOT NULL,
+            last_reviewed_at INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_review_state_due ON review_state (due_at);
+        CREATE TRIGGER IF NOT EXISTS trg_favorites_delete_review AFTER DELETE ON favorites
+        BEGIN
+            DELETE FROM review_state WHERE law_id = OLD.law_id;
+        END;",
+    )
+    .map_err(AppError::user_db_error)?;
+
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS annotations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            law_name TEXT NOT NULL,
+            start_offset INTEGER NOT NULL,
+            end_offset INTEGER NOT NULL,
+            quote TEXT NOT NULL,
+            color TEXT NOT NULL DEFAULT 'yellow',
+            note TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            orphaned INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_annotations_law_name ON annotations (law_name);",
+    )
+    .map_err(AppError::user_db_error)?;
+
+    // 用户导入的术语释义，优先于法规库自带的 term_definitions 表
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS term_definitions (
+            term TEXT PRIMARY KEY,
+            definition TEXT NOT NULL,
+            law_name TEXT,
+            article_number TEXT,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::user_db_error)?;
+
+    Ok(())
+}
+
+// 早期版本的标签以逗号分隔保存，统一转换为 JSON 数组，列表筛选可直接在 SQL 中按标签匹配
+fn migrate_legacy_tags(conn: &Connection) -> Result<(), AppError> {
+    let legacy: Vec<(i64, String)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, tags FROM favorites
+                 WHERE tags IS NOT NULL AND (NOT json_valid(tags) OR json_type(tags) != 'array')",
+            )
+            .map_err(AppError::user_db_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        rows
+    };
+    if legacy.is_empty() {
+        return Ok(());
+    }
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(AppError::user_db_error)?;
+    for (id, raw) in &legacy {
+        tx.execute(
+            "UPDATE favorites SET tags = ?2 WHERE id = ?1",
+            rusqlite::params![id, tags_to_json(&parse_tags(Some(raw.clone())))],
+        )
+        .map_err(AppError::user_db_error)?;
+    }
+    tx.commit().map_err(AppError::user_db_error)?;
+    info!("Converted tags of {} favorites to JSON", legacy.len());
+    Ok(())
+}
+// 读取设置并按版本迁移；文件无法解析时备份为 settings.json.bak，返回默认设置及提示
+fn load_settings_from_disk(path: &std::path::Path) -> (AppSettings, Option<String>) {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            let warning = format!("无法读取设置文件（{}），已使用默认设置", e);
+            warn!("{}", warning);
+            return (AppSettings::default(), Some(warning));
+        }
+    };
+
+    let parsed = match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(serde_json::Value::Object(mut map)) => {
+            let migrated = migrate_settings(&mut map);
+            serde_json::from_value::<AppSettings>(serde_json::Value::Object(map))
+                .map(|settings| (settings, migrated))
+                .map_err(|e| e.to_string())
+        }
+        Ok(_) => Err("内容不是 JSON 对象".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let (mut settings, migrated) = match parsed {
+        Ok(result) => result,
+        Err(e) => {
+            let backup = path.with_extension("json.bak");
+            let warning = match fs::copy(path, &backup) {
+                Ok(_) => format!(
+                    "设置文件无法解析（{}），已备份为 {} 并恢复默认设置",
+                    e,
+                    backup.display()
+                ),
+                Err(copy_err) => format!(
+                    "设置文件无法解析（{}），备份失败（{}），已恢复默认设置",
+                    e, copy_err
+                ),
+            };
+            warn!("{}", warning);
+            return (AppSettings::default(), Some(warning));
+        }
+    };
+
+    resolve_api_keys(&mut settings);
+    // 版本升级后写回新结构；旧版本明文保存的 Key 同时迁入凭据管理器
+    let has_legacy_keys = !settings.plaintext_api_keys && has_plaintext_api_keys(&settings);
+    if migrated || has_legacy_keys {
+        match write_settings_file(path, &settings) {
+            Ok(()) => info!(
+                "Settings file upgraded to version {}",
+                CURRENT_SETTINGS_VERSION
+            ),
+            Err(e) => warn!("Settings file upgrade skipped: {}", e),
+        }
+    }
+    (settings, None)
+}
+
+// --- 设置文件版本迁移 ---
+// 调整字段结构时追加一个迁移函数，并把 CURRENT_SETTINGS_VERSION 加一
+const CURRENT_SETTINGS_VERSION: u32 = 1;
+
+type SettingsMap = serde_json::Map<String, serde_json::Value>;
+
+// 下标 i 的函数把第 i 版升级到第 i+1 版
+const SETTINGS_MIGRATIONS: [fn(&mut SettingsMap); CURRENT_SETTINGS_VERSION as usize] =
+    [migrate_settings_v0_to_v1];
+
+// 返回是否做过升级；比当前版本更新的文件保持原样（未知字段由 serde 忽略）
+fn migrate_settings(map: &mut SettingsMap) -> bool {
+    let version = map
+        .get("settings_version")
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or(0) as usize;
+    if version >= SETTINGS_MIGRATIONS.len() {
+        if version > SETTINGS_MIGRATIONS.len() {
+            warn!(
+                "Settings file version {} is newer than supported {}",
+                version, CURRENT_SETTINGS_VERSION
+            );
+        }
+        return false;
+    }
+
+    for (from, migration) in SETTINGS_MIGRATIONS.iter().enumerate().skip(version) {
+        migration(map);
+        info!("Migrated settings from version {} to {}", from, from + 1);
+    }
+    map.insert(
+        "settings_version".to_string(),
+        serde_json::json!(CURRENT_SETTINGS_VERSION),
+    );
+    true
+}
+
+// v0 -> v1：旧版前端把未填写的可选项保存为空字符串，统一改为 null
+fn migrate_settings_v0_to_v1(map: &mut SettingsMap) {
+    for field in [
+        "custom_data_path",
+        "custom_deep_prompt",
+        "custom_simple_prompt",
+        "http_proxy",
+        "no_proxy_hosts",
+    ] {
+        if map
+            .get(field)
+            .and_then(serde_json::Value::as_str)
+            .is_some_and(|v| v.trim().is_empty())
+        {
+            map.insert(field.to_string(), serde_json::Value::Null);
+        }
+    }
+}
+
+// --- 安装模式 (便携 / 标准) ---
+// exe 目录下的模式标记，内容为 "portable" 或 "standard"，优先于自动检测
+const INSTALL_MODE_MARKER: &str = "install_mode";
+
+#[derive(Debug, Clone, PartialEq)]
+struct InstallPaths {
+    portable: bool,
+    settings_path: PathBuf,
+    user_db_path: PathBuf,
+}
+
+impl InstallPaths {
+    fn for_mode(
+        portable: bool,
+        exe_dir: &std::path::Path,
+        app_config_dir: &std::path::Path,
+    ) -> Self {
+        let dir = if portable { exe_dir } else { app_config_dir };
+        Self {
+            portable,
+            settings_path: dir.join("settings.json"),
+            user_db_path: dir.join("user_data.db"),
+        }
+    }
+}
+
+fn read_install_marker(exe_dir: &std::path::Path) -> Option<bool> {
+    let content = fs::read_to_string(exe_dir.join(INSTALL_MODE_MARKER)).ok()?;
+    match content.trim() {
+        "portable" => Some(true),
+        "standard" => Some(false),
+        _ => None,
+    }
+}
+
+// 规则：有模式标记时按标记；否则 exe 旁边有配置文件即为便携模式，数据库也读旁边的，
+// 其余情况全部走系统目录
+fn resolve_install_paths(
+    exe_dir: &std::path::Path,
+    app_config_dir: &std::path::Path,
+) -> InstallPaths {
+    let portable =
+        read_install_marker(exe_dir).unwrap_or_else(|| exe_dir.join("settings.json").exists());
+    InstallPaths::for_mode(portable, exe_dir, app_config_dir)
+}
+
+// 写回 settings.json；除非开启明文保存，API Key 只以占位符形式落盘
+fn write_settings_file(path: &std::path::Path, settings: &AppSettings) -> Result<(), String> {
+    let mut on_disk = settings.clone();
+    if !on_disk.plaintext_api_keys {
+        stash_api_keys(&mut on_disk)?;
+    }
+    let json = serde_json::to_string_pretty(&on_disk).map_err(|e| e.to_string())?;
+    write_file_atomic(path, json.as_bytes())
+        .map_err(|e| format!("无法写入设置文件 {}: {}", path.display(), e))
+}
+
+// 先写同目录下的临时文件再改名覆盖，写到一半崩溃也不会留下残缺的文件
+fn write_file_atomic(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = write_and_rename(&tmp_path, path, contents);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn write_and_rename(
+    tmp_path: &std::path::Path,
+    path: &std::path::Path,
+    contents: &[u8],
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = fs::File::create(tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(tmp_path, path)
+}
+
+// --- API Key 存储 (系统凭据管理器) ---
+const KEYRING_SERVICE: &str = "lawvault";
+// settings.json 中代替真实 Key 的占位符
+const KEYRING_MARKER: &str = "<stored-in-keychain>";
+const API_KEY_ACCOUNTS: [&str; 2] = ["embedding_api_key", "chat_api_key"];
+
+fn api_key_fields(settings: &mut AppSettings) -> [(&'static str, &mut String); 2] {
+    [
+        (API_KEY_ACCOUNTS[0], &mut settings.embedding_api_key),
+        (API_KEY_ACCOUNTS[1], &mut settings.chat_api_key),
+    ]
+}
+
+fn keyring_error(e: keyring::Error) -> String {
+    format!(
+        "系统凭据管理器不可用（{}），可在设置中开启“明文保存 API Key”",
+        e
+    )
+}
+
+fn has_plaintext_api_keys(settings: &AppSettings) -> bool {
+    [&settings.embedding_api_key, &settings.chat_api_key]
+        .iter()
+        .any(|key| !key.is_empty() && key.as_str() != KEYRING_MARKER)
+}
+
+// Key 存入凭据管理器并替换为占位符；清空的 Key 同时删除已保存的凭据
+fn stash_secret(account: &str, value: &mut String) -> Result<(), String> {
+    if *value == KEYRING_MARKER {
+        return Ok(());
+    }
+    if value.is_empty() {
+        return delete_secret(account);
+    }
+    keyring::Entry::new(KEYRING_SERVICE, account)
+        .and_then(|e| e.set_password(value))
+        .map_err(keyring_error)?;
+    *value = KEYRING_MARKER.to_string();
+    Ok(())
+}
+
+// 占位符还原为真实 Key；读取失败时保留占位符，保存设置时也不会覆盖已存的凭据
+fn resolve_secret(account: &str, value: &mut String) {
+    if *value != KEYRING_MARKER {
+        return;
+    }
+    match keyring::Entry::new(KEYRING_SERVICE, account).and_then(|e| e.get_password()) {
+        Ok(secret) => *value = secret,
+        Err(e) => warn!("Failed to read {} from keychain: {}", account, e),
+    }
+}
+
+fn delete_secret(account: &str) -> Result<(), String> {
+    match keyring::Entry::new(KEYRING_SERVICE, account).and_then(|e| e.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(keyring_error(e)),
+    }
+}
+
+fn stash_api_keys(settings: &mut AppSettings) -> Result<(), String> {
+    for (account, value) in api_key_fields(settings) {
+        stash_secret(account, value)?;
+    }
+    Ok(())
+}
+
+fn resolve_api_keys(settings: &mut AppSettings) {
+    for (account, value) in api_key_fields(settings) {
+        resolve_secret(account, value);
+    }
+}
+
+fn delete_stored_api_keys() -> Result<(), String> {
+    for account in API_KEY_ACCOUNTS {
+        delete_secret(account)?;
+    }
+    Ok(())
+}
+
+fn get_effective_data_dir(state: &AppState) -> PathBuf {
+    resolve_data_dir(state, &snapshot_settings(state))
+}
+
+// 相对路径的基准：便携模式为 exe 目录，标准模式为配置目录，与进程的工作目录无关
+fn data_path_base(state: &AppState) -> &std::path::Path {
+    if state.portable_mode {
+        &state.exe_dir
+    } else {
+        &state.app_config_dir
+    }
+}
+
+// 自定义数据路径解析为绝对路径；目录存在时再规范化（去掉 ./ 与 ..），不存在时原样返回
+fn resolve_custom_data_path(state: &AppState, custom_path: &str) -> PathBuf {
+    let path = PathBuf::from(custom_path.trim());
+    let path = if path.is_relative() {
+        data_path_base(state).join(path)
+    } else {
+        path
+    };
+    match path.canonicalize() {
+        Ok(canonical) => PathBuf::from(normalize_db_path(&canonical)),
+        Err(_) => path,
+    }
+}
+
+fn configured_data_path(settings: &AppSettings) -> Option<&str> {
+    settings
+        .custom_data_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+}
+
+// 自定义数据路径可访问时优先使用；配置了却无法访问时回退到默认资源目录，
+// 并记录下来，由检索命令通知前端，而不是静默使用内置数据
+fn resolve_data_dir(state: &AppState, settings: &AppSettings) -> PathBuf {
+    let Some(custom_path) = configured_data_path(settings) else {
+        *state.data_dir_fallback.lock_or_recover() = None;
+        return state.app_data_dir.clone();
+    };
+    let path = resolve_custom_data_path(state, custom_path);
+    let mut fallback = state.data_dir_fallback.lock_or_recover();
+    if path.is_dir() {
+        *fallback = None;
+        return path;
+    }
+    if fallback
+        .as_ref()
+        .map_or(true, |f| f.configured_path != path)
+    {
+        warn!(
+            "Custom data directory unreachable, using default: {}",
+            path.display()
+        );
+        *fallback = Some(DataDirFallback {
+            configured_path: path,
+            reported: false,
+        });
+    }
+    state.app_data_dir.clone()
+}
+
+// 默认数据目录在数据源列表中的名称
+const PRIMARY_SOURCE_NAME: &str = "默认";
+
+// 已启用且当前可访问的附加数据源 (名称, 目录)
+fn enabled_extra_sources(state: &AppState, settings: &AppSettings) -> Vec<(String, PathBuf)> {
+    settings
+        .data_sources
+        .iter()
+        .filter(|source| source.enabled)
+        .filter_map(|source| {
+            let dir = resolve_custom_data_path(state, &source.path);
+            if dir.is_dir() {
+                Some((source.name.clone(), dir))
+            } else {
+                warn!("Data source {} unreachable: {}", source.name, dir.display());
+                None
+            }
+        })
+        .collect()
+}
+
+// 条文所在数据源的目录：None 为默认数据目录，其余按名称在附加数据源中查找
+fn source_data_dir(state: &AppState, source_name: Option<&str>) -> Result<PathBuf, AppError> {
+    let Some(name) = source_name.filter(|name| *name != PRIMARY_SOURCE_NAME) else {
+        return Ok(get_effective_data_dir(state));
+    };
+    let settings = snapshot_settings(state);
+    let source = settings
+        .data_sources
+        .iter()
+        .find(|source| source.name == name)
+        .ok_or_else(|| AppError::not_found(format!("数据源不存在: {}", name)))?;
+    Ok(resolve_custom_data_path(state, &source.path))
+}
+
+fn source_db(state: &AppState, source_name: Option<&str>) -> Result<PooledConnection, AppError> {
+    state
+        .content_pool
+        .get(&source_data_dir(state, source_name)?, |dir| {
+            connect_sqlite(state, dir)
+        })
+}
+
+// 取出尚未提示过的数据目录回退
+fn take_data_dir_fallback(state: &AppState) -> Option<DataDirUnreachableEvent> {
+    let mut fallback = state.data_dir_fallback.lock_or_recover();
+    let fallback = fallback.as_mut().filter(|f| !f.reported)?;
+    fallback.reported = true;
+    Some(DataDirUnreachableEvent {
+        configured_path: fallback.configured_path.to_string_lossy().to_string(),
+        fallback_dir: state.app_data_dir.to_string_lossy().to_string(),
+    })
+}
+
+// 命令开头取一份设置快照，之后只读快照，不再反复加锁
+fn snapshot_settings(state: &AppState) -> AppSettings {
+    state
+        .settings
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+fn settings_write_guard(state: &AppState) -> RwLockWriteGuard<'_, AppSettings> {
+    state
+        .settings
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+// 某个命令 panic 导致锁中毒后仍取回内部数据，避免之后的命令全部失败
+trait MutexExt<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// 本机地址始终直连，本地 Ollama 不受代理影响
+const DEFAULT_NO_PROXY: &str = "localhost,127.0.0.1,::1";
+
+// 读取附加 CA 证书文件，返回其中的全部证书
+fn load_extra_ca_certs(path: &str) -> Result<Vec<reqwest::Certificate>, String> {
+    let pem = fs::read(path).map_err(|e| format!("无法读取 CA 证书 {}: {}", path, e))?;
+    let certs = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("CA 证书 {} 不是有效的 PEM 文件: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("CA 证书 {} 中没有证书", path));
+    }
+    Ok(certs)
+}
+
+fn configured_extra_ca(settings: &AppSettings) -> Option<&str> {
+    settings
+        .extra_ca_cert_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+}
+
+// 全局共享的 HTTP 客户端：保持长连接复用 TLS 会话，代理、证书与 UA 统一在这里配置。
+// 使用 rustls 并加载系统证书库，企业安装到系统中的根证书可直接生效
+fn build_http_client(settings: &AppSettings) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .use_rustls_tls()
+        .user_agent(concat!("LawVault/", env!("CARGO_PKG_VERSION")))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(8)
+        .tcp_keepalive(Duration::from_secs(60));
+    // 证书文件读不出时只跳过附加证书，代理等其余配置照常生效；保存设置时 check_settings 会报错
+    if let Some(path) = configured_extra_ca(settings) {
+        match load_extra_ca_certs(path) {
+            Ok(certs) => {
+                for cert in certs {
+                    builder = builder.add_root_certificate(cert);
+                }
+            }
+            Err(e) => warn!("Extra CA certificate skipped: {}", e),
+        }
+    }
+    if settings.accept_invalid_certs {
+        warn!("HTTPS certificate verification is disabled (accept_invalid_certs)");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    if let Some(proxy_url) = settings
+        .http_proxy
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+    {
+        let mut no_proxy = DEFAULT_NO_PROXY.to_string();
+        if let Some(hosts) = settings.no_proxy_hosts.as_deref() {
+            for host in hosts.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+                no_proxy.push(',');
+                no_proxy.push_str(host);
+            }
+        }
+        let proxy = reqwest::Proxy::all(proxy_url.trim())
+            .map_err(|e| format!("代理地址无效: {}", e))?
+            .no_proxy(reqwest::NoProxy::from_string(&no_proxy));
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn http_client(state: &AppState) -> reqwest::Client {
+    state.http_client.lock_or_recover().clone()
+}
+
+fn timeout_error(service: &str, timeout: Duration) -> String {
+    format!("{}响应超时（{}秒）", service, timeout.as_secs())
+}
+
+// 连接失败的具体原因，便于区分证书问题与服务未启动
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConnectFailure {
+    Certificate,
+    Refused,
+    Other,
+}
+
+// 沿错误链查找：rustls / 系统 TLS 的证书错误信息都含 "certificate"
+fn classify_connect_failure(e: &reqwest::Error) -> ConnectFailure {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::ConnectionRefused {
+                return ConnectFailure::Refused;
+            }
+        }
+        let message = err.to_string().to_lowercase();
+        if message.contains("certificate") || message.contains("unknownissuer") {
+            return ConnectFailure::Certificate;
+        }
+        if message.contains("connection refused") || message.contains("os error 10061") {
+            return ConnectFailure::Refused;
+        }
+        source = err.source();
+    }
+    ConnectFailure::Other
+}
+
+// 连接测试的失败说明，证书错误与连接被拒分开提示
+fn describe_connect_error(e: &reqwest::Error) -> String {
+    match classify_connect_failure(e) {
+        ConnectFailure::Certificate => format!(
+            "连接失败: HTTPS 证书校验未通过，可能是公司网关替换了证书。\
+             请将公司根证书安装到系统，或在设置中指定附加 CA 证书 ({})",
+            e
+        ),
+        ConnectFailure::Refused => format!(
+            "连接失败: 连接被拒绝，请确认服务已启动、地址与端口正确 ({})",
+            e
+        ),
+        ConnectFailure::Other => format!("连接失败: 网络请求错误 ({})", e),
+    }
+}
+
+// reqwest 错误统一归为网络错误，超时给出明确的秒数，证书错误单独说明
+fn request_error(e: reqwest::Error, service: &str, timeout: Duration) -> AppError {
+    if e.is_timeout() {
+        AppError::network(timeout_error(service, timeout))
+    } else if classify_connect_failure(&e) == ConnectFailure::Certificate {
+        AppError::network(format!("无法连接{}：HTTPS 证书校验未通过", service)).with_detail(e)
+    } else {
+        AppError::network(format!("无法连接{}", service)).with_detail(e)
+    }
+}
+
+// 嵌入服务配置：provider 为 "local" 时使用数据目录下的 ONNX 模型，否则走 HTTP 接口
+#[derive(Clone, Debug)]
+struct EmbeddingConfig {
+    provider: String,
+    base_url: String,
+    api_key: String,
+    model: String,
+    api_format: String,
+    timeout: Duration,
+    local_model_dir: PathBuf,
+}
+
+impl EmbeddingConfig {
+    fn from_settings(settings: &AppSettings, data_dir: &std::path::Path) -> Self {
+        Self {
+            provider: settings.embedding_provider.clone(),
+            base_url: settings.embedding_base_url.clone(),
+            api_key: settings.embedding_api_key.clone(),
+            model: settings.embedding_model.clone(),
+            api_format: settings.embedding_api_format.clone(),
+            timeout: Duration::from_secs(settings.embedding_timeout_secs),
+            local_model_dir: local_model_dir(data_dir),
+        }
+    }
+
+    fn is_local(&self) -> bool {
+        self.provider == "local"
+    }
+}
+
+fn embedding_config(state: &AppState) -> EmbeddingConfig {
+    let settings = snapshot_settings(state);
+    EmbeddingConfig::from_settings(&settings, &resolve_data_dir(state, &settings))
+}
+
+// api_format: "openai" 走 /embeddings，"ollama" 走原生 /api/embeddings
+async fn get_embedding(
+    client: &reqwest::Client,
+    config: &EmbeddingConfig,
+    text: &str,
+) -> Result<Vec<f32>, AppError> {
+    if config.is_local() {
+        let mut vectors =
+            embed_locally(config.local_model_dir.clone(), vec![text.to_string()]).await?;
+        return vectors
+            .pop()
+            .ok_or(AppError::embedding_api("本地嵌入模型未返回向量"));
+    }
+
+    let (base_url, api_key, model) = (&config.base_url, &config.api_key, &config.model);
+    let prompt = text.replace("\n", " ");
+    let timeout = config.timeout;
+    let map_err = |e: reqwest::Error| request_error(e, "嵌入服务", timeout);
+
+    let (url, body) = if config.api_format == "ollama" {
+        (
+            format!("{}/api/embeddings", ollama_base(base_url)),
+            serde_json::json!({ "model": model, "prompt": prompt }),
+        )
+    } else {
+        (
+            format!("{}/embeddings", base_url.trim_end_matches('/')),
+            // 部分服务默认返回 base64，显式要求浮点数组
+            serde_json::json!({ "model": model, "input": prompt, "encoding_format": "float" }),
+        )
+    };
+
+    let res = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(map_err)?;
+
+    if !res.status().is_success() {
+        return Err(embedding_status_error(res.status()));
+    }
+
+    let json: serde_json::Value = res.json().await.map_err(map_err)?;
+
+    if let Some(data) = json.get("data") {
+        if let Some(first) = data.get(0) {
+            if let Some(vec) = first.get("embedding") {
+                return parse_embedding_values(vec, model).map_err(AppError::embedding_api);
+            }
+        }
+    }
+    if let Some(vec) = json.get("embedding") {
+        return parse_embedding_values(vec, model).map_err(AppError::embedding_api);
+    }
+
+    Err(AppError::embedding_api("嵌入服务响应中未找到向量").with_detail(json))
+}
+
+fn embedding_status_error(status: reqwest::StatusCode) -> AppError {
+    AppError::embedding_api(format!("嵌入服务返回错误 (HTTP {})", status.as_u16()))
+}
+
+// 严格解析向量：非数字元素、空数组或全零向量都视为错误，避免静默得到无意义的检索结果
+fn parse_embedding_values(vec: &serde_json::Value, model: &str) -> Result<Vec<f32>, String> {
+    let embedding = match vec {
+        serde_json::Value::Array(values) => {
+            let mut embedding = Vec::with_capacity(values.len());
+            for (i, v) in values.iter().enumerate() {
+                let n = v.as_f64().ok_or_else(|| {
+                    format!(
+                        "嵌入模型 {} 返回的向量第 {} 个元素不是数字: {}",
+                        model, i, v
+                    )
+                })?;
+                embedding.push(n as f32);
+            }
+            embedding
+        }
+        // encoding_format=base64：小端序 f32 字节流
+        serde_json::Value::String(encoded) => decode_base64_embedding(encoded, model)?,
+        other => return Err(format!("嵌入模型 {} 返回的向量格式无效: {}", model, other)),
+    };
+
+    if embedding.is_empty() {
+        return Err(format!("嵌入模型 {} 返回了空向量", model));
+    }
+    if let Some(i) = embedding.iter().position(|v| !v.is_finite()) {
+        return Err(format!(
+            "嵌入模型 {} 返回的向量第 {} 个元素不是有效数字",
+            model, i
+        ));
+    }
+    if embedding.iter().all(|v| *v == 0.0) {
+        return Err(format!("嵌入模型 {} 返回了全零向量", model));
+    }
+    Ok(embedding)
+}
+
+fn decode_base64_embedding(encoded: &str, model: &str) -> Result<Vec<f32>, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("嵌入模型 {} 返回的 base64 向量无法解码: {}", model, e))?;
+    if bytes.len() % 4 != 0 {
+        return Err(format!(
+            "嵌入模型 {} 返回的 base64 向量长度 {} 不是 4 的倍数",
+            model,
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+async fn open_vector_table(data_dir: &std::path::Path) -> Result<lancedb::Table, AppError> {
+    connect_vector_db(data_dir)
+        .await?
+        .open_table("laws_vectors")
+        .execute()
+        .await
+        .map_err(AppError::vector_db_error)
+}
+
+async fn connect_vector_db(data_dir: &std::path::Path) -> Result<lancedb::Connection, AppError> {
+    let lancedb_path_buf = data_dir.join("law_db.lancedb");
+    let path_str = normalize_db_path(&lancedb_path_buf);
+
+    if !lancedb_path_buf.exists() {
+        return Err(AppError::not_found(format!(
+            "数据库路径不存在: {}",
+            path_str
+        )));
+    }
+
+    lancedb::connect(&path_str)
+        .execute()
+        .await
+        .map_err(AppError::vector_db_error)
+}
+
+// 向量列（FixedSizeList）的维度，没有向量列时返回 None
+async fn table_vector_dimension(table: &lancedb::Table) -> Result<Option<usize>, AppError> {
+    let schema = table.schema().await.map_err(AppError::vector_db_error)?;
+    Ok(schema.fields().iter().find_map(|f| match f.data_type() {
+        DataType::FixedSizeList(_, size) => Some(*size as usize),
+        _ => None,
+    }))
+}
+
+fn compare_embedding_dimension(model_dim: usize, db_dim: usize) -> Result<(), AppError> {
+    if model_dim == db_dim {
+        Ok(())
+    } else {
+        Err(AppError::vector_db(format!(
+            "嵌入维度不匹配：模型输出{}维，数据库为{}维，请更换模型或重建索引",
+            model_dim, db_dim
+        )))
+    }
+}
+
+// 批量嵌入：OpenAI 兼容接口一次发送整个数组；Ollama 原生接口或服务端拒绝数组输入时逐条请求
+async fn get_embeddings_batch(
+    client: &reqwest::Client,
+    config: &EmbeddingConfig,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, AppError> {
+    if config.is_local() {
+        return embed_locally(config.local_model_dir.clone(), texts.to_vec()).await;
+    }
+    if config.api_format != "ollama" && texts.len() > 1 {
+        match request_embeddings_array(client, config, texts).await? {
+            Some(vectors) => return Ok(vectors),
+            None => info!("Batch embedding not supported, falling back to single requests"),
+        }
+    }
+
+    let mut vectors = Vec::with_capacity(texts.len());
+    for text in texts {
+        vectors.push(get_embedding(client, config, text).await?);
+    }
+    Ok(vectors)
+}
+
+// 返回 Ok(None) 表示服务端不支持数组输入（4xx 或返回条数不符），网络错误与超时直接报错
+async fn request_embeddings_array(
+    client: &reqwest::Client,
+    config: &EmbeddingConfig,
+    texts: &[String],
+) -> Result<Option<Vec<Vec<f32>>>, AppError> {
+    let (base_url, api_key, model) = (&config.base_url, &config.api_key, &config.model);
+    let timeout = config.timeout;
+    let map_err = |e: reqwest::Error| request_error(e, "嵌入服务", timeout);
+    let input: Vec<String> = texts.iter().map(|t| t.replace("\n", " ")).collect();
+
+    let res = client
+        .post(format!("{}/embeddings", base_url.trim_end_matches('/')))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({ "model": model, "input": input, "encoding_format": "float" }))
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(map_err)?;
+
+    if res.status().is_client_error() {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(embedding_status_error(res.status()));
+    }
+
+    let json: serde_json::Value = res.json().await.map_err(map_err)?;
+    let data = match json["data"].as_array() {
+        Some(data) if data.len() == texts.len() => data,
+        _ => return Ok(None),
+    };
+
+    // 部分服务端不保证返回顺序，按 index 字段还原
+    let mut indexed: Vec<(usize, Vec<f32>)> = Vec::with_capacity(data.len());
+    for (pos, item) in data.iter().enumerate() {
+        let index = item["index"].as_u64().map_or(pos, |i| i as usize);
+        let vector =
+            parse_embedding_values(&item["embedding"], model).map_err(AppError::embedding_api)?;
+        indexed.push((index, vector));
+    }
+    indexed.sort_by_key(|(index, _)| *index);
+    if indexed
+        .iter()
+        .enumerate()
+        .any(|(i, (index, _))| i != *index)
+    {
+        return Err(AppError::embedding_api("嵌入服务批量返回的 index 不连续"));
+    }
+
+    Ok(Some(indexed.into_iter().map(|(_, v)| v).collect()))
+}
+
+// --- 日志 (tracing) ---
+// 按天滚动写入 logs/lawvault.log.YYYY-MM-DD，release 版没有控制台也能事后排查
+const LOG_FILE_PREFIX: &str = "lawvault.log";
+// MCP 模式与桌面端可能同时运行，各写各的日志文件，避免两个进程交替写同一文件
+const MCP_LOG_FILE_PREFIX: &str = "lawvault-mcp.log";
+
+type LogFilterHandle =
+    tracing_subscriber::reload::Handle<tracing_subscriber::EnvFilter, tracing_subscriber::Registry>;
+
+static LOG_FILTER: OnceLock<LogFilterHandle> = OnceLock::new();
+// 后台写文件线程的守卫，被释放后缓冲中的日志会丢失，因此常驻
+static LOG_GUARD: OnceLock<tracing_appender::non_blocking::WorkerGuard> = OnceLock::new();
+static LOG_QUERY_TEXT: AtomicBool = AtomicBool::new(false);
+
+// 只调整本应用的级别，依赖库固定为 warn，避免 debug 时被 lancedb 等刷屏
+fn log_filter(level: &str) -> tracing_subscriber::EnvFilter {
+    let level = match level.trim().to_lowercase().as_str() {
+        l @ ("error" | "warn" | "info" | "debug" | "trace") => l.to_string(),
+        _ => "info".to_string(),
+    };
+    tracing_subscriber::EnvFilter::new(format!("warn,app_lib={}", level))
+}
+
+fn init_logging(log_dir: &std::path::Path, file_prefix: &str) {
+    let _ = fs::create_dir_all(log_dir);
+    let file_appender = tracing_appender::rolling::daily(log_dir, file_prefix);
+    let (writer, guard) = tracing_appender::non_blocking(file_appender);
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(log_filter("info"));
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false),
+        )
+        // 控制台日志写 stderr，stdout 留给 MCP 模式的协议消息
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Logging init failed: {}", e);
+        return;
+    }
+    let _ = LOG_GUARD.set(guard);
+    let _ = LOG_FILTER.set(handle);
+}
+
+// 启动及保存设置时调用，切换日志级别无需重启
+fn apply_log_settings(settings: &AppSettings) {
+    if let Some(handle) = LOG_FILTER.get() {
+        if let Err(e) = handle.reload(log_filter(&settings.log_level)) {
+            warn!("Failed to reload log level: {}", e);
+        }
+    }
+    LOG_QUERY_TEXT.store(settings.log_query_text, Ordering::Relaxed);
+}
+
+// 用户的问题可能包含案情隐私，默认只记录长度
+fn log_query(query: &str) -> String {
+    if LOG_QUERY_TEXT.load(Ordering::Relaxed) {
+        query.to_string()
+    } else {
+        format!("<{} chars>", query.chars().count())
+    }
+}
+
+// --- 本地嵌入模型 (fastembed / ONNX) ---
+const LOCAL_MODEL_REPO: &str = "Xenova/bge-small-zh-v1.5";
+const LOCAL_MODEL_FILES: [&str; 5] = [
+    "onnx/model.onnx",
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+const DEFAULT_MODEL_ENDPOINT: &str = "https://huggingface.co";
+
+// 已加载的本地模型（按模型目录缓存），避免每次检索重新读取 ONNX 文件
+static LOCAL_EMBEDDER: Mutex<Option<(PathBuf, Arc<TextEmbedding>)>> = Mutex::new(None);
+
+fn local_model_dir(data_dir: &std::path::Path) -> PathBuf {
+    data_dir.join("models").join("bge-small-zh-v1.5")
+}
+
+fn load_local_embedder(dir: &std::path::Path) -> Result<Arc<TextEmbedding>, AppError> {
+    let mut guard = LOCAL_EMBEDDER.lock_or_recover();
+    if let Some((loaded_dir, model)) = guard.as_ref() {
+        if loaded_dir == dir {
+            return Ok(model.clone());
+        }
+    }
+
+    let read = |name: &str| {
+        fs::read(dir.join(name)).map_err(|e| {
+            AppError::not_found(format!("本地嵌入模型文件缺失: {}", name)).with_detail(e)
+        })
+    };
+    let tokenizer_files = TokenizerFiles {
+        tokenizer_file: read("tokenizer.json")?,
+        config_file: read("config.json")?,
+        special_tokens_map_file: read("special_tokens_map.json")?,
+        tokenizer_config_file: read("tokenizer_config.json")?,
+    };
+    // BGE 系列使用 [CLS] 向量
+    let user_model = UserDefinedEmbeddingModel::new(read("onnx/model.onnx")?, tokenizer_files)
+        .with_pooling(Pooling::Cls);
+    let model =
+        TextEmbedding::try_new_from_user_defined(user_model, InitOptionsUserDefined::default())
+            .map_err(|e| AppError::embedding_api("加载本地嵌入模型失败").with_detail(e))?;
+
+    let model = Arc::new(model);
+    *guard = Some((dir.to_path_buf(), model.clone()));
+    Ok(model)
+}
+
+// ONNX 推理是 CPU 密集操作，放到阻塞线程池执行
+async fn embed_locally(dir: PathBuf, texts: Vec<String>) -> Result<Vec<Vec<f32>>, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let model = load_local_embedder(&dir)?;
+        model
+            .embed(texts, None)
+            .map_err(|e| AppError::embedding_api("本地嵌入失败").with_detail(e))
+    })
+    .await
+    .map_err(|e| AppError::embedding_api("本地嵌入失败").with_detail(e))?
+}
+
+// Chat 接口协议：OpenAI 兼容 (/chat/completions)、Anthropic Messages (/messages)
+// 或 Ollama 原生接口 (/api/chat)
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ChatApiFormat {
+    OpenAi,
+    Anthropic,
+    Ollama,
+}
+
+impl ChatApiFormat {
+    fn from_setting(value: &str) -> Self {
+        match value {
+            "anthropic" => ChatApiFormat::Anthropic,
+            "ollama" => ChatApiFormat::Ollama,
+            _ => ChatApiFormat::OpenAi,
+        }
+    }
+}
+
+// Ollama 原生接口挂在根路径下，兼容用户沿用 OpenAI 风格的 .../v1 地址
+fn ollama_base(base_url: &str) -> &str {
+    let base = base_url.trim_end_matches('/');
+    base.strip_suffix("/v1").unwrap_or(base)
+}
+
+// Anthropic 要求必须携带 max_tokens
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 4096;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+struct ChatEndpoint {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    format: ChatApiFormat,
+    timeout: Duration,
+}
+
+impl ChatEndpoint {
+    fn new(client: &reqwest::Client, base_url: &str, api_key: &str, format: ChatApiFormat) -> Self {
+        let base = base_url.trim_end_matches('/');
+        let url = match format {
+            ChatApiFormat::OpenAi => format!("{}/chat/completions", base),
+            ChatApiFormat::Anthropic => format!("{}/messages", base),
+            ChatApiFormat::Ollama => format!("{}/api/chat", ollama_base(base)),
+        };
+        Self {
+            client: client.clone(),
+            url,
+            api_key: api_key.to_string(),
+            format,
+            timeout: Duration::from_secs(default_chat_timeout_secs()),
+        }
+    }
+
+    fn with_timeout(mut self, timeout_secs: u64) -> Self {
+        self.timeout = Duration::from_secs(timeout_secs);
+        self
+    }
+
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        let request = self.client.post(&self.url).json(body);
+        match self.format {
+            ChatApiFormat::OpenAi | ChatApiFormat::Ollama => {
+                request.header("Authorization", format!("Bearer {}", self.api_key))
+            }
+            ChatApiFormat::Anthropic => request
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION),
+        }
+    }
+}
+
+// 按协议构造请求体，采样参数由调用方另行写入
+fn build_chat_body(
+    format: ChatApiFormat,
+    model: &str,
+    system: Option<&str>,
+    user: &str,
+    stream: bool,
+) -> serde_json::Value {
+    match format {
+        ChatApiFormat::OpenAi | ChatApiFormat::Ollama => {
+            let mut messages = Vec::new();
+            if let Some(system) = system {
+                messages.push(serde_json::json!({ "role": "system", "content": system }));
+            }
+            messages.push(serde_json::json!({ "role": "user", "content": user }));
+            serde_json::json!({
+                "model": model,
+                "messages": messages,
+                "stream": stream,
+            })
+        }
+        ChatApiFormat::Anthropic => {
+            let mut body = serde_json::json!({
+                "model": model,
+                "messages": [{ "role": "user", "content": user }],
+                "max_tokens": ANTHROPIC_DEFAULT_MAX_TOKENS,
+                "stream": stream,
+            });
+            if let Some(system) = system {
+                body["system"] = serde_json::json!(system);
+            }
+            body
+        }
+    }
+}
+
+const MAX_SEND_ATTEMPTS: u32 = 3;
+
+// 发送请求：连接失败、超时、429 和 5xx 时按指数退避重试；构造请求或请求体出错不会因重试而改变，直接返回
+// 只重试建立连接/等待响应头阶段，拿到响应后（包括流式读取）不再重试。
+// timeout 限制每次尝试等待响应头的时间，退避等待不计入
+// on_retry(即将进行的第几次尝试, 最大尝试次数)
+async fn send_with_retry<F>(
+    request: reqwest::RequestBuilder,
+    service: &str,
+    timeout: Duration,
+    mut on_retry: F,
+) -> Result<reqwest::Response, AppError>
+where
+    F: FnMut(u32, u32),
+{
+    type Attempt = Result<Result<reqwest::Response, reqwest::Error>, tokio::time::error::Elapsed>;
+    let finish = |result: Attempt| match result {
+        Ok(result) => result.map_err(|e| request_error(e, service, timeout)),
+        Err(_) => Err(AppError::network(timeout_error(service, timeout))),
+    };
+    let mut attempt = 1;
+    loop {
+        let Some(req) = request.try_clone() else {
+            // 请求体无法复制（如流式 body），只能发送一次
+            return finish(tokio::time::timeout(timeout, request.send()).await);
+        };
+        let result = tokio::time::timeout(timeout, req.send()).await;
+
+        let retryable = match &result {
+            Ok(Ok(res)) => {
+                res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || res.status().is_server_error()
+            }
+            Ok(Err(e)) => e.is_connect() || e.is_timeout(),
+            Err(_) => true,
+        };
+
+        if !retryable || attempt >= MAX_SEND_ATTEMPTS {
+            return finish(result);
+        }
+
+        attempt += 1;
+        on_retry(attempt, MAX_SEND_ATTEMPTS);
+        let backoff = std::time::Duration::from_millis(500 * 2u64.pow(attempt - 2));
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+fn chat_status_error(status: reqwest::StatusCode, body: Option<String>) -> AppError {
+    let error = AppError::chat_api(format!("对话服务返回错误 (HTTP {})", status.as_u16()));
+    match body.filter(|b| !b.is_empty()) {
+        Some(body) => error.with_detail(body),
+        None => error,
+    }
+}
+
+fn missing_answer_error(json: &serde_json::Value) -> AppError {
+    AppError::chat_api("对话服务响应中没有回答内容").with_detail(json)
+}
+
+#[tracing::instrument(skip_all, fields(model = %model))]
+async fn call_llm(
+    client: &reqwest::Client,
+    model: &str,
+    prompt: &str,
+    base_url: &str,
+    api_key: &str,
+    api_format: &str,
+    timeout_secs: u64,
+) -> Result<String, AppError> {
+    let endpoint = ChatEndpoint::new(
+        client,
+        base_url,
+        api_key,
+        ChatApiFormat::from_setting(api_format),
+    )
+    .with_timeout(timeout_secs);
+    let map_err = |e: reqwest::Error| request_error(e, "对话服务", endpoint.timeout);
+
+    let mut req_body = build_chat_body(endpoint.format, model, None, prompt, false);
+    match endpoint.format {
+        ChatApiFormat::Ollama => req_body["options"] = serde_json::json!({ "temperature": 0.1 }),
+        _ => req_body["temperature"] = serde_json::json!(0.1),
+    }
+
+    let request = endpoint.request(&req_body).timeout(endpoint.timeout);
+    let res = send_with_retry(request, "对话服务", endpoint.timeout, |attempt, max| {
+        warn!("LLM request failed, retrying ({}/{})", attempt, max);
+    })
+    .await?;
+
+    if !res.status().is_success() {
+        return Err(chat_status_error(res.status(), None));
+    }
+
+    let json: serde_json::Value = res.json().await.map_err(map_err)?;
+    let (content, reasoning_field) = extract_full_answer(endpoint.format, &json);
+    let content = content.ok_or_else(|| missing_answer_error(&json))?;
+
+    // 推理内容只记录日志，不参与后续 JSON 解析
+    let (answer, reasoning) = split_think_blocks(&content);
+    let reasoning = reasoning_field.unwrap_or_default() + &reasoning;
+    if !reasoning.trim().is_empty() {
+        debug!("LLM Reasoning: {}", reasoning.trim());
+    }
+
+    Ok(answer)
+}
+
+fn clean_json_str(s: &str) -> String {
+    let mut content = s.to_string();
+
+    // 1. 移除 <think>...</think>
+    while let Some(start) = content.find("<think>") {
+        if let Some(end) = content.find("</think>") {
+            if end > start {
+                content.replace_range(start..end + 8, "");
+            } else {
+                content = content.replace("<think>", "").replace("</think>", "");
+            }
+        } else {
+            content = content.replace("<think>", "");
+        }
+    }
+
+    // 2. 智能提取 JSON (Array 或 Object)
+    let first_brace = content.find('{');
+    let first_bracket = content.find('[');
+    
+    let (start, end) = match (first_brace, first_bracket) {
+        (Some(brace), Some(bracket)) => {
+            if brace < bracket {
+                // 对象在数组前面，说明是 {...}
+                (brace, content.rfind('}'))
+            } else {
+                // 数组在对象前面，说明是 [...]
+                (bracket, content.rfind(']'))
+            }
+        },
+        (Some(brace), None) => (brace, content.rfind('}')),
+        (None, Some(bracket)) => (bracket, content.rfind(']')),
+        (None, None) => return content, // 没找到，直接返回原文本尝试解析
+    };
+
+    match (start, end) { // 这里的 start/end 是 usize，不是 Option
+        (s, Some(e)) if s <= e => content[s..=e].to_string(),
+        _ => content // 提取失败，返回原样
+    }
+}
+
+const CONTEXT_PLACEHOLDER: &str = "{context}";
+
+// 向量距离超过该值的检索结果视为不相关
+const RELEVANCE_DISTANCE_THRESHOLD: f32 = 1.2;
+
+// 是否至少有一条上下文通过相关度阈值（没有距离信息的条文视为相关）
+fn has_relevant_context(chunks: &[ChatContextChunk]) -> bool {
+    chunks.iter().any(|c| {
+        !c.distance
+            .is_some_and(|d| d >= RELEVANCE_DISTANCE_THRESHOLD)
+    })
+}
+
+// 解析搜索建议 JSON 数组，最多保留 3 条
+fn parse_search_suggestions(raw: &str) -> Vec<String> {
+    match serde_json::from_str::<Vec<String>>(&clean_json_str(raw)) {
+        Ok(list) => list
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .take(3)
+            .collect(),
+        Err(e) => {
+            warn!("Suggestion JSON Parse Error: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+// 取出非空的自定义提示词模版
+fn custom_template(template: &Option<String>) -> Option<&str> {
+    template.as_deref().filter(|t| !t.trim().is_empty())
+}
+
+// 保存前的设置校验：明显会导致检索/对话失败的配置记为 error，可疑但可用的记为 warning
+fn check_settings(settings: &AppSettings) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    if settings.search_top_k == 0 {
+        issues.push(ValidationIssue::error(
+            "search_top_k",
+            "检索返回数量必须大于 0",
+        ));
+    }
+    if settings.chat_top_k == 0 {
+        issues.push(ValidationIssue::error(
+            "chat_top_k",
+            "AI 参考数量必须大于 0",
+        ));
+    }
+    if settings.max_agent_loops < 0 {
+        issues.push(ValidationIssue::error(
+            "max_agent_loops",
+            "智能体最大轮数不能为负数（0 表示不限制）",
+        ));
+    }
+    if settings.history_limit == 0 {
+        issues.push(ValidationIssue::error(
+            "history_limit",
+            "搜索历史保留条数必须大于 0",
+        ));
+    }
+    if settings.workspace_limit == 0 {
+        issues.push(ValidationIssue::error(
+            "workspace_limit",
+            "工作区条文上限必须大于 0",
+        ));
+    }
+    let mut source_names = HashSet::new();
+    for source in &settings.data_sources {
+        let name = source.name.trim();
+        if name.is_empty() || name == PRIMARY_SOURCE_NAME {
+            issues.push(ValidationIssue::error(
+                "data_sources",
+                format!("数据源名称不能为空或使用“{}”", PRIMARY_SOURCE_NAME),
+            ));
+        } else if !source_names.insert(name) {
+            issues.push(ValidationIssue::error(
+                "data_sources",
+                format!("数据源名称重复: {}", name),
+            ));
+        }
+    }
+    // 映射值会拼进视图定义，只允许普通列名
+    let schema_map = &settings.content_schema_map;
+    for (table, spec, map) in [
+        ("chunks", CHUNKS_SCHEMA, &schema_map.chunks),
+        ("full_texts", FULL_TEXTS_SCHEMA, &schema_map.full_texts),
+    ] {
+        for (column, source) in map {
+            if !spec.iter().any(|(c, _, _)| c == column) {
+                issues.push(ValidationIssue::error(
+                    "content_schema_map",
+                    format!("{} 表没有标准列 {}", table, column),
+                ));
+            } else if source.is_empty() || !source.chars().all(|c| c.is_alphanumeric() || c == '_')
+            {
+                issues.push(ValidationIssue::error(
+                    "content_schema_map",
+                    format!("列名只能包含字母、数字和下划线: {}", source),
+                ));
+            }
+        }
+    }
+    let shortcut = settings.quick_search_shortcut.trim();
+    if !shortcut.is_empty()
+        && shortcut
+            .parse::<tauri_plugin_global_shortcut::Shortcut>()
+            .is_err()
+    {
+        issues.push(ValidationIssue::error(
+            "quick_search_shortcut",
+            format!("无法识别的快捷键: {}", shortcut),
+        ));
+    }
+    if settings.api_server_port == 0 {
+        issues.push(ValidationIssue::error(
+            "api_server_port",
+            "本地 API 端口必须大于 0",
+        ));
+    }
+    if settings.embedding_timeout_secs == 0 {
+        issues.push(ValidationIssue::error(
+            "embedding_timeout_secs",
+            "嵌入服务超时时间必须大于 0 秒",
+        ));
+    }
+    if settings.chat_timeout_secs == 0 {
+        issues.push(ValidationIssue::error(
+            "chat_timeout_secs",
+            "对话服务超时时间必须大于 0 秒",
+        ));
+    }
+
+    // 只校验正在使用的服务地址
+    let endpoints = [
+        (
+            "embedding_base_url",
+            "嵌入服务",
+            &settings.embedding_base_url,
+            &settings.embedding_api_key,
+            settings.embedding_provider != "local",
+        ),
+        (
+            "chat_base_url",
+            "对话服务",
+            &settings.chat_base_url,
+            &settings.chat_api_key,
+            settings.enable_ai_chat,
+        ),
+    ];
+    for (field, label, url, api_key, in_use) in endpoints {
+        if !in_use {
+            continue;
+        }
+        match reqwest::Url::parse(url.trim()) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+                let is_local = parsed.host_str().is_some_and(is_local_host);
+                if !is_local && api_key.trim() == "ollama" {
+                    issues.push(ValidationIssue::warning(
+                        field,
+                        format!("{}不是本机地址，但 API Key 仍为默认值 ollama", label),
+                    ));
+                }
+            }
+            _ => issues.push(ValidationIssue::error(
+                field,
+                format!("{}地址无效，需以 http:// 或 https:// 开头", label),
+            )),
+        }
+    }
+
+    if let Some(proxy) = settings
+        .http_proxy
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+    {
+        if reqwest::Proxy::all(proxy.trim()).is_err() {
+            issues.push(ValidationIssue::error("http_proxy", "代理地址无效"));
+        }
+    }
+
+    if let Some(path) = configured_extra_ca(settings) {
+        if let Err(e) = load_extra_ca_certs(path) {
+            issues.push(ValidationIssue::error("extra_ca_cert_path", e));
+        }
+    }
+    if settings.accept_invalid_certs {
+        issues.push(ValidationIssue::warning(
+            "accept_invalid_certs",
+            "已关闭 HTTPS 证书校验，API Key 与对话内容可能被截获，排查完毕后请关闭",
+        ));
+    }
+
+    if !matches!(
+        settings.log_level.trim().to_lowercase().as_str(),
+        "error" | "warn" | "info" | "debug" | "trace"
+    ) {
+        issues.push(ValidationIssue::warning(
+            "log_level",
+            format!("未知的日志级别 {}，将按 info 处理", settings.log_level),
+        ));
+    }
+
+    // 自定义提示词必须包含 {context} 占位符
+    let templates = [
+        (
+            "custom_deep_prompt",
+            "深度模式",
+            &settings.custom_deep_prompt,
+        ),
+        (
+            "custom_simple_prompt",
+            "普通模式",
+            &settings.custom_simple_prompt,
+        ),
+    ];
+    for (field, label, template) in templates {
+        if let Some(t) = custom_template(template) {
+            if !t.contains(CONTEXT_PLACEHOLDER) {
+                issues.push(ValidationIssue::error(
+                    field,
+                    format!("{}自定义提示词缺少 {} 占位符", label, CONTEXT_PLACEHOLDER),
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+fn is_local_host(host: &str) -> bool {
+    host == "localhost" || host == "[::1]" || host.starts_with("127.")
+}
+
+// 写入采样参数：单次覆盖 > 设置 > 内置默认；未设置的字段不写入请求体
+// Ollama 原生接口的采样参数放在 options 中，max_tokens 对应 num_predict
+fn apply_sampling_params(
+    body: &mut serde_json::Value,
+    format: ChatApiFormat,
+    settings: &AppSettings,
+    options: &ChatOptions,
+    mode: &str,
+) {
+    let default_temperature = if mode == "deep" { 0.4 } else { 0.3 };
+    let temperature = options
+        .temperature
+        .or(settings.chat_temperature)
+        .unwrap_or(default_temperature);
+    let max_tokens = options.max_tokens.or(settings.chat_max_tokens);
+    let top_p = options.top_p.or(settings.chat_top_p);
+
+    let (target, max_tokens_key) = match format {
+        ChatApiFormat::Ollama => {
+            body["options"] = serde_json::json!({});
+            (&mut body["options"], "num_predict")
+        }
+        _ => (body, "max_tokens"),
+    };
+
+    target["temperature"] = serde_json::json!(temperature);
+    if let Some(max_tokens) = max_tokens {
+        target[max_tokens_key] = serde_json::json!(max_tokens);
+    }
+    if let Some(top_p) = top_p {
+        target["top_p"] = serde_json::json!(top_p);
+    }
+}
+
+// 将上下文条文拼接为带编号的引用块，如 "[1] 《民法典》第577条"
+fn build_context_string(chunks: &[ChatContextChunk]) -> String {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format_context_entry(i + 1, c))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn format_context_entry(number: usize, chunk: &ChatContextChunk) -> String {
+    format!(
+        "[{}] 《{}》{}\n{}",
+        number,
+        chunk
+            .law_name
+            .trim_start_matches('《')
+            .trim_end_matches('》'),
+        chunk.article_number,
+        chunk.content
+    )
+}
+
+// 按相关度顺序保留完整条文，直到用尽字数预算；至少保留第一条。返回被丢弃的条数
+fn trim_chunks_to_budget(chunks: &mut Vec<ChatContextChunk>, budget: usize) -> usize {
+    let mut used = 0;
+    let mut keep = 0;
+    for (i, chunk) in chunks.iter().enumerate() {
+        // 条文之间的 "\n\n" 分隔也计入
+        let cost = format_context_entry(i + 1, chunk).chars().count() + 2;
+        if keep > 0 && used + cost > budget {
+            break;
+        }
+        used += cost;
+        keep += 1;
+    }
+    let dropped = chunks.len() - keep;
+    chunks.truncate(keep);
+    dropped
+}
+
+// 提取回答中的 [n] 编号（去重、按出现顺序），并映射回上下文条文
+fn collect_answer_references(answer: &str, chunks: &[ChatContextChunk]) -> Vec<ChatReference> {
+    let mut numbers: Vec<usize> = Vec::new();
+    let mut rest = answer;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let end = match rest.find(']') {
+            Some(end) => end,
+            None => break,
+        };
+        let inner = &rest[..end];
+        if !inner.is_empty() && inner.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(n) = inner.parse::<usize>() {
+                if !numbers.contains(&n) {
+                    numbers.push(n);
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+
+    numbers
+        .into_iter()
+        .map(|n| match n.checked_sub(1).and_then(|i| chunks.get(i)) {
+            Some(chunk) => ChatReference {
+                number: n,
+                chunk_id: Some(chunk.id.clone()),
+                law_name: Some(chunk.law_name.clone()),
+                article_number: Some(chunk.article_number.clone()),
+                valid: true,
+            },
+            None => ChatReference {
+                number: n,
+                chunk_id: None,
+                law_name: None,
+                article_number: None,
+                valid: false,
+            },
+        })
+        .collect()
+}
+
+// 法条引用：可选的《法名》+ 第X条（之一），与前端 LawDetailView 的识别规则保持一致；
+// 另外接受阿拉伯数字条号（第1043条），解析时统一改写为中文条号
+fn citation_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(《[^《》]+》)?(第(?:[一二三四五六七八九十百千零]+|\d{1,4})条(?:之(?:[一二三四五六七八九十]|\d))?)",
+        )
+        .unwrap()
+    })
+}
+
+struct ParsedCitation {
+    text: String,
+    start: usize,
+    end: usize,
+    law_name: String,
+    article_number: String,
+}
+
+// 解析文本中的法条引用；未写法名的“第X条”沿用前文最近出现的法名，没有则跳过
+fn parse_citations(text: &str) -> Vec<ParsedCitation> {
+    let mut citations = Vec::new();
+    let mut current_law: Option<String> = None;
+    let mut last_byte = 0;
+    let mut offset = 0;
+
+    for caps in citation_regex().captures_iter(text) {
+        let m = caps.get(0).unwrap();
+        offset += text[last_byte..m.start()].encode_utf16().count();
+        let len = m.as_str().encode_utf16().count();
+        last_byte = m.end();
+
+        if let Some(name) = caps.get(1) {
+            let name = name
+                .as_str()
+                .trim_start_matches('《')
+                .trim_end_matches('》');
+            current_law = Some(name.to_string());
+        }
+        if let Some(law_name) = &current_law {
+            citations.push(ParsedCitation {
+                text: m.as_str().to_string(),
+                start: offset,
+                end: offset + len,
+                law_name: law_name.clone(),
+                article_number: normalize_article_digits(&caps[2]),
+            });
+        }
+        offset += len;
+    }
+    citations
+}
+
+// 阿拉伯数字条号（第1043条、第10条之1）改写为库中使用的中文条号
+fn normalize_article_digits(text: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"第(\d{1,4})条(?:之(\d))?").unwrap());
+    re.replace_all(text, |caps: &regex::Captures| {
+        let main = to_chinese_numeral(caps[1].parse().unwrap_or(0));
+        match caps.get(2) {
+            Some(sub) => format!(
+                "第{}条之{}",
+                main,
+                to_chinese_numeral(sub.as_str().parse().unwrap_or(0))
+            ),
+            None => format!("第{}条", main),
+        }
+    })
+    .into_owned()
+}
+
+// 小于一万的数值转中文数字：1043 -> "一千零四十三"，12 -> "十二"
+fn to_chinese_numeral(n: u64) -> String {
+    const DIGITS: [char; 10] = ['零', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+    const UNITS: [&str; 4] = ["千", "百", "十", ""];
+    if n == 0 || n >= 10_000 {
+        return n.to_string().replace('0', "零");
+    }
+    let digits = [n / 1000, n / 100 % 10, n / 10 % 10, n % 10];
+    let mut out = String::new();
+    let mut pending_zero = false;
+    for (i, &d) in digits.iter().enumerate() {
+        if d == 0 {
+            pending_zero = !out.is_empty();
+            continue;
+        }
+        if pending_zero {
+            out.push('零');
+            pending_zero = false;
+        }
+        // “十二”不写作“一十二”
+        if !(d == 1 && i == 2 && out.is_empty()) {
+            out.push(DIGITS[d as usize]);
+        }
+        out.push_str(UNITS[i]);
+    }
+    out
+}
+
+// 单条引用文本的解析：支持阿拉伯数字条号，省略书名号时把“第X条”之前的文字视为法名，
+// 如“民法典第500条”
+fn parse_single_citation(text: &str) -> Option<ParsedCitation> {
+    let text = normalize_article_digits(text.trim());
+    if let Some(citation) = parse_citations(&text).into_iter().next() {
+        return Some(citation);
+    }
+    let caps = citation_regex().captures(&text)?;
+    let article = caps.get(2)?;
+    let law_name = text[..article.start()]
+        .trim()
+        .trim_matches(|c| c == '《' || c == '》')
+        .trim();
+    if law_name.is_empty() {
+        return None;
+    }
+    Some(ParsedCitation {
+        text: text[..article.end()].to_string(),
+        start: 0,
+        end: text[..article.end()].encode_utf16().count(),
+        law_name: law_name.to_string(),
+        article_number: article.as_str().to_string(),
+    })
+}
+
+// 单条引用对应的条文：法名按 resolve_law_name 解析为唯一法规后精确取条
+fn resolve_citation(
+    conn: &Connection,
+    citation: &ParsedCitation,
+) -> Result<Option<LawChunk>, AppError> {
+    match resolve_law_name(conn, &citation.law_name)? {
+        Some(law_name) => find_article_exact(conn, &law_name, &citation.article_number),
+        None => Ok(None),
+    }
+}
+
+// 按法名（模糊匹配）与条号在本地库中查找条文，返回 (完整法名, 条文内容)
+fn lookup_article(
+    conn: &Connection,
+    law_name: &str,
+    article_number: &str,
+) -> Result<Option<(String, String)>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let sql =
+        "SELECT law_name, content FROM chunks WHERE law_name LIKE ? ESCAPE '\\' AND article_number = ? LIMIT 1";
+    let law_pattern = like_contains(law_name);
+
+    conn.query_row(sql, rusqlite::params![law_pattern, article_number], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })
+    .optional()
+    .map_err(AppError::content_db_error)
+}
+
+// 转义用户输入中的 % 和 _，配合 ESCAPE '\' 使用
+fn like_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// LIKE 子串匹配的模式
+fn like_contains(text: &str) -> String {
+    format!("%{}%", like_escape(text))
+}
+
+// 与 lookup_article 相同的模糊匹配，返回完整条文；多部法规匹配时取名称最短的
+fn find_article_chunk(
+    conn: &Connection,
+    law_name: &str,
+    article_number: &str,
+) -> Result<Option<LawChunk>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let sql = format!(
+        "SELECT {} FROM chunks WHERE law_name LIKE ?1 ESCAPE '\\' AND article_number = ?2
+         ORDER BY length(law_name) LIMIT 1",
+        CHUNK_COLUMNS
+    );
+    conn.query_row(
+        &sql,
+        rusqlite::params![like_contains(law_name), article_number],
+        law_chunk_from_row,
+    )
+    .optional()
+    .map_err(AppError::content_db_error)
+}
+
+// 将回答中的法条引用逐条对照本地库，找不到的标记 exists=false（可能是模型编造）
+fn resolve_citations(conn: &Connection, answer: &str) -> Vec<ChatCitation> {
+    let mut cache: HashMap<(String, String), Option<String>> = HashMap::new();
+    parse_citations(answer)
+        .into_iter()
+        .map(|c| {
+            let key = (c.law_name.clone(), c.article_number.clone());
+            let resolved = cache
+                .entry(key)
+                .or_insert_with(|| {
+                    lookup_article(conn, &c.law_name, &c.article_number)
+                        .ok()
+                        .flatten()
+                        .map(|(name, _)| name)
+                })
+                .clone();
+            ChatCitation {
+                text: c.text,
+                start: c.start,
+                end: c.end,
+                exists: resolved.is_some(),
+                law_name: resolved.unwrap_or(c.law_name),
+                article_number: c.article_number,
+            }
+        })
+        .collect()
+}
+
+// <think>...</think> 推理块拆分器
+// 流式输出时标签可能被拆到多个 delta 中，未闭合的标签前缀会暂存在 carry 里
+#[derive(Default)]
+struct ThinkTagSplitter {
+    in_think: bool,
+    carry: String,
+}
+
+impl ThinkTagSplitter {
+    const OPEN: &'static str = "<think>";
+    const CLOSE: &'static str = "</think>";
+
+    // 输入一段增量文本，返回 (正文, 推理内容)
+    fn feed(&mut self, delta: &str) -> (String, String) {
+        let input = std::mem::take(&mut self.carry) + delta;
+        let mut answer = String::new();
+        let mut reasoning = String::new();
+        let mut rest = input.as_str();
+
+        loop {
+            let tag = if self.in_think {
+                Self::CLOSE
+            } else {
+                Self::OPEN
+            };
+            let target = if self.in_think {
+                &mut reasoning
+            } else {
+                &mut answer
+            };
+
+            if let Some(pos) = rest.find(tag) {
+                target.push_str(&rest[..pos]);
+                rest = &rest[pos + tag.len()..];
+                self.in_think = !self.in_think;
+                continue;
+            }
+
+            // 末尾可能是被截断的标签，先留着等下一个 delta
+            let keep = (1..tag.len())
+                .rev()
+                .find(|len| rest.ends_with(&tag[..*len]))
+                .unwrap_or(0);
+            let split = rest.len() - keep;
+            target.push_str(&rest[..split]);
+            self.carry = rest[split..].to_string();
+            break;
+        }
+
+        (answer, reasoning)
+    }
+
+    // 流结束时把暂存内容原样吐出
+    fn finish(&mut self) -> (String, String) {
+        let carry = std::mem::take(&mut self.carry);
+        if self.in_think {
+            (String::new(), carry)
+        } else {
+            (carry, String::new())
+        }
+    }
+}
+
+// 一次性拆分完整文本（非流式响应）
+fn split_think_blocks(text: &str) -> (String, String) {
+    let mut splitter = ThinkTagSplitter::default();
+    let (mut answer, mut reasoning) = splitter.feed(text);
+    let (tail_answer, tail_reasoning) = splitter.finish();
+    answer.push_str(&tail_answer);
+    reasoning.push_str(&tail_reasoning);
+    (answer, reasoning)
+}
+
+// 单次流式回答的累积状态
+#[derive(Default)]
+struct ChatStreamState {
+    think: ThinkTagSplitter,
+    answer: String,
+    summary: ChatDoneEvent,
+}
+
+// 将正文和推理内容分别发送到 {event_id} 与 {event_id}-reasoning
+fn emit_chat_delta<R: Runtime>(
+    app: &AppHandle<R>,
+    event_id: &str,
+    answer: &str,
+    reasoning: &str,
+    stream: &mut ChatStreamState,
+) {
+    if !reasoning.is_empty() {
+        let _ = app.emit(&format!("{}-reasoning", event_id), reasoning);
+    }
+    if !answer.is_empty() {
+        stream.summary.total_chars += answer.chars().count();
+        stream.answer.push_str(answer);
+        let _ = app.emit(event_id, answer);
+    }
+}
+
+// 流中一行数据的处理结果；Failed 为服务端在流中返回的错误，整个回答以错误结束
+#[derive(Debug, PartialEq)]
+enum LineOutcome {
+    Continue,
+    Done,
+    Failed(String),
+}
+
+// 解析一行 SSE 数据并转发 token
+fn process_sse_line<R: Runtime>(
+    line: &str,
+    app: &AppHandle<R>,
+    event_id: &str,
+    format: ChatApiFormat,
+    stream: &mut ChatStreamState,
+) -> LineOutcome {
+    let line = line.trim();
+    if format == ChatApiFormat::Ollama {
+        return process_ollama_line(line, app, event_id, stream);
+    }
+
+    // Anthropic 的 "event:" 行只是类型提示，data 中已包含 type 字段
+    let json_str = match line.strip_prefix("data:") {
+        Some(rest) => rest.trim(),
+        None => return LineOutcome::Continue,
+    };
+    if json_str == "[DONE]" {
+        return LineOutcome::Done;
+    }
+
+    let json = match serde_json::from_str::<serde_json::Value>(json_str) {
+        Ok(json) => json,
+        Err(_) => return LineOutcome::Continue,
+    };
+
+    if format == ChatApiFormat::Anthropic {
+        return process_anthropic_event(&json, app, event_id, stream);
+    }
+
+    let delta = &json["choices"][0]["delta"];
+
+    // deepseek-r1 等模型通过独立字段返回推理内容
+    let reasoning_field = delta["reasoning_content"]
+        .as_str()
+        .or_else(|| delta["reasoning"].as_str());
+    if let Some(reasoning) = reasoning_field {
+        emit_chat_delta(app, event_id, "", reasoning, stream);
+    }
+
+    let content = delta["content"]
+        .as_str()
+        .or_else(|| json["message"]["content"].as_str());
+    if let Some(content) = content {
+        let (answer, reasoning) = stream.think.feed(content);
+        emit_chat_delta(app, event_id, &answer, &reasoning, stream);
+    }
+
+    if let Some(reason) = json["choices"][0]["finish_reason"].as_str() {
+        stream.summary.finish_reason = Some(reason.to_string());
+    }
+    if json["usage"].is_object() {
+        stream.summary.usage = Some(json["usage"].clone());
+    }
+
+    LineOutcome::Continue
+}
+
+// 处理 Anthropic Messages API 的流式事件，message_stop 为 Done
+fn process_anthropic_event<R: Runtime>(
+    json: &serde_json::Value,
+    app: &AppHandle<R>,
+    event_id: &str,
+    stream: &mut ChatStreamState,
+) -> LineOutcome {
+    match json["type"].as_str().unwrap_or_default() {
+        "content_block_delta" => {
+            let delta = &json["delta"];
+            if let Some(thinking) = delta["thinking"].as_str() {
+                emit_chat_delta(app, event_id, "", thinking, stream);
+            }
+            if let Some(text) = delta["text"].as_str() {
+                let (answer, reasoning) = stream.think.feed(text);
+                emit_chat_delta(app, event_id, &answer, &reasoning, stream);
+            }
+        }
+        "message_start" => {
+            merge_anthropic_usage(&mut stream.summary, &json["message"]["usage"]);
+        }
+        "message_delta" => {
+            if let Some(reason) = json["delta"]["stop_reason"].as_str() {
+                stream.summary.finish_reason = Some(map_anthropic_stop_reason(reason));
+            }
+            merge_anthropic_usage(&mut stream.summary, &json["usage"]);
+        }
+        "message_stop" => return LineOutcome::Done,
+        "error" => return LineOutcome::Failed(json["error"].to_string()),
+        _ => {}
+    }
+    LineOutcome::Continue
+}
+
+// 处理 Ollama /api/chat 的一行 NDJSON，done 为 Done
+fn process_ollama_line<R: Runtime>(
+    line: &str,
+    app: &AppHandle<R>,
+    event_id: &str,
+    stream: &mut ChatStreamState,
+) -> LineOutcome {
+    if line.is_empty() {
+        return LineOutcome::Continue;
+    }
+    let json = match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(json) => json,
+        Err(_) => return LineOutcome::Continue,
+    };
+
+    if let Some(err) = json["error"].as_str() {
+        return LineOutcome::Failed(err.to_string());
+    }
+
+    if let Some(thinking) = json["message"]["thinking"].as_str() {
+        emit_chat_delta(app, event_id, "", thinking, stream);
+    }
+    if let Some(content) = json["message"]["content"].as_str() {
+        let (answer, reasoning) = stream.think.feed(content);
+        emit_chat_delta(app, event_id, &answer, &reasoning, stream);
+    }
+
+    if json["done"].as_bool().unwrap_or(false) {
+        apply_ollama_summary(&mut stream.summary, &json);
+        return LineOutcome::Done;
+    }
+    LineOutcome::Continue
+}
+
+// Ollama 在最后一条消息中给出 done_reason 与 token 统计
+fn apply_ollama_summary(summary: &mut ChatDoneEvent, json: &serde_json::Value) {
+    if let Some(reason) = json["done_reason"].as_str() {
+        summary.finish_reason = Some(reason.to_string());
+    }
+    let prompt_tokens = json["prompt_eval_count"].as_u64();
+    let completion_tokens = json["eval_count"].as_u64();
+    if prompt_tokens.is_some() || completion_tokens.is_some() {
+        let prompt_tokens = prompt_tokens.unwrap_or(0);
+        let completion_tokens = completion_tokens.unwrap_or(0);
+        summary.usage = Some(serde_json::json!({
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        }));
+    }
+}
+
+// Anthropic stop_reason 映射为 OpenAI 风格的 finish_reason
+fn map_anthropic_stop_reason(reason: &str) -> String {
+    match reason {
+        "end_turn" | "stop_sequence" => "stop".to_string(),
+        "max_tokens" => "length".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// Anthropic 的 input/output_tokens 分散在 message_start 与 message_delta 中，合并成 OpenAI 风格 usage
+fn merge_anthropic_usage(summary: &mut ChatDoneEvent, usage: &serde_json::Value) {
+    if !usage.is_object() {
+        return;
+    }
+    let mut merged = summary
+        .usage
+        .take()
+        .unwrap_or_else(|| serde_json::json!({}));
+    if let Some(input) = usage["input_tokens"].as_u64() {
+        merged["prompt_tokens"] = serde_json::json!(input);
+    }
+    if let Some(output) = usage["output_tokens"].as_u64() {
+        merged["completion_tokens"] = serde_json::json!(output);
+    }
+    let total = merged["prompt_tokens"].as_u64().unwrap_or(0)
+        + merged["completion_tokens"].as_u64().unwrap_or(0);
+    merged["total_tokens"] = serde_json::json!(total);
+    summary.usage = Some(merged);
+}
+
+// 流式请求的结果：正常完成，或网关不支持 stream=true
+enum StreamOutcome {
+    Completed,
+    Rejected(String),
+}
+
+// 网关拒绝流式请求：400/422，且错误的 param 为 stream 或错误信息提到 stream。
+// 模型不存在、上下文过长等其他 400 直接报错，不再以非流式重发
+fn is_stream_rejection(status: reqwest::StatusCode, body: &str) -> bool {
+    if status != reqwest::StatusCode::BAD_REQUEST
+        && status != reqwest::StatusCode::UNPROCESSABLE_ENTITY
+    {
+        return false;
+    }
+    let param_is_stream = serde_json::from_str::<serde_json::Value>(body)
+        .is_ok_and(|json| json["error"]["param"].as_str() == Some("stream"));
+    param_is_stream || mentions_stream(body)
+}
+
+// 按单词匹配 stream，"upstream" 之类的网关错误不算
+fn mentions_stream(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.match_indices("stream").any(|(i, _)| {
+        !text[..i]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphabetic())
+    })
+}
+
+// 发送 chat 请求（带重试），重试进度通过 {event_id}-status 通知前端
+async fn send_chat_request<R: Runtime>(
+    app: &AppHandle<R>,
+    endpoint: &ChatEndpoint,
+    body: &serde_json::Value,
+    event_id: &str,
+) -> Result<reqwest::Response, AppError> {
+    let status_event = format!("{}-status", event_id);
+    // 每次尝试只限制等待响应头的时间，流式正文的读取由调用方按数据包单独计时
+    send_with_retry(
+        endpoint.request(body),
+        "对话服务",
+        endpoint.timeout,
+        |attempt, max| {
+            let _ = app.emit(
+                &status_event,
+                format!("连接失败，正在重试 ({}/{})", attempt, max),
+            );
+        },
+    )
+    .await
+}
+
+fn chat_timeout_error(endpoint: &ChatEndpoint) -> AppError {
+    AppError::network(timeout_error("对话服务", endpoint.timeout))
+}
+
+// 从非流式响应中取出 (回答, 推理字段)
+fn extract_full_answer(
+    format: ChatApiFormat,
+    json: &serde_json::Value,
+) -> (Option<String>, Option<String>) {
+    match format {
+        ChatApiFormat::OpenAi => {
+            let message = &json["choices"][0]["message"];
+            let content = message["content"]
+                .as_str()
+                .or_else(|| json["message"]["content"].as_str())
+                .map(|s| s.to_string());
+            let reasoning = message["reasoning_content"].as_str().map(|s| s.to_string());
+            (content, reasoning)
+        }
+        ChatApiFormat::Ollama => {
+            let message = &json["message"];
+            let content = message["content"].as_str().map(|s| s.to_string());
+            let reasoning = message["thinking"].as_str().map(|s| s.to_string());
+            (content, reasoning)
+        }
+        ChatApiFormat::Anthropic => {
+            let blocks = match json["content"].as_array() {
+                Some(blocks) => blocks,
+                None => return (None, None),
+            };
+            let mut text = String::new();
+            let mut thinking = String::new();
+            for block in blocks {
+                match block["type"].as_str() {
+                    Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+                    Some("thinking") => {
+                        thinking.push_str(block["thinking"].as_str().unwrap_or_default())
+                    }
+                    _ => {}
+                }
+            }
+            let thinking = if thinking.is_empty() {
+                None
+            } else {
+                Some(thinking)
+            };
+            (Some(text), thinking)
+        }
+    }
+}
+
+// 将非流式响应的完整回答一次性发送，前端仍按 token 事件处理
+fn emit_full_answer<R: Runtime>(
+    app: &AppHandle<R>,
+    event_id: &str,
+    format: ChatApiFormat,
+    json: &serde_json::Value,
+    chat: &mut ChatStreamState,
+) -> Result<(), AppError> {
+    let (content, reasoning_field) = extract_full_answer(format, json);
+    if let Some(reasoning) = reasoning_field {
+        emit_chat_delta(app, event_id, "", &reasoning, chat);
+    }
+
+    let content = content.ok_or_else(|| missing_answer_error(json))?;
+    let (answer, reasoning) = chat.think.feed(&content);
+    emit_chat_delta(app, event_id, &answer, &reasoning, chat);
+
+    match format {
+        ChatApiFormat::OpenAi => {
+            if let Some(reason) = json["choices"][0]["finish_reason"].as_str() {
+                chat.summary.finish_reason = Some(reason.to_string());
+            }
+            if json["usage"].is_object() {
+                chat.summary.usage = Some(json["usage"].clone());
+            }
+        }
+        ChatApiFormat::Anthropic => {
+            if let Some(reason) = json["stop_reason"].as_str() {
+                chat.summary.finish_reason = Some(map_anthropic_stop_reason(reason));
+            }
+            merge_anthropic_usage(&mut chat.summary, &json["usage"]);
+        }
+        ChatApiFormat::Ollama => apply_ollama_summary(&mut chat.summary, json),
+    }
+    Ok(())
+}
+
+// 流式输出中途收到的服务端错误：由调用方发出 -error，不再发出 -citations / -done
+fn stream_error(message: String) -> AppError {
+    AppError::chat_api("对话服务返回错误").with_detail(message)
+}
+
+async fn run_streaming_chat<R: Runtime>(
+    app: &AppHandle<R>,
+    endpoint: &ChatEndpoint,
+    body: &serde_json::Value,
+    event_id: &str,
+    chat: &mut ChatStreamState,
+) -> Result<StreamOutcome, AppError> {
+    let res = send_chat_request(app, endpoint, body, event_id).await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await.unwrap_or_default();
+        if is_stream_rejection(status, &text) {
+            return Ok(StreamOutcome::Rejected(format!("{} {}", status, text)));
+        }
+        return Err(chat_status_error(status, Some(text)));
+    }
+
+    let is_json = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("application/json"))
+        .unwrap_or(false);
+    if is_json {
+        let json: serde_json::Value = res
+            .json()
+            .await
+            .map_err(|e| request_error(e, "对话服务", endpoint.timeout))?;
+        if let Some(err) = json.get("error") {
+            let message = err.to_string();
+            if mentions_stream(&message) {
+                return Ok(StreamOutcome::Rejected(message));
+            }
+            return Err(AppError::chat_api("对话服务返回错误").with_detail(message));
+        }
+        // 网关忽略了 stream 参数，直接返回了完整回答
+        emit_full_answer(app, event_id, endpoint.format, &json, chat)?;
+        return Ok(StreamOutcome::Completed);
+    }
+
+    // 按字节缓冲，避免多字节中文字符或 SSE 行被拆分到两个网络包中
+    let mut pending: Vec<u8> = Vec::new();
+    let mut stream = res.bytes_stream();
+
+    'outer: loop {
+        let item = tokio::time::timeout(endpoint.timeout, stream.next())
+            .await
+            .map_err(|_| chat_timeout_error(endpoint))?;
+        let bytes = match item {
+            Some(item) => item.map_err(|e| AppError::network("对话输出中断").with_detail(e))?,
+            None => break,
+        };
+        pending.extend_from_slice(&bytes);
+        while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes);
+            match process_sse_line(&line, app, event_id, endpoint.format, chat) {
+                LineOutcome::Continue => {}
+                LineOutcome::Done => break 'outer,
+                LineOutcome::Failed(message) => return Err(stream_error(message)),
+            }
+        }
+    }
+
+    // 处理末尾没有换行符的残留数据
+    if !pending.is_empty() {
+        let line = String::from_utf8_lossy(&pending).to_string();
+        if let LineOutcome::Failed(message) =
+            process_sse_line(&line, app, event_id, endpoint.format, chat)
+        {
+            return Err(stream_error(message));
+        }
+    }
+
+    Ok(StreamOutcome::Completed)
+}
+
+async fn run_blocking_chat<R: Runtime>(
+    app: &AppHandle<R>,
+    endpoint: &ChatEndpoint,
+    body: &serde_json::Value,
+    event_id: &str,
+    chat: &mut ChatStreamState,
+) -> Result<(), AppError> {
+    let res = send_chat_request(app, endpoint, body, event_id).await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await.unwrap_or_default();
+        return Err(chat_status_error(status, Some(text)));
+    }
+
+    let json: serde_json::Value = tokio::time::timeout(endpoint.timeout, res.json())
+        .await
+        .map_err(|_| chat_timeout_error(endpoint))?
+        .map_err(|e| request_error(e, "对话服务", endpoint.timeout))?;
+    emit_full_answer(app, event_id, endpoint.format, &json, chat)
+}
+
+// ==========================================
+// 4. 核心逻辑
+// ==========================================
+
+// 列顺序：id, content, law_name, category, region, publish_date, part, chapter, article_number
+const CHUNK_COLUMNS: &str =
+    "id, content, law_name, category, region, publish_date, part, chapter, article_number";
+
+fn law_chunk_from_row(row: &rusqlite::Row) -> rusqlite::Result<LawChunk> {
+    let law_name: String = row.get(2)?;
+    Ok(LawChunk {
+        id: row.get(0)?,
+        _distance: 0.0,
+        content: row.get(1)?,
+        source_file: format!("{}.txt", law_name),
+        law_name,
+        category: row.get(3)?,
+        region: row.get(4)?,
+        publish_date: row.get(5)?,
+        part: row.get(6).unwrap_or_default(),
+        chapter: row.get(7).unwrap_or_default(),
+        article_number: row.get(8)?,
+        content_truncated: false,
+        source_name: None,
+    })
+}
+
+const SEARCH_CACHE_CAPACITY: usize = 30;
+
+const INDEX_META_FILE: &str = "index_meta.json";
+
+fn index_meta_path(data_dir: &std::path::Path) -> PathBuf {
+    data_dir.join("law_db.lancedb").join(INDEX_META_FILE)
+}
+
+// 旧版本构建的向量库没有该文件，此时不做模型校验
+fn read_index_meta(data_dir: &std::path::Path) -> Option<IndexMeta> {
+    let content = fs::read_to_string(index_meta_path(data_dir)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// LanceDB 默认按 L2 距离检索
+fn write_index_meta(
+    data_dir: &std::path::Path,
+    model: &str,
+    dimension: usize,
+) -> std::io::Result<()> {
+    let meta = IndexMeta {
+        embedding_model: model.to_string(),
+        dimension,
+        metric: "l2".to_string(),
+        built_at: chrono::Local::now().to_rfc3339(),
+    };
+    let json = serde_json::to_string_pretty(&meta).unwrap_or_default();
+    write_file_atomic(&index_meta_path(data_dir), json.as_bytes())
+}
+
+// 首次检索时对照向量库记录的模型与当前设置，结果缓存到设置或数据变化为止
+fn check_index_meta(state: &AppState, settings: &AppSettings, data_dir: &std::path::Path) {
+    let mut check = state.index_meta_check.lock_or_recover();
+    if check.is_some() {
+        return;
+    }
+    let mismatch = read_index_meta(data_dir)
+        .filter(|meta| meta.embedding_model != settings.embedding_model)
+        .map(|meta| IndexModelMismatch {
+            index_model: meta.embedding_model,
+            configured_model: settings.embedding_model.clone(),
+            built_at: meta.built_at,
+        });
+    if let Some(m) = &mismatch {
+        warn!(
+            "Vector index was built with {} but {} is configured",
+            m.index_model, m.configured_model
+        );
+    }
+    *check = Some(IndexMetaCheck {
+        mismatch,
+        reported: false,
+    });
+}
+
+// 取出尚未提示过的模型不一致，每次校验只提示一次
+fn take_index_mismatch(state: &AppState) -> Option<IndexModelMismatch> {
+    let mut check = state.index_meta_check.lock_or_recover();
+    let check = check.as_mut().filter(|c| !c.reported)?;
+    check.reported = true;
+    check.mismatch.clone()
+}
+
+// 缓存键中的模型标识：同名模型换了服务商也视为不同模型
+fn embedding_cache_id(settings: &AppSettings) -> String {
+    format!(
+        "{}:{}",
+        settings.embedding_provider, settings.embedding_model
+    )
+}
+
+fn cached_search_hits(state: &AppState, key: &SearchCacheKey) -> Option<Vec<(String, f32)>> {
+    let mut cache = state.search_result_cache.lock_or_recover();
+    cache.get_mut(key).map(|entry| {
+        entry.used_at = Instant::now();
+        entry.hits.clone()
+    })
+}
+
+// 超出容量时淘汰最久未使用的一条
+fn cache_search_hits(state: &AppState, key: SearchCacheKey, hits: Vec<(String, f32)>) {
+    let mut cache = state.search_result_cache.lock_or_recover();
+    while cache.len() >= SEARCH_CACHE_CAPACITY && !cache.contains_key(&key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.used_at)
+            .map(|(k, _)| k.clone());
+        match oldest {
+            Some(k) => cache.remove(&k),
+            None => break,
+        };
+    }
+    cache.insert(
+        key,
+        CachedSearch {
+            hits,
+            used_at: Instant::now(),
+        },
+    );
+}
+
+// 记录一次异步命令的完成耗时，并按返回的 Result 区分成功与失败（AppError 与 String 错误都计入）
+async fn metered<T, E>(
+    metrics: &MetricsRegistry,
+    command: &str,
+    task: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = task.await;
+    metrics.record(command, started.elapsed(), result.is_err());
+    result
+}
+
+// 同步命令的对应版本：failed 判断返回值是否算作失败
+fn metered_sync<T>(
+    metrics: &MetricsRegistry,
+    command: &str,
+    failed: impl FnOnce(&T) -> bool,
+    task: impl FnOnce() -> T,
+) -> T {
+    let started = Instant::now();
+    let result = task();
+    metrics.record(command, started.elapsed(), failed(&result));
+    result
+}
+
+// 包装命令：以函数名为命令名，在命令返回时记录一次耗时，返回 Result 的命令同时记录失败。
+// 调度处看不到命令的返回值，因此由命令自身记录。追加的 State 参数不读取前端传参，
+// 也不占用命令原有的参数名
+macro_rules! metered_command {
+    (
+        $(#[$attr:meta])*
+        async fn $name:ident($($($arg:ident)+: $ty:ty),* $(,)?) -> $ret:ty $body:block
+    ) => {
+        $(#[$attr])*
+        #[allow(clippy::too_many_arguments)]
+        async fn $name($($($arg)+: $ty,)* metrics_state: tauri::State<'_, AppState>) -> $ret {
+            metered(&metrics_state.metrics, stringify!($name), async $body).await
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        fn $name:ident($($($arg:ident)+: $ty:ty),* $(,)?) -> Result<$ok:ty, $err:ty> $body:block
+    ) => {
+        $(#[$attr])*
+        #[allow(clippy::too_many_arguments)]
+        fn $name(
+            $($($arg)+: $ty,)*
+            metrics_state: tauri::State<'_, AppState>,
+        ) -> Result<$ok, $err> {
+            metered_sync(
+                &metrics_state.metrics,
+                stringify!($name),
+                Result::is_err,
+                move || -> Result<$ok, $err> { $body },
+            )
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        fn $name:ident($($($arg:ident)+: $ty:ty),* $(,)?) $(-> $ret:ty)? $body:block
+    ) => {
+        $(#[$attr])*
+        #[allow(clippy::too_many_arguments)]
+        fn $name($($($arg)+: $ty,)* metrics_state: tauri::State<'_, AppState>) $(-> $ret)? {
+            metered_sync(&metrics_state.metrics, stringify!($name), |_| false, move || $body)
+        }
+    };
+}
+
+// 只走向量检索，嵌入服务不可用时返回错误。
+// Agent、MCP、本地 HTTP API 与术语释义按相关度取用结果，不能混入关键词匹配的结果
+pub async fn search_law_logic(
+    query: String,
+    filter_region: Option<String>,
+    preview_chars: usize,
+    state: &AppState,
+) -> Result<Vec<LawChunk>, AppError> {
+    let config = SearchConfig::capture(state);
+    vector_search_law(query, filter_region, preview_chars, &config, state)
+        .await
+        .map(|outcome| outcome.results)
+}
+
+pub async fn search_law_with_fallback(
+    query: String,
+    filter_region: Option<String>,
+    preview_chars: usize,
+    state: &AppState,
+) -> Result<SearchOutcome, AppError> {
+    let config = SearchConfig::capture(state);
+    search_with_config(query, filter_region, preview_chars, &config, state).await
+}
+
+// 嵌入服务连不上时不让检索整体失败：改用 content.db 的关键词匹配，并记录降级原因
+pub async fn search_with_config(
+    query: String,
+    filter_region: Option<String>,
+    preview_chars: usize,
+    config: &SearchConfig,
+    state: &AppState,
+) -> Result<SearchOutcome, AppError> {
+    match vector_search_law(
+        query.clone(),
+        filter_region.clone(),
+        preview_chars,
+        config,
+        state,
+    )
+    .await
+    {
+        Ok(outcome) => {
+            *state.embedding_outage.lock_or_recover() = None;
+            Ok(outcome)
+        }
+        Err(AppError::Network(info)) => {
+            let reason = info.message.clone();
+            warn!(
+                "Embedding service unavailable, falling back to keyword search: {}",
+                reason
+            );
+            {
+                let mut outage = state.embedding_outage.lock_or_recover();
+                if outage.is_none() {
+                    *outage = Some(EmbeddingOutage {
+                        reason: reason.clone(),
+                        reported: false,
+                    });
+                }
+            }
+            let conn = state
+                .content_pool
+                .get(&config.data_dir, |dir| connect_sqlite(state, dir))?;
+            let results = keyword_search_chunks(
+                &conn,
+                &query,
+                filter_region.as_deref(),
+                config.settings.search_top_k,
+                preview_chars,
+            )?;
+            Ok(SearchOutcome {
+                results,
+                degraded: Some(reason),
+                cache_hit: false,
+            })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// 取出尚未提示过的嵌入服务中断
+fn take_embedding_outage(state: &AppState) -> Option<EmbeddingUnavailableEvent> {
+    let mut outage = state.embedding_outage.lock_or_recover();
+    let outage = outage.as_mut().filter(|o| !o.reported)?;
+    outage.reported = true;
+    Some(EmbeddingUnavailableEvent {
+        reason: outage.reason.clone(),
+    })
+}
+
+// 关键词检索用到的虚词，按这些字词切分查询
+const KEYWORD_STOP_WORDS: &[&str] = &[
+    "请问", "如何", "怎么", "怎样", "什么", "哪些", "可以", "能否", "是否", "应该", "需要", "没有",
+    "的", "了", "吗", "呢", "是", "在", "和", "与", "或", "及", "我", "你", "他", "被", "对", "把",
+    "有",
+];
+// 关键词最多取这么多个，避免 SQL 条件过长
+const KEYWORD_LIMIT: usize = 12;
+
+// 从查询中取关键词：按标点与虚词切分，超过 4 个字的片段再拆成相邻两字
+fn extract_search_keywords(query: &str) -> Vec<String> {
+    let mut text = query.to_string();
+    for word in KEYWORD_STOP_WORDS {
+        text = text.replace(word, " ");
+    }
+    let mut keywords: Vec<String> = Vec::new();
+    for segment in text.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<char> = segment.chars().collect();
+        let pieces: Vec<String> = if chars.len() <= 4 {
+            vec![segment.to_string()]
+        } else {
+            chars.windows(2).map(|w| w.iter().collect()).collect()
+        };
+        for piece in pieces {
+            if piece.chars().count() >= 2 && !keywords.contains(&piece) {
+                keywords.push(piece);
+            }
+        }
+    }
+    keywords.truncate(KEYWORD_LIMIT);
+    keywords
+}
+
+// 按命中关键词的个数排序；_distance 由命中比例换算（全部命中为 0），前端的相关度显示照常可用
+fn keyword_search_chunks(
+    conn: &Connection,
+    query: &str,
+    filter_region: Option<&str>,
+    top_k: usize,
+    preview_chars: usize,
+) -> Result<Vec<LawChunk>, AppError> {
+    let keywords = extract_search_keywords(query);
+    if keywords.is_empty() {
+        return Ok(Vec::new());
+    }
+    let score = keywords
+        .iter()
+        .enumerate()
+        .map(|(i, _)| format!("(content LIKE ?{})", i + 1))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    // 子查询的列都起别名，外层按别名排序、按列名读取；预览时 content 是截断后的文本
+    let content = if preview_chars > 0 {
+        format!("substr(content, 1, {}) AS content", preview_chars)
+    } else {
+        "content".to_string()
+    };
+    let sql = format!(
+        "SELECT * FROM (SELECT {}, length(content) AS full_len, {} AS score FROM chunks)
+         WHERE score > 0 ORDER BY score DESC, full_len LIMIT {}",
+        CHUNK_COLUMNS.replacen("content", &content, 1),
+        score,
+        top_k * 3
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let patterns: Vec<String> = keywords.iter().map(|k| format!("%{}%", k)).collect();
+    let total = keywords.len() as f32;
+    let chunks = stmt
+        .query_map(rusqlite::params_from_iter(patterns.iter()), |row| {
+            let mut chunk = law_chunk_from_row(row)?;
+            if preview_chars > 0 {
+                let full_len: i64 = row.get("full_len")?;
+                chunk.content_truncated = full_len > preview_chars as i64;
+                if chunk.content_truncated {
+                    cut_at_sentence(&mut chunk.content);
+                }
+            }
+            let score: i64 = row.get("score")?;
+            chunk._distance = 2.0 * (1.0 - score as f32 / total);
+            Ok(chunk)
+        })
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+    Ok(chunks
+        .into_iter()
+        .filter(|chunk| region_allows(chunk, filter_region))
+        .take(top_k)
+        .collect())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(query = %log_query(&query), cache_hit = tracing::field::Empty)
+)]
+async fn vector_search_law(
+    query: String,
+    filter_region: Option<String>,
+    preview_chars: usize,
+    config: &SearchConfig,
+    state: &AppState,
+) -> Result<SearchOutcome, AppError> {
+    let started = Instant::now();
+    let (settings, data_dir) = (&config.settings, &config.data_dir);
+    check_index_meta(state, settings, data_dir);
+    let key = SearchCacheKey {
+        query,
+        filter_region: filter_region.clone(),
+        embedding_model: embedding_cache_id(settings),
+        top_k: settings.search_top_k,
+        data_dir: data_dir.clone(),
+    };
+
+    let extra_sources = enabled_extra_sources(state, settings);
+    if !extra_sources.is_empty() {
+        let outcome = search_all_sources(key, config, &extra_sources, preview_chars, state).await?;
+        info!(
+            "Search across {} sources finished in {}ms",
+            extra_sources.len() + 1,
+            started.elapsed().as_millis()
+        );
+        return Ok(outcome);
+    }
+
+    if settings.enable_search_cache {
+        if let Some(hits) = cached_search_hits(state, &key) {
+            tracing::Span::current().record("cache_hit", true);
+            let results = hydrate_search_hits(
+                state,
+                data_dir,
+                &hits,
+                filter_region,
+                settings.search_top_k,
+                preview_chars,
+            )?;
+            info!(
+                "Search served from cache in {}ms",
+                started.elapsed().as_millis()
+            );
+            return Ok(SearchOutcome {
+                results,
+                degraded: None,
+                cache_hit: true,
+            });
+        }
+    }
+    tracing::Span::current().record("cache_hit", false);
+    info!("Searching");
+
+    let hits = search_hits_deduped(&key, settings, data_dir, state).await?;
+    if settings.enable_search_cache {
+        cache_search_hits(state, key, hits.clone());
+    }
+    let results = hydrate_search_hits(
+        state,
+        data_dir,
+        &hits,
+        filter_region,
+        settings.search_top_k,
+        preview_chars,
+    )?;
+    info!("Search finished in {}ms", started.elapsed().as_millis());
+    Ok(SearchOutcome {
+        results,
+        degraded: None,
+        cache_hit: false,
+    })
+}
+
+// 多数据源检索：查询向量只算一次，各数据源并发检索后按距离合并
+async fn search_all_sources(
+    key: SearchCacheKey,
+    config: &SearchConfig,
+    sources: &[(String, PathBuf)],
+    preview_chars: usize,
+    state: &AppState,
+) -> Result<SearchOutcome, AppError> {
+    let embedding = EmbeddingConfig::from_settings(&config.settings, &config.data_dir);
+    let vector = get_embedding(&http_client(state), &embedding, &key.query).await?;
+    let filter_region = key.filter_region.clone();
+    search_sources_with_vector(
+        vector,
+        filter_region,
+        Some(key),
+        config,
+        sources,
+        preview_chars,
+        state,
+    )
+    .await
+}
+
+// 用同一个查询向量检索默认数据目录与附加数据源。给出 cache_key 时默认数据目录的结果走检索缓存
+// （cache_hit 只反映默认数据目录）；附加数据源出错只记录日志，不影响其余结果
+async fn search_sources_with_vector(
+    vector: Vec<f32>,
+    filter_region: Option<String>,
+    cache_key: Option<SearchCacheKey>,
+    config: &SearchConfig,
+    sources: &[(String, PathBuf)],
+    preview_chars: usize,
+    state: &AppState,
+) -> Result<SearchOutcome, AppError> {
+    let (settings, data_dir) = (&config.settings, &config.data_dir);
+    let cache_key = cache_key.filter(|_| settings.enable_search_cache);
+    let primary = async {
+        if let Some(hits) = cache_key
+            .as_ref()
+            .and_then(|key| cached_search_hits(state, key))
+        {
+            return Ok((hits, true));
+        }
+        let hits = vector_search_hits(vector.clone(), settings, data_dir, state).await?;
+        if let Some(key) = &cache_key {
+            cache_search_hits(state, key.clone(), hits.clone());
+        }
+        Ok::<_, AppError>((hits, false))
+    };
+    let extras = futures::future::join_all(
+        sources
+            .iter()
+            .map(|(_, dir)| source_search_hits(vector.clone(), settings, dir, state)),
+    );
+    let (primary, extras) = futures::join!(primary, extras);
+    let (primary_hits, cache_hit) = primary?;
+
+    let mut results = hydrate_search_hits(
+        state,
+        data_dir,
+        &primary_hits,
+        filter_region.clone(),
+        settings.search_top_k,
+        preview_chars,
+    )?;
+    for ((name, dir), hits) in sources.iter().zip(extras) {
+        let chunks = hits.and_then(|hits| {
+            let conn = state
+                .content_pool
+                .get(dir, |dir| connect_sqlite(state, dir))?;
+            hydrate_hits_from(
+                &conn,
+                &hits,
+                filter_region.clone(),
+                settings.search_top_k,
+                preview_chars,
+            )
+        });
+        match chunks {
+            Ok(chunks) => results.extend(chunks.into_iter().map(|mut chunk| {
+                chunk.source_name = Some(name.clone());
+                chunk
+            })),
+            Err(e) => warn!("Data source {} search failed: {}", name, e),
+        }
+    }
+    results.sort_by(|a, b| a._distance.total_cmp(&b._distance));
+    results.truncate(settings.search_top_k);
+    Ok(SearchOutcome {
+        results,
+        degraded: None,
+        cache_hit,
+    })
+}
+
+// 用已经算好的查询向量检索（Agent 批量嵌入后直接复用），与 vector_search_law 一样覆盖附加数据源
+#[tracing::instrument(skip_all, fields(region = ?filter_region))]
+async fn search_law_by_vector(
+    vector: Vec<f32>,
+    filter_region: Option<String>,
+    config: &SearchConfig,
+    state: &AppState,
+) -> Result<Vec<LawChunk>, AppError> {
+    let (settings, data_dir) = (&config.settings, &config.data_dir);
+    let extra_sources = enabled_extra_sources(state, settings);
+    if !extra_sources.is_empty() {
+        let outcome = search_sources_with_vector(
+            vector,
+            filter_region,
+            None,
+            config,
+            &extra_sources,
+            0,
+            state,
+        )
+        .await?;
+        return Ok(outcome.results);
+    }
+    let hits = vector_search_hits(vector, settings, data_dir, state).await?;
+    hydrate_search_hits(
+        state,
+        data_dir,
+        &hits,
+        filter_region,
+        settings.search_top_k,
+        0,
+    )
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WarmupStep {
+    pub name: String,
+    pub millis: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WarmupReport {
+    pub steps: Vec<WarmupStep>,
+    pub total_ms: u64,
+}
+
+fn warmup_step(name: &str, started: Instant, result: Result<(), AppError>) -> WarmupStep {
+    let millis = started.elapsed().as_millis() as u64;
+    let error = result.err().map(|e| {
+        debug!("Warm-up step {} failed: {}", name, e);
+        e.to_string()
+    });
+    WarmupStep {
+        name: name.to_string(),
+        millis,
+        error,
+    }
+}
+
+// 启动预热：打开向量表并做一次 limit 1 扫描、建立 content.db 连接并预编译查询，
+// 开启 warmup_on_start 时再发一次嵌入请求。各步骤失败只记录，不影响正常使用
+async fn run_warmup(state: &AppState) -> WarmupReport {
+    let total = Instant::now();
+    let data_dir = get_effective_data_dir(state);
+    let mut steps = Vec::new();
+
+    let started = Instant::now();
+    let result = async {
+        let table = cached_vector_table(state, &data_dir).await?;
+        let mut stream = table
+            .query()
+            .limit(1)
+            .execute()
+            .await
+            .map_err(AppError::vector_db_error)?;
+        if let Some(batch) = stream.next().await {
+            batch.map_err(AppError::vector_db_error)?;
+        }
+        Ok::<(), AppError>(())
+    }
+    .await;
+    steps.push(warmup_step("vector_table", started, result));
+
+    let started = Instant::now();
+    let result = content_db(state).and_then(|conn| {
+        conn.prepare_cached(&format!(
+            "SELECT {} FROM chunks WHERE id IN (?1)",
+            CHUNK_COLUMNS
+        ))
+        .map(|_| ())
+        .map_err(AppError::content_db_error)
+    });
+    steps.push(warmup_step("content_db", started, result));
+
+    let settings = snapshot_settings(state);
+    if settings.warmup_on_start {
+        let started = Instant::now();
+        let result = get_embedding(&http_client(state), &embedding_config(state), "预热")
+            .await
+            .map(|_| ());
+        steps.push(warmup_step("embedding", started, result));
+    }
+
+    let report = WarmupReport {
+        steps,
+        total_ms: total.elapsed().as_millis() as u64,
+    };
+    info!("Warm-up finished in {}ms", report.total_ms);
+    report
+}
+
+// 发起者中途被取消时移除登记，等待中的请求随之自行检索
+struct InFlightGuard<'a> {
+    state: &'a AppState,
+    key: &'a SearchCacheKey,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.state
+            .search_in_flight
+            .lock_or_recover()
+            .remove(self.key);
+    }
+}
+
+// 相同的查询正在检索时直接等待它的结果，避免快速输入时重复请求嵌入服务
+async fn search_hits_deduped(
+    key: &SearchCacheKey,
+    settings: &AppSettings,
+    data_dir: &std::path::Path,
+    state: &AppState,
+) -> Result<Vec<(String, f32)>, AppError> {
+    let receiver = {
+        let mut in_flight = state.search_in_flight.lock_or_recover();
+        match in_flight.get(key) {
+            Some(sender) => Some(sender.subscribe()),
+            None => {
+                in_flight.insert(key.clone(), tokio::sync::watch::channel(None).0);
+                None
+            }
+        }
+    };
+
+    if let Some(mut receiver) = receiver {
+        debug!("Joining in-flight search");
+        loop {
+            let current = receiver.borrow().clone();
+            if let Some(result) = current {
+                return result;
+            }
+            if receiver.changed().await.is_err() {
+                break;
+            }
+        }
+        // 发起者没有给出结果就结束了，自己检索
+        let embedding = EmbeddingConfig::from_settings(settings, data_dir);
+        let vector = get_embedding(&http_client(state), &embedding, &key.query).await?;
+        return vector_search_hits(vector, settings, data_dir, state).await;
+    }
+
+    let _guard = InFlightGuard { state, key };
+    let result = async {
+        let embedding = EmbeddingConfig::from_settings(settings, data_dir);
+        let vector = get_embedding(&http_client(state), &embedding, &key.query).await?;
+        vector_search_hits(vector, settings, data_dir, state).await
+    }
+    .await;
+    if let Some(sender) = state.search_in_flight.lock_or_recover().get(key) {
+        sender.send_replace(Some(result.clone()));
+    }
+    result
+}
+
+// 复用已打开的向量表，避免每次检索都重新 connect/open_table
+async fn cached_vector_table(
+    state: &AppState,
+    data_dir: &std::path::Path,
+) -> Result<lancedb::Table, AppError> {
+    let cached = state
+        .vector_table
+        .lock_or_recover()
+        .as_ref()
+        .filter(|(dir, _)| dir == data_dir)
+        .map(|(_, table)| table.clone());
+    if let Some(table) = cached {
+        return Ok(table);
+    }
+    let table = open_vector_table(data_dir).await?;
+    *state.vector_table.lock_or_recover() = Some((data_dir.to_path_buf(), table.clone()));
+    Ok(table)
+}
+
+// 向量检索，返回按距离排序的 (chunk_id, distance)
+async fn vector_search_hits(
+    vector: Vec<f32>,
+    settings: &AppSettings,
+    data_dir: &std::path::Path,
+    state: &AppState,
+) -> Result<Vec<(String, f32)>, AppError> {
+    let table = cached_vector_table(state, data_dir).await?;
+
+    // 换了嵌入模型却没重建向量库时，给出明确提示而不是 LanceDB 的维度错误。
+    // 校验结果按当前设置缓存，沿用旧配置快照的 Agent 检索不读写该缓存
+    let current_settings = snapshot_settings(state);
+    let current = embedding_cache_id(&current_settings) == embedding_cache_id(settings)
+        && resolve_data_dir(state, &current_settings) == data_dir;
+    if !current {
+        if let Some(db_dim) = table_vector_dimension(&table).await? {
+            compare_embedding_dimension(vector.len(), db_dim)?;
+        }
+        return query_vector_table(&table, vector, settings.search_top_k * 3).await;
+    }
+    let cached_check = state.embedding_dimension_check.lock_or_recover().clone();
+    match cached_check {
+        Some(result) => result?,
+        None => {
+            let result = match table_vector_dimension(&table).await? {
+                Some(db_dim) => compare_embedding_dimension(vector.len(), db_dim),
+                None => Ok(()),
+            };
+            *state.embedding_dimension_check.lock_or_recover() = Some(result.clone());
+            result?;
+        }
+    }
+
+    query_vector_table(&table, vector, settings.search_top_k * 3).await
+}
+
+// 附加数据源的向量检索；维度不一致时直接报错，不做缓存
+async fn source_search_hits(
+    vector: Vec<f32>,
+    settings: &AppSettings,
+    data_dir: &std::path::Path,
+    state: &AppState,
+) -> Result<Vec<(String, f32)>, AppError> {
+    let cached = state
+        .source_vector_tables
+        .lock_or_recover()
+        .get(data_dir)
+        .cloned();
+    let table = match cached {
+        Some(table) => table,
+        None => {
+            let table = open_vector_table(data_dir).await?;
+            state
+                .source_vector_tables
+                .lock_or_recover()
+                .insert(data_dir.to_path_buf(), table.clone());
+            table
+        }
+    };
+    if let Some(db_dim) = table_vector_dimension(&table).await? {
+        compare_embedding_dimension(vector.len(), db_dim)?;
+    }
+    query_vector_table(&table, vector, settings.search_top_k * 3).await
+}
+
+async fn query_vector_table(
+    table: &lancedb::Table,
+    vector: Vec<f32>,
+    fetch_limit: usize,
+) -> Result<Vec<(String, f32)>, AppError> {
+    let results_stream = table
+        .query()
+        .nearest_to(vector)
+        .map_err(AppError::vector_db_error)?
+        .limit(fetch_limit)
+        .execute()
+        .await
+        .map_err(AppError::vector_db_error)?;
+
+    let mut stream = results_stream;
+    let mut hits: Vec<(String, f32)> = Vec::new();
+
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(batch) => {
+                let id_col = batch
+                    .column_by_name("chunk_id")
+                    .ok_or_else(|| AppError::vector_db_error("Missing chunk_id"))?;
+                let dist_col = batch
+                    .column_by_name("_distance")
+                    .ok_or_else(|| AppError::vector_db_error("Missing _distance"))?;
+                let ids = id_col
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .ok_or_else(|| AppError::vector_db_error("chunk_id error"))?;
+                let dists = dist_col
+                    .as_any()
+                    .downcast_ref::<Float32Array>()
+                    .ok_or_else(|| AppError::vector_db_error("_distance error"))?;
+                for i in 0..batch.num_rows() {
+                    hits.push((ids.value(i).to_string(), dists.value(i)));
+                }
+            }
+            Err(e) => return Err(AppError::vector_db_error(e)),
+        }
+    }
+    Ok(hits)
+}
+
+// SQLite 默认最多 999 个绑定参数，IN 查询按批拆分
+const HYDRATE_BATCH: usize = 500;
+// 候选数超过该值时改用临时表 JOIN，避免多次 IN 查询
+const HYDRATE_TEMP_TABLE_THRESHOLD: usize = 2000;
+
+// 带预览时只读取内容的前 preview_chars 个字符，末尾附加完整长度用于判断是否截断
+// content_mode 为 preview 而设置中未指定预览字数时使用
+const DEFAULT_PREVIEW_CHARS: usize = 200;
+const SENTENCE_ENDS: &[char] = &['。', '；', '！', '？', ';', '\n'];
+
+// 本次请求实际使用的预览字数，0 表示完整内容
+fn preview_chars_for(mode: Option<ContentMode>, settings: &AppSettings) -> usize {
+    match mode {
+        None => settings.search_preview_chars,
+        Some(ContentMode::Full) => 0,
+        Some(ContentMode::Preview) if settings.search_preview_chars > 0 => {
+            settings.search_preview_chars
+        }
+        Some(ContentMode::Preview) => DEFAULT_PREVIEW_CHARS,
+    }
+}
+
+// 已截断的预览退到最后一个句末，避免停在半句话中间；句末太靠前（不到一半）时保持原样
+fn cut_at_sentence(content: &mut String) {
+    let half = content.chars().count() / 2;
+    let end = content
+        .char_indices()
+        .enumerate()
+        .filter(|(i, (_, c))| *i >= half && SENTENCE_ENDS.contains(c))
+        .last()
+        .map(|(_, (pos, c))| pos + c.len_utf8());
+    if let Some(end) = end {
+        content.truncate(end);
+        content.truncate(content.trim_end().len());
+    }
+}
+
+// 已在内存中的完整内容按预览字数截断（Agent 结果、收藏列表）
+fn shape_content(content: &mut String, preview_chars: usize) -> bool {
+    if preview_chars == 0 {
+        return false;
+    }
+    match content.char_indices().nth(preview_chars) {
+        Some((pos, _)) => {
+            content.truncate(pos);
+            cut_at_sentence(content);
+            true
+        }
+        None => false,
+    }
+}
+
+fn shape_chunks(chunks: &mut [LawChunk], preview_chars: usize) {
+    for chunk in chunks.iter_mut().filter(|chunk| !chunk.content_truncated) {
+        chunk.content_truncated = shape_content(&mut chunk.content, preview_chars);
+    }
+}
+
+fn hydrate_columns(preview_chars: usize) -> String {
+    if preview_chars == 0 {
+        return CHUNK_COLUMNS.to_string();
+    }
+    format!(
+        "{}, length(content)",
+        CHUNK_COLUMNS.replacen(
+            "content",
+            &format!("substr(content, 1, {})", preview_chars),
+            1
+        )
+    )
+}
+
+// 按 id 批量读取条文，直接放入按检索排名预分配的位置，不再经过中间 HashMap 和逐条 clone。
+// 返回值与 hits 一一对应，content.db 中查不到的位置为 None
+fn load_ranked_chunks(
+    conn: &Connection,
+    hits: &[(String, f32)],
+    preview_chars: usize,
+) -> Result<Vec<Option<LawChunk>>, AppError> {
+    let rank: HashMap<&str, usize> = hits
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _))| (id.as_str(), i))
+        .collect();
+    let mut slots: Vec<Option<LawChunk>> = Vec::with_capacity(hits.len());
+    slots.resize_with(hits.len(), || None);
+    let columns = hydrate_columns(preview_chars);
+
+    let mut place = |row: &rusqlite::Row| -> rusqlite::Result<()> {
+        let mut chunk = law_chunk_from_row(row)?;
+        if preview_chars > 0 {
+            let full_len: i64 = row.get(9)?;
+            chunk.content_truncated = full_len > preview_chars as i64;
+            if chunk.content_truncated {
+                cut_at_sentence(&mut chunk.content);
+            }
+        }
+        if let Some(&i) = rank.get(chunk.id.as_str()) {
+            chunk._distance = hits[i].1;
+            slots[i] = Some(chunk);
+        }
+        Ok(())
+    };
+
+    if hits.len() > HYDRATE_TEMP_TABLE_THRESHOLD {
+        conn.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS hydrate_ids (id TEXT PRIMARY KEY);
+             DELETE FROM temp.hydrate_ids;",
+        )
+        .map_err(AppError::content_db_error)?;
+        {
+            let mut insert = conn
+                .prepare("INSERT OR IGNORE INTO temp.hydrate_ids (id) VALUES (?1)")
+                .map_err(AppError::content_db_error)?;
+            for (id, _) in hits {
+                insert.execute([id]).map_err(AppError::content_db_error)?;
+            }
+        }
+        let sql = format!(
+            "SELECT {} FROM chunks WHERE id IN (SELECT id FROM temp.hydrate_ids)",
+            columns
+        );
+        let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+        let mut rows = stmt.query([]).map_err(AppError::content_db_error)?;
+        while let Some(row) = rows.next().map_err(AppError::content_db_error)? {
+            if let Err(e) = place(row) {
+                warn!("Skipping malformed chunk row: {}", e);
+            }
+        }
+        drop(rows);
+        conn.execute("DELETE FROM temp.hydrate_ids", [])
+            .map_err(AppError::content_db_error)?;
+    } else {
+        for batch in hits.chunks(HYDRATE_BATCH) {
+            let placeholders: String = batch.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let sql = format!(
+                "SELECT {} FROM chunks WHERE id IN ({})",
+                columns, placeholders
+            );
+            let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+            let mut rows = stmt
+                .query(rusqlite::params_from_iter(batch.iter().map(|(id, _)| id)))
+                .map_err(AppError::content_db_error)?;
+            while let Some(row) = rows.next().map_err(AppError::content_db_error)? {
+                if let Err(e) = place(row) {
+                    warn!("Skipping malformed chunk row: {}", e);
+                }
+            }
+        }
+    }
+    Ok(slots)
+}
+
+// 从 content.db 补全检索结果，并按地区过滤地方法规
+fn hydrate_search_hits(
+    state: &AppState,
+    data_dir: &std::path::Path,
+    hits: &[(String, f32)],
+    filter_region: Option<String>,
+    top_k: usize,
+    preview_chars: usize,
+) -> Result<Vec<LawChunk>, AppError> {
+    if hits.is_empty() {
+        return Ok(Vec::new());
+    }
+    let conn = state
+        .content_pool
+        .get(data_dir, |dir| connect_sqlite(state, dir))?;
+    hydrate_hits_from(&conn, hits, filter_region, top_k, preview_chars)
+}
+
+fn hydrate_hits_from(
+    conn: &Connection,
+    hits: &[(String, f32)],
+    filter_region: Option<String>,
+    top_k: usize,
+    preview_chars: usize,
+) -> Result<Vec<LawChunk>, AppError> {
+    let slots = load_ranked_chunks(conn, hits, preview_chars)?;
+
+    // 向量库里有、content.db 里查不到的结果会被静默丢弃，记录下来便于发现数据不一致
+    let misses = slots.iter().filter(|slot| slot.is_none()).count();
+    if misses > 0 {
+        warn!(
+            "{} of {} vector hits missing from content.db, run verify_data_integrity",
+            misses,
+            hits.len()
+        );
+    }
+
+    let final_results = slots
+        .into_iter()
+        .flatten()
+        .filter(|chunk| region_allows(chunk, filter_region.as_deref()))
+        .take(top_k)
+        .collect();
+    Ok(final_results)
+}
+
+// 地方法规只在指定了对应地区时返回
+fn region_allows(chunk: &LawChunk, filter_region: Option<&str>) -> bool {
+    chunk.category != "地方法规"
+        || filter_region.is_some_and(|target_region| chunk.region.contains(target_region))
+}
+
+// 读取单条条文的完整内容
+fn load_chunk_content(conn: &Connection, chunk_id: &str) -> Result<String, AppError> {
+    use rusqlite::OptionalExtension;
+
+    conn.query_row(
+        "SELECT content FROM chunks WHERE id = ?1",
+        [chunk_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(AppError::content_db_error)?
+    .ok_or_else(|| AppError::not_found(format!("未找到条文: {}", chunk_id)))
+}
+
+// 前端传回的条文只带预览时，写入收藏或素材前补全内容
+fn ensure_full_content(state: &AppState, chunks: &mut [LawChunk]) -> Result<(), AppError> {
+    if !chunks.iter().any(|chunk| chunk.content_truncated) {
+        return Ok(());
+    }
+    let conn = content_db(state)?;
+    for chunk in chunks.iter_mut().filter(|chunk| chunk.content_truncated) {
+        chunk.content = load_chunk_content(&conn, &chunk.id)?;
+        chunk.content_truncated = false;
+    }
+    Ok(())
+}
+
+// ==========================================
+// 5. Tauri 命令
+// ==========================================
+
+// 5.1 智能体搜索命令 (Agent)
+metered_command! {
+    #[tauri::command]
+    #[tracing::instrument(skip_all, fields(event_id = %event_id, query = %log_query(&query)))]
+    async fn start_agent_search(
+        window: tauri::Window,
+        query: String,
+        event_id: String,
+        content_mode: Option<ContentMode>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawChunk>, String> {
+        let started = Instant::now();
+        let should_run = Arc::new(AtomicBool::new(true));
+        {
+            let mut flags = state.agent_abort_flags.lock_or_recover();
+            flags.insert(event_id.clone(), should_run.clone());
+        }
+
+        macro_rules! check_abort {
+            () => {
+                if !should_run.load(Ordering::Relaxed) {
+                    // 清理并返回中断信号
+                    let mut flags = state.agent_abort_flags.lock_or_recover();
+                    flags.remove(&event_id);
+                    return Err("深度思考已手动停止".to_string());
+                }
+            };
+        }
+
+        // 整个运行期间沿用开始时的设置与数据目录
+        let config = SearchConfig::capture(&state);
+        let settings = config.settings.clone();
+        let client = http_client(&state);
+        let (model, base_url, api_key, api_format, max_loops, timeout_secs) = (
+            settings.chat_model,
+            settings.chat_base_url,
+            settings.chat_api_key,
+            settings.chat_api_format,
+            settings.max_agent_loops,
+            settings.chat_timeout_secs,
+        );
+
+        let mut completed_log: Vec<CompletedTask> = vec![];
+
+        // 使用 HashSet 收集 ID 去重，Vec 收集结果
+        let mut all_found_chunks: Vec<LawChunk> = vec![];
+        let mut seen_ids: HashSet<String> = HashSet::new();
+
+        check_abort!();
+
+        window
+            .emit(
+                "agent-update",
+                AgentUpdateEvent {
+                    step_type: "planning".into(),
+                    todo_list: vec![],
+                    completed_log: vec![],
+                    current_task: None,
+                    thought: Some("正在拆解法律问题...".into()),
+                },
+            )
+            .unwrap();
+
+        let plan_prompt = PLANNER_PROMPT.replace("{user_query}", &query);
+        info!("Agent planning");
+        let plan_result = call_llm(
+            &client,
+            &model,
+            &plan_prompt,
+            &base_url,
+            &api_key,
+            &api_format,
+            timeout_secs,
+        )
+        .await;
+        let mut todo_list: Vec<String> = match plan_result {
+            Ok(json) => {
+                debug!("LLM Raw Output: {}", json);
+                let clean = clean_json_str(&json);
+                debug!("Cleaned JSON: {}", clean);
+                match serde_json::from_str::<Vec<String>>(&clean) {
+                    Ok(list) => {
+                        info!("Parsed {} tasks", list.len());
+                        list
+                    }
+                    Err(e) => {
+                        warn!("Plan JSON Parse Error: {}", e);
+                        // 如果解析失败，回退到原始查询
+                        vec![query.clone()]
+                    }
+                }
+            }
+            Err(_) => vec![query.clone()],
+        };
+
+        let mut loop_count = 0;
+        let limit = if max_loops <= 0 { 99 } else { max_loops };
+        let mut task_vectors: HashMap<String, Vec<f32>> = HashMap::new();
+
+        while !todo_list.is_empty() && loop_count < limit {
+            check_abort!();
+            loop_count += 1;
+            let current_task = todo_list.remove(0);
+            info!(
+                "Step {}: executing task {}",
+                loop_count,
+                log_query(&current_task)
+            );
+            window
+                .emit(
+                    "agent-update",
+                    AgentUpdateEvent {
+                        step_type: "executing".into(),
+                        todo_list: todo_list.clone(),
+                        completed_log: completed_log.clone(),
+                        current_task: Some(current_task.clone()),
+                        thought: None,
+                    },
+                )
+                .unwrap();
+
+            // 待办任务一次性批量嵌入，后续步骤直接复用向量
+            let pending: Vec<String> = std::iter::once(&current_task)
+                .chain(todo_list.iter())
+                .filter(|task| !task_vectors.contains_key(*task))
+                .cloned()
+                .collect();
+            if pending.len() > 1 {
+                match get_embeddings_batch(&client, &config.embedding(), &pending).await {
+                    Ok(vectors) => task_vectors.extend(pending.into_iter().zip(vectors)),
+                    Err(e) => warn!("Batch embedding failed: {}", e),
+                }
+            }
+
+            let search_res = match task_vectors.remove(&current_task) {
+                Some(vector) => search_law_by_vector(vector, None, &config, &state).await,
+                None => vector_search_law(current_task.clone(), None, 0, &config, &state)
+                    .await
+                    .map(|outcome| outcome.results),
+            };
+
+            check_abort!();
+
+            let mut result_text = String::new();
+            let mut found_count = 0;
+            let step_max_chunks = 10; 
+
+            match search_res {
+                Ok(chunks) => {
+                    for r in chunks {
+                        // 1.2 阈值过滤
+                        if r._distance < RELEVANCE_DISTANCE_THRESHOLD {
+                            if found_count >= step_max_chunks {
+                                break;
+                            }
+                            found_count += 1;
+                            // 收集文本给 Agent 看
+                            result_text.push_str(&format!(
+                                "法规：《{}》{}\n内容：{}\n\n",
+                                r.law_name, r.article_number, r.content
+                            ));
+
+                            // 收集对象给前端
+                            if !seen_ids.contains(&r.id) {
+                                seen_ids.insert(r.id.clone());
+                                all_found_chunks.push(r);
+                            }
+                        }
+                    }
+                }
+                // 检索失败（如嵌入服务超时）只记为本步失败，交给评估器调整后续计划
+                Err(e) => {
+                    warn!("Search failed: {}", e);
+                    result_text = format!("搜索出错: {}", e);
+                }
+            }
+
+            if result_text.trim().is_empty() {
+                result_text = "未找到直接相关法条。".to_string();
+                info!("No results found for this task");
+            } else {
+                info!("Found {} relevant chunks", found_count);
+            }
+            check_abort!();
+            window
+                .emit(
+                    "agent-update",
+                    AgentUpdateEvent {
+                        step_type: "thinking".into(),
+                        todo_list: todo_list.clone(),
+                        completed_log: completed_log.clone(),
+                        current_task: Some(current_task.clone()),
+                        thought: Some("正在评估检索结果...".into()),
+                    },
+                )
+                .unwrap();
+
+            let review_prompt = EXECUTOR_PROMPT
+                .replace("{user_query}", &query)
+                .replace("{current_task}", &current_task)
+                .replace("{search_results}", &result_text)
+                .replace(
+                    "{remaining_todo_list}",
+                    &serde_json::to_string(&todo_list).unwrap_or("[]".into()),
+                );
+            check_abort!();
+            match call_llm(
+                &client,
+                &model,
+                &review_prompt,
+                &base_url,
+                &api_key,
+                &api_format,
+                timeout_secs,
+            )
+            .await
+            {
+                Ok(json) => {
+                    let clean = clean_json_str(&json);
+                    if let Ok(res) = serde_json::from_str::<ExecutorResponse>(&clean) {
+                        debug!("Thought: {}", res.thought);
+                        debug!("Updated List: {:?}", res.new_todo_list);
+                        todo_list = res.new_todo_list;
+                        completed_log.push(CompletedTask {
+                            task: current_task,
+                            thought: res.thought,
+                        });
+                    } else {
+                        warn!("Executor JSON Parse Failed: {}", clean);
+                        completed_log.push(CompletedTask {
+                            task: current_task,
+                            thought: "解析思考结果失败，继续执行原计划。".into(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    warn!("LLM Reflection Error: {}", e);
+                    completed_log.push(CompletedTask {
+                        task: current_task,
+                        thought: "LLM 调用失败，跳过此步分析。".into(),
+                    });
+                }
+            }
+        }
+
+        {
+            let mut flags = state.agent_abort_flags.lock_or_recover();
+            flags.remove(&event_id);
+        }
+
+        window
+            .emit(
+                "agent-update",
+                AgentUpdateEvent {
+                    step_type: "finished".into(),
+                    todo_list: vec![],
+                    completed_log,
+                    current_task: None,
+                    thought: Some("所有任务执行完毕，正在生成最终回答...".into()),
+                },
+            )
+            .unwrap();
+        info!(
+            "Agent finished in {:.1}s. Total chunks found: {}",
+            started.elapsed().as_secs_f64(),
+            all_found_chunks.len()
+        );
+        record_history(&state, &query, "agent", &all_found_chunks);
+        shape_chunks(
+            &mut all_found_chunks,
+            preview_chars_for(content_mode, &config.settings),
+        );
+        Ok(all_found_chunks)
+    }
+}
+
+// 5.2 普通搜索命令 (Search)
+// request_id 由前端递增生成；已被更新的请求取代时不再检索，检索完成时已被取代的结果
+// 不返回也不写入历史，响应中 stale 为 true
+metered_command! {
+    #[tauri::command]
+    async fn search_law(
+        query: String,
+        filter_region: Option<String>,
+        request_id: Option<u64>,
+        content_mode: Option<ContentMode>,
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<SearchLawResponse, AppError> {
+        if let Some(id) = request_id {
+            state.latest_search_request.fetch_max(id, Ordering::SeqCst);
+        }
+        let superseded =
+            || request_id.is_some_and(|id| id < state.latest_search_request.load(Ordering::SeqCst));
+        if superseded() {
+            debug!("Search superseded before it started");
+            return Ok(SearchLawResponse::superseded());
+        }
+        let settings = snapshot_settings(&state);
+        let rewritten_query = if settings.enable_query_rewrite_rules {
+            rewrite_query(&state, &query)
+        } else {
+            None
+        };
+        let effective_query = rewritten_query.clone().unwrap_or_else(|| query.clone());
+        let outcome = search_law_with_fallback(
+            effective_query,
+            filter_region,
+            preview_chars_for(content_mode, &settings),
+            &state,
+        )
+        .await;
+        // 检索失败时同样提示，模型不一致往往就是失败原因
+        if let Some(mismatch) = take_index_mismatch(&state) {
+            let _ = app.emit("index-model-mismatch", mismatch);
+        }
+        if let Some(fallback) = take_data_dir_fallback(&state) {
+            let _ = app.emit("data-dir-unreachable", fallback);
+        }
+        if let Some(outage) = take_embedding_outage(&state) {
+            let _ = app.emit("embedding-unavailable", outage);
+        }
+        let SearchOutcome {
+            results,
+            degraded,
+            cache_hit,
+        } = outcome?;
+        if superseded() {
+            debug!("Search superseded by a newer request");
+            return Ok(SearchLawResponse::superseded());
+        }
+        record_history(&state, &query, "search", &results);
+        spawn_full_text_prefetch(&app, &settings, &results);
+        Ok(SearchLawResponse {
+            results,
+            rewritten_query,
+            degraded: degraded.is_some(),
+            degraded_reason: degraded,
+            cache_hit,
+            stale: false,
+        })
+    }
+}
+
+// 内置的口语 -> 法律用语对照；右侧为空的是检索时去掉的口语虚词。
+// 只收录在各领域含义都不变的说法：“公司”“赔偿”在公司法、侵权等场景本身就是法律用语，不做替换
+const BUILTIN_REWRITE_RULES: &[(&str, &str)] = &[
+    ("炒鱿鱼", "解除劳动合同"),
+    ("被炒", "被解除劳动合同"),
+    ("炒了", "解除劳动合同"),
+    ("开除", "解除劳动合同"),
+    ("辞退", "解除劳动合同"),
+    ("老板", "用人单位"),
+    ("工资", "劳动报酬"),
+    ("欠薪", "拖欠劳动报酬"),
+    ("老赖", "失信被执行人"),
+    ("打官司", "诉讼"),
+    ("坐牢", "有期徒刑"),
+    ("房东", "出租人"),
+    ("租客", "承租人"),
+    ("彩礼", "婚约财礼"),
+    ("请问", ""),
+    ("有没有", ""),
+    ("怎么办", ""),
+    ("能不能", ""),
+];
+
+fn load_query_rewrite_rules(state: &AppState) -> Result<Vec<QueryRewriteRule>, AppError> {
+    let conn = user_db(state)?;
+    let mut stmt = conn
+        .prepare("SELECT id, from_text, to_text FROM query_rewrite_rules ORDER BY id")
+        .map_err(AppError::user_db_error)?;
+    let user_rules: Vec<QueryRewriteRule> = stmt
+        .query_map([], |row| {
+            Ok(QueryRewriteRule {
+                id: row.get(0)?,
+                from: row.get(1)?,
+                to: row.get(2)?,
+                builtin: false,
+            })
+        })
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::user_db_error)?;
+    let builtin = BUILTIN_REWRITE_RULES
+        .iter()
+        .filter(|(from, _)| !user_rules.iter().any(|r| r.from == *from))
+        .map(|(from, to)| QueryRewriteRule {
+            id: None,
+            from: from.to_string(),
+            to: to.to_string(),
+            builtin: true,
+        })
+        .collect::<Vec<_>>();
+    Ok(builtin.into_iter().chain(user_rules).collect())
+}
+
+// 从左到右扫描，每个位置取最长的匹配规则；没有变化或查询是引用/法规名时返回 None。
+// 改写只是辅助，规则读取失败时按原查询检索
+fn rewrite_query(state: &AppState, query: &str) -> Option<String> {
+    if matches!(
+        classify_query(query),
+        Some(QueryKind::Citation) | Some(QueryKind::LawName)
+    ) {
+        return None;
+    }
+    let mut rules = match load_query_rewrite_rules(state) {
+        Ok(rules) => rules,
+        Err(e) => {
+            warn!(
+                "Failed to load query rewrite rules, searching without rewrite: {}",
+                e
+            );
+            return None;
+        }
+    };
+    rules.retain(|r| !r.from.is_empty());
+    rules.sort_by_key(|r| std::cmp::Reverse(r.from.len()));
+
+    let mut rewritten = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        match rules.iter().find(|r| rest.starts_with(r.from.as_str())) {
+            Some(rule) => {
+                rewritten.push_str(&rule.to);
+                rest = &rest[rule.from.len()..];
+            }
+            None => {
+                rewritten.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    let rewritten = rewritten.split_whitespace().collect::<Vec<_>>().join(" ");
+    if rewritten.is_empty() || rewritten == query.trim() {
+        return None;
+    }
+    debug!("Query rewritten by rules");
+    Some(rewritten)
+}
+
+// 添加或覆盖改写规则（同一 from 只保留一条）
+metered_command! {
+    #[tauri::command]
+    fn add_query_rewrite_rule(
+        from: String,
+        to: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<QueryRewriteRule, AppError> {
+        let from = from.trim().to_string();
+        let to = to.trim().to_string();
+        if from.is_empty() {
+            return Err(AppError::validation("要替换的说法不能为空"));
+        }
+        if from == to {
+            return Err(AppError::validation("替换前后相同"));
+        }
+        let conn = user_db(&state)?;
+        let id: i64 = conn
+            .query_row(
+                "INSERT INTO query_rewrite_rules (from_text, to_text) VALUES (?1, ?2)
+                 ON CONFLICT(from_text) DO UPDATE SET to_text = excluded.to_text
+                 RETURNING id",
+                rusqlite::params![from, to],
+                |row| row.get(0),
+            )
+            .map_err(AppError::user_db_error)?;
+        Ok(QueryRewriteRule {
+            id: Some(id),
+            from,
+            to,
+            builtin: false,
+        })
+    }
+}
+
+// 内置规则在前，用户规则按添加顺序在后；被用户覆盖的内置规则不再列出
+metered_command! {
+    #[tauri::command]
+    fn list_query_rewrite_rules(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<QueryRewriteRule>, AppError> {
+        load_query_rewrite_rules(&state)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn delete_query_rewrite_rule(
+        id: i64,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let deleted = conn
+            .execute("DELETE FROM query_rewrite_rules WHERE id = ?1", [id])
+            .map_err(AppError::user_db_error)?;
+        if deleted == 0 {
+            return Err(AppError::not_found("改写规则不存在"));
+        }
+        Ok(())
+    }
+}
+
+// 搜索结果只带预览时，展开条文再取完整内容
+metered_command! {
+    #[tauri::command]
+    fn get_chunk_content(
+        chunk_id: String,
+        source_name: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        let conn = source_db(&state, source_name.as_deref())?;
+        load_chunk_content(&conn, &chunk_id)
+    }
+}
+
+// 5.3 其他命令 (Others)
+// 兼容旧前端：只返回是否可用，原因见 get_db_status_detailed
+metered_command! {
+    #[tauri::command]
+    async fn check_db_status(state: tauri::State<'_, AppState>) -> Result<bool, AppError> {
+        Ok(db_status(&state).await.ready)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    async fn get_db_status_detailed(state: tauri::State<'_, AppState>) -> Result<DbStatus, AppError> {
+        Ok(db_status(&state).await)
+    }
+}
+
+// content.db 的 chunks 表需要的列，与 CHUNK_COLUMNS 一致
+const REQUIRED_CHUNK_COLUMNS: [&str; 9] = [
+    "id",
+    "content",
+    "law_name",
+    "category",
+    "region",
+    "publish_date",
+    "part",
+    "chapter",
+    "article_number",
+];
+
+async fn db_status(state: &AppState) -> DbStatus {
+    use rusqlite::OptionalExtension;
+
+    let data_dir = get_effective_data_dir(state);
+    let mut status = DbStatus {
+        data_dir: data_dir.to_string_lossy().to_string(),
+        data_dir_exists: data_dir.is_dir(),
+        lancedb_present: data_dir.join("law_db.lancedb").exists(),
+        content_db_present: data_dir.join("content.db").exists(),
+        ..Default::default()
+    };
+
+    if status.lancedb_present {
+        match open_vector_table(&data_dir).await {
+            Ok(table) => {
+                status.table_openable = true;
+                match table.count_rows(None).await {
+                    Ok(count) => status.row_count = Some(count),
+                    Err(e) => status.vector_error = Some(e.to_string()),
+                }
+                match table_vector_dimension(&table).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => status.schema_issues.push("向量表中没有向量列".to_string()),
+                    Err(e) => status
+                        .schema_issues
+                        .push(format!("无法读取向量表结构: {}", e)),
+                }
+            }
+            Err(e) => status.vector_error = Some(e.to_string()),
+        }
+    }
+
+    // 直接打开而不走连接池，避免把打不开的连接缓存下来
+    if status.content_db_present {
+        match connect_sqlite(state, &data_dir) {
+            Ok(conn) => {
+                // 读取出错（如无权限）记入 content_error，不当作缺表
+                let has_table = conn
+                    .query_row(
+                        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'chunks'",
+                        [],
+                        |_| Ok(()),
+                    )
+                    .optional()
+                    .map(|found| found.is_some())
+                    .unwrap_or_else(|e| {
+                        status.content_error = Some(e.to_string());
+                        true
+                    });
+                if !has_table {
+                    status
+                        .schema_issues
+                        .push("content.db 中没有 chunks 表".to_string());
+                }
+                for column in REQUIRED_CHUNK_COLUMNS.iter().filter(|_| has_table) {
+                    match table_has_column(&conn, "chunks", column) {
+                        Ok(true) => {}
+                        Ok(false) => status
+                            .schema_issues
+                            .push(format!("content.db 的 chunks 表缺少列 {}", column)),
+                        Err(e) => {
+                            status.content_error = Some(e.to_string());
+                            break;
+                        }
+                    }
+                }
+                match conn.query_row("SELECT COUNT(*) FROM chunks", [], |row| row.get(0)) {
+                    Ok(count) => status.chunks_count = Some(count),
+                    Err(e) => {
+                        status.content_error.get_or_insert(e.to_string());
+                    }
+                }
+            }
+            Err(e) => status.content_error = Some(e.to_string()),
+        }
+    }
+
+    status.ready = status.table_openable
+        && status.row_count.is_some_and(|n| n > 0)
+        && status.chunks_count.is_some_and(|n| n > 0)
+        && status.schema_issues.is_empty();
+    if !status.ready {
+        warn!("Database not ready: {:?}", status);
+    }
+    status
+}
+
+metered_command! {
+    #[tauri::command]
+    fn add_draft_material(
+        mut chunk: LawChunk,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        ensure_full_content(&state, std::slice::from_mut(&mut chunk))?;
+        let conn = user_db(&state)?;
+        conn.execute(
+            "INSERT INTO draft_materials (law_id, law_name, article_number, content) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(law_id) DO NOTHING",
+            rusqlite::params![chunk.id, chunk.law_name, chunk.article_number, chunk.content],
+        ).map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_draft_materials(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DraftMaterial>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn.prepare("SELECT id, law_id, law_name, article_number, content, added_at FROM draft_materials ORDER BY added_at DESC").map_err(AppError::user_db_error)?;
+        let items = stmt
+            .query_map([], |row| {
+                Ok(DraftMaterial {
+                    id: row.get(0)?,
+                    law_id: row.get(1)?,
+                    law_name: row.get(2)?,
+                    article_number: row.get(3)?,
+                    content: row.get(4)?,
+                    added_at: row.get(5)?,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(items)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn remove_draft_material(
+        law_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute(
+            "DELETE FROM draft_materials WHERE law_id = ?1",
+            rusqlite::params![law_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn clear_draft_materials(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute("DELETE FROM draft_materials", [])
+            .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// 工作区条文按加入顺序排列，整条 LawChunk 以 JSON 保存
+fn load_workspace(conn: &Connection) -> Result<Vec<LawChunk>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT chunk FROM workspace_items ORDER BY added_at, rowid")
+        .map_err(AppError::user_db_error)?;
+    let items = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(AppError::user_db_error)?
+        .filter_map(Result::ok)
+        .filter_map(|json| serde_json::from_str(&json).ok())
+        .collect();
+    Ok(items)
+}
+
+fn emit_workspace_changed(app: &AppHandle, conn: &Connection) -> Result<usize, AppError> {
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM workspace_items", [], |row| row.get(0))
+        .map_err(AppError::user_db_error)?;
+    let count = count as usize;
+    let _ = app.emit("workspace-changed", WorkspaceChangedEvent { count });
+    Ok(count)
+}
+
+// 加入工作区，已在其中时不重复加入；返回当前条数
+metered_command! {
+    #[tauri::command]
+    fn workspace_add(
+        mut chunk: LawChunk,
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        ensure_full_content(&state, std::slice::from_mut(&mut chunk))?;
+        let limit = snapshot_settings(&state).workspace_limit;
+        let conn = user_db(&state)?;
+        let (count, exists): (i64, bool) = conn
+            .query_row(
+                "SELECT COUNT(*), EXISTS(SELECT 1 FROM workspace_items WHERE chunk_id = ?1)
+                 FROM workspace_items",
+                [&chunk.id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(AppError::user_db_error)?;
+        if !exists && count as usize >= limit {
+            return Err(AppError::validation(format!(
+                "工作区最多收集 {} 条条文，请先移除一些",
+                limit
+            )));
+        }
+        chunk._distance = 0.0;
+        let json = serde_json::to_string(&chunk).map_err(AppError::user_db_error)?;
+        conn.execute(
+            "INSERT INTO workspace_items (chunk_id, chunk) VALUES (?1, ?2)
+             ON CONFLICT(chunk_id) DO NOTHING",
+            rusqlite::params![chunk.id, json],
+        )
+        .map_err(AppError::user_db_error)?;
+        emit_workspace_changed(&app, &conn)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn workspace_remove(
+        chunk_id: String,
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        let conn = user_db(&state)?;
+        conn.execute(
+            "DELETE FROM workspace_items WHERE chunk_id = ?1",
+            [&chunk_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        emit_workspace_changed(&app, &conn)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn workspace_list(state: tauri::State<'_, AppState>) -> Result<Vec<LawChunk>, AppError> {
+        let conn = user_db(&state)?;
+        load_workspace(&conn)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn workspace_clear(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute("DELETE FROM workspace_items", [])
+            .map_err(AppError::user_db_error)?;
+        emit_workspace_changed(&app, &conn)?;
+        Ok(())
+    }
+}
+
+// 工作区条文转为 chat_stream 的 context_chunks，不带向量距离（视为相关）
+metered_command! {
+    #[tauri::command]
+    fn workspace_send_to_chat(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<ChatContextChunk>, AppError> {
+        let conn = user_db(&state)?;
+        let chunks = load_workspace(&conn)?
+            .into_iter()
+            .map(|chunk| ChatContextChunk {
+                id: chunk.id,
+                law_name: chunk.law_name,
+                article_number: chunk.article_number,
+                content: chunk.content,
+                distance: None,
+            })
+            .collect();
+        Ok(chunks)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn add_template(
+        name: String,
+        content: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute("INSERT INTO custom_templates (name, content) VALUES (?1, ?2) ON CONFLICT(name) DO UPDATE SET content = excluded.content", rusqlite::params![name, content]).map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_templates(state: tauri::State<'_, AppState>) -> Result<Vec<CustomTemplate>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare("SELECT id, name, content FROM custom_templates ORDER BY id DESC")
+            .map_err(AppError::user_db_error)?;
+        let items = stmt
+            .query_map([], |row| {
+                Ok(CustomTemplate {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    content: row.get(2)?,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(items)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn delete_template(id: i32, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute(
+            "DELETE FROM custom_templates WHERE id = ?1",
+            rusqlite::params![id],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn search_law_by_name(
+        query: String,
+        limit: usize,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawNameSuggestion>, AppError> {
+        search_law_names(&state, &query, limit)
+    }
+}
+
+fn search_law_names(
+    state: &AppState,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<LawNameSuggestion>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let conn = source_db(state, None)?;
+
+    // 较新的法规库带有 law_name_en 列，旧库没有
+    let has_en = table_has_column(&conn, "full_texts", "law_name_en").unwrap_or(false);
+    let english = normalize_english_law_name(query);
+    let sql = if has_en {
+        "SELECT DISTINCT law_name, region, category, law_name_en FROM full_texts
+         WHERE law_name LIKE ?1 OR (?2 != '' AND lower(law_name_en) LIKE '%' || ?2 || '%')
+         LIMIT 200"
+    } else {
+        "SELECT DISTINCT law_name, region, category, NULL FROM full_texts
+         WHERE law_name LIKE ?1 LIMIT 200"
+    };
+    let query_pattern = format!("%{}%", query);
+
+    let mut stmt = conn.prepare(sql).map_err(AppError::content_db_error)?;
+
+    let params: &[&dyn rusqlite::ToSql] = if has_en {
+        &[&query_pattern, &english]
+    } else {
+        &[&query_pattern]
+    };
+    let mut suggestions: Vec<LawNameSuggestion> = stmt
+        .query_map(params, |row| {
+            Ok(LawNameSuggestion {
+                name: row.get(0)?,
+                region: row.get(1)?,
+                category: row.get(2)?,
+                name_en: row.get(3)?,
+                source_name: None,
+            })
+        })
+        .map_err(AppError::content_db_error)?
+        .filter_map(Result::ok)
+        .collect();
+
+    // 用户译名：补上库中缺少的英文名，并加入按译名匹配到的法规
+    let translations = load_law_name_translations(state)?;
+    for suggestion in &mut suggestions {
+        if suggestion.name_en.is_none() {
+            suggestion.name_en = translations.get(&suggestion.name).cloned();
+        }
+    }
+    if !english.is_empty() {
+        let mut by_name = conn
+            .prepare("SELECT region, category FROM full_texts WHERE law_name = ?1 LIMIT 1")
+            .map_err(AppError::content_db_error)?;
+        for (name, en) in &translations {
+            if !normalize_english_law_name(en).contains(&english)
+                || suggestions.iter().any(|s| &s.name == name)
+            {
+                continue;
+            }
+            let found = by_name
+                .query_row([name], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()
+                .map_err(AppError::content_db_error)?;
+            if let Some((region, category)) = found {
+                suggestions.push(LawNameSuggestion {
+                    name: name.clone(),
+                    region,
+                    category,
+                    name_en: Some(en.clone()),
+                    source_name: None,
+                });
+            }
+        }
+    }
+
+    // 附加数据源只按中文名匹配，无法访问的数据源跳过
+    for (source_name, _) in enabled_extra_sources(state, &snapshot_settings(state)) {
+        let conn = match source_db(state, Some(&source_name)) {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Data source {} unavailable: {}", source_name, e);
+                continue;
+            }
+        };
+        let mut stmt = conn
+            .prepare(
+                "SELECT DISTINCT law_name, region, category FROM full_texts
+                 WHERE law_name LIKE ?1 LIMIT 200",
+            )
+            .map_err(AppError::content_db_error)?;
+        let found: Vec<LawNameSuggestion> = stmt
+            .query_map([&query_pattern], |row| {
+                Ok(LawNameSuggestion {
+                    name: row.get(0)?,
+                    region: row.get(1)?,
+                    category: row.get(2)?,
+                    name_en: None,
+                    source_name: Some(source_name.clone()),
+                })
+            })
+            .map_err(AppError::content_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+        suggestions.extend(found);
+    }
+
+    fn get_category_priority(cat: &str) -> i32 {
+        match cat {
+            "法律" => 1,
+            "司法解释" => 2,
+            "行政法规" => 3,
+            "地方法规" => 4,
+            _ => 99,
+        }
+    }
+
+    suggestions.sort_by(|a, b| {
+        let p_a = get_category_priority(&a.category);
+        let p_b = get_category_priority(&b.category);
+
+        if p_a != p_b {
+            p_a.cmp(&p_b)
+        } else {
+            a.name.len().cmp(&b.name.len())
+        }
+    });
+
+    if suggestions.len() > limit {
+        suggestions.truncate(limit);
+    }
+
+    Ok(suggestions)
+}
+
+// 查询类型的启发式判断，无法确定时返回 None：
+// 带问句特征或较长的按语义检索；含条号的是引用；以法规类名称结尾或整体是书名号的是法规名
+fn classify_query(query: &str) -> Option<QueryKind> {
+    const QUESTION_MARKERS: [&str; 14] = [
+        "？",
+        "?",
+        "吗",
+        "呢",
+        "怎么",
+        "如何",
+        "什么",
+        "是否",
+        "能否",
+        "可以",
+        "应当",
+        "哪些",
+        "为什么",
+        "怎样",
+    ];
+    const LAW_SUFFIXES: [&str; 12] = [
+        "法", "法典", "条例", "规定", "办法", "解释", "决定", "细则", "规则", "章程", "意见",
+        "通知",
+    ];
+    // 以“法”结尾但不是法规名的常见词，如“合同违法”“工作方法”
+    const NON_LAW_ENDINGS: [&str; 17] = [
+        "违法", "合法", "非法", "不法", "犯法", "守法", "依法", "执法", "司法", "立法", "方法",
+        "做法", "说法", "想法", "看法", "手法", "用法",
+    ];
+    let query = query.trim();
+    let len = query.chars().count();
+    if len == 0 {
+        return None;
+    }
+    if QUESTION_MARKERS.iter().any(|m| query.contains(m)) {
+        return Some(QueryKind::Semantic);
+    }
+    if len <= 40 && parse_single_citation(query).is_some() {
+        return Some(QueryKind::Citation);
+    }
+    if len > 30 || query.contains(['，', ',', '。', '；', ';']) {
+        return Some(QueryKind::Semantic);
+    }
+    if query.starts_with('《') && query.ends_with('》') {
+        return Some(QueryKind::LawName);
+    }
+    if LAW_SUFFIXES.iter().any(|s| query.ends_with(s))
+        && !NON_LAW_ENDINGS.iter().any(|s| query.ends_with(s))
+        && len >= 3
+    {
+        return Some(QueryKind::LawName);
+    }
+    // 短关键词（如“劳动合同”“Civil Code”）既可能是法规名也可能是检索词
+    if len <= 10 || query.chars().any(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(QueryKind::Semantic)
+}
+
+// 启发式无法确定时请大模型分类；未启用 AI 对话或请求失败时按语义检索
+async fn classify_query_with_llm(state: &AppState, query: &str) -> QueryKind {
+    let settings = snapshot_settings(state);
+    if !settings.enable_ai_chat {
+        return QueryKind::Semantic;
+    }
+    let prompt = QUERY_CLASSIFY_PROMPT.replace("{query}", query);
+    let answer = call_llm(
+        &http_client(state),
+        &settings.chat_model,
+        &prompt,
+        &settings.chat_base_url,
+        &settings.chat_api_key,
+        &settings.chat_api_format,
+        settings.chat_timeout_secs,
+    )
+    .await;
+    match answer {
+        Ok(answer) if answer.contains("law_name") => QueryKind::LawName,
+        Ok(answer) if answer.contains("citation") => QueryKind::Citation,
+        Ok(_) => QueryKind::Semantic,
+        Err(e) => {
+            warn!("Query classification failed: {}", e);
+            QueryKind::Semantic
+        }
+    }
+}
+
+// 自动识别查询类型并选择检索方式：引用查条文、法规名查法规、其余按语义检索。
+// 识别为引用但本地库中找不到该条时改为语义检索
+metered_command! {
+    #[tauri::command]
+    async fn classify_and_search(
+        query: String,
+        content_mode: Option<ContentMode>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<ClassifiedSearch, AppError> {
+        let kind = match classify_query(&query) {
+            Some(kind) => kind,
+            None => classify_query_with_llm(&state, &query).await,
+        };
+        debug!("Query classified as {:?}", kind);
+
+        match kind {
+            QueryKind::Citation => {
+                if let Some(citation) = parse_single_citation(&query) {
+                    if let Some(chunk) = resolve_citation(&*content_db(&state)?, &citation)? {
+                        return Ok(ClassifiedSearch::Citation {
+                            results: vec![chunk],
+                        });
+                    }
+                }
+            }
+            QueryKind::LawName => {
+                let name = query.trim().trim_start_matches('《').trim_end_matches('》');
+                let results = search_law_names(&state, name, 10)?;
+                if !results.is_empty() {
+                    return Ok(ClassifiedSearch::LawName { results });
+                }
+            }
+            QueryKind::Semantic => {}
+        }
+
+        let settings = snapshot_settings(&state);
+        let preview_chars = preview_chars_for(content_mode, &settings);
+        let SearchOutcome {
+            results, degraded, ..
+        } = search_law_with_fallback(query.clone(), None, preview_chars, &state).await?;
+        record_history(&state, &query, "search", &results);
+        Ok(ClassifiedSearch::Semantic {
+            results,
+            degraded_reason: degraded,
+        })
+    }
+}
+
+// 地方法规在检索结果中常被全国性条文挤出，地方一栏按这个倍数多取候选后再筛选
+const LOCAL_SEARCH_OVERFETCH: usize = 4;
+// 交给大模型比较的条文数（每栏）与每条的字数上限
+const COMPARISON_MAX_ARTICLES: usize = 5;
+const COMPARISON_ARTICLE_CHARS: usize = 400;
+
+// 冲突规范检索：同一问题分别检索全国性规定（非地方法规）与指定地区的地方法规，
+// 两栏共用一份配置快照；启用 AI 对话时再请大模型按条文编号比较差异
+metered_command! {
+    #[tauri::command]
+    async fn search_national_vs_local(
+        query: String,
+        region: String,
+        content_mode: Option<ContentMode>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<NationalLocalComparison, AppError> {
+        let query = query.trim().to_string();
+        let region = region.trim().to_string();
+        if query.is_empty() {
+            return Err(AppError::validation("检索内容不能为空"));
+        }
+        if region.is_empty() {
+            return Err(AppError::validation("请填写要对照的地区，如“广东省”"));
+        }
+
+        let config = SearchConfig::capture(&state);
+        let top_k = config.settings.search_top_k;
+        let preview_chars = preview_chars_for(content_mode, &config.settings);
+        let mut local_config = config.clone();
+        local_config.settings.search_top_k = top_k * LOCAL_SEARCH_OVERFETCH;
+
+        // 不带地区时 region_allows 只放行非地方法规，即全国性规定
+        let (national, local) = futures::join!(
+            search_with_config(query.clone(), None, preview_chars, &config, &state),
+            search_with_config(
+                query.clone(),
+                Some(region.clone()),
+                preview_chars,
+                &local_config,
+                &state
+            )
+        );
+        let (national, local) = (national?, local?);
+        let degraded_reason = national.degraded.or(local.degraded);
+        let national = national.results;
+        let local: Vec<LawChunk> = local
+            .results
+            .into_iter()
+            .filter(|chunk| chunk.category == "地方法规")
+            .take(top_k)
+            .collect();
+
+        let pairs = (0..national.len().max(local.len()))
+            .map(|i| ProvisionPair {
+                national_id: national.get(i).map(|c| c.id.clone()),
+                local_id: local.get(i).map(|c| c.id.clone()),
+            })
+            .collect();
+
+        let settings = &config.settings;
+        let comparison = if settings.enable_ai_chat && !national.is_empty() && !local.is_empty() {
+            compare_national_local(&state, settings, &query, &region, &national, &local).await
+        } else {
+            None
+        };
+        info!(
+            "National vs local search: {} national, {} local, comparison {}",
+            national.len(),
+            local.len(),
+            comparison.is_some()
+        );
+
+        Ok(NationalLocalComparison {
+            query,
+            region,
+            national,
+            local,
+            pairs,
+            comparison,
+            degraded_reason,
+        })
+    }
+}
+
+// 列出交给大模型的条文，编号用条文 id，以便比较结果对应回检索结果
+fn format_comparison_articles(chunks: &[LawChunk]) -> String {
+    chunks
+        .iter()
+        .take(COMPARISON_MAX_ARTICLES)
+        .map(|chunk| {
+            let content: String = chunk
+                .content
+                .chars()
+                .take(COMPARISON_ARTICLE_CHARS)
+                .collect();
+            format!(
+                "[{}] 《{}》{}：{}",
+                chunk.id, chunk.law_name, chunk.article_number, content
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// 请求失败或输出无法解析时返回 None，不影响检索结果
+async fn compare_national_local(
+    state: &AppState,
+    settings: &AppSettings,
+    query: &str,
+    region: &str,
+    national: &[LawChunk],
+    local: &[LawChunk],
+) -> Option<ProvisionComparison> {
+    let prompt = NATIONAL_LOCAL_PROMPT
+        .replace("{region}", region)
+        .replace("{query}", query)
+        .replace("{national}", &format_comparison_articles(national))
+        .replace("{local}", &format_comparison_articles(local));
+    let raw = match call_llm(
+        &http_client(state),
+        &settings.chat_model,
+        &prompt,
+        &settings.chat_base_url,
+        &settings.chat_api_key,
+        &settings.chat_api_format,
+        settings.chat_timeout_secs,
+    )
+    .await
+    {
+        Ok(raw) => raw,
+        Err(e) => {
+            warn!("National vs local comparison failed: {}", e);
+            return None;
+        }
+    };
+    let mut comparison: ProvisionComparison = match serde_json::from_str(&clean_json_str(&raw)) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            warn!("Unparseable national vs local comparison: {}", e);
+            return None;
+        }
+    };
+    // 去掉模型编造或写错的条文编号
+    let known = |ids: &mut Vec<String>, chunks: &[LawChunk]| {
+        ids.retain(|id| chunks.iter().any(|c| &c.id == id));
+    };
+    for point in &mut comparison.points {
+        known(&mut point.national_ids, national);
+        known(&mut point.local_ids, local);
+    }
+    Some(comparison)
+}
+
+// 英文法名的比较形式：小写、合并空白，去掉 "PRC" / "P.R.C." / "People's Republic of China" 前缀；
+// 查询不含英文字母时返回空串
+fn normalize_english_law_name(raw: &str) -> String {
+    if !raw.chars().any(|c| c.is_ascii_alphabetic()) {
+        return String::new();
+    }
+    let mut name = raw
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    for prefix in [
+        "the people's republic of china",
+        "people's republic of china",
+        "p. r. c.",
+        "p.r.c.",
+        "p.r.c",
+        "prc",
+    ] {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            name = rest.trim_start().to_string();
+            break;
+        }
+    }
+    name
+}
+
+fn load_law_name_translations(state: &AppState) -> Result<HashMap<String, String>, AppError> {
+    let conn = user_db(state)?;
+    let mut stmt = conn
+        .prepare("SELECT law_name, english FROM law_name_translations")
+        .map_err(AppError::user_db_error)?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<HashMap<_, _>, _>>()
+        .map_err(AppError::user_db_error)?;
+    Ok(rows)
+}
+
+// 保存法规的英文译名，english 为空时删除
+metered_command! {
+    #[tauri::command]
+    fn set_law_name_translation(
+        law_name: String,
+        english: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let law_name = law_name.trim();
+        if law_name.is_empty() {
+            return Err(AppError::validation("法规名称不能为空"));
+        }
+        let english = english.split_whitespace().collect::<Vec<_>>().join(" ");
+        let conn = user_db(&state)?;
+        if english.is_empty() {
+            conn.execute(
+                "DELETE FROM law_name_translations WHERE law_name = ?1",
+                [law_name],
+            )
+            .map_err(AppError::user_db_error)?;
+        } else {
+            conn.execute(
+                "INSERT INTO law_name_translations (law_name, english) VALUES (?1, ?2)
+                 ON CONFLICT(law_name) DO UPDATE SET english = excluded.english,
+                     updated_at = CURRENT_TIMESTAMP",
+                rusqlite::params![law_name, english],
+            )
+            .map_err(AppError::user_db_error)?;
+        }
+        Ok(())
+    }
+}
+
+// 浏览用的索引只在每个数据目录首次浏览时尝试创建；只读目录建不了索引时照常查询
+fn ensure_browse_indexes(conn: &Connection, data_dir: &std::path::Path) {
+    static INDEXED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    let mut indexed = INDEXED.get_or_init(Default::default).lock_or_recover();
+    if !indexed.insert(data_dir.to_path_buf()) {
+        return;
+    }
+    let result = conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_full_texts_category ON full_texts(category);
+         CREATE INDEX IF NOT EXISTS idx_full_texts_region ON full_texts(region);
+         CREATE INDEX IF NOT EXISTS idx_chunks_law_name ON chunks(law_name);",
+    );
+    if let Err(e) = result {
+        warn!("Failed to create browse indexes: {}", e);
+    }
+}
+
+// 按分类/地区/名称浏览法规；sort_by: "name" (默认) / "publish_date" (新的在前) / "article_count" (多的在前)
+metered_command! {
+    #[tauri::command]
+    fn browse_laws(
+        category: Option<String>,
+        region: Option<String>,
+        name_filter: Option<String>,
+        sort_by: Option<String>,
+        offset: usize,
+        limit: usize,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<LawBrowsePage, AppError> {
+        let data_dir = get_effective_data_dir(&state);
+        let conn = connect_sqlite(&state, &data_dir)?;
+        ensure_browse_indexes(&conn, &data_dir);
+
+        let non_empty =
+            |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let category = non_empty(category);
+        let region = non_empty(region);
+        let name_filter = non_empty(name_filter);
+        let limit = limit.clamp(1, 200);
+
+        let filter = "WHERE (?1 IS NULL OR f.category = ?1)
+                        AND (?2 IS NULL OR f.region LIKE '%' || ?2 || '%')
+                        AND (?3 IS NULL OR f.law_name LIKE '%' || ?3 || '%')";
+        let total: i64 = conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM full_texts f {}", filter),
+                rusqlite::params![category, region, name_filter],
+                |row| row.get(0),
+            )
+            .map_err(AppError::content_db_error)?;
+
+        let order = match sort_by.as_deref() {
+            Some("publish_date") => "publish_date DESC, f.law_name",
+            Some("article_count") => "article_count DESC, f.law_name",
+            _ => "f.law_name",
+        };
+        let sql = format!(
+            "SELECT f.law_name, f.category, f.region,
+                    (SELECT MAX(publish_date) FROM chunks c WHERE c.law_name = f.law_name) AS publish_date,
+                    (SELECT COUNT(*) FROM chunks c WHERE c.law_name = f.law_name) AS article_count
+             FROM full_texts f {} ORDER BY {} LIMIT ?4 OFFSET ?5",
+            filter, order
+        );
+        // 用户库不可用时不影响浏览，只是不显示收藏标记
+        let favorite_laws: HashSet<String> = user_db(&state)
+            .ok()
+            .and_then(|user_conn| favorite_law_names(&user_conn).ok())
+            .unwrap_or_default();
+        let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+        let items = stmt
+            .query_map(
+                rusqlite::params![category, region, name_filter, limit as i64, offset as i64],
+                |row| {
+                    Ok(LawBrowseItem {
+                        law_name: row.get(0)?,
+                        category: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                        region: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                        publish_date: row.get(3)?,
+                        article_count: row.get::<_, i64>(4)? as usize,
+                        is_favorite: false,
+                    })
+                },
+            )
+            .map_err(AppError::content_db_error)?
+            .collect::<Result<Vec<LawBrowseItem>, _>>()
+            .map_err(AppError::content_db_error)?
+            .into_iter()
+            .map(|mut item| {
+                item.is_favorite = favorite_laws.contains(&item.law_name);
+                item
+            })
+            .collect();
+
+        Ok(LawBrowsePage {
+            total: total as usize,
+            items,
+        })
+    }
+}
+
+// 发布日期的宽松解析，统一为可比较的 YYYYMMDD；
+// 支持 20240101、2024-01-01、2024/1/1、2024年1月1日、2024.01 等写法
+fn parse_publish_date(raw: &str) -> Option<String> {
+    let parts: Vec<&str> = raw
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|p| !p.is_empty())
+        .collect();
+    let (year, month, day) = match parts.as_slice() {
+        [compact] if compact.len() == 8 => (&compact[0..4], &compact[4..6], &compact[6..8]),
+        [year] if year.len() == 4 => (*year, "1", "1"),
+        [year, month] => (*year, *month, "1"),
+        [year, month, day, ..] => (*year, *month, *day),
+        _ => return None,
+    };
+    let year: u32 = year.parse().ok().filter(|y| (1900..=2100).contains(y))?;
+    let month: u32 = month.parse().ok().filter(|m| (1..=12).contains(m))?;
+    let day: u32 = day.parse().ok().filter(|d| (1..=31).contains(d))?;
+    Some(format!("{:04}{:02}{:02}", year, month, day))
+}
+
+// 法规的发布日期取各条文日期中最新的一个
+fn latest_publish_date(dates: Option<String>) -> Option<String> {
+    dates?.split(',').filter_map(parse_publish_date).max()
+}
+
+// 在 full_texts 上维护解析后的日期列 publish_date_sort（无法解析的为空串），
+// 只补算尚未计算的行（新导入的法规）；数据目录只读时返回 false
+fn ensure_publish_date_sort(conn: &Connection) -> bool {
+    match fill_publish_date_sort(conn) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to maintain publish_date_sort: {}", e);
+            false
+        }
+    }
+}
+
+fn fill_publish_date_sort(conn: &Connection) -> rusqlite::Result<()> {
+    if !table_has_column(conn, "full_texts", "publish_date_sort")? {
+        conn.execute(
+            "ALTER TABLE full_texts ADD COLUMN publish_date_sort TEXT",
+            [],
+        )?;
+    }
+    let pending: Vec<(String, Option<String>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT f.law_name,
+                    (SELECT GROUP_CONCAT(DISTINCT c.publish_date) FROM chunks c
+                     WHERE c.law_name = f.law_name)
+             FROM full_texts f WHERE f.publish_date_sort IS NULL",
+        )?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows
+    };
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt =
+            tx.prepare("UPDATE full_texts SET publish_date_sort = ?2 WHERE law_name = ?1")?;
+        for (law_name, dates) in &pending {
+            let sort_key = latest_publish_date(dates.clone()).unwrap_or_default();
+            stmt.execute(rusqlite::params![law_name, sort_key])?;
+        }
+    }
+    tx.commit()?;
+    debug!("Computed publish_date_sort for {} laws", pending.len());
+    Ok(())
+}
+
+// 按发布日期区间 [from, to]（YYYYMMDD）列出法规，新的在前；from 为空串时包含日期无法解析的法规，排在最后
+fn query_laws_by_date(
+    state: &AppState,
+    from: &str,
+    to: &str,
+    limit: usize,
+) -> Result<Vec<LawBrowseItem>, AppError> {
+    let data_dir = get_effective_data_dir(state);
+    let conn = connect_sqlite(state, &data_dir)?;
+    ensure_browse_indexes(&conn, &data_dir);
+
+    // 日期列可用时直接读取，否则每次解析全部日期
+    let cached = ensure_publish_date_sort(&conn);
+    let date_expr = if cached {
+        "f.publish_date_sort"
+    } else {
+        "(SELECT GROUP_CONCAT(DISTINCT c.publish_date) FROM chunks c WHERE c.law_name = f.law_name)"
+    };
+    let sql = format!(
+        "SELECT f.law_name, f.category, f.region, {},
+                (SELECT COUNT(*) FROM chunks c WHERE c.law_name = f.law_name)
+         FROM full_texts f",
+        date_expr
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let mut rows: Vec<(String, LawBrowseItem)> = stmt
+        .query_map([], |row| {
+            let raw: Option<String> = row.get(3)?;
+            let sort_key = if cached {
+                raw.unwrap_or_default()
+            } else {
+                latest_publish_date(raw).unwrap_or_default()
+            };
+            Ok((
+                sort_key,
+                LawBrowseItem {
+                    law_name: row.get(0)?,
+                    category: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    region: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    publish_date: None,
+                    article_count: row.get::<_, i64>(4)? as usize,
+                    is_favorite: false,
+                },
+            ))
+        })
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+
+    rows.retain(|(key, _)| key.as_str() >= from && key.as_str() <= to);
+    rows.sort_by(|(a, item_a), (b, item_b)| {
+        a.is_empty()
+            .cmp(&b.is_empty())
+            .then_with(|| b.cmp(a))
+            .then_with(|| item_a.law_name.cmp(&item_b.law_name))
+    });
+    rows.truncate(limit.clamp(1, 500));
+
+    let favorite_laws: HashSet<String> = user_db(state)
+        .ok()
+        .and_then(|user_conn| favorite_law_names(&user_conn).ok())
+        .unwrap_or_default();
+    Ok(rows
+        .into_iter()
+        .map(|(key, mut item)| {
+            item.publish_date = (!key.is_empty()).then(|| format_publish_date(&key));
+            item.is_favorite = favorite_laws.contains(&item.law_name);
+            item
+        })
+        .collect())
+}
+
+// 最近 months 个月内发布或修订的法规；months <= 0 时不限时间，日期未知的排在最后
+metered_command! {
+    #[tauri::command]
+    fn get_recent_laws(
+        months: i32,
+        limit: usize,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawBrowseItem>, AppError> {
+        let from = if months > 0 {
+            chrono::Local::now()
+                .date_naive()
+                .checked_sub_months(chrono::Months::new(months as u32))
+                .map(|date| date.format("%Y%m%d").to_string())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        query_laws_by_date(&state, &from, "99999999", limit)
+    }
+}
+
+// 某一年发布的法规，供时间轴浏览
+metered_command! {
+    #[tauri::command]
+    fn get_laws_by_year(
+        year: i32,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawBrowseItem>, AppError> {
+        if !(1900..=2100).contains(&year) {
+            return Err(AppError::validation("年份超出范围"));
+        }
+        query_laws_by_date(
+            &state,
+            &format!("{:04}0101", year),
+            &format!("{:04}1231", year),
+            500,
+        )
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_article_snippet(
+        law_name_query: Option<String>,
+        article_number: String,
+        current_law_name: String,
+        source_name: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        // 引用其他法规时到默认法规库中查找，本法内的引用留在当前数据源
+        let source_name = source_name.filter(|_| law_name_query.is_none());
+        let conn = source_db(&state, source_name.as_deref())?;
+
+        let target_law = match law_name_query {
+            Some(name) => name,
+            None => current_law_name,
+        };
+
+        match lookup_article(&conn, &target_law, &article_number)? {
+            Some((_, content)) => Ok(content),
+            None => Ok(format!("未找到《{}》的{}", target_law, article_number)),
+        }
+    }
+}
+
+// 内置引用格式：(模板, 多条之间的分隔符)
+fn default_citation_template(style: &str) -> Option<(&'static str, &'static str)> {
+    match style {
+        "brief" => Some(("《{short_name}》{article}", "、")),
+        "full" => Some(("《{law_name}》{article}\n“{content}”", "\n\n")),
+        "markdown" => Some(("> **《{law_name}》{article}**\n>\n> {content}", "\n\n")),
+        _ => None,
+    }
+}
+
+// 引用时省略“中华人民共和国”前缀，如《民法典》
+fn short_law_name(law_name: &str) -> &str {
+    law_name
+        .strip_prefix("中华人民共和国")
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(law_name)
+}
+
+// 按所选格式生成引用文本，由前端写入剪贴板；chunk_ids 的顺序即输出顺序
+metered_command! {
+    #[tauri::command]
+    fn format_citation(
+        chunk_ids: Vec<String>,
+        style: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let (default_template, separator) = default_citation_template(&style)
+            .ok_or_else(|| AppError::validation(format!("未知的引用格式: {}", style)))?;
+        let template = snapshot_settings(&state)
+            .citation_templates
+            .get(&style)
+            .filter(|t| !t.trim().is_empty())
+            .cloned()
+            .unwrap_or_else(|| default_template.to_string());
+
+        let conn = content_db(&state)?;
+        let sql = format!("SELECT {} FROM chunks WHERE id = ?1", CHUNK_COLUMNS);
+        let mut parts = Vec::with_capacity(chunk_ids.len());
+        for id in &chunk_ids {
+            let chunk = conn
+                .query_row(&sql, [id], law_chunk_from_row)
+                .optional()
+                .map_err(AppError::content_db_error)?
+                .ok_or_else(|| AppError::not_found(format!("未找到条文: {}", id)))?;
+            // Markdown 引用块中每一行都要带 "> "
+            let content = if style == "markdown" {
+                chunk.content.trim().replace('\n', "\n> ")
+            } else {
+                chunk.content.trim().to_string()
+            };
+            parts.push(
+                template
+                    .replace("{short_name}", short_law_name(&chunk.law_name))
+                    .replace("{law_name}", &chunk.law_name)
+                    .replace("{article}", &chunk.article_number)
+                    .replace("{content}", &content),
+            );
+        }
+        Ok(parts.join(separator))
+    }
+}
+
+// 下载本地嵌入模型到数据目录，进度通过 embedding-model-progress 事件推送
+// endpoint 可填 HuggingFace 镜像地址，默认 https://huggingface.co
+metered_command! {
+    #[tauri::command]
+    async fn download_embedding_model(
+        app: AppHandle,
+        endpoint: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = local_model_dir(&get_effective_data_dir(&state));
+        let client = http_client(&state);
+        let endpoint = endpoint
+            .filter(|e| !e.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_MODEL_ENDPOINT.to_string());
+        let endpoint = endpoint.trim().trim_end_matches('/');
+
+        for (index, file) in LOCAL_MODEL_FILES.iter().enumerate() {
+            let target = dir.join(file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| AppError::embedding_api("创建模型目录失败").with_detail(e))?;
+            }
+
+            let url = format!("{}/{}/resolve/main/{}", endpoint, LOCAL_MODEL_REPO, file);
+            let res = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| AppError::network(format!("下载 {} 失败", file)).with_detail(e))?;
+            if !res.status().is_success() {
+                return Err(AppError::network(format!(
+                    "下载 {} 失败 (HTTP {})",
+                    file,
+                    res.status().as_u16()
+                )));
+            }
+
+            let total = res.content_length();
+            let mut progress = ModelDownloadProgress {
+                file: file.to_string(),
+                file_index: index + 1,
+                file_count: LOCAL_MODEL_FILES.len(),
+                downloaded: 0,
+                total,
+            };
+            let _ = app.emit("embedding-model-progress", progress.clone());
+
+            let save_error = |e: std::io::Error| {
+                AppError::embedding_api(format!("保存 {} 失败", file)).with_detail(e)
+            };
+            // 先写临时文件，下载完整后再改名，避免中断后留下残缺模型
+            let part_path = dir.join(format!("{}.part", file));
+            let mut out = tokio::fs::File::create(&part_path)
+                .await
+                .map_err(save_error)?;
+            let mut stream = res.bytes_stream();
+            let mut last_emitted = 0;
+            while let Some(chunk) = stream.next().await {
+                let bytes = chunk
+                    .map_err(|e| AppError::network(format!("下载 {} 中断", file)).with_detail(e))?;
+                out.write_all(&bytes).await.map_err(save_error)?;
+                progress.downloaded += bytes.len() as u64;
+                // 每 512KB 推送一次进度
+                if progress.downloaded - last_emitted >= 512 * 1024 {
+                    last_emitted = progress.downloaded;
+                    let _ = app.emit("embedding-model-progress", progress.clone());
+                }
+            }
+            out.flush().await.map_err(save_error)?;
+            drop(out);
+            fs::rename(&part_path, &target).map_err(save_error)?;
+            let _ = app.emit("embedding-model-progress", progress);
+        }
+
+        // 下次检索时重新加载新文件
+        *LOCAL_EMBEDDER.lock_or_recover() = None;
+        Ok(dir.to_string_lossy().to_string())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn check_local_model(state: tauri::State<'_, AppState>) -> LocalModelStatus {
+        let dir = local_model_dir(&get_effective_data_dir(&state));
+        let missing_files: Vec<String> = LOCAL_MODEL_FILES
+            .iter()
+            .filter(|f| !dir.join(f).exists())
+            .map(|f| f.to_string())
+            .collect();
+
+        // 维度取自模型配置的 hidden_size，无需加载 ONNX
+        let dimension = fs::read_to_string(dir.join("config.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|config| config["hidden_size"].as_u64())
+            .map(|size| size as usize);
+
+        LocalModelStatus {
+            available: missing_files.is_empty(),
+            path: dir.to_string_lossy().to_string(),
+            dimension,
+            missing_files,
+        }
+    }
+}
+
+// 通过代理请求一个地址，报告是否连通及耗时；http_proxy 为空时使用已保存的设置
+metered_command! {
+    #[tauri::command]
+    async fn test_proxy(
+        http_proxy: Option<String>,
+        url: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, String> {
+        let mut settings = snapshot_settings(&state);
+        if http_proxy.is_some() {
+            settings.http_proxy = http_proxy;
+        }
+        let client = build_http_client(&settings)?;
+        let url = url
+            .filter(|u| !u.trim().is_empty())
+            .unwrap_or_else(|| PROXY_TEST_URL.to_string());
+
+        let started = Instant::now();
+        let res = client
+            .get(&url)
+            .timeout(Duration::from_secs(15))
+            .send()
+            .await
+            .map_err(|e| format!("连接失败: {}", e))?;
+
+        // 能拿到任意 HTTP 响应即说明代理链路可用
+        Ok(format!(
+            "连接成功：HTTP {}，耗时 {} ms",
+            res.status().as_u16(),
+            started.elapsed().as_millis()
+        ))
+    }
+}
+
+const PROXY_TEST_URL: &str = "https://api.openai.com/v1/models";
+
+// 重新测试嵌入服务；连通后清除中断记录，之后的检索恢复语义检索
+metered_command! {
+    #[tauri::command]
+    async fn probe_embedding_service(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<EmbeddingProbeResult, AppError> {
+        let started = Instant::now();
+        let vector =
+            get_embedding(&http_client(&state), &embedding_config(&state), "连接测试").await?;
+        *state.embedding_outage.lock_or_recover() = None;
+        info!("Embedding service reachable again");
+        Ok(EmbeddingProbeResult {
+            dimension: vector.len(),
+            latency_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    async fn check_ai_connection(
+        base_url: String,
+        api_key: String,
+        model: String,
+        api_format: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, String> {
+        let format = ChatApiFormat::from_setting(api_format.as_deref().unwrap_or_default());
+        probe_chat_service(&http_client(&state), &base_url, &api_key, &model, format).await
+    }
+}
+
+async fn probe_chat_service(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    format: ChatApiFormat,
+) -> Result<String, String> {
+    if format == ChatApiFormat::Anthropic {
+        return check_anthropic_connection(client, base_url, api_key, model).await;
+    }
+    if format == ChatApiFormat::Ollama {
+        return check_ollama_connection(client, base_url, model).await;
+    }
+
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+
+    let res = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .map_err(|e| describe_connect_error(&e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("连接失败: 服务器返回状态码 {}", res.status()));
+    }
+
+    let json: serde_json::Value = res.json().await.map_err(|e| format!("解析失败: {}", e))?;
+
+    if let Some(data) = json.get("data").and_then(|d| d.as_array()) {
+        let model_exists = data
+            .iter()
+            .any(|m| m.get("id").and_then(|id| id.as_str()) == Some(model));
+
+        if model_exists {
+            Ok(format!("连接成功！发现模型: {}", model))
+        } else {
+            Ok(format!(
+                "连接通畅，但在列表中未找到模型 '{}' (可能仍可用)",
+                model
+            ))
+        }
+    } else {
+        Ok("连接成功！(未能验证模型名称)".to_string())
+    }
+}
+
+// 首次运行检查清单：数据目录、向量库、嵌入/对话服务、用户库与安装模式
+metered_command! {
+    #[tauri::command]
+    async fn run_setup_diagnostics(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<SetupDiagnostics, String> {
+        let settings = snapshot_settings(&state);
+        let data_dir = resolve_data_dir(&state, &settings);
+        let client = http_client(&state);
+        let mut checks = Vec::new();
+
+        if let Some(path) = configured_data_path(&settings) {
+            let resolved = resolve_custom_data_path(&state, path);
+            if !resolved.is_dir() {
+                checks.push(
+                    DiagnosticCheck::warn(
+                        "DATA_DIR_MISSING",
+                        format!(
+                            "自定义数据路径无法访问，已回退到默认目录: {}",
+                            resolved.display()
+                        ),
+                    )
+                    .with_field("custom_data_path"),
+                );
+            }
+        }
+        checks.extend(check_data_dir(&data_dir));
+        checks.extend(check_content_schema(
+            &data_dir,
+            &settings.content_schema_map,
+        ));
+
+        // 嵌入服务：实际请求一次，顺带核对向量维度
+        let embedding_field = if settings.embedding_provider == "local" {
+            "embedding_provider"
+        } else {
+            "embedding_base_url"
+        };
+        let embedding_check =
+            match get_embedding(&client, &embedding_config(&state), "法律检索连通性测试").await
+            {
+                Ok(vector) => match open_vector_table(&data_dir).await {
+                    Ok(table) => match table_vector_dimension(&table).await {
+                        Ok(Some(db_dim)) if db_dim != vector.len() => DiagnosticCheck::fail(
+                            "EMBEDDING_DIMENSION",
+                            format!(
+                                "嵌入维度不匹配：模型输出{}维，数据库为{}维",
+                                vector.len(),
+                                db_dim
+                            ),
+                        )
+                        .with_field("embedding_model"),
+                        _ => DiagnosticCheck::ok(
+                            "EMBEDDING_SERVICE",
+                            format!("嵌入服务可用，向量维度 {}", vector.len()),
+                        ),
+                    },
+                    Err(_) => DiagnosticCheck::ok(
+                        "EMBEDDING_SERVICE",
+                        format!("嵌入服务可用，向量维度 {}", vector.len()),
+                    ),
+                },
+                Err(e) => DiagnosticCheck::fail("EMBEDDING_SERVICE", e.to_string())
+                    .with_field(embedding_field),
+            };
+        checks.push(embedding_check);
+
+        let chat_check = if !settings.enable_ai_chat {
+            DiagnosticCheck::ok("CHAT_SERVICE", "AI 对话未启用，已跳过")
+                .with_field("enable_ai_chat")
+        } else {
+            let format = ChatApiFormat::from_setting(&settings.chat_api_format);
+            match probe_chat_service(
+                &client,
+                &settings.chat_base_url,
+                &settings.chat_api_key,
+                &settings.chat_model,
+                format,
+            )
+            .await
+            {
+                Ok(message) => DiagnosticCheck::ok("CHAT_SERVICE", message),
+                Err(e) => DiagnosticCheck::fail("CHAT_SERVICE", e).with_field("chat_base_url"),
+            }
+        };
+        checks.push(chat_check);
+
+        // 关闭证书校验只应临时使用，诊断中一直提示
+        if settings.accept_invalid_certs {
+            checks.push(
+                DiagnosticCheck::fail(
+                    "TLS_VERIFY_DISABLED",
+                    "已关闭 HTTPS 证书校验：所有出站请求都可能被中间人截获（含 API Key），请尽快关闭",
+                )
+                .with_field("accept_invalid_certs"),
+            );
+        }
+        if let Some(path) = configured_extra_ca(&settings) {
+            checks.push(match load_extra_ca_certs(path) {
+                Ok(certs) => DiagnosticCheck::ok(
+                    "EXTRA_CA_CERT",
+                    format!("已加载附加 CA 证书 {} 张", certs.len()),
+                ),
+                Err(e) => DiagnosticCheck::fail("EXTRA_CA_CERT", e),
+            }
+            .with_field("extra_ca_cert_path"));
+        }
+
+        // BEGIN IMMEDIATE 会申请写锁，只读目录或被占用时在这里暴露
+        let user_db_check = user_db(&state).and_then(|conn| {
+            conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")
+                .map_err(AppError::user_db_error)
+        });
+        checks.push(match user_db_check {
+            Ok(()) => DiagnosticCheck::ok("USER_DB", "用户数据库可写"),
+            Err(e) => DiagnosticCheck::fail("USER_DB", format!("用户数据库不可写: {}", e)),
+        });
+
+        let install_mode = if state.portable_mode {
+            "portable"
+        } else {
+            "standard"
+        };
+        checks.push(DiagnosticCheck::ok(
+            "INSTALL_MODE",
+            if state.portable_mode {
+                "便携模式：设置与用户数据保存在程序目录"
+            } else {
+                "标准模式：设置与用户数据保存在系统应用目录"
+            },
+        ));
+
+        Ok(SetupDiagnostics {
+            install_mode: install_mode.to_string(),
+            data_dir: data_dir.to_string_lossy().to_string(),
+            checks,
+        })
+    }
+}
+
+// 切换数据目录：先确认目标目录数据完整再保存；传空字符串恢复为内置资源目录
+metered_command! {
+    #[tauri::command]
+    fn set_data_path(
+        app: AppHandle,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        let path = path.trim();
+        let custom_data_path = if path.is_empty() {
+            None
+        } else {
+            // 相对路径按 exe 目录（便携）或配置目录（标准）解析，保存时保留原样
+            let dir = resolve_custom_data_path(&state, path);
+            if !dir.is_dir() {
+                return Err(AppError::not_found(format!(
+                    "目录不存在: {}",
+                    dir.display()
+                )));
+            }
+            let missing: Vec<String> = check_data_dir(&dir)
+                .into_iter()
+                .filter(DiagnosticCheck::is_fail)
+                .map(|check| check.message)
+                .collect();
+            if !missing.is_empty() {
+                return Err(AppError::validation("所选目录不是有效的数据目录")
+                    .with_detail(missing.join("；")));
+            }
+            Some(path.to_string())
+        };
+
+        let mut new_settings = snapshot_settings(&state);
+        new_settings.custom_data_path = custom_data_path;
+        apply_settings(&app, &state, new_settings).map_err(AppError::validation)?;
+
+        let data_dir = get_effective_data_dir(&state);
+        let data_dir_str = data_dir.to_string_lossy().to_string();
+        info!("Data directory switched to {}", data_dir_str);
+        let _ = app.emit(
+            "data-path-changed",
+            DataPathChangedEvent {
+                data_dir: data_dir_str.clone(),
+                is_default: data_dir == state.app_data_dir,
+            },
+        );
+        Ok(data_dir_str)
+    }
+}
+
+fn install_mode_name(portable: bool) -> &'static str {
+    if portable {
+        "portable"
+    } else {
+        "standard"
+    }
+}
+
+fn install_mode_info(state: &AppState) -> InstallModeInfo {
+    let pending = resolve_install_paths(&state.exe_dir, &state.app_config_dir);
+    InstallModeInfo {
+        mode: install_mode_name(state.portable_mode).to_string(),
+        pending_mode: install_mode_name(pending.portable).to_string(),
+        settings_path: state.settings_path.to_string_lossy().to_string(),
+        user_db_path: state.user_db_path.to_string_lossy().to_string(),
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_install_mode(state: tauri::State<'_, AppState>) -> InstallModeInfo {
+        install_mode_info(&state)
+    }
+}
+
+// 数据源列表：默认数据目录在前，附加数据源按添加顺序
+fn data_source_infos(state: &AppState) -> Vec<DataSourceInfo> {
+    let settings = snapshot_settings(state);
+    let primary_dir = resolve_data_dir(state, &settings);
+    let mut sources = vec![DataSourceInfo {
+        name: PRIMARY_SOURCE_NAME.to_string(),
+        path: primary_dir.to_string_lossy().to_string(),
+        enabled: true,
+        primary: true,
+        reachable: primary_dir.is_dir(),
+    }];
+    sources.extend(settings.data_sources.iter().map(|source| {
+        let dir = resolve_custom_data_path(state, &source.path);
+        DataSourceInfo {
+            name: source.name.clone(),
+            path: dir.to_string_lossy().to_string(),
+            enabled: source.enabled,
+            primary: false,
+            reachable: dir.is_dir(),
+        }
+    }));
+    sources
+}
+
+metered_command! {
+    #[tauri::command]
+    fn list_data_sources(state: tauri::State<'_, AppState>) -> Vec<DataSourceInfo> {
+        data_source_infos(&state)
+    }
+}
+
+// 添加附加数据源：目录需包含 content.db 与 law_db.lancedb
+metered_command! {
+    #[tauri::command]
+    fn add_data_source(
+        app: AppHandle,
+        name: String,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DataSourceInfo>, AppError> {
+        let (name, path) = (name.trim(), path.trim());
+        if path.is_empty() {
+            return Err(AppError::validation("数据源目录不能为空"));
+        }
+        let dir = resolve_custom_data_path(&state, path);
+        let missing: Vec<String> = check_data_dir(&dir)
+            .into_iter()
+            .filter(DiagnosticCheck::is_fail)
+            .map(|check| check.message)
+            .collect();
+        if !missing.is_empty() {
+            return Err(
+                AppError::validation("所选目录不是有效的数据目录").with_detail(missing.join("；"))
+            );
+        }
+
+        let mut new_settings = snapshot_settings(&state);
+        new_settings.data_sources.push(DataSource {
+            name: name.to_string(),
+            path: path.to_string(),
+            enabled: true,
+        });
+        apply_settings(&app, &state, new_settings).map_err(AppError::validation)?;
+        info!("Data source added: {}", name);
+        Ok(data_source_infos(&state))
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn set_data_source_enabled(
+        app: AppHandle,
+        name: String,
+        enabled: bool,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DataSourceInfo>, AppError> {
+        let mut new_settings = snapshot_settings(&state);
+        let source = new_settings
+            .data_sources
+            .iter_mut()
+            .find(|source| source.name == name)
+            .ok_or_else(|| AppError::not_found(format!("数据源不存在: {}", name)))?;
+        source.enabled = enabled;
+        apply_settings(&app, &state, new_settings).map_err(AppError::validation)?;
+        Ok(data_source_infos(&state))
+    }
+}
+
+// 只移除设置中的登记，不删除目录中的文件
+metered_command! {
+    #[tauri::command]
+    fn remove_data_source(
+        app: AppHandle,
+        name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DataSourceInfo>, AppError> {
+        let mut new_settings = snapshot_settings(&state);
+        let before = new_settings.data_sources.len();
+        new_settings
+            .data_sources
+            .retain(|source| source.name != name);
+        if new_settings.data_sources.len() == before {
+            return Err(AppError::not_found(format!("数据源不存在: {}", name)));
+        }
+        apply_settings(&app, &state, new_settings).map_err(AppError::validation)?;
+        Ok(data_source_infos(&state))
+    }
+}
+
+// 当前实际使用的数据目录、设置文件与用户库路径
+metered_command! {
+    #[tauri::command]
+    fn get_effective_paths(state: tauri::State<'_, AppState>) -> EffectivePaths {
+        let settings = snapshot_settings(&state);
+        let data_dir = resolve_data_dir(&state, &settings);
+        let custom =
+            configured_data_path(&settings).map(|path| resolve_custom_data_path(&state, path));
+        EffectivePaths {
+            is_default: data_dir == state.app_data_dir,
+            data_dir: data_dir.to_string_lossy().to_string(),
+            custom_data_path_reachable: custom.as_ref().is_some_and(|path| path.is_dir()),
+            custom_data_path: custom.map(|path| path.to_string_lossy().to_string()),
+            settings_path: state.settings_path.to_string_lossy().to_string(),
+            user_db_path: state.user_db_path.to_string_lossy().to_string(),
+            log_dir: state.log_dir.to_string_lossy().to_string(),
+        }
+    }
+}
+
+// 便携/标准模式互转：复制设置与用户库到目标位置，核对行数后写入模式标记，重启生效
+metered_command! {
+    #[tauri::command]
+    fn convert_install_mode(
+        target: String,
+        overwrite: Option<bool>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<InstallModeInfo, String> {
+        let to_portable = match target.as_str() {
+            "portable" => true,
+            "standard" => false,
+            _ => return Err(format!("未知的安装模式: {}", target)),
+        };
+        if to_portable == state.portable_mode {
+            return Err("当前已是该安装模式".to_string());
+        }
+
+        let target_paths =
+            InstallPaths::for_mode(to_portable, &state.exe_dir, &state.app_config_dir);
+        let has_existing =
+            target_paths.settings_path.exists() || target_paths.user_db_path.exists();
+        if has_existing && !overwrite.unwrap_or(false) {
+            return Err(format!(
+                "目标位置已有数据，如需覆盖请确认后重试: {}",
+                target_paths.settings_path.display()
+            ));
+        }
+
+        if let Some(dir) = target_paths.settings_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("无法创建目标目录: {}", e))?;
+        }
+        // 先在目标位置写出临时文件，全部就绪后再改名，失败时不会留下半套数据
+        let staged_settings = staging_path(&target_paths.settings_path);
+        let staged_db = staging_path(&target_paths.user_db_path);
+        let result = write_settings_file(&staged_settings, &snapshot_settings(&state))
+            .and_then(|_| copy_user_db(&state.user_db_path, &staged_db))
+            .and_then(|_| {
+                switch_install_mode(
+                    &state.exe_dir,
+                    to_portable,
+                    &[
+                        (&staged_db, &target_paths.user_db_path),
+                        (&staged_settings, &target_paths.settings_path),
+                    ],
+                )
+            });
+        let _ = fs::remove_file(&staged_settings);
+        let _ = fs::remove_file(&staged_db);
+        result?;
+        info!(
+            "Install mode will switch to {} after restart",
+            install_mode_name(to_portable)
+        );
+
+        Ok(install_mode_info(&state))
+    }
+}
+
+fn staging_path(path: &std::path::Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".converting");
+    path.with_file_name(name)
+}
+
+// 先写模式标记，再把临时文件依次改名到位（设置文件最后）；任一步失败都恢复原来的标记，
+// 否则 exe 旁残留的 settings.json 会让下次启动误判为便携模式
+fn switch_install_mode(
+    exe_dir: &std::path::Path,
+    to_portable: bool,
+    renames: &[(&PathBuf, &PathBuf)],
+) -> Result<(), String> {
+    let marker = exe_dir.join(INSTALL_MODE_MARKER);
+    let previous = fs::read(&marker).ok();
+    fs::write(&marker, install_mode_name(to_portable))
+        .map_err(|e| format!("无法写入模式标记（程序目录可能不可写）: {}", e))?;
+
+    for (from, to) in renames {
+        if let Err(e) = fs::rename(from, to) {
+            let _ = match &previous {
+                Some(content) => fs::write(&marker, content),
+                None => fs::remove_file(&marker),
+            };
+            return Err(format!("无法写入 {}: {}", to.display(), e));
+        }
+    }
+    Ok(())
+}
+
+// 用 VACUUM INTO 生成一致的副本，再逐表核对行数
+fn copy_user_db(source: &std::path::Path, target: &std::path::Path) -> Result<(), String> {
+    if target.exists() {
+        fs::remove_file(target).map_err(|e| format!("无法清理上次残留的临时文件: {}", e))?;
+    }
+    let conn = connect_user_db(source)?;
+    conn.execute(
+        "VACUUM INTO ?1",
+        rusqlite::params![normalize_db_path(target)],
+    )
+    .map_err(|e| format!("复制用户库失败: {}", e))?;
+
+    let copied = Connection::open(normalize_db_path(target))
+        .map_err(|e| format!("无法打开复制后的用户库: {}", e))?;
+    for (table, expected) in table_row_counts(&conn)? {
+        let actual: i64 = copied
+            .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("校验用户库失败: {}", e))?;
+        if actual != expected {
+            return Err(format!(
+                "用户库复制不完整：表 {} 应有 {} 行，实际 {} 行",
+                table, expected, actual
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn table_row_counts(conn: &Connection) -> Result<Vec<(String, i64)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .map_err(|e| e.to_string())?;
+    let tables: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+    tables
+        .into_iter()
+        .map(|table| {
+            let count: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+                    row.get(0)
+                })
+                .map_err(|e| e.to_string())?;
+            Ok((table, count))
+        })
+        .collect()
+}
+
+// 数据目录中必须同时有 content.db（法条原文）与 law_db.lancedb（向量索引）
+fn check_data_dir(data_dir: &std::path::Path) -> Vec<DiagnosticCheck> {
+    let required = [
+        ("CONTENT_DB", "content.db", "法条数据库"),
+        ("VECTOR_DB", "law_db.lancedb", "向量索引"),
+    ];
+    required
+        .into_iter()
+        .map(|(code, file, label)| {
+            if data_dir.join(file).exists() {
+                DiagnosticCheck::ok(code, format!("已找到{} {}", label, file))
+            } else {
+                DiagnosticCheck::fail(
+                    code,
+                    format!("数据目录中缺少{} {}: {}", label, file, data_dir.display()),
+                )
+                .with_field("custom_data_path")
+            }
+        })
+        .collect()
+}
+
+const CONTENT_VERSION_FILE: &str = "content_version.json";
+// 更新时整体替换的文件；版本文件随数据一起换入，新目录中没有时跳过
+const CONTENT_DATA_FILES: [&str; 3] = ["content.db", "law_db.lancedb", CONTENT_VERSION_FILE];
+// 下载、解压与备份都放在数据目录内，保证最后的改名在同一文件系统上完成
+const CONTENT_STAGING_DIR: &str = ".content-update";
+
+static CONTENT_UPDATE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// 按语义化版本比较，旧版本（回退）不算更新；版本号无法解析时退回为“不同即更新”
+fn is_newer_content_version(current: Option<&str>, latest: &str) -> bool {
+    let Some(current) = current else {
+        return true;
+    };
+    match (
+        parse_content_version(current),
+        parse_content_version(latest),
+    ) {
+        (Some(current), Some(latest)) => latest > current,
+        _ => current.trim() != latest.trim(),
+    }
+}
+
+// 允许 v 前缀、2024.10 这类省略修订号与 2024.10.01 这类带前导零的日期版本
+fn parse_content_version(version: &str) -> Option<semver::Version> {
+    let version = version.trim().trim_start_matches(['v', 'V']);
+    semver::Version::parse(version).ok().or_else(|| {
+        let parts = version
+            .split('.')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        match parts[..] {
+            [major] => Some(semver::Version::new(major, 0, 0)),
+            [major, minor] => Some(semver::Version::new(major, minor, 0)),
+            [major, minor, patch] => Some(semver::Version::new(major, minor, patch)),
+            _ => None,
+        }
+    })
+}
+
+fn read_content_version(data_dir: &std::path::Path) -> Option<ContentVersion> {
+    let content = fs::read_to_string(data_dir.join(CONTENT_VERSION_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+async fn fetch_content_manifest(
+    client: &reqwest::Client,
+    settings: &AppSettings,
+) -> Result<ContentManifest, AppError> {
+    let url = settings
+        .content_update_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|u| !u.is_empty())
+        .ok_or_else(|| AppError::validation("未配置法规数据更新地址"))?;
+    let res = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| AppError::network("无法获取更新清单").with_detail(e))?;
+    if !res.status().is_success() {
+        return Err(
+            AppError::network("无法获取更新清单").with_detail(format!("HTTP {}", res.status()))
+        );
+    }
+    res.json::<ContentManifest>()
+        .await
+        .map_err(|e| AppError::validation("更新清单格式错误").with_detail(e))
+}
+
+metered_command! {
+    #[tauri::command]
+    async fn check_content_updates(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<ContentUpdateInfo, AppError> {
+        let settings = snapshot_settings(&state);
+        let manifest = fetch_content_manifest(&http_client(&state), &settings).await?;
+        let current_version =
+            read_content_version(&get_effective_data_dir(&state)).map(|v| v.version);
+        Ok(ContentUpdateInfo {
+            has_update: is_newer_content_version(current_version.as_deref(), &manifest.version),
+            current_version,
+            latest_version: manifest.version,
+            size: manifest.size,
+            published_at: manifest.published_at,
+            notes: manifest.notes,
+        })
+    }
+}
+
+// 下载并安装法规数据更新，进度通过 content-update-progress 事件推送
+// 下载、校验、解压任一步失败时原有数据保持不变
+metered_command! {
+    #[tauri::command]
+    async fn download_content_update(
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        if CONTENT_UPDATE_RUNNING.swap(true, Ordering::SeqCst) {
+            return Err(AppError::validation("数据更新正在进行中"));
+        }
+        let data_dir = get_effective_data_dir(&state);
+        let staging = data_dir.join(CONTENT_STAGING_DIR);
+        let result = install_content_update(&app, &state, &data_dir, &staging).await;
+        let _ = fs::remove_dir_all(&staging);
+        CONTENT_UPDATE_RUNNING.store(false, Ordering::SeqCst);
+
+        match &result {
+            Ok(version) => {
+                info!("Content data updated to {}", version);
+                // 新数据的向量维度可能不同，下次检索重新校验
+                *state.embedding_dimension_check.lock_or_recover() = None;
+                *state.index_meta_check.lock_or_recover() = None;
+                invalidate_content_caches(&state);
+                reset_cross_ref_index(&state, &data_dir);
+                spawn_cross_ref_index(&app);
+                let _ = app.emit(
+                    "content-update-progress",
+                    ContentUpdateProgress {
+                        stage: "done".to_string(),
+                        downloaded: 0,
+                        total: 0,
+                    },
+                );
+            }
+            Err(e) => error!("Content update failed: {}", e),
+        }
+        result
+    }
+}
+
+async fn install_content_update(
+    app: &AppHandle,
+    state: &AppState,
+    data_dir: &std::path::Path,
+    staging: &std::path::Path,
+) -> Result<String, AppError> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncWriteExt;
+
+    let settings = snapshot_settings(state);
+    let client = http_client(state);
+    let manifest = fetch_content_manifest(&client, &settings).await?;
+
+    let io_error = |e: std::io::Error| AppError::content_db("更新数据写入失败").with_detail(e);
+    if staging.exists() {
+        fs::remove_dir_all(staging).map_err(io_error)?;
+    }
+    fs::create_dir_all(staging).map_err(io_error)?;
+
+    // 1. 流式下载，同时计算 sha256
+    let res = client
+        .get(&manifest.url)
+        .send()
+        .await
+        .map_err(|e| AppError::network("下载更新包失败").with_detail(e))?;
+    if !res.status().is_success() {
+        return Err(
+            AppError::network("下载更新包失败").with_detail(format!("HTTP {}", res.status()))
+        );
+    }
+    let archive_path = staging.join("content.zip");
+    let mut out = tokio::fs::File::create(&archive_path)
+        .await
+        .map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    let mut progress = ContentUpdateProgress {
+        stage: "download".to_string(),
+        downloaded: 0,
+        total: manifest.size,
+    };
+    let _ = app.emit("content-update-progress", progress.clone());
+    let mut stream = res.bytes_stream();
+    let mut last_emitted = 0;
+    while let Some(chunk) = stream.next().await {
+        let bytes = chunk.map_err(|e| AppError::network("更新包下载中断").with_detail(e))?;
+        hasher.update(&bytes);
+        out.write_all(&bytes).await.map_err(io_error)?;
+        progress.downloaded += bytes.len() as u64;
+        if progress.downloaded - last_emitted >= 512 * 1024 {
+            last_emitted = progress.downloaded;
+            let _ = app.emit("content-update-progress", progress.clone());
+        }
+    }
+    out.flush().await.map_err(io_error)?;
+    drop(out);
+
+    // 2. 校验大小与 sha256
+    progress.stage = "verify".to_string();
+    let _ = app.emit("content-update-progress", progress.clone());
+    if progress.downloaded != manifest.size {
+        return Err(AppError::validation("更新包大小不符").with_detail(format!(
+            "应为 {} 字节，实际 {} 字节",
+            manifest.size, progress.downloaded
+        )));
+    }
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !digest.eq_ignore_ascii_case(manifest.sha256.trim()) {
+        return Err(AppError::validation("更新包校验失败，文件可能已损坏")
+            .with_detail(format!("sha256 应为 {}，实际 {}", manifest.sha256, digest)));
+    }
+
+    // 3. 解压到临时目录并确认数据完整
+    progress.stage = "extract".to_string();
+    let _ = app.emit("content-update-progress", progress.clone());
+    let extract_dir = staging.join("extracted");
+    let (zip_path, zip_target) = (archive_path.clone(), extract_dir.clone());
+    tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let file = fs::File::open(&zip_path).map_err(|e| e.to_string())?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+        archive.extract(&zip_target).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| AppError::validation("解压更新包失败").with_detail(e))?
+    .map_err(|e| AppError::validation("解压更新包失败").with_detail(e))?;
+    let missing: Vec<String> = check_data_dir(&extract_dir)
+        .into_iter()
+        .filter(DiagnosticCheck::is_fail)
+        .map(|check| check.message)
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::validation("更新包内容不完整").with_detail(missing.join("；")));
+    }
+
+    // 版本文件随数据一起换入，替换失败还原时不会出现新版本号配旧数据
+    let version = ContentVersion {
+        version: manifest.version.clone(),
+        installed_at: chrono::Local::now().to_rfc3339(),
+    };
+    let json =
+        serde_json::to_string_pretty(&version).map_err(|e| AppError::validation(e.to_string()))?;
+    fs::write(extract_dir.join(CONTENT_VERSION_FILE), json).map_err(io_error)?;
+
+    // 4. 替换数据文件：旧文件先移入备份目录，任一步失败则全部还原
+    progress.stage = "install".to_string();
+    let _ = app.emit("content-update-progress", progress);
+    let backup_dir = staging.join("backup");
+    fs::create_dir_all(&backup_dir).map_err(io_error)?;
+    // 先释放缓存的连接与向量表，Windows 下文件被占用时无法改名
+    invalidate_content_caches(state);
+    swap_content_files(data_dir, &extract_dir, &backup_dir).map_err(io_error)?;
+    if read_index_meta(data_dir).is_none() {
+        if let (Some(model), Some(dimension)) =
+            (&manifest.embedding_model, manifest.embedding_dimension)
+        {
+            if let Err(e) = write_index_meta(data_dir, model, dimension) {
+                warn!("Failed to write index meta: {}", e);
+            }
+        }
+    }
+    Ok(manifest.version)
+}
+
+fn swap_content_files(
+    data_dir: &std::path::Path,
+    new_dir: &std::path::Path,
+    backup_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let mut moved = Vec::new();
+    let mut installed = Vec::new();
+    let result = move_content_files(data_dir, new_dir, backup_dir, &mut moved, &mut installed);
+    if result.is_err() {
+        for file in &installed {
+            let _ = fs::rename(data_dir.join(file), new_dir.join(file));
+        }
+        for file in &moved {
+            let _ = fs::rename(backup_dir.join(file), data_dir.join(file));
+        }
+    }
+    result
+}
+
+fn move_content_files(
+    data_dir: &std::path::Path,
+    new_dir: &std::path::Path,
+    backup_dir: &std::path::Path,
+    moved: &mut Vec<&'static str>,
+    installed: &mut Vec<&'static str>,
+) -> std::io::Result<()> {
+    for file in CONTENT_DATA_FILES {
+        if data_dir.join(file).exists() {
+            fs::rename(data_dir.join(file), backup_dir.join(file))?;
+            moved.push(file);
+        }
+    }
+    for file in CONTENT_DATA_FILES {
+        if file == CONTENT_VERSION_FILE && !new_dir.join(file).exists() {
+            continue;
+        }
+        fs::rename(new_dir.join(file), data_dir.join(file))?;
+        installed.push(file);
+    }
+    Ok(())
+}
+
+const IMPORT_EMBED_BATCH: usize = 32;
+
+fn article_heading_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(第[一二三四五六七八九十百千零〇两0-9]+条(?:之[一二三四五六七八九十])?)")
+            .unwrap()
+    })
+}
+
+fn section_heading_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^第[一二三四五六七八九十百千零〇两0-9]+(编|章|节)").unwrap())
+}
+
+// 按行首的“第X条”切分；找不到任何条文时按空行分段，条号记为“第N段”
+fn split_law_articles(text: &str) -> Vec<ParsedArticle> {
+    let mut articles: Vec<ParsedArticle> = Vec::new();
+    let (mut part, mut chapter) = (String::new(), String::new());
+    for raw in text.lines() {
+        let line = raw.trim().trim_start_matches('#').trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(caps) = section_heading_regex().captures(line) {
+            match &caps[1] {
+                "编" => {
+                    part = line.to_string();
+                    chapter.clear();
+                }
+                "章" => chapter = line.to_string(),
+                _ => {}
+            }
+            continue;
+        }
+        if let Some(m) = article_heading_regex().find(line) {
+            articles.push(ParsedArticle {
+                part: part.clone(),
+                chapter: chapter.clone(),
+                article_number: m.as_str().to_string(),
+                content: line.to_string(),
+            });
+        } else if let Some(last) = articles.last_mut() {
+            last.content.push('\n');
+            last.content.push_str(line);
+        }
+    }
+    if !articles.is_empty() {
+        return articles;
+    }
+
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .enumerate()
+        .map(|(i, paragraph)| ParsedArticle {
+            part: String::new(),
+            chapter: String::new(),
+            article_number: format!("第{}段", i + 1),
+            content: paragraph.to_string(),
+        })
+        .collect()
+}
+
+fn ensure_imported_laws_table(conn: &Connection) -> Result<(), AppError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS imported_laws (
+            law_name TEXT PRIMARY KEY,
+            chunk_prefix TEXT NOT NULL,
+            category TEXT,
+            region TEXT,
+            publish_date TEXT,
+            article_count INTEGER,
+            imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(AppError::content_db_error)?;
+    Ok(())
+}
+
+// 按目标表的结构拼 RecordBatch：chunk_id 与向量列填值，其余列留空
+fn build_vector_batch(
+    schema: lancedb::arrow::arrow_schema::SchemaRef,
+    chunk_ids: Vec<String>,
+    vectors: Vec<Vec<f32>>,
+) -> Result<RecordBatch, AppError> {
+    let rows = chunk_ids.len();
+    let mut ids = Some(StringArray::from(chunk_ids));
+    let mut columns: Vec<ArrayRef> = Vec::new();
+    for field in schema.fields() {
+        let column: ArrayRef = match field.data_type() {
+            DataType::FixedSizeList(inner, size) => {
+                for vector in &vectors {
+                    compare_embedding_dimension(vector.len(), *size as usize)?;
+                }
+                let values = Float32Array::from(vectors.concat());
+                Arc::new(
+                    FixedSizeListArray::try_new(inner.clone(), *size, Arc::new(values), None)
+                        .map_err(AppError::vector_db_error)?,
+                )
+            }
+            _ if field.name() == "chunk_id" => match ids.take() {
+                Some(ids) => Arc::new(ids),
+                None => return Err(AppError::vector_db_error("重复的 chunk_id 列")),
+            },
+            other => arrow_array::new_null_array(other, rows),
+        };
+        columns.push(column);
+    }
+    RecordBatch::try_new(schema, columns).map_err(AppError::vector_db_error)
+}
+
+// 导入自有法规文本 (.txt / .md)：按条切分、批量嵌入后写入向量库与 content.db
+// 先在事务中写好 SQLite，再追加向量，最后提交；提交失败时按 chunk_id 前缀删除已追加的向量
+metered_command! {
+    #[tauri::command]
+    async fn import_law_document(
+        app: AppHandle,
+        path: String,
+        metadata: LawImportMeta,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<ImportedLaw, AppError> {
+        let file_path = std::path::Path::new(&path);
+        let is_text = file_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("txt") || e.eq_ignore_ascii_case("md"));
+        if !is_text {
+            return Err(AppError::validation("仅支持导入 .txt 或 .md 文件"));
+        }
+        let text = fs::read_to_string(file_path)
+            .map_err(|e| AppError::validation("无法读取文件（需为 UTF-8 编码）").with_detail(e))?;
+        let text = text.trim_start_matches('\u{feff}');
+
+        let law_name = metadata
+            .law_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(str::to_string)
+            .or_else(|| {
+                file_path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().trim().to_string())
+            })
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| AppError::validation("法规名称不能为空"))?;
+
+        let data_dir = get_effective_data_dir(&state);
+        {
+            let conn = connect_sqlite(&state, &data_dir)?;
+            ensure_writable_content_schema(&conn, &snapshot_settings(&state).content_schema_map)?;
+            let exists: bool = conn
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM full_texts WHERE law_name = ?1)",
+                    [&law_name],
+                    |row| row.get(0),
+                )
+                .map_err(AppError::content_db_error)?;
+            if exists {
+                return Err(AppError::validation(format!(
+                    "数据库中已有《{}》",
+                    law_name
+                )));
+            }
+        }
+
+        let articles = split_law_articles(text);
+        if articles.is_empty() {
+            return Err(AppError::validation("文件中没有可导入的内容"));
+        }
+        let mut progress = LawImportProgress {
+            law_name: law_name.clone(),
+            stage: "parsed".to_string(),
+            total: articles.len(),
+            embedded: 0,
+        };
+        let _ = app.emit("law-import-progress", progress.clone());
+
+        // 1. 批量嵌入；这一步失败时尚未写入任何数据
+        let client = http_client(&state);
+        let config = embedding_config(&state);
+        progress.stage = "embedding".to_string();
+        let mut vectors: Vec<Vec<f32>> = Vec::with_capacity(articles.len());
+        for batch in articles.chunks(IMPORT_EMBED_BATCH) {
+            let texts: Vec<String> = batch.iter().map(|a| a.content.clone()).collect();
+            vectors.extend(get_embeddings_batch(&client, &config, &texts).await?);
+            progress.embedded = vectors.len();
+            let _ = app.emit("law-import-progress", progress.clone());
+        }
+
+        // 2. 在事务中写入 content.db 并追加向量，向量写入成功后才提交
+        progress.stage = "saving".to_string();
+        let _ = app.emit("law-import-progress", progress.clone());
+        let chunk_prefix = imported_chunk_prefix()?;
+        let chunk_ids: Vec<String> = (0..articles.len())
+            .map(|i| format!("{}{}", chunk_prefix, i))
+            .collect();
+        let meta = ImportedLaw {
+            law_name,
+            category: metadata.category.trim().to_string(),
+            region: metadata.region.trim().to_string(),
+            publish_date: metadata.publish_date.trim().to_string(),
+            article_count: articles.len(),
+            imported_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        let table = open_vector_table(&data_dir).await?;
+        let schema = table.schema().await.map_err(AppError::vector_db_error)?;
+        let batch = build_vector_batch(schema.clone(), chunk_ids.clone(), vectors)?;
+
+        // Transaction 不能跨 await 持有，事务用 BEGIN / COMMIT 手动控制
+        let conn = connect_sqlite(&state, &data_dir)?;
+        ensure_imported_laws_table(&conn)?;
+        conn.execute_batch("BEGIN IMMEDIATE").map_err(AppError::content_db_error)?;
+        if let Err(e) =
+            insert_imported_law(&conn, &meta, &chunk_prefix, &chunk_ids, &articles, text)
+        {
+            let _ = conn.execute_batch("ROLLBACK");
+            return Err(e);
+        }
+        let added = table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await;
+        if let Err(e) = added {
+            let _ = conn.execute_batch("ROLLBACK");
+            return Err(AppError::vector_db_error(e));
+        }
+        if let Err(e) = conn.execute_batch("COMMIT") {
+            let _ = conn.execute_batch("ROLLBACK");
+            if let Err(cleanup) = table
+                .delete(&format!("chunk_id LIKE '{}%'", chunk_prefix))
+                .await
+            {
+                error!("Failed to roll back imported vectors: {}", cleanup);
+            }
+            return Err(AppError::content_db_error(e));
+        }
+
+        info!(
+            "Imported law {} ({} articles)",
+            meta.law_name, meta.article_count
+        );
+        invalidate_content_caches(&state);
+        spawn_cross_ref_index(&app);
+        progress.stage = "done".to_string();
+        let _ = app.emit("law-import-progress", progress);
+        Ok(meta)
+    }
+}
+
+// 导入的条文使用随机前缀，同一毫秒内的两次导入也不会冲突
+fn imported_chunk_prefix() -> Result<String, AppError> {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| AppError::validation("无法生成导入条文编号").with_detail(e))?;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("user-{}-", hex))
+}
+
+// 映射后的 content.db 是临时视图，不能写入；导入前检查，避免嵌入完成后才失败
+fn ensure_writable_content_schema(
+    conn: &Connection,
+    overrides: &ContentSchemaMap,
+) -> Result<(), AppError> {
+    let mappings = detect_content_schema(conn, overrides).map_err(AppError::content_db_error)?;
+    let non_standard = mappings
+        .iter()
+        .any(|m| !m.mapped.is_empty() || !m.defaulted.is_empty() || !m.missing_required.is_empty());
+    if non_standard {
+        return Err(AppError::validation(
+            "当前法规库的列名与标准格式不同，按列映射只读打开，不支持导入法规",
+        ));
+    }
+    Ok(())
+}
+
+// 在调用方开启的事务中写入条文、全文与导入记录
+fn insert_imported_law(
+    conn: &Connection,
+    meta: &ImportedLaw,
+    chunk_prefix: &str,
+    chunk_ids: &[String],
+    articles: &[ParsedArticle],
+    full_text: &str,
+) -> Result<(), AppError> {
+    {
+        let mut stmt = conn
+            .prepare(
+                "INSERT INTO chunks (id, content, law_name, category, region, publish_date, part, chapter, article_number)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .map_err(AppError::content_db_error)?;
+        for (id, article) in chunk_ids.iter().zip(articles) {
+            stmt.execute(rusqlite::params![
+                id,
+                article.content,
+                meta.law_name,
+                meta.category,
+                meta.region,
+                meta.publish_date,
+                article.part,
+                article.chapter,
+                article.article_number,
+            ])
+            .map_err(AppError::content_db_error)?;
+        }
+    }
+    conn.execute(
+        "INSERT INTO full_texts (law_name, region, category, full_text) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![meta.law_name, meta.region, meta.category, full_text],
+    )
+    .map_err(AppError::content_db_error)?;
+    conn.execute(
+        "INSERT INTO imported_laws (law_name, chunk_prefix, category, region, publish_date, article_count, imported_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            meta.law_name,
+            chunk_prefix,
+            meta.category,
+            meta.region,
+            meta.publish_date,
+            meta.article_count as i64,
+            meta.imported_at,
+        ],
+    )
+    .map_err(AppError::content_db_error)?;
+    Ok(())
+}
+
+metered_command! {
+    #[tauri::command]
+    fn list_imported_laws(state: tauri::State<'_, AppState>) -> Result<Vec<ImportedLaw>, AppError> {
+        let conn = content_db(&state)?;
+        ensure_imported_laws_table(&conn)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT law_name, category, region, publish_date, article_count, imported_at
+                 FROM imported_laws ORDER BY imported_at DESC",
+            )
+            .map_err(AppError::content_db_error)?;
+        let laws = stmt
+            .query_map([], |row| {
+                Ok(ImportedLaw {
+                    law_name: row.get(0)?,
+                    category: row.get(1).unwrap_or_default(),
+                    region: row.get(2).unwrap_or_default(),
+                    publish_date: row.get(3).unwrap_or_default(),
+                    article_count: row.get::<_, i64>(4).unwrap_or_default() as usize,
+                    imported_at: row.get(5).unwrap_or_default(),
+                })
+            })
+            .map_err(AppError::content_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(laws)
+    }
+}
+
+// 只能删除用户导入的法规，内置数据不受影响
+metered_command! {
+    #[tauri::command]
+    async fn delete_imported_law(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let data_dir = get_effective_data_dir(&state);
+        let chunk_prefix: String = {
+            let conn = connect_sqlite(&state, &data_dir)?;
+            ensure_imported_laws_table(&conn)?;
+            conn.query_row(
+                "SELECT chunk_prefix FROM imported_laws WHERE law_name = ?1",
+                [&law_name],
+                |row| row.get(0),
+            )
+            .map_err(|_| AppError::not_found(format!("《{}》不是导入的法规", law_name)))?
+        };
+
+        // 先删 SQLite：之后删除向量失败只会留下孤立向量，可由完整性检查清理
+        {
+            let mut conn = connect_sqlite(&state, &data_dir)?;
+            let tx = conn.transaction().map_err(AppError::content_db_error)?;
+            tx.execute(
+                "DELETE FROM chunks WHERE id LIKE ?1",
+                [format!("{}%", chunk_prefix)],
+            )
+            .map_err(AppError::content_db_error)?;
+            tx.execute("DELETE FROM full_texts WHERE law_name = ?1", [&law_name])
+                .map_err(AppError::content_db_error)?;
+            tx.execute("DELETE FROM imported_laws WHERE law_name = ?1", [&law_name])
+                .map_err(AppError::content_db_error)?;
+            tx.commit().map_err(AppError::content_db_error)?;
+        }
+
+        let table = open_vector_table(&data_dir).await?;
+        if let Err(e) = table
+            .delete(&format!("chunk_id LIKE '{}%'", chunk_prefix))
+            .await
+        {
+            warn!(
+                "Failed to delete vectors of imported law {}: {}",
+                law_name, e
+            );
+        }
+
+        // 该法规发出的引用一并删除，指向它的引用改回未解析
+        let index_path = cross_ref_db_path(&state, &data_dir);
+        if index_path.exists() {
+            let mut index = open_cross_ref_db(&index_path)?;
+            let tx = index.transaction().map_err(AppError::user_db_error)?;
+            tx.execute(
+                "DELETE FROM cross_refs WHERE source_id LIKE ?1",
+                [format!("{}%", chunk_prefix)],
+            )
+            .map_err(AppError::user_db_error)?;
+            tx.execute(
+                "UPDATE cross_refs SET target_id = NULL WHERE target_id LIKE ?1",
+                [format!("{}%", chunk_prefix)],
+            )
+            .map_err(AppError::user_db_error)?;
+            tx.execute(
+                "DELETE FROM cross_ref_scanned WHERE law_name = ?1",
+                [&law_name],
+            )
+            .map_err(AppError::user_db_error)?;
+            tx.commit().map_err(AppError::user_db_error)?;
+        }
+        info!("Deleted imported law {}", law_name);
+        invalidate_content_caches(&state);
+        Ok(())
+    }
+}
+
+fn dir_size(path: &std::path::Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn query_stat_groups(conn: &Connection, column: &str) -> Result<Vec<StatGroup>, AppError> {
+    let sql = format!(
+        "SELECT {0}, COUNT(DISTINCT law_name), COUNT(*) FROM chunks GROUP BY {0} ORDER BY COUNT(*) DESC",
+        column
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let groups = stmt
+        .query_map([], |row| {
+            Ok(StatGroup {
+                name: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                law_count: row.get::<_, i64>(1)? as usize,
+                chunk_count: row.get::<_, i64>(2)? as usize,
+            })
+        })
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+    Ok(groups)
+}
+
+fn compute_content_stats(
+    data_dir: &std::path::Path,
+    schema: &ContentSchemaMap,
+) -> Result<ContentStats, AppError> {
+    let conn = open_content_db(data_dir, schema)?;
+    let (law_count, chunk_count, earliest, latest): (i64, i64, Option<String>, Option<String>) =
+        conn.query_row(
+            "SELECT COUNT(DISTINCT law_name), COUNT(*),
+                    MIN(NULLIF(publish_date, '')), MAX(NULLIF(publish_date, ''))
+             FROM chunks",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(AppError::content_db_error)?;
+    let full_text_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM full_texts", [], |row| row.get(0))
+        .map_err(AppError::content_db_error)?;
+
+    Ok(ContentStats {
+        law_count: law_count as usize,
+        chunk_count: chunk_count as usize,
+        full_text_count: full_text_count as usize,
+        earliest_publish_date: earliest,
+        latest_publish_date: latest,
+        by_category: query_stat_groups(&conn, "category")?,
+        by_region: query_stat_groups(&conn, "region")?,
+        content_db_bytes: fs::metadata(data_dir.join("content.db"))
+            .map(|m| m.len())
+            .unwrap_or(0),
+        vector_db_bytes: dir_size(&data_dir.join("law_db.lancedb")),
+    })
+}
+
+// 法规库概览；refresh 为 true 时忽略缓存重新统计
+metered_command! {
+    #[tauri::command]
+    async fn get_db_stats(
+        refresh: Option<bool>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<DbStats, AppError> {
+        let cached = if refresh.unwrap_or(false) {
+            None
+        } else {
+            state.content_stats_cache.lock_or_recover().clone()
+        };
+        let content = match cached {
+            Some(stats) => stats,
+            None => {
+                let data_dir = get_effective_data_dir(&state);
+                let schema = snapshot_settings(&state).content_schema_map;
+                let stats =
+                    tokio::task::spawn_blocking(move || compute_content_stats(&data_dir, &schema))
+                        .await
+                        .map_err(AppError::content_db_error)??;
+                *state.content_stats_cache.lock_or_recover() = Some(stats.clone());
+                stats
+            }
+        };
+
+        let conn = user_db(&state)?;
+        let count = |table: &str| -> Result<usize, AppError> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|n| n as usize)
+            .map_err(AppError::user_db_error)
+        };
+        Ok(DbStats {
+            content,
+            favorite_count: count("favorites")?,
+            history_count: count("search_history")?,
+        })
+    }
+}
+
+const INTEGRITY_SAMPLE_LIMIT: usize = 1000;
+const ORPHAN_DELETE_BATCH: usize = 500;
+
+async fn vector_chunk_ids(table: &lancedb::Table) -> Result<HashSet<String>, AppError> {
+    let mut stream = table
+        .query()
+        .select(Select::columns(&["chunk_id"]))
+        .execute()
+        .await
+        .map_err(AppError::vector_db_error)?;
+    let mut ids = HashSet::new();
+    while let Some(batch) = stream.next().await {
+        let batch = batch.map_err(AppError::vector_db_error)?;
+        let column = batch
+            .column_by_name("chunk_id")
+            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+            .ok_or_else(|| AppError::vector_db_error("chunk_id error"))?;
+        ids.extend(column.iter().flatten().map(str::to_string));
+    }
+    Ok(ids)
+}
+
+fn sorted_sample(ids: impl Iterator<Item = String>) -> Vec<String> {
+    let mut ids: Vec<String> = ids.collect();
+    ids.sort();
+    ids.truncate(INTEGRITY_SAMPLE_LIMIT);
+    ids
+}
+
+// 核对向量库与 content.db：孤立向量、缺少向量的条文、没有条文的全文
+// repair 为 true 时删除孤立向量；缺少向量的条文需重建向量库或重新导入
+metered_command! {
+    #[tauri::command]
+    async fn verify_data_integrity(
+        repair: Option<bool>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<IntegrityReport, AppError> {
+        let data_dir = get_effective_data_dir(&state);
+        let table = open_vector_table(&data_dir).await?;
+        let vector_ids = vector_chunk_ids(&table).await?;
+
+        let (chunk_ids, laws_without_chunks) = {
+            let conn = connect_sqlite(&state, &data_dir)?;
+            let mut stmt = conn
+                .prepare("SELECT id FROM chunks")
+                .map_err(AppError::content_db_error)?;
+            let chunk_ids = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .map_err(AppError::content_db_error)?
+                .collect::<Result<HashSet<_>, _>>()
+                .map_err(AppError::content_db_error)?;
+            let mut stmt = conn
+                .prepare(
+                    "SELECT law_name FROM full_texts f
+                     WHERE NOT EXISTS (SELECT 1 FROM chunks c WHERE c.law_name = f.law_name)
+                     ORDER BY law_name",
+                )
+                .map_err(AppError::content_db_error)?;
+            let laws = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .map_err(AppError::content_db_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(AppError::content_db_error)?;
+            (chunk_ids, laws)
+        };
+
+        let orphans: Vec<String> = vector_ids.difference(&chunk_ids).cloned().collect();
+        let missing_vector_count = chunk_ids.difference(&vector_ids).count();
+        let mut report = IntegrityReport {
+            vector_count: vector_ids.len(),
+            chunk_count: chunk_ids.len(),
+            orphan_vector_count: orphans.len(),
+            orphan_vectors: sorted_sample(orphans.iter().cloned()),
+            missing_vector_count,
+            chunks_missing_vectors: sorted_sample(chunk_ids.difference(&vector_ids).cloned()),
+            laws_without_chunks,
+            repaired_vectors: 0,
+        };
+
+        if repair.unwrap_or(false) && !orphans.is_empty() {
+            for batch in orphans.chunks(ORPHAN_DELETE_BATCH) {
+                table
+                    .delete(&chunk_id_filter(batch))
+                    .await
+                    .map_err(AppError::vector_db_error)?;
+                report.repaired_vectors += batch.len();
+            }
+            info!("Deleted {} orphan vectors", report.repaired_vectors);
+            invalidate_content_caches(&state);
+        }
+
+        info!(
+            "Integrity check: {} orphan vectors, {} chunks without vectors, {} laws without chunks",
+            report.orphan_vector_count,
+            report.missing_vector_count,
+            report.laws_without_chunks.len()
+        );
+        Ok(report)
+    }
+}
+
+// 向量表按 chunk_id 批量匹配的过滤条件
+fn chunk_id_filter(ids: &[String]) -> String {
+    let list = ids
+        .iter()
+        .map(|id| format!("'{}'", id.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("chunk_id IN ({})", list)
+}
+
+const REBUILD_TABLE: &str = "laws_vectors_rebuild";
+const REBUILD_CHECKPOINT_FILE: &str = "vector_rebuild.json";
+const REBUILD_BATCH: usize = 64;
+
+static VECTOR_REBUILD_RUNNING: AtomicBool = AtomicBool::new(false);
+static VECTOR_REBUILD_CANCEL: AtomicBool = AtomicBool::new(false);
+
+// 嵌入配置指纹：换了模型或服务后旧断点作废
+fn embedding_fingerprint(config: &EmbeddingConfig, dimension: usize) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        config.provider, config.base_url, config.model, dimension
+    )
+}
+
+fn read_rebuild_checkpoint(data_dir: &std::path::Path) -> Option<RebuildCheckpoint> {
+    let content = fs::read_to_string(data_dir.join(REBUILD_CHECKPOINT_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn load_chunk_batch(
+    conn: &Connection,
+    after_rowid: i64,
+) -> Result<Vec<(i64, String, String, String)>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT rowid, id, content, law_name FROM chunks WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+        )
+        .map_err(AppError::content_db_error)?;
+    let rows = stmt
+        .query_map(
+            rusqlite::params![after_rowid, REBUILD_BATCH as i64],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+    Ok(rows)
+}
+
+fn count_chunks(conn: &Connection, sql: &str, rowid: i64) -> Result<usize, AppError> {
+    conn.query_row(sql, [rowid], |row| row.get::<_, i64>(0))
+        .map(|n| n as usize)
+        .map_err(AppError::content_db_error)
+}
+
+// 用当前嵌入模型重建向量库：写入新表 laws_vectors_rebuild，完成后替换 laws_vectors
+// 每批写入后记录断点，取消或中断后再次执行会从断点继续
+metered_command! {
+    #[tauri::command]
+    async fn rebuild_vector_index(
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        if VECTOR_REBUILD_RUNNING.swap(true, Ordering::SeqCst) {
+            return Err(AppError::validation("向量库正在重建中"));
+        }
+        VECTOR_REBUILD_CANCEL.store(false, Ordering::SeqCst);
+        let result = run_vector_rebuild(&app, &state).await;
+        VECTOR_REBUILD_RUNNING.store(false, Ordering::SeqCst);
+
+        match &result {
+            Ok(total) => {
+                info!("Vector index rebuilt ({} chunks)", total);
+                *state.embedding_dimension_check.lock_or_recover() = None;
+                *state.index_meta_check.lock_or_recover() = None;
+                invalidate_content_caches(&state);
+            }
+            Err(e) => warn!("Vector index rebuild stopped: {}", e),
+        }
+        result
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn cancel_vector_rebuild() {
+        VECTOR_REBUILD_CANCEL.store(true, Ordering::SeqCst);
+    }
+}
+
+async fn run_vector_rebuild<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &AppState,
+) -> Result<usize, AppError> {
+    use lancedb::arrow::arrow_schema::{Field, Schema};
+
+    let data_dir = get_effective_data_dir(state);
+    let client = http_client(state);
+    let config = embedding_config(state);
+
+    // 先探测嵌入服务，顺便取得新模型的向量维度
+    let probe = get_embedding(&client, &config, "法律")
+        .await
+        .map_err(|e| AppError::embedding_api("嵌入服务不可用，无法重建向量库").with_detail(e))?;
+    let fingerprint = embedding_fingerprint(&config, probe.len());
+
+    let db = connect_vector_db(&data_dir).await?;
+    let lancedb_dir = data_dir.join("law_db.lancedb");
+    let rebuild_dir = lancedb_dir.join(format!("{}.lance", REBUILD_TABLE));
+    let checkpoint_path = data_dir.join(REBUILD_CHECKPOINT_FILE);
+    let io_error = |e: std::io::Error| AppError::vector_db("重建向量库失败").with_detail(e);
+
+    // 续跑时断点之后的一批可能已写入新表、只是断点没来得及更新，写入前先删掉这批
+    let mut resuming = false;
+    let mut last_rowid = match read_rebuild_checkpoint(&data_dir) {
+        Some(checkpoint) if checkpoint.fingerprint == fingerprint && rebuild_dir.exists() => {
+            info!(
+                "Resuming vector rebuild after rowid {}",
+                checkpoint.last_rowid
+            );
+            resuming = true;
+            checkpoint.last_rowid
+        }
+        _ => {
+            if rebuild_dir.exists() {
+                fs::remove_dir_all(&rebuild_dir).map_err(io_error)?;
+            }
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("chunk_id", DataType::Utf8, false),
+                Field::new(
+                    "vector",
+                    DataType::FixedSizeList(
+                        Arc::new(Field::new("item", DataType::Float32, true)),
+                        probe.len() as i32,
+                    ),
+                    true,
+                ),
+            ]));
+            db.create_empty_table(REBUILD_TABLE, schema)
+                .execute()
+                .await
+                .map_err(AppError::vector_db_error)?;
+            0
+        }
+    };
+    let table = db
+        .open_table(REBUILD_TABLE)
+        .execute()
+        .await
+        .map_err(AppError::vector_db_error)?;
+    let schema = table.schema().await.map_err(AppError::vector_db_error)?;
+
+    let conn = connect_sqlite(state, &data_dir)?;
+    let total = count_chunks(
+        &conn,
+        "SELECT COUNT(*) FROM chunks WHERE rowid > ?1",
+        i64::MIN,
+    )?;
+    let mut processed = count_chunks(
+        &conn,
+        "SELECT COUNT(*) FROM chunks WHERE rowid <= ?1",
+        last_rowid,
+    )?;
+    let resumed_from = processed;
+    let started = Instant::now();
+
+    loop {
+        if VECTOR_REBUILD_CANCEL.load(Ordering::SeqCst) {
+            return Err(AppError::validation("已取消重建，再次重建时将从断点继续"));
+        }
+        let rows = load_chunk_batch(&conn, last_rowid)?;
+        let (batch_last_rowid, current_law) = match rows.last() {
+            Some(last) => (last.0, last.3.clone()),
+            None => break,
+        };
+
+        let texts: Vec<String> = rows.iter().map(|r| r.2.clone()).collect();
+        let vectors = get_embeddings_batch(&client, &config, &texts).await?;
+        let ids: Vec<String> = rows.into_iter().map(|r| r.1).collect();
+        if std::mem::take(&mut resuming) {
+            table
+                .delete(&chunk_id_filter(&ids))
+                .await
+                .map_err(AppError::vector_db_error)?;
+        }
+        let batch = build_vector_batch(schema.clone(), ids, vectors)?;
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .map_err(AppError::vector_db_error)?;
+
+        last_rowid = batch_last_rowid;
+        processed += texts.len();
+        let checkpoint = RebuildCheckpoint {
+            fingerprint: fingerprint.clone(),
+            last_rowid,
+        };
+        let json = serde_json::to_string(&checkpoint).unwrap_or_default();
+        write_file_atomic(&checkpoint_path, json.as_bytes()).map_err(io_error)?;
+
+        let done_this_run = (processed - resumed_from) as f64;
+        let eta_secs = (done_this_run > 0.0).then(|| {
+            let per_item = started.elapsed().as_secs_f64() / done_this_run;
+            (per_item * total.saturating_sub(processed) as f64) as u64
+        });
+        let _ = app.emit(
+            "vector-rebuild-progress",
+            VectorRebuildProgress {
+                processed,
+                total,
+                percent: if total == 0 {
+                    100.0
+                } else {
+                    processed as f32 * 100.0 / total as f32
+                },
+                eta_secs,
+                current_law,
+            },
+        );
+    }
+    drop(table);
+    *state.vector_table.lock_or_recover() = None;
+
+    // 目录改名替换旧表；新表挂载失败时还原
+    let current_dir = lancedb_dir.join("laws_vectors.lance");
+    let backup_dir = lancedb_dir.join("laws_vectors_old.lance");
+    if backup_dir.exists() {
+        fs::remove_dir_all(&backup_dir).map_err(io_error)?;
+    }
+    if current_dir.exists() {
+        fs::rename(&current_dir, &backup_dir).map_err(io_error)?;
+    }
+    if let Err(e) = fs::rename(&rebuild_dir, &current_dir) {
+        let _ = fs::rename(&backup_dir, &current_dir);
+        return Err(io_error(e));
+    }
+    let _ = fs::remove_dir_all(&backup_dir);
+    let _ = fs::remove_file(&checkpoint_path);
+    if let Err(e) = write_index_meta(&data_dir, &config.model, probe.len()) {
+        warn!("Failed to write index meta: {}", e);
+    }
+    Ok(processed)
+}
+
+// Anthropic 没有 /models 接口，用一次 max_tokens=1 的 messages 调用作为探测
+async fn check_anthropic_connection(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+) -> Result<String, String> {
+    let endpoint = ChatEndpoint::new(client, base_url, api_key, ChatApiFormat::Anthropic);
+    let mut body = build_chat_body(endpoint.format, model, None, "ping", false);
+    body["max_tokens"] = serde_json::json!(1);
+
+    let res = endpoint
+        .request(&body)
+        .send()
+        .await
+        .map_err(|e| describe_connect_error(&e))?;
+
+    let status = res.status();
+    if status.is_success() {
+        return Ok(format!("连接成功！模型 {} 可用", model));
+    }
+
+    let json: serde_json::Value = res.json().await.unwrap_or_default();
+    let message = json["error"]["message"].as_str().unwrap_or_default();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        Err(format!("连接通畅，但模型 '{}' 不可用: {}", model, message))
+    } else {
+        Err(format!("连接失败: 服务器返回状态码 {} {}", status, message))
+    }
+}
+
+// Ollama 原生接口通过 /api/tags 列出本地模型
+async fn check_ollama_connection(
+    client: &reqwest::Client,
+    base_url: &str,
+    model: &str,
+) -> Result<String, String> {
+    let url = format!("{}/api/tags", ollama_base(base_url));
+
+    let res = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| describe_connect_error(&e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("连接失败: 服务器返回状态码 {}", res.status()));
+    }
+
+    let json: serde_json::Value = res.json().await.map_err(|e| format!("解析失败: {}", e))?;
+    let model_exists = json["models"].as_array().is_some_and(|models| {
+        models.iter().any(|m| {
+            let name = m["name"].as_str().unwrap_or_default();
+            // Ollama 会为未写 tag 的模型补上 :latest
+            name == model || name == format!("{}:latest", model)
+        })
+    });
+
+    if model_exists {
+        Ok(format!("连接成功！发现模型: {}", model))
+    } else {
+        Ok(format!(
+            "连接通畅，但在本地模型中未找到 '{}'，请先执行 ollama pull",
+            model
+        ))
+    }
+}
+
+// 按 精确 -> 后缀 -> 包含 的顺序匹配法规名，返回 full_texts 中的完整名称
+fn resolve_law_name(conn: &Connection, raw_name: &str) -> Result<Option<String>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let candidates = [
+        ("SELECT law_name FROM full_texts WHERE law_name = ? LIMIT 1", raw_name.to_string()),
+        (
+            "SELECT law_name FROM full_texts WHERE law_name LIKE ? ESCAPE '\\' ORDER BY length(law_name) ASC LIMIT 1",
+            format!("%{}", like_escape(raw_name)),
+        ),
+        (
+            "SELECT law_name FROM full_texts WHERE law_name LIKE ? ESCAPE '\\' ORDER BY length(law_name) ASC LIMIT 1",
+            like_contains(raw_name),
+        ),
+    ];
+    for (sql, pattern) in candidates {
+        let found = conn
+            .query_row(sql, [&pattern], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(AppError::content_db_error)?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
+
+fn load_law_chunks(conn: &Connection, law_name: &str) -> Result<Vec<LawChunk>, AppError> {
+    let sql = format!(
+        "SELECT {} FROM chunks WHERE law_name = ? ORDER BY rowid",
+        CHUNK_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let chunks = stmt
+        .query_map([law_name], law_chunk_from_row)
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+    Ok(chunks)
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_full_text(
+        source_file: String,
+        structured: Option<bool>,
+        source_name: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<FullText, String> {
+        load_full_text(
+            &state,
+            &source_file,
+            structured.unwrap_or(false),
+            source_name.as_deref(),
+        )
+    }
+}
+
+fn load_full_text(
+    state: &AppState,
+    source_file: &str,
+    structured: bool,
+    source_name: Option<&str>,
+) -> Result<FullText, String> {
+    let raw_name = source_file.trim_end_matches(".txt");
+    let data_dir = source_data_dir(state, source_name)?;
+    let key = (data_dir.clone(), raw_name.to_string());
+    if !structured {
+        if let Some(text) = cached_full_text(state, &key) {
+            return Ok(FullText::Raw(text));
+        }
+    }
+    let conn = state
+        .content_pool
+        .get(&data_dir, |dir| connect_sqlite(state, dir))?;
+
+    let law_name = resolve_law_name(&conn, raw_name)?
+        .ok_or_else(|| format!("未找到法律文件：{}", raw_name))?;
+    if structured {
+        return Ok(FullText::Structured(load_law_chunks(&conn, &law_name)?));
+    }
+
+    let text: String = conn
+        .query_row(
+            "SELECT full_text FROM full_texts WHERE law_name = ? LIMIT 1",
+            [&law_name],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    cache_full_text(state, key, &text);
+    Ok(FullText::Raw(text))
+}
+
+// 检索结果中前几条涉及的法规会预读全文
+const FULL_TEXT_PREFETCH_RESULTS: usize = 10;
+const FULL_TEXT_CACHE_CAPACITY: usize = 20;
+
+fn full_text_cache_budget(settings: &AppSettings) -> usize {
+    if settings.low_memory_mode {
+        0
+    } else {
+        settings.full_text_cache_mb * 1024 * 1024
+    }
+}
+
+fn cached_full_text(state: &AppState, key: &FullTextCacheKey) -> Option<String> {
+    let mut cache = state.full_text_cache.lock_or_recover();
+    cache.get_mut(key).map(|entry| {
+        entry.used_at = Instant::now();
+        entry.text.clone()
+    })
+}
+
+// 超出条数或内存上限时淘汰最久未使用的条目；单篇超过上限的全文不缓存
+fn cache_full_text(state: &AppState, key: FullTextCacheKey, text: &str) {
+    let budget = full_text_cache_budget(&snapshot_settings(state));
+    if text.len() > budget {
+        return;
+    }
+    let mut cache = state.full_text_cache.lock_or_recover();
+    cache.remove(&key);
+    let mut used: usize = cache.values().map(|entry| entry.text.len()).sum();
+    while cache.len() >= FULL_TEXT_CACHE_CAPACITY || used + text.len() > budget {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.used_at)
+            .map(|(k, _)| k.clone());
+        match oldest.and_then(|k| cache.remove(&k)) {
+            Some(entry) => used -= entry.text.len(),
+            None => break,
+        }
+    }
+    cache.insert(
+        key,
+        CachedFullText {
+            text: text.to_string(),
+            used_at: Instant::now(),
+        },
+    );
+}
+
+// 后台读取结果中法规的全文放入缓存，不通知前端；失败只记日志
+fn spawn_full_text_prefetch(app: &AppHandle, settings: &AppSettings, results: &[LawChunk]) {
+    if full_text_cache_budget(settings) == 0 {
+        return;
+    }
+    let mut targets: Vec<(Option<String>, String)> = Vec::new();
+    for chunk in results.iter().take(FULL_TEXT_PREFETCH_RESULTS) {
+        let target = (chunk.source_name.clone(), chunk.law_name.clone());
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        for (source_name, law_name) in targets {
+            let data_dir = match source_data_dir(&state, source_name.as_deref()) {
+                Ok(data_dir) => data_dir,
+                Err(e) => {
+                    debug!("Full text prefetch skipped for {}: {}", law_name, e);
+                    continue;
+                }
+            };
+            let key = (data_dir, law_name);
+            if state.full_text_cache.lock_or_recover().contains_key(&key) {
+                continue;
+            }
+            let text = state
+                .content_pool
+                .get(&key.0, |dir| connect_sqlite(&state, dir))
+                .and_then(|conn| {
+                    conn.query_row(
+                        "SELECT full_text FROM full_texts WHERE law_name = ?1 LIMIT 1",
+                        [&key.1],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .map_err(AppError::content_db_error)
+                });
+            match text {
+                Ok(text) => cache_full_text(&state, key, &text),
+                Err(e) => debug!("Full text prefetch skipped for {}: {}", key.1, e),
+            }
+        }
+    });
+}
+
+// 按法名解析到库中的完整名称并读取全文
+fn read_law_full_text(conn: &Connection, law_name: &str) -> Result<(String, String), AppError> {
+    let resolved = resolve_law_name(conn, law_name.trim_end_matches(".txt"))?
+        .ok_or_else(|| AppError::not_found(format!("未找到法律文件：{}", law_name)))?;
+    let full_text: String = conn
+        .query_row(
+            "SELECT full_text FROM full_texts WHERE law_name = ? LIMIT 1",
+            [&resolved],
+            |row| row.get(0),
+        )
+        .map_err(AppError::content_db_error)?;
+    Ok((resolved, full_text))
+}
+
+impl TocNode {
+    fn new(title: &str, level: u8) -> Self {
+        Self {
+            title: title.to_string(),
+            level,
+            first_article: None,
+            chunk_id: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn starting_at(mut self, chunk: &LawChunk) -> Self {
+        self.first_article = Some(chunk.article_number.clone());
+        self.chunk_id = Some(chunk.id.clone());
+        self
+    }
+}
+
+const FULL_TEXT_MATCH_LIMIT: usize = 500;
+const FULL_TEXT_CONTEXT_CHARS: usize = 30;
+
+// 全文中每个条文标题行的起始偏移，按出现顺序排列
+fn article_offsets(full_text: &str) -> Vec<(usize, String)> {
+    let mut offsets = Vec::new();
+    let mut line_start = 0;
+    for line in full_text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(m) = article_heading_regex().find(trimmed) {
+            offsets.push((line_start, m.as_str().to_string()));
+        }
+        line_start += line.len();
+    }
+    offsets
+}
+
+// 命中位置前后各取若干字符作为上下文，切分点都落在字符边界上
+fn match_context(text: &str, start: usize, end: usize) -> (String, String) {
+    let before_start = text[..start]
+        .char_indices()
+        .rev()
+        .take(FULL_TEXT_CONTEXT_CHARS)
+        .last()
+        .map_or(start, |(i, _)| i);
+    let after_end = text[end..]
+        .char_indices()
+        .nth(FULL_TEXT_CONTEXT_CHARS)
+        .map_or(text.len(), |(i, _)| end + i);
+    let flatten = |s: &str| s.replace(['\r', '\n'], " ");
+    (
+        flatten(&text[before_start..start]),
+        flatten(&text[end..after_end]),
+    )
+}
+
+// 在单部法规全文中查找关键词，返回每处命中的上下文与所在条文
+metered_command! {
+    #[tauri::command]
+    fn search_in_full_text(
+        law_name: String,
+        keyword: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<FullTextSearchResult, AppError> {
+        let keyword = keyword.trim();
+        if keyword.is_empty() {
+            return Err(AppError::validation("关键词不能为空"));
+        }
+        let conn = content_db(&state)?;
+        let (resolved, full_text) = read_law_full_text(&conn, &law_name)?;
+        let chunk_ids: HashMap<String, String> = load_law_chunks(&conn, &resolved)?
+            .into_iter()
+            .map(|c| (c.article_number, c.id))
+            .collect();
+
+        let offsets = article_offsets(&full_text);
+        let mut hits = full_text.match_indices(keyword);
+        let matches: Vec<FullTextMatch> = hits
+            .by_ref()
+            .take(FULL_TEXT_MATCH_LIMIT)
+            .map(|(start, matched)| {
+                let end = start + matched.len();
+                let (before, after) = match_context(&full_text, start, end);
+                let article_number = offsets
+                    .partition_point(|(offset, _)| *offset <= start)
+                    .checked_sub(1)
+                    .map(|i| offsets[i].1.clone());
+                FullTextMatch {
+                    start,
+                    end,
+                    before,
+                    matched: matched.to_string(),
+                    after,
+                    chunk_id: article_number
+                        .as_ref()
+                        .and_then(|a| chunk_ids.get(a).cloned()),
+                    article_number,
+                }
+            })
+            .collect();
+
+        Ok(FullTextSearchResult {
+            law_name: resolved,
+            truncated: hits.next().is_some(),
+            matches,
+        })
+    }
+}
+
+// 沿最后一个子节点向下，挂到层级更高的最近标题下
+fn insert_toc_node(nodes: &mut Vec<TocNode>, node: TocNode) {
+    match nodes.last_mut() {
+        Some(last) if last.level < node.level => insert_toc_node(&mut last.children, node),
+        _ => nodes.push(node),
+    }
+}
+
+fn toc_from_chunks(chunks: &[LawChunk]) -> Vec<TocNode> {
+    let mut roots = Vec::new();
+    let (mut current_part, mut current_chapter) = ("", "");
+    for chunk in chunks {
+        let part = chunk.part.trim();
+        if !part.is_empty() && part != current_part {
+            current_part = part;
+            current_chapter = "";
+            insert_toc_node(&mut roots, TocNode::new(part, 1).starting_at(chunk));
+        }
+        let chapter = chunk.chapter.trim();
+        if !chapter.is_empty() && chapter != current_chapter {
+            current_chapter = chapter;
+            insert_toc_node(&mut roots, TocNode::new(chapter, 2).starting_at(chunk));
+        }
+    }
+    roots
+}
+
+// 章节字段为空时，从全文的 编/章/节 标题行解析目录
+fn toc_from_full_text(full_text: &str, chunks: &[LawChunk]) -> Vec<TocNode> {
+    let chunk_ids: HashMap<&str, &str> = chunks
+        .iter()
+        .map(|c| (c.article_number.as_str(), c.id.as_str()))
+        .collect();
+    let mut headings: Vec<TocNode> = Vec::new();
+    let mut pending = 0;
+    for raw in full_text.lines() {
+        let line = raw.trim();
+        if let Some(caps) = section_heading_regex().captures(line) {
+            let level = match &caps[1] {
+                "编" => 1,
+                "章" => 2,
+                _ => 3,
+            };
+            headings.push(TocNode::new(line, level));
+        } else if let Some(m) = article_heading_regex().find(line) {
+            for heading in &mut headings[pending..] {
+                heading.first_article = Some(m.as_str().to_string());
+                heading.chunk_id = chunk_ids.get(m.as_str()).map(|id| id.to_string());
+            }
+            pending = headings.len();
+        }
+    }
+
+    let mut roots = Vec::new();
+    for heading in headings {
+        insert_toc_node(&mut roots, heading);
+    }
+    roots
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_law_toc(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<TocNode>, AppError> {
+        let conn = content_db(&state)?;
+        let resolved = resolve_law_name(&conn, law_name.trim_end_matches(".txt"))?
+            .ok_or_else(|| AppError::not_found(format!("未找到法律文件：{}", law_name)))?;
+        let chunks = load_law_chunks(&conn, &resolved)?;
+
+        let toc = toc_from_chunks(&chunks);
+        if !toc.is_empty() {
+            return Ok(toc);
+        }
+        let full_text: String = conn
+            .query_row(
+                "SELECT full_text FROM full_texts WHERE law_name = ? LIMIT 1",
+                [&resolved],
+                |row| row.get(0),
+            )
+            .map_err(AppError::content_db_error)?;
+        Ok(toc_from_full_text(&full_text, &chunks))
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    async fn chat_stream(
+        app: AppHandle,
+        query: String,
+        context_chunks: Vec<ChatContextChunk>,
+        mode: String,
+        event_id: String,
+        options: Option<ChatOptions>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let settings = snapshot_settings(&state);
+
+        // 深度模式下，允许更多的上下文进入（例如 Top 10），普通模式 Top 5
+        let limit = if mode == "deep" || mode == "draft" {
+            settings.chat_top_k * 2
+        } else {
+            settings.chat_top_k
+        };
+
+        let request = ChatRequest {
+            query,
+            mode,
+            chunks: context_chunks.into_iter().take(limit).collect(),
+            options: options.unwrap_or_default(),
+            style_hint: None,
+        };
+        start_chat(app, &state, settings, request, event_id)
+    }
+}
+
+// 用上一次对话缓存的上下文重新生成回答，可附加风格要求（如“更简洁”）
+metered_command! {
+    #[tauri::command]
+    async fn regenerate_chat(
+        app: AppHandle,
+        event_id: String,
+        new_event_id: String,
+        style_hint: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let settings = snapshot_settings(&state);
+        let cached = {
+            let contexts = state.chat_contexts.lock_or_recover();
+            contexts
+                .get(&event_id)
+                .filter(|c| c.created_at.elapsed() < CHAT_CONTEXT_TTL)
+                .map(|c| c.request.clone())
+        };
+
+        let mut request =
+            cached.ok_or_else(|| AppError::not_found("上一次对话的上下文已失效，请重新提问"))?;
+        request.style_hint = style_hint;
+        start_chat(app, &state, settings, request, new_event_id)
+    }
+}
+
+const CHAT_CONTEXT_TTL: Duration = Duration::from_secs(60 * 60);
+
+// 记录本次对话上下文：先清理过期条目，超出容量时淘汰最早的一条
+fn cache_chat_context(state: &AppState, event_id: &str, request: ChatRequest, capacity: usize) {
+    let mut contexts = state.chat_contexts.lock_or_recover();
+    contexts.retain(|_, c| c.created_at.elapsed() < CHAT_CONTEXT_TTL);
+    if capacity == 0 {
+        return;
+    }
+    while contexts.len() >= capacity {
+        let oldest = contexts
+            .iter()
+            .min_by_key(|(_, c)| c.created_at)
+            .map(|(id, _)| id.clone());
+        match oldest {
+            Some(id) => contexts.remove(&id),
+            None => break,
+        };
+    }
+    contexts.insert(
+        event_id.to_string(),
+        CachedChatContext {
+            request,
+            created_at: Instant::now(),
+        },
+    );
+}
+
+// 检索结果均低于相关度阈值时跳过正式回答，只用一次轻量调用生成改写后的搜索词
+async fn respond_without_context(
+    app: AppHandle,
+    client: reqwest::Client,
+    settings: AppSettings,
+    query: String,
+    event_id: String,
+) {
+    let prompt = SUGGESTION_PROMPT.replace("{user_query}", &query);
+    let suggestions = match call_llm(
+        &client,
+        &settings.chat_model,
+        &prompt,
+        &settings.chat_base_url,
+        &settings.chat_api_key,
+        &settings.chat_api_format,
+        settings.chat_timeout_secs,
+    )
+    .await
+    {
+        Ok(raw) => parse_search_suggestions(&raw),
+        Err(e) => {
+            warn!("Suggestion request failed: {}", e);
+            Vec::new()
+        }
+    };
+
+    let mut message =
+        "未找到直接相关依据：检索到的法条与您的问题相关度均较低，为避免给出缺乏依据的回答，本次未生成分析。"
+            .to_string();
+    if !suggestions.is_empty() {
+        message.push_str("\n\n建议使用以下搜索词重新检索：");
+        for suggestion in &suggestions {
+            message.push_str(&format!("\n- {}", suggestion));
+        }
+    }
+
+    let _ = app.emit(
+        &format!("{}-ungrounded", event_id),
+        UngroundedEvent {
+            message: message.clone(),
+            suggestions,
+        },
+    );
+    let _ = app.emit(&event_id, message.clone());
+    let _ = app.emit(
+        &format!("{}-done", event_id),
+        ChatDoneEvent {
+            finish_reason: Some("ungrounded".to_string()),
+            total_chars: message.chars().count(),
+            ..Default::default()
+        },
+    );
+}
+
+fn start_chat(
+    app: AppHandle,
+    state: &AppState,
+    settings: AppSettings,
+    request: ChatRequest,
+    event_id: String,
+) -> Result<(), AppError> {
+    log_activity(state, &[("chat", None, None)]);
+    cache_chat_context(
+        state,
+        &event_id,
+        request.clone(),
+        settings.chat_context_cache_size,
+    );
+    let ChatRequest {
+        query,
+        mode,
+        chunks: mut selected_chunks,
+        options,
+        style_hint,
+    } = request;
+
+    // 严格依据：检索结果全部不相关时不让模型凭训练数据作答
+    if settings.strict_grounding && mode != "draft" && !has_relevant_context(&selected_chunks) {
+        let chat_task = tauri::async_runtime::spawn(respond_without_context(
+            app,
+            http_client(state),
+            settings,
+            query,
+            event_id.clone(),
+        ));
+        state
+            .chat_tasks
+            .lock_or_recover()
+            .insert(event_id, chat_task);
+        return Ok(());
+    }
+
+    // === 分析 Prompts ===
+
+    // 1. 深度思考模式 Prompt：专业法律意见书风格
+    let deep_prompt = r#"你是一位资深的中国法律顾问。用户提出了一个具体的法律问题，你已经通过检索工具找到了相关的法律条文。
+你的任务是根据这些法条，为用户撰写一份专业的《法律检索分析报告》。
+
+要求：
+1. 每个结论必须引用具体法条（格式：《XX法》第X条）
+2. 如果检索结果不足，明确说明缺少的部分
+3. 专业但通俗，避免过度术语堆砌
+4. 不编造法条，不做绝对承诺
+5. 不需要寒暄
+6. 引用法条时在句末标注上下文中的编号，如 [1]
+
+输出结构：
+
+一、核心结论
+用一句话回答用户的核心问题。
+
+二、法律依据分析
+针对争议点逐条分析：
+- 法条依据：《XX法》第X条规定...
+- 适用分析：对用户情况的具体解读
+- 注意事项：适用条件或例外情况
+
+三、实操建议
+1. 证据准备：需要保留哪些材料
+2. 维权路径：协商/仲裁/诉讼的具体步骤
+3. 时间节点：诉讼时效、关键期限
+
+---
+【检索到的法条上下文】：
+{context}
+"#;
+
+    // 2. 普通模式 Prompt
+    let simple_prompt = r#"你是一个法条检索助手。请基于以下检索结果，先简要评估其与用户问题的相关性。然后再给出回答。不需要寒暄。
+
+【检索到的法条】：
+{context}
+
+要求：
+1. 如果法条和问题高度相关，请直接根据法条内容回答用户问题，答案简洁明了，需要引用具体相关法条。不相关法条请予以忽略。
+输出示例：
+```
+关于（用户问题）的问题，（基于xx法xx条，此行为可能构成……）
+```
+2. 如果法条不相关，请直接告知用户“未找到直接相关依据”，并建议更换搜索词。搜索词应基于法条相似度Embedding的方向设计。
+输出示例：
+```
+查找到的法条相关度较低，根据您的问题，建议以下搜索词重新搜索：（数个搜索词）
+```
+3. 如果法条相关度完全不足，请告知用户检查向量模型和数据库是否匹配。
+4. 引用法条时在句末标注上下文中的编号，如 [1]。
+"#;
+
+    let draft_prompt = r#"你是一位专业的法律文书起草专家。用户提供了一些参考法条和具体的写作要求。
+你的任务是根据这些素材，起草一份高质量的法律文书或段落。
+
+【参考法条/素材】：
+{context}
+
+【要求】：
+1. 格式规范，用词严谨。
+2. 必须充分利用提供的素材中的法律依据。
+3. 如果用户提供了模版，请严格遵循模版的结构。
+4. 直接输出文书正文。
+5. 不要任何寒暄。
+6. 不要使用超过提供法条之外的法条文本。
+"#;
+
+    // 根据 mode 选择 prompt，已配置的自定义模版优先
+    let custom_deep = custom_template(&settings.custom_deep_prompt);
+    let custom_simple = custom_template(&settings.custom_simple_prompt);
+    let template = match mode.as_str() {
+        "deep" => custom_deep.unwrap_or(deep_prompt),
+        "draft" => draft_prompt,
+        "custom" => match custom_deep.or(custom_simple) {
+            Some(t) => t,
+            None => {
+                return Err(AppError::validation(
+                    "自定义模式需要先在设置中配置自定义提示词",
+                ))
+            }
+        },
+        _ => custom_simple.unwrap_or(simple_prompt),
+    };
+
+    // 重新生成时附加的风格要求
+    let extra = match style_hint.as_deref().map(str::trim) {
+        Some(hint) if !hint.is_empty() => format!("\n\n【补充要求】：{}", hint),
+        _ => String::new(),
+    };
+    let user_prompt = if mode == "draft" {
+        format!("【写作指令】：{}{}\n\n请开始起草：", query, extra)
+    } else {
+        format!("用户问题：{}{}\n\n请开始分析：", query, extra)
+    };
+
+    // 字数预算需扣除模版本身与用户问题，剩余部分才留给法条上下文
+    let mut dropped_chunks = 0;
+    if settings.chat_context_char_budget > 0 {
+        let template_len = template.chars().count();
+        let overhead =
+            template_len.saturating_sub(CONTEXT_PLACEHOLDER.len()) + user_prompt.chars().count();
+        let context_budget = settings.chat_context_char_budget.saturating_sub(overhead);
+        dropped_chunks = trim_chunks_to_budget(&mut selected_chunks, context_budget);
+    }
+
+    let mut context_str = build_context_string(&selected_chunks);
+    if dropped_chunks > 0 {
+        context_str.push_str(&format!(
+            "\n\n（另有{}条法条因篇幅限制未纳入）",
+            dropped_chunks
+        ));
+    }
+    let system_prompt = template.replace(CONTEXT_PLACEHOLDER, &context_str);
+    let event_id_for_task = event_id.clone();
+    let data_dir = get_effective_data_dir(state);
+    let client = http_client(state);
+    let span = tracing::info_span!("chat", event_id = %event_id, mode = %mode);
+    info!(parent: &span, "Chat started, query {}", log_query(&query));
+
+    let chat_task = tauri::async_runtime::spawn(
+        async move {
+            let endpoint = ChatEndpoint::new(
+                &client,
+                &settings.chat_base_url,
+                &settings.chat_api_key,
+                ChatApiFormat::from_setting(&settings.chat_api_format),
+            )
+            .with_timeout(settings.chat_timeout_secs);
+
+            let mut body = build_chat_body(
+                endpoint.format,
+                &settings.chat_model,
+                Some(&system_prompt),
+                &user_prompt,
+                true,
+            );
+            apply_sampling_params(&mut body, endpoint.format, &settings, &options, &mode);
+
+            let done_event = format!("{}-done", event_id_for_task);
+            let error_event = format!("{}-error", event_id_for_task);
+
+            let mut chat = ChatStreamState::default();
+            let result = if settings.chat_streaming_enabled {
+                match run_streaming_chat(&app, &endpoint, &body, &event_id_for_task, &mut chat)
+                    .await
+                {
+                    Ok(StreamOutcome::Completed) => Ok(()),
+                    Ok(StreamOutcome::Rejected(reason)) => {
+                        warn!(
+                            "Streaming rejected, falling back to non-streaming: {}",
+                            reason
+                        );
+                        body["stream"] = serde_json::json!(false);
+                        run_blocking_chat(&app, &endpoint, &body, &event_id_for_task, &mut chat)
+                            .await
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                body["stream"] = serde_json::json!(false);
+                run_blocking_chat(&app, &endpoint, &body, &event_id_for_task, &mut chat).await
+            };
+
+            if let Err(e) = result {
+                let _ = app.emit(&error_event, e);
+                return;
+            }
+
+            let (answer, reasoning) = chat.think.finish();
+            emit_chat_delta(&app, &event_id_for_task, &answer, &reasoning, &mut chat);
+
+            // 本地库不可用时不做法条核对，避免把所有引用都误标为“未找到”
+            let citations = match open_content_db(&data_dir, &settings.content_schema_map) {
+                Ok(conn) => resolve_citations(&conn, &chat.answer),
+                Err(e) => {
+                    warn!("Citation lookup skipped: {}", e);
+                    Vec::new()
+                }
+            };
+            let _ = app.emit(
+                &format!("{}-citations", event_id_for_task),
+                ChatCitationsEvent {
+                    references: collect_answer_references(&chat.answer, &selected_chunks),
+                    citations,
+                },
+            );
+            chat.summary.included_chunks = selected_chunks.len();
+            chat.summary.dropped_chunks = dropped_chunks;
+            let _ = app.emit(&done_event, chat.summary);
+        }
+        .instrument(span),
+    );
+
+    // 3. 将任务句柄存入 Map (使用原始的 event_id)
+    {
+        let mut tasks = state.chat_tasks.lock_or_recover();
+        tasks.insert(event_id, chat_task);
+    }
+
+    Ok(())
+}
+
+metered_command! {
+    #[tauri::command]
+    fn stop_chat(event_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+        let mut tasks = state.chat_tasks.lock_or_recover();
+        if let Some(handle) = tasks.remove(&event_id) {
+            handle.abort(); // 强制中止任务
+            info!("Chat task aborted: {}", event_id);
+        }
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn stop_task(event_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+        // 1. 尝试停止 Chat Stream 任务
+        let mut tasks = state.chat_tasks.lock_or_recover();
+        if let Some(handle) = tasks.remove(&event_id) {
+            handle.abort();
+            info!("Chat task aborted: {}", event_id);
+        }
+
+        // 2. 尝试停止 Agent 循环
+        let mut flags = state.agent_abort_flags.lock_or_recover();
+        if let Some(flag) = flags.remove(&event_id) {
+            flag.store(false, Ordering::Relaxed); // 设置开关为 false
+            info!("Agent loop abort signaled: {}", event_id);
+        }
+
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_settings(state: tauri::State<'_, AppState>) -> AppSettings {
+        snapshot_settings(&state)
+    }
+}
+
+// 启动阶段记录的问题；启动时已随 startup-warnings 事件推送，这里供晚于推送注册监听的前端补取
+metered_command! {
+    #[tauri::command]
+    fn get_startup_warnings(state: tauri::State<'_, AppState>) -> Vec<String> {
+        state.startup_warnings.lock_or_recover().clone()
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn validate_settings(settings: AppSettings) -> Vec<ValidationIssue> {
+        check_settings(&settings)
+    }
+}
+
+// 保存成功时返回不阻止保存的警告
+metered_command! {
+    #[tauri::command]
+    fn save_settings(
+        app: AppHandle,
+        new_settings: AppSettings,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<ValidationIssue>, String> {
+        apply_settings(&app, &state, new_settings)
+    }
+}
+
+// 只更新 patch 中出现的字段，避免各个开关分别提交整份设置时互相覆盖
+metered_command! {
+    #[tauri::command]
+    fn update_settings_partial(
+        app: AppHandle,
+        patch: serde_json::Value,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<AppSettings, String> {
+        let patch = match patch {
+            serde_json::Value::Object(map) => map,
+            _ => return Err("设置补丁必须是 JSON 对象".to_string()),
+        };
+
+        let current = snapshot_settings(&state);
+        let mut merged = serde_json::to_value(&current).map_err(|e| e.to_string())?;
+        let fields = merged.as_object_mut().ok_or("设置序列化失败")?;
+        for (key, value) in patch {
+            if !fields.contains_key(&key) {
+                return Err(format!("未知的设置项: {}", key));
+            }
+            fields.insert(key, value);
+        }
+        let new_settings: AppSettings =
+            serde_json::from_value(merged).map_err(|e| format!("设置项类型错误: {}", e))?;
+
+        for warning in apply_settings(&app, &state, new_settings.clone())? {
+            warn!("Settings warning [{}]: {}", warning.field, warning.message);
+        }
+        Ok(new_settings)
+    }
+}
+
+// 校验、落盘并替换当前设置；有 error 级问题时不做任何修改
+// 成功后按变更字段清理相关缓存，并广播 settings-changed
+fn apply_settings(
+    app: &AppHandle,
+    state: &AppState,
+    new_settings: AppSettings,
+) -> Result<Vec<ValidationIssue>, String> {
+    let (errors, mut warnings): (Vec<_>, Vec<_>) = check_settings(&new_settings)
+        .into_iter()
+        .partition(ValidationIssue::is_error);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.into_iter().map(|issue| issue.message).collect();
+        return Err(messages.join("；"));
+    }
+    let client = build_http_client(&new_settings)?;
+
+    let old_settings = snapshot_settings(state);
+    let changed_fields = settings_diff(&old_settings, &new_settings);
+
+    // 进行中的深度思考沿用开始时的配置快照，不会混用新旧数据库，这里只做提示
+    let search_field = changed_fields.iter().find(|f| {
+        f.as_str() == "custom_data_path"
+            || f.as_str() == "data_sources"
+            || EMBEDDING_FIELDS.contains(&f.as_str())
+    });
+    if let Some(field) = search_field {
+        if !state.agent_abort_flags.lock_or_recover().is_empty() {
+            warnings.push(ValidationIssue::warning(
+                field,
+                "深度思考正在进行，本次运行仍使用原数据目录和模型，新设置从下一次检索起生效",
+            ));
+        }
+    }
+
+    // 改为明文保存时，凭据管理器里的旧 Key 不再使用，一并删除
+    if new_settings.plaintext_api_keys && !old_settings.plaintext_api_keys {
+        if let Err(e) = delete_stored_api_keys() {
+            warn!("Failed to remove keychain entries: {}", e);
+        }
+    }
+    commit_settings(state, new_settings)?;
+    if changed_fields
+        .iter()
+        .any(|f| PROXY_FIELDS.contains(&f.as_str()))
+    {
+        *state.http_client.lock_or_recover() = client;
+    }
+    invalidate_settings_caches(state, &changed_fields);
+    // 新数据目录的引用索引按需增量建立
+    if changed_fields.iter().any(|f| f == "custom_data_path") {
+        spawn_cross_ref_index(app);
+    }
+    if changed_fields
+        .iter()
+        .any(|f| API_SERVER_FIELDS.contains(&f.as_str()))
+    {
+        restart_api_server(app);
+    }
+    // 快捷键被其他程序占用时设置照常保存，只提示用户更换
+    if changed_fields.iter().any(|f| f == "quick_search_shortcut") {
+        let shortcut = snapshot_settings(state).quick_search_shortcut;
+        if let Err(message) = register_quick_search_shortcut(app, &shortcut) {
+            warnings.push(ValidationIssue::warning("quick_search_shortcut", message));
+        }
+    }
+    if changed_fields.iter().any(|f| f == "enable_clipboard_watch") {
+        sync_clipboard_watcher(app);
+    }
+
+    if !changed_fields.is_empty() {
+        info!("Settings changed: {:?}", changed_fields);
+        let _ = app.emit("settings-changed", SettingsChangedEvent { changed_fields });
+    }
+    Ok(warnings)
+}
+
+// 先落盘再替换内存中的设置：写入失败时内存保持原值，与磁盘上的文件一致
+fn commit_settings(state: &AppState, new_settings: AppSettings) -> Result<(), String> {
+    write_settings_file(&state.settings_path, &new_settings)?;
+    let mut guard = settings_write_guard(state);
+    *guard = new_settings;
+    apply_log_settings(&guard);
+    Ok(())
+}
+
+// 变更后需要重启本地 HTTP API 的字段，其余设置变化不影响正在运行的服务
+const API_SERVER_FIELDS: &[&str] = &[
+    "api_server_enabled",
+    "api_server_port",
+    "api_server_token",
+    "api_server_cors_origins",
+];
+// 影响 HTTP 客户端的字段
+const PROXY_FIELDS: &[&str] = &[
+    "http_proxy",
+    "no_proxy_hosts",
+    "extra_ca_cert_path",
+    "accept_invalid_certs",
+];
+// 影响查询向量或向量库位置的字段，变更后需重新校验嵌入维度
+const EMBEDDING_FIELDS: &[&str] = &[
+    "embedding_provider",
+    "embedding_base_url",
+    "embedding_model",
+    "embedding_api_format",
+    "custom_data_path",
+];
+
+// 按序列化后的顶层字段逐一比较，返回发生变化的字段名
+fn settings_diff(old: &AppSettings, new: &AppSettings) -> Vec<String> {
+    let (old, new) = match (serde_json::to_value(old), serde_json::to_value(new)) {
+        (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) => (old, new),
+        _ => return Vec::new(),
+    };
+    new.iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+// 法规数据变化后清空依赖它的缓存
+fn invalidate_content_caches(state: &AppState) {
+    *state.content_stats_cache.lock_or_recover() = None;
+    *state.index_meta_check.lock_or_recover() = None;
+    state.search_result_cache.lock_or_recover().clear();
+    state.defined_terms_cache.lock_or_recover().clear();
+    state.full_text_cache.lock_or_recover().clear();
+    *state.vector_table.lock_or_recover() = None;
+    state.source_vector_tables.lock_or_recover().clear();
+    state.content_pool.clear();
+}
+
+fn invalidate_settings_caches(state: &AppState, changed_fields: &[String]) {
+    if changed_fields
+        .iter()
+        .any(|f| EMBEDDING_FIELDS.contains(&f.as_str()))
+    {
+        *state.embedding_dimension_check.lock_or_recover() = None;
+        *state.index_meta_check.lock_or_recover() = None;
+    }
+    if changed_fields
+        .iter()
+        .any(|f| f == "full_text_cache_mb" || f == "low_memory_mode")
+    {
+        state.full_text_cache.lock_or_recover().clear();
+    }
+    if changed_fields
+        .iter()
+        .any(|f| f == "custom_data_path" || f == "data_sources" || f == "content_schema_map")
+    {
+        invalidate_content_caches(state);
+    }
+    if changed_fields
+        .iter()
+        .any(|f| EMBEDDING_FIELDS.contains(&f.as_str()) || f == "search_top_k")
+    {
+        state.search_result_cache.lock_or_recover().clear();
+    }
+}
+
+// 恢复默认设置；keep_paths 为 true 时保留自定义数据路径
+metered_command! {
+    #[tauri::command]
+    fn reset_settings(
+        app: AppHandle,
+        keep_paths: bool,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<AppSettings, String> {
+        let mut new_settings = AppSettings::default();
+        if keep_paths {
+            new_settings.custom_data_path = snapshot_settings(&state).custom_data_path;
+        }
+        apply_settings(&app, &state, new_settings.clone())?;
+        Ok(new_settings)
+    }
+}
+
+// 导出文件中代替 API Key 与本地 API 令牌的占位符，导入时跳过
+const REDACTED_MARKER: &str = "<redacted>";
+
+metered_command! {
+    #[tauri::command]
+    fn export_settings(
+        path: String,
+        redact_keys: bool,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), String> {
+        let mut settings = snapshot_settings(&state);
+        if redact_keys {
+            let redact = |value: &mut String| {
+                if !value.is_empty() {
+                    *value = REDACTED_MARKER.to_string();
+                }
+            };
+            for (_, value) in api_key_fields(&mut settings) {
+                redact(value);
+            }
+            // 本地 API 令牌同样可以直接调用本机接口，与 API Key 一并脱敏
+            redact(&mut settings.api_server_token);
+        }
+        let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("无法写入导出文件: {}", e))
+    }
+}
+
+// 逐字段导入：未知字段、类型错误或未通过校验的字段被拒绝，其余照常应用
+metered_command! {
+    #[tauri::command]
+    fn import_settings(
+        app: AppHandle,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<SettingsImportReport, String> {
+        let content = fs::read_to_string(&path).map_err(|e| format!("无法读取导入文件: {}", e))?;
+        let imported = match serde_json::from_str::<serde_json::Value>(&content) {
+            Ok(serde_json::Value::Object(map)) => map,
+            Ok(_) => return Err("导入文件不是有效的设置对象".to_string()),
+            Err(e) => return Err(format!("导入文件格式错误: {}", e)),
+        };
+
+        let (new_settings, report) = merge_imported_settings(&snapshot_settings(&state), imported)?;
+        apply_settings(&app, &state, new_settings)?;
+        Ok(report)
+    }
+}
+
+// 逐字段合并导入的设置：某个字段引入新的 error（包括与其他字段组合后才出现的问题）时只拒绝该字段
+fn merge_imported_settings(
+    current: &AppSettings,
+    imported: serde_json::Map<String, serde_json::Value>,
+) -> Result<(AppSettings, SettingsImportReport), String> {
+    let errors_of = |settings: &AppSettings| -> Vec<(String, String)> {
+        check_settings(settings)
+            .into_iter()
+            .filter(ValidationIssue::is_error)
+            .map(|issue| (issue.field, issue.message))
+            .collect()
+    };
+    // 当前设置中已有的问题不归咎于导入的字段
+    let baseline = errors_of(current);
+    let mut candidate = serde_json::to_value(current).map_err(|e| e.to_string())?;
+    let mut report = SettingsImportReport::default();
+    let reject = |report: &mut SettingsImportReport, field: &str, reason: String| {
+        report.rejected.push(RejectedSetting {
+            field: field.to_string(),
+            reason,
+        });
+    };
+
+    for (key, value) in imported {
+        // 版本号描述的是文件结构，不随导入改变；本地 API 令牌只属于本机，不从其他设备导入
+        if key == "settings_version" || key == "api_server_token" {
+            continue;
+        }
+        if value.as_str() == Some(REDACTED_MARKER) || value.as_str() == Some(KEYRING_MARKER) {
+            reject(&mut report, &key, "已脱敏，保留当前值".to_string());
+            continue;
+        }
+        if candidate.get(&key).is_none() {
+            reject(&mut report, &key, "未知的设置项".to_string());
+            continue;
+        }
+
+        let mut trial = candidate.clone();
+        trial[&key] = value;
+        let parsed: AppSettings = match serde_json::from_value(trial.clone()) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                reject(&mut report, &key, format!("类型错误: {}", e));
+                continue;
+            }
+        };
+        let new_error = errors_of(&parsed)
+            .into_iter()
+            .find(|error| !baseline.contains(error));
+        match new_error {
+            Some((field, message)) if field == key => reject(&mut report, &key, message),
+            Some((field, message)) => {
+                reject(&mut report, &key, format!("与 {} 冲突: {}", field, message))
+            }
+            None => {
+                candidate = trial;
+                report.applied.push(key);
+            }
+        }
+    }
+
+    let new_settings: AppSettings = serde_json::from_value(candidate).map_err(|e| e.to_string())?;
+    Ok((new_settings, report))
+}
+
+// 删除凭据管理器中保存的 API Key；非明文模式下当前设置里的 Key 也随之清空
+metered_command! {
+    #[tauri::command]
+    fn clear_stored_credentials(state: tauri::State<'_, AppState>) -> Result<(), String> {
+        delete_stored_api_keys()?;
+
+        let mut guard = settings_write_guard(&state);
+        if !guard.plaintext_api_keys {
+            // 写入成功后再更新内存中的设置，保持与磁盘一致
+            let mut settings = guard.clone();
+            settings.embedding_api_key.clear();
+            settings.chat_api_key.clear();
+            write_settings_file(&state.settings_path, &settings)?;
+            *guard = settings;
+        }
+        Ok(())
+    }
+}
+
+// 读取最新一份日志的最后 N 行，方便用户附在问题反馈中
+metered_command! {
+    #[tauri::command]
+    fn get_recent_logs(
+        lines: usize,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<String>, String> {
+        let latest = fs::read_dir(&state.log_dir)
+            .map_err(|e| format!("无法读取日志目录: {}", e))?
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(LOG_FILE_PREFIX)
+            })
+            .max_by_key(|entry| entry.file_name());
+        let path = match latest {
+            Some(entry) => entry.path(),
+            None => return Ok(Vec::new()),
+        };
+
+        let bytes = fs::read(&path).map_err(|e| format!("无法读取日志文件: {}", e))?;
+        let content = String::from_utf8_lossy(&bytes);
+        let all: Vec<&str> = content.lines().collect();
+        let start = all.len().saturating_sub(lines);
+        Ok(all[start..].iter().map(|line| line.to_string()).collect())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn open_log_folder(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+        fs::create_dir_all(&state.log_dir).map_err(|e| e.to_string())?;
+        app.opener()
+            .open_path(state.log_dir.to_string_lossy(), None::<&str>)
+            .map_err(|e| format!("无法打开日志目录: {}", e))
+    }
+}
+
+// 各命令的调用次数与耗时，供诊断面板显示或附在问题反馈中
+metered_command! {
+    #[tauri::command]
+    fn get_metrics_snapshot(state: tauri::State<'_, AppState>) -> MetricsReport {
+        state.metrics.snapshot()
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn reset_metrics(state: tauri::State<'_, AppState>) {
+        state.metrics.reset();
+    }
+}
+
+// === 模型服务配置方案 ===
+
+// 把当前设置中的模型服务字段另存为方案，同名方案直接覆盖
+metered_command! {
+    #[tauri::command]
+    fn save_settings_profile(
+        name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::validation("方案名称不能为空"));
+        }
+        let settings = snapshot_settings(&state);
+        let mut profile = SettingsProfile::from_settings(name, &settings);
+        if !settings.plaintext_api_keys {
+            for (account, value) in profile.key_fields() {
+                stash_secret(&account, value).map_err(AppError::validation)?;
+            }
+        }
+
+        let data = serde_json::to_string(&profile).map_err(AppError::user_db_error)?;
+        let conn = user_db(&state)?;
+        conn.execute(
+            "INSERT INTO settings_profiles (name, data) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET data = excluded.data, updated_at = CURRENT_TIMESTAMP",
+            rusqlite::params![name, data],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// 列出全部方案；Key 字段保持落盘时的形式（占位符），不读取凭据管理器
+metered_command! {
+    #[tauri::command]
+    fn list_settings_profiles(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<SettingsProfile>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare("SELECT data FROM settings_profiles ORDER BY name")
+            .map_err(AppError::user_db_error)?;
+        let profiles = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
+            .filter_map(|data| serde_json::from_str(&data).ok())
+            .collect();
+        Ok(profiles)
+    }
+}
+
+// 应用方案：与 save_settings 走同一套校验与落盘流程
+metered_command! {
+    #[tauri::command]
+    fn apply_settings_profile(
+        app: AppHandle,
+        name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<AppSettings, AppError> {
+        let conn = user_db(&state)?;
+        let data: String = conn
+            .query_row(
+                "SELECT data FROM settings_profiles WHERE name = ?1",
+                rusqlite::params![name],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => {
+                    AppError::not_found(format!("配置方案不存在: {}", name))
+                }
+                e => AppError::user_db_error(e),
+            })?;
+        let mut profile: SettingsProfile =
+            serde_json::from_str(&data).map_err(AppError::user_db_error)?;
+        for (account, value) in profile.key_fields() {
+            resolve_secret(&account, value);
+        }
+
+        let mut new_settings = snapshot_settings(&state);
+        profile.apply_to(&mut new_settings);
+        apply_settings(&app, &state, new_settings.clone()).map_err(AppError::validation)?;
+        Ok(new_settings)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn delete_settings_profile(
+        name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute(
+            "DELETE FROM settings_profiles WHERE name = ?1",
+            rusqlite::params![name],
+        )
+        .map_err(AppError::user_db_error)?;
+
+        for account in SettingsProfile::key_accounts(&name) {
+            if let Err(e) = delete_secret(&account) {
+                warn!("Failed to remove keychain entry {}: {}", account, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+// === User Data CRUD Commands ===
+
+// 收藏标签以 JSON 数组字符串保存；旧数据可能是逗号分隔，读取时一并兼容
+fn parse_tags(raw: Option<String>) -> Vec<String> {
+    let raw = match raw {
+        Some(raw) if !raw.trim().is_empty() => raw,
+        _ => return Vec::new(),
+    };
+    let tags: Vec<String> = if raw.trim_start().starts_with('[') {
+        serde_json::from_str(&raw).unwrap_or_default()
+    } else {
+        raw.split([',', '，']).map(str::to_string).collect()
+    };
+    normalize_tags(tags)
+}
+
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+// 没有标签时存 NULL
+fn tags_to_json(tags: &[String]) -> Option<String> {
+    if tags.is_empty() {
+        None
+    } else {
+        serde_json::to_string(tags).ok()
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn add_favorite(
+        chunk: LawChunk,
+        folder_id: Option<i32>, // 修改：接收 folder_id
+        tags: Option<Vec<String>>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        insert_favorite(&state, chunk, folder_id, tags)
+    }
+}
+
+fn insert_favorite(
+    state: &AppState,
+    mut chunk: LawChunk,
+    folder_id: Option<i32>,
+    tags: Option<Vec<String>>,
+) -> Result<(), AppError> {
+    ensure_folder_exists(state, folder_id)?;
+    ensure_full_content(state, std::slice::from_mut(&mut chunk))?;
+    let conn = user_db(state)?;
+    let tags = tags.map(normalize_tags).and_then(|t| tags_to_json(&t));
+    // 重复收藏时未传标签则保留原有标签；传了文件夹时加入该文件夹，原有文件夹保持不变
+    retry_on_busy(|| {
+        conn.execute(
+            &format!(
+                "INSERT INTO favorites (law_id, law_name, article_number, content, tags, category, sort_order)
+                 VALUES (?1, ?2, ?3, ?4, ?5, NULLIF(?6, ''), {})
+                 ON CONFLICT(law_id) DO UPDATE SET
+                     tags = COALESCE(excluded.tags, favorites.tags),
+                     category = COALESCE(excluded.category, favorites.category)",
+                UNCATEGORIZED_NEXT_SORT_ORDER
+            ),
+            rusqlite::params![
+                chunk.id,
+                chunk.law_name,
+                chunk.article_number,
+                chunk.content,
+                tags,
+                chunk.category
+            ],
+        )
+    })?;
+    if let Some(folder_id) = folder_id {
+        retry_on_busy(|| link_favorite_to_folder(&conn, &chunk.id, folder_id))?;
+    }
+    enroll_reviews_if_enabled(state, &[chunk.id.as_str()]);
+    log_activity(state, &[("favorite", Some(chunk.law_name.as_str()), None)]);
+    Ok(())
+}
+
+// 按引用文本直接收藏，如 "《民法典》第1043条"。条号可写阿拉伯数字，法名与快速查询一样
+// 按 resolve_law_name 解析（“刑法”对应《中华人民共和国刑法》）；
+// 本地库中没有该条时返回 NotFound，detail 为解析出的 { law_name, article_number } JSON，
+// 供前端改为联网搜索
+metered_command! {
+    #[tauri::command]
+    fn favorite_by_citation(
+        citation: String,
+        folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<UserFavorite, AppError> {
+        let parsed = parse_single_citation(&citation)
+            .ok_or_else(|| AppError::validation("未识别到法条引用，请使用《法规名》第X条的格式"))?;
+        ensure_folder_exists(&state, folder_id)?;
+
+        let chunk = resolve_citation(&*content_db(&state)?, &parsed)?.ok_or_else(|| {
+            AppError::not_found(format!(
+                "本地库中未找到《{}》{}",
+                parsed.law_name, parsed.article_number
+            ))
+            .with_detail(serde_json::json!({
+                "law_name": parsed.law_name,
+                "article_number": parsed.article_number,
+            }))
+        })?;
+
+        let law_id = chunk.id.clone();
+        insert_favorite(&state, chunk, folder_id, None)?;
+        let conn = user_db(&state)?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM favorites WHERE law_id = ?1",
+                FAVORITE_COLUMNS
+            ),
+            [&law_id],
+            favorite_from_row,
+        )
+        .map_err(AppError::user_db_error)
+    }
+}
+
+// 覆盖收藏的标签，返回去重整理后的标签
+metered_command! {
+    #[tauri::command]
+    fn update_favorite_tags(
+        law_id: String,
+        tags: Vec<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<String>, AppError> {
+        let conn = user_db(&state)?;
+        let tags = normalize_tags(tags);
+        let updated = conn
+            .execute(
+                "UPDATE favorites SET tags = ?2 WHERE law_id = ?1",
+                rusqlite::params![law_id, tags_to_json(&tags)],
+            )
+            .map_err(AppError::user_db_error)?;
+        if updated == 0 {
+            return Err(AppError::not_found("该条文尚未收藏"));
+        }
+        Ok(tags)
+    }
+}
+
+// 所有标签及使用次数，按次数降序，供标签选择器自动补全
+metered_command! {
+    #[tauri::command]
+    fn get_all_tags(state: tauri::State<'_, AppState>) -> Result<Vec<TagCount>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare("SELECT tags FROM favorites WHERE tags IS NOT NULL AND tags != ''")
+            .map_err(AppError::user_db_error)?;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let rows = stmt
+            .query_map([], |row| row.get::<_, Option<String>>(0))
+            .map_err(AppError::user_db_error)?;
+        for raw in rows.filter_map(Result::ok) {
+            for tag in parse_tags(raw) {
+                *counts.entry(tag).or_default() += 1;
+            }
+        }
+
+        let mut tags: Vec<TagCount> = counts
+            .into_iter()
+            .map(|(tag, count)| TagCount { tag, count })
+            .collect();
+        tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        Ok(tags)
+    }
+}
+
+// 从 from_folder_id 移到 folder_id；不传 from_folder_id 时移出所有文件夹。
+// folder_id 为空表示只移出，不再属于任何文件夹时回到未分类
+metered_command! {
+    #[tauri::command]
+    fn move_favorite(
+        law_id: String,
+        folder_id: Option<i32>,
+        from_folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        if move_favorite_links(&tx, &law_id, from_folder_id, folder_id)? == 0 {
+            return Err(AppError::not_found("该条文尚未收藏"));
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// 把收藏加入文件夹（已在其中时不变），排在文件夹末尾
+fn link_favorite_to_folder(
+    conn: &Connection,
+    law_id: &str,
+    folder_id: i32,
+) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR IGNORE INTO favorite_folder_links (law_id, folder_id, sort_order)
+         VALUES (?1, ?2,
+             (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM favorite_folder_links WHERE folder_id = ?2))",
+        rusqlite::params![law_id, folder_id],
+    )
+}
+
+// 返回 0 表示该条文未收藏
+fn move_favorite_links(
+    conn: &Connection,
+    law_id: &str,
+    from_folder_id: Option<i32>,
+    to_folder_id: Option<i32>,
+) -> Result<usize, AppError> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM favorites WHERE law_id = ?1)",
+            [law_id],
+            |row| row.get(0),
+        )
+        .map_err(AppError::user_db_error)?;
+    if !exists {
+        return Ok(0);
+    }
+    if from_folder_id.is_some() && from_folder_id == to_folder_id {
+        return Ok(1);
+    }
+    // 回到未分类时排在未分类末尾
+    if to_folder_id.is_none() {
+        conn.execute(
+            &format!(
+                "UPDATE favorites SET sort_order = {} WHERE law_id = ?1",
+                UNCATEGORIZED_NEXT_SORT_ORDER
+            ),
+            [law_id],
+        )
+        .map_err(AppError::user_db_error)?;
+    }
+    conn.execute(
+        "DELETE FROM favorite_folder_links WHERE law_id = ?1 AND (?2 IS NULL OR folder_id = ?2)",
+        rusqlite::params![law_id, from_folder_id],
+    )
+    .map_err(AppError::user_db_error)?;
+    if let Some(folder_id) = to_folder_id {
+        link_favorite_to_folder(conn, law_id, folder_id).map_err(AppError::user_db_error)?;
+    }
+    Ok(1)
+}
+
+// 包含该收藏的全部文件夹，按加入时间排序
+metered_command! {
+    #[tauri::command]
+    fn get_favorite_folders(
+        law_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<UserFolder>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT d.id, d.name, d.created_at, d.parent_id
+                 FROM favorite_folder_links l JOIN favorite_folders d ON d.id = l.folder_id
+                 WHERE l.law_id = ?1
+                 ORDER BY l.created_at, d.id",
+            )
+            .map_err(AppError::user_db_error)?;
+        let folders = stmt
+            .query_map([&law_id], |row| {
+                Ok(UserFolder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    created_at: row.get(2)?,
+                    parent_id: row.get(3)?,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        Ok(folders)
+    }
+}
+
+// 在一个事务里对每个 law_id 执行同一条语句，未影响任何行的记为 skipped
+fn run_favorites_bulk(
+    state: &AppState,
+    sql: &str,
+    law_ids: Vec<String>,
+    execute: impl Fn(&mut rusqlite::Statement, &str) -> rusqlite::Result<usize>,
+) -> Result<BulkResult, AppError> {
+    let mut conn = user_db(state)?;
+    let tx = conn.transaction().map_err(AppError::user_db_error)?;
+    let mut result = BulkResult::default();
+    {
+        let mut stmt = tx.prepare(sql).map_err(AppError::user_db_error)?;
+        for law_id in law_ids {
+            let changed = execute(&mut stmt, &law_id).map_err(AppError::user_db_error)?;
+            if changed == 0 {
+                result.skipped.push(law_id);
+            } else {
+                result.affected += changed;
+            }
+        }
+    }
+    tx.commit().map_err(AppError::user_db_error)?;
+    Ok(result)
+}
+
+fn ensure_folder_exists(state: &AppState, folder_id: Option<i32>) -> Result<(), AppError> {
+    if let Some(id) = folder_id {
+        if !folder_exists(&*user_db(state)?, id)? {
+            return Err(AppError::not_found("文件夹不存在"));
+        }
+    }
+    Ok(())
+}
+
+// 批量收藏；已收藏的条文保持原样并记入 skipped
+metered_command! {
+    #[tauri::command]
+    fn add_favorites_bulk(
+        mut chunks: Vec<LawChunk>,
+        folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<BulkResult, AppError> {
+        ensure_folder_exists(&state, folder_id)?;
+        ensure_full_content(&state, &mut chunks)?;
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let mut result = BulkResult::default();
+        let mut added = Vec::new();
+        {
+            let mut stmt = tx
+                .prepare(&format!(
+                    "INSERT INTO favorites (law_id, law_name, article_number, content, category, sort_order)
+                     VALUES (?1, ?2, ?3, ?4, NULLIF(?5, ''), {})
+                     ON CONFLICT(law_id) DO NOTHING",
+                    UNCATEGORIZED_NEXT_SORT_ORDER
+                ))
+                .map_err(AppError::user_db_error)?;
+            for chunk in chunks {
+                let changed = stmt
+                    .execute(rusqlite::params![
+                        chunk.id,
+                        chunk.law_name,
+                        chunk.article_number,
+                        chunk.content,
+                        chunk.category
+                    ])
+                    .map_err(AppError::user_db_error)?;
+                if let (Some(folder_id), true) = (folder_id, changed > 0) {
+                    link_favorite_to_folder(&tx, &chunk.id, folder_id)
+                        .map_err(AppError::user_db_error)?;
+                }
+                if changed == 0 {
+                    result.skipped.push(chunk.id);
+                } else {
+                    result.affected += changed;
+                    added.push(chunk.id);
+                }
+            }
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+        let added: Vec<&str> = added.iter().map(String::as_str).collect();
+        enroll_reviews_if_enabled(&state, &added);
+        Ok(result)
+    }
+}
+
+// 与 move_favorite 相同，from_folder_id 为空时移出所有文件夹
+metered_command! {
+    #[tauri::command]
+    fn move_favorites_bulk(
+        law_ids: Vec<String>,
+        folder_id: Option<i32>,
+        from_folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<BulkResult, AppError> {
+        ensure_folder_exists(&state, folder_id)?;
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let mut result = BulkResult::default();
+        for law_id in law_ids {
+            match move_favorite_links(&tx, &law_id, from_folder_id, folder_id)? {
+                0 => result.skipped.push(law_id),
+                changed => result.affected += changed,
+            }
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+        Ok(result)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn remove_favorites_bulk(
+        law_ids: Vec<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<BulkResult, AppError> {
+        run_favorites_bulk(
+            &state,
+            "DELETE FROM favorites WHERE law_id = ?1",
+            law_ids,
+            |stmt, law_id| stmt.execute([law_id]),
+        )
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn remove_favorite(law_id: String, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        retry_on_busy(|| {
+            conn.execute(
+                "DELETE FROM favorites WHERE law_id = ?1",
+                rusqlite::params![law_id],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+fn folder_exists(conn: &Connection, folder_id: i32) -> Result<bool, AppError> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM favorite_folders WHERE id = ?1)",
+        rusqlite::params![folder_id],
+        |row| row.get(0),
+    )
+    .map_err(AppError::user_db_error)
+}
+
+// 文件夹自身及全部下级文件夹的 id
+fn folder_subtree(conn: &Connection, folder_id: i32) -> Result<Vec<i32>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "WITH RECURSIVE tree(id) AS (
+                SELECT ?1
+                UNION
+                SELECT f.id FROM favorite_folders f JOIN tree t ON f.parent_id = t.id
+            )
+            SELECT id FROM tree",
+        )
+        .map_err(AppError::user_db_error)?;
+    let ids = stmt
+        .query_map(rusqlite::params![folder_id], |row| row.get(0))
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(AppError::user_db_error)?;
+    Ok(ids)
+}
+
+// 把 folder_id 挂到 new_parent 下是否会成环（new_parent 是它自己或它的下级）
+fn folder_would_cycle(
+    conn: &Connection,
+    folder_id: i32,
+    new_parent: Option<i32>,
+) -> Result<bool, AppError> {
+    match new_parent {
+        Some(parent) => Ok(folder_subtree(conn, folder_id)?.contains(&parent)),
+        None => Ok(false),
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn create_folder(
+        name: String,
+        parent_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        if let Some(parent) = parent_id {
+            if !folder_exists(&conn, parent)? {
+                return Err(AppError::not_found("上级文件夹不存在"));
+            }
+        }
+        conn.execute(
+            "INSERT INTO favorite_folders (name, parent_id) VALUES (?1, ?2)",
+            rusqlite::params![name, parent_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// 调整文件夹层级；new_parent_id 为空时移到顶层
+metered_command! {
+    #[tauri::command]
+    fn move_folder(
+        folder_id: i32,
+        new_parent_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        if !folder_exists(&conn, folder_id)? {
+            return Err(AppError::not_found("文件夹不存在"));
+        }
+        if let Some(parent) = new_parent_id {
+            if !folder_exists(&conn, parent)? {
+                return Err(AppError::not_found("上级文件夹不存在"));
+            }
+        }
+        if folder_would_cycle(&conn, folder_id, new_parent_id)? {
+            return Err(AppError::validation("不能移动到自身或其子文件夹下"));
+        }
+        conn.execute(
+            "UPDATE favorite_folders SET parent_id = ?2 WHERE id = ?1",
+            rusqlite::params![folder_id, new_parent_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_folders(state: tauri::State<'_, AppState>) -> Result<Vec<UserFolder>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, created_at, parent_id FROM favorite_folders ORDER BY created_at ASC",
+            )
+            .map_err(AppError::user_db_error)?;
+
+        let folders = stmt
+            .query_map([], |row| {
+                Ok(UserFolder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    created_at: row.get(2)?,
+                    parent_id: row.get(3)?,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(folders)
+    }
+}
+
+// 每条已有收藏对所在文件夹的贡献：同一法规 0.6、标签有交集 0.25、同一分类 0.15
+const SUGGEST_LAW_WEIGHT: f32 = 0.6;
+const SUGGEST_TAG_WEIGHT: f32 = 0.25;
+const SUGGEST_CATEGORY_WEIGHT: f32 = 0.15;
+// 最高分低于此值时视为没有合适的文件夹，可改由大模型分类
+const SUGGEST_MIN_CONFIDENCE: f32 = 0.35;
+const SUGGEST_LIMIT: usize = 3;
+
+#[derive(Default)]
+struct FolderTally {
+    total: usize,
+    weight: f32,
+    same_law: usize,
+    same_tags: usize,
+    same_category: usize,
+}
+
+// 按文件夹中已有收藏与该条文的相似程度打分，只读 user_data.db。
+// 置信度兼顾占比（文件夹里多大比例是同类收藏）与绝对数量（同类收藏越多越可信），
+// 已包含该条文的文件夹不再推荐
+fn score_folder_suggestions(
+    conn: &Connection,
+    chunk: &LawChunk,
+    tags: &[String],
+) -> Result<Vec<FolderSuggestion>, AppError> {
+    let mut tallies: HashMap<i32, FolderTally> = HashMap::new();
+    let mut exclude: HashSet<i32> = HashSet::new();
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT l.folder_id, f.law_id, f.law_name, f.category, f.tags
+                 FROM favorite_folder_links l JOIN favorites f ON f.law_id = l.law_id",
+            )
+            .map_err(AppError::user_db_error)?;
+        let mut rows = stmt.query([]).map_err(AppError::user_db_error)?;
+        while let Some(row) = rows.next().map_err(AppError::user_db_error)? {
+            let folder_id: i32 = row.get(0).map_err(AppError::user_db_error)?;
+            let law_id: String = row.get(1).map_err(AppError::user_db_error)?;
+            if law_id == chunk.id {
+                exclude.insert(folder_id);
+                continue;
+            }
+            let law_name: Option<String> = row.get(2).map_err(AppError::user_db_error)?;
+            let category: Option<String> = row.get(3).map_err(AppError::user_db_error)?;
+            let fav_tags = parse_tags(row.get(4).map_err(AppError::user_db_error)?);
+
+            let tally = tallies.entry(folder_id).or_default();
+            tally.total += 1;
+            if law_name.as_deref() == Some(chunk.law_name.as_str()) {
+                tally.same_law += 1;
+                tally.weight += SUGGEST_LAW_WEIGHT;
+            }
+            if !tags.is_empty() && fav_tags.iter().any(|t| tags.contains(t)) {
+                tally.same_tags += 1;
+                tally.weight += SUGGEST_TAG_WEIGHT;
+            }
+            if !chunk.category.is_empty() && category.as_deref() == Some(chunk.category.as_str()) {
+                tally.same_category += 1;
+                tally.weight += SUGGEST_CATEGORY_WEIGHT;
+            }
+        }
+    }
+
+    let names: HashMap<i32, String> = {
+        let mut stmt = conn
+            .prepare("SELECT id, name FROM favorite_folders")
+            .map_err(AppError::user_db_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(AppError::user_db_error)?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut suggestions: Vec<FolderSuggestion> = tallies
+        .into_iter()
+        .filter(|(id, tally)| tally.weight > 0.0 && !exclude.contains(id))
+        .filter_map(|(id, tally)| {
+            let name = names.get(&id)?.clone();
+            let share = tally.weight / tally.total as f32;
+            let support = 1.0 - 0.5f32.powf(tally.weight / SUGGEST_LAW_WEIGHT);
+            let mut reasons = Vec::new();
+            if tally.same_law > 0 {
+                reasons.push(format!("{} 条同一法规", tally.same_law));
+            }
+            if tally.same_tags > 0 {
+                reasons.push(format!("{} 条标签相同", tally.same_tags));
+            }
+            if tally.same_category > 0 {
+                reasons.push(format!("{} 条同类法规", tally.same_category));
+            }
+            Some(FolderSuggestion {
+                folder_id: id,
+                folder_name: name,
+                confidence: (0.4 * share + 0.6 * support).min(1.0),
+                reason: format!("已有{}", reasons.join("、")),
+                source: "rules",
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.confidence
+            .partial_cmp(&a.confidence)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.folder_id.cmp(&b.folder_id))
+    });
+    suggestions.truncate(SUGGEST_LIMIT);
+    Ok(suggestions)
+}
+
+// 规则打分没有合适结果时请大模型从文件夹名中选一个；失败或回答无效时返回 None
+async fn suggest_folder_with_llm(
+    state: &AppState,
+    chunk: &LawChunk,
+    exclude: &[i32],
+) -> Option<FolderSuggestion> {
+    let settings = snapshot_settings(state);
+    let folders: Vec<(i32, String)> = {
+        let conn = user_db(state).ok()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name FROM favorite_folders
+                 WHERE id NOT IN (SELECT folder_id FROM favorite_folder_links WHERE law_id = ?1)
+                 ORDER BY id",
+            )
+            .ok()?;
+        let rows = stmt
+            .query_map([&chunk.id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .ok()?;
+        rows.filter_map(Result::ok)
+            .filter(|(id, _)| !exclude.contains(id))
+            .collect()
+    };
+    if folders.is_empty() {
+        return None;
+    }
+    let folder_list = folders
+        .iter()
+        .map(|(id, name)| format!("{}. {}", id, name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let excerpt: String = chunk.content.chars().take(300).collect();
+    let prompt = FOLDER_SUGGEST_PROMPT
+        .replace("{law_name}", &chunk.law_name)
+        .replace("{article_number}", &chunk.article_number)
+        .replace("{category}", &chunk.category)
+        .replace("{content}", &excerpt)
+        .replace("{folders}", &folder_list);
+    let answer = call_llm(
+        &http_client(state),
+        &settings.chat_model,
+        &prompt,
+        &settings.chat_base_url,
+        &settings.chat_api_key,
+        &settings.chat_api_format,
+        settings.chat_timeout_secs,
+    )
+    .await;
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(e) => {
+            warn!("Folder suggestion by LLM failed: {}", e);
+            return None;
+        }
+    };
+    let id: i32 = answer
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|s| !s.is_empty())?
+        .parse()
+        .ok()?;
+    let (folder_id, folder_name) = folders.into_iter().find(|(fid, _)| *fid == id)?;
+    Some(FolderSuggestion {
+        folder_id,
+        folder_name,
+        confidence: SUGGEST_MIN_CONFIDENCE,
+        reason: "AI 按文件夹名称推荐".to_string(),
+        source: "ai",
+    })
+}
+
+// 收藏时推荐文件夹，最多 3 个，按置信度从高到低；只返回建议，不会自动加入文件夹。
+// tags 为空时使用该条文已有收藏的标签
+metered_command! {
+    #[tauri::command]
+    async fn suggest_folder(
+        chunk: LawChunk,
+        tags: Option<Vec<String>>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<FolderSuggestion>, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let mut suggestions = {
+            let conn = user_db(&state)?;
+            let tags = match tags {
+                Some(tags) => normalize_tags(tags),
+                None => parse_tags(
+                    conn.query_row(
+                        "SELECT tags FROM favorites WHERE law_id = ?1",
+                        [&chunk.id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(AppError::user_db_error)?
+                    .flatten(),
+                ),
+            };
+            score_folder_suggestions(&conn, &chunk, &tags)?
+        };
+
+        let best = suggestions.first().map_or(0.0, |s| s.confidence);
+        if best < SUGGEST_MIN_CONFIDENCE && snapshot_settings(&state).enable_ai_chat {
+            let exclude: Vec<i32> = suggestions.iter().map(|s| s.folder_id).collect();
+            if let Some(ai) = suggest_folder_with_llm(&state, &chunk, &exclude).await {
+                suggestions.insert(0, ai);
+                suggestions.truncate(SUGGEST_LIMIT);
+            }
+        }
+        Ok(suggestions)
+    }
+}
+
+// mode: "move_to_uncategorized" (默认，收藏移到未分类)、"move_to_folder" (移到 target_folder_id)
+// 或 "delete_contents" (连同下级文件夹与其中收藏一起删除)
+// 前两种方式下，下级文件夹改挂到被删文件夹的上级
+metered_command! {
+    #[tauri::command]
+    fn delete_folder(
+        folder_id: i32,
+        mode: Option<String>,
+        target_folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        use rusqlite::OptionalExtension;
+
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let parent_id: Option<i32> = tx
+            .query_row(
+                "SELECT parent_id FROM favorite_folders WHERE id = ?1",
+                rusqlite::params![folder_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(AppError::user_db_error)?
+            .ok_or_else(|| AppError::not_found("文件夹不存在"))?;
+
+        let favorites_target = match mode.as_deref().unwrap_or("move_to_uncategorized") {
+            "move_to_uncategorized" => None,
+            "move_to_folder" => {
+                let target = target_folder_id
+                    .filter(|id| *id != folder_id)
+                    .ok_or_else(|| AppError::validation("请选择另一个目标文件夹"))?;
+                if !folder_exists(&tx, target)? {
+                    return Err(AppError::not_found("目标文件夹不存在"));
+                }
+                Some(target)
+            }
+            "delete_contents" => {
+                // 同时还在子树以外文件夹中的收藏只移出，不删除
+                let subtree = folder_subtree(&tx, folder_id)?;
+                let ids = subtree
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                tx.execute(
+                    &format!(
+                        "DELETE FROM favorites
+                         WHERE law_id IN (SELECT law_id FROM favorite_folder_links WHERE folder_id IN ({0}))
+                           AND law_id NOT IN (SELECT law_id FROM favorite_folder_links WHERE folder_id NOT IN ({0}))",
+                        ids
+                    ),
+                    [],
+                )
+                .map_err(AppError::user_db_error)?;
+                for id in subtree {
+                    tx.execute(
+                        "DELETE FROM favorite_folder_links WHERE folder_id = ?1",
+                        rusqlite::params![id],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                    tx.execute(
+                        "DELETE FROM favorite_laws WHERE folder_id = ?1",
+                        rusqlite::params![id],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                    tx.execute(
+                        "DELETE FROM favorite_folders WHERE id = ?1",
+                        rusqlite::params![id],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                }
+                return tx.commit().map_err(AppError::user_db_error);
+            }
+            other => {
+                return Err(AppError::validation(format!(
+                    "未知的文件夹删除方式: {}",
+                    other
+                )))
+            }
+        };
+
+        // 移到目标文件夹时保持原有顺序，排在目标文件夹已有收藏之后；
+        // 收藏同时在其他文件夹中时，移到未分类只是少了这一个文件夹
+        if let Some(target) = favorites_target {
+            tx.execute(
+                "INSERT OR IGNORE INTO favorite_folder_links (law_id, folder_id, sort_order, created_at)
+                 SELECT law_id, ?2,
+                     (SELECT COALESCE(MAX(sort_order), 0) FROM favorite_folder_links WHERE folder_id = ?2)
+                         + sort_order,
+                     created_at
+                 FROM favorite_folder_links WHERE folder_id = ?1",
+                rusqlite::params![folder_id, target],
+            )
+            .map_err(AppError::user_db_error)?;
+        }
+        tx.execute(
+            "DELETE FROM favorite_folder_links WHERE folder_id = ?1",
+            rusqlite::params![folder_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.execute(
+            "UPDATE favorite_laws SET folder_id = ?2 WHERE folder_id = ?1",
+            rusqlite::params![folder_id, favorites_target],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.execute(
+            "UPDATE favorite_folders SET parent_id = ?2 WHERE parent_id = ?1",
+            rusqlite::params![folder_id, parent_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.execute(
+            "DELETE FROM favorite_folders WHERE id = ?1",
+            rusqlite::params![folder_id],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.commit().map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+const FAVORITE_COLUMNS: &str = "id, law_id, law_name, article_number, content, created_at, tags, \
+     (SELECT GROUP_CONCAT(l.folder_id) FROM favorite_folder_links l WHERE l.law_id = favorites.law_id), \
+     from_export, note, stale, original_content";
+
+fn favorite_from_row(row: &rusqlite::Row) -> rusqlite::Result<UserFavorite> {
+    let mut folder_ids: Vec<i32> = row
+        .get::<_, Option<String>>(7)?
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.parse().ok())
+        .collect();
+    folder_ids.sort_unstable();
+    Ok(UserFavorite {
+        id: row.get(0)?,
+        law_id: row.get(1)?,
+        law_name: row.get(2)?,
+        article_number: row.get(3)?,
+        content: row.get(4)?,
+        created_at: row.get(5)?,
+        tags: parse_tags(row.get(6)?),
+        folder_id: folder_ids.first().copied(),
+        folder_ids,
+        from_export: row.get(8)?,
+        note: row.get(9)?,
+        stale: row.get(10)?,
+        original_content: row.get(11)?,
+        content_truncated: false,
+    })
+}
+
+// get_favorites 的 folder_id 取此值时只返回未分类的收藏
+const UNCATEGORIZED_FOLDER_ID: i32 = -1;
+// 未分类 = 不在任何文件夹中
+const UNCATEGORIZED_FILTER: &str = "law_id NOT IN (SELECT law_id FROM favorite_folder_links)";
+// 新收藏或回到未分类的收藏排在未分类末尾
+const UNCATEGORIZED_NEXT_SORT_ORDER: &str =
+    "(SELECT COALESCE(MAX(sort_order), 0) + 1 FROM favorites
+      WHERE law_id NOT IN (SELECT law_id FROM favorite_folder_links))";
+
+// 中文数字（含阿拉伯数字）转数值，如 "一百零五" -> 105；无法解析时返回 None
+fn parse_chinese_numeral(text: &str) -> Option<u64> {
+    if text.is_empty() {
+        return None;
+    }
+    if let Ok(n) = text.parse::<u64>() {
+        return Some(n);
+    }
+    let (mut total, mut section, mut digit) = (0u64, 0u64, 0u64);
+    for c in text.chars() {
+        match c {
+            '零' | '〇' => digit = 0,
+            '一' => digit = 1,
+            '二' | '两' => digit = 2,
+            '三' => digit = 3,
+            '四' => digit = 4,
+            '五' => digit = 5,
+            '六' => digit = 6,
+            '七' => digit = 7,
+            '八' => digit = 8,
+            '九' => digit = 9,
+            '十' | '百' | '千' => {
+                let unit = match c {
+                    '十' => 10,
+                    '百' => 100,
+                    _ => 1000,
+                };
+                // “十二”省略了前面的“一”
+                section += digit.max(1) * unit;
+                digit = 0;
+            }
+            '万' => {
+                total += (section + digit) * 10_000;
+                section = 0;
+                digit = 0;
+            }
+            _ => return None,
+        }
+    }
+    Some(total + section + digit)
+}
+
+// 条号排序键：“第十条之一” -> (10, 1)；无法解析的排在最后
+fn article_sort_key(article_number: &str) -> (u64, u64) {
+    let body = article_number.trim().trim_start_matches('第');
+    let (main, sub) = match body.split_once('条') {
+        Some((main, rest)) => (main, rest.trim_start_matches('之')),
+        None => (body, ""),
+    };
+    (
+        parse_chinese_numeral(main).unwrap_or(u64::MAX),
+        parse_chinese_numeral(sub).unwrap_or(0),
+    )
+}
+
+// folder_id 为空时返回全部，为 UNCATEGORIZED_FOLDER_ID 时只返回未分类
+// sort_by: "created_at" (默认，新的在前) / "law_name" / "article_number" (同一法规内按条号数值排序)
+//          / "manual" (按 reorder_favorite 调整的顺序)
+// limit 为空时不分页
+metered_command! {
+    #[tauri::command]
+    fn get_favorites(
+        tag: Option<String>,
+        folder_id: Option<i32>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        sort_by: Option<String>,
+        content_mode: Option<ContentMode>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<FavoritesPage, AppError> {
+        let mut page = load_favorites_page(&state, tag, folder_id, offset, limit, sort_by)?;
+        // 收藏列表默认返回完整内容，只有显式要求 preview 时才截断
+        if content_mode == Some(ContentMode::Preview) {
+            let preview_chars = preview_chars_for(content_mode, &snapshot_settings(&state));
+            for favorite in &mut page.items {
+                favorite.content_truncated = shape_content(&mut favorite.content, preview_chars);
+            }
+        }
+        Ok(page)
+    }
+}
+
+// 收藏列表只带预览时取完整内容；以收藏中保存的文本为准，可能与当前法规库不同
+metered_command! {
+    #[tauri::command]
+    fn get_favorite_content(
+        law_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let conn = user_db(&state)?;
+        conn.query_row(
+            "SELECT content FROM favorites WHERE law_id = ?1",
+            [&law_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(AppError::user_db_error)?
+        .ok_or_else(|| AppError::not_found(format!("未找到收藏: {}", law_id)))
+    }
+}
+
+fn load_favorites_page(
+    state: &AppState,
+    tag: Option<String>,
+    folder_id: Option<i32>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort_by: Option<String>,
+) -> Result<FavoritesPage, AppError> {
+    use rusqlite::types::Value;
+
+    let conn = user_db(state)?;
+    let mut params: Vec<Value> = Vec::new();
+    let mut conditions: Vec<String> = Vec::new();
+    // 文件夹内的手动顺序记录在关联上，同一收藏在不同文件夹中的位置互不影响
+    let mut manual_key = "sort_order".to_string();
+    match folder_id {
+        None => {}
+        Some(UNCATEGORIZED_FOLDER_ID) => conditions.push(UNCATEGORIZED_FILTER.to_string()),
+        Some(id) => {
+            params.push(Value::from(id));
+            let n = params.len();
+            conditions.push(format!(
+                "law_id IN (SELECT law_id FROM favorite_folder_links WHERE folder_id = ?{})",
+                n
+            ));
+            manual_key = format!(
+                "(SELECT l.sort_order FROM favorite_folder_links l
+                  WHERE l.law_id = favorites.law_id AND l.folder_id = ?{})",
+                n
+            );
+        }
+    }
+    // 标签以 JSON 数组保存（旧的逗号分隔格式在迁移时已转换）
+    if let Some(tag) = tag.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
+        params.push(Value::from(tag));
+        conditions.push(format!(
+            "EXISTS (SELECT 1 FROM json_each(CASE WHEN json_valid(tags) THEN tags ELSE '[]' END)
+                     WHERE value = ?{})",
+            params.len()
+        ));
+    }
+    let filter = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let total: i64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM favorites {}", filter),
+            rusqlite::params_from_iter(&params),
+            |row| row.get(0),
+        )
+        .map_err(AppError::user_db_error)?;
+    let total = total as usize;
+
+    // 条号需要解析中文数字，无法在 SQL 中排序，只有这种排序在内存中分页
+    let by_article = sort_by.as_deref() == Some("article_number");
+    let order = match sort_by.as_deref() {
+        Some("law_name") | Some("article_number") => {
+            "law_name ASC, created_at DESC, id DESC".to_string()
+        }
+        Some("manual") => format!("{0} IS NULL, {0} ASC, created_at ASC, id ASC", manual_key),
+        _ => "created_at DESC, id DESC".to_string(),
+    };
+    let mut sql = format!(
+        "SELECT {} FROM favorites {} ORDER BY {}",
+        FAVORITE_COLUMNS, filter, order
+    );
+    if !by_article {
+        // LIMIT -1 表示不限制
+        let limit = limit.map_or(-1, |l| i64::try_from(l).unwrap_or(i64::MAX));
+        let offset = i64::try_from(offset.unwrap_or(0)).unwrap_or(i64::MAX);
+        sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+    }
+    let mut stmt = conn.prepare(&sql).map_err(AppError::user_db_error)?;
+    let mut favorites = stmt
+        .query_map(rusqlite::params_from_iter(&params), favorite_from_row)
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<Vec<UserFavorite>, _>>()
+        .map_err(AppError::user_db_error)?;
+    if !by_article {
+        return Ok(FavoritesPage {
+            items: favorites,
+            total,
+        });
+    }
+
+    // 稳定排序，条号相同的保持收藏时间顺序
+    favorites.sort_by(|a, b| {
+        a.law_name.cmp(&b.law_name).then_with(|| {
+            article_sort_key(&a.article_number).cmp(&article_sort_key(&b.article_number))
+        })
+    });
+    let offset = offset.unwrap_or(0).min(favorites.len());
+    let end = limit.map_or(favorites.len(), |l| {
+        offset.saturating_add(l).min(favorites.len())
+    });
+    let items = favorites.drain(offset..end).collect();
+    Ok(FavoritesPage { items, total })
+}
+
+// 相邻位置差小于该值时重新编号
+const SORT_ORDER_MIN_GAP: f64 = 1e-6;
+
+// 手动排序所在的表与筛选条件（?1 = folder_id）：
+// 文件夹内按 favorite_folder_links.sort_order，未分类按 favorites.sort_order
+fn sort_scope(folder_id: Option<i32>) -> (&'static str, &'static str) {
+    match folder_id {
+        Some(_) => ("favorite_folder_links", "folder_id = ?1"),
+        None => (
+            "favorites",
+            "?1 IS NULL AND law_id NOT IN (SELECT law_id FROM favorite_folder_links)",
+        ),
+    }
+}
+
+// 按当前手动顺序把文件夹内的位置重排为 1, 2, 3...，未排过序的排在最后
+fn renormalize_sort_order(conn: &Connection, folder_id: Option<i32>) -> Result<(), AppError> {
+    let (table, filter) = sort_scope(folder_id);
+    let ids: Vec<String> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT law_id FROM {} WHERE {}
+                 ORDER BY sort_order IS NULL, sort_order ASC, created_at ASC",
+                table, filter
+            ))
+            .map_err(AppError::user_db_error)?;
+        let ids = stmt
+            .query_map([folder_id], |row| row.get(0))
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        ids
+    };
+    let sql = format!(
+        "UPDATE {} SET sort_order = ?3 WHERE {} AND law_id = ?2",
+        table, filter
+    );
+    for (index, id) in ids.iter().enumerate() {
+        conn.execute(&sql, rusqlite::params![folder_id, id, (index + 1) as f64])
+            .map_err(AppError::user_db_error)?;
+    }
+    Ok(())
+}
+
+// 计算插到 after 之后（after 为空时排到最前）的位置；间隔太小时返回 None
+fn sort_position_after(
+    conn: &Connection,
+    folder_id: Option<i32>,
+    law_id: &str,
+    after_law_id: Option<&str>,
+) -> Result<Option<f64>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let (table, filter) = sort_scope(folder_id);
+    let after: Option<f64> = match after_law_id {
+        Some(after_id) => Some(
+            conn.query_row(
+                &format!(
+                    "SELECT sort_order FROM {} WHERE {} AND law_id = ?2",
+                    table, filter
+                ),
+                rusqlite::params![folder_id, after_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(AppError::user_db_error)?
+            .ok_or_else(|| AppError::validation("目标条文不在同一文件夹中"))?,
+        ),
+        None => None,
+    };
+    let next: Option<f64> = conn
+        .query_row(
+            &format!(
+                "SELECT MIN(sort_order) FROM {} WHERE {} AND law_id != ?2
+                 AND (?3 IS NULL OR sort_order > ?3)",
+                table, filter
+            ),
+            rusqlite::params![folder_id, law_id, after],
+            |row| row.get(0),
+        )
+        .map_err(AppError::user_db_error)?;
+    Ok(match (after, next) {
+        (Some(a), Some(n)) if n - a < SORT_ORDER_MIN_GAP => None,
+        (Some(a), Some(n)) => Some((a + n) / 2.0),
+        (Some(a), None) => Some(a + 1.0),
+        (None, Some(n)) => Some(n - 1.0),
+        (None, None) => Some(1.0),
+    })
+}
+
+// 手动调整收藏在文件夹内的顺序：放到 after_law_id 之后，为空时放到最前
+// 收藏在多个文件夹中时需指定 folder_id；只在一个文件夹或未分类时可省略
+metered_command! {
+    #[tauri::command]
+    fn reorder_favorite(
+        law_id: String,
+        after_law_id: Option<String>,
+        folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        if after_law_id.as_deref() == Some(law_id.as_str()) {
+            return Err(AppError::validation("不能排在自身之后"));
+        }
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let favorited: bool = tx
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM favorites WHERE law_id = ?1)",
+                [&law_id],
+                |row| row.get(0),
+            )
+            .map_err(AppError::user_db_error)?;
+        if !favorited {
+            return Err(AppError::not_found("该条文尚未收藏"));
+        }
+        let linked: Vec<i32> = {
+            let mut stmt = tx
+                .prepare("SELECT folder_id FROM favorite_folder_links WHERE law_id = ?1")
+                .map_err(AppError::user_db_error)?;
+            let ids = stmt
+                .query_map([&law_id], |row| row.get(0))
+                .map_err(AppError::user_db_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(AppError::user_db_error)?;
+            ids
+        };
+        let folder_id = match (
+            folder_id.filter(|id| *id != UNCATEGORIZED_FOLDER_ID),
+            linked.as_slice(),
+        ) {
+            (Some(id), linked) if linked.contains(&id) => Some(id),
+            (Some(_), _) => return Err(AppError::validation("该条文不在此文件夹中")),
+            (None, []) => None,
+            (None, [only]) => Some(*only),
+            (None, _) => return Err(AppError::validation("该条文在多个文件夹中，请指定文件夹")),
+        };
+
+        let (table, filter) = sort_scope(folder_id);
+        let has_unsorted: bool = tx
+            .query_row(
+                &format!(
+                    "SELECT EXISTS(SELECT 1 FROM {} WHERE {} AND sort_order IS NULL)",
+                    table, filter
+                ),
+                [folder_id],
+                |row| row.get(0),
+            )
+            .map_err(AppError::user_db_error)?;
+        if has_unsorted {
+            renormalize_sort_order(&tx, folder_id)?;
+        }
+
+        let position = match sort_position_after(&tx, folder_id, &law_id, after_law_id.as_deref())?
+        {
+            Some(position) => position,
+            None => {
+                renormalize_sort_order(&tx, folder_id)?;
+                sort_position_after(&tx, folder_id, &law_id, after_law_id.as_deref())?
+                    .ok_or_else(|| AppError::user_db("无法计算排序位置"))?
+            }
+        };
+        tx.execute(
+            &format!(
+                "UPDATE {} SET sort_order = ?3 WHERE {} AND law_id = ?2",
+                table, filter
+            ),
+            rusqlite::params![folder_id, law_id, position],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO favorite_manual_order (folder_id) VALUES (?1)",
+            [folder_id.unwrap_or(UNCATEGORIZED_FOLDER_ID)],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.commit().map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// 按 law_id 查当前条文；id 变了（数据更新后重新编号）时按法规名 + 条号查找
+fn current_chunk_content(
+    conn: &Connection,
+    law_id: &str,
+    law_name: &str,
+    article_number: &str,
+) -> Result<Option<String>, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let by_id = conn
+        .query_row(
+            "SELECT content FROM chunks WHERE id = ?1",
+            [law_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(AppError::content_db_error)?;
+    if by_id.is_some() {
+        return Ok(by_id);
+    }
+    conn.query_row(
+        "SELECT content FROM chunks WHERE law_name = ?1 AND article_number = ?2 LIMIT 1",
+        [law_name, article_number],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(AppError::content_db_error)
+}
+
+// 对比收藏内容与当前法规库，返回已修改或已删除的收藏，并记录到 stale 标记
+metered_command! {
+    #[tauri::command]
+    fn check_favorites_freshness(
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<FavoriteFreshness>, AppError> {
+        let content_conn = content_db(&state)?;
+        let mut conn = user_db(&state)?;
+        let favorites: Vec<(String, String, String, String)> = {
+            let mut stmt = conn
+                .prepare("SELECT law_id, law_name, article_number, content FROM favorites")
+                .map_err(AppError::user_db_error)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })
+                .map_err(AppError::user_db_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(AppError::user_db_error)?;
+            rows
+        };
+
+        let mut report = Vec::new();
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        for (law_id, law_name, article_number, content) in favorites {
+            let current =
+                current_chunk_content(&content_conn, &law_id, &law_name, &article_number)?;
+            let status = match &current {
+                None => Some("missing"),
+                Some(current) if current.trim() != content.trim() => Some("changed"),
+                Some(_) => None,
+            };
+            tx.execute(
+                "UPDATE favorites SET stale = ?2 WHERE law_id = ?1",
+                rusqlite::params![law_id, status.is_some()],
+            )
+            .map_err(AppError::user_db_error)?;
+            if let Some(status) = status {
+                report.push(FavoriteFreshness {
+                    law_id,
+                    law_name,
+                    article_number,
+                    status: status.to_string(),
+                    current_content: current,
+                });
+            }
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+        Ok(report)
+    }
+}
+
+// 用当前条文更新收藏内容，首次刷新时把原文存入 original_content；返回是否有更新
+fn refresh_favorite_content(
+    conn: &Connection,
+    content_conn: &Connection,
+    law_id: &str,
+) -> Result<bool, AppError> {
+    let (law_name, article_number): (String, String) = conn
+        .query_row(
+            "SELECT law_name, article_number FROM favorites WHERE law_id = ?1",
+            [law_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|_| AppError::not_found("该条文尚未收藏"))?;
+    let current = match current_chunk_content(content_conn, law_id, &law_name, &article_number)? {
+        Some(current) => current,
+        None => return Ok(false),
+    };
+    let changed = conn
+        .execute(
+            "UPDATE favorites
+             SET original_content = COALESCE(original_content, content), content = ?2, stale = 0
+             WHERE law_id = ?1 AND content != ?2",
+            rusqlite::params![law_id, current],
+        )
+        .map_err(AppError::user_db_error)?;
+    Ok(changed > 0)
+}
+
+metered_command! {
+    #[tauri::command]
+    fn refresh_favorite(
+        law_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<bool, AppError> {
+        let content_conn = content_db(&state)?;
+        let conn = user_db(&state)?;
+        refresh_favorite_content(&conn, &content_conn, &law_id)
+    }
+}
+
+// 刷新全部收藏，返回内容有更新的条数；法规库中已删除的条文保持原样
+metered_command! {
+    #[tauri::command]
+    fn refresh_all_favorites(state: tauri::State<'_, AppState>) -> Result<usize, AppError> {
+        let content_conn = content_db(&state)?;
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let law_ids: Vec<String> = {
+            let mut stmt = tx
+                .prepare("SELECT law_id FROM favorites")
+                .map_err(AppError::user_db_error)?;
+            let ids = stmt
+                .query_map([], |row| row.get(0))
+                .map_err(AppError::user_db_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(AppError::user_db_error)?;
+            ids
+        };
+        let mut updated = 0;
+        for law_id in law_ids {
+            if refresh_favorite_content(&tx, &content_conn, &law_id)? {
+                updated += 1;
+            }
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+        info!("Refreshed {} favorites", updated);
+        Ok(updated)
+    }
+}
+
+// 设置收藏笔记；传空字符串清除
+metered_command! {
+    #[tauri::command]
+    fn set_favorite_note(
+        law_id: String,
+        note: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let note = Some(note).filter(|n| !n.trim().is_empty());
+        let updated = conn
+            .execute(
+                "UPDATE favorites SET note = ?2 WHERE law_id = ?1",
+                rusqlite::params![law_id, note],
+            )
+            .map_err(AppError::user_db_error)?;
+        if updated == 0 {
+            return Err(AppError::not_found("该条文尚未收藏"));
+        }
+        Ok(())
+    }
+}
+
+// 在笔记、条文内容与法规名称中搜索收藏
+metered_command! {
+    #[tauri::command]
+    fn search_favorites(
+        query: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<UserFavorite>, AppError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(load_favorites_page(&state, None, None, None, None, None)?.items);
+        }
+        let conn = user_db(&state)?;
+        let sql = format!(
+            "SELECT {} FROM favorites
+             WHERE note LIKE ?1 OR content LIKE ?1 OR law_name LIKE ?1
+             ORDER BY created_at DESC",
+            FAVORITE_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql).map_err(AppError::user_db_error)?;
+        let favorites = stmt
+            .query_map([format!("%{}%", query)], favorite_from_row)
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(favorites)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn check_is_favorite(
+        law_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<bool, AppError> {
+        let conn = user_db(&state)?;
+        let count: i32 = conn
+            .query_row(
+                "SELECT count(*) FROM favorites WHERE law_id = ?1",
+                rusqlite::params![law_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+        Ok(count > 0)
+    }
+}
+
+fn favorite_law_names(conn: &Connection) -> Result<HashSet<String>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT law_name FROM favorite_laws")
+        .map_err(AppError::user_db_error)?;
+    let names = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<HashSet<String>, _>>()
+        .map_err(AppError::user_db_error)?;
+    Ok(names)
+}
+
+// 收藏整部法规；已收藏时只更新文件夹与笔记
+metered_command! {
+    #[tauri::command]
+    fn add_law_favorite(
+        law_name: String,
+        category: Option<String>,
+        region: Option<String>,
+        folder_id: Option<i32>,
+        note: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let law_name = law_name.trim();
+        if law_name.is_empty() {
+            return Err(AppError::validation("法规名称不能为空"));
+        }
+        let conn = user_db(&state)?;
+        if let Some(id) = folder_id {
+            if !folder_exists(&conn, id)? {
+                return Err(AppError::not_found("文件夹不存在"));
+            }
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+        conn.execute(
+            "INSERT INTO favorite_laws (law_name, category, region, folder_id, note)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(law_name) DO UPDATE SET
+                folder_id = excluded.folder_id,
+                note = COALESCE(excluded.note, favorite_laws.note)",
+            rusqlite::params![
+                law_name,
+                category.unwrap_or_default(),
+                region.unwrap_or_default(),
+                folder_id,
+                note
+            ],
+        )
+        .map_err(AppError::user_db_error)?;
+        log_activity(&state, &[("favorite", Some(law_name), None)]);
+        Ok(())
+    }
+}
+
+// 取消整部法规收藏，不影响该法规下的条文收藏
+metered_command! {
+    #[tauri::command]
+    fn remove_law_favorite(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute(
+            "DELETE FROM favorite_laws WHERE law_name = ?1",
+            rusqlite::params![law_name],
+        )
+        .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
+
+// folder_id 为 UNCATEGORIZED_FOLDER_ID 时只返回未分类的法规收藏
+metered_command! {
+    #[tauri::command]
+    fn get_law_favorites(
+        folder_id: Option<i32>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawFavorite>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, law_name, category, region, created_at, folder_id, note FROM favorite_laws
+                 WHERE ?1 IS NULL OR (?1 = ?2 AND folder_id IS NULL) OR folder_id = ?1
+                 ORDER BY created_at DESC",
+            )
+            .map_err(AppError::user_db_error)?;
+        let laws = stmt
+            .query_map(
+                rusqlite::params![folder_id, UNCATEGORIZED_FOLDER_ID],
+                |row| {
+                    Ok(LawFavorite {
+                        id: row.get(0)?,
+                        law_name: row.get(1)?,
+                        category: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                        region: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                        created_at: row.get(4)?,
+                        folder_id: row.get(5)?,
+                        note: row.get(6)?,
+                    })
+                },
+            )
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        Ok(laws)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn check_is_law_favorite(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<bool, AppError> {
+        let conn = user_db(&state)?;
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM favorite_laws WHERE law_name = ?1)",
+                rusqlite::params![law_name],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+        Ok(exists)
+    }
+}
+
+// 导出收藏；指定 folder_id 时只导出该文件夹
+metered_command! {
+    #[tauri::command]
+    fn export_favorites(
+        folder_id: Option<i32>,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        let conn = user_db(&state)?;
+        // 每条收藏所在的文件夹名称，按加入时间排序
+        let mut folder_names: HashMap<String, Vec<String>> = HashMap::new();
+        {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT l.law_id, d.name
+                     FROM favorite_folder_links l JOIN favorite_folders d ON d.id = l.folder_id
+                     ORDER BY l.created_at, d.id",
+                )
+                .map_err(AppError::user_db_error)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(AppError::user_db_error)?;
+            for row in rows {
+                let (law_id, name) = row.map_err(AppError::user_db_error)?;
+                folder_names.entry(law_id).or_default().push(name);
+            }
+        }
+        let mut stmt = conn
+            .prepare(
+                "SELECT law_id, law_name, article_number, content, tags, note FROM favorites
+                 WHERE ?1 IS NULL
+                    OR law_id IN (SELECT law_id FROM favorite_folder_links WHERE folder_id = ?1)
+                 ORDER BY created_at ASC",
+            )
+            .map_err(AppError::user_db_error)?;
+        let favorites: Vec<ExportedFavorite> = stmt
+            .query_map([folder_id], |row| {
+                let law_id: String = row.get(0)?;
+                let folders = folder_names.remove(&law_id).unwrap_or_default();
+                Ok(ExportedFavorite {
+                    law_name: row.get(1)?,
+                    article_number: row.get(2)?,
+                    content: row.get(3)?,
+                    tags: parse_tags(row.get(4)?),
+                    folder: folders.first().cloned(),
+                    folders,
+                    note: row.get(5)?,
+                    law_id,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT l.law_name, l.category, l.region, d.name, l.note
+                 FROM favorite_laws l LEFT JOIN favorite_folders d ON d.id = l.folder_id
+                 WHERE ?1 IS NULL OR l.folder_id = ?1
+                 ORDER BY l.created_at ASC",
+            )
+            .map_err(AppError::user_db_error)?;
+        let laws: Vec<ExportedLawFavorite> = stmt
+            .query_map([folder_id], |row| {
+                Ok(ExportedLawFavorite {
+                    law_name: row.get(0)?,
+                    category: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    region: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    folder: row.get(3)?,
+                    note: row.get(4)?,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+
+        let mut folders: Vec<String> = Vec::new();
+        let used_folders = favorites
+            .iter()
+            .flat_map(|f| f.folders.iter())
+            .chain(laws.iter().filter_map(|l| l.folder.as_ref()));
+        for name in used_folders {
+            if !folders.contains(name) {
+                folders.push(name.clone());
+            }
+        }
+        let export = FavoritesExport {
+            format: FAVORITES_EXPORT_FORMAT.to_string(),
+            version: FAVORITES_EXPORT_VERSION,
+            exported_at: chrono::Local::now().to_rfc3339(),
+            folders,
+            favorites,
+            laws,
+        };
+        let json = serde_json::to_string_pretty(&export)
+            .map_err(|e| AppError::validation("导出失败").with_detail(e))?;
+        fs::write(&path, json)
+            .map_err(|e| AppError::validation("无法写入导出文件").with_detail(e))?;
+        Ok(export.favorites.len() + export.laws.len())
+    }
+}
+
+struct FolderExportItem {
+    law_name: String,
+    article_number: String,
+    content: String,
+    note: Option<String>,
+    sort_order: Option<f64>,
+}
+
+// 文件夹手动调整过顺序时按手动顺序（未排到的在后），否则按法规名称、条号排列
+fn load_folder_export_items(
+    conn: &Connection,
+    folder_id: Option<i32>,
+) -> Result<Vec<FolderExportItem>, AppError> {
+    let manual: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM favorite_manual_order WHERE folder_id = ?1)",
+            [folder_id.unwrap_or(UNCATEGORIZED_FOLDER_ID)],
+            |row| row.get(0),
+        )
+        .map_err(AppError::user_db_error)?;
+    let sql = match folder_id {
+        Some(_) => {
+            "SELECT f.law_name, f.article_number, f.content, f.note, l.sort_order
+             FROM favorite_folder_links l JOIN favorites f ON f.law_id = l.law_id
+             WHERE l.folder_id = ?1"
+        }
+        None => {
+            "SELECT law_name, article_number, content, note, sort_order FROM favorites
+             WHERE ?1 IS NULL AND law_id NOT IN (SELECT law_id FROM favorite_folder_links)"
+        }
+    };
+    let mut stmt = conn.prepare(sql).map_err(AppError::user_db_error)?;
+    let mut items = stmt
+        .query_map([folder_id], |row| {
+            Ok(FolderExportItem {
+                law_name: row.get(0)?,
+                article_number: row.get(1)?,
+                content: row.get(2)?,
+                note: row
+                    .get::<_, Option<String>>(3)?
+                    .filter(|n| !n.trim().is_empty()),
+                sort_order: if manual { row.get(4)? } else { None },
+            })
+        })
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::user_db_error)?;
+    items.sort_by(|a, b| match (a.sort_order, b.sort_order) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.law_name.cmp(&b.law_name).then_with(|| {
+            article_sort_key(&a.article_number).cmp(&article_sort_key(&b.article_number))
+        }),
+    });
+    Ok(items)
+}
+
+fn render_folder_markdown(title: &str, items: &[FolderExportItem]) -> String {
+    let mut out = format!("# {}\n\n", title);
+    for item in items {
+        out.push_str(&format!(
+            "## 《{}》{}\n\n{}\n\n",
+            item.law_name,
+            item.article_number,
+            item.content.trim()
+        ));
+        if let Some(note) = &item.note {
+            out.push_str(&format!("*笔记：{}*\n\n", note.trim()));
+        }
+    }
+    out
+}
+
+fn write_folder_docx(path: &str, title: &str, items: &[FolderExportItem]) -> Result<(), AppError> {
+    use docx_rs::{Docx, Paragraph, Run, Style, StyleType};
+
+    let mut docx = Docx::new()
+        .add_style(
+            Style::new("Heading1", StyleType::Paragraph)
+                .name("Heading 1")
+                .size(36)
+                .bold(),
+        )
+        .add_style(
+            Style::new("Heading2", StyleType::Paragraph)
+                .name("Heading 2")
+                .size(28)
+                .bold(),
+        )
+        .add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(title))
+                .style("Heading1"),
+        );
+    for item in items {
+        docx = docx.add_paragraph(
+            Paragraph::new()
+                .add_run(
+                    Run::new().add_text(format!("《{}》{}", item.law_name, item.article_number)),
+                )
+                .style("Heading2"),
+        );
+        for line in item.content.lines().filter(|l| !l.trim().is_empty()) {
+            docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_text(line.trim())));
+        }
+        if let Some(note) = &item.note {
+            docx = docx.add_paragraph(
+                Paragraph::new().add_run(
+                    Run::new()
+                        .add_text(format!("笔记：{}", note.trim()))
+                        .italic(),
+                ),
+            );
+        }
+    }
+
+    let file = fs::File::create(path)
+        .map_err(|e| AppError::validation("无法写入导出文件").with_detail(e))?;
+    docx.build()
+        .pack(file)
+        .map_err(|e| AppError::validation("生成 Word 文档失败").with_detail(e))?;
+    Ok(())
+}
+
+// 把文件夹内的收藏导出为 Markdown 或 Word 文档，返回写入的路径
+metered_command! {
+    #[tauri::command]
+    fn export_folder(
+        folder_id: i32,
+        format: String,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let conn = user_db(&state)?;
+        let (folder, title) = if folder_id == UNCATEGORIZED_FOLDER_ID {
+            (None, "未分类".to_string())
+        } else {
+            let name: String = conn
+                .query_row(
+                    "SELECT name FROM favorite_folders WHERE id = ?1",
+                    [folder_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(AppError::user_db_error)?
+                .ok_or_else(|| AppError::not_found("文件夹不存在"))?;
+            (Some(folder_id), name)
+        };
+        let items = load_folder_export_items(&conn, folder)?;
+
+        match format.as_str() {
+            "markdown" => fs::write(&path, render_folder_markdown(&title, &items))
+                .map_err(|e| AppError::validation("无法写入导出文件").with_detail(e))?,
+            "docx" => write_folder_docx(&path, &title, &items)?,
+            other => return Err(AppError::validation(format!("未知的导出格式: {}", other))),
+        }
+        info!(
+            "Exported folder {} ({} favorites) as {}",
+            folder_id,
+            items.len(),
+            format
+        );
+        Ok(path)
+    }
+}
+
+// --- 法规全文 PDF 导出 ---
+const PDF_PAGE_WIDTH_MM: f32 = 210.0;
+const PDF_PAGE_HEIGHT_MM: f32 = 297.0;
+const PDF_MARGIN_MM: f32 = 20.0;
+const PT_TO_MM: f32 = 0.3528;
+
+// PDF 中会嵌入该字体，只能用单个 .ttf/.otf（不支持 .ttc 字体集合）
+const CJK_FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simkai.ttf",
+    "C:\\Windows\\Fonts\\simfang.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansSC-Regular.otf",
+    "/usr/share/fonts/noto-cjk/NotoSansSC-Regular.otf",
+];
+
+// 依次查找：指定路径 > 数据目录 fonts/ 下的字体 > 系统字体
+fn find_cjk_font(data_dir: &std::path::Path, font_path: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = font_path.map(str::trim).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let bundled = fs::read_dir(data_dir.join("fonts"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf")
+                })
+        });
+    bundled.or_else(|| {
+        CJK_FONT_CANDIDATES
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.exists())
+    })
+}
+
+// 按字符宽度折行：中文等全角字符占 1 个字宽，ASCII 占半个
+fn wrap_text(text: &str, max_em: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut width = 0.0;
+    for c in text.chars() {
+        let w = if c.is_ascii() { 0.5 } else { 1.0 };
+        if width + w > max_em && !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+            width = 0.0;
+        }
+        current.push(c);
+        width += w;
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+// 逐行排版并在需要时翻页，页脚写页码；文档只保存已排好的页面，不拼接全文
+struct PdfLayout {
+    doc: printpdf::PdfDocumentReference,
+    font: printpdf::IndirectFontRef,
+    layer: printpdf::PdfLayerReference,
+    // 下一行基线距页面底部的距离 (mm)
+    cursor_mm: f32,
+    page_number: usize,
+    font_size: f32,
+}
+
+impl PdfLayout {
+    fn new(title: &str, font_file: &std::path::Path, font_size: f32) -> Result<Self, AppError> {
+        use printpdf::{Mm, PdfDocument};
+
+        let (doc, page, layer) = PdfDocument::new(
+            title,
+            Mm(PDF_PAGE_WIDTH_MM),
+            Mm(PDF_PAGE_HEIGHT_MM),
+            "content",
+        );
+        let font_reader = fs::File::open(font_file)
+            .map(std::io::BufReader::new)
+            .map_err(|e| {
+                AppError::validation(format!("无法读取字体文件 {}", font_file.display()))
+                    .with_detail(e)
+            })?;
+        let font = doc.add_external_font(font_reader).map_err(|e| {
+            AppError::validation(format!("字体文件 {} 无法使用", font_file.display()))
+                .with_detail(e)
+        })?;
+        let layer = doc.get_page(page).get_layer(layer);
+        let mut layout = Self {
+            doc,
+            font,
+            layer,
+            cursor_mm: 0.0,
+            page_number: 0,
+            font_size,
+        };
+        layout.start_page(false);
+        Ok(layout)
+    }
+
+    fn start_page(&mut self, add_page: bool) {
+        use printpdf::Mm;
+
+        if add_page {
+            let (page, layer) =
+                self.doc
+                    .add_page(Mm(PDF_PAGE_WIDTH_MM), Mm(PDF_PAGE_HEIGHT_MM), "content");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+        }
+        self.page_number += 1;
+        self.cursor_mm = PDF_PAGE_HEIGHT_MM - PDF_MARGIN_MM;
+        // 页码居中写在页脚
+        let footer = format!("- {} -", self.page_number);
+        let footer_size = self.font_size * 0.8;
+        let footer_width = footer.len() as f32 * 0.5 * footer_size * PT_TO_MM;
+        self.layer.use_text(
+            footer,
+            footer_size,
+            Mm((PDF_PAGE_WIDTH_MM - footer_width) / 2.0),
+            Mm(PDF_MARGIN_MM / 2.0),
+            &self.font,
+        );
+    }
+
+    fn line_height(size: f32) -> f32 {
+        size * PT_TO_MM * 1.7
+    }
+
+    // 写一段文字：自动折行，indent_em 为首行缩进，centered 时每行居中
+    fn paragraph(&mut self, text: &str, size: f32, indent_em: f32, centered: bool) {
+        use printpdf::Mm;
+
+        let em_mm = size * PT_TO_MM;
+        let max_em = (PDF_PAGE_WIDTH_MM - 2.0 * PDF_MARGIN_MM) / em_mm;
+        let indented = format!("{}{}", "　".repeat(indent_em as usize), text.trim());
+        for line in wrap_text(&indented, max_em) {
+            let height = Self::line_height(size);
+            if self.cursor_mm - height < PDF_MARGIN_MM {
+                self.start_page(true);
+            }
+            self.cursor_mm -= height;
+            let x = if centered {
+                let width: f32 = line
+                    .chars()
+                    .map(|c| if c.is_ascii() { 0.5 } else { 1.0 })
+                    .sum::<f32>()
+                    * em_mm;
+                (PDF_PAGE_WIDTH_MM - width) / 2.0
+            } else {
+                PDF_MARGIN_MM
+            };
+            self.layer
+                .use_text(line, size, Mm(x), Mm(self.cursor_mm), &self.font);
+        }
+    }
+
+    fn gap(&mut self, lines: f32) {
+        self.cursor_mm -= Self::line_height(self.font_size) * lines;
+    }
+
+    fn save(self, path: &str) -> Result<usize, AppError> {
+        let file = fs::File::create(path).map_err(|e| export_write_error(path, &e))?;
+        self.doc
+            .save(&mut std::io::BufWriter::new(file))
+            .map_err(|e| AppError::validation("PDF 生成失败").with_detail(e))?;
+        Ok(self.page_number)
+    }
+}
+
+// 按原文顺序逐条读取并排版，编、章变化时插入标题；返回页数
+fn write_law_pdf(
+    conn: &Connection,
+    law_name: &str,
+    path: &str,
+    font_file: &std::path::Path,
+    options: &PdfOptions,
+) -> Result<usize, AppError> {
+    let size = options.font_size;
+    let mut layout = PdfLayout::new(law_name, font_file, size)?;
+
+    layout.paragraph(law_name, size * 1.6, 0.0, true);
+    if options.include_publish_date {
+        let publish_date: Option<String> = conn
+            .query_row(
+                "SELECT MAX(publish_date) FROM chunks WHERE law_name = ?1",
+                [law_name],
+                |row| row.get(0),
+            )
+            .map_err(AppError::content_db_error)?;
+        if let Some(date) = publish_date.filter(|d| !d.trim().is_empty()) {
+            layout.paragraph(
+                &format!("发布日期：{}", format_publish_date(&date)),
+                size * 0.9,
+                0.0,
+                true,
+            );
+        }
+    }
+    if let Some(cover) = options
+        .cover_line
+        .as_deref()
+        .filter(|c| !c.trim().is_empty())
+    {
+        layout.paragraph(cover, size * 0.9, 0.0, true);
+    }
+    layout.gap(1.0);
+
+    let sql = format!(
+        "SELECT {} FROM chunks WHERE law_name = ?1 ORDER BY rowid",
+        CHUNK_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let mut rows = stmt.query([law_name]).map_err(AppError::content_db_error)?;
+    let (mut part, mut chapter) = (String::new(), String::new());
+    let mut articles = 0;
+    while let Some(row) = rows.next().map_err(AppError::content_db_error)? {
+        let chunk = law_chunk_from_row(row).map_err(AppError::content_db_error)?;
+        if !chunk.part.is_empty() && chunk.part != part {
+            layout.gap(0.5);
+            layout.paragraph(&chunk.part, size * 1.3, 0.0, true);
+            part = chunk.part;
+        }
+        if !chunk.chapter.is_empty() && chunk.chapter != chapter {
+            layout.gap(0.5);
+            layout.paragraph(&chunk.chapter, size * 1.15, 0.0, true);
+            chapter = chunk.chapter;
+        }
+        // 条文内容一般不含条号，补在第一段开头；之后的段落首行缩进两格
+        let content = chunk.content.trim();
+        let mut paragraphs = content.lines().map(str::trim).filter(|l| !l.is_empty());
+        if let Some(first) = paragraphs.next() {
+            let first = if first.starts_with(&chunk.article_number) {
+                first.to_string()
+            } else {
+                format!("{}　{}", chunk.article_number, first)
+            };
+            layout.paragraph(&first, size, 0.0, false);
+        }
+        for paragraph in paragraphs {
+            layout.paragraph(paragraph, size, 2.0, false);
+        }
+        layout.gap(0.3);
+        articles += 1;
+    }
+    if articles == 0 {
+        return Err(AppError::not_found(format!("《{}》没有条文数据", law_name)));
+    }
+    layout.save(path)
+}
+
+// 导出整部法规为 PDF，返回页数
+metered_command! {
+    #[tauri::command]
+    async fn export_law_pdf(
+        law_name: String,
+        path: String,
+        options: PdfOptions,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        if !(6.0..=32.0).contains(&options.font_size) {
+            return Err(AppError::validation("字号应在 6 到 32 之间"));
+        }
+        let data_dir = get_effective_data_dir(&state);
+        let font_file =
+            find_cjk_font(&data_dir, options.font_path.as_deref()).ok_or_else(|| {
+                AppError::not_found("未找到可用的中文字体")
+                    .with_detail("请在数据目录的 fonts 文件夹中放入 .ttf 或 .otf 中文字体")
+            })?;
+        let schema = snapshot_settings(&state).content_schema_map;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_content_db(&data_dir, &schema)?;
+            let resolved = resolve_law_name(&conn, &law_name)?
+                .ok_or_else(|| AppError::not_found(format!("未找到法规：{}", law_name)))?;
+            let pages = write_law_pdf(&conn, &resolved, &path, &font_file, &options)?;
+            info!("Exported {} as PDF ({} pages)", resolved, pages);
+            Ok(pages)
+        })
+        .await
+        .map_err(AppError::content_db_error)?
+    }
+}
+
+const RESULT_EXPORT_COLUMNS: [(&str, f64); 7] = [
+    ("law_name", 32.0),
+    ("category", 12.0),
+    ("region", 12.0),
+    ("publish_date", 14.0),
+    ("article_number", 16.0),
+    ("content", 80.0),
+    ("relevance", 10.0),
+];
+
+// 与前端结果卡片显示的相关度一致（0-100）
+fn relevance_score(distance: f32) -> f64 {
+    ((1.0 - f64::from(distance) / 2.0) * 100.0)
+        .round()
+        .clamp(0.0, 100.0)
+}
+
+// 20240101 -> 2024-01-01，格式不对时原样返回
+fn format_publish_date(date: &str) -> String {
+    if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])
+    } else {
+        date.to_string()
+    }
+}
+
+// 导出文件写入失败：最常见的是文件正在 Excel 中打开（Windows 上会被锁定）
+fn export_write_error(path: &str, e: &std::io::Error) -> AppError {
+    // 32: ERROR_SHARING_VIOLATION，33: ERROR_LOCK_VIOLATION
+    let locked = matches!(e.raw_os_error(), Some(32) | Some(33))
+        || e.kind() == std::io::ErrorKind::PermissionDenied;
+    let message = if locked {
+        format!(
+            "无法写入 {}，文件可能正在被 Excel 等程序打开，请关闭后重试",
+            path
+        )
+    } else {
+        format!("无法写入 {}", path)
+    };
+    AppError::validation(message).with_detail(e)
+}
+
+// RFC 4180：含逗号、引号或换行的字段加引号，内部引号加倍
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+fn write_results_csv(path: &str, chunks: &[LawChunk]) -> Result<(), AppError> {
+    // 带 BOM 的 UTF-8，Excel 直接双击打开时中文不会乱码
+    let mut out = String::from("\u{feff}");
+    let header: Vec<&str> = RESULT_EXPORT_COLUMNS
+        .iter()
+        .map(|(name, _)| *name)
+        .collect();
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    for chunk in chunks {
+        let publish_date = format_publish_date(&chunk.publish_date);
+        let fields = [
+            csv_field(&chunk.law_name),
+            csv_field(&chunk.category),
+            csv_field(&chunk.region),
+            csv_field(&publish_date),
+            csv_field(&chunk.article_number),
+            csv_field(&chunk.content),
+            relevance_score(chunk._distance).to_string().into(),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    fs::write(path, out).map_err(|e| export_write_error(path, &e))
+}
+
+fn write_results_xlsx(path: &str, chunks: &[LawChunk]) -> Result<(), AppError> {
+    use rust_xlsxwriter::{Color, Format, FormatAlign, Workbook, XlsxError};
+
+    let xlsx_error = |e: XlsxError| match e {
+        XlsxError::IoError(io) => export_write_error(path, &io),
+        other => AppError::validation("生成 Excel 文件失败").with_detail(other),
+    };
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("检索结果").map_err(xlsx_error)?;
+
+    let header_format = Format::new()
+        .set_bold()
+        .set_background_color(Color::RGB(0xF2F2F2));
+    let cell_format = Format::new().set_align(FormatAlign::Top);
+    let content_format = Format::new().set_align(FormatAlign::Top).set_text_wrap();
+    for (col, (name, width)) in RESULT_EXPORT_COLUMNS.iter().enumerate() {
+        let col = col as u16;
+        sheet
+            .write_string_with_format(0, col, *name, &header_format)
+            .map_err(xlsx_error)?;
+        sheet.set_column_width(col, *width).map_err(xlsx_error)?;
+    }
+    // 冻结表头，滚动时始终可见
+    sheet.set_freeze_panes(1, 0).map_err(xlsx_error)?;
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let row = index as u32 + 1;
+        let texts = [
+            chunk.law_name.clone(),
+            chunk.category.clone(),
+            chunk.region.clone(),
+            format_publish_date(&chunk.publish_date),
+            chunk.article_number.clone(),
+        ];
+        for (col, text) in texts.iter().enumerate() {
+            sheet
+                .write_string_with_format(row, col as u16, text, &cell_format)
+                .map_err(xlsx_error)?;
+        }
+        sheet
+            .write_string_with_format(row, 5, chunk.content.trim(), &content_format)
+            .map_err(xlsx_error)?;
+        sheet
+            .write_number_with_format(row, 6, relevance_score(chunk._distance), &cell_format)
+            .map_err(xlsx_error)?;
+    }
+    workbook.save(path).map_err(xlsx_error)
+}
+
+// 把当前搜索结果导出为 CSV 或 Excel，返回写入的行数（不含表头）
+metered_command! {
+    #[tauri::command]
+    fn export_search_results(
+        mut chunks: Vec<LawChunk>,
+        format: String,
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        ensure_full_content(&state, &mut chunks)?;
+        match format.as_str() {
+            "csv" => write_results_csv(&path, &chunks)?,
+            "xlsx" => write_results_xlsx(&path, &chunks)?,
+            other => return Err(AppError::validation(format!("未知的导出格式: {}", other))),
+        }
+        info!("Exported {} search results as {}", chunks.len(), format);
+        Ok(chunks.len())
+    }
+}
+
+fn find_or_create_folder(conn: &Connection, name: &str) -> Result<i32, AppError> {
+    use rusqlite::OptionalExtension;
+
+    let existing: Option<i32> = conn
+        .query_row(
+            "SELECT id FROM favorite_folders WHERE name = ?1 ORDER BY id LIMIT 1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(AppError::user_db_error)?;
+    match existing {
+        Some(id) => Ok(id),
+        None => {
+            conn.execute("INSERT INTO favorite_folders (name) VALUES (?1)", [name])
+                .map_err(AppError::user_db_error)?;
+            Ok(conn.last_insert_rowid() as i32)
+        }
+    }
+}
+
+// 导入收藏：strategy 为 "merge" 时跳过已有条文，"replace" 时用导入内容覆盖
+metered_command! {
+    #[tauri::command]
+    fn import_favorites(
+        path: String,
+        strategy: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<FavoritesImportReport, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let replace = match strategy.as_str() {
+            "merge" => false,
+            "replace" => true,
+            _ => {
+                return Err(AppError::validation(format!(
+                    "未知的导入方式: {}",
+                    strategy
+                )))
+            }
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::validation("无法读取导入文件").with_detail(e))?;
+        let export: FavoritesExport = serde_json::from_str(&content)
+            .map_err(|e| AppError::validation("导入文件格式错误").with_detail(e))?;
+        if export.format != FAVORITES_EXPORT_FORMAT {
+            return Err(AppError::validation("不是收藏导出文件"));
+        }
+        if export.version > FAVORITES_EXPORT_VERSION {
+            return Err(AppError::validation("导出文件版本较新，请先升级应用"));
+        }
+
+        // 法规库不可用时不做标记，视为本地都有
+        let content_conn = content_db(&state).ok();
+        let exists_locally = |law_id: &str| -> bool {
+            match &content_conn {
+                Some(conn) => conn
+                    .query_row(
+                        "SELECT EXISTS(SELECT 1 FROM chunks WHERE id = ?1)",
+                        [law_id],
+                        |row| row.get(0),
+                    )
+                    .unwrap_or(true),
+                None => true,
+            }
+        };
+
+        let mut conn = user_db(&state)?;
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        let mut report = FavoritesImportReport::default();
+        for folder in &export.folders {
+            find_or_create_folder(&tx, folder)?;
+        }
+        for favorite in &export.favorites {
+            // v3 起为 folders，更早的导出文件只有 folder
+            let names = if favorite.folders.is_empty() {
+                favorite.folder.iter().cloned().collect()
+            } else {
+                favorite.folders.clone()
+            };
+            let folder_ids = names
+                .iter()
+                .map(|name| find_or_create_folder(&tx, name))
+                .collect::<Result<Vec<_>, _>>()?;
+            let from_export = !exists_locally(&favorite.law_id);
+            if from_export {
+                report.missing_locally.push(favorite.law_id.clone());
+            }
+
+            let existing: Option<String> = tx
+                .query_row(
+                    "SELECT content FROM favorites WHERE law_id = ?1",
+                    [&favorite.law_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(AppError::user_db_error)?;
+            match existing {
+                None => {
+                    tx.execute(
+                        "INSERT INTO favorites (law_id, law_name, article_number, content, tags, from_export, note)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        rusqlite::params![
+                            favorite.law_id,
+                            favorite.law_name,
+                            favorite.article_number,
+                            favorite.content,
+                            tags_to_json(&normalize_tags(favorite.tags.clone())),
+                            from_export,
+                            favorite.note
+                        ],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                    for folder_id in &folder_ids {
+                        link_favorite_to_folder(&tx, &favorite.law_id, *folder_id)
+                            .map_err(AppError::user_db_error)?;
+                    }
+                    report.imported += 1;
+                }
+                Some(_) if replace => {
+                    tx.execute(
+                        "UPDATE favorites SET law_name = ?2, article_number = ?3, content = ?4, tags = ?5,
+                         from_export = ?6, note = ?7 WHERE law_id = ?1",
+                        rusqlite::params![
+                            favorite.law_id,
+                            favorite.law_name,
+                            favorite.article_number,
+                            favorite.content,
+                            tags_to_json(&normalize_tags(favorite.tags.clone())),
+                            from_export,
+                            favorite.note
+                        ],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                    // 覆盖时文件夹也以导入文件为准
+                    tx.execute(
+                        "DELETE FROM favorite_folder_links WHERE law_id = ?1",
+                        [&favorite.law_id],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                    for folder_id in &folder_ids {
+                        link_favorite_to_folder(&tx, &favorite.law_id, *folder_id)
+                            .map_err(AppError::user_db_error)?;
+                    }
+                    report.updated += 1;
+                }
+                Some(local_content) => {
+                    if local_content != favorite.content {
+                        report.conflicts.push(favorite.law_id.clone());
+                    }
+                    report.skipped += 1;
+                }
+            }
+        }
+        for law in &export.laws {
+            let folder_id = match &law.folder {
+                Some(name) => Some(find_or_create_folder(&tx, name)?),
+                None => None,
+            };
+            let exists: bool = tx
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM favorite_laws WHERE law_name = ?1)",
+                    [&law.law_name],
+                    |row| row.get(0),
+                )
+                .map_err(AppError::user_db_error)?;
+            if exists && !replace {
+                report.skipped += 1;
+                continue;
+            }
+            tx.execute(
+                "INSERT INTO favorite_laws (law_name, category, region, folder_id, note)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(law_name) DO UPDATE SET category = excluded.category,
+                    region = excluded.region, folder_id = excluded.folder_id, note = excluded.note",
+                rusqlite::params![law.law_name, law.category, law.region, folder_id, law.note],
+            )
+            .map_err(AppError::user_db_error)?;
+            if exists {
+                report.updated += 1;
+            } else {
+                report.imported += 1;
+            }
+        }
+        tx.commit().map_err(AppError::user_db_error)?;
+
+        info!(
+            "Imported favorites: {} new, {} updated, {} skipped",
+            report.imported, report.updated, report.skipped
+        );
+        Ok(report)
+    }
+}
 
-    Ok(conn)
+metered_command! {
+    #[tauri::command]
+    fn add_history(query: String, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        // 只更新时间与次数，保留搜索完成时记录的类型与结果数
+        retry_on_busy(|| {
+            conn.execute(
+                "INSERT INTO search_history (query, timestamp) VALUES (?1, ?2)
+                 ON CONFLICT(query) DO UPDATE SET timestamp = excluded.timestamp,
+                    use_count = search_history.use_count + 1",
+                rusqlite::params![query, timestamp],
+            )
+        })?;
+
+        prune_history(&conn, snapshot_settings(&state).history_limit)
+    }
 }
-fn load_settings_from_disk(path: &PathBuf) -> AppSettings {
-    if let Ok(content) = fs::read_to_string(path) {
-        if let Ok(settings) = serde_json::from_str(&content) {
-            return settings;
-        }
+
+// 搜索完成后写入历史与使用统计；失败只记日志，不影响搜索结果
+fn record_history(state: &AppState, query: &str, search_type: &str, results: &[LawChunk]) {
+    let query = query.trim();
+    if query.is_empty() {
+        return;
+    }
+    let result_count = results.len();
+    let timestamp = chrono::Utc::now().timestamp();
+    let result = user_db(state).and_then(|conn| {
+        retry_on_busy(|| {
+            conn.execute(
+                "INSERT INTO search_history (query, timestamp, search_type, result_count)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(query) DO UPDATE SET timestamp = excluded.timestamp,
+                    search_type = excluded.search_type, result_count = excluded.result_count,
+                    use_count = search_history.use_count + 1",
+                rusqlite::params![query, timestamp, search_type, result_count as i64],
+            )
+        })?;
+        prune_history(&conn, snapshot_settings(state).history_limit)
+    });
+    if let Err(e) = result {
+        warn!("Failed to record search history: {}", e);
     }
-    AppSettings::default()
+
+    let mut law_names: Vec<&str> = results.iter().map(|c| c.law_name.as_str()).collect();
+    law_names.sort_unstable();
+    law_names.dedup();
+    let mut events = vec![(search_type, None, Some(query))];
+    events.extend(
+        law_names
+            .into_iter()
+            .map(|name| ("retrieved", Some(name), None)),
+    );
+    log_activity(state, &events);
 }
 
-fn get_effective_data_dir(state: &AppState) -> PathBuf {
-    let settings = state.settings.lock().unwrap();
-    if let Some(custom_path) = &settings.custom_data_path {
-        if !custom_path.trim().is_empty() {
-            let path = PathBuf::from(custom_path);
-            if path.exists() {
-                return path;
-            }
+// 写入使用统计：(event_type, law_name, query)；关闭统计时不记录
+fn log_activity(state: &AppState, events: &[(&str, Option<&str>, Option<&str>)]) {
+    if !snapshot_settings(state).enable_usage_stats || events.is_empty() {
+        return;
+    }
+    let timestamp = chrono::Utc::now().timestamp();
+    let result = user_db(state).and_then(|mut conn| {
+        let tx = conn.transaction().map_err(AppError::user_db_error)?;
+        for (event_type, law_name, query) in events {
+            tx.execute(
+                "INSERT INTO activity_log (event_type, timestamp, law_name, query)
+                 VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![event_type, timestamp, law_name, query],
+            )
+            .map_err(AppError::user_db_error)?;
         }
+        tx.commit().map_err(AppError::user_db_error)
+    });
+    if let Err(e) = result {
+        warn!("Failed to record activity: {}", e);
     }
-    state.app_data_dir.clone()
 }
 
-async fn get_embedding(
-    text: &str,
-    base_url: &str,
-    api_key: &str,
-    model: &str,
-) -> Result<Vec<f32>, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/embeddings", base_url.trim_end_matches('/'));
-    let prompt = text.replace("\n", " ");
+// 最近 days 天的使用统计
+metered_command! {
+    #[tauri::command]
+    fn get_usage_stats(
+        days: i32,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<UsageStats, AppError> {
+        let conn = user_db(&state)?;
+        let since = chrono::Utc::now().timestamp() - i64::from(days.max(1)) * 24 * 3600;
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&serde_json::json!({
-            "model": model,
-            "input": prompt,
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+        let named_counts = |sql: &str| -> Result<Vec<NamedCount>, AppError> {
+            let mut stmt = conn.prepare(sql).map_err(AppError::user_db_error)?;
+            let rows = stmt
+                .query_map([since], |row| {
+                    Ok(NamedCount {
+                        name: row.get(0)?,
+                        count: row.get::<_, i64>(1)? as usize,
+                    })
+                })
+                .map_err(AppError::user_db_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(AppError::user_db_error)?;
+            Ok(rows)
+        };
 
-    if !res.status().is_success() {
-        return Err(format!("Embedding API Error: {}", res.status()));
+        let mut stmt = conn
+            .prepare(
+                "SELECT date(timestamp, 'unixepoch', 'localtime') AS day, COUNT(*) FROM activity_log
+                 WHERE event_type IN ('search', 'agent') AND timestamp >= ?1
+                 GROUP BY day ORDER BY day",
+            )
+            .map_err(AppError::user_db_error)?;
+        let searches_per_day = stmt
+            .query_map([since], |row| {
+                Ok(DailyCount {
+                    day: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as usize,
+                })
+            })
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+
+        let top_laws = named_counts(
+            "SELECT law_name, COUNT(*) AS n FROM activity_log
+             WHERE event_type = 'retrieved' AND law_name IS NOT NULL AND timestamp >= ?1
+             GROUP BY law_name ORDER BY n DESC, law_name LIMIT 10",
+        )?;
+        let top_queries = named_counts(
+            "SELECT query, COUNT(*) AS n FROM activity_log
+             WHERE event_type IN ('search', 'agent') AND query IS NOT NULL AND timestamp >= ?1
+             GROUP BY query HAVING n > 1 ORDER BY n DESC, query LIMIT 10",
+        )?;
+
+        Ok(UsageStats {
+            searches_per_day,
+            top_laws,
+            top_queries,
+        })
     }
+}
 
-    let json: serde_json::Value = res.json().await.map_err(|e| e.to_string())?;
+metered_command! {
+    #[tauri::command]
+    fn clear_usage_stats(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute("DELETE FROM activity_log", [])
+            .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
 
-    if let Some(data) = json.get("data") {
-        if let Some(first) = data.get(0) {
-            if let Some(vec) = first.get("embedding") {
-                let embedding: Vec<f32> = vec
-                    .as_array()
-                    .ok_or("Invalid embedding format")?
-                    .iter()
-                    .map(|v| v.as_f64().unwrap_or(0.0) as f32)
-                    .collect();
-                return Ok(embedding);
-            }
-        }
+// 未置顶的历史只保留最近的 limit 条，置顶记录不计入上限、也不会被清理
+fn prune_history(conn: &Connection, limit: usize) -> Result<(), AppError> {
+    conn.execute(
+        "DELETE FROM search_history WHERE COALESCE(pinned, 0) = 0 AND id NOT IN (SELECT id FROM search_history WHERE COALESCE(pinned, 0) = 0 ORDER BY timestamp DESC LIMIT ?1)",
+        [limit.max(1) as i64],
+    )
+    .map_err(AppError::user_db_error)?;
+    Ok(())
+}
+
+const HISTORY_COLUMNS: &str =
+    "id, query, timestamp, search_type, result_count, COALESCE(pinned, 0), use_count";
+
+fn history_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<SearchHistoryItem> {
+    Ok(SearchHistoryItem {
+        id: row.get(0)?,
+        query: row.get(1)?,
+        timestamp: row.get(2)?,
+        search_type: row.get(3)?,
+        result_count: row.get(4)?,
+        pinned: row.get(5)?,
+        use_count: row.get(6)?,
+    })
+}
+
+fn load_history(state: &AppState) -> Result<Vec<SearchHistoryItem>, AppError> {
+    let conn = user_db(state)?;
+    let sql = format!(
+        "SELECT {} FROM search_history ORDER BY COALESCE(pinned, 0) DESC, timestamp DESC",
+        HISTORY_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::user_db_error)?;
+
+    let history = stmt
+        .query_map([], history_item_from_row)
+        .map_err(AppError::user_db_error)?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(history)
+}
+
+metered_command! {
+    #[tauri::command]
+    fn get_history(state: tauri::State<'_, AppState>) -> Result<Vec<SearchHistoryItem>, AppError> {
+        load_history(&state)
     }
-    if let Some(vec) = json.get("embedding") {
-        let embedding: Vec<f32> = vec
-            .as_array()
-            .ok_or("Invalid embedding format")?
-            .iter()
-            .map(|v| v.as_f64().unwrap_or(0.0) as f32)
+}
+
+// 每多搜索一次相当于晚 3 天，次数最多计 20 次，避免老的高频查询永远排在前面
+const HISTORY_USE_WEIGHT_SECS: i64 = 3 * 24 * 3600;
+const HISTORY_USE_COUNT_CAP: i64 = 20;
+
+// 按前缀查找历史，综合最近使用时间与搜索次数排序
+metered_command! {
+    #[tauri::command]
+    fn search_history(
+        prefix: String,
+        limit: usize,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<SearchHistoryItem>, AppError> {
+        let conn = user_db(&state)?;
+        let sql = format!(
+            "SELECT {} FROM search_history WHERE query LIKE ?1 || '%'
+             ORDER BY timestamp + MIN(use_count, ?2) * ?3 DESC LIMIT ?4",
+            HISTORY_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql).map_err(AppError::user_db_error)?;
+        let history = stmt
+            .query_map(
+                rusqlite::params![
+                    prefix.trim(),
+                    HISTORY_USE_COUNT_CAP,
+                    HISTORY_USE_WEIGHT_SECS,
+                    limit.clamp(1, 100) as i64
+                ],
+                history_item_from_row,
+            )
+            .map_err(AppError::user_db_error)?
+            .filter_map(Result::ok)
             .collect();
-        return Ok(embedding);
+        Ok(history)
     }
+}
 
-    Err("Could not find embedding in response".to_string())
+// 兼容旧前端：只返回查询文本
+metered_command! {
+    #[tauri::command]
+    fn get_history_strings(state: tauri::State<'_, AppState>) -> Result<Vec<String>, AppError> {
+        Ok(load_history(&state)?
+            .into_iter()
+            .map(|item| item.query)
+            .collect())
+    }
 }
 
-async fn call_llm(
-    model: &str,
-    prompt: &str,
-    base_url: &str,
-    api_key: &str,
-) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
-
-    let req_body = serde_json::json!({
-        "model": model,
-        "messages": [{ "role": "user", "content": prompt }],
-        "temperature": 0.1,
-        "stream": false
-    });
+metered_command! {
+    #[tauri::command]
+    fn delete_history_item(id: i32, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        conn.execute("DELETE FROM search_history WHERE id = ?1", [id])
+            .map_err(AppError::user_db_error)?;
+        Ok(())
+    }
+}
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&req_body)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+metered_command! {
+    #[tauri::command]
+    fn pin_history_item(
+        id: i32,
+        pinned: bool,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let updated = conn
+            .execute(
+                "UPDATE search_history SET pinned = ?2 WHERE id = ?1",
+                rusqlite::params![id, pinned],
+            )
+            .map_err(AppError::user_db_error)?;
+        if updated == 0 {
+            return Err(AppError::not_found("历史记录不存在"));
+        }
+        Ok(())
+    }
+}
 
-    if !res.status().is_success() {
-        return Err(format!("LLM API Error: {}", res.status()));
+// keep_pinned 默认为 true，只清除未置顶的记录
+metered_command! {
+    #[tauri::command]
+    fn clear_history(
+        keep_pinned: Option<bool>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let sql = if keep_pinned.unwrap_or(true) {
+            "DELETE FROM search_history WHERE COALESCE(pinned, 0) = 0"
+        } else {
+            "DELETE FROM search_history"
+        };
+        conn.execute(sql, []).map_err(AppError::user_db_error)?;
+        Ok(())
     }
+}
 
-    let json: serde_json::Value = res.json().await.map_err(|e| e.to_string())?;
-    let content = json["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or("No content in response")?
-        .to_string();
+// 收藏复习 (Spaced review)
+// 简化的 SM-2：评分 0 = 忘记，1 = 困难，2 = 记得，3 = 轻松
 
-    Ok(content)
-}
+const REVIEW_DEFAULT_EASE: f64 = 2.5;
+const REVIEW_MIN_EASE: f64 = 1.3;
+const REVIEW_MAX_EASE: f64 = 3.0;
+// 忘记的条文在本轮复习稍后再出现
+const REVIEW_RELEARN_SECS: i64 = 10 * 60;
 
-fn clean_json_str(s: &str) -> String {
-    let mut content = s.to_string();
+impl ReviewSchedule {
+    fn new(now: i64) -> Self {
+        Self {
+            ease: REVIEW_DEFAULT_EASE,
+            interval_days: 0.0,
+            repetitions: 0,
+            due_at: now,
+        }
+    }
 
-    // 1. 移除 <think>...</think>
-    while let Some(start) = content.find("<think>") {
-        if let Some(end) = content.find("</think>") {
-            if end > start {
-                content.replace_range(start..end + 8, "");
-            } else {
-                content = content.replace("<think>", "").replace("</think>", "");
+    // 按评分计算下一次复习；首次记得为 1 天，第二次 3 天，之后按 ease 倍增
+    fn next(self, grade: u8, now: i64) -> Self {
+        let (ease, interval_days, repetitions) = match grade {
+            0 => (self.ease - 0.2, 0.0, 0),
+            1 => (
+                self.ease - 0.15,
+                (self.interval_days * 1.2).max(1.0),
+                self.repetitions + 1,
+            ),
+            _ => {
+                let base = match self.repetitions {
+                    0 => 1.0,
+                    1 => 3.0,
+                    _ => self.interval_days * self.ease,
+                };
+                if grade >= 3 {
+                    (
+                        self.ease + 0.15,
+                        (base * 1.3).max(4.0),
+                        self.repetitions + 1,
+                    )
+                } else {
+                    (self.ease, base, self.repetitions + 1)
+                }
             }
+        };
+        let interval_days = interval_days.round();
+        let due_at = if interval_days == 0.0 {
+            now + REVIEW_RELEARN_SECS
         } else {
-            content = content.replace("<think>", "");
+            now + (interval_days * 86400.0) as i64
+        };
+        Self {
+            ease: ease.clamp(REVIEW_MIN_EASE, REVIEW_MAX_EASE),
+            interval_days,
+            repetitions,
+            due_at,
         }
     }
+}
 
-    // 2. 智能提取 JSON (Array 或 Object)
-    let first_brace = content.find('{');
-    let first_bracket = content.find('[');
-    
-    let (start, end) = match (first_brace, first_bracket) {
-        (Some(brace), Some(bracket)) => {
-            if brace < bracket {
-                // 对象在数组前面，说明是 {...}
-                (brace, content.rfind('}'))
-            } else {
-                // 数组在对象前面，说明是 [...]
-                (bracket, content.rfind(']'))
+// 开启复习模式时把收藏加入复习计划，已在计划中的保持不变。
+// 在收藏提交之后调用，失败只记录日志，不让已成功的收藏报错
+fn enroll_reviews_if_enabled(state: &AppState, law_ids: &[&str]) {
+    if !snapshot_settings(state).enable_review_mode || law_ids.is_empty() {
+        return;
+    }
+    if let Err(e) = insert_review_states(state, law_ids) {
+        warn!("Failed to enroll favorites for review: {}", e);
+    }
+}
+
+fn insert_review_states(state: &AppState, law_ids: &[&str]) -> Result<(), AppError> {
+    let conn = user_db(state)?;
+    let mut stmt = conn
+        .prepare("INSERT OR IGNORE INTO review_state (law_id, due_at) VALUES (?1, ?2)")
+        .map_err(AppError::user_db_error)?;
+    let now = chrono::Utc::now().timestamp();
+    for law_id in law_ids {
+        stmt.execute(rusqlite::params![law_id, now])
+            .map_err(AppError::user_db_error)?;
+    }
+    Ok(())
+}
+
+// 将已有收藏加入复习计划；不传 law_ids 时加入全部收藏，返回新加入的数量
+metered_command! {
+    #[tauri::command]
+    fn enroll_reviews(
+        law_ids: Option<Vec<String>>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        let conn = user_db(&state)?;
+        let now = chrono::Utc::now().timestamp();
+        let added = match law_ids {
+            Some(law_ids) => {
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT OR IGNORE INTO review_state (law_id, due_at)
+                         SELECT law_id, ?2 FROM favorites WHERE law_id = ?1",
+                    )
+                    .map_err(AppError::user_db_error)?;
+                let mut added = 0;
+                for law_id in law_ids {
+                    added += stmt
+                        .execute(rusqlite::params![law_id, now])
+                        .map_err(AppError::user_db_error)?;
+                }
+                added
             }
-        },
-        (Some(brace), None) => (brace, content.rfind('}')),
-        (None, Some(bracket)) => (bracket, content.rfind(']')),
-        (None, None) => return content, // 没找到，直接返回原文本尝试解析
-    };
+            None => conn
+                .execute(
+                    "INSERT OR IGNORE INTO review_state (law_id, due_at) SELECT law_id, ?1 FROM favorites",
+                    [now],
+                )
+                .map_err(AppError::user_db_error)?,
+        };
+        Ok(added)
+    }
+}
 
-    match (start, end) { // 这里的 start/end 是 usize，不是 Option
-        (s, Some(e)) if s <= e => content[s..=e].to_string(),
-        _ => content // 提取失败，返回原样
+// 到期的复习卡片，最早到期的在前；limit 默认 20
+metered_command! {
+    #[tauri::command]
+    fn get_due_reviews(
+        limit: Option<usize>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<ReviewCard>, AppError> {
+        let conn = user_db(&state)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT f.law_id, f.law_name, f.article_number, f.content, f.note,
+                        r.ease, r.interval_days, r.repetitions, r.due_at
+                 FROM review_state r JOIN favorites f ON f.law_id = r.law_id
+                 WHERE r.due_at <= ?1
+                 ORDER BY r.due_at, f.id
+                 LIMIT ?2",
+            )
+            .map_err(AppError::user_db_error)?;
+        let cards = stmt
+            .query_map(
+                rusqlite::params![chrono::Utc::now().timestamp(), limit.unwrap_or(20) as i64],
+                |row| {
+                    let article_number: String =
+                        row.get::<_, Option<String>>(2)?.unwrap_or_default();
+                    let content: String = row.get::<_, Option<String>>(3)?.unwrap_or_default();
+                    Ok(ReviewCard {
+                        law_id: row.get(0)?,
+                        law_name: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                        masked_content: mask_article_number(&content, &article_number),
+                        article_number,
+                        content,
+                        note: row.get(4)?,
+                        schedule: ReviewSchedule {
+                            ease: row.get(5)?,
+                            interval_days: row.get(6)?,
+                            repetitions: row.get(7)?,
+                            due_at: row.get(8)?,
+                        },
+                    })
+                },
+            )
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        Ok(cards)
     }
 }
 
-// ==========================================
-// 4. 核心逻辑
-// ==========================================
+// 条文内容通常以“第X条”开头，替换为下划线，避免复习时直接看到条号
+fn mask_article_number(content: &str, article_number: &str) -> String {
+    if article_number.is_empty() {
+        return content.to_string();
+    }
+    content.replace(article_number, "第＿＿条")
+}
 
-pub async fn search_law_logic(
-    query: String,
-    filter_region: Option<String>,
-    state: &AppState,
-) -> Result<Vec<LawChunk>, String> {
-    println!(">>> (Logic) Searching for: {}", query);
+// 提交一次复习评分（0..=3）；尚未加入计划的收藏在首次评分时加入
+metered_command! {
+    #[tauri::command]
+    fn submit_review(
+        law_id: String,
+        grade: u8,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<ReviewSchedule, AppError> {
+        use rusqlite::OptionalExtension;
 
-    let settings = state.settings.lock().unwrap().clone();
-    let data_dir = get_effective_data_dir(state);
+        if grade > 3 {
+            return Err(AppError::validation("评分应为 0 到 3"));
+        }
+        let conn = user_db(&state)?;
+        let favorited: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM favorites WHERE law_id = ?1)",
+                [&law_id],
+                |row| row.get(0),
+            )
+            .map_err(AppError::user_db_error)?;
+        if !favorited {
+            return Err(AppError::not_found("该条文尚未收藏"));
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let current = conn
+            .query_row(
+                "SELECT ease, interval_days, repetitions, due_at FROM review_state WHERE law_id = ?1",
+                [&law_id],
+                |row| {
+                    Ok(ReviewSchedule {
+                        ease: row.get(0)?,
+                        interval_days: row.get(1)?,
+                        repetitions: row.get(2)?,
+                        due_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(AppError::user_db_error)?
+            .unwrap_or_else(|| ReviewSchedule::new(now));
+        let next = current.next(grade, now);
+
+        retry_on_busy(|| {
+            conn.execute(
+                "INSERT INTO review_state (law_id, ease, interval_days, repetitions, due_at, last_reviewed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(law_id) DO UPDATE SET ease = excluded.ease,
+                     interval_days = excluded.interval_days, repetitions = excluded.repetitions,
+                     due_at = excluded.due_at, last_reviewed_at = excluded.last_reviewed_at",
+                rusqlite::params![
+                    law_id,
+                    next.ease,
+                    next.interval_days,
+                    next.repetitions,
+                    next.due_at,
+                    now
+                ],
+            )
+        })?;
+        Ok(next)
+    }
+}
 
-    let vector = get_embedding(
-        &query,
-        &settings.embedding_base_url,
-        &settings.embedding_api_key,
-        &settings.embedding_model,
+// 全文批注 (Annotations)
+
+const ANNOTATION_COLORS: &[&str] = &["yellow", "green", "blue", "pink", "purple"];
+const ANNOTATION_COLUMNS: &str =
+    "id, law_name, start_offset, end_offset, quote, color, note, created_at, orphaned";
+
+fn annotation_from_row(row: &rusqlite::Row) -> rusqlite::Result<Annotation> {
+    Ok(Annotation {
+        id: row.get(0)?,
+        law_name: row.get(1)?,
+        start_offset: row.get::<_, i64>(2)? as usize,
+        end_offset: row.get::<_, i64>(3)? as usize,
+        quote: row.get(4)?,
+        color: row.get(5)?,
+        note: row.get(6)?,
+        created_at: row.get(7)?,
+        orphaned: row.get(8)?,
+    })
+}
+
+fn load_annotation(conn: &Connection, id: i64) -> Result<Annotation, AppError> {
+    use rusqlite::OptionalExtension;
+
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM annotations WHERE id = ?1",
+            ANNOTATION_COLUMNS
+        ),
+        [id],
+        annotation_from_row,
     )
-    .await?;
+    .optional()
+    .map_err(AppError::user_db_error)?
+    .ok_or_else(|| AppError::not_found("批注不存在"))
+}
 
-    let lancedb_path_buf = data_dir.join("law_db.lancedb");
-    let mut path_str = lancedb_path_buf.to_string_lossy().to_string();
-    #[cfg(windows)]
-    {
-        if path_str.starts_with(r"\\?\") {
-            path_str = path_str[4..].to_string();
+fn validate_annotation_color(color: &str) -> Result<(), AppError> {
+    if ANNOTATION_COLORS.contains(&color) {
+        Ok(())
+    } else {
+        Err(AppError::validation(format!(
+            "不支持的颜色：{}（可选 {}）",
+            color,
+            ANNOTATION_COLORS.join(" / ")
+        )))
+    }
+}
+
+// UTF-16 偏移换算为字节偏移；落在代理对中间或超出全文时为 None
+fn utf16_to_byte_offset(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+    for (byte, ch) in text.char_indices() {
+        if units >= offset {
+            return (units == offset).then_some(byte);
         }
+        units += ch.len_utf16();
     }
+    (units == offset).then_some(text.len())
+}
 
-    if !lancedb_path_buf.exists() {
-        return Err(format!("数据库路径不存在: {}", path_str));
+fn utf16_range_to_bytes(text: &str, start: usize, end: usize) -> Option<std::ops::Range<usize>> {
+    Some(utf16_to_byte_offset(text, start)?..utf16_to_byte_offset(text, end)?)
+}
+
+// 偏移必须落在字符边界上且不超过全文长度，否则切出的原文会是半个字符；返回对应的字节范围
+fn validate_annotation_range(
+    text: &str,
+    start: usize,
+    end: usize,
+) -> Result<std::ops::Range<usize>, AppError> {
+    if start >= end {
+        return Err(AppError::validation("批注范围为空"));
     }
+    let len = text.encode_utf16().count();
+    if end > len {
+        return Err(AppError::validation(format!(
+            "批注范围超出全文长度（{} > {}）",
+            end, len
+        )));
+    }
+    utf16_range_to_bytes(text, start, end)
+        .ok_or_else(|| AppError::validation("批注范围没有落在字符边界上"))
+}
 
-    let db = lancedb::connect(&path_str)
-        .execute()
-        .await
-        .map_err(|e| format!("Connect error: {}", e))?;
-    let table = db
-        .open_table("laws_vectors")
-        .execute()
-        .await
-        .map_err(|e| format!("Open table error: {}", e))?;
+metered_command! {
+    #[tauri::command]
+    fn add_annotation(
+        law_name: String,
+        start_offset: usize,
+        end_offset: usize,
+        color: Option<String>,
+        note: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Annotation, AppError> {
+        let (law_name, full_text) = read_law_full_text(&*content_db(&state)?, &law_name)?;
+        let range = validate_annotation_range(&full_text, start_offset, end_offset)?;
+        let color = color.unwrap_or_else(|| ANNOTATION_COLORS[0].to_string());
+        validate_annotation_color(&color)?;
+        let note = note.filter(|n| !n.trim().is_empty());
+
+        let conn = user_db(&state)?;
+        conn.execute(
+            "INSERT INTO annotations (law_name, start_offset, end_offset, quote, color, note)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                law_name,
+                start_offset as i64,
+                end_offset as i64,
+                &full_text[range],
+                color,
+                note
+            ],
+        )
+        .map_err(AppError::user_db_error)?;
+        load_annotation(&conn, conn.last_insert_rowid())
+    }
+}
+
+// 按在全文中的位置排序；读取前先对照当前全文重新定位
+metered_command! {
+    #[tauri::command]
+    fn get_annotations(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<Annotation>, AppError> {
+        let (law_name, full_text) = read_law_full_text(&*content_db(&state)?, &law_name)?;
+        let conn = user_db(&state)?;
+        reconcile_law_annotations(&conn, &law_name, &full_text)?;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM annotations WHERE law_name = ?1 ORDER BY start_offset, id",
+                ANNOTATION_COLUMNS
+            ))
+            .map_err(AppError::user_db_error)?;
+        let annotations = stmt
+            .query_map([&law_name], annotation_from_row)
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        Ok(annotations)
+    }
+}
+
+// 修改颜色或笔记，未传的字段保持不变；笔记传空字符串清除
+metered_command! {
+    #[tauri::command]
+    fn update_annotation(
+        id: i64,
+        color: Option<String>,
+        note: Option<String>,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Annotation, AppError> {
+        let conn = user_db(&state)?;
+        let current = load_annotation(&conn, id)?;
+        let color = color.unwrap_or(current.color);
+        validate_annotation_color(&color)?;
+        let note = match note {
+            Some(note) => Some(note).filter(|n| !n.trim().is_empty()),
+            None => current.note,
+        };
+        conn.execute(
+            "UPDATE annotations SET color = ?2, note = ?3 WHERE id = ?1",
+            rusqlite::params![id, color, note],
+        )
+        .map_err(AppError::user_db_error)?;
+        load_annotation(&conn, id)
+    }
+}
+
+metered_command! {
+    #[tauri::command]
+    fn delete_annotation(id: i64, state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+        let conn = user_db(&state)?;
+        let deleted = conn
+            .execute("DELETE FROM annotations WHERE id = ?1", [id])
+            .map_err(AppError::user_db_error)?;
+        if deleted == 0 {
+            return Err(AppError::not_found("批注不存在"));
+        }
+        Ok(())
+    }
+}
+
+// 法规库更新后全文可能变化：原位置仍是原文的保持不变，
+// 否则在新全文中查找原文，取离原位置最近的一处；找不到的标记为 orphaned
+metered_command! {
+    #[tauri::command]
+    fn reconcile_annotations(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<AnnotationReconcileReport, AppError> {
+        let (law_name, full_text) = read_law_full_text(&*content_db(&state)?, &law_name)?;
+        reconcile_law_annotations(&*user_db(&state)?, &law_name, &full_text)
+    }
+}
+
+fn reconcile_law_annotations(
+    conn: &Connection,
+    law_name: &str,
+    full_text: &str,
+) -> Result<AnnotationReconcileReport, AppError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM annotations WHERE law_name = ?1",
+            ANNOTATION_COLUMNS
+        ))
+        .map_err(AppError::user_db_error)?;
+    let annotations = stmt
+        .query_map([law_name], annotation_from_row)
+        .map_err(AppError::user_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::user_db_error)?;
+
+    let mut report = AnnotationReconcileReport {
+        checked: annotations.len(),
+        ..Default::default()
+    };
+    for annotation in annotations {
+        let in_place =
+            utf16_range_to_bytes(full_text, annotation.start_offset, annotation.end_offset)
+                .and_then(|range| full_text.get(range))
+                == Some(annotation.quote.as_str());
+        if in_place {
+            if annotation.orphaned {
+                conn.execute(
+                    "UPDATE annotations SET orphaned = 0 WHERE id = ?1",
+                    [annotation.id],
+                )
+                .map_err(AppError::user_db_error)?;
+            }
+            continue;
+        }
+
+        let nearest = full_text
+            .match_indices(annotation.quote.as_str())
+            .map(|(start, _)| full_text[..start].encode_utf16().count())
+            .min_by_key(|start| start.abs_diff(annotation.start_offset));
+        match nearest {
+            Some(start) => {
+                conn.execute(
+                    "UPDATE annotations SET start_offset = ?2, end_offset = ?3, orphaned = 0 WHERE id = ?1",
+                    rusqlite::params![
+                        annotation.id,
+                        start as i64,
+                        (start + annotation.quote.encode_utf16().count()) as i64
+                    ],
+                )
+                .map_err(AppError::user_db_error)?;
+                report.relocated += 1;
+            }
+            None => {
+                if !annotation.orphaned {
+                    conn.execute(
+                        "UPDATE annotations SET orphaned = 1 WHERE id = ?1",
+                        [annotation.id],
+                    )
+                    .map_err(AppError::user_db_error)?;
+                }
+                report.orphaned.push(annotation.id);
+            }
+        }
+    }
+    if report.relocated > 0 || !report.orphaned.is_empty() {
+        info!(
+            "Annotations reconciled for {}: {} relocated, {} orphaned",
+            law_name,
+            report.relocated,
+            report.orphaned.len()
+        );
+    }
+    Ok(report)
+}
+
+// 条文互引 (Cross references)
+// 扫描条文中的《法名》第X条 与 本法第X条，解析为条文 ID 后以有向边存入引用索引库
+// 按法规增量扫描：已扫描的法规记录在 cross_ref_scanned 中，导入新法规后只处理新增部分。
+// 索引库放在用户数据目录下、按数据目录各建一个：安装版的 content.db 位于只读的资源目录，
+// 数据更新时 content.db 也会被整体替换
+
+static CROSS_REF_RUNNING: AtomicBool = AtomicBool::new(false);
+const CROSS_REF_QUERY_LIMIT: usize = 200;
+const CROSS_REF_DIR: &str = "cross_refs";
+
+fn cross_ref_db_path(state: &AppState, data_dir: &std::path::Path) -> PathBuf {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(data_dir.to_string_lossy().as_bytes());
+    let name: String = digest
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    state
+        .user_db_path
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new("."))
+        .join(CROSS_REF_DIR)
+        .join(format!("{}.db", name))
+}
+
+// 与用户库相同的 WAL 与忙等待设置，后台扫描写入时检索仍可读取
+fn open_cross_ref_db(path: &std::path::Path) -> Result<Connection, AppError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| AppError::user_db("无法创建引用索引目录").with_detail(e))?;
+    }
+    let conn = open_user_db(path)?;
+    ensure_cross_ref_tables(&conn)?;
+    Ok(conn)
+}
+
+// 数据目录的内容被整体替换后条文 ID 可能变化，删除旧索引等待重新扫描
+fn reset_cross_ref_index(state: &AppState, data_dir: &std::path::Path) {
+    let path = cross_ref_db_path(state, data_dir);
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        match fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove cross reference index {:?}: {}", file, e),
+        }
+    }
+}
+
+// target_id 为空表示引用的条文不在库中，后续扫描时会再尝试解析
+fn ensure_cross_ref_tables(conn: &Connection) -> Result<(), AppError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cross_refs (
+            source_id TEXT NOT NULL,
+            target_law TEXT NOT NULL,
+            target_article TEXT NOT NULL,
+            target_id TEXT,
+            ref_text TEXT NOT NULL,
+            PRIMARY KEY (source_id, target_law, target_article)
+        );
+        CREATE INDEX IF NOT EXISTS idx_cross_refs_target ON cross_refs (target_id);
+        CREATE TABLE IF NOT EXISTS cross_ref_scanned (
+            law_name TEXT PRIMARY KEY,
+            scanned_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    )
+    .map_err(AppError::user_db_error)
+}
 
-    let fetch_limit = settings.search_top_k * 3;
+fn cross_ref_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?:《([^《》]+)》|(本法))?(第(?:[一二三四五六七八九十百千零]+|\d{1,4})条(?:之(?:[一二三四五六七八九十]|\d))?)",
+        )
+        .unwrap()
+    })
+}
 
-    let results_stream = table
-        .query()
-        .nearest_to(vector)
-        .map_err(|e| format!("Vector query error: {}", e))?
-        .limit(fetch_limit)
-        .execute()
-        .await
-        .map_err(|e| format!("Search execution error: {}", e))?;
+// 从条文内容中提取 (法名, 条号, 原文)，法名为 None 表示“本法”；阿拉伯数字条号改写为库中的中文条号
+// 紧跟在引用之后、以顿号等连接的“第Y条”沿用同一法名，如《刑法》第二十条、第二十一条
+fn extract_cross_refs(content: &str) -> Vec<(Option<String>, String, String)> {
+    let mut refs = Vec::new();
+    let mut current: Option<Option<String>> = None;
+    let mut last_end = 0;
 
-    let mut stream = results_stream;
-    let mut chunk_ids: Vec<String> = Vec::new();
-    let mut distances: Vec<f32> = Vec::new();
+    for caps in cross_ref_regex().captures_iter(content) {
+        let m = caps.get(0).unwrap();
+        let gap = content[last_end..m.start()].trim();
+        let target = if let Some(name) = caps.get(1) {
+            Some(Some(name.as_str().trim().to_string()))
+        } else if caps.get(2).is_some() {
+            Some(None)
+        } else if last_end > 0 && matches!(gap, "、" | "，" | "和" | "及" | "以及" | "或者" | "至")
+        {
+            current.clone()
+        } else {
+            None
+        };
+        last_end = m.end();
+        if let Some(law) = &target {
+            refs.push((
+                law.clone(),
+                normalize_article_digits(&caps[3]),
+                m.as_str().to_string(),
+            ));
+        }
+        current = target;
+    }
+    refs
+}
 
-    while let Some(item) = stream.next().await {
-        match item {
-            Ok(batch) => {
-                let id_col = batch.column_by_name("chunk_id").ok_or("Missing chunk_id")?;
-                let dist_col = batch
-                    .column_by_name("_distance")
-                    .ok_or("Missing _distance")?;
-                let ids = id_col
-                    .as_any()
-                    .downcast_ref::<StringArray>()
-                    .ok_or("chunk_id error")?;
-                let dists = dist_col
-                    .as_any()
-                    .downcast_ref::<Float32Array>()
-                    .ok_or("_distance error")?;
-                for i in 0..batch.num_rows() {
-                    chunk_ids.push(ids.value(i).to_string());
-                    distances.push(dists.value(i));
-                }
+// 引用目标解析，按 (法名, 条号) 缓存；法名走与搜索相同的模糊匹配
+#[derive(Default)]
+struct CrossRefResolver {
+    law_names: HashMap<String, Option<String>>,
+    chunk_ids: HashMap<(String, String), Option<String>>,
+}
+
+impl CrossRefResolver {
+    fn resolve(
+        &mut self,
+        conn: &Connection,
+        law_name: &str,
+        article: &str,
+    ) -> Result<Option<String>, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let resolved = match self.law_names.get(law_name) {
+            Some(resolved) => resolved.clone(),
+            None => {
+                let resolved = resolve_law_name(conn, law_name)?;
+                self.law_names
+                    .insert(law_name.to_string(), resolved.clone());
+                resolved
             }
-            Err(e) => return Err(format!("Stream error: {}", e)),
+        };
+        let Some(resolved) = resolved else {
+            return Ok(None);
+        };
+        let key = (resolved, article.to_string());
+        if let Some(id) = self.chunk_ids.get(&key) {
+            return Ok(id.clone());
         }
+        let id: Option<String> = conn
+            .query_row(
+                "SELECT id FROM chunks WHERE law_name = ?1 AND article_number = ?2 LIMIT 1",
+                rusqlite::params![key.0, key.1],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(AppError::content_db_error)?;
+        self.chunk_ids.insert(key, id.clone());
+        Ok(id)
     }
+}
 
-    if chunk_ids.is_empty() {
-        return Ok(Vec::new());
+fn run_cross_ref_index<R: Runtime>(
+    app: &AppHandle<R>,
+    data_dir: &std::path::Path,
+    index_path: &std::path::Path,
+) -> Result<CrossRefIndexStats, AppError> {
+    let mut stats = CrossRefIndexStats::default();
+    if !data_dir.join("content.db").exists() {
+        return Ok(stats);
     }
+    // 只读 content.db；导入法规等写入进行时等待锁释放
+    let content = connect_sqlite(&app.state::<AppState>(), data_dir)?;
+    content
+        .busy_timeout(Duration::from_millis(USER_DB_BUSY_TIMEOUT_MS))
+        .map_err(AppError::content_db_error)?;
+    let mut index = open_cross_ref_db(index_path)?;
 
-    let conn = connect_sqlite(&data_dir)?;
-    let placeholders: String = chunk_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let sql = format!(
-        "SELECT id, content, law_name, category, region, publish_date, part, chapter, article_number 
-         FROM chunks WHERE id IN ({})", 
-        placeholders
-    );
-    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
-    let params = rusqlite::params_from_iter(chunk_ids.iter());
-
-    let chunk_map: std::collections::HashMap<String, LawChunk> = stmt
-        .query_map(params, |row| {
-            let id: String = row.get(0)?;
-            let law_name: String = row.get(2)?;
-            Ok((
-                id.clone(),
-                LawChunk {
-                    id,
-                    _distance: 0.0,
-                    content: row.get(1)?,
-                    law_name: law_name.clone(),
-                    category: row.get(3)?,
-                    region: row.get(4)?,
-                    publish_date: row.get(5)?,
-                    part: row.get(6).unwrap_or_default(),
-                    chapter: row.get(7).unwrap_or_default(),
-                    article_number: row.get(8)?,
-                    source_file: format!("{}.txt", law_name),
-                },
-            ))
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
+    let scanned: HashSet<String> = {
+        let mut stmt = index
+            .prepare("SELECT law_name FROM cross_ref_scanned")
+            .map_err(AppError::user_db_error)?;
+        let laws = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<HashSet<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        laws
+    };
+    let pending_laws: Vec<String> = {
+        let mut stmt = content
+            .prepare("SELECT DISTINCT law_name FROM chunks")
+            .map_err(AppError::content_db_error)?;
+        let laws = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(AppError::content_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::content_db_error)?;
+        laws.into_iter()
+            .filter(|law| !scanned.contains(law))
+            .collect()
+    };
+    if pending_laws.is_empty() {
+        return Ok(stats);
+    }
+    info!("Indexing cross references for {} laws", pending_laws.len());
 
-    let mut final_results = Vec::new();
-    for (i, id) in chunk_ids.iter().enumerate() {
-        if let Some(mut chunk) = chunk_map.get(id).cloned() {
-            chunk._distance = distances[i];
+    let mut resolver = CrossRefResolver::default();
+    let mut chunks_stmt = content
+        .prepare("SELECT id, content FROM chunks WHERE law_name = ?1")
+        .map_err(AppError::content_db_error)?;
+    for (i, law_name) in pending_laws.iter().enumerate() {
+        let chunks = chunks_stmt
+            .query_map([law_name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(AppError::content_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::content_db_error)?;
 
-            let should_keep = if chunk.category != "地方法规" {
-                true
-            } else {
-                if let Some(ref target_region) = filter_region {
-                    chunk.region.contains(target_region)
-                } else {
-                    false
+        let tx = index.transaction().map_err(AppError::user_db_error)?;
+        for (source_id, text) in chunks {
+            for (law, article, ref_text) in extract_cross_refs(&text) {
+                let target_law = law.unwrap_or_else(|| law_name.clone());
+                let target_id = resolver.resolve(&content, &target_law, &article)?;
+                if target_id.as_deref() == Some(source_id.as_str()) {
+                    continue;
                 }
-            };
+                stats.new_edges += tx
+                    .execute(
+                        "INSERT OR IGNORE INTO cross_refs
+                         (source_id, target_law, target_article, target_id, ref_text)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        rusqlite::params![source_id, target_law, article, target_id, ref_text],
+                    )
+                    .map_err(AppError::user_db_error)?;
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO cross_ref_scanned (law_name) VALUES (?1)",
+            [law_name],
+        )
+        .map_err(AppError::user_db_error)?;
+        tx.commit().map_err(AppError::user_db_error)?;
+        stats.scanned_laws += 1;
+
+        if i % 20 == 0 || i + 1 == pending_laws.len() {
+            let _ = app.emit(
+                "cross-ref-progress",
+                CrossRefProgress {
+                    processed: i + 1,
+                    total: pending_laws.len(),
+                    current_law: law_name.clone(),
+                },
+            );
+        }
+    }
 
-            if should_keep {
-                final_results.push(chunk);
+    // 有新法规加入时，之前找不到目标的引用再解析一次
+    let unresolved: Vec<(String, String, String)> = {
+        let mut stmt = index
+            .prepare(
+                "SELECT source_id, target_law, target_article FROM cross_refs WHERE target_id IS NULL",
+            )
+            .map_err(AppError::user_db_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        rows
+    };
+    let tx = index.transaction().map_err(AppError::user_db_error)?;
+    for (source_id, target_law, article) in unresolved {
+        match resolver.resolve(&content, &target_law, &article)? {
+            Some(target_id) if target_id != source_id => {
+                tx.execute(
+                    "UPDATE cross_refs SET target_id = ?4
+                     WHERE source_id = ?1 AND target_law = ?2 AND target_article = ?3",
+                    rusqlite::params![source_id, target_law, article, target_id],
+                )
+                .map_err(AppError::user_db_error)?;
+                stats.resolved_pending += 1;
             }
+            _ => stats.unresolved += 1,
         }
     }
+    tx.commit().map_err(AppError::user_db_error)?;
+    Ok(stats)
+}
 
-    Ok(final_results
-        .into_iter()
-        .take(settings.search_top_k)
-        .collect())
+// 扫描期间切换了数据目录时，结束后接着为新目录建立索引
+async fn index_cross_refs(
+    app: &AppHandle,
+    state: &AppState,
+) -> Result<CrossRefIndexStats, AppError> {
+    if CROSS_REF_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::validation("正在建立条文引用索引"));
+    }
+    let result = loop {
+        let data_dir = get_effective_data_dir(state);
+        let index_path = cross_ref_db_path(state, &data_dir);
+        let handle = app.clone();
+        let scanned_dir = data_dir.clone();
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            run_cross_ref_index(&handle, &scanned_dir, &index_path)
+        })
+        .await
+        .map_err(|e| AppError::content_db("建立条文引用索引失败").with_detail(e))
+        .and_then(|result| result);
+        if result.is_err() || get_effective_data_dir(state) == data_dir {
+            break result;
+        }
+    };
+    CROSS_REF_RUNNING.store(false, Ordering::SeqCst);
+    result
 }
 
-// ==========================================
-// 5. Tauri 命令
-// ==========================================
+// 启动、导入法规或更新数据后在后台增量建立索引，失败只记录日志
+fn spawn_cross_ref_index(app: &AppHandle) {
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = handle.state::<AppState>();
+        match index_cross_refs(&handle, &state).await {
+            Ok(stats) if stats.scanned_laws > 0 => info!(
+                "Cross references indexed: {} laws, {} new edges, {} unresolved",
+                stats.scanned_laws, stats.new_edges, stats.unresolved
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Cross reference indexing failed: {}", e),
+        }
+    });
+}
 
-// 5.1 智能体搜索命令 (Agent)
-#[tauri::command]
-async fn start_agent_search(
-    window: tauri::Window,
-    query: String,
-    event_id: String,
-    state: tauri::State<'_, AppState>,
-) -> Result<Vec<LawChunk>, String> {
-    let should_run = Arc::new(AtomicBool::new(true));
-    {
-        let mut flags = state.agent_abort_flags.lock().unwrap();
-        flags.insert(event_id.clone(), should_run.clone());
+// 手动触发一次增量索引，进度通过 cross-ref-progress 事件推送
+metered_command! {
+    #[tauri::command]
+    async fn build_cross_refs(
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<CrossRefIndexStats, AppError> {
+        index_cross_refs(&app, &state).await
     }
+}
 
-    macro_rules! check_abort {
-        () => {
-            if !should_run.load(Ordering::Relaxed) {
-                // 清理并返回中断信号
-                let mut flags = state.agent_abort_flags.lock().unwrap();
-                flags.remove(&event_id);
-                return Err("深度思考已手动停止".to_string());
-            }
-        };
+// 按引用方向查询关联条文；索引尚未建立时返回空列表
+fn load_cross_ref_chunks(
+    state: &AppState,
+    chunk_id: &str,
+    outgoing: bool,
+) -> Result<Vec<LawChunk>, AppError> {
+    let index_path = cross_ref_db_path(state, &get_effective_data_dir(state));
+    if !index_path.exists() {
+        return Ok(Vec::new());
+    }
+    let sql = if outgoing {
+        "SELECT DISTINCT target_id FROM cross_refs WHERE source_id = ?1 AND target_id IS NOT NULL"
+    } else {
+        "SELECT DISTINCT source_id FROM cross_refs WHERE target_id = ?1"
+    };
+    let ids: Vec<String> = {
+        let index = open_cross_ref_db(&index_path)?;
+        let mut stmt = index
+            .prepare(&format!("{} LIMIT {}", sql, CROSS_REF_QUERY_LIMIT))
+            .map_err(AppError::user_db_error)?;
+        let ids = stmt
+            .query_map([chunk_id], |row| row.get(0))
+            .map_err(AppError::user_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::user_db_error)?;
+        ids
+    };
+    if ids.is_empty() {
+        return Ok(Vec::new());
     }
 
-    let settings = state.settings.lock().unwrap().clone();
-    let (model, base_url, api_key, max_loops) = (
-        settings.chat_model,
-        settings.chat_base_url,
-        settings.chat_api_key,
-        settings.max_agent_loops,
+    let conn = content_db(state)?;
+    let placeholders = vec!["?"; ids.len()].join(",");
+    let sql = format!(
+        "SELECT {} FROM chunks WHERE id IN ({}) ORDER BY rowid",
+        CHUNK_COLUMNS, placeholders
     );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let chunks = stmt
+        .query_map(rusqlite::params_from_iter(&ids), law_chunk_from_row)
+        .map_err(AppError::content_db_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::content_db_error)?;
+    Ok(chunks)
+}
+
+// 本条引用的条文
+metered_command! {
+    #[tauri::command]
+    fn get_outgoing_refs(
+        chunk_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawChunk>, AppError> {
+        load_cross_ref_chunks(&state, &chunk_id, true)
+    }
+}
 
-    let mut completed_log: Vec<CompletedTask> = vec![];
+// 引用了本条的条文
+metered_command! {
+    #[tauri::command]
+    fn get_incoming_refs(
+        chunk_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<LawChunk>, AppError> {
+        load_cross_ref_chunks(&state, &chunk_id, false)
+    }
+}
 
-    // 使用 HashSet 收集 ID 去重，Vec 收集结果
-    let mut all_found_chunks: Vec<LawChunk> = vec![];
-    let mut seen_ids: HashSet<String> = HashSet::new();
+// 引用核对 (Citation check)
+// 与单条引用查询共用 parse_citations，格式识别保持一致
 
-    check_abort!();
+// 约 20 万字，足够一份完整的文书
+const CITATION_CHECK_MAX_CHARS: usize = 200_000;
 
-    window
-        .emit(
-            "agent-update",
-            AgentUpdateEvent {
-                step_type: "planning".into(),
-                todo_list: vec![],
-                completed_log: vec![],
-                current_task: None,
-                thought: Some("正在拆解法律问题...".into()),
-            },
-        )
-        .unwrap();
+// 法名模糊匹配到的完整法名（名称最短的优先）；带“中华人民共和国”前缀而库中没有时去掉前缀再试
+fn match_law_name(conn: &Connection, law_name: &str) -> Result<Option<String>, AppError> {
+    use rusqlite::OptionalExtension;
 
-    let plan_prompt = PLANNER_PROMPT.replace("{user_query}", &query);
-    println!(">>> Agent Planning...");
-    let mut todo_list: Vec<String> = match call_llm(&model, &plan_prompt, &base_url, &api_key).await
-    {
-        Ok(json) => {
-            println!(">>> LLM Raw Output: {}", json);
-            let clean = clean_json_str(&json);
-            println!(">>> Cleaned JSON: {}", clean);
-            match serde_json::from_str::<Vec<String>>(&clean) {
-                Ok(list) => {
-                    println!(">>> Parsed Task List: {:?}", list);
-                    list
-                }
-                Err(e) => {
-                    println!(">>> JSON Parse Error: {}", e);
-                    // 如果解析失败，回退到原始查询
-                    vec![query.clone()]
-                }
-            }
+    let mut names = vec![law_name];
+    if let Some(short) = law_name.strip_prefix("中华人民共和国") {
+        if !short.is_empty() {
+            names.push(short);
         }
-        Err(_) => vec![query.clone()],
-    };
+    }
+    for name in names {
+        let matched = conn
+            .query_row(
+                "SELECT law_name FROM chunks WHERE law_name LIKE ?1 ORDER BY length(law_name) LIMIT 1",
+                [format!("%{}%", name)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(AppError::content_db_error)?;
+        if matched.is_some() {
+            return Ok(matched);
+        }
+    }
+    Ok(None)
+}
 
-    let mut loop_count = 0;
-    let limit = if max_loops <= 0 { 99 } else { max_loops };
+fn find_article_exact(
+    conn: &Connection,
+    law_name: &str,
+    article_number: &str,
+) -> Result<Option<LawChunk>, AppError> {
+    use rusqlite::OptionalExtension;
 
-    while !todo_list.is_empty() && loop_count < limit {
-        check_abort!();
-        loop_count += 1;
-        let current_task = todo_list.remove(0);
-        println!(
-            ">>> [Agent] Step {}: Executing task '{}'",
-            loop_count, current_task
-        );
-        window
-            .emit(
-                "agent-update",
-                AgentUpdateEvent {
-                    step_type: "executing".into(),
-                    todo_list: todo_list.clone(),
-                    completed_log: completed_log.clone(),
-                    current_task: Some(current_task.clone()),
-                    thought: None,
-                },
-            )
-            .unwrap();
+    let sql = format!(
+        "SELECT {} FROM chunks WHERE law_name = ?1 AND article_number = ?2 LIMIT 1",
+        CHUNK_COLUMNS
+    );
+    conn.query_row(&sql, [law_name, article_number], law_chunk_from_row)
+        .optional()
+        .map_err(AppError::content_db_error)
+}
 
-        let search_res = search_law_logic(current_task.clone(), None, &state).await;
+// 逐条核对文本中的法条引用：条文存在 / 法规存在但无此条 / 法规不存在
+metered_command! {
+    #[tauri::command]
+    fn check_citations_in_text(
+        text: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<CitationCheckReport, AppError> {
+        if text.chars().count() > CITATION_CHECK_MAX_CHARS {
+            return Err(AppError::validation(format!(
+                "文本超过 {} 字，请分段核对",
+                CITATION_CHECK_MAX_CHARS
+            )));
+        }
 
-        check_abort!();
+        let conn = content_db(&state)?;
+        // 同一部法规在文中往往被多次引用，法名匹配结果按原文法名缓存
+        let mut law_names: HashMap<String, Option<String>> = HashMap::new();
+        let mut report = CitationCheckReport {
+            citations: Vec::new(),
+            found: 0,
+            article_missing: 0,
+            law_not_found: 0,
+        };
 
-        let mut result_text = String::new();
-        let mut found_count = 0;
-        let step_max_chunks = 10; 
+        for citation in parse_citations(&text) {
+            let matched_law_name = match law_names.get(&citation.law_name) {
+                Some(matched) => matched.clone(),
+                None => {
+                    let matched = match_law_name(&conn, &citation.law_name)?;
+                    law_names.insert(citation.law_name.clone(), matched.clone());
+                    matched
+                }
+            };
 
-        match search_res {
-            Ok(chunks) => {
-                for r in chunks {
-                    // 1.2 阈值过滤
-                    if r._distance < 1.2 {
-                        if found_count >= step_max_chunks {
-                            break;
-                        }
-                        found_count += 1;
-                        // 收集文本给 Agent 看
-                        result_text.push_str(&format!(
-                            "法规：《{}》{}\n内容：{}\n\n",
-                            r.law_name, r.article_number, r.content
-                        ));
-
-                        // 收集对象给前端
-                        if !seen_ids.contains(&r.id) {
-                            seen_ids.insert(r.id.clone());
-                            all_found_chunks.push(r);
+            let chunk = match &matched_law_name {
+                Some(matched) => {
+                    match find_article_exact(&conn, matched, &citation.article_number)? {
+                        Some(chunk) => Some(chunk),
+                        None => {
+                            find_article_chunk(&conn, &citation.law_name, &citation.article_number)?
                         }
                     }
                 }
+                None => None,
+            };
+            let status = match (&matched_law_name, &chunk) {
+                (_, Some(_)) => CitationCheckStatus::Found,
+                (Some(_), None) => CitationCheckStatus::ArticleMissing,
+                (None, None) => CitationCheckStatus::LawNotFound,
+            };
+            match status {
+                CitationCheckStatus::Found => report.found += 1,
+                CitationCheckStatus::ArticleMissing => report.article_missing += 1,
+                CitationCheckStatus::LawNotFound => report.law_not_found += 1,
             }
-            Err(e) => {
-                result_text = format!("搜索出错: {}", e);
-            }
-        }
-
-        if result_text.trim().is_empty() {
-            result_text = "未找到直接相关法条。".to_string();
-            println!(">>> [Agent] No results found for this task.");
-        } else {
-            println!(">>> [Agent] Found {} relevant chunks.", found_count);
-        }
-        check_abort!();
-        window
-            .emit(
-                "agent-update",
-                AgentUpdateEvent {
-                    step_type: "thinking".into(),
-                    todo_list: todo_list.clone(),
-                    completed_log: completed_log.clone(),
-                    current_task: Some(current_task.clone()),
-                    thought: Some("正在评估检索结果...".into()),
-                },
-            )
-            .unwrap();
 
-        let review_prompt = EXECUTOR_PROMPT
-            .replace("{user_query}", &query)
-            .replace("{current_task}", &current_task)
-            .replace("{search_results}", &result_text)
-            .replace(
-                "{remaining_todo_list}",
-                &serde_json::to_string(&todo_list).unwrap_or("[]".into()),
-            );
-        check_abort!();
-        match call_llm(&model, &review_prompt, &base_url, &api_key).await {
-            Ok(json) => {
-                let clean = clean_json_str(&json);
-                if let Ok(res) = serde_json::from_str::<ExecutorResponse>(&clean) {
-                    println!(">>> [Agent] Thought: {}", res.thought);
-                    println!(">>> [Agent] Updated List: {:?}", res.new_todo_list);
-                    todo_list = res.new_todo_list;
-                    completed_log.push(CompletedTask {
-                        task: current_task,
-                        thought: res.thought,
-                    });
-                } else {
-                    println!(">>> [Agent] JSON Parse Failed: {}", clean);
-                    completed_log.push(CompletedTask {
-                        task: current_task,
-                        thought: "解析思考结果失败，继续执行原计划。".into(),
-                    });
-                }
-            }
-            Err(e) => {
-                println!(">>> [Agent] LLM Reflection Error: {}", e);
-                completed_log.push(CompletedTask {
-                    task: current_task,
-                    thought: "LLM 调用失败，跳过此步分析。".into(),
-                });
-            }
+            report.citations.push(CitationCheck {
+                text: citation.text,
+                start: citation.start,
+                end: citation.end,
+                matched_law_name: chunk
+                    .as_ref()
+                    .map(|c| c.law_name.clone())
+                    .or(matched_law_name),
+                law_name: citation.law_name,
+                article_number: citation.article_number,
+                status,
+                chunk,
+            });
         }
+        Ok(report)
     }
-
-    {
-        let mut flags = state.agent_abort_flags.lock().unwrap();
-        flags.remove(&event_id);
-    }
-
-    window
-        .emit(
-            "agent-update",
-            AgentUpdateEvent {
-                step_type: "finished".into(),
-                todo_list: vec![],
-                completed_log: completed_log,
-                current_task: None,
-                thought: Some("所有任务执行完毕，正在生成最终回答...".into()),
-            },
-        )
-        .unwrap();
-    println!(
-        ">>> [Agent] Finished. Total chunks found: {}",
-        all_found_chunks.len()
-    );
-    Ok(all_found_chunks)
 }
 
-// 5.2 普通搜索命令 (Search)
-#[tauri::command]
-async fn search_law(
-    query: String,
-    filter_region: Option<String>,
-    state: tauri::State<'_, AppState>,
-) -> Result<Vec<LawChunk>, String> {
-    search_law_logic(query, filter_region, &state).await
-}
+// 术语释义 (Definitions)
+// 查找顺序：用户导入的释义 > 法规库自带的 term_definitions 表 > 条文中的定义句 > 语义检索
 
-// 5.3 其他命令 (Others)
-#[tauri::command]
-fn check_db_status(state: tauri::State<'_, AppState>) -> bool {
-    let data_dir = get_effective_data_dir(&state);
-    let lancedb_path = data_dir.join("law_db.lancedb");
-    lancedb_path.exists()
-}
+const TERM_MAX_CHARS: usize = 30;
+// 按定义句查找时最多检查的候选条文数
+const DEFINITION_SCAN_LIMIT: usize = 200;
+// 定义句缓存的法规数，超出时整体清空
+const DEFINED_TERMS_CACHE_CAPACITY: usize = 20;
 
-#[tauri::command]
-fn add_draft_material(chunk: LawChunk, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "INSERT INTO draft_materials (law_id, law_name, article_number, content) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(law_id) DO NOTHING",
-        rusqlite::params![chunk.id, chunk.law_name, chunk.article_number, chunk.content],
-    ).map_err(|e| e.to_string())?;
-    Ok(())
-}
+// “，指”之后紧跟这些字时是“指定”“指导”等动词，不是定义
+const NON_DEFINITION_ZHI: &str = "定示导出挥令明派控引责认标南针望使向称";
 
-#[tauri::command]
-fn get_draft_materials(state: tauri::State<'_, AppState>) -> Result<Vec<DraftMaterial>, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let mut stmt = conn.prepare("SELECT id, law_id, law_name, article_number, content, added_at FROM draft_materials ORDER BY added_at DESC").map_err(|e| e.to_string())?;
-    let items = stmt
-        .query_map([], |row| {
-            Ok(DraftMaterial {
-                id: row.get(0)?,
-                law_id: row.get(1)?,
-                law_name: row.get(2)?,
-                article_number: row.get(3)?,
-                content: row.get(4)?,
-                added_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(items)
+// 定义句：“本法所称XX，是指”、“XX”是指、（一）XX，是指，以及以“XX是指”开头的句子
+fn definition_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?:(?:本法|本条例|本规定|本办法|本解释|本编|本章|本节|本条|前款|前两款)中?所称的?[“「]?|[“「]|（[一二三四五六七八九十]+）|^|[。；\n])\s*([^，。；：、“”「」（）\s]{{2,20}}?)[”」]?(?:[，,]?(?:是指|系指)|[，,]指(?:[^{}]|$))",
+            NON_DEFINITION_ZHI
+        ))
+        .unwrap()
+    })
 }
 
-#[tauri::command]
-fn remove_draft_material(law_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "DELETE FROM draft_materials WHERE law_id = ?1",
-        rusqlite::params![law_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+// 单独的“，指”定义用语，排除“，指定”“，指导”等
+fn comma_zhi_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(&format!("[，,]指(?:[^{}]|$)", NON_DEFINITION_ZHI)).unwrap())
 }
 
-#[tauri::command]
-fn clear_draft_materials(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute("DELETE FROM draft_materials", [])
-        .map_err(|e| e.to_string())?;
-    Ok(())
+// 取出文本中的 (术语, 定义整句)；定义句延续到句号为止
+fn find_definitions(content: &str) -> Vec<(String, String)> {
+    definition_regex()
+        .captures_iter(content)
+        .map(|caps| {
+            let term = caps.get(1).unwrap();
+            let start = content[..term.start()]
+                .rfind(['。', '\n'])
+                .map(|i| i + content[i..].chars().next().map_or(1, char::len_utf8))
+                .unwrap_or(0);
+            let end = content[term.end()..]
+                .find('。')
+                .map(|i| term.end() + i + '。'.len_utf8())
+                .unwrap_or(content.len());
+            (
+                term.as_str().to_string(),
+                content[start..end].trim().to_string(),
+            )
+        })
+        .collect()
 }
 
-#[tauri::command]
-fn add_template(
-    name: String,
-    content: String,
-    state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute("INSERT INTO custom_templates (name, content) VALUES (?1, ?2) ON CONFLICT(name) DO UPDATE SET content = excluded.content", rusqlite::params![name, content]).map_err(|e| e.to_string())?;
-    Ok(())
+// 是否含有定义用语（用于给语义检索结果排序）
+fn has_definition_marker(content: &str) -> bool {
+    ["所称", "是指", "系指"]
+        .iter()
+        .any(|marker| content.contains(marker))
+        || comma_zhi_regex().is_match(content)
 }
 
-#[tauri::command]
-fn get_templates(state: tauri::State<'_, AppState>) -> Result<Vec<CustomTemplate>, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let mut stmt = conn
-        .prepare("SELECT id, name, content FROM custom_templates ORDER BY id DESC")
-        .map_err(|e| e.to_string())?;
-    let items = stmt
-        .query_map([], |row| {
-            Ok(CustomTemplate {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                content: row.get(2)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(items)
-}
+// 释义表中的一条：(释义, 出处法规名, 出处条号)
+type CuratedDefinition = (String, Option<String>, Option<String>);
 
-#[tauri::command]
-fn delete_template(id: i32, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "DELETE FROM custom_templates WHERE id = ?1",
-        rusqlite::params![id],
+// 释义表条目；法规库的表可能不存在，此时返回 None
+fn lookup_curated_definition(
+    conn: &Connection,
+    term: &str,
+) -> rusqlite::Result<Option<CuratedDefinition>> {
+    use rusqlite::OptionalExtension;
+
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'term_definitions')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(None);
+    }
+    conn.query_row(
+        "SELECT definition, law_name, article_number FROM term_definitions WHERE term = ?1",
+        [term],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
     )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+    .optional()
 }
 
-#[tauri::command]
-fn search_law_by_name(
-    query: String,
-    limit: usize,
-    state: tauri::State<'_, AppState>,
-) -> Result<Vec<LawNameSuggestion>, String> {
-    let data_dir = get_effective_data_dir(&state);
-    let conn = connect_sqlite(&data_dir)?;
+fn curated_definition(state: &AppState, term: &str) -> Result<Option<TermDefinition>, AppError> {
+    let user_conn = user_db(state)?;
+    let found =
+        match lookup_curated_definition(&user_conn, term).map_err(AppError::user_db_error)? {
+            Some(found) => Some(found),
+            None => {
+                let conn = content_db(state)?;
+                lookup_curated_definition(&conn, term).map_err(AppError::content_db_error)?
+            }
+        };
+    let Some((definition, law_name, article_number)) = found else {
+        return Ok(None);
+    };
+    let chunk = match (law_name, article_number) {
+        (Some(law_name), Some(article_number)) => {
+            let conn = content_db(state)?;
+            find_article_chunk(&conn, &law_name, &article_number)?
+        }
+        _ => None,
+    };
+    Ok(Some(TermDefinition {
+        term: term.to_string(),
+        definition,
+        source: DefinitionSource::Curated,
+        confidence: DefinitionConfidence::High,
+        chunk,
+    }))
+}
 
-    let sql = "SELECT DISTINCT law_name, region, category FROM full_texts WHERE law_name LIKE ? LIMIT 200";
-    let query_pattern = format!("%{}%", query);
+// 在含有该术语和定义用语的条文中找“XX是指”的定义句；法规名短的（通常是上位法）优先
+fn pattern_definition(conn: &Connection, term: &str) -> Result<Option<TermDefinition>, AppError> {
+    // GLOB 的字符集支持中文，与 comma_zhi_regex 一致地排除“，指定”等
+    let sql = format!(
+        "SELECT {} FROM chunks
+         WHERE content LIKE ?1 ESCAPE '\\'
+           AND (content LIKE '%是指%' OR content LIKE '%系指%' OR content GLOB '*，指[^{}]*')
+         ORDER BY length(law_name) LIMIT {}",
+        CHUNK_COLUMNS, NON_DEFINITION_ZHI, DEFINITION_SCAN_LIMIT
+    );
+    let mut stmt = conn.prepare(&sql).map_err(AppError::content_db_error)?;
+    let mut rows = stmt
+        .query([like_contains(term)])
+        .map_err(AppError::content_db_error)?;
+    while let Some(row) = rows.next().map_err(AppError::content_db_error)? {
+        let chunk = law_chunk_from_row(row).map_err(AppError::content_db_error)?;
+        let definition = find_definitions(&chunk.content)
+            .into_iter()
+            .find(|(found, _)| found == term);
+        if let Some((_, definition)) = definition {
+            return Ok(Some(TermDefinition {
+                term: term.to_string(),
+                definition,
+                source: DefinitionSource::Pattern,
+                confidence: DefinitionConfidence::High,
+                chunk: Some(chunk),
+            }));
+        }
+    }
+    Ok(None)
+}
 
-    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+// 语义检索兜底：含有该术语及定义用语的条文为 medium，只含术语的为 low
+async fn vector_definition(
+    state: &AppState,
+    term: &str,
+) -> Result<Option<TermDefinition>, AppError> {
+    let results = search_law_logic(format!("{}是指什么", term), None, 0, state).await?;
+    let best = results
+        .iter()
+        .find(|chunk| chunk.content.contains(term) && has_definition_marker(&chunk.content))
+        .map(|chunk| (chunk, DefinitionConfidence::Medium))
+        .or_else(|| {
+            results
+                .iter()
+                .find(|chunk| chunk.content.contains(term))
+                .map(|chunk| (chunk, DefinitionConfidence::Low))
+        });
+    Ok(best.map(|(chunk, confidence)| {
+        // 优先给出包含术语的那一句
+        let definition = chunk
+            .content
+            .split_inclusive(['。', '；'])
+            .find(|sentence| sentence.contains(term))
+            .unwrap_or(&chunk.content)
+            .trim()
+            .to_string();
+        TermDefinition {
+            term: term.to_string(),
+            definition,
+            source: DefinitionSource::Vector,
+            confidence,
+            chunk: Some(chunk.clone()),
+        }
+    }))
+}
 
-    let mut suggestions: Vec<LawNameSuggestion> = stmt
-        .query_map(rusqlite::params![query_pattern], |row| {
-            Ok(LawNameSuggestion {
-                name: row.get(0)?,
-                region: row.get(1)?,
-                category: row.get(2)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
+// 查询术语的法定含义
+metered_command! {
+    #[tauri::command]
+    async fn lookup_term_definition(
+        term: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<TermDefinition, AppError> {
+        let term = term.trim().trim_matches(['“', '”', '"']);
+        if term.is_empty() {
+            return Err(AppError::validation("术语不能为空"));
+        }
+        if term.chars().count() > TERM_MAX_CHARS {
+            return Err(AppError::validation(format!(
+                "术语不能超过 {} 个字",
+                TERM_MAX_CHARS
+            )));
+        }
 
-    fn get_category_priority(cat: &str) -> i32 {
-        match cat {
-            "法律" => 1,
-            "司法解释" => 2,
-            "行政法规" => 3,
-            "地方法规" => 4,
-            _ => 99,
+        if let Some(found) = curated_definition(&state, term)? {
+            return Ok(found);
+        }
+        let pattern = {
+            let conn = content_db(&state)?;
+            pattern_definition(&conn, term)?
+        };
+        if let Some(found) = pattern {
+            return Ok(found);
         }
+        vector_definition(&state, term)
+            .await?
+            .ok_or_else(|| AppError::not_found(format!("未找到“{}”的定义", term)))
     }
+}
 
-    suggestions.sort_by(|a, b| {
-        let p_a = get_category_priority(&a.category);
-        let p_b = get_category_priority(&b.category);
+// 扫描整部法规的定义句，同一术语只保留第一次定义；结果按法规缓存
+metered_command! {
+    #[tauri::command]
+    fn extract_defined_terms(
+        law_name: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DefinedTerm>, AppError> {
+        if let Some(terms) = state.defined_terms_cache.lock_or_recover().get(&law_name) {
+            return Ok(terms.clone());
+        }
 
-        if p_a != p_b {
-            p_a.cmp(&p_b)
-        } else {
-            a.name.len().cmp(&b.name.len())
+        let conn = content_db(&state)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, article_number, content FROM chunks WHERE law_name = ?1 ORDER BY rowid",
+            )
+            .map_err(AppError::content_db_error)?;
+        let rows = stmt
+            .query_map([&law_name], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(AppError::content_db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(AppError::content_db_error)?;
+        if rows.is_empty() {
+            return Err(AppError::not_found(format!("未找到法规: {}", law_name)));
         }
-    });
 
-    if suggestions.len() > limit {
-        suggestions.truncate(limit);
+        let mut seen = HashSet::new();
+        let mut terms = Vec::new();
+        for (chunk_id, article_number, content) in rows {
+            for (term, definition) in find_definitions(&content) {
+                if seen.insert(term.clone()) {
+                    terms.push(DefinedTerm {
+                        term,
+                        definition,
+                        article_number: article_number.clone(),
+                        chunk_id: chunk_id.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut cache = state.defined_terms_cache.lock_or_recover();
+        if cache.len() >= DEFINED_TERMS_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(law_name, terms.clone());
+        Ok(terms)
     }
+}
 
-    Ok(suggestions)
+// 导入释义文件（JSON 数组：term、definition，可选 law_name、article_number），同名术语覆盖
+metered_command! {
+    #[tauri::command]
+    fn import_term_definitions(
+        path: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<usize, AppError> {
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::validation("无法读取导入文件").with_detail(e))?;
+        let entries: Vec<TermDefinitionEntry> = serde_json::from_str(&content)
+            .map_err(|e| AppError::validation("导入文件格式错误").with_detail(e))?;
+
+        let conn = user_db(&state)?;
+        retry_on_busy(|| {
+            let tx = conn.unchecked_transaction()?;
+            let mut imported = 0;
+            for entry in &entries {
+                let term = entry.term.trim();
+                let definition = entry.definition.trim();
+                if term.is_empty() || definition.is_empty() {
+                    continue;
+                }
+                tx.execute(
+                    "INSERT INTO term_definitions (term, definition, law_name, article_number)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(term) DO UPDATE SET definition = excluded.definition,
+                         law_name = excluded.law_name, article_number = excluded.article_number,
+                         updated_at = CURRENT_TIMESTAMP",
+                    rusqlite::params![term, definition, entry.law_name, entry.article_number],
+                )?;
+                imported += 1;
+            }
+            tx.commit()?;
+            Ok(imported)
+        })
+    }
 }
 
-#[tauri::command]
-fn get_article_snippet(
-    law_name_query: Option<String>,
-    article_number: String,
-    current_law_name: String,
-    state: tauri::State<'_, AppState>,
-) -> Result<String, String> {
-    let data_dir = get_effective_data_dir(&state);
-    let conn = connect_sqlite(&data_dir)?;
+// 检索评测 (Benchmark)
+// 用固定的查询集比较不同嵌入模型的检索效果，只在本地运行，不上传任何数据
 
-    let target_law = match law_name_query {
-        Some(name) => name,
-        None => current_law_name,
-    };
+// 内置用例：(查询, 法规名, 条号)，条号以现行文本为准
+const BUILTIN_BENCHMARK_CASES: &[(&str, &str, &str)] = &[
+    ("夫妻感情破裂起诉离婚", "民法典", "第一千零七十九条"),
+    ("协议离婚需要什么条件", "民法典", "第一千零七十六条"),
+    ("离婚后孩子归谁抚养", "民法典", "第一千零八十四条"),
+    ("婚后买的房子算不算共同财产", "民法典", "第一千零六十二条"),
+    ("父母去世后遗产怎么分", "民法典", "第一千一百二十七条"),
+    ("自己手写的遗嘱有没有效", "民法典", "第一千一百三十四条"),
+    ("欠钱不还过了几年就不能起诉", "民法典", "第一百八十八条"),
+    ("合同无效的情形", "民法典", "第一百四十三条"),
+    ("对方不履行合同要承担什么责任", "民法典", "第五百七十七条"),
+    ("约定的违约金过高可以调整吗", "民法典", "第五百八十五条"),
+    ("借款合同的定义", "民法典", "第六百六十七条"),
+    ("民间借贷利息太高", "民法典", "第六百八十条"),
+    ("租房合同是什么", "民法典", "第七百零三条"),
+    ("因为过错损害他人要赔偿", "民法典", "第一千一百六十五条"),
+    ("被打伤了能要求赔偿哪些费用", "民法典", "第一千一百七十九条"),
+    ("公司不签劳动合同要赔双倍工资", "劳动合同法", "第八十二条"),
+    ("被辞退的经济补偿怎么算", "劳动合同法", "第四十七条"),
+    ("员工严重违纪公司可以直接开除", "劳动合同法", "第三十九条"),
+    ("试用期最长多久", "劳动合同法", "第十九条"),
+    ("什么情况下签无固定期限劳动合同", "劳动合同法", "第十四条"),
+    ("加班费怎么计算", "劳动法", "第四十四条"),
+    ("盗窃罪怎么判", "刑法", "第二百六十四条"),
+    ("诈骗罪的量刑", "刑法", "第二百六十六条"),
+    ("醉酒驾驶机动车", "刑法", "第一百三十三条之一"),
+    ("自首可以从轻处罚吗", "刑法", "第六十七条"),
+    ("未成年人犯罪负刑事责任的年龄", "刑法", "第十七条"),
+    ("故意伤害他人身体", "刑法", "第二百三十四条"),
+    ("贪污罪的处罚标准", "刑法", "第三百八十三条"),
+    ("交通事故责任怎么赔偿", "道路交通安全法", "第七十六条"),
+    ("商家欺诈消费者退一赔三", "消费者权益保护法", "第五十五条"),
+];
 
-    let sql = "SELECT content FROM chunks WHERE law_name LIKE ? AND article_number = ? LIMIT 1";
-    let law_pattern = format!("%{}%", target_law);
+// 用户用例文件的条数上限
+const BENCHMARK_MAX_CASES: usize = 500;
 
-    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-    let mut rows = stmt
-        .query(rusqlite::params![law_pattern, article_number])
-        .map_err(|e| e.to_string())?;
+fn builtin_benchmark_cases() -> Vec<BenchmarkCase> {
+    BUILTIN_BENCHMARK_CASES
+        .iter()
+        .map(|(query, law_name, article_number)| BenchmarkCase {
+            query: query.to_string(),
+            law_name: law_name.to_string(),
+            article_number: article_number.to_string(),
+        })
+        .collect()
+}
 
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        Ok(row.get(0).map_err(|e| e.to_string())?)
-    } else {
-        Ok(format!("未找到《{}》的{}", target_law, article_number))
+fn load_benchmark_cases(path: &str) -> Result<Vec<BenchmarkCase>, AppError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| AppError::validation("无法读取评测用例文件").with_detail(e.to_string()))?;
+    let cases: Vec<BenchmarkCase> = serde_json::from_str(&content).map_err(|e| {
+        AppError::validation("评测用例格式错误，应为包含 query、law_name、article_number 的数组")
+            .with_detail(e.to_string())
+    })?;
+    if cases.is_empty() {
+        return Err(AppError::validation("评测用例文件为空"));
     }
+    if cases.len() > BENCHMARK_MAX_CASES {
+        return Err(AppError::validation(format!(
+            "评测用例过多（{} 条），上限为 {} 条",
+            cases.len(),
+            BENCHMARK_MAX_CASES
+        )));
+    }
+    Ok(cases)
 }
 
-#[tauri::command]
-async fn check_ai_connection(
-    base_url: String,
-    api_key: String,
-    model: String,
-) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/models", base_url.trim_end_matches('/'));
+// 法名比较前去掉书名号、空白、“中华人民共和国”前缀和末尾的版本说明，如“（2018修正）”
+fn normalize_benchmark_law_name(name: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"[（(][^（）()]*[）)]$").unwrap());
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '《' && *c != '》')
+        .collect();
+    let name = name.strip_prefix("中华人民共和国").unwrap_or(&name);
+    re.replace(name, "").to_string()
+}
 
-    let res = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .send()
-        .await
-        .map_err(|e| format!("连接失败: 网络请求错误 ({})", e))?;
+fn benchmark_case_matches(case: &BenchmarkCase, chunk: &LawChunk) -> bool {
+    normalize_benchmark_law_name(&case.law_name) == normalize_benchmark_law_name(&chunk.law_name)
+        && normalize_article_digits(case.article_number.trim())
+            == normalize_article_digits(chunk.article_number.trim())
+}
 
-    if !res.status().is_success() {
-        return Err(format!("连接失败: 服务器返回状态码 {}", res.status()));
+fn benchmark_markdown(report: &RetrievalBenchmarkReport) -> String {
+    let mut md = format!(
+        "# 检索评测\n\n- 时间：{}\n- 嵌入模型：{} / {}\n- 返回条数：{}\n- 用例数：{}\n- 耗时：{:.1} 秒\n\n\
+         | 指标 | 数值 |\n| --- | --- |\n| hit@5 | {:.1}% |\n| hit@10 | {:.1}% |\n| MRR | {:.3} |\n\n\
+         | 查询 | 期望条文 | 名次 |\n| --- | --- | --- |\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        report.embedding_provider,
+        report.embedding_model,
+        report.top_k,
+        report.total,
+        report.elapsed_ms as f64 / 1000.0,
+        report.hit_at_5 * 100.0,
+        report.hit_at_10 * 100.0,
+        report.mrr,
+    );
+    for case in &report.cases {
+        let rank = match (&case.error, case.rank) {
+            (Some(error), _) => format!("出错：{}", error),
+            (None, Some(rank)) => rank.to_string(),
+            (None, None) => "未命中".to_string(),
+        };
+        md.push_str(&format!(
+            "| {} | {} | {} |\n",
+            case.query, case.expected, rank
+        ));
     }
+    md
+}
 
-    let json: serde_json::Value = res.json().await.map_err(|e| format!("解析失败: {}", e))?;
+// 逐条检索并统计 hit@5 / hit@10 / MRR；返回条数受 search_top_k 限制，小于 10 时 hit@10 偏低
+metered_command! {
+    #[tauri::command]
+    async fn run_retrieval_benchmark(
+        queries_path: Option<String>,
+        app: AppHandle,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<RetrievalBenchmarkReport, AppError> {
+        let cases = match queries_path.as_deref().map(str::trim) {
+            Some(path) if !path.is_empty() => load_benchmark_cases(path)?,
+            _ => builtin_benchmark_cases(),
+        };
+        let settings = snapshot_settings(&state);
+        let started = Instant::now();
+        let total = cases.len();
+        info!("Running retrieval benchmark with {} cases", total);
 
-    if let Some(data) = json.get("data").and_then(|d| d.as_array()) {
-        let model_exists = data
+        let mut results = Vec::with_capacity(total);
+        for (i, case) in cases.iter().enumerate() {
+            let _ = app.emit(
+                "retrieval-benchmark-progress",
+                BenchmarkProgress {
+                    processed: i,
+                    total,
+                    current_query: case.query.clone(),
+                },
+            );
+            let query = if settings.enable_query_rewrite_rules {
+                rewrite_query(&state, &case.query).unwrap_or_else(|| case.query.clone())
+            } else {
+                case.query.clone()
+            };
+            let outcome =
+                search_law_with_fallback(query, None, settings.search_preview_chars, &state).await;
+            let expected = format!("《{}》{}", case.law_name, case.article_number);
+            let result = match outcome {
+                // 降级为关键词检索时的结果与嵌入模型无关，评测没有意义
+                Ok(SearchOutcome {
+                    degraded: Some(reason),
+                    ..
+                }) => {
+                    return Err(AppError::network("嵌入服务不可用，无法评测").with_detail(reason));
+                }
+                Ok(SearchOutcome { results: hits, .. }) => BenchmarkCaseResult {
+                    query: case.query.clone(),
+                    expected,
+                    rank: hits
+                        .iter()
+                        .position(|chunk| benchmark_case_matches(case, chunk))
+                        .map(|pos| pos + 1),
+                    error: None,
+                },
+                Err(e) => BenchmarkCaseResult {
+                    query: case.query.clone(),
+                    expected,
+                    rank: None,
+                    error: Some(e.to_string()),
+                },
+            };
+            results.push(result);
+        }
+        let _ = app.emit(
+            "retrieval-benchmark-progress",
+            BenchmarkProgress {
+                processed: total,
+                total,
+                current_query: String::new(),
+            },
+        );
+
+        let hits_within = |k: usize| {
+            results
+                .iter()
+                .filter(|r| r.rank.is_some_and(|rank| rank <= k))
+                .count() as f32
+                / total as f32
+        };
+        let mrr = results
             .iter()
-            .any(|m| m.get("id").and_then(|id| id.as_str()) == Some(&model));
+            .filter_map(|r| r.rank)
+            .map(|rank| 1.0 / rank as f32)
+            .sum::<f32>()
+            / total as f32;
+        let mut report = RetrievalBenchmarkReport {
+            embedding_provider: settings.embedding_provider.clone(),
+            embedding_model: settings.embedding_model.clone(),
+            top_k: settings.search_top_k,
+            total,
+            hit_at_5: hits_within(5),
+            hit_at_10: hits_within(10),
+            mrr,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            cases: results,
+            report_path: None,
+        };
 
-        if model_exists {
-            Ok(format!("连接成功！发现模型: {}", model))
-        } else {
-            Ok(format!(
-                "连接通畅，但在列表中未找到模型 '{}' (可能仍可用)",
-                model
-            ))
+        let path = data_path_base(&state).join(format!(
+            "retrieval_benchmark_{}.md",
+            chrono::Local::now().format("%Y%m%d_%H%M%S")
+        ));
+        match fs::write(&path, benchmark_markdown(&report)) {
+            Ok(()) => report.report_path = Some(path.to_string_lossy().to_string()),
+            Err(e) => warn!("Failed to write benchmark summary {:?}: {}", path, e),
         }
-    } else {
-        Ok("连接成功！(未能验证模型名称)".to_string())
+        info!(
+            "Retrieval benchmark done: hit@5={:.3} hit@10={:.3} mrr={:.3}",
+            report.hit_at_5, report.hit_at_10, report.mrr
+        );
+        Ok(report)
     }
 }
 
-#[tauri::command]
-fn get_full_text(source_file: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
-    let data_dir = get_effective_data_dir(&state);
-    let conn = connect_sqlite(&data_dir)?;
-    let raw_name = source_file.trim_end_matches(".txt");
+// 条文对比 (Diff)
+// 逐字比较：中文没有词边界，按词切分反而会把整句标成改动
 
-    let mut stmt = conn
-        .prepare("SELECT full_text FROM full_texts WHERE law_name = ? LIMIT 1")
-        .map_err(|e| e.to_string())?;
+// 单侧超过该字数时不再对比，避免长文本拖慢界面
+const DIFF_MAX_CHARS: usize = 20_000;
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
 
-    let mut rows = stmt
-        .query(rusqlite::params![raw_name])
-        .map_err(|e| e.to_string())?;
+fn compute_diff(left: &str, right: &str) -> Result<Vec<DiffHunk>, AppError> {
+    use similar::{Algorithm, ChangeTag, TextDiff};
 
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        return Ok(row.get(0).map_err(|e| e.to_string())?);
+    for (side, text) in [("左侧", left), ("右侧", right)] {
+        let len = text.chars().count();
+        if len > DIFF_MAX_CHARS {
+            return Err(AppError::validation(format!(
+                "{}文本共 {} 字，超过对比上限 {} 字，请只选取需要对比的条文",
+                side, len, DIFF_MAX_CHARS
+            )));
+        }
     }
 
-    let fuzzy_pattern = format!("%{}", raw_name);
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Myers)
+        .timeout(DIFF_TIMEOUT)
+        .diff_chars(left, right);
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let op = match change.tag() {
+            ChangeTag::Equal => DiffOp::Equal,
+            ChangeTag::Insert => DiffOp::Insert,
+            ChangeTag::Delete => DiffOp::Delete,
+        };
+        match hunks.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(change.value()),
+            _ => hunks.push(DiffHunk {
+                op,
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    Ok(hunks)
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT full_text FROM full_texts WHERE law_name LIKE ? ORDER BY length(law_name) ASC LIMIT 1"
-    ).map_err(|e| e.to_string())?;
+// 对比最长可能耗时 DIFF_TIMEOUT，放到阻塞线程池中执行，不占用异步运行时
+async fn compute_diff_blocking(left: String, right: String) -> Result<Vec<DiffHunk>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || compute_diff(&left, &right))
+        .await
+        .map_err(|e| AppError::validation("条文对比失败").with_detail(e))?
+}
 
-    let mut rows = stmt
-        .query(rusqlite::params![fuzzy_pattern])
-        .map_err(|e| e.to_string())?;
+metered_command! {
+    #[tauri::command]
+    async fn diff_texts(left: String, right: String) -> Result<Vec<DiffHunk>, AppError> {
+        compute_diff_blocking(left, right).await
+    }
+}
 
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        return Ok(row.get(0).map_err(|e| e.to_string())?);
+// 对比两条条文（如新旧版本）的完整内容
+metered_command! {
+    #[tauri::command]
+    async fn diff_chunks(
+        left_id: String,
+        right_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<Vec<DiffHunk>, AppError> {
+        let (left, right) = {
+            let conn = content_db(&state)?;
+            (
+                load_chunk_content(&conn, &left_id)?,
+                load_chunk_content(&conn, &right_id)?,
+            )
+        };
+        compute_diff_blocking(left, right).await
     }
+}
 
-    let loose_pattern = format!("%{}%", raw_name);
-    let mut stmt = conn.prepare(
-        "SELECT full_text FROM full_texts WHERE law_name LIKE ? ORDER BY length(law_name) ASC LIMIT 1"
-    ).map_err(|e| e.to_string())?;
+// 5.4 本地 HTTP API (Local API)
+// 供其他本机工具调用检索，各接口直接复用命令背后的逻辑函数与结构体
 
-    let mut rows = stmt
-        .query(rusqlite::params![loose_pattern])
-        .map_err(|e| e.to_string())?;
+#[derive(Clone)]
+struct ApiContext {
+    app: AppHandle,
+    token: Arc<str>,
+}
+
+#[derive(Deserialize)]
+struct ApiSearchParams {
+    q: String,
+    region: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiFavoritesParams {
+    folder_id: Option<i32>,
+    tag: Option<String>,
+}
+
+// 访问令牌：操作系统密码学安全随机数生成的 32 字节，十六进制编码
+fn generate_api_token() -> Result<String, AppError> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| AppError::validation("无法生成 API 访问令牌").with_detail(e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
 
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        return Ok(row.get(0).map_err(|e| e.to_string())?);
+// 取出访问令牌，尚未生成时生成并写入设置文件
+fn ensure_api_token(state: &AppState) -> Result<String, AppError> {
+    let mut guard = settings_write_guard(state);
+    if !guard.api_server_token.is_empty() {
+        return Ok(guard.api_server_token.clone());
     }
+    let mut updated = guard.clone();
+    updated.api_server_token = generate_api_token()?;
+    write_settings_file(&state.settings_path, &updated)
+        .map_err(|e| AppError::validation("无法保存 API 访问令牌").with_detail(e))?;
+    *guard = updated;
+    Ok(guard.api_server_token.clone())
+}
 
-    Err(format!("未找到法律文件：{}", raw_name))
+// 按常数时间比较，响应耗时不会泄露令牌前缀是否正确
+fn api_token_matches(given: &str, expected: &str) -> bool {
+    use subtle::ConstantTimeEq;
+
+    !expected.is_empty() && bool::from(given.as_bytes().ct_eq(expected.as_bytes()))
 }
 
-#[tauri::command]
-async fn chat_stream(
-    app: AppHandle,
-    query: String,
-    context_chunks: Vec<String>,
-    mode: String,
-    event_id: String,
-    state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let settings = state.settings.lock().unwrap().clone();
+async fn require_api_token(
+    axum::extract::State(ctx): axum::extract::State<ApiContext>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
 
-    // 深度模式下，允许更多的上下文进入（例如 Top 10），普通模式 Top 5
-    let limit = if mode == "deep" || mode == "draft" {
-        settings.chat_top_k * 2
-    } else {
-        settings.chat_top_k
-    };
+    let authorized = request
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| api_token_matches(token.trim(), &ctx.token));
+    if !authorized {
+        return (
+            axum::http::StatusCode::UNAUTHORIZED,
+            axum::Json(AppError::validation("缺少或错误的访问令牌")),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
 
-    let selected_chunks = if context_chunks.len() > limit {
-        &context_chunks[..limit]
-    } else {
-        &context_chunks[..]
-    };
+async fn api_search(
+    axum::extract::State(ctx): axum::extract::State<ApiContext>,
+    axum::extract::Query(params): axum::extract::Query<ApiSearchParams>,
+) -> Result<axum::Json<Vec<LawChunk>>, AppError> {
+    if params.q.trim().is_empty() {
+        return Err(AppError::validation("缺少查询参数 q"));
+    }
+    let state = ctx.app.state::<AppState>();
+    let region = params.region.filter(|r| !r.trim().is_empty());
+    let results = search_law_logic(params.q, region, 0, &state).await?;
+    Ok(axum::Json(results))
+}
 
-    let context_str = selected_chunks.join("\n\n");
+async fn api_article(
+    axum::extract::State(ctx): axum::extract::State<ApiContext>,
+    axum::extract::Path((law_name, article_number)): axum::extract::Path<(String, String)>,
+) -> Result<axum::Json<LawChunk>, AppError> {
+    let state = ctx.app.state::<AppState>();
+    let conn = content_db(&state)?;
+    find_article_chunk(&conn, &law_name, &article_number)?
+        .map(axum::Json)
+        .ok_or_else(|| AppError::not_found(format!("未找到《{}》{}", law_name, article_number)))
+}
 
-    // === 分析 Prompts ===
+async fn api_favorites(
+    axum::extract::State(ctx): axum::extract::State<ApiContext>,
+    axum::extract::Query(params): axum::extract::Query<ApiFavoritesParams>,
+) -> Result<axum::Json<FavoritesPage>, AppError> {
+    let state = ctx.app.state::<AppState>();
+    load_favorites_page(&state, params.tag, params.folder_id, None, None, None).map(axum::Json)
+}
 
-    // 1. 深度思考模式 Prompt：专业法律意见书风格
-    let deep_prompt = format!(
-        r#"你是一位资深的中国法律顾问。用户提出了一个具体的法律问题，你已经通过检索工具找到了相关的法律条文。
-你的任务是根据这些法条，为用户撰写一份专业的《法律检索分析报告》。
+fn build_api_router(ctx: ApiContext, cors_origins: &[String]) -> axum::Router {
+    use axum::routing::get;
 
-要求：
-1. 每个结论必须引用具体法条（格式：《XX法》第X条）
-2. 如果检索结果不足，明确说明缺少的部分
-3. 专业但通俗，避免过度术语堆砌
-4. 不编造法条，不做绝对承诺
-5. 不需要寒暄
+    let router = axum::Router::new()
+        .route("/search", get(api_search))
+        .route("/law/:name/article/:no", get(api_article))
+        .route("/favorites", get(api_favorites))
+        .route_layer(axum::middleware::from_fn_with_state(
+            ctx.clone(),
+            require_api_token,
+        ))
+        .with_state(ctx);
 
-输出结构：
+    // 默认不返回 CORS 头，浏览器页面无法跨域调用；只放行设置中明确列出的来源
+    let origins: Vec<axum::http::HeaderValue> = cors_origins
+        .iter()
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| axum::http::HeaderValue::from_str(origin).ok())
+        .collect();
+    if origins.is_empty() {
+        return router;
+    }
+    router.layer(
+        tower_http::cors::CorsLayer::new()
+            .allow_origin(tower_http::cors::AllowOrigin::list(origins))
+            .allow_methods([axum::http::Method::GET])
+            .allow_headers([axum::http::header::AUTHORIZATION]),
+    )
+}
 
-一、核心结论
-用一句话回答用户的核心问题。
+fn api_server_status(state: &AppState) -> ApiServerStatus {
+    let port = state.api_server.lock_or_recover().as_ref().map(|h| h.port);
+    ApiServerStatus {
+        running: port.is_some(),
+        port,
+        token: snapshot_settings(state).api_server_token,
+    }
+}
 
-二、法律依据分析
-针对争议点逐条分析：
-- 法条依据：《XX法》第X条规定...
-- 适用分析：对用户情况的具体解读
-- 注意事项：适用条件或例外情况
+// 停止正在运行的服务，等待端口释放后返回
+async fn shutdown_api_server(state: &AppState) {
+    let handle = state.api_server.lock_or_recover().take();
+    if let Some(handle) = handle {
+        let _ = handle.shutdown.send(());
+        let _ = handle.task.await;
+        info!("API server on port {} stopped", handle.port);
+    }
+}
 
-三、实操建议
-1. 证据准备：需要保留哪些材料
-2. 维权路径：协商/仲裁/诉讼的具体步骤
-3. 时间节点：诉讼时效、关键期限
+// 按当前设置（重新）启动服务
+async fn launch_api_server(app: &AppHandle) -> Result<ApiServerStatus, AppError> {
+    let state = app.state::<AppState>();
+    shutdown_api_server(&state).await;
 
----
-【检索到的法条上下文】：
-{}
-"#,
-        context_str
+    let token = ensure_api_token(&state)?;
+    let settings = snapshot_settings(&state);
+    let port = settings.api_server_port;
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| AppError::network(format!("无法监听端口 {}", port)).with_detail(e))?;
+    let router = build_api_router(
+        ApiContext {
+            app: app.clone(),
+            token: token.as_str().into(),
+        },
+        &settings.api_server_cors_origins,
     );
 
-    // 2. 普通模式 Prompt
-    let simple_prompt = format!(
-        r#"你是一个法条检索助手。请基于以下检索结果，先简要评估其与用户问题的相关性。然后再给出回答。不需要寒暄。
+    let (shutdown, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let task = tauri::async_runtime::spawn(async move {
+        let server = axum::serve(listener, router).with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+        });
+        if let Err(e) = server.await {
+            error!("API server error: {}", e);
+        }
+    });
+    *state.api_server.lock_or_recover() = Some(ApiServerHandle {
+        port,
+        shutdown,
+        task,
+    });
+    info!("API server listening on {}", addr);
+    Ok(api_server_status(&state))
+}
 
-【检索到的法条】：
-{}
+// 相关设置变化后在后台重启或停止服务
+fn restart_api_server(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        if snapshot_settings(&state).api_server_enabled {
+            if let Err(e) = launch_api_server(&app).await {
+                error!("Failed to start API server: {}", e);
+            }
+        } else {
+            shutdown_api_server(&state).await;
+        }
+    });
+}
 
-要求：
-1. 如果法条和问题高度相关，请直接根据法条内容回答用户问题，答案简洁明了，需要引用具体相关法条。不相关法条请予以忽略。
-输出示例：
-```
-关于（用户问题）的问题，（基于xx法xx条，此行为可能构成……）
-```
-2. 如果法条不相关，请直接告知用户“未找到直接相关依据”，并建议更换搜索词。搜索词应基于法条相似度Embedding的方向设计。
-输出示例：
-```
-查找到的法条相关度较低，根据您的问题，建议以下搜索词重新搜索：（数个搜索词）
-```
-3. 如果法条相关度完全不足，请告知用户检查向量模型和数据库是否匹配。
-"#,
-        context_str
-    );
+metered_command! {
+    #[tauri::command]
+    async fn start_api_server(app: AppHandle) -> Result<ApiServerStatus, AppError> {
+        launch_api_server(&app).await
+    }
+}
 
-    let draft_prompt = format!(
-        r#"你是一位专业的法律文书起草专家。用户提供了一些参考法条和具体的写作要求。
-你的任务是根据这些素材，起草一份高质量的法律文书或段落。
+metered_command! {
+    #[tauri::command]
+    async fn stop_api_server(state: tauri::State<'_, AppState>) -> Result<ApiServerStatus, AppError> {
+        shutdown_api_server(&state).await;
+        Ok(api_server_status(&state))
+    }
+}
 
-【参考法条/素材】：
-{}
+metered_command! {
+    #[tauri::command]
+    fn get_api_server_status(state: tauri::State<'_, AppState>) -> ApiServerStatus {
+        api_server_status(&state)
+    }
+}
 
-【要求】：
-1. 格式规范，用词严谨。
-2. 必须充分利用提供的素材中的法律依据。
-3. 如果用户提供了模版，请严格遵循模版的结构。
-4. 直接输出文书正文。
-5. 不要任何寒暄。
-6. 不要使用超过提供法条之外的法条文本。
-"#,
-        context_str
-    );
+// 5.5 深度链接 (lawvault://)
+// lawvault://law/<法规名>[/<条号>] 打开法规或条文，lawvault://search?q=<关键词> 发起搜索
 
-    // 根据 mode 选择 prompt
-    let system_prompt = match mode.as_str() {
-        "deep" => deep_prompt,
-        "draft" => draft_prompt,
-        _ => simple_prompt,
-    };
+const DEEP_LINK_SCHEME: &str = "lawvault";
 
-    let user_prompt = if mode == "draft" {
-        format!("【写作指令】：{}\n\n请开始起草：", query)
-    } else {
-        format!("用户问题：{}\n\n请开始分析：", query)
-    };
-    let event_id_for_task = event_id.clone();
+enum DeepLink {
+    Law {
+        name: String,
+        article: Option<String>,
+    },
+    Search {
+        query: String,
+    },
+}
 
-    let chat_task = tauri::async_runtime::spawn(async move {
-        let client = reqwest::Client::new();
-        let url = format!(
-            "{}/chat/completions",
-            settings.chat_base_url.trim_end_matches('/')
-        );
+fn parse_deep_link(url: &tauri::Url) -> Result<DeepLink, String> {
+    if url.scheme() != DEEP_LINK_SCHEME {
+        return Err(format!("不支持的链接协议: {}", url.scheme()));
+    }
+    let segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .filter(|s| !s.is_empty())
+                // 路径段里的中文是百分号编码的，按 UTF-8 还原
+                .map(|s| {
+                    percent_encoding::percent_decode_str(s)
+                        .decode_utf8_lossy()
+                        .into_owned()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    match url.host_str() {
+        Some("law") => {
+            let mut segments = segments.into_iter();
+            let name = segments
+                .next()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .ok_or("链接中缺少法规名称")?;
+            let article = segments
+                .next()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+            Ok(DeepLink::Law { name, article })
+        }
+        Some("search") => {
+            let query = url
+                .query_pairs()
+                .find(|(key, _)| key == "q")
+                .map(|(_, value)| value.trim().to_string())
+                .filter(|q| !q.is_empty())
+                .ok_or("链接中缺少搜索关键词 q")?;
+            Ok(DeepLink::Search { query })
+        }
+        _ => Err("无法识别的链接，应为 lawvault://law/... 或 lawvault://search?q=...".to_string()),
+    }
+}
 
-        let response = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", settings.chat_api_key))
-            .json(&serde_json::json!({
-                "model": settings.chat_model,
-                "messages": [
-                    { "role": "system", "content": system_prompt },
-                    { "role": "user", "content": user_prompt }
-                ],
-                "stream": true,
-                "temperature": if mode == "deep" { 0.4 } else { 0.3 }
-            }))
-            .send()
-            .await;
+// 按法规名模糊匹配（与全文查看相同的规则）找到具体法规或条文
+fn resolve_deep_link(state: &AppState, link: DeepLink) -> Result<NavigateTarget, String> {
+    let (name, article) = match link {
+        DeepLink::Search { query } => return Ok(NavigateTarget::Search { query }),
+        DeepLink::Law { name, article } => (name, article),
+    };
+    let conn = content_db(state)?;
+    let law_name =
+        resolve_law_name(&conn, &name)?.ok_or_else(|| format!("未找到法规：{}", name))?;
+    match article {
+        Some(article) => find_article_chunk(&conn, &law_name, &article)?
+            .map(|chunk| NavigateTarget::Article { chunk })
+            .ok_or_else(|| format!("未找到《{}》{}", law_name, article)),
+        None => {
+            let (region, category) = conn
+                .query_row(
+                    "SELECT region, category FROM chunks WHERE law_name = ?1 LIMIT 1",
+                    [&law_name],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(AppError::content_db_error)?;
+            Ok(NavigateTarget::Law {
+                law: LawNameSuggestion {
+                    name: law_name,
+                    region,
+                    category,
+                    name_en: None,
+                    source_name: None,
+                },
+            })
+        }
+    }
+}
 
-        match response {
-            Ok(res) => {
-                let mut stream = res.bytes_stream();
-                while let Some(item) = stream.next().await {
-                    match item {
-                        Ok(bytes) => {
-                            let text = String::from_utf8_lossy(&bytes);
-                            for line in text.lines() {
-                                if line.starts_with("data: ") {
-                                    let json_str = line.trim_start_matches("data: ").trim();
-                                    if json_str == "[DONE]" {
-                                        break;
-                                    }
-                                    if let Ok(json) =
-                                        serde_json::from_str::<serde_json::Value>(json_str)
-                                    {
-                                        if let Some(content) =
-                                            json["choices"][0]["delta"]["content"].as_str()
-                                        {
-                                            let _ = app.emit(&event_id_for_task, content);
-                                        } else if let Some(content) =
-                                            json["message"]["content"].as_str()
-                                        {
-                                            let _ = app.emit(&event_id_for_task, content);
-                                        }
-                                    }
-                                }
-                            }
-                            let _ = app.emit(&event_id_for_task, "[DONE]");
-                        }
-                        Err(e) => {
-                            let _ = app.emit(&event_id_for_task, format!("[Error: {}]", e));
-                        }
-                    }
-                }
+fn resolve_deep_link_url(
+    state: &AppState,
+    url: &tauri::Url,
+) -> Result<NavigateTarget, DeepLinkError> {
+    parse_deep_link(url)
+        .and_then(|link| resolve_deep_link(state, link))
+        .map_err(|message| {
+            warn!("Deep link {} not resolved: {}", url, message);
+            DeepLinkError {
+                url: url.to_string(),
+                message,
+            }
+        })
+}
+
+// 程序运行中收到链接：解析后通知前端跳转并把窗口调到前台
+fn handle_deep_links(app: &AppHandle, urls: &[tauri::Url]) {
+    let state = app.state::<AppState>();
+    for url in urls {
+        info!("Deep link received: {}", url);
+        match resolve_deep_link_url(&state, url) {
+            Ok(target) => {
+                let _ = app.emit("navigate", target);
             }
             Err(e) => {
-                let _ = app.emit(&event_id_for_task, format!("[Error: {}]", e));
+                let _ = app.emit("deep-link-error", e);
             }
         }
-    });
+    }
+    focus_main_window(app);
+}
 
-    // 3. 将任务句柄存入 Map (使用原始的 event_id)
-    {
-        let mut tasks = state.chat_tasks.lock().unwrap();
-        tasks.insert(event_id, chat_task);
+fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+// 通过链接启动时事件发出时前端还没有监听，先暂存，由前端初始化时取走
+metered_command! {
+    #[tauri::command]
+    fn take_pending_navigation(state: tauri::State<'_, AppState>) -> Option<NavigateTarget> {
+        state.pending_navigation.lock_or_recover().take()
+    }
+}
+
+// 条文的规范链接，供分享使用
+metered_command! {
+    #[tauri::command]
+    fn make_deep_link(
+        chunk_id: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<String, AppError> {
+        use rusqlite::OptionalExtension;
+
+        let conn = content_db(&state)?;
+        let (law_name, article_number): (String, String) = conn
+            .query_row(
+                "SELECT law_name, article_number FROM chunks WHERE id = ?1",
+                [&chunk_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(AppError::content_db_error)?
+            .ok_or_else(|| AppError::not_found(format!("未找到条文: {}", chunk_id)))?;
+
+        let mut url = tauri::Url::parse(&format!("{}://law", DEEP_LINK_SCHEME))
+            .map_err(|e| AppError::validation("链接生成失败").with_detail(e))?;
+        url.path_segments_mut()
+            .map_err(|_| AppError::validation("链接生成失败"))?
+            .push(&law_name)
+            .push(&article_number);
+        Ok(url.to_string())
     }
+}
 
-    Ok(())
+// 5.6 快速查询 (托盘 + 全局快捷键)
+// 在其他程序里按快捷键弹出置顶小窗，输入引用或关键词直接查看条文
+
+const QUICK_SEARCH_WINDOW: &str = "quick-search";
+const QUICK_LOOKUP_EXCERPT_CHARS: usize = 200;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QuickLookupItem {
+    chunk_id: String,
+    citation: String,
+    law_name: String,
+    article_number: String,
+    excerpt: String,
+    truncated: bool,
 }
 
-#[tauri::command]
-fn stop_chat(event_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut tasks = state.chat_tasks.lock().unwrap();
-    if let Some(handle) = tasks.remove(&event_id) {
-        handle.abort(); // 强制中止任务
-        println!(">>> Chat task aborted: {}", event_id);
-    }
-    Ok(())
+#[derive(Serialize, Debug, Clone)]
+pub struct QuickLookupResult {
+    // true 表示按引用精确找到了条文，false 为语义检索的结果
+    exact: bool,
+    items: Vec<QuickLookupItem>,
+    // 嵌入服务不可用、结果来自关键词匹配时为原因
+    degraded_reason: Option<String>,
 }
 
-#[tauri::command]
-fn stop_task(event_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    // 1. 尝试停止 Chat Stream 任务
-    let mut tasks = state.chat_tasks.lock().unwrap();
-    if let Some(handle) = tasks.remove(&event_id) {
-        handle.abort();
-        println!(">>> Chat task aborted: {}", event_id);
+impl From<LawChunk> for QuickLookupItem {
+    fn from(chunk: LawChunk) -> Self {
+        let content = chunk.content.trim();
+        let excerpt: String = content.chars().take(QUICK_LOOKUP_EXCERPT_CHARS).collect();
+        Self {
+            citation: format!("《{}》{}", chunk.law_name, chunk.article_number),
+            truncated: excerpt.len() < content.len(),
+            excerpt,
+            chunk_id: chunk.id,
+            law_name: chunk.law_name,
+            article_number: chunk.article_number,
+        }
     }
+}
 
-    // 2. 尝试停止 Agent 循环
-    let mut flags = state.agent_abort_flags.lock().unwrap();
-    if let Some(flag) = flags.remove(&event_id) {
-        flag.store(false, Ordering::Relaxed); // 设置开关为 false
-        println!(">>> Agent loop abort signaled: {}", event_id);
+// 输入是引用时直接取该条，否则退回语义检索前 3 条
+metered_command! {
+    #[tauri::command]
+    async fn quick_lookup(
+        text: String,
+        state: tauri::State<'_, AppState>,
+    ) -> Result<QuickLookupResult, AppError> {
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(AppError::validation("请输入法条引用或关键词"));
+        }
+        // 先按引用解析：《民法典》第五百条，也接受不带书名号的“民法典第500条”
+        if let Some(citation) = parse_single_citation(&text) {
+            if let Some(chunk) = resolve_citation(&*content_db(&state)?, &citation)? {
+                return Ok(QuickLookupResult {
+                    exact: true,
+                    items: vec![chunk.into()],
+                    degraded_reason: None,
+                });
+            }
+            debug!("Quick lookup citation not found, falling back to search");
+        }
+        let SearchOutcome {
+            results, degraded, ..
+        } = search_law_with_fallback(text, None, 0, &state).await?;
+        Ok(QuickLookupResult {
+            exact: false,
+            items: results.into_iter().take(3).map(Into::into).collect(),
+            degraded_reason: degraded,
+        })
     }
-
-    Ok(())
 }
 
-#[tauri::command]
-fn get_settings(state: tauri::State<'_, AppState>) -> AppSettings {
-    state.settings.lock().unwrap().clone()
+// 快速查询窗口第一次呼出时创建，之后只显示/隐藏；失去焦点时自动隐藏
+fn show_quick_search(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(QUICK_SEARCH_WINDOW) {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.emit("quick-search-shown", ());
+        return;
+    }
+    let built = tauri::WebviewWindowBuilder::new(
+        app,
+        QUICK_SEARCH_WINDOW,
+        tauri::WebviewUrl::App("index.html#/quick-search".into()),
+    )
+    .title("LawVault 快速查询")
+    .inner_size(560.0, 380.0)
+    .resizable(false)
+    .decorations(false)
+    .always_on_top(true)
+    .skip_taskbar(true)
+    .center()
+    .focused(true)
+    .build();
+    match built {
+        Ok(window) => {
+            let handle = window.clone();
+            window.on_window_event(move |event| {
+                if let tauri::WindowEvent::Focused(false) = event {
+                    let _ = handle.hide();
+                }
+            });
+        }
+        Err(e) => error!("Failed to open quick search window: {}", e),
+    }
 }
 
-#[tauri::command]
-fn save_settings(
-    new_settings: AppSettings,
-    state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let mut guard = state.settings.lock().unwrap();
-    *guard = new_settings.clone();
+// 重新注册快速查询快捷键；失败（格式错误或被其他程序占用）时返回提示语
+fn register_quick_search_shortcut(app: &AppHandle, shortcut: &str) -> Result<(), String> {
+    use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+    let global_shortcut = app.global_shortcut();
+    if let Err(e) = global_shortcut.unregister_all() {
+        warn!("Failed to unregister shortcuts: {}", e);
+    }
+    let shortcut = shortcut.trim();
+    if shortcut.is_empty() {
+        return Ok(());
+    }
+    global_shortcut
+        .on_shortcut(shortcut, |app, _shortcut, event| {
+            if event.state == ShortcutState::Pressed {
+                show_quick_search(app);
+            }
+        })
+        .map_err(|e| {
+            warn!("Failed to register shortcut {}: {}", shortcut, e);
+            format!(
+                "快捷键 {} 注册失败，可能已被其他程序占用（{}）",
+                shortcut, e
+            )
+        })
+}
 
-    let json = serde_json::to_string_pretty(&new_settings).map_err(|e| e.to_string())?;
-    let _ = fs::write(&state.settings_path, json);
+fn build_tray(app: &tauri::App) -> tauri::Result<()> {
+    use tauri::menu::{Menu, MenuItem};
+    use tauri::tray::TrayIconBuilder;
 
+    let quick = MenuItem::with_id(app, "quick-search", "快速查询", true, None::<&str>)?;
+    let show = MenuItem::with_id(app, "show", "显示主窗口", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&quick, &show, &quit])?;
+    let mut tray = TrayIconBuilder::with_id("main")
+        .tooltip("LawVault")
+        .menu(&menu)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "quick-search" => show_quick_search(app),
+            "show" => focus_main_window(app),
+            "quit" => app.exit(0),
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
     Ok(())
 }
 
-// === User Data CRUD Commands ===
+// 5.7 剪贴板引用识别
+// 起草文书时复制的引用（如《民法典》第五百条）自动查出条文，由前端弹出提示
+// 剪贴板内容只保留哈希用于去重，不写日志、不落盘
 
-#[tauri::command]
-fn add_favorite(
-    chunk: LawChunk,
-    folder_id: Option<i32>, // 修改：接收 folder_id
-    state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    // 使用 REPLACE INTO 或者 ON CONFLICT 更新 folder_id
-    conn.execute(
-        "INSERT INTO favorites (law_id, law_name, article_number, content, folder_id) 
-         VALUES (?1, ?2, ?3, ?4, ?5)
-         ON CONFLICT(law_id) DO UPDATE SET folder_id = excluded.folder_id",
-        rusqlite::params![
-            chunk.id,
-            chunk.law_name,
-            chunk.article_number,
-            chunk.content,
-            folder_id
-        ],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_millis(800);
+// 后台运行时每隔一段时间确认是否仍接通电源
+const CLIPBOARD_POWER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// 同一条引用在此时间内重复复制不再提示
+const CLIPBOARD_DEBOUNCE: Duration = Duration::from_secs(60);
+// 过长的文本（如整篇文书）不做识别，避免一次弹出大量提示
+const CLIPBOARD_MAX_CHARS: usize = 2000;
+const CLIPBOARD_MAX_CITATIONS: usize = 3;
+
+// 任一电池处于放电状态即视为使用电池；无法获取电池信息（如台式机）时视为接通电源
+fn on_battery_power() -> bool {
+    let manager = match starship_battery::Manager::new() {
+        Ok(manager) => manager,
+        Err(_) => return false,
+    };
+    match manager.batteries() {
+        Ok(batteries) => batteries
+            .flatten()
+            .any(|battery| battery.state() == starship_battery::State::Discharging),
+        Err(_) => false,
+    }
 }
 
-#[tauri::command]
-fn move_favorite(
-    law_id: String,
-    folder_id: Option<i32>,
-    state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "UPDATE favorites SET folder_id = ?2 WHERE law_id = ?1",
-        rusqlite::params![law_id, folder_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+// 设置开启，且主窗口在前台或接通电源时才允许监听
+fn clipboard_watch_allowed(state: &AppState) -> bool {
+    snapshot_settings(state).enable_clipboard_watch
+        && (state.main_window_focused.load(Ordering::SeqCst) || !on_battery_power())
 }
 
-#[tauri::command]
-fn remove_favorite(law_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "DELETE FROM favorites WHERE law_id = ?1",
-        rusqlite::params![law_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+// 按当前设置与运行状态启动或停止监听线程；设置变更、窗口切换前后台时调用
+fn sync_clipboard_watcher(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let allowed = clipboard_watch_allowed(&state);
+    let mut guard = state.clipboard_watcher.lock_or_recover();
+    let running = guard
+        .as_ref()
+        .is_some_and(|watcher| !watcher.thread.is_finished());
+
+    if allowed && !running {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = app.clone();
+        let thread_stop = stop.clone();
+        let spawned = std::thread::Builder::new()
+            .name("clipboard-watcher".into())
+            .spawn(move || run_clipboard_watcher(handle, thread_stop));
+        match spawned {
+            Ok(thread) => {
+                info!("Clipboard watcher started");
+                *guard = Some(ClipboardWatcher { stop, thread });
+            }
+            Err(e) => error!("Failed to start clipboard watcher: {}", e),
+        }
+    } else if !allowed {
+        if let Some(watcher) = guard.take() {
+            // 唤醒休眠中的线程，使其立即退出而不是等到下一次轮询
+            watcher.stop.store(true, Ordering::SeqCst);
+            watcher.thread.thread().unpark();
+            info!("Clipboard watcher stopped");
+        }
+    }
 }
 
-#[tauri::command]
-fn create_folder(name: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "INSERT INTO favorite_folders (name) VALUES (?1)",
-        rusqlite::params![name],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+fn run_clipboard_watcher(app: AppHandle, stop: Arc<AtomicBool>) {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            warn!("Clipboard unavailable: {}", e);
+            return;
+        }
+    };
+    let text_hash = |text: &str| {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        text.hash(&mut hasher);
+        hasher.finish()
+    };
+    // 启动前已在剪贴板里的内容不算新复制
+    let mut last_hash = clipboard.get_text().ok().map(|text| text_hash(&text));
+    let mut recent: HashMap<(String, String), Instant> = HashMap::new();
+    let mut last_power_check = Instant::now();
+
+    while !stop.load(Ordering::SeqCst) {
+        if last_power_check.elapsed() >= CLIPBOARD_POWER_CHECK_INTERVAL {
+            last_power_check = Instant::now();
+            if !clipboard_watch_allowed(&app.state::<AppState>()) {
+                info!("Clipboard watcher paused: running on battery in background");
+                return;
+            }
+        }
+        if let Ok(text) = clipboard.get_text() {
+            let hash = text_hash(&text);
+            if last_hash != Some(hash) {
+                last_hash = Some(hash);
+                detect_clipboard_citations(&app, &text, &mut recent);
+            }
+        }
+        std::thread::park_timeout(CLIPBOARD_POLL_INTERVAL);
+    }
 }
 
-#[tauri::command]
-fn get_folders(state: tauri::State<'_, AppState>) -> Result<Vec<UserFolder>, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let mut stmt = conn
-        .prepare("SELECT id, name, created_at FROM favorite_folders ORDER BY created_at ASC")
-        .map_err(|e| e.to_string())?;
+// 识别新复制文本中的引用，逐条查库并发出 citation-detected；recent 记录最近提示过的引用
+fn detect_clipboard_citations(
+    app: &AppHandle,
+    text: &str,
+    recent: &mut HashMap<(String, String), Instant>,
+) {
+    if text.chars().count() > CLIPBOARD_MAX_CHARS {
+        return;
+    }
+    let citations = parse_citations(text);
+    if citations.is_empty() {
+        return;
+    }
+    let state = app.state::<AppState>();
+    let conn = match content_db(&state) {
+        Ok(conn) => conn,
+        Err(e) => {
+            debug!("Clipboard citation lookup skipped: {}", e);
+            return;
+        }
+    };
+    recent.retain(|_, at| at.elapsed() < CLIPBOARD_DEBOUNCE);
 
-    let folders = stmt
-        .query_map([], |row| {
-            Ok(UserFolder {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                created_at: row.get(2)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
+    for citation in citations.into_iter().take(CLIPBOARD_MAX_CITATIONS) {
+        let key = (citation.law_name.clone(), citation.article_number.clone());
+        if recent.contains_key(&key) {
+            continue;
+        }
+        recent.insert(key, Instant::now());
 
-    Ok(folders)
+        let chunk = match resolve_law_name(&conn, &citation.law_name) {
+            Ok(Some(law_name)) => find_article_chunk(&conn, &law_name, &citation.article_number),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        match chunk {
+            Ok(Some(chunk)) => {
+                let _ = app.emit(
+                    "citation-detected",
+                    CitationDetectedEvent {
+                        citation: citation.text,
+                        chunk,
+                    },
+                );
+            }
+            Ok(None) => {}
+            Err(e) => debug!("Clipboard citation lookup failed: {}", e),
+        }
+    }
 }
 
-#[tauri::command]
-fn delete_folder(folder_id: i32, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute(
-        "DELETE FROM favorites WHERE folder_id = ?1",
-        rusqlite::params![folder_id],
-    )
-    .map_err(|e| e.to_string())?;
+// 5.8 MCP 服务 (Model Context Protocol, stdio)
+// 以 `LawVault --mcp` 启动，供 Claude Desktop 等客户端把检索作为工具调用；
+// 每行一条 JSON-RPC 2.0 消息，stdout 只输出协议消息，日志写 stderr 与单独的 MCP 日志文件
 
-    conn.execute(
-        "DELETE FROM favorite_folders WHERE id = ?1",
-        rusqlite::params![folder_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+const MCP_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+fn mcp_tools() -> serde_json::Value {
+    serde_json::json!([
+        {
+            "name": "search_law",
+            "description": "在本地法规库中语义检索相关法条，返回条文内容及引用格式",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "检索问题或关键词" },
+                    "region": {
+                        "type": "string",
+                        "description": "地方法规所属地区，如“广东省”；不填时只返回全国性法律法规"
+                    }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "get_article",
+            "description": "按法规名称（可简称）与条号获取单条法条原文",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "law_name": { "type": "string", "description": "法规名称，如“民法典”" },
+                    "article_number": { "type": "string", "description": "条号，如“第五百条”" }
+                },
+                "required": ["law_name", "article_number"]
+            }
+        },
+        {
+            "name": "get_full_text",
+            "description": "获取整部法规的全文",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "law_name": { "type": "string", "description": "法规名称，如“民法典”" }
+                },
+                "required": ["law_name"]
+            }
+        }
+    ])
 }
 
-#[tauri::command]
-fn get_favorites(state: tauri::State<'_, AppState>) -> Result<Vec<UserFavorite>, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let mut stmt = conn.prepare("SELECT id, law_id, law_name, article_number, content, created_at, tags, folder_id FROM favorites ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
+fn mcp_citation(chunk: &LawChunk) -> serde_json::Value {
+    serde_json::json!({
+        "citation": format!("《{}》{}", chunk.law_name, chunk.article_number),
+        "chunk_id": chunk.id,
+        "law_name": chunk.law_name,
+        "article_number": chunk.article_number,
+        "region": chunk.region,
+        "publish_date": chunk.publish_date,
+        "content": chunk.content,
+    })
+}
 
-    let favorites = stmt
-        .query_map([], |row| {
-            Ok(UserFavorite {
-                id: row.get(0)?,
-                law_id: row.get(1)?,
-                law_name: row.get(2)?,
-                article_number: row.get(3)?,
-                content: row.get(4)?,
-                created_at: row.get(5)?,
-                tags: row.get(6)?,
-                folder_id: row.get(7)?,
+// 工具结果：text 供模型阅读，structuredContent 供客户端解析
+fn mcp_chunks_result(chunks: &[LawChunk]) -> serde_json::Value {
+    let text = if chunks.is_empty() {
+        "未找到相关法条。".to_string()
+    } else {
+        chunks
+            .iter()
+            .map(|c| {
+                format!(
+                    "《{}》{}\n{}",
+                    c.law_name,
+                    c.article_number,
+                    c.content.trim()
+                )
             })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
-
-    Ok(favorites)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    let results: Vec<serde_json::Value> = chunks.iter().map(mcp_citation).collect();
+    serde_json::json!({
+        "content": [{ "type": "text", "text": text }],
+        "structuredContent": { "results": results },
+        "isError": false,
+    })
 }
 
-#[tauri::command]
-fn check_is_favorite(law_id: String, state: tauri::State<'_, AppState>) -> Result<bool, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let count: i32 = conn
-        .query_row(
-            "SELECT count(*) FROM favorites WHERE law_id = ?1",
-            rusqlite::params![law_id],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
-    Ok(count > 0)
+// 工具执行失败按 MCP 约定放在结果里返回，让模型看到原因
+fn mcp_tool_error(message: impl std::fmt::Display) -> serde_json::Value {
+    serde_json::json!({
+        "content": [{ "type": "text", "text": message.to_string() }],
+        "isError": true,
+    })
 }
 
-#[tauri::command]
-fn add_history(query: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+async fn mcp_call_tool(
+    state: &AppState,
+    name: &str,
+    args: &serde_json::Value,
+) -> serde_json::Value {
+    let arg = |key: &str| {
+        args.get(key)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    match name {
+        "search_law" => {
+            let Some(query) = arg("query") else {
+                return mcp_tool_error("缺少参数 query");
+            };
+            match search_law_logic(query, arg("region"), 0, state).await {
+                // 只返回“AI 参考数量”条，避免占满模型上下文
+                Ok(mut results) => {
+                    results.truncate(snapshot_settings(state).chat_top_k.max(1));
+                    mcp_chunks_result(&results)
+                }
+                Err(e) => mcp_tool_error(e),
+            }
+        }
+        "get_article" => {
+            let (Some(law_name), Some(article_number)) = (arg("law_name"), arg("article_number"))
+            else {
+                return mcp_tool_error("缺少参数 law_name 或 article_number");
+            };
+            let found = content_db(state)
+                .and_then(|conn| find_article_chunk(&conn, &law_name, &article_number));
+            match found {
+                Ok(Some(chunk)) => mcp_chunks_result(&[chunk]),
+                Ok(None) => mcp_tool_error(format!("未找到《{}》{}", law_name, article_number)),
+                Err(e) => mcp_tool_error(e),
+            }
+        }
+        "get_full_text" => {
+            let Some(law_name) = arg("law_name") else {
+                return mcp_tool_error("缺少参数 law_name");
+            };
+            match load_full_text(state, &law_name, false, None) {
+                Ok(FullText::Raw(text)) => serde_json::json!({
+                    "content": [{ "type": "text", "text": format!("《{}》\n\n{}", law_name, text) }],
+                    "structuredContent": {
+                        "citation": format!("《{}》", law_name),
+                        "law_name": law_name,
+                        "full_text": text,
+                    },
+                    "isError": false,
+                }),
+                Ok(FullText::Structured(_)) => mcp_tool_error("全文格式错误"),
+                Err(e) => mcp_tool_error(e),
+            }
+        }
+        _ => mcp_tool_error(format!("未知的工具: {}", name)),
+    }
+}
 
-    conn.execute(
-        "REPLACE INTO search_history (query, timestamp) VALUES (?1, ?2)",
-        rusqlite::params![query, timestamp],
-    )
-    .map_err(|e| e.to_string())?;
+// 处理一条请求，返回 JSON-RPC 的 result 或 (错误码, 错误信息)
+async fn mcp_handle_request(
+    state: &AppState,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, (i64, String)> {
+    match method {
+        "initialize" => {
+            // 客户端请求的版本受支持时沿用，否则回复我们支持的最新版本
+            let requested = params
+                .get("protocolVersion")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let version = MCP_PROTOCOL_VERSIONS
+                .iter()
+                .find(|v| **v == requested)
+                .unwrap_or(&MCP_PROTOCOL_VERSIONS[0]);
+            Ok(serde_json::json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "lawvault", "version": env!("CARGO_PKG_VERSION") },
+            }))
+        }
+        "ping" => Ok(serde_json::json!({})),
+        "tools/list" => Ok(serde_json::json!({ "tools": mcp_tools() })),
+        "tools/call" => {
+            let name = params
+                .get("name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| (-32602, "缺少工具名称".to_string()))?;
+            let args = params
+                .get("arguments")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({}));
+            Ok(mcp_call_tool(state, name, &args).await)
+        }
+        _ => Err((-32601, format!("Method not found: {}", method))),
+    }
+}
 
-    conn.execute(
-        "DELETE FROM search_history WHERE id NOT IN (SELECT id FROM search_history ORDER BY timestamp DESC LIMIT 50)",
-        [],
-    ).map_err(|e| e.to_string())?;
+async fn mcp_write<W: tokio::io::AsyncWrite + Unpin>(
+    stdout: &mut W,
+    message: &serde_json::Value,
+) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
 
-    Ok(())
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stdout.write_all(&line).await?;
+    stdout.flush().await
 }
 
-#[tauri::command]
-fn get_history(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    let mut stmt = conn
-        .prepare("SELECT query FROM search_history ORDER BY timestamp DESC")
-        .map_err(|e| e.to_string())?;
+// 读取输入直到客户端关闭；startup_error 不为空时只回复一次该错误后退出。
+// 运行时为 stdin/stdout，测试中为内存管道
+async fn serve_mcp<R, W>(
+    state: &AppState,
+    startup_error: Option<String>,
+    input: R,
+    stdout: &mut W,
+) -> i32
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncBufReadExt;
 
-    let history = stmt
-        .query_map([], |row| row.get(0))
-        .map_err(|e| e.to_string())?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(history)
+    let mut lines = input.lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                error!("MCP stdin read failed: {}", e);
+                return 1;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                let reply = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": -32700, "message": format!("Parse error: {}", e) },
+                });
+                if mcp_write(stdout, &reply).await.is_err() {
+                    return 1;
+                }
+                continue;
+            }
+        };
+        // 没有 id 的是通知（如 notifications/initialized），不需要回复
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let method = message
+            .get("method")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        debug!("MCP request: {}", method);
+
+        let result = match &startup_error {
+            Some(e) => Err((-32002, e.clone())),
+            None => {
+                let params = message
+                    .get("params")
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null);
+                mcp_handle_request(state, method, &params).await
+            }
+        };
+        let reply = match result {
+            Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        if let Err(e) = mcp_write(stdout, &reply).await {
+            error!("MCP stdout write failed: {}", e);
+            return 1;
+        }
+        if startup_error.is_some() {
+            return 1;
+        }
+    }
+    0
 }
 
-#[tauri::command]
-fn clear_history(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let conn = connect_user_db(&state.user_db_path)?;
-    conn.execute("DELETE FROM search_history", [])
-        .map_err(|e| e.to_string())?;
-    Ok(())
+// 与 Tauri 的 BaseDirectory::AppConfig 相同（系统配置目录 + tauri.conf.json 中的 identifier），
+// MCP 模式下不启动 Tauri 时使用
+fn standalone_app_config_dir(identifier: &str) -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(identifier)
+}
+
+// 与 Tauri 的 BaseDirectory::Resource 规则一致：
+// Windows 为 exe 所在目录，macOS 为 Contents/Resources，Linux 安装包为 /usr/lib/<产品名>
+fn standalone_resource_dir(exe_dir: &std::path::Path, product_name: &str) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        return exe_dir.join("../Resources");
+    }
+    if cfg!(target_os = "linux") {
+        let installed = match std::env::var_os("APPDIR") {
+            Some(appdir) => PathBuf::from(appdir).join("usr/lib").join(product_name),
+            None => PathBuf::from("/usr/lib").join(product_name),
+        };
+        if installed.exists() {
+            return installed;
+        }
+    }
+    exe_dir.to_path_buf()
 }
 
 // ==========================================
 // 6. 程序入口
 // ==========================================
 
+// 按安装模式加载设置、初始化用户库并构造全局状态；桌面端与 MCP 模式共用
+fn load_app_state(
+    exe_path: PathBuf,
+    app_config_dir: PathBuf,
+    resource_data_dir: PathBuf,
+    log_file_prefix: &str,
+) -> Result<AppState, Box<dyn std::error::Error>> {
+    // 1. 决策路径
+    let install = resolve_install_paths(&exe_path, &app_config_dir);
+    let is_portable = install.portable;
+    let (final_settings_path, final_user_db_path) = (install.settings_path, install.user_db_path);
+
+    // 日志写在配置文件旁的 logs/ 下，便携模式同样跟随 exe 目录
+    let log_dir = final_settings_path
+        .parent()
+        .map(|dir| dir.join("logs"))
+        .unwrap_or_else(|| app_config_dir.join("logs"));
+    init_logging(&log_dir, log_file_prefix);
+    info!(
+        "Mode: {}",
+        if is_portable {
+            "Portable"
+        } else {
+            "Standard (AppData)"
+        }
+    );
+
+    // 2. 加载配置
+    let mut startup_warnings = Vec::new();
+    let settings = if final_settings_path.exists() {
+        let (settings, warning) = load_settings_from_disk(&final_settings_path);
+        startup_warnings.extend(warning);
+        settings
+    } else {
+        info!("Creating default settings at {:?}", final_settings_path);
+        let default = AppSettings::default();
+        // 首次运行自动生成配置文件；写入失败时仍以默认设置启动，并提示用户
+        let json = serde_json::to_string_pretty(&default)?;
+        if let Err(e) = write_file_atomic(&final_settings_path, json.as_bytes()) {
+            error!("Failed to write default settings: {}", e);
+            startup_warnings.push(format!(
+                "无法创建设置文件 {}（{}），修改的设置将无法保存",
+                final_settings_path.display(),
+                e
+            ));
+        }
+        default
+    };
+    apply_log_settings(&settings);
+
+    let http_client = build_http_client(&settings).unwrap_or_else(|e| {
+        error!(
+            "HTTP client init failed, proxy and certificate settings ignored: {}",
+            e
+        );
+        build_http_client(&AppSettings::default()).unwrap_or_default()
+    });
+
+    // 3. 初始化用户数据库并执行迁移（只在启动时执行一次）
+    // 如果文件不存在，connect_user_db 内部会自动创建
+    let _ = connect_user_db(&final_user_db_path).map_err(|e| {
+        error!("User DB init failed: {}", e);
+        e
+    });
+
+    // 4. 默认资源路径 (content.db)
+    // 同样支持便携优先: exe/data > resource/app_data
+    let portable_data_dir = exe_path.join("data");
+
+    let final_app_data_dir = if portable_data_dir.exists() {
+        portable_data_dir
+    } else {
+        resource_data_dir
+    };
+
+    let state = AppState {
+        settings: RwLock::new(settings),
+        settings_path: final_settings_path,
+        app_data_dir: final_app_data_dir,
+        user_db_path: final_user_db_path,
+        chat_tasks: Mutex::new(HashMap::new()),
+        agent_abort_flags: Mutex::new(HashMap::new()),
+        chat_contexts: Mutex::new(HashMap::new()),
+        http_client: Mutex::new(http_client),
+        embedding_dimension_check: Mutex::new(None),
+        content_stats_cache: Mutex::new(None),
+        defined_terms_cache: Mutex::new(HashMap::new()),
+        data_dir_fallback: Mutex::new(None),
+        source_vector_tables: Mutex::new(HashMap::new()),
+        embedding_outage: Mutex::new(None),
+        full_text_cache: Mutex::new(HashMap::new()),
+        metrics: MetricsRegistry::new(),
+        search_result_cache: Mutex::new(HashMap::new()),
+        content_pool: SqlitePool::new(),
+        user_pool: SqlitePool::new(),
+        vector_table: Mutex::new(None),
+        search_in_flight: Mutex::new(HashMap::new()),
+        latest_search_request: AtomicU64::new(0),
+        api_server: Mutex::new(None),
+        pending_navigation: Mutex::new(None),
+        clipboard_watcher: Mutex::new(None),
+        main_window_focused: AtomicBool::new(true),
+        index_meta_check: Mutex::new(None),
+        log_dir,
+        startup_warnings: Mutex::new(startup_warnings),
+        portable_mode: is_portable,
+        exe_dir: exe_path,
+        app_config_dir,
+    };
+
+    // 自定义数据目录启动时就无法访问，随启动提示一并告知
+    get_effective_data_dir(&state);
+    if let Some(fallback) = take_data_dir_fallback(&state) {
+        state.startup_warnings.lock_or_recover().push(format!(
+            "自定义数据目录无法访问（{}），已暂时使用默认数据目录",
+            fallback.configured_path
+        ));
+    }
+    Ok(state)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // 必须最先注册：再次打开程序（包括点击 lawvault:// 链接）时交给已运行的实例处理
+        .plugin(tauri_plugin_single_instance::init(|app, _argv, _cwd| {
+            focus_main_window(app);
+        }))
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
@@ -1535,82 +16981,180 @@ pub fn run() {
             // 1. 获取 exe 目录 (便携模式检测)
             let mut exe_path = std::env::current_exe()?;
             exe_path.pop();
-            let portable_settings = exe_path.join("settings.json");
-            let portable_user_db = exe_path.join("user_data.db");
 
             // 2. 获取系统 AppData 目录
             let app_config_dir = app.path().resolve("", BaseDirectory::AppConfig)?;
             if !app_config_dir.exists() {
                 std::fs::create_dir_all(&app_config_dir)?;
             }
-            let system_settings = app_config_dir.join("settings.json");
-            let system_user_db = app_config_dir.join("user_data.db");
-
-            // 3. 决策路径
-            // 规则：如果 exe 旁边有配置文件，就认为是便携模式，数据库也读旁边的
-            // 否则全部走系统目录
-            let (final_settings_path, final_user_db_path) = if portable_settings.exists() {
-                println!(">>> Mode: Portable");
-                (portable_settings, portable_user_db)
-            } else {
-                println!(">>> Mode: Standard (AppData)");
-                (system_settings, system_user_db)
-            };
-
-            // 4. 加载配置
-            let settings = if final_settings_path.exists() {
-                load_settings_from_disk(&final_settings_path)
-            } else {
-                println!(">>> Creating default settings at {:?}", final_settings_path);
-                let default = AppSettings::default();
-                // 首次运行自动生成配置文件
-                let json = serde_json::to_string_pretty(&default)?;
-                let _ = fs::write(&final_settings_path, json);
-                default
-            };
-
-            // 5. 初始化用户数据库
-            // 如果文件不存在，connect_user_db 内部会自动创建
-            let _ = connect_user_db(&final_user_db_path).map_err(|e| {
-                eprintln!("User DB init failed: {}", e);
-                e
-            });
 
-            // 6. 默认资源路径 (content.db)
-            // 同样支持便携优先: exe/data > resource/app_data
-            let portable_data_dir = exe_path.join("data");
+            // 3. 默认资源路径，加载设置并初始化全局状态
             let resource_data_dir = app
                 .path()
                 .resolve("resources/app_data", BaseDirectory::Resource)?;
+            app.manage(load_app_state(
+                exe_path,
+                app_config_dir,
+                resource_data_dir,
+                LOG_FILE_PREFIX,
+            )?);
 
-            let final_app_data_dir = if portable_data_dir.exists() {
-                portable_data_dir
-            } else {
-                resource_data_dir
-            };
-
-            app.manage(AppState {
-                settings: Mutex::new(settings),
-                settings_path: final_settings_path,
-                app_data_dir: final_app_data_dir,
-                user_db_path: final_user_db_path,
-                chat_tasks: Mutex::new(HashMap::new()),
-                agent_abort_flags: Mutex::new(HashMap::new()),
+            // 4. 后台预热，失败不影响窗口显示
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<AppState>();
+                let report = run_warmup(&state).await;
+                let _ = handle.emit("warmup-complete", report);
             });
+            // 条文互引索引：首次启动时全量扫描，之后只处理新增法规
+            spawn_cross_ref_index(app.handle());
+
+            // 5. 按设置启动本地 HTTP API
+            if snapshot_settings(&app.state::<AppState>()).api_server_enabled {
+                restart_api_server(app.handle());
+            }
+
+            // 6. 托盘与快速查询快捷键，失败不影响主窗口
+            if let Err(e) = build_tray(app) {
+                error!("Failed to create tray icon: {}", e);
+            }
+            let shortcut = snapshot_settings(&app.state::<AppState>()).quick_search_shortcut;
+            if let Err(message) = register_quick_search_shortcut(app.handle(), &shortcut) {
+                app.state::<AppState>()
+                    .startup_warnings
+                    .lock_or_recover()
+                    .push(message);
+            }
+
+            // 7. 剪贴板引用识别：记录主窗口前后台状态，切换时按需启停监听
+            if let Some(window) = app.get_webview_window("main") {
+                let handle = app.handle().clone();
+                window.on_window_event(move |event| {
+                    if let tauri::WindowEvent::Focused(focused) = event {
+                        let state = handle.state::<AppState>();
+                        state.main_window_focused.store(*focused, Ordering::SeqCst);
+                        if state.clipboard_watcher.lock_or_recover().is_some() || *focused {
+                            sync_clipboard_watcher(&handle);
+                        }
+                    }
+                });
+            }
+            sync_clipboard_watcher(app.handle());
+
+            // 8. lawvault:// 链接：启动时带的链接暂存，运行中收到的直接发给前端
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+
+                if let Ok(Some(urls)) = app.deep_link().get_current() {
+                    let state = app.state::<AppState>();
+                    match urls.first().map(|url| resolve_deep_link_url(&state, url)) {
+                        Some(Ok(target)) => {
+                            *state.pending_navigation.lock_or_recover() = Some(target)
+                        }
+                        Some(Err(e)) => state
+                            .startup_warnings
+                            .lock_or_recover()
+                            .push(format!("无法打开链接 {}：{}", e.url, e.message)),
+                        None => {}
+                    }
+                }
+                let handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    handle_deep_links(&handle, &event.urls());
+                });
+            }
+
+            // 9. 推送启动提示；前端晚于此注册监听时通过 get_startup_warnings 拉取
+            let warnings = app
+                .state::<AppState>()
+                .startup_warnings
+                .lock_or_recover()
+                .clone();
+            if !warnings.is_empty() {
+                let _ = app.emit("startup-warnings", warnings);
+            }
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             search_law,
+            get_chunk_content,
             chat_stream,
+            regenerate_chat,
             stop_chat,
             stop_task,
             get_settings,
+            get_startup_warnings,
+            validate_settings,
             save_settings,
+            update_settings_partial,
+            reset_settings,
+            export_settings,
+            import_settings,
+            save_settings_profile,
+            list_settings_profiles,
+            apply_settings_profile,
+            delete_settings_profile,
+            clear_stored_credentials,
             search_law_by_name,
+            classify_and_search,
+            add_query_rewrite_rule,
+            list_query_rewrite_rules,
+            delete_query_rewrite_rule,
+            set_law_name_translation,
             get_full_text,
             check_ai_connection,
+            run_setup_diagnostics,
+            set_data_path,
+            get_install_mode,
+            get_effective_paths,
+            probe_embedding_service,
+            run_retrieval_benchmark,
+            get_db_status_detailed,
+            list_data_sources,
+            add_data_source,
+            set_data_source_enabled,
+            remove_data_source,
+            convert_install_mode,
+            check_content_updates,
+            download_content_update,
+            import_law_document,
+            list_imported_laws,
+            delete_imported_law,
+            rebuild_vector_index,
+            cancel_vector_rebuild,
+            get_db_stats,
+            browse_laws,
+            get_recent_laws,
+            get_laws_by_year,
+            get_law_toc,
+            search_in_full_text,
+            verify_data_integrity,
+            export_favorites,
+            export_folder,
+            export_search_results,
+            export_law_pdf,
+            import_favorites,
+            update_favorite_tags,
+            get_all_tags,
+            set_favorite_note,
+            search_favorites,
+            check_favorites_freshness,
+            refresh_favorite,
+            refresh_all_favorites,
+            test_proxy,
+            download_embedding_model,
+            check_local_model,
+            get_recent_logs,
+            open_log_folder,
             get_article_snippet,
+            format_citation,
+            make_deep_link,
+            quick_lookup,
+            take_pending_navigation,
+            start_api_server,
+            stop_api_server,
+            get_api_server_status,
             check_db_status,
             start_agent_search,
             // User Data Commands
@@ -1618,21 +17162,124 @@ pub fn run() {
             remove_favorite,
             get_favorites,
             check_is_favorite,
+            add_law_favorite,
+            remove_law_favorite,
+            get_law_favorites,
+            check_is_law_favorite,
             add_history,
             get_history,
+            get_history_strings,
+            delete_history_item,
+            pin_history_item,
+            search_history,
+            get_usage_stats,
+            clear_usage_stats,
             clear_history,
             create_folder,
             get_folders,
             delete_folder,
             move_favorite,
+            get_favorite_folders,
+            favorite_by_citation,
+            reorder_favorite,
+            move_folder,
+            add_favorites_bulk,
+            move_favorites_bulk,
+            remove_favorites_bulk,
             add_draft_material,
             get_draft_materials,
             remove_draft_material,
+            add_annotation,
+            get_annotations,
+            update_annotation,
+            delete_annotation,
+            reconcile_annotations,
+            enroll_reviews,
+            get_due_reviews,
+            submit_review,
+            build_cross_refs,
+            get_outgoing_refs,
+            get_incoming_refs,
+            diff_texts,
+            diff_chunks,
+            check_citations_in_text,
+            lookup_term_definition,
+            extract_defined_terms,
+            import_term_definitions,
             clear_draft_materials,
+            workspace_add,
+            workspace_remove,
+            workspace_list,
+            workspace_clear,
+            workspace_send_to_chat,
             add_template,
             get_templates,
-            delete_template
+            delete_template,
+            get_metrics_snapshot,
+            reset_metrics,
+            suggest_folder,
+            search_national_vs_local,
+            get_favorite_content
         ])
-        .run(tauri::generate_context!())
+        .run(app_context())
         .expect("error while running tauri application");
 }
+
+// 桌面端与 MCP 模式共用，tauri.conf.json 只在这里嵌入一次
+fn app_context() -> tauri::Context<tauri::Wry> {
+    tauri::generate_context!()
+}
+
+// MCP 模式入口：不创建窗口，按与桌面端相同的规则解析设置与数据目录，返回进程退出码
+pub fn run_mcp_server() -> i32 {
+    let exe_dir = match std::env::current_exe() {
+        Ok(mut path) => {
+            path.pop();
+            path
+        }
+        Err(e) => {
+            eprintln!("Failed to locate executable: {}", e);
+            return 1;
+        }
+    };
+    // 标识与产品名取自 tauri.conf.json，与桌面端解析出的目录保持一致
+    let context = app_context();
+    let config = context.config();
+    let app_config_dir = standalone_app_config_dir(&config.identifier);
+    let _ = fs::create_dir_all(&app_config_dir);
+    let product_name = config.product_name.as_deref().unwrap_or("LawVault");
+    let resource_data_dir =
+        standalone_resource_dir(&exe_dir, product_name).join("resources/app_data");
+    let state = match load_app_state(
+        exe_dir,
+        app_config_dir,
+        resource_data_dir,
+        MCP_LOG_FILE_PREFIX,
+    ) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to initialize: {}", e);
+            return 1;
+        }
+    };
+
+    let vector_db = get_effective_data_dir(&state).join("law_db.lancedb");
+    let startup_error = if vector_db.exists() {
+        info!("MCP server started, data dir: {:?}", vector_db.parent());
+        None
+    } else {
+        let message = format!(
+            "未找到向量数据库 {}，请先打开 LawVault 完成数据配置",
+            vector_db.display()
+        );
+        error!("{}", message);
+        Some(message)
+    };
+    tauri::async_runtime::block_on(async {
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        serve_mcp(&state, startup_error, stdin, &mut tokio::io::stdout()).await
+    })
+}
+
+#[cfg(test)]
+mod tests;
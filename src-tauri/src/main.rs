@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+  // MCP 客户端（如 Claude Desktop）以 `LawVault --mcp` 启动，通过 stdio 通信
+  if std::env::args().skip(1).any(|arg| arg == "--mcp") {
+    std::process::exit(app_lib::run_mcp_server());
+  }
   app_lib::run();
 }
\ No newline at end of file
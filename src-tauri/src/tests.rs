@@ -0,0 +1,2630 @@
+use super::*;
+use tauri::Listener;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// ==========================================
+// 测试辅助
+// ==========================================
+
+// 模拟服务端的一次响应
+struct MockResponse {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl MockResponse {
+    fn new(status: u16, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn json(body: serde_json::Value) -> Self {
+        Self::new(200, "application/json", body.to_string())
+    }
+}
+
+// 本机临时 HTTP 服务：每个连接按顺序返回一条预设响应后关闭，收到的请求正文记录在返回的列表中
+async fn mock_server(responses: Vec<MockResponse>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&requests);
+    tokio::spawn(async move {
+        for response in responses {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let body = read_request_body(&mut socket).await;
+            seen.lock().unwrap().push(body);
+            let head = format!(
+                "HTTP/1.1 {} MOCK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                response.status,
+                response.content_type,
+                response.body.len()
+            );
+            let _ = socket.write_all(head.as_bytes()).await;
+            let _ = socket.write_all(response.body.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+    (format!("http://{}", addr), requests)
+}
+
+async fn read_request_body(socket: &mut tokio::net::TcpStream) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            return String::new();
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + length {
+        let n = socket.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    String::from_utf8_lossy(&buf[header_end..]).to_string()
+}
+
+// 不读取环境变量中的代理，直连本机模拟服务
+fn test_client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().unwrap()
+}
+
+// 在临时目录中按启动流程初始化 AppState（非便携模式，设置与用户库写在 config/ 下）
+fn test_state() -> (tempfile::TempDir, AppState) {
+    let dir = tempfile::tempdir().unwrap();
+    let exe_dir = dir.path().join("exe");
+    let config_dir = dir.path().join("config");
+    let resource_dir = dir.path().join("resources");
+    for d in [&exe_dir, &config_dir, &resource_dir] {
+        fs::create_dir_all(d).unwrap();
+    }
+    let state = load_app_state(exe_dir, config_dir, resource_dir, LOG_FILE_PREFIX).unwrap();
+    (dir, state)
+}
+
+// 托管了 AppState 的模拟应用，用于直接调用接收 tauri::State 的命令
+fn test_app() -> (tempfile::TempDir, tauri::App<tauri::test::MockRuntime>) {
+    let (dir, state) = test_state();
+    let app = tauri::test::mock_app();
+    app.manage(state);
+    (dir, app)
+}
+
+// 已执行迁移的内存用户库
+fn test_user_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    migrate_user_db(&conn).unwrap();
+    conn
+}
+
+// 在数据目录中生成标准结构的 content.db：count 条《测试法》条文，id 为 c1..cN
+fn write_content_db(data_dir: &std::path::Path, count: usize) {
+    fs::create_dir_all(data_dir).unwrap();
+    let mut conn = Connection::open(data_dir.join("content.db")).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE chunks (id TEXT PRIMARY KEY, content TEXT, law_name TEXT, category TEXT,
+            region TEXT, publish_date TEXT, part TEXT, chapter TEXT, article_number TEXT);
+         CREATE TABLE full_texts (law_name TEXT, region TEXT, category TEXT, full_text TEXT);",
+    )
+    .unwrap();
+    let tx = conn.transaction().unwrap();
+    for i in 1..=count {
+        tx.execute(
+            "INSERT INTO chunks VALUES (?1, ?2, '测试法', '法律', '', '2024-01-01', '', '', ?3)",
+            rusqlite::params![
+                format!("c{}", i),
+                format!("第{}条 条文内容{}。", i, i),
+                format!("第{}条", i)
+            ],
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+}
+
+// 在数据目录中生成 laws_vectors 向量表
+async fn write_vector_table(data_dir: &std::path::Path, rows: Vec<(String, Vec<f32>)>) {
+    use lancedb::arrow::arrow_schema::{Field, Schema};
+
+    fs::create_dir_all(data_dir.join("law_db.lancedb")).unwrap();
+    let dimension = rows[0].1.len() as i32;
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("chunk_id", DataType::Utf8, false),
+        Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            true,
+        ),
+    ]));
+    let db = connect_vector_db(data_dir).await.unwrap();
+    let table = db
+        .create_empty_table("laws_vectors", schema.clone())
+        .execute()
+        .await
+        .unwrap();
+    let (ids, vectors) = rows.into_iter().unzip();
+    let batch = build_vector_batch(schema.clone(), ids, vectors).unwrap();
+    table
+        .add(RecordBatchIterator::new(vec![Ok(batch)], schema))
+        .execute()
+        .await
+        .unwrap();
+}
+
+// 嵌入服务指向模拟服务器的状态
+fn remote_embedding_state(url: &str) -> (tempfile::TempDir, AppState) {
+    let (dir, state) = test_state();
+    {
+        let mut settings = settings_write_guard(&state);
+        settings.embedding_provider = "openai".to_string();
+        settings.embedding_api_format = "openai".to_string();
+        settings.embedding_base_url = url.to_string();
+        settings.embedding_api_key = "key".to_string();
+    }
+    *state.http_client.lock_or_recover() = test_client();
+    (dir, state)
+}
+
+fn law_chunk(id: &str, law_name: &str, article_number: &str) -> LawChunk {
+    LawChunk {
+        id: id.to_string(),
+        _distance: 0.0,
+        content: format!("{}条文内容。", article_number),
+        law_name: law_name.to_string(),
+        category: "法律".to_string(),
+        publish_date: "2024-01-01".to_string(),
+        part: String::new(),
+        chapter: String::new(),
+        article_number: article_number.to_string(),
+        region: String::new(),
+        source_file: format!("{}.txt", law_name),
+        content_truncated: false,
+        source_name: None,
+    }
+}
+
+// 向连接池放入一个空闲的内存连接
+fn seed_pool(pool: &Arc<SqlitePool>, path: &std::path::Path) {
+    let conn = pool
+        .get(path, |_| Ok(Connection::open_in_memory().unwrap()))
+        .unwrap();
+    drop(conn);
+}
+
+// 收集发往某个事件名的全部负载
+fn collect_events<R: Runtime>(app: &AppHandle<R>, event: &str) -> Arc<Mutex<Vec<String>>> {
+    let payloads = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&payloads);
+    app.listen_any(event, move |e| {
+        sink.lock().unwrap().push(e.payload().to_string())
+    });
+    payloads
+}
+
+// ==========================================
+// 对话流式输出
+// ==========================================
+
+fn sse(events: &[serde_json::Value]) -> String {
+    let mut body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+    body.push_str("data: [DONE]\n\n");
+    body
+}
+
+#[tokio::test]
+async fn streaming_chat_forwards_tokens_until_done() {
+    let body = sse(&[
+        serde_json::json!({ "choices": [{ "delta": { "content": "你好" } }] }),
+        serde_json::json!({
+            "choices": [{ "delta": { "content": "，世界" }, "finish_reason": "stop" }],
+            "usage": { "total_tokens": 12 }
+        }),
+    ]);
+    let (url, _) = mock_server(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+    let app = tauri::test::mock_app();
+    let tokens = collect_events(app.handle(), "evt");
+
+    let endpoint = ChatEndpoint::new(&test_client(), &url, "key", ChatApiFormat::OpenAi);
+    let mut chat = ChatStreamState::default();
+    let outcome = run_streaming_chat(
+        app.handle(),
+        &endpoint,
+        &serde_json::json!({}),
+        "evt",
+        &mut chat,
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(outcome, StreamOutcome::Completed));
+    assert_eq!(chat.answer, "你好，世界");
+    assert_eq!(chat.summary.finish_reason.as_deref(), Some("stop"));
+    assert_eq!(chat.summary.total_chars, 5);
+    assert!(chat.summary.usage.is_some());
+    assert_eq!(tokens.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn streaming_chat_ends_with_error_on_mid_stream_error() {
+    let body = "event: content_block_delta\n\
+                data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"部分\"}}\n\n\
+                event: error\n\
+                data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\"}}\n\n";
+    let (url, _) = mock_server(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+    let app = tauri::test::mock_app();
+
+    let endpoint = ChatEndpoint::new(&test_client(), &url, "key", ChatApiFormat::Anthropic);
+    let mut chat = ChatStreamState::default();
+    let result = run_streaming_chat(
+        app.handle(),
+        &endpoint,
+        &serde_json::json!({}),
+        "evt",
+        &mut chat,
+    )
+    .await;
+
+    assert!(matches!(result, Err(AppError::ChatApi(_))));
+    assert_eq!(chat.answer, "部分");
+}
+
+#[tokio::test]
+async fn streaming_chat_reports_http_failure() {
+    let (url, _) = mock_server(vec![MockResponse::new(
+        401,
+        "application/json",
+        r#"{"error":"invalid api key"}"#,
+    )])
+    .await;
+    let app = tauri::test::mock_app();
+
+    let endpoint = ChatEndpoint::new(&test_client(), &url, "key", ChatApiFormat::OpenAi);
+    let mut chat = ChatStreamState::default();
+    let result = run_streaming_chat(
+        app.handle(),
+        &endpoint,
+        &serde_json::json!({}),
+        "evt",
+        &mut chat,
+    )
+    .await;
+
+    assert!(result.is_err());
+    assert!(chat.answer.is_empty());
+}
+
+#[test]
+fn only_stream_errors_trigger_non_streaming_fallback() {
+    use reqwest::StatusCode;
+
+    let unsupported =
+        r#"{"error":{"message":"Unsupported value","param":"stream","code":"unsupported_value"}}"#;
+    assert!(is_stream_rejection(StatusCode::BAD_REQUEST, unsupported));
+    assert!(is_stream_rejection(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        r#"{"detail":"Streaming is not supported"}"#
+    ));
+    for body in [
+        r#"{"error":{"message":"The model `gpt-x` does not exist","code":"model_not_found"}}"#,
+        r#"{"error":{"message":"maximum context length exceeded","param":"messages"}}"#,
+        r#"{"error":"upstream request rejected"}"#,
+    ] {
+        assert!(
+            !is_stream_rejection(StatusCode::BAD_REQUEST, body),
+            "{}",
+            body
+        );
+    }
+    assert!(!is_stream_rejection(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        r#"{"error":{"param":"stream"}}"#
+    ));
+}
+
+// 第一次尝试迟迟不返回响应头：超时只作用于这一次，重试仍有完整的等待时间
+#[tokio::test]
+async fn chat_timeout_applies_to_each_attempt() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stalled, _) = listener.accept().await.unwrap();
+        let (mut socket, _) = listener.accept().await.unwrap();
+        read_request_body(&mut socket).await;
+        // 第二次请求在接近超时时才返回，若超时覆盖整个重试过程则早已失败
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        let body = r#"{"choices":[{"message":{"content":"ok"}}]}"#;
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        let _ = socket.write_all(head.as_bytes()).await;
+        let _ = socket.write_all(body.as_bytes()).await;
+        drop(stalled);
+    });
+    let app = tauri::test::mock_app();
+    let retries = collect_events(app.handle(), "evt-status");
+
+    let endpoint =
+        ChatEndpoint::new(&test_client(), &url, "key", ChatApiFormat::OpenAi).with_timeout(2);
+    let res = send_chat_request(app.handle(), &endpoint, &serde_json::json!({}), "evt")
+        .await
+        .unwrap();
+
+    assert!(res.status().is_success());
+    assert_eq!(retries.lock().unwrap().len(), 1);
+}
+
+// ==========================================
+// 嵌入
+// ==========================================
+
+fn remote_embedding_config(base_url: &str) -> EmbeddingConfig {
+    EmbeddingConfig {
+        provider: "openai".to_string(),
+        base_url: base_url.to_string(),
+        api_key: "key".to_string(),
+        model: "test-embedding".to_string(),
+        api_format: "openai".to_string(),
+        timeout: Duration::from_secs(5),
+        local_model_dir: PathBuf::new(),
+    }
+}
+
+#[tokio::test]
+async fn batch_embeddings_are_reordered_by_index() {
+    let (url, requests) = mock_server(vec![MockResponse::json(serde_json::json!({
+        "data": [
+            { "index": 2, "embedding": [3.0, 3.0] },
+            { "index": 0, "embedding": [1.0, 1.0] },
+            { "index": 1, "embedding": [2.0, 2.0] }
+        ]
+    }))])
+    .await;
+    let texts: Vec<String> = ["甲", "乙", "丙"].iter().map(|t| t.to_string()).collect();
+
+    let vectors = get_embeddings_batch(&test_client(), &remote_embedding_config(&url), &texts)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        vectors,
+        vec![vec![1.0, 1.0], vec![2.0, 2.0], vec![3.0, 3.0]]
+    );
+    // 整批只发一次请求
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn batch_embeddings_reject_duplicate_indices() {
+    let (url, _) = mock_server(vec![MockResponse::json(serde_json::json!({
+        "data": [
+            { "index": 0, "embedding": [1.0] },
+            { "index": 0, "embedding": [2.0] }
+        ]
+    }))])
+    .await;
+    let texts = vec!["甲".to_string(), "乙".to_string()];
+
+    let result = get_embeddings_batch(&test_client(), &remote_embedding_config(&url), &texts).await;
+
+    assert!(matches!(result, Err(AppError::EmbeddingApi(_))));
+}
+
+// ==========================================
+// 设置
+// ==========================================
+
+#[test]
+fn display_only_change_keeps_connection_caches() {
+    let (dir, state) = test_state();
+    let db_path = dir.path().join("content.db");
+    seed_pool(&state.content_pool, &db_path);
+    *state.embedding_dimension_check.lock_or_recover() = Some(Ok(()));
+
+    let old = snapshot_settings(&state);
+    let mut new = old.clone();
+    new.display_density = "compact".to_string();
+    let changed = settings_diff(&old, &new);
+    assert_eq!(changed, vec!["display_density".to_string()]);
+    invalidate_settings_caches(&state, &changed);
+
+    assert_eq!(state.content_pool.idle.lock_or_recover().len(), 1);
+    assert!(state.embedding_dimension_check.lock_or_recover().is_some());
+}
+
+#[test]
+fn data_path_change_drops_connection_caches() {
+    let (dir, state) = test_state();
+    seed_pool(&state.content_pool, &dir.path().join("content.db"));
+    *state.embedding_dimension_check.lock_or_recover() = Some(Ok(()));
+
+    let old = snapshot_settings(&state);
+    let mut new = old.clone();
+    new.custom_data_path = Some(dir.path().join("other").to_string_lossy().to_string());
+    invalidate_settings_caches(&state, &settings_diff(&old, &new));
+
+    assert!(state.content_pool.idle.lock_or_recover().is_empty());
+    assert!(state.embedding_dimension_check.lock_or_recover().is_none());
+}
+
+#[test]
+fn import_rejects_only_the_conflicting_field() {
+    let current = AppSettings::default();
+    let imported = serde_json::json!({
+        "chat_base_url": "not a url",
+        "display_density": "compact",
+        "enable_ai_chat": true,
+        "search_top_k": 0,
+        "no_such_field": 1
+    });
+    let serde_json::Value::Object(imported) = imported else {
+        unreachable!()
+    };
+
+    let (settings, report) = merge_imported_settings(&current, imported).unwrap();
+
+    // 对话地址只在启用对话时校验：两者单独都合法，组合后冲突，只拒绝后处理的那一个
+    let rejected: Vec<&str> = report.rejected.iter().map(|r| r.field.as_str()).collect();
+    assert!(report.applied.contains(&"display_density".to_string()));
+    assert_eq!(report.applied.len(), 2);
+    assert_eq!(rejected.len(), 3);
+    assert!(rejected.contains(&"search_top_k") && rejected.contains(&"no_such_field"));
+    assert!(!(settings.enable_ai_chat && settings.chat_base_url == "not a url"));
+    assert_eq!(settings.display_density, "compact");
+    assert!(check_settings(&settings).iter().all(|i| !i.is_error()));
+}
+
+#[test]
+fn redacted_export_hides_api_server_token() {
+    let (dir, app) = test_app();
+    let state = app.state::<AppState>();
+    {
+        let mut settings = settings_write_guard(&state);
+        settings.chat_api_key = "sk-chat".to_string();
+        settings.api_server_token = "local-token".to_string();
+    }
+    let path = dir.path().join("settings-export.json");
+
+    export_settings(
+        path.to_string_lossy().to_string(),
+        true,
+        app.state(),
+        app.state(),
+    )
+    .unwrap();
+    let exported = fs::read_to_string(&path).unwrap();
+    assert!(!exported.contains("sk-chat") && !exported.contains("local-token"));
+
+    // 未脱敏的导出文件中的令牌也不会覆盖本机令牌
+    let imported = serde_json::json!({ "api_server_token": "other-device" });
+    let serde_json::Value::Object(imported) = imported else {
+        unreachable!()
+    };
+    let (settings, report) = merge_imported_settings(&snapshot_settings(&state), imported).unwrap();
+    assert_eq!(settings.api_server_token, "local-token");
+    assert!(report.applied.is_empty());
+}
+
+#[test]
+fn panic_while_holding_settings_does_not_break_get_settings() {
+    let (_dir, state) = test_state();
+    let app = tauri::test::mock_app();
+    app.manage(state);
+    let state = app.state::<AppState>();
+
+    let result = std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let mut guard = settings_write_guard(&state);
+                guard.search_top_k = 7;
+                panic!("simulated command panic");
+            })
+            .join()
+    });
+    assert!(result.is_err());
+    assert!(state.settings.is_poisoned());
+
+    assert_eq!(get_settings(app.state(), app.state()).search_top_k, 7);
+    assert_eq!(snapshot_settings(&state).search_top_k, 7);
+
+    // Mutex 字段同理
+    let _ = std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                state
+                    .startup_warnings
+                    .lock_or_recover()
+                    .push("提示".to_string());
+                let _guard = state.startup_warnings.lock_or_recover();
+                panic!("simulated command panic");
+            })
+            .join()
+    });
+    assert!(state.startup_warnings.is_poisoned());
+    assert!(get_startup_warnings(app.state(), app.state()).contains(&"提示".to_string()));
+}
+
+// 附加 CA 证书文件丢失（如启动时所在的网络盘未挂载）：只跳过证书，代理仍然生效
+#[tokio::test]
+async fn missing_extra_ca_keeps_proxy() {
+    let (proxy_url, _) = mock_server(vec![MockResponse::new(200, "text/plain", "via proxy")]).await;
+    let dir = tempfile::tempdir().unwrap();
+    let settings = AppSettings {
+        http_proxy: Some(proxy_url),
+        extra_ca_cert_path: Some(dir.path().join("missing.pem").to_string_lossy().to_string()),
+        ..AppSettings::default()
+    };
+    assert!(check_settings(&settings)
+        .iter()
+        .any(|issue| issue.is_error() && issue.field == "extra_ca_cert_path"));
+
+    let client = build_http_client(&settings).unwrap();
+    let body = client
+        .get("http://lawvault.invalid/ping")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "via proxy");
+}
+
+// ==========================================
+// 安装模式转换
+// ==========================================
+
+#[test]
+fn failed_mode_switch_restores_marker() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe_dir = dir.path();
+    fs::write(exe_dir.join(INSTALL_MODE_MARKER), "standard").unwrap();
+    let staged = exe_dir.join("settings.json.converting");
+    let target = exe_dir.join("settings.json");
+
+    // 临时文件不存在，改名失败
+    let result = switch_install_mode(exe_dir, true, &[(&staged, &target)]);
+
+    assert!(result.is_err());
+    assert_eq!(read_install_marker(exe_dir), Some(false));
+    assert!(!resolve_install_paths(exe_dir, &dir.path().join("config")).portable);
+}
+
+#[test]
+fn failed_mode_switch_without_marker_leaves_no_portable_traces() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe_dir = dir.path().join("exe");
+    fs::create_dir_all(&exe_dir).unwrap();
+    let db = exe_dir.join("user_data.db.converting");
+    fs::write(&db, b"db").unwrap();
+    let missing = exe_dir.join("settings.json.converting");
+    let targets = (exe_dir.join("user_data.db"), exe_dir.join("settings.json"));
+
+    let result = switch_install_mode(&exe_dir, true, &[(&db, &targets.0), (&missing, &targets.1)]);
+
+    assert!(result.is_err());
+    assert!(!exe_dir.join(INSTALL_MODE_MARKER).exists());
+    assert!(!targets.1.exists());
+    assert!(!resolve_install_paths(&exe_dir, &dir.path().join("config")).portable);
+}
+
+#[test]
+fn mode_switch_replaces_existing_target_only_after_staging() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source.db");
+    {
+        let conn = Connection::open(&source).unwrap();
+        conn.execute_batch("CREATE TABLE t (v INTEGER); INSERT INTO t VALUES (1), (2);")
+            .unwrap();
+    }
+    let target = dir.path().join("user_data.db");
+    fs::write(&target, b"old").unwrap();
+    let staged = staging_path(&target);
+
+    copy_user_db(&source, &staged).unwrap();
+    // 复制完成前目标文件保持原样
+    assert_eq!(fs::read(&target).unwrap(), b"old");
+    switch_install_mode(dir.path(), true, &[(&staged, &target)]).unwrap();
+
+    let count: i64 = Connection::open(&target)
+        .unwrap()
+        .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(read_install_marker(dir.path()), Some(true));
+}
+
+// ==========================================
+// 法规数据更新
+// ==========================================
+
+#[test]
+fn content_update_requires_newer_version() {
+    assert!(is_newer_content_version(None, "1.0.0"));
+    assert!(is_newer_content_version(Some("1.2.0"), "1.10.0"));
+    assert!(is_newer_content_version(Some("v2024.9"), "2024.10.01"));
+    assert!(!is_newer_content_version(Some("1.10.0"), "1.2.0"));
+    assert!(!is_newer_content_version(Some("2024.10.01"), "2024.10.1"));
+    assert!(!is_newer_content_version(Some("2.0.0"), "2.0.0-rc.1"));
+    // 无法解析的版本号按是否不同判断
+    assert!(is_newer_content_version(Some("旧版"), "新版"));
+    assert!(!is_newer_content_version(Some("旧版"), "旧版"));
+}
+
+#[test]
+fn content_swap_installs_version_file_with_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let (data_dir, new_dir, backup_dir) = (
+        dir.path().join("data"),
+        dir.path().join("new"),
+        dir.path().join("backup"),
+    );
+    for d in [&data_dir, &new_dir, &backup_dir] {
+        fs::create_dir_all(d).unwrap();
+    }
+    for d in [&data_dir, &new_dir] {
+        fs::write(d.join("content.db"), d.to_string_lossy().as_bytes()).unwrap();
+        fs::create_dir_all(d.join("law_db.lancedb")).unwrap();
+    }
+    fs::write(
+        data_dir.join(CONTENT_VERSION_FILE),
+        r#"{"version":"1.0.0","installed_at":""}"#,
+    )
+    .unwrap();
+    fs::write(
+        new_dir.join(CONTENT_VERSION_FILE),
+        r#"{"version":"1.1.0","installed_at":""}"#,
+    )
+    .unwrap();
+
+    swap_content_files(&data_dir, &new_dir, &backup_dir).unwrap();
+    assert_eq!(read_content_version(&data_dir).unwrap().version, "1.1.0");
+    assert_eq!(read_content_version(&backup_dir).unwrap().version, "1.0.0");
+
+    // 换入失败（新目录缺少向量库）时版本号与数据一同还原
+    fs::remove_dir_all(&backup_dir).unwrap();
+    fs::create_dir_all(&backup_dir).unwrap();
+    fs::write(new_dir.join("content.db"), b"newer").unwrap();
+    fs::write(
+        new_dir.join(CONTENT_VERSION_FILE),
+        r#"{"version":"1.2.0","installed_at":""}"#,
+    )
+    .unwrap();
+    assert!(swap_content_files(&data_dir, &new_dir, &backup_dir).is_err());
+    assert_eq!(read_content_version(&data_dir).unwrap().version, "1.1.0");
+    assert_ne!(fs::read(data_dir.join("content.db")).unwrap(), b"newer");
+}
+
+// ==========================================
+// 收藏导入导出
+// ==========================================
+
+#[test]
+fn exported_favorite_tags_accept_null_text_and_array() {
+    let parse = |tags: serde_json::Value| {
+        let favorite: ExportedFavorite = serde_json::from_value(serde_json::json!({
+            "law_id": "1",
+            "law_name": "中华人民共和国民法典",
+            "article_number": "第一条",
+            "content": "",
+            "tags": tags
+        }))
+        .unwrap();
+        favorite.tags
+    };
+
+    assert!(parse(serde_json::Value::Null).is_empty());
+    assert_eq!(
+        parse(serde_json::json!("合同, 违约，合同")),
+        vec!["合同", "违约"]
+    );
+    assert_eq!(
+        parse(serde_json::json!(["合同", null, " 违约 "])),
+        vec!["合同", "违约"]
+    );
+    assert!(parse(serde_json::json!("")).is_empty());
+
+    let missing: ExportedFavorite = serde_json::from_value(serde_json::json!({
+        "law_id": "1",
+        "law_name": "中华人民共和国民法典",
+        "article_number": "第一条",
+        "content": ""
+    }))
+    .unwrap();
+    assert!(missing.tags.is_empty());
+}
+
+// ==========================================
+// 收藏文件夹
+// ==========================================
+
+fn folder_id(app: &tauri::App<tauri::test::MockRuntime>, name: &str) -> i32 {
+    get_folders(app.state(), app.state())
+        .unwrap()
+        .into_iter()
+        .find(|f| f.name == name)
+        .unwrap()
+        .id
+}
+
+#[test]
+fn moving_folder_under_itself_or_descendant_is_rejected() {
+    let (_dir, app) = test_app();
+    create_folder("民法".to_string(), None, app.state(), app.state()).unwrap();
+    let civil = folder_id(&app, "民法");
+    create_folder("合同".to_string(), Some(civil), app.state(), app.state()).unwrap();
+    let contract = folder_id(&app, "合同");
+    create_folder("违约".to_string(), Some(contract), app.state(), app.state()).unwrap();
+    let breach = folder_id(&app, "违约");
+
+    for parent in [civil, contract, breach] {
+        let result = move_folder(civil, Some(parent), app.state(), app.state());
+        assert!(
+            matches!(result, Err(AppError::Validation(_))),
+            "parent {}",
+            parent
+        );
+    }
+    assert!(matches!(
+        move_folder(civil, Some(9999), app.state(), app.state()),
+        Err(AppError::NotFound(_))
+    ));
+
+    // 移到顶层或兄弟分支下是允许的，之后原来的祖先关系不再限制
+    move_folder(breach, None, app.state(), app.state()).unwrap();
+    move_folder(civil, Some(breach), app.state(), app.state()).unwrap();
+    let folders = get_folders(app.state(), app.state()).unwrap();
+    let parent_of = |id: i32| folders.iter().find(|f| f.id == id).unwrap().parent_id;
+    assert_eq!(parent_of(civil), Some(breach));
+    assert_eq!(parent_of(breach), None);
+    assert!(matches!(
+        move_folder(breach, Some(contract), app.state(), app.state()),
+        Err(AppError::Validation(_))
+    ));
+}
+
+#[test]
+fn folder_cycle_check_follows_whole_subtree() {
+    let conn = test_user_db();
+    conn.execute_batch(
+        "INSERT INTO favorite_folders (id, name, parent_id) VALUES
+            (1, 'a', NULL), (2, 'b', 1), (3, 'c', 2), (4, 'd', 1), (5, 'e', NULL);",
+    )
+    .unwrap();
+
+    assert!(folder_would_cycle(&conn, 1, Some(1)).unwrap());
+    assert!(folder_would_cycle(&conn, 1, Some(3)).unwrap());
+    assert!(folder_would_cycle(&conn, 2, Some(3)).unwrap());
+    assert!(!folder_would_cycle(&conn, 2, Some(4)).unwrap());
+    assert!(!folder_would_cycle(&conn, 1, Some(5)).unwrap());
+    assert!(!folder_would_cycle(&conn, 3, None).unwrap());
+}
+
+// ==========================================
+// 收藏列表
+// ==========================================
+
+#[test]
+fn favorites_page_filters_tags_and_paginates_in_sql() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    {
+        let conn = user_db(&state).unwrap();
+        for i in 1..=5 {
+            let tags = if i % 2 == 1 {
+                r#"["合同"]"#
+            } else {
+                r#"["侵权"]"#
+            };
+            conn.execute(
+                "INSERT INTO favorites (law_id, law_name, article_number, content, tags, created_at)
+                 VALUES (?1, '民法典', ?2, '', ?3, ?4)",
+                rusqlite::params![
+                    format!("id-{}", i),
+                    format!("第{}条", i),
+                    tags,
+                    format!("2024-01-0{} 00:00:00", i)
+                ],
+            )
+            .unwrap();
+        }
+        // 旧版本保存的逗号分隔标签，迁移后同样能按标签筛选
+        conn.execute(
+            "INSERT INTO favorites (law_id, law_name, article_number, content, tags, created_at)
+             VALUES ('id-6', '民法典', '第6条', '', '合同，违约', '2024-01-06 00:00:00')",
+            [],
+        )
+        .unwrap();
+        migrate_user_db(&conn).unwrap();
+    }
+
+    let page =
+        load_favorites_page(&state, Some("合同".into()), None, Some(1), Some(2), None).unwrap();
+    assert_eq!(page.total, 4);
+    let ids: Vec<&str> = page.items.iter().map(|f| f.law_id.as_str()).collect();
+    assert_eq!(ids, vec!["id-5", "id-3"]);
+
+    let page =
+        load_favorites_page(&state, None, None, Some(usize::MAX), Some(usize::MAX), None).unwrap();
+    assert_eq!(page.total, 6);
+    assert!(page.items.is_empty());
+
+    let page = load_favorites_page(
+        &state,
+        Some("合同".into()),
+        None,
+        Some(3),
+        Some(usize::MAX),
+        Some("article_number".into()),
+    )
+    .unwrap();
+    assert_eq!(page.total, 4);
+    let ids: Vec<&str> = page.items.iter().map(|f| f.law_id.as_str()).collect();
+    assert_eq!(ids, vec!["id-6"]);
+}
+
+#[test]
+fn favorites_page_reports_unreadable_rows() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    user_db(&state)
+        .unwrap()
+        .execute(
+            "INSERT INTO favorites (law_id, law_name, article_number, content, stale)
+             VALUES ('bad', '民法典', '第一条', '', 'not a number')",
+            [],
+        )
+        .unwrap();
+
+    let result = load_favorites_page(&state, None, None, None, None, None);
+    assert!(matches!(result, Err(AppError::UserDb(_))));
+}
+
+#[test]
+fn folder_export_uses_law_order_until_manually_reordered() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    create_folder("合同".to_string(), None, app.state(), app.state()).unwrap();
+    let folder = folder_id(&app, "合同");
+    {
+        let conn = user_db(&state).unwrap();
+        // 按收藏先后分配的 sort_order 与条号顺序相反
+        for (i, article) in ["第三条", "第十条", "第二条"].iter().enumerate() {
+            let law_id = format!("id-{}", i);
+            conn.execute(
+                "INSERT INTO favorites (law_id, law_name, article_number, content)
+                 VALUES (?1, '民法典', ?2, '')",
+                rusqlite::params![law_id, article],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO favorite_folder_links (law_id, folder_id, sort_order) VALUES (?1, ?2, ?3)",
+                rusqlite::params![law_id, folder, (i + 1) as f64],
+            )
+            .unwrap();
+        }
+    }
+    let articles = |state: &AppState| -> Vec<String> {
+        load_folder_export_items(&user_db(state).unwrap(), Some(folder))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.article_number)
+            .collect()
+    };
+
+    assert_eq!(articles(&state), vec!["第二条", "第三条", "第十条"]);
+
+    // 把第二条拖到最前之后按手动顺序导出
+    reorder_favorite(
+        "id-2".to_string(),
+        None,
+        Some(folder),
+        app.state(),
+        app.state(),
+    )
+    .unwrap();
+    assert_eq!(articles(&state), vec!["第二条", "第三条", "第十条"]);
+    reorder_favorite(
+        "id-0".to_string(),
+        Some("id-1".to_string()),
+        Some(folder),
+        app.state(),
+        app.state(),
+    )
+    .unwrap();
+    assert_eq!(articles(&state), vec!["第二条", "第十条", "第三条"]);
+}
+
+// ==========================================
+// 搜索历史
+// ==========================================
+
+#[test]
+fn prune_history_keeps_pinned_entries() {
+    let conn = test_user_db();
+    for i in 0..10 {
+        conn.execute(
+            "INSERT INTO search_history (query, timestamp, pinned) VALUES (?1, ?2, ?3)",
+            rusqlite::params![format!("q{}", i), i, (i < 3) as i64],
+        )
+        .unwrap();
+    }
+
+    prune_history(&conn, 2).unwrap();
+
+    let mut stmt = conn
+        .prepare("SELECT query FROM search_history ORDER BY timestamp")
+        .unwrap();
+    let remaining: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    // 最早的三条是置顶记录，即使超出上限也保留；未置顶的只留最新两条
+    assert_eq!(remaining, vec!["q0", "q1", "q2", "q8", "q9"]);
+
+    // 上限为 0 时仍按 1 处理，置顶记录依旧不动
+    prune_history(&conn, 0).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM search_history", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 4);
+}
+
+#[test]
+fn add_history_never_prunes_pinned_entries() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    settings_write_guard(&state).history_limit = 1;
+    add_history("置顶".to_string(), app.state(), app.state()).unwrap();
+    user_db(&state)
+        .unwrap()
+        .execute("UPDATE search_history SET pinned = 1", [])
+        .unwrap();
+
+    for query in ["甲", "乙", "丙"] {
+        add_history(query.to_string(), app.state(), app.state()).unwrap();
+    }
+
+    let queries: Vec<String> = get_history(app.state(), app.state())
+        .unwrap()
+        .into_iter()
+        .map(|item| item.query)
+        .collect();
+    // 同一秒内写入的记录时间戳相同，只断言条数与置顶记录
+    assert_eq!(queries.len(), 2);
+    assert_eq!(queries[0], "置顶");
+}
+
+// ==========================================
+// 检索
+// ==========================================
+
+#[tokio::test]
+async fn cached_search_reports_cache_hit() {
+    let (_dir, state) = test_state();
+    let config = SearchConfig::capture(&state);
+    write_content_db(&config.data_dir, 3);
+    let key = SearchCacheKey {
+        query: "试用期".to_string(),
+        filter_region: None,
+        embedding_model: embedding_cache_id(&config.settings),
+        top_k: config.settings.search_top_k,
+        data_dir: config.data_dir.clone(),
+    };
+    cache_search_hits(
+        &state,
+        key,
+        vec![("c2".to_string(), 0.1), ("c1".to_string(), 0.2)],
+    );
+
+    let outcome = vector_search_law("试用期".to_string(), None, 0, &config, &state)
+        .await
+        .unwrap();
+
+    assert!(outcome.cache_hit);
+    assert!(outcome.degraded.is_none());
+    let ids: Vec<&str> = outcome.results.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["c2", "c1"]);
+}
+
+// 复用连接省去每次打开文件和执行迁移的开销：依次调用始终拿到同一个连接
+#[test]
+fn pooled_user_db_skips_per_call_open_and_migration() {
+    let (_dir, state) = test_state();
+    // 临时表只对创建它的连接可见
+    user_db(&state)
+        .unwrap()
+        .execute_batch("CREATE TEMP TABLE pool_marker (id INTEGER)")
+        .unwrap();
+
+    for _ in 0..50 {
+        let conn = user_db(&state).unwrap();
+        conn.query_row("SELECT COUNT(*) FROM temp.pool_marker", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .unwrap();
+    }
+    assert_eq!(state.user_pool.idle.lock().unwrap().len(), 1);
+}
+
+// Agent 并发检索时各任务借还连接不能互相等待
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_searches_share_pool_without_deadlock() {
+    let (_dir, state) = test_state();
+    let state = Arc::new(state);
+    let config = SearchConfig::capture(&state);
+    write_content_db(&config.data_dir, 20);
+    let key = SearchCacheKey {
+        query: "劳动合同".to_string(),
+        filter_region: None,
+        embedding_model: embedding_cache_id(&config.settings),
+        top_k: config.settings.search_top_k,
+        data_dir: config.data_dir.clone(),
+    };
+    let hits = (1..=20).map(|i| (format!("c{}", i), i as f32)).collect();
+    cache_search_hits(&state, key, hits);
+
+    let tasks: Vec<_> = (0..16)
+        .map(|_| {
+            let state = Arc::clone(&state);
+            let config = SearchConfig::capture(&state);
+            tokio::spawn(async move {
+                vector_search_law("劳动合同".to_string(), None, 0, &config, &state)
+                    .await
+                    .map(|outcome| outcome.results.len())
+            })
+        })
+        .collect();
+    let all = tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(tasks))
+        .await
+        .expect("concurrent searches deadlocked");
+
+    for result in all {
+        assert_eq!(
+            result.unwrap().unwrap(),
+            config.settings.search_top_k.min(20)
+        );
+    }
+    assert!(state.content_pool.idle.lock().unwrap().len() <= SQLITE_POOL_MAX_IDLE);
+}
+
+// 超过 SQLite 999 个绑定参数的候选集：分批 IN 查询与临时表两条路径都要保持检索排名
+#[test]
+fn hydration_handles_candidate_sets_beyond_parameter_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 2500);
+    let conn = open_content_db(dir.path(), &ContentSchemaMap::default()).unwrap();
+
+    for count in [1500, HYDRATE_TEMP_TABLE_THRESHOLD + 500] {
+        let mut hits: Vec<(String, f32)> = (1..=count)
+            .rev()
+            .map(|i| (format!("c{}", i), i as f32))
+            .collect();
+        hits.push(("missing".to_string(), 0.0));
+
+        let slots = load_ranked_chunks(&conn, &hits, 0).unwrap();
+
+        assert_eq!(slots.len(), count + 1);
+        assert!(slots[count].is_none());
+        for (slot, (id, distance)) in slots.iter().zip(&hits).take(count) {
+            let chunk = slot.as_ref().unwrap();
+            assert_eq!(&chunk.id, id);
+            assert_eq!(chunk._distance, *distance);
+        }
+    }
+}
+
+// 收藏与历史从多个任务同时写入，不应出现 database is locked
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_favorite_and_history_writes_never_lock() {
+    let (_dir, app) = test_app();
+    const TASKS: usize = 8;
+    const WRITES: usize = 25;
+
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            let handle = app.handle().clone();
+            tokio::task::spawn_blocking(move || -> Result<(), AppError> {
+                for i in 0..WRITES {
+                    if task % 2 == 0 {
+                        let chunk = law_chunk(
+                            &format!("t{}-{}", task, i),
+                            "测试法",
+                            &format!("第{}条", i + 1),
+                        );
+                        insert_favorite(&handle.state::<AppState>(), chunk, None, None)?;
+                    } else {
+                        add_history(
+                            format!("查询{}-{}", task, i),
+                            handle.state(),
+                            handle.state(),
+                        )?;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for result in futures::future::join_all(tasks).await {
+        if let Err(e) = result.unwrap() {
+            panic!("concurrent write failed: {:?}", e);
+        }
+    }
+
+    let conn = user_db(&app.state::<AppState>()).unwrap();
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    };
+    assert_eq!(count("favorites"), (TASKS / 2 * WRITES) as i64);
+    // 历史超出上限时会被裁剪
+    assert_eq!(
+        count("search_history"),
+        (TASKS / 2 * WRITES).min(default_history_limit()) as i64
+    );
+}
+
+// ==========================================
+// Windows 路径
+// ==========================================
+
+#[test]
+fn extended_length_prefix_is_stripped() {
+    assert_eq!(
+        strip_extended_length_prefix(r"\\?\C:\Users\张三\AppData\Roaming\lawvault\user_data.db"),
+        r"C:\Users\张三\AppData\Roaming\lawvault\user_data.db"
+    );
+    assert_eq!(
+        strip_extended_length_prefix(r"\\?\UNC\nas\法规 数据\content.db"),
+        r"\\nas\法规 数据\content.db"
+    );
+}
+
+#[test]
+fn paths_without_extended_prefix_are_unchanged() {
+    for path in [
+        r"C:\Program Files\LawVault\法规 数据\content.db",
+        r"\\nas\share\content.db",
+        r"D:\我的 文档\user_data.db",
+        "/home/user/法规 数据/content.db",
+    ] {
+        assert_eq!(strip_extended_length_prefix(path), path);
+    }
+}
+
+// canonicalize 在 Windows 上返回 \\?\ 前缀路径，规范化后 SQLite 应能直接打开
+#[cfg(windows)]
+#[test]
+fn canonicalized_windows_path_opens_with_sqlite() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("法规 数据");
+    fs::create_dir_all(&data_dir).unwrap();
+    let db_path = fs::canonicalize(&data_dir).unwrap().join("user_data.db");
+    assert!(db_path.to_string_lossy().starts_with(r"\\?\"));
+
+    let normalized = normalize_db_path(&db_path);
+    assert!(!normalized.starts_with(r"\\?\"));
+    assert!(normalized.ends_with(r"法规 数据\user_data.db"));
+    let conn = connect_user_db(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO search_history (query, timestamp) VALUES ('测试', 0)",
+        [],
+    )
+    .unwrap();
+}
+
+// 配置目录只读时保存失败：返回带路径的错误，内存中的设置与磁盘保持一致
+#[test]
+fn failed_settings_write_keeps_memory_and_disk_unchanged() {
+    let (_dir, state) = test_state();
+    let before = snapshot_settings(&state);
+    let on_disk = fs::read(&state.settings_path).ok();
+    let mut changed = before.clone();
+    changed.plaintext_api_keys = true;
+    changed.search_top_k = before.search_top_k + 1;
+
+    // root 不受目录权限限制，同时用目录占住临时文件名，保证写入一定失败
+    let config_dir = state.settings_path.parent().unwrap().to_path_buf();
+    fs::create_dir(config_dir.join("settings.json.tmp")).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&config_dir, fs::Permissions::from_mode(0o555)).unwrap();
+    }
+
+    let result = commit_settings(&state, changed);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&config_dir, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let err = result.unwrap_err();
+    assert!(err.contains(&state.settings_path.display().to_string()));
+    assert_eq!(snapshot_settings(&state).search_top_k, before.search_top_k);
+    assert!(!snapshot_settings(&state).plaintext_api_keys);
+    assert_eq!(fs::read(&state.settings_path).ok(), on_disk);
+}
+
+// 快速输入时同时发出的相同查询只请求一次嵌入服务
+#[tokio::test]
+async fn identical_concurrent_searches_share_one_embedding_call() {
+    let responses = (0..5)
+        .map(|_| MockResponse::json(serde_json::json!({ "data": [{ "embedding": [1.0, 0.0] }] })))
+        .collect();
+    let (url, requests) = mock_server(responses).await;
+    let (_dir, state) = remote_embedding_state(&url);
+    let config = SearchConfig::capture(&state);
+    write_vector_table(
+        &config.data_dir,
+        vec![
+            ("c1".to_string(), vec![1.0, 0.0]),
+            ("c2".to_string(), vec![0.0, 1.0]),
+        ],
+    )
+    .await;
+    let key = SearchCacheKey {
+        query: "试用期".to_string(),
+        filter_region: None,
+        embedding_model: embedding_cache_id(&config.settings),
+        top_k: config.settings.search_top_k,
+        data_dir: config.data_dir.clone(),
+    };
+
+    let searches =
+        (0..5).map(|_| search_hits_deduped(&key, &config.settings, &config.data_dir, &state));
+    let results = futures::future::join_all(searches).await;
+
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    for hits in results {
+        let ids: Vec<String> = hits.unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["c1".to_string(), "c2".to_string()]);
+    }
+    assert!(state.search_in_flight.lock_or_recover().is_empty());
+}
+
+// 按线程统计分配字节数，测试之间并行运行也互不干扰
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED_BYTES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATED_BYTES.try_with(|n| n.set(n.get() + layout.size()));
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocated_bytes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED_BYTES.with(|n| n.get());
+    let value = f();
+    (value, ALLOCATED_BYTES.with(|n| n.get()) - before)
+}
+
+// 500 条长条文：列表预览只读取截断后的内容，分配量应远小于读取全文
+#[test]
+fn preview_hydration_allocates_less_than_full_content() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 500);
+    let conn = open_content_db(dir.path(), &ContentSchemaMap::default()).unwrap();
+    conn.execute(
+        "UPDATE chunks SET content = content || ?1",
+        [
+            "用人单位应当依法建立和完善劳动规章制度，保障劳动者享有劳动权利、履行劳动义务。"
+                .repeat(100),
+        ],
+    )
+    .unwrap();
+    let hits: Vec<(String, f32)> = (1..=500).map(|i| (format!("c{}", i), i as f32)).collect();
+
+    let (full, full_bytes) = allocated_bytes(|| load_ranked_chunks(&conn, &hits, 0).unwrap());
+    let (preview, preview_bytes) =
+        allocated_bytes(|| load_ranked_chunks(&conn, &hits, 200).unwrap());
+
+    assert_eq!(full.len(), preview.len());
+    assert!(preview
+        .iter()
+        .flatten()
+        .all(|chunk| chunk.content_truncated));
+    assert!(full.iter().flatten().all(|chunk| !chunk.content_truncated));
+    assert!(preview_bytes * 4 < full_bytes);
+    // 每行预览（200 字）连同其他字段不超过 4 KiB
+    assert!(preview_bytes < hits.len() * 4096);
+}
+
+// 新表已写入断点之后的一批、断点却没来得及更新时中断：续跑先删掉这批再写，不会重复
+#[tokio::test]
+async fn resumed_rebuild_does_not_duplicate_the_pending_batch() {
+    let (url, _) = mock_server(vec![
+        MockResponse::json(serde_json::json!({ "data": [{ "embedding": [1.0, 0.0] }] })),
+        MockResponse::json(serde_json::json!({ "data": [
+            { "index": 0, "embedding": [1.0, 0.0] },
+            { "index": 1, "embedding": [0.0, 1.0] }
+        ] })),
+    ])
+    .await;
+    let (_dir, state) = remote_embedding_state(&url);
+    let data_dir = get_effective_data_dir(&state);
+    write_content_db(&data_dir, 2);
+    write_vector_table(
+        &data_dir,
+        vec![
+            ("c1".to_string(), vec![1.0, 0.0]),
+            ("c2".to_string(), vec![0.0, 1.0]),
+        ],
+    )
+    .await;
+    let lancedb_dir = data_dir.join("law_db.lancedb");
+    fs::rename(
+        lancedb_dir.join("laws_vectors.lance"),
+        lancedb_dir.join(format!("{}.lance", REBUILD_TABLE)),
+    )
+    .unwrap();
+    let checkpoint = RebuildCheckpoint {
+        fingerprint: embedding_fingerprint(&embedding_config(&state), 2),
+        last_rowid: 0,
+    };
+    fs::write(
+        data_dir.join(REBUILD_CHECKPOINT_FILE),
+        serde_json::to_string(&checkpoint).unwrap(),
+    )
+    .unwrap();
+
+    let app = tauri::test::mock_app();
+    assert_eq!(run_vector_rebuild(app.handle(), &state).await.unwrap(), 2);
+
+    let table = connect_vector_db(&data_dir)
+        .await
+        .unwrap()
+        .open_table("laws_vectors")
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(table.count_rows(None).await.unwrap(), 2);
+    assert!(!data_dir.join(REBUILD_CHECKPOINT_FILE).exists());
+}
+
+// 200 条长条文经 IPC 返回：content_mode 为 preview 时序列化后的负载应远小于 full
+#[test]
+fn preview_content_mode_shrinks_serialized_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 200);
+    let conn = open_content_db(dir.path(), &ContentSchemaMap::default()).unwrap();
+    conn.execute(
+        "UPDATE chunks SET content = content || ?1",
+        ["劳动者依法享有平等就业和选择职业的权利。".repeat(100)],
+    )
+    .unwrap();
+    let hits: Vec<(String, f32)> = (1..=200).map(|i| (format!("c{}", i), i as f32)).collect();
+    let settings = AppSettings::default();
+    let payload = |mode| {
+        let chunks: Vec<LawChunk> =
+            load_ranked_chunks(&conn, &hits, preview_chars_for(Some(mode), &settings))
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect();
+        assert_eq!(chunks.len(), 200);
+        serde_json::to_vec(&chunks).unwrap().len()
+    };
+
+    let (full, preview) = (payload(ContentMode::Full), payload(ContentMode::Preview));
+    assert!(preview * 4 < full);
+}
+
+// ==========================================
+// 本地 HTTP API
+// ==========================================
+
+#[test]
+fn api_tokens_are_random_and_compared_exactly() {
+    let token = generate_api_token().unwrap();
+    assert_eq!(token.len(), 64);
+    assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_ne!(token, generate_api_token().unwrap());
+
+    assert!(api_token_matches(&token, &token));
+    assert!(!api_token_matches(&token[..63], &token));
+    assert!(!api_token_matches("", ""));
+}
+
+#[test]
+fn article_lookup_treats_like_wildcards_literally() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 2);
+    let conn = open_content_db(dir.path(), &ContentSchemaMap::default()).unwrap();
+
+    assert!(find_article_chunk(&conn, "测试法", "第1条")
+        .unwrap()
+        .is_some());
+    assert!(find_article_chunk(&conn, "%", "第1条").unwrap().is_none());
+    assert!(find_article_chunk(&conn, "测_法", "第1条")
+        .unwrap()
+        .is_none());
+    assert!(lookup_article(&conn, "%", "第2条").unwrap().is_none());
+}
+
+// ==========================================
+// MCP
+// ==========================================
+
+// 经管道与 MCP 服务交互的客户端：发送一条消息，有 id 时读取一行回复
+struct McpClient<R, W> {
+    lines: tokio::io::Lines<tokio::io::BufReader<R>>,
+    writer: W,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin, W: tokio::io::AsyncWrite + Unpin> McpClient<R, W> {
+    async fn send(&mut self, message: serde_json::Value) -> Option<serde_json::Value> {
+        let mut line = message.to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+        message.get("id")?;
+        let reply = self.lines.next_line().await.unwrap().unwrap();
+        Some(serde_json::from_str(&reply).unwrap())
+    }
+}
+
+type PipeReader = tokio::io::ReadHalf<tokio::io::DuplexStream>;
+type PipeWriter = tokio::io::WriteHalf<tokio::io::DuplexStream>;
+
+fn mcp_pipe() -> (
+    McpClient<PipeReader, PipeWriter>,
+    tokio::io::BufReader<PipeReader>,
+    PipeWriter,
+) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (client_in, client_out) = tokio::io::split(client);
+    let (server_in, server_out) = tokio::io::split(server);
+    let client = McpClient {
+        lines: tokio::io::AsyncBufReadExt::lines(tokio::io::BufReader::new(client_in)),
+        writer: client_out,
+    };
+    (client, tokio::io::BufReader::new(server_in), server_out)
+}
+
+#[tokio::test]
+async fn mcp_handshake_and_tool_call_over_pipe() {
+    let (_dir, state) = test_state();
+    write_content_db(&get_effective_data_dir(&state), 2);
+    let (mut client, server_in, mut server_out) = mcp_pipe();
+
+    let server = serve_mcp(&state, None, server_in, &mut server_out);
+    let session = async move {
+        let init = client
+            .send(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "1.0" }
+                }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(init["id"], 1);
+        assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(init["result"]["serverInfo"]["name"], "lawvault");
+
+        let notification = client
+            .send(serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await;
+        assert!(notification.is_none());
+
+        let tools = client
+            .send(serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }))
+            .await
+            .unwrap();
+        let names: Vec<&str> = tools["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["search_law", "get_article", "get_full_text"]);
+
+        let article = client
+            .send(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "tools/call",
+                "params": {
+                    "name": "get_article",
+                    "arguments": { "law_name": "测试法", "article_number": "第2条" }
+                }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(article["result"]["isError"], false);
+        assert_eq!(
+            article["result"]["structuredContent"]["results"][0]["citation"],
+            "《测试法》第2条"
+        );
+
+        let unknown = client
+            .send(serde_json::json!({ "jsonrpc": "2.0", "id": 4, "method": "resources/list" }))
+            .await
+            .unwrap();
+        assert_eq!(unknown["error"]["code"], -32601);
+
+        client.writer.shutdown().await.unwrap();
+    };
+
+    let (code, ()) = tokio::join!(server, session);
+    assert_eq!(code, 0);
+}
+
+#[tokio::test]
+async fn mcp_reports_startup_error_and_exits() {
+    let (_dir, state) = test_state();
+    let (mut client, server_in, mut server_out) = mcp_pipe();
+
+    let server = serve_mcp(
+        &state,
+        Some("未找到向量数据库".to_string()),
+        server_in,
+        &mut server_out,
+    );
+    let session = async move {
+        let reply = client
+            .send(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": { "protocolVersion": "2025-06-18" }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(reply["error"]["code"], -32002);
+        assert_eq!(reply["error"]["message"], "未找到向量数据库");
+    };
+
+    let (code, ()) = tokio::join!(server, session);
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn standalone_config_dir_follows_tauri_identifier() {
+    let context = app_context();
+    let dir = standalone_app_config_dir(&context.config().identifier);
+    assert!(dir.ends_with(&context.config().identifier));
+    assert_eq!(dir.parent(), dirs::config_dir().as_deref());
+}
+
+// ==========================================
+// 深度链接
+// ==========================================
+
+#[test]
+fn deep_link_segments_are_percent_decoded() {
+    let url = tauri::Url::parse(
+        "lawvault://law/%E6%B0%91%E6%B3%95%E5%85%B8/%E7%AC%AC%E4%BA%94%E7%99%BE%E6%9D%A1",
+    )
+    .unwrap();
+    match parse_deep_link(&url).unwrap() {
+        DeepLink::Law { name, article } => {
+            assert_eq!(name, "民法典");
+            assert_eq!(article.as_deref(), Some("第五百条"));
+        }
+        DeepLink::Search { .. } => panic!("expected a law link"),
+    }
+
+    // 残缺的编码按原样保留，无效的 UTF-8 以替换字符代替
+    let url = tauri::Url::parse("lawvault://law/100%25%E6%B0/%zz").unwrap();
+    match parse_deep_link(&url).unwrap() {
+        DeepLink::Law { name, article } => {
+            assert_eq!(name, "100%\u{FFFD}");
+            assert_eq!(article.as_deref(), Some("%zz"));
+        }
+        DeepLink::Search { .. } => panic!("expected a law link"),
+    }
+}
+
+// ==========================================
+// 批注
+// ==========================================
+
+#[test]
+fn annotation_offsets_are_utf16_positions() {
+    // 𠀀 在 UTF-16 中占两个单位（代理对）
+    let text = "第一条 𠀀民法典规定。";
+    let range = validate_annotation_range(text, 6, 9).unwrap();
+    assert_eq!(&text[range], "民法典");
+    assert_eq!(&text[validate_annotation_range(text, 4, 6).unwrap()], "𠀀");
+
+    assert!(validate_annotation_range(text, 5, 7).is_err());
+    assert!(validate_annotation_range(text, 6, 13).is_err());
+    assert!(validate_annotation_range(text, 6, 6).is_err());
+    assert_eq!(
+        &text[validate_annotation_range(text, 9, 12).unwrap()],
+        "规定。"
+    );
+}
+
+#[test]
+fn reconcile_relocates_annotations_by_utf16_offset() {
+    let conn = test_user_db();
+    conn.execute(
+        "INSERT INTO annotations (law_name, start_offset, end_offset, quote, color)
+         VALUES ('测试法', 4, 7, '民法典', 'yellow'), ('测试法', 0, 2, '删除', 'yellow')",
+        [],
+    )
+    .unwrap();
+
+    let report =
+        reconcile_law_annotations(&conn, "测试法", "新增条款。第一条 民法典规定。").unwrap();
+
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.relocated, 1);
+    assert_eq!(report.orphaned.len(), 1);
+    let (start, end): (i64, i64) = conn
+        .query_row(
+            "SELECT start_offset, end_offset FROM annotations WHERE quote = '民法典'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((start, end), (9, 12));
+}
+
+// ==========================================
+// 条文互引
+// ==========================================
+
+#[test]
+fn cross_refs_accept_arabic_article_numbers() {
+    let refs = extract_cross_refs("依照《民法典》第1165条、本法第3条之1及第十条规定");
+    let articles: Vec<(Option<String>, String)> = refs
+        .into_iter()
+        .map(|(law, article, _)| (law, article))
+        .collect();
+    assert_eq!(
+        articles,
+        vec![
+            (Some("民法典".to_string()), "第一千一百六十五条".to_string()),
+            (None, "第三条之一".to_string()),
+            (None, "第十条".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn cross_ref_index_lives_outside_content_db() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    let data_dir = get_effective_data_dir(&state);
+    write_content_db(&data_dir, 3);
+    {
+        let conn = Connection::open(data_dir.join("content.db")).unwrap();
+        conn.execute(
+            "UPDATE chunks SET article_number = '第一条' WHERE id = 'c1'",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE chunks SET content = '第2条 依照本法第1条处理。' WHERE id = 'c2'",
+            [],
+        )
+        .unwrap();
+    }
+
+    let index_path = cross_ref_db_path(&state, &data_dir);
+    let stats = run_cross_ref_index(app.handle(), &data_dir, &index_path).unwrap();
+    assert_eq!(stats.scanned_laws, 1);
+    assert_eq!(stats.new_edges, 1);
+    assert!(index_path.starts_with(state.user_db_path.parent().unwrap()));
+
+    let incoming = load_cross_ref_chunks(&state, "c1", false).unwrap();
+    assert_eq!(incoming.len(), 1);
+    assert_eq!(incoming[0].id, "c2");
+    let outgoing = load_cross_ref_chunks(&state, "c2", true).unwrap();
+    assert_eq!(outgoing[0].id, "c1");
+
+    let conn = Connection::open(data_dir.join("content.db")).unwrap();
+    let tables: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name LIKE 'cross_ref%'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(tables, 0);
+
+    // 已扫描的法规不再重复处理；数据被替换后重置索引
+    let again = run_cross_ref_index(app.handle(), &data_dir, &index_path).unwrap();
+    assert_eq!(again.scanned_laws, 0);
+    reset_cross_ref_index(&state, &data_dir);
+    assert!(!index_path.exists());
+    assert!(load_cross_ref_chunks(&state, "c1", false)
+        .unwrap()
+        .is_empty());
+}
+
+// ==========================================
+// 收藏复习
+// ==========================================
+
+#[test]
+fn review_intervals_grow_with_successful_recalls() {
+    let day = 86400;
+    let first = ReviewSchedule::new(0).next(2, 0);
+    assert_eq!((first.interval_days, first.repetitions), (1.0, 1));
+    assert_eq!(first.due_at, day);
+
+    let second = first.next(2, day);
+    assert_eq!((second.interval_days, second.repetitions), (3.0, 2));
+    assert_eq!(second.due_at, 4 * day);
+
+    // 之后按 ease 倍增并取整：3 × 2.5 = 7.5 -> 8
+    let third = second.next(2, 4 * day);
+    assert_eq!((third.interval_days, third.repetitions), (8.0, 3));
+    assert_eq!(third.ease, REVIEW_DEFAULT_EASE);
+
+    // 轻松：8 × 2.5 × 1.3 = 26，ease 上调
+    let easy = third.next(3, 0);
+    assert_eq!(easy.interval_days, 26.0);
+    assert!((easy.ease - 2.65).abs() < 1e-9);
+}
+
+#[test]
+fn review_lapses_and_hard_grades_shrink_intervals() {
+    let learned = ReviewSchedule::new(0).next(2, 0).next(2, 0).next(2, 0);
+
+    let forgot = learned.next(0, 1000);
+    assert_eq!((forgot.interval_days, forgot.repetitions), (0.0, 0));
+    assert_eq!(forgot.due_at, 1000 + REVIEW_RELEARN_SECS);
+    assert!((forgot.ease - 2.3).abs() < 1e-9);
+
+    // 困难：间隔 ×1.2 且至少一天
+    let hard = learned.next(1, 0);
+    assert_eq!(hard.interval_days, 10.0);
+    assert_eq!(forgot.next(1, 0).interval_days, 1.0);
+
+    // 首次即轻松至少 4 天
+    assert_eq!(ReviewSchedule::new(0).next(3, 0).interval_days, 4.0);
+}
+
+#[test]
+fn review_ease_stays_within_bounds() {
+    let mut schedule = ReviewSchedule::new(0);
+    for _ in 0..20 {
+        schedule = schedule.next(0, 0);
+    }
+    assert_eq!(schedule.ease, REVIEW_MIN_EASE);
+    for _ in 0..20 {
+        schedule = schedule.next(3, 0);
+    }
+    assert_eq!(schedule.ease, REVIEW_MAX_EASE);
+}
+
+// ==========================================
+// 收藏文件夹迁移
+// ==========================================
+
+// 文件夹改为多对多之前的用户库：收藏直接记录 folder_id
+fn legacy_user_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE favorite_folders (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP, parent_id INTEGER);
+         CREATE TABLE favorites (id INTEGER PRIMARY KEY AUTOINCREMENT, law_id TEXT UNIQUE,
+            law_name TEXT, article_number TEXT, content TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP, tags TEXT, folder_id INTEGER,
+            sort_order REAL);
+         INSERT INTO favorite_folders (id, name) VALUES (1, '劳动'), (2, '合同');
+         INSERT INTO favorites (law_id, law_name, article_number, folder_id, sort_order) VALUES
+            ('a', '劳动法', '第一条', 1, 2.0),
+            ('b', '民法典', '第二条', 2, 1.0),
+            ('c', '民法典', '第三条', 9, 3.0),
+            ('d', '刑法', '第四条', NULL, 4.0);",
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn legacy_folder_ids_migrate_to_links() {
+    let conn = legacy_user_db();
+    migrate_user_db(&conn).unwrap();
+
+    let mut stmt = conn
+        .prepare("SELECT law_id, folder_id, sort_order FROM favorite_folder_links ORDER BY law_id")
+        .unwrap();
+    let links: Vec<(String, i64, f64)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    // 指向已删除文件夹的收藏不生成链接
+    assert_eq!(
+        links,
+        vec![("a".to_string(), 1, 2.0), ("b".to_string(), 2, 1.0)]
+    );
+    let remaining: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM favorites WHERE folder_id IS NOT NULL",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(remaining, 0);
+    let favorites: i64 = conn
+        .query_row("SELECT COUNT(*) FROM favorites", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(favorites, 4);
+
+    // 重复迁移不改变结果
+    migrate_user_db(&conn).unwrap();
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM favorite_folder_links", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn failed_folder_link_migration_rolls_back() {
+    let conn = legacy_user_db();
+    conn.execute_batch(
+        "CREATE TABLE favorite_folder_links (law_id TEXT NOT NULL, folder_id INTEGER NOT NULL,
+            sort_order REAL, created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (law_id, folder_id));
+         CREATE TRIGGER fail_clear BEFORE UPDATE OF folder_id ON favorites
+         BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+    )
+    .unwrap();
+
+    assert!(migrate_favorite_folder_links(&conn).is_err());
+    assert!(conn.is_autocommit());
+    let links: i64 = conn
+        .query_row("SELECT COUNT(*) FROM favorite_folder_links", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(links, 0);
+    let legacy: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM favorites WHERE folder_id IS NOT NULL",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(legacy, 3);
+}
+
+#[test]
+fn favorite_into_missing_folder_is_rejected() {
+    let (_dir, state) = test_state();
+    let result = insert_favorite(&state, law_chunk("c1", "测试法", "第一条"), Some(42), None);
+    assert!(matches!(result, Err(AppError::NotFound(_))));
+    let favorites: i64 = user_db(&state)
+        .unwrap()
+        .query_row("SELECT COUNT(*) FROM favorites", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(favorites, 0);
+}
+
+// ==========================================
+// 按引用收藏
+// ==========================================
+
+// 刑法与刑法修正案都有第二十条，法名写法不同的引用都应落到刑法本身
+fn write_criminal_law_fixture(data_dir: &std::path::Path) {
+    write_content_db(data_dir, 0);
+    let conn = Connection::open(data_dir.join("content.db")).unwrap();
+    for (id, law) in [
+        ("x1", "中华人民共和国刑法"),
+        ("x2", "中华人民共和国刑法修正案（十一）"),
+    ] {
+        conn.execute(
+            "INSERT INTO chunks VALUES (?1, '第二十条 正当防卫。', ?2, '法律', '', '', '', '', '第二十条')",
+            [id, law],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO full_texts VALUES (?1, '', '法律', '')", [law])
+            .unwrap();
+    }
+}
+
+#[test]
+fn single_citation_parsing_is_shared() {
+    for text in [
+        "《刑法》第20条",
+        "刑法第二十条",
+        "刑法第20条",
+        " 《刑法第20条 ",
+    ] {
+        let citation = parse_single_citation(text).unwrap();
+        assert_eq!(
+            (citation.law_name.as_str(), citation.article_number.as_str()),
+            ("刑法", "第二十条"),
+            "{}",
+            text
+        );
+    }
+    assert!(parse_single_citation("第二十条").is_none());
+    assert!(parse_single_citation("正当防卫").is_none());
+}
+
+#[test]
+fn favorite_by_citation_resolves_short_law_names() {
+    let (_dir, app) = test_app();
+    let state = app.state::<AppState>();
+    write_criminal_law_fixture(&get_effective_data_dir(&state));
+
+    let favorite =
+        favorite_by_citation("刑法第20条".to_string(), None, state.clone(), state.clone()).unwrap();
+    assert_eq!(favorite.law_id, "x1");
+    assert_eq!(favorite.law_name, "中华人民共和国刑法");
+
+    // 法名中的 % 不作为通配符
+    let result = favorite_by_citation(
+        "刑法%第二十条".to_string(),
+        None,
+        state.clone(),
+        state.clone(),
+    );
+    assert!(matches!(result, Err(AppError::NotFound(_))));
+    let result = favorite_by_citation("《民法典》第一条".to_string(), None, state.clone(), state);
+    assert!(matches!(result, Err(AppError::NotFound(_))));
+}
+
+// ==========================================
+// 条文对比
+// ==========================================
+
+fn diff_ops(hunks: &[DiffHunk]) -> Vec<(DiffOp, &str)> {
+    hunks.iter().map(|h| (h.op, h.text.as_str())).collect()
+}
+
+#[tokio::test]
+async fn identical_texts_diff_to_one_equal_hunk() {
+    let text = "第一条 为了保护民事主体的合法权益，制定本法。";
+    let hunks = compute_diff_blocking(text.to_string(), text.to_string())
+        .await
+        .unwrap();
+    assert_eq!(diff_ops(&hunks), vec![(DiffOp::Equal, text)]);
+    assert!(compute_diff_blocking(String::new(), String::new())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn punctuation_only_changes_are_isolated() {
+    let hunks = compute_diff_blocking(
+        "当事人应当遵循诚信原则，按照约定全面履行义务。".to_string(),
+        "当事人应当遵循诚信原则；按照约定全面履行义务".to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        diff_ops(&hunks),
+        vec![
+            (DiffOp::Equal, "当事人应当遵循诚信原则"),
+            (DiffOp::Delete, "，"),
+            (DiffOp::Insert, "；"),
+            (DiffOp::Equal, "按照约定全面履行义务"),
+            (DiffOp::Delete, "。"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn oversized_diff_is_rejected() {
+    let long = "法".repeat(DIFF_MAX_CHARS + 1);
+    let result = compute_diff_blocking(long, "法".to_string()).await;
+    assert!(matches!(result, Err(AppError::Validation(_))));
+}
+
+// ==========================================
+// 查询分类
+// ==========================================
+
+#[test]
+fn classify_query_labelled_cases() {
+    use QueryKind::{Citation, LawName, Semantic};
+
+    let cases: [(&str, Option<QueryKind>); 30] = [
+        // 引用
+        ("《民法典》第五百条", Some(Citation)),
+        ("民法典第500条", Some(Citation)),
+        ("《刑法》第20条", Some(Citation)),
+        ("劳动合同法第39条", Some(Citation)),
+        ("公司法第一百四十七条", Some(Citation)),
+        (" 《民法典》第一千零四十三条 ", Some(Citation)),
+        // 法规名
+        ("《劳动合同法》", Some(LawName)),
+        ("民法典", Some(LawName)),
+        ("劳动合同法", Some(LawName)),
+        ("治安管理处罚法", Some(LawName)),
+        ("立法法", Some(LawName)),
+        ("道路交通安全法实施条例", Some(LawName)),
+        ("住房公积金管理条例", Some(LawName)),
+        (
+            "最高人民法院关于审理劳动争议案件适用法律问题的解释",
+            Some(LawName),
+        ),
+        // 语义检索
+        ("试用期可以延长吗", Some(Semantic)),
+        ("公司拖欠工资怎么办", Some(Semantic)),
+        ("劳动合同到期不续签是否有经济补偿", Some(Semantic)),
+        ("醉酒驾驶机动车会被判刑？", Some(Semantic)),
+        (
+            "用人单位未依法为劳动者缴纳社会保险费，劳动者解除劳动合同",
+            Some(Semantic),
+        ),
+        ("交通事故中机动车一方的赔偿责任比例划分标准", Some(Semantic)),
+        ("员工在上下班途中发生交通事故认定工伤", Some(Semantic)),
+        ("应当承担什么责任", Some(Semantic)),
+        // 以“法”结尾的普通词不是法规名，交给大模型判断
+        ("合同违法", None),
+        ("行政执法", None),
+        ("工作方法", None),
+        ("刑事司法程序中认定证据合法", Some(Semantic)),
+        // 短关键词与英文
+        ("劳动合同", None),
+        ("第二十条", None),
+        ("Civil Code", None),
+        ("", None),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(classify_query(query), expected, "{:?}", query);
+    }
+}
+
+// ==========================================
+// 查询改写
+// ==========================================
+
+#[test]
+fn rewrite_rules_keep_domain_terms() {
+    let (_dir, state) = test_state();
+    assert_eq!(
+        rewrite_query(&state, "被炒了怎么办").as_deref(),
+        Some("被解除劳动合同了")
+    );
+    // 公司法、侵权赔偿中的“公司”“赔偿”本身就是法律用语
+    assert_eq!(rewrite_query(&state, "公司股东损害赔偿的责任"), None);
+    // 引用与法规名不改写
+    assert_eq!(rewrite_query(&state, "《劳动合同法》第39条"), None);
+}
+
+#[test]
+fn rewrite_falls_back_when_rules_cannot_load() {
+    let (_dir, state) = test_state();
+    user_db(&state)
+        .unwrap()
+        .execute("DROP TABLE query_rewrite_rules", [])
+        .unwrap();
+    assert_eq!(rewrite_query(&state, "老板欠薪"), None);
+}
+
+// ==========================================
+// 术语释义
+// ==========================================
+
+fn defined_terms(content: &str) -> Vec<String> {
+    find_definitions(content)
+        .into_iter()
+        .map(|(term, _)| term)
+        .collect()
+}
+
+#[test]
+fn definitions_found_in_statutory_articles() {
+    let cases = [
+        // 刑法第九十四条
+        (
+            "本法所称司法工作人员，是指有侦查、检察、审判、监管职责的工作人员。",
+            vec!["司法工作人员"],
+        ),
+        // 产品质量法第二条第二款
+        (
+            "本法所称产品是指经过加工、制作，用于销售的产品。",
+            vec!["产品"],
+        ),
+        // 行政许可法第二条
+        (
+            "本法所称行政许可，是指行政机关根据公民、法人或者其他组织的申请，经依法审查，准予其从事特定活动的行为。",
+            vec!["行政许可"],
+        ),
+        // 个人信息保护法第七十三条
+        (
+            "本法下列用语的含义：\n（一）个人信息处理者，是指在个人信息处理活动中自主决定处理目的、处理方式的组织、个人。\n（二）自动化决策，是指通过计算机程序自动分析、评估个人的行为习惯、兴趣爱好或者经济、健康、信用状况，并进行决策的活动。",
+            vec!["个人信息处理者", "自动化决策"],
+        ),
+        // 道路交通安全法第一百一十九条
+        (
+            "（一）“道路”，是指公路、城市道路和虽在单位管辖范围但允许社会机动车通行的地方。",
+            vec!["道路"],
+        ),
+        ("本条例所称托育机构，指为三周岁以下婴幼儿提供全日制托育服务的机构。", vec!["托育机构"]),
+    ];
+    for (content, expected) in &cases {
+        assert_eq!(defined_terms(content), expected.clone(), "{}", content);
+        assert!(has_definition_marker(content));
+    }
+
+    let (_, sentence) = find_definitions(cases[0].0).remove(0);
+    assert_eq!(sentence, cases[0].0);
+}
+
+#[test]
+fn comma_zhi_verbs_are_not_definitions() {
+    for content in [
+        "当事人协商不成的，指定一名诉讼代表人。",
+        "县级以上人民政府应当加强领导，指导监督检查工作。",
+        "受害人可以提供证据，指明侵权人。",
+        "人民法院认为必要时，指令下级人民法院再审。",
+    ] {
+        assert!(defined_terms(content).is_empty(), "{}", content);
+        assert!(!has_definition_marker(content), "{}", content);
+    }
+}
+
+#[test]
+fn definition_prefilter_skips_comma_zhi_verbs() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 0);
+    let conn = Connection::open(dir.path().join("content.db")).unwrap();
+    conn.execute_batch(
+        "INSERT INTO chunks VALUES ('d1', '托育机构由县级人民政府负责，指定专人管理。', '托育条例', '', '', '', '', '', '第一条');
+         INSERT INTO chunks VALUES ('d2', '本条例所称托育机构，指为婴幼儿提供托育服务的机构。', '托育条例', '', '', '', '', '', '第二条');",
+    )
+    .unwrap();
+
+    let found = pattern_definition(&conn, "托育机构").unwrap().unwrap();
+    assert_eq!(found.chunk.unwrap().id, "d2");
+    assert_eq!(
+        found.definition,
+        "本条例所称托育机构，指为婴幼儿提供托育服务的机构。"
+    );
+}
+
+// ==========================================
+// 多数据源
+// ==========================================
+
+#[tokio::test]
+async fn agent_vector_search_covers_extra_sources() {
+    let (dir, state) = remote_embedding_state("http://127.0.0.1:9");
+    let extra = dir.path().join("extra");
+    let rows = vec![
+        ("c1".to_string(), vec![1.0, 0.0]),
+        ("c2".to_string(), vec![0.0, 1.0]),
+    ];
+    let primary = get_effective_data_dir(&state);
+    for data_dir in [&primary, &extra] {
+        write_content_db(data_dir, 2);
+        write_vector_table(data_dir, rows.clone()).await;
+    }
+    Connection::open(extra.join("content.db"))
+        .unwrap()
+        .execute(
+            "INSERT INTO full_texts VALUES ('测试法', '', '法律', '')",
+            [],
+        )
+        .unwrap();
+    settings_write_guard(&state).data_sources = vec![DataSource {
+        name: "地方库".to_string(),
+        path: extra.to_string_lossy().to_string(),
+        enabled: true,
+    }];
+    let config = SearchConfig::capture(&state);
+
+    let results = search_law_by_vector(vec![1.0, 0.0], None, &config, &state)
+        .await
+        .unwrap();
+    let sources: HashSet<Option<&str>> = results
+        .iter()
+        .filter(|chunk| chunk.id == "c1")
+        .map(|chunk| chunk.source_name.as_deref())
+        .collect();
+    assert_eq!(sources, HashSet::from([None, Some("地方库")]));
+
+    // 法规名联想同样包含附加数据源
+    let names = search_law_names(&state, "测试", 10).unwrap();
+    assert!(names
+        .iter()
+        .any(|name| name.source_name.as_deref() == Some("地方库")));
+}
+
+// Agent 运行中途切换数据目录：同一次运行的检索都来自开始时的目录
+#[tokio::test]
+async fn agent_run_keeps_data_dir_captured_at_start() {
+    let (dir, state) = remote_embedding_state("http://127.0.0.1:9");
+    let original = get_effective_data_dir(&state);
+    let other = dir.path().join("other");
+    let rows = vec![
+        ("c1".to_string(), vec![1.0, 0.0]),
+        ("c2".to_string(), vec![0.0, 1.0]),
+    ];
+    for data_dir in [&original, &other] {
+        write_content_db(data_dir, 2);
+        write_vector_table(data_dir, rows.clone()).await;
+    }
+    Connection::open(other.join("content.db"))
+        .unwrap()
+        .execute("UPDATE chunks SET law_name = '新目录法'", [])
+        .unwrap();
+    let config = SearchConfig::capture(&state);
+
+    let mut law_names = HashSet::new();
+    for (step, vector) in [vec![1.0, 0.0], vec![0.0, 1.0]].into_iter().enumerate() {
+        if step == 1 {
+            settings_write_guard(&state).custom_data_path =
+                Some(other.to_string_lossy().to_string());
+        }
+        let results = search_law_by_vector(vector, None, &config, &state)
+            .await
+            .unwrap();
+        assert!(!results.is_empty());
+        law_names.extend(results.into_iter().map(|chunk| chunk.law_name));
+    }
+    assert_eq!(law_names, HashSet::from(["测试法".to_string()]));
+
+    // 之后开始的运行取到新目录
+    let config = SearchConfig::capture(&state);
+    let results = search_law_by_vector(vec![1.0, 0.0], None, &config, &state)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|chunk| chunk.law_name == "新目录法"));
+}
+
+// ==========================================
+// content.db 列映射
+// ==========================================
+
+fn columns(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn owned_pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(a, b)| (a.to_string(), b.to_string()))
+        .collect()
+}
+
+#[test]
+fn standard_columns_need_no_mapping() {
+    let actual = columns(&[
+        "id",
+        "content",
+        "law_name",
+        "category",
+        "region",
+        "publish_date",
+        "part",
+        "chapter",
+        "Article_Number",
+    ]);
+    let mapping = map_table_schema("chunks", &actual, CHUNKS_SCHEMA, &HashMap::new());
+    assert!(mapping.mapped.is_empty());
+    assert!(mapping.defaulted.is_empty());
+    assert!(mapping.missing_required.is_empty());
+}
+
+#[test]
+fn aliases_map_and_optional_columns_default() {
+    let actual = columns(&["chunk_id", "text", "title", "article", "area"]);
+    let mapping = map_table_schema("chunks", &actual, CHUNKS_SCHEMA, &HashMap::new());
+    assert_eq!(
+        mapping.mapped,
+        owned_pairs(&[
+            ("id", "chunk_id"),
+            ("content", "text"),
+            ("law_name", "title"),
+            ("region", "area"),
+            ("article_number", "article"),
+        ])
+    );
+    assert_eq!(
+        mapping.defaulted,
+        columns(&["category", "publish_date", "part", "chapter"])
+    );
+    assert!(mapping.missing_required.is_empty());
+}
+
+#[test]
+fn overrides_win_and_missing_override_columns_fall_back() {
+    let actual = columns(&["title", "full_name", "content"]);
+    let overrides = HashMap::from([
+        ("law_name".to_string(), "full_name".to_string()),
+        ("full_text".to_string(), "no_such_column".to_string()),
+    ]);
+    let mapping = map_table_schema("full_texts", &actual, FULL_TEXTS_SCHEMA, &overrides);
+    assert_eq!(
+        mapping.mapped,
+        owned_pairs(&[("law_name", "full_name"), ("full_text", "content")])
+    );
+    assert_eq!(mapping.defaulted, columns(&["region", "category"]));
+}
+
+#[test]
+fn missing_required_columns_are_reported() {
+    let actual = columns(&["uid", "body"]);
+    let mapping = map_table_schema("chunks", &actual, CHUNKS_SCHEMA, &HashMap::new());
+    assert_eq!(
+        mapping.missing_required,
+        columns(&["law_name", "article_number"])
+    );
+}
+
+// 两种列名互不相同的第三方数据集
+fn write_divergent_content_dbs(root: &std::path::Path) -> (PathBuf, PathBuf) {
+    let aliased = root.join("aliased");
+    let custom = root.join("custom");
+    for dir in [&aliased, &custom] {
+        fs::create_dir_all(dir).unwrap();
+    }
+    Connection::open(aliased.join("content.db"))
+        .unwrap()
+        .execute_batch(
+            "CREATE TABLE chunks (chunk_id TEXT, text TEXT, title TEXT, type TEXT, area TEXT,
+                date TEXT, article TEXT);
+             INSERT INTO chunks VALUES ('a1', '第一条 内容', '甲法', '法律', '北京', '2020-01-01', '第一条');
+             CREATE TABLE full_texts (title TEXT, content TEXT);
+             INSERT INTO full_texts VALUES ('甲法', '甲法全文');",
+        )
+        .unwrap();
+    Connection::open(custom.join("content.db"))
+        .unwrap()
+        .execute_batch(
+            "CREATE TABLE chunks (uid TEXT, body TEXT, statute TEXT, law TEXT, clause TEXT);
+             INSERT INTO chunks VALUES ('b1', '第二条 内容', '乙条例', '旧名', '第二条');
+             CREATE TABLE full_texts (statute TEXT, body TEXT);
+             INSERT INTO full_texts VALUES ('乙条例', '乙条例全文');",
+        )
+        .unwrap();
+    (aliased, custom)
+}
+
+fn first_chunk(conn: &Connection) -> (String, String, String, String) {
+    conn.query_row(
+        "SELECT id, law_name, article_number, region FROM chunks",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .unwrap()
+}
+
+#[test]
+fn divergent_schemas_map_per_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let (aliased, custom) = write_divergent_content_dbs(dir.path());
+
+    let conn = open_content_db(&aliased, &ContentSchemaMap::default()).unwrap();
+    assert_eq!(
+        first_chunk(&conn),
+        (
+            "a1".to_string(),
+            "甲法".to_string(),
+            "第一条".to_string(),
+            "北京".to_string()
+        )
+    );
+
+    // 第二个数据集需要显式映射；映射只属于各自的 AppState，互不影响
+    let (_state_dir, state) = test_state();
+    settings_write_guard(&state).content_schema_map = ContentSchemaMap {
+        chunks: HashMap::from([("law_name".to_string(), "statute".to_string())]),
+        full_texts: HashMap::from([
+            ("law_name".to_string(), "statute".to_string()),
+            ("full_text".to_string(), "body".to_string()),
+        ]),
+    };
+    let (_other_dir, other) = test_state();
+
+    let conn = connect_sqlite(&state, &custom).unwrap();
+    assert_eq!(
+        first_chunk(&conn),
+        (
+            "b1".to_string(),
+            "乙条例".to_string(),
+            "第二条".to_string(),
+            String::new()
+        )
+    );
+    let full_text: String = conn
+        .query_row(
+            "SELECT full_text FROM full_texts WHERE law_name = '乙条例'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(full_text, "乙条例全文");
+
+    // 未设置映射时按别名 law 取值
+    let conn = connect_sqlite(&other, &custom).unwrap();
+    assert_eq!(first_chunk(&conn).1, "旧名");
+    let conn = connect_sqlite(&other, &aliased).unwrap();
+    assert_eq!(first_chunk(&conn).1, "甲法");
+}
+
+// 映射后的表是只读视图，导入法规在嵌入之前就应被拒绝
+#[test]
+fn importing_into_mapped_content_db_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let (aliased, _custom) = write_divergent_content_dbs(dir.path());
+    let schema = ContentSchemaMap::default();
+
+    let conn = open_content_db(&aliased, &schema).unwrap();
+    assert!(matches!(
+        ensure_writable_content_schema(&conn, &schema),
+        Err(AppError::Validation(_))
+    ));
+
+    let standard = dir.path().join("standard");
+    write_content_db(&standard, 1);
+    let conn = open_content_db(&standard, &schema).unwrap();
+    ensure_writable_content_schema(&conn, &schema).unwrap();
+}
+
+#[test]
+fn imported_chunk_prefixes_are_unique() {
+    let first = imported_chunk_prefix().unwrap();
+    let second = imported_chunk_prefix().unwrap();
+    assert_ne!(first, second);
+    assert!(first.starts_with("user-") && first.ends_with('-'));
+}
+
+// 导入写在调用方的事务中：向量写入失败回滚后 content.db 不留下任何记录
+#[test]
+fn rolled_back_import_leaves_no_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    write_content_db(dir.path(), 0);
+    let conn = open_content_db(dir.path(), &ContentSchemaMap::default()).unwrap();
+    ensure_imported_laws_table(&conn).unwrap();
+    let meta = ImportedLaw {
+        law_name: "自定义规定".to_string(),
+        category: String::new(),
+        region: String::new(),
+        publish_date: String::new(),
+        article_count: 1,
+        imported_at: String::new(),
+    };
+    let articles = split_law_articles("第一条 内容。");
+    let chunk_ids = vec!["user-x-0".to_string()];
+
+    conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+    insert_imported_law(&conn, &meta, "user-x-", &chunk_ids, &articles, "全文").unwrap();
+    conn.execute_batch("ROLLBACK").unwrap();
+
+    for table in ["chunks", "full_texts", "imported_laws"] {
+        let count: i64 = conn
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0, "{}", table);
+    }
+}
+
+// ==========================================
+// 全文缓存
+// ==========================================
+
+#[test]
+fn full_text_cache_is_keyed_by_data_dir() {
+    let (dir, state) = test_state();
+    let original = get_effective_data_dir(&state);
+    let other = dir.path().join("other");
+    for (data_dir, text) in [(&original, "旧目录全文"), (&other, "新目录全文")] {
+        write_content_db(data_dir, 1);
+        Connection::open(data_dir.join("content.db"))
+            .unwrap()
+            .execute(
+                "INSERT INTO full_texts VALUES ('测试法', '', '法律', ?1)",
+                [text],
+            )
+            .unwrap();
+    }
+    let raw = |state: &AppState| match load_full_text(state, "测试法.txt", false, None).unwrap()
+    {
+        FullText::Raw(text) => text,
+        FullText::Structured(_) => panic!("expected raw full text"),
+    };
+
+    assert_eq!(raw(&state), "旧目录全文");
+    assert_eq!(state.full_text_cache.lock_or_recover().len(), 1);
+
+    // 只改设置、不清缓存，模拟预读与切换数据目录交错的情况
+    settings_write_guard(&state).custom_data_path = Some(other.to_string_lossy().to_string());
+    assert_eq!(raw(&state), "新目录全文");
+    assert_eq!(state.full_text_cache.lock_or_recover().len(), 2);
+}
+
+// ==========================================
+// 命令耗时统计
+// ==========================================
+
+fn command_metrics(metrics: &MetricsRegistry, command: &str) -> CommandMetricsSnapshot {
+    metrics
+        .snapshot()
+        .commands
+        .into_iter()
+        .find(|m| m.command == command)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn metered_commands_count_failures_from_their_result() {
+    let (_dir, app) = test_app();
+    let long = "法".repeat(DIFF_MAX_CHARS + 1);
+    assert!(diff_texts(long, "法".to_string(), app.state())
+        .await
+        .is_err());
+    diff_texts("甲".to_string(), "乙".to_string(), app.state())
+        .await
+        .unwrap();
+
+    let metrics = &app.state::<AppState>().metrics;
+    let snapshot = command_metrics(metrics, "diff_texts");
+    assert_eq!(snapshot.error_count, 1);
+
+    // 仍返回 String 错误的命令同样计入失败
+    let result: Result<(), String> = metered(metrics, "string_command", async {
+        Err("失败".to_string())
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(command_metrics(metrics, "string_command").error_count, 1);
+}
+
+#[test]
+fn sync_commands_record_each_call_once_with_its_result() {
+    let (_dir, app) = test_app();
+    get_settings(app.state(), app.state());
+    assert!(matches!(
+        move_folder(1, Some(9999), app.state(), app.state()),
+        Err(AppError::NotFound(_))
+    ));
+    create_folder("民法".to_string(), None, app.state(), app.state()).unwrap();
+
+    let metrics = &app.state::<AppState>().metrics;
+    let settings = command_metrics(metrics, "get_settings");
+    assert_eq!((settings.count, settings.error_count), (1, 0));
+    let moved = command_metrics(metrics, "move_folder");
+    assert_eq!((moved.count, moved.error_count), (1, 1));
+    let created = command_metrics(metrics, "create_folder");
+    assert_eq!((created.count, created.error_count), (1, 0));
+}
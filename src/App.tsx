@@ -4,6 +4,7 @@ import { useState, useEffect, useRef, useMemo } from "react";
 import { SearchBar } from "./components/SearchBar";
 import { ResultsDisplay } from "./components/ResultsDisplay";
 import { FavoritesSidebar } from "./components/FavoritesSidebar";
+import { RecentLaws } from "./components/RecentLaws";
 import { SearchHistory } from "./components/SearchHistory";
 import { StatusBar } from "./components/StatusBar";
 import { ExportButton } from "./components/ExportButton";
@@ -15,7 +16,20 @@ import {
   LawChunk,
   LawNameSuggestion,
   getSettings,
-  checkDbStatus,
+  getDbStatusDetailed,
+  describeDbStatus,
+  formatError,
+  isAppError,
+  SettingsChangedEvent,
+  getStartupWarnings,
+  NavigateTarget,
+  DeepLinkError,
+  CitationDetectedEvent,
+  IndexModelMismatch,
+  DataDirUnreachableEvent,
+  EmbeddingUnavailableEvent,
+  probeEmbeddingService,
+  takePendingNavigation,
 } from "./services/api";
 import { AnimatePresence, motion } from "framer-motion";
 import { getVersion } from "@tauri-apps/api/app";
@@ -23,14 +37,20 @@ import { UpdateModal, GithubUpdate } from "./components/UpdateModal";
 import { startAgentSearch, AgentUpdateEvent, stopTask } from "./services/api";
 import { AgentView } from "./components/AgentView";
 import { listen } from "@tauri-apps/api/event";
-import { Sparkles } from "lucide-react";
+import { Sparkles, X } from "lucide-react";
 import { useHistory } from "./hooks/useHistory";
 import { TabBar } from "./components/TabBar";
 import { LawDetailView } from "./components/LawDetailView";
 import { Tab } from "./types";
 import { DraftingView } from "./components/DraftingView";
+import { NationalLocalModal } from "./components/NationalLocalModal";
 import { useDrafting } from "./hooks/useDrafting";
 
+// 同一条启动提示可能既来自事件又来自拉取，以内容作为 toast id 只显示一次
+function showStartupWarning(message: string) {
+  toast.error(message, { id: `startup-${message}`, duration: 10000 });
+}
+
 function App() {
   // === Tab State ===
   const [tabs, setTabs] = useState<Tab[]>([
@@ -41,6 +61,9 @@ function App() {
   // === Search State  ===
   const [query, setQuery] = useState("");
   const [executedQuery, setExecutedQuery] = useState("");
+  const [rewrittenQuery, setRewrittenQuery] = useState<string | null>(null);
+  const [degradedReason, setDegradedReason] = useState<string | null>(null);
+  const [isProbing, setIsProbing] = useState(false);
   const [rawResults, setRawResults] = useState<LawChunk[]>([]);
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
@@ -61,6 +84,7 @@ function App() {
   const [filterCategories, setFilterCategories] = useState<string[]>([]);
   const [searchLocal, setSearchLocal] = useState(false);
   const [regionQuery, setRegionQuery] = useState("");
+  const [isCompareOpen, setIsCompareOpen] = useState(false);
 
   const [searchTime] = useState<number | null>(null);
   const searchInputRef = useRef<HTMLInputElement>(null);
@@ -71,7 +95,7 @@ function App() {
   const { addMaterial } = useDrafting();
   const {
     history: searchHistory,
-    add: addToHistory,
+    refresh: refreshHistory,
     clear: clearHistory,
   } = useHistory();
 
@@ -86,6 +110,123 @@ function App() {
     };
   }, []);
 
+  // lawvault:// 链接跳转；处理函数依赖最新的标签页状态，经 ref 调用
+  const navigateRef = useRef<(target: NavigateTarget) => void>(() => {});
+  useEffect(() => {
+    const unlistenNavigate = listen<NavigateTarget>("navigate", (e) => {
+      navigateRef.current(e.payload);
+    });
+    const unlistenError = listen<DeepLinkError>("deep-link-error", (e) => {
+      toast.error(`无法打开链接：${e.payload.message}`);
+    });
+    return () => {
+      unlistenNavigate.then((f) => f());
+      unlistenError.then((f) => f());
+    };
+  }, []);
+
+  // 向量库由其他嵌入模型构建：检索结果不可靠，长时间显示提示
+  useEffect(() => {
+    const unlisten = listen<IndexModelMismatch>("index-model-mismatch", (e) => {
+      const { index_model, configured_model } = e.payload;
+      toast.error(
+        `向量库由 ${index_model} 构建，与当前嵌入模型 ${configured_model} 不一致，检索结果可能不准确。请在设置中重建向量库或换回原模型。`,
+        { id: "index-model-mismatch", duration: 20000 }
+      );
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // 自定义数据目录（如网络盘）无法访问时，检索结果来自默认数据，需明确提示
+  useEffect(() => {
+    const unlisten = listen<DataDirUnreachableEvent>("data-dir-unreachable", (e) => {
+      toast.error(
+        `数据目录 ${e.payload.configured_path} 无法访问，当前检索使用的是默认数据。请检查网络盘连接或在设置中更改数据路径。`,
+        { id: "data-dir-unreachable", duration: 20000 }
+      );
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // 嵌入服务连不上时检索已改用关键词匹配，提示一次；结果区另有常驻提示
+  useEffect(() => {
+    const unlisten = listen<EmbeddingUnavailableEvent>("embedding-unavailable", (e) => {
+      toast.error(
+        `嵌入服务不可用（${e.payload.reason}），已改用关键词检索，结果可能不如语义检索准确。`,
+        { id: "embedding-unavailable", duration: 10000 }
+      );
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // 剪贴板中复制了法条引用：弹出可关闭的提示，点击查看全文
+  useEffect(() => {
+    const unlisten = listen<CitationDetectedEvent>("citation-detected", (e) => {
+      const { chunk } = e.payload;
+      toast(
+        (t) => (
+          <div className="flex items-start gap-2 max-w-sm">
+            <div
+              className="flex flex-col gap-1 cursor-pointer min-w-0"
+              onClick={() => {
+                toast.dismiss(t.id);
+                navigateRef.current({ kind: "article", chunk });
+              }}
+            >
+              <span className="font-bold text-sm">
+                《{chunk.law_name}》{chunk.article_number}
+              </span>
+              <span className="text-xs text-base-content/70 line-clamp-4">
+                {chunk.content}
+              </span>
+            </div>
+            <button
+              className="btn btn-ghost btn-xs btn-square shrink-0"
+              onClick={() => toast.dismiss(t.id)}
+              title="关闭"
+            >
+              <X size={14} />
+            </button>
+          </div>
+        ),
+        { id: `citation-${chunk.id}`, duration: 15000, icon: "📋" }
+      );
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // 启动阶段的问题随 startup-warnings 事件推送，监听注册晚于推送时由 initApp 拉取补齐
+  useEffect(() => {
+    const unlisten = listen<string[]>("startup-warnings", (e) => {
+      e.payload.forEach(showStartupWarning);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // 设置在别处被修改（如切换配置方案）时同步界面
+  useEffect(() => {
+    const unlisten = listen<SettingsChangedEvent>("settings-changed", (e) => {
+      if (e.payload.changed_fields.includes("display_density")) {
+        getSettings()
+          .then((data) => setDensity(data.display_density))
+          .catch((err) => console.error("Failed to load settings:", err));
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
   useEffect(() => {
     const initApp = async () => {
       try {
@@ -93,20 +234,26 @@ function App() {
         if (settings.display_density) {
           setDensity(settings.display_density);
         }
+        const warnings = await getStartupWarnings();
+        warnings.forEach(showStartupWarning);
       } catch (e) {
         console.error(e);
       }
 
-      const dbReady = await checkDbStatus();
+      const dbStatus = await getDbStatusDetailed().catch((e) => {
+        console.error("Failed to check db status", e);
+        return null;
+      });
+      const dbProblem = dbStatus ? describeDbStatus(dbStatus) : "无法检查数据库状态";
 
-      if (!dbReady) {
+      if (dbProblem) {
         setIsMissingDb(true);
         setIsSettingsOpen(true);
         toast(
           (_t) => (
             <div className="flex flex-col gap-1">
               <span className="font-bold text-base">👋 欢迎使用 LawVault</span>
-              <span className="text-xs">检测到数据库未配置。</span>
+              <span className="text-xs">检测到数据库不可用：{dbProblem}</span>
               <span className="text-xs">
                 请在设置中选择您解压的 <b>数据文件夹</b> (包含 content.db)。
               </span>
@@ -120,7 +267,12 @@ function App() {
         );
       }
     };
-    initApp();
+    initApp()
+      .then(() => takePendingNavigation())
+      .then((target) => {
+        if (target) navigateRef.current(target);
+      })
+      .catch((e) => console.error(e));
   }, []);
 
   useEffect(() => {
@@ -241,8 +393,8 @@ function App() {
 
   const handleSettingsClose = async () => {
     if (isMissingDb) {
-      const ready = await checkDbStatus();
-      if (ready) {
+      const status = await getDbStatusDetailed().catch(() => null);
+      if (status?.ready) {
         setIsMissingDb(false);
         setIsSettingsOpen(false);
         toast.success("数据库配置成功，正在重载...", { duration: 3000 });
@@ -250,15 +402,33 @@ function App() {
           window.location.reload();
         }, 1000);
       } else {
-        toast.error("请先选择正确的数据库路径！", { duration: 4000 });
+        const problem = status ? describeDbStatus(status) : null;
+        toast.error(problem ?? "请先选择正确的数据库路径！", { duration: 6000 });
       }
     } else {
       setIsSettingsOpen(false);
     }
   };
 
-  const handleSearch = async (searchQuery: string) => {
+  // 重新连接嵌入服务，成功后用语义检索重跑当前查询
+  const handleProbeEmbedding = async () => {
+    setIsProbing(true);
+    try {
+      await probeEmbeddingService();
+      toast.success("嵌入服务已恢复");
+      if (executedQuery) handleSearch(executedQuery, false);
+      else setDegradedReason(null);
+    } catch (err) {
+      toast.error(`仍无法连接嵌入服务：${formatError(err)}`);
+    } finally {
+      setIsProbing(false);
+    }
+  };
+
+  // useAgent 用于从历史记录重新运行，未传时按当前“深度思考”开关
+  const handleSearch = async (searchQuery: string, useAgent?: boolean) => {
     if (!searchQuery.trim()) return;
+    const runAgent = useAgent ?? isDeepThink;
 
     if (activeTabId !== "home") setActiveTabId("home");
 
@@ -272,23 +442,28 @@ function App() {
     setHasSearched(true);
     setAgentEvent(null);
     setRawResults([]);
-    addToHistory(searchQuery);
+    setRewrittenQuery(null);
+    setDegradedReason(null);
 
     const newAgentId = `agent-${Date.now()}`;
     currentAgentIdRef.current = newAgentId;
 
     try {
-      if (isDeepThink) {
+      if (runAgent) {
         setIsAgentRunning(true);
         const agentResults = await startAgentSearch(searchQuery, newAgentId);
         setRawResults(agentResults);
       } else {
         const regionParam = searchLocal ? regionQuery : undefined;
         const response = await searchLaw(searchQuery, regionParam);
-        setRawResults(response.results);
+        if (!response.stale) {
+          setRawResults(response.results);
+          setRewrittenQuery(response.rewrittenQuery);
+          setDegradedReason(response.degradedReason);
+        }
       }
     } catch (err) {
-      const errorMsg = String(err);
+      const errorMsg = formatError(err);
       if (errorMsg.includes("手动停止")) {
         console.log("用户停止深度思考，自动降级为普通搜索...");
         toast("已停止深度思考，显示普通搜索结果", { icon: "🛑" });
@@ -296,18 +471,25 @@ function App() {
         try {
           const regionParam = searchLocal ? regionQuery : undefined;
           const response = await searchLaw(searchQuery, regionParam);
-          setRawResults(response.results);
+          if (!response.stale) {
+            setRawResults(response.results);
+            setRewrittenQuery(response.rewrittenQuery);
+            setDegradedReason(response.degradedReason);
+          }
         } catch (fallbackErr) {
-          setError("普通搜索也失败了: " + String(fallbackErr));
+          setError("普通搜索也失败了: " + formatError(fallbackErr));
         }
       } else {
-        setError("搜索失败，请检查服务日志。");
+        setError(
+          isAppError(err) ? `搜索失败：${err.message}` : "搜索失败，请检查服务日志。"
+        );
         console.error(err);
       }
     } finally {
       setIsLoading(false);
       setIsAgentRunning(false);
       currentAgentIdRef.current = null;
+      refreshHistory();
     }
   };
 
@@ -324,10 +506,27 @@ function App() {
       part: "",
       chapter: "",
       _distance: 0,
+      source_name: suggestion.source_name,
     };
     openLawTab(lawToView);
   };
 
+  const handleNavigate = (target: NavigateTarget) => {
+    switch (target.kind) {
+      case "article":
+        openLawTab(target.chunk);
+        break;
+      case "law":
+        handleSuggestionClick(target.law);
+        break;
+      case "search":
+        setQuery(target.query);
+        handleSearch(target.query);
+        break;
+    }
+  };
+  navigateRef.current = handleNavigate;
+
   // === Memos ===
 
   const visibleCategories = useMemo(() => {
@@ -373,6 +572,7 @@ function App() {
           onOpenDrafting={openDraftingTab}
           onOpenSettings={() => setIsSettingsOpen(true)}
           onOpenFavorites={() => setIsFavoritesOpen(true)}
+          onOpenLaw={openLawTab}
         />
       </div>
 
@@ -438,13 +638,27 @@ function App() {
                   </div>
                 </div>
 
+                {!hasSearched && (
+                  <div className="mt-8 max-w-2xl mx-auto">
+                    <RecentLaws
+                      onOpen={(item) =>
+                        handleSuggestionClick({
+                          name: item.law_name,
+                          region: item.region,
+                          category: item.category,
+                        })
+                      }
+                    />
+                  </div>
+                )}
+
                 {!hasSearched && searchHistory.length > 0 && (
                   <div className="mt-8 max-w-2xl mx-auto">
                     <SearchHistory
                       history={searchHistory}
-                      onHistoryClick={(q) => {
-                        setQuery(q);
-                        handleSearch(q);
+                      onHistoryClick={(item) => {
+                        setQuery(item.query);
+                        handleSearch(item.query, item.search_type === "agent");
                       }}
                       onClearHistory={clearHistory}
                     />
@@ -518,6 +732,15 @@ function App() {
                             </motion.div>
                           )}
                         </AnimatePresence>
+                        {searchLocal && regionQuery.trim() && query.trim() && (
+                          <button
+                            className="btn btn-sm btn-ghost join-item text-xs"
+                            title="并排对照全国性规定与该地区的地方规定"
+                            onClick={() => setIsCompareOpen(true)}
+                          >
+                            对照全国
+                          </button>
+                        )}
                       </div>
                       <select
                         value={sortBy}
@@ -548,6 +771,29 @@ function App() {
                 </div>
               )}
 
+              {degradedReason !== null && !isLoading && (
+                <div className="max-w-4xl mx-auto mb-3 alert alert-warning py-2 text-sm">
+                  <span className="grow">
+                    嵌入服务不可用，当前为关键词匹配结果
+                    {degradedReason && `（${degradedReason}）`}
+                  </span>
+                  <button
+                    className="btn btn-xs"
+                    onClick={handleProbeEmbedding}
+                    disabled={isProbing}
+                  >
+                    {isProbing && <span className="loading loading-spinner loading-xs" />}
+                    重试连接
+                  </button>
+                </div>
+              )}
+
+              {rewrittenQuery && !isLoading && (
+                <div className="max-w-4xl mx-auto mb-3 text-xs text-base-content/50">
+                  已按“{rewrittenQuery}”检索
+                </div>
+              )}
+
               <ResultsDisplay
                 results={displayedResults}
                 isLoading={isLoading}
@@ -618,6 +864,16 @@ function App() {
         )}
       </AnimatePresence>
       <SettingsModal isOpen={isSettingsOpen} onClose={handleSettingsClose} />
+      <NationalLocalModal
+        isOpen={isCompareOpen}
+        query={query}
+        region={regionQuery}
+        onClose={() => setIsCompareOpen(false)}
+        onOpenLaw={(law) => {
+          setIsCompareOpen(false);
+          openLawTab(law);
+        }}
+      />
       {availableUpdate && (
         <UpdateModal
           update={availableUpdate}
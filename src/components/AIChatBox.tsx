@@ -2,7 +2,13 @@
 
 import React, { useState, useEffect, useRef, useMemo } from "react";
 import { Sparkles, Bot, AlertCircle, FileText, Square, BrainCircuit } from "lucide-react";
-import { startChatStream, getSettings, stopChat } from "../services/api";
+import {
+  startChatStream,
+  getSettings,
+  stopChat,
+  ChatContextChunk,
+  resolveChunkContent,
+} from "../services/api";
 import ReactMarkdown from "react-markdown";
 import remarkGfm from "remark-gfm";
 
@@ -14,6 +20,7 @@ interface AIChatBoxProps {
 
 export const AIChatBox: React.FC<AIChatBoxProps> = ({ query, results, mode = "simple" }) => {
   const [rawOutput, setRawOutput] = useState(""); 
+  const [reasoning, setReasoning] = useState("");
   const [isStreaming, setIsStreaming] = useState(false);
   const [isEnabled, setIsEnabled] = useState(false);
   const [error, setError] = useState<string | null>(null);
@@ -35,7 +42,7 @@ export const AIChatBox: React.FC<AIChatBoxProps> = ({ query, results, mode = "si
 
   const { thought, content } = useMemo(() => {
     const thinkMatch = rawOutput.match(/<think>([\s\S]*?)(?:<\/think>|$)/);
-    const thoughtContent = thinkMatch ? thinkMatch[1].trim() : "";
+    const thoughtContent = reasoning.trim() || (thinkMatch ? thinkMatch[1].trim() : "");
     let mainContent = rawOutput.replace(/<think>[\s\S]*?<\/think>/g, "").trim();
     
     if (rawOutput.includes("<think>") && !rawOutput.includes("</think>")) {
@@ -43,7 +50,7 @@ export const AIChatBox: React.FC<AIChatBoxProps> = ({ query, results, mode = "si
     }
 
     return { thought: thoughtContent, content: mainContent };
-  }, [rawOutput]);
+  }, [rawOutput, reasoning]);
 
   const showThought = isThoughtExpanded || (isStreaming && !content && !!thought);
 
@@ -56,13 +63,20 @@ export const AIChatBox: React.FC<AIChatBoxProps> = ({ query, results, mode = "si
 
     const start = async () => {
       setRawOutput("");
+      setReasoning("");
       setError(null);
       setIsStreaming(true);
       setIsThoughtExpanded(true);
 
       try {
-        const contextChunks = results.map(
-          (r) => `法规：${r.law_name} ${r.article_number}\n内容：${r.content}`
+        const contextChunks: ChatContextChunk[] = await Promise.all(
+          results.map(async (r) => ({
+            id: r.id,
+            law_name: r.law_name,
+            article_number: r.article_number,
+            content: await resolveChunkContent(r),
+            distance: r._distance,
+          }))
         );
 
         unlisten = await startChatStream(query, contextChunks, mode, (token) => {
@@ -74,7 +88,7 @@ export const AIChatBox: React.FC<AIChatBoxProps> = ({ query, results, mode = "si
           } else {
             setRawOutput((prev) => prev + token);
           }
-        }, currentEventId);
+        }, currentEventId, (text) => setReasoning((prev) => prev + text));
       } catch (e) {
         setError("无法连接 AI 服务");
         setIsStreaming(false);
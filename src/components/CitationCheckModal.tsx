@@ -0,0 +1,141 @@
+// frontend/src/components/CitationCheckModal.tsx
+// 引用核对：粘贴文书后逐条对照本地法规库，标出找不到的法规或条文
+
+import React, { useEffect, useState } from "react";
+import { LoaderCircle, ShieldCheck, X } from "lucide-react";
+import { toast } from "react-hot-toast";
+import {
+  CitationCheck,
+  CitationCheckReport,
+  CitationCheckStatus,
+  checkCitationsInText,
+  formatError,
+} from "../services/api";
+
+interface CitationCheckModalProps {
+  isOpen: boolean;
+  initialText: string;
+  onClose: () => void;
+}
+
+const STATUS_LABELS: Record<CitationCheckStatus, { label: string; className: string }> = {
+  found: { label: "已核实", className: "badge-success" },
+  article_missing: { label: "无此条", className: "badge-warning" },
+  law_not_found: { label: "未找到法规", className: "badge-error" },
+};
+
+export const CitationCheckModal: React.FC<CitationCheckModalProps> = ({
+  isOpen,
+  initialText,
+  onClose,
+}) => {
+  const [text, setText] = useState("");
+  const [report, setReport] = useState<CitationCheckReport | null>(null);
+  const [isChecking, setIsChecking] = useState(false);
+  const [expanded, setExpanded] = useState<number | null>(null);
+
+  useEffect(() => {
+    if (isOpen) {
+      setText(initialText);
+      setReport(null);
+      setExpanded(null);
+    }
+  }, [isOpen]);
+
+  if (!isOpen) return null;
+
+  const handleCheck = async () => {
+    if (!text.trim()) return;
+    setIsChecking(true);
+    try {
+      setReport(await checkCitationsInText(text));
+      setExpanded(null);
+    } catch (err) {
+      toast.error(formatError(err));
+    } finally {
+      setIsChecking(false);
+    }
+  };
+
+  // 显示引用前后各一小段原文，便于定位
+  const contextOf = (c: CitationCheck) =>
+    `${text.slice(Math.max(0, c.start - 20), c.start)}【${c.text}】${text.slice(c.end, c.end + 20)}`;
+
+  return (
+    <div className="modal modal-open z-60">
+      <div className="modal-backdrop" onClick={onClose}></div>
+      <div className="modal-box max-w-3xl bg-base-100 shadow-2xl border border-base-200 p-6 flex flex-col gap-4">
+        <div className="flex items-center justify-between">
+          <h3 className="font-bold text-lg flex items-center gap-2">
+            <ShieldCheck size={20} className="text-primary" /> 引用核对
+          </h3>
+          <button className="btn btn-ghost btn-sm btn-circle" onClick={onClose}>
+            <X size={18} />
+          </button>
+        </div>
+
+        <textarea
+          className="textarea textarea-bordered w-full h-40 text-sm"
+          placeholder="粘贴文书全文，逐条核对其中的《法规名》第X条引用"
+          value={text}
+          onChange={(e) => setText(e.target.value)}
+        />
+        <div className="flex items-center gap-3">
+          <button
+            className="btn btn-primary btn-sm"
+            onClick={handleCheck}
+            disabled={isChecking || !text.trim()}
+          >
+            {isChecking && <LoaderCircle size={14} className="animate-spin" />}
+            开始核对
+          </button>
+          {report && (
+            <span className="text-xs text-base-content/60">
+              共 {report.citations.length} 处引用：已核实 {report.found}，无此条{" "}
+              {report.article_missing}，未找到法规 {report.law_not_found}
+            </span>
+          )}
+        </div>
+
+        {report && report.citations.length === 0 && (
+          <p className="text-sm text-base-content/50 text-center py-6">
+            未识别到法条引用
+          </p>
+        )}
+
+        {report && report.citations.length > 0 && (
+          <div className="max-h-[45vh] overflow-y-auto flex flex-col gap-2">
+            {report.citations.map((c, index) => (
+              <div
+                key={`${c.start}-${index}`}
+                className="border border-base-200 rounded-lg p-3 text-sm"
+              >
+                <div
+                  className={`flex items-center gap-2 ${c.chunk ? "cursor-pointer" : ""}`}
+                  onClick={() =>
+                    c.chunk && setExpanded(expanded === index ? null : index)
+                  }
+                >
+                  <span className={`badge badge-sm ${STATUS_LABELS[c.status].className}`}>
+                    {STATUS_LABELS[c.status].label}
+                  </span>
+                  <span className="font-medium">
+                    《{c.matched_law_name ?? c.law_name}》{c.article_number}
+                  </span>
+                </div>
+                <div className="text-xs text-base-content/50 mt-1 truncate">
+                  {contextOf(c)}
+                </div>
+                {expanded === index && c.chunk && (
+                  <p className="mt-2 text-sm leading-6 bg-base-200/40 rounded p-2 whitespace-pre-line">
+                    {c.chunk.content}
+                  </p>
+                )}
+              </div>
+            ))}
+          </div>
+        )}
+      </div>
+    </div>
+  );
+};
@@ -0,0 +1,74 @@
+// frontend/src/components/CrossRefPanel.tsx
+// 条文互引：本条引用了哪些条文、哪些条文引用了本条
+
+import React, { useEffect, useState } from "react";
+import { LoaderCircle } from "lucide-react";
+import {
+  LawChunk,
+  getOutgoingRefs,
+  getIncomingRefs,
+  formatError,
+} from "../services/api";
+
+interface CrossRefPanelProps {
+  law: LawChunk;
+  onOpen: (law: LawChunk) => void;
+}
+
+const RefList: React.FC<{
+  title: string;
+  items: LawChunk[];
+  onOpen: (law: LawChunk) => void;
+}> = ({ title, items, onOpen }) => (
+  <div className="flex flex-col gap-1 min-w-0">
+    <div className="text-xs font-bold text-base-content/60">
+      {title}（{items.length}）
+    </div>
+    {items.length === 0 ? (
+      <div className="text-xs text-base-content/40">无</div>
+    ) : (
+      items.map((item) => (
+        <button
+          key={item.id}
+          className="text-left text-xs px-2 py-1 rounded hover:bg-base-200 truncate"
+          onClick={() => onOpen(item)}
+          title={item.content}
+        >
+          《{item.law_name}》{item.article_number}
+        </button>
+      ))
+    )}
+  </div>
+);
+
+export const CrossRefPanel: React.FC<CrossRefPanelProps> = ({ law, onOpen }) => {
+  const [outgoing, setOutgoing] = useState<LawChunk[] | null>(null);
+  const [incoming, setIncoming] = useState<LawChunk[] | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    Promise.all([getOutgoingRefs(law.id), getIncomingRefs(law.id)])
+      .then(([out, inc]) => {
+        setOutgoing(out);
+        setIncoming(inc);
+      })
+      .catch((err) => setError(formatError(err)));
+  }, [law.id]);
+
+  if (error) {
+    return <div className="text-xs text-error">{error}</div>;
+  }
+  if (!outgoing || !incoming) {
+    return (
+      <div className="flex items-center gap-2 text-xs text-base-content/50">
+        <LoaderCircle size={14} className="animate-spin" /> 正在查询引用关系...
+      </div>
+    );
+  }
+  return (
+    <div className="grid grid-cols-2 gap-4 p-3 bg-base-200/30 rounded-lg">
+      <RefList title="本条引用" items={outgoing} onOpen={onOpen} />
+      <RefList title="被以下条文引用" items={incoming} onOpen={onOpen} />
+    </div>
+  );
+};
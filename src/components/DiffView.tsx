@@ -0,0 +1,56 @@
+// frontend/src/components/DiffView.tsx
+// 两段文本的逐字对比：删除标红划线，新增标绿
+
+import React, { useEffect, useState } from "react";
+import { LoaderCircle } from "lucide-react";
+import { DiffHunk, diffTexts, formatError } from "../services/api";
+
+interface DiffViewProps {
+  left: string;
+  right: string;
+}
+
+export const DiffView: React.FC<DiffViewProps> = ({ left, right }) => {
+  const [hunks, setHunks] = useState<DiffHunk[] | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    setHunks(null);
+    setError(null);
+    diffTexts(left, right)
+      .then(setHunks)
+      .catch((err) => setError(formatError(err)));
+  }, [left, right]);
+
+  if (error) {
+    return <div className="text-xs text-error">{error}</div>;
+  }
+  if (!hunks) {
+    return (
+      <div className="flex items-center gap-2 text-xs text-base-content/50">
+        <LoaderCircle size={14} className="animate-spin" /> 对比中...
+      </div>
+    );
+  }
+  if (hunks.every((h) => h.op === "equal")) {
+    return <div className="text-xs text-base-content/50">内容没有变化</div>;
+  }
+
+  return (
+    <p className="text-xs leading-relaxed whitespace-pre-wrap font-mono">
+      {hunks.map((hunk, i) =>
+        hunk.op === "insert" ? (
+          <ins key={i} className="bg-success/20 text-success no-underline">
+            {hunk.text}
+          </ins>
+        ) : hunk.op === "delete" ? (
+          <del key={i} className="bg-error/20 text-error">
+            {hunk.text}
+          </del>
+        ) : (
+          <span key={i}>{hunk.text}</span>
+        )
+      )}
+    </p>
+  );
+};
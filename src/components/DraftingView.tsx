@@ -4,6 +4,7 @@ import {
   stopTask,
   startAgentSearch,
   AgentUpdateEvent,
+  ChatContextChunk,
 } from "../services/api";
 import {
   Trash2,
@@ -25,6 +26,7 @@ import {
   FileType,
   ClipboardCopy,
   Square,
+  ShieldCheck,
 } from "lucide-react";
 import ReactMarkdown from "react-markdown";
 import remarkGfm from "remark-gfm";
@@ -33,6 +35,7 @@ import { toast } from "react-hot-toast";
 import { motion, AnimatePresence } from "framer-motion";
 import { ConfirmModal } from "./ConfirmModal";
 import { AgentView } from "./AgentView";
+import { CitationCheckModal } from "./CitationCheckModal";
 import { listen } from "@tauri-apps/api/event";
 
 export const DraftingView: React.FC = () => {
@@ -48,9 +51,11 @@ export const DraftingView: React.FC = () => {
 
   const [prompt, setPrompt] = useState("");
   const [rawResult, setRawResult] = useState("");
+  const [reasoning, setReasoning] = useState("");
   const [isGenerating, setIsGenerating] = useState(false);
   const [isAgentSearching, setIsAgentSearching] = useState(false);
   const [useAgentMode, setUseAgentMode] = useState(false);
+  const [isCitationCheckOpen, setIsCitationCheckOpen] = useState(false);
   const [agentEvent, setAgentEvent] = useState<AgentUpdateEvent | null>(null);
 
   const [sidebarTab, setSidebarTab] = useState<"materials" | "templates">(
@@ -83,7 +88,7 @@ export const DraftingView: React.FC = () => {
 
   const { thought, content } = useMemo(() => {
     const thinkMatch = rawResult.match(/<think>([\s\S]*?)(?:<\/think>|$)/);
-    const thoughtContent = thinkMatch ? thinkMatch[1].trim() : "";
+    const thoughtContent = reasoning.trim() || (thinkMatch ? thinkMatch[1].trim() : "");
     let mainContent = rawResult.replace(/<think>[\s\S]*?<\/think>/g, "").trim();
 
     if (
@@ -94,7 +99,7 @@ export const DraftingView: React.FC = () => {
       mainContent = "";
     }
     return { thought: thoughtContent, content: mainContent };
-  }, [rawResult, isGenerating]);
+  }, [rawResult, reasoning, isGenerating]);
 
   useEffect(() => {
     if (isGenerating || isAgentSearching) {
@@ -117,6 +122,7 @@ export const DraftingView: React.FC = () => {
 
     setIsGenerating(true);
     setRawResult("");
+    setReasoning("");
     setIsThoughtOpen(true);
     setAgentEvent(null);
 
@@ -125,9 +131,12 @@ export const DraftingView: React.FC = () => {
     eventIdRef.current = currentEventId;
 
     try {
-      let contextChunks: string[] = materials.map(
-        (i) => `【${i.law_name} ${i.article_number}】\n${i.content}`
-      );
+      let contextChunks: ChatContextChunk[] = materials.map((i) => ({
+        id: i.law_id,
+        law_name: i.law_name,
+        article_number: i.article_number,
+        content: i.content,
+      }));
 
       if (useAgentMode) {
         setIsAgentSearching(true);
@@ -139,11 +148,17 @@ export const DraftingView: React.FC = () => {
             await addMaterial(m);
           }
 
-          const newChunks = foundMaterials.map(
-            (i) => `【${i.law_name} ${i.article_number}】\n${i.content}`
-          );
-
-          contextChunks = Array.from(new Set([...contextChunks, ...newChunks]));
+          const existingIds = new Set(contextChunks.map((c) => c.id));
+          const newChunks: ChatContextChunk[] = foundMaterials
+            .filter((i) => !existingIds.has(i.id))
+            .map((i) => ({
+              id: i.id,
+              law_name: i.law_name,
+              article_number: i.article_number,
+              content: i.content,
+            }));
+
+          contextChunks = [...contextChunks, ...newChunks];
           toast.success(
             `智能搜材完成，引用了 ${foundMaterials.length} 条新依据`
           );
@@ -167,7 +182,8 @@ export const DraftingView: React.FC = () => {
             setRawResult((prev) => prev + token);
           }
         },
-        currentEventId
+        currentEventId,
+        (text) => setReasoning((prev) => prev + text)
       );
     } catch (e) {
       const errStr = String(e);
@@ -223,6 +239,7 @@ export const DraftingView: React.FC = () => {
 
   const performClear = () => {
     setRawResult("");
+    setReasoning("");
     setShowClearConfirm(false);
     toast.success("内容已清空");
   };
@@ -393,6 +410,14 @@ export const DraftingView: React.FC = () => {
             <div className="font-bold text-sm">文书生成</div>
           </div>
           <div className="flex gap-2">
+            <div className="tooltip tooltip-bottom" data-tip="核对引用">
+              <button
+                onClick={() => setIsCitationCheckOpen(true)}
+                className="btn btn-sm btn-ghost btn-square text-base-content/50"
+              >
+                <ShieldCheck size={16} />
+              </button>
+            </div>
             {content && !isGenerating ? (
               <>
                 <div className="tooltip tooltip-bottom" data-tip="清空">
@@ -645,6 +670,11 @@ export const DraftingView: React.FC = () => {
         )}
       </AnimatePresence>
 
+      <CitationCheckModal
+        isOpen={isCitationCheckOpen}
+        initialText={isGenerating ? "" : content}
+        onClose={() => setIsCitationCheckOpen(false)}
+      />
       <ConfirmModal
         isOpen={showClearConfirm}
         title="清空内容"
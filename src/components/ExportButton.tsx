@@ -1,6 +1,11 @@
 // frontend/src/components/ExportButton.tsx
 import React, { useState } from "react";
-import { LawChunk } from "../services/api";
+import {
+  LawChunk,
+  resolveChunkContent,
+  exportSearchResults,
+  formatError,
+} from "../services/api";
 import { Download } from "lucide-react";
 import { save } from "@tauri-apps/plugin-dialog";
 import { writeTextFile } from "@tauri-apps/plugin-fs";
@@ -15,14 +20,6 @@ export const ExportButton: React.FC<ExportButtonProps> = ({ results }) => {
 
   const handleExport = async () => {
     setIsExporting(true);
-    const textContent = results
-      .map(
-        (r) =>
-          `【${r.law_name} - ${r.article_number}】\n类别: ${
-            r.category
-          }\n发布日期: ${r.publish_date || "N/A"}\n\n${r.content}\n\n`
-      )
-      .join("----------------------------------------\n\n");
 
     try {
       const filePath = await save({
@@ -35,16 +32,39 @@ export const ExportButton: React.FC<ExportButtonProps> = ({ results }) => {
             name: "Text File",
             extensions: ["txt"],
           },
+          {
+            name: "Excel",
+            extensions: ["xlsx"],
+          },
+          {
+            name: "CSV",
+            extensions: ["csv"],
+          },
         ],
       });
 
-      if (filePath) {
-        await writeTextFile(filePath, textContent);
-        toast.success("导出成功！文件已保存。");
+      if (!filePath) return;
+      const ext = filePath.split(".").pop()?.toLowerCase();
+      if (ext === "csv" || ext === "xlsx") {
+        const rows = await exportSearchResults(results, ext, filePath);
+        toast.success(`导出成功，共 ${rows} 条。`);
+        return;
       }
+
+      const contents = await Promise.all(results.map(resolveChunkContent));
+      const textContent = results
+        .map(
+          (r, i) =>
+            `【${r.law_name} - ${r.article_number}】\n类别: ${
+              r.category
+            }\n发布日期: ${r.publish_date || "N/A"}\n\n${contents[i]}\n\n`
+        )
+        .join("----------------------------------------\n\n");
+      await writeTextFile(filePath, textContent);
+      toast.success("导出成功！文件已保存。");
     } catch (error) {
       console.error("导出文件失败:", error);
-      toast.error(`导出失败: ${String(error)}`);
+      toast.error(`导出失败: ${formatError(error)}`);
     } finally {
       setIsExporting(false);
     }
@@ -8,11 +8,15 @@ import {
   Folder,
   Star,
   MoreVertical,
+  GraduationCap,
+  GitCompare,
 } from "lucide-react";
 import { motion } from "framer-motion";
 import { LawChunk, UserFavorite } from "../services/api";
 import { useFavorites } from "../hooks/useFavorites";
 import { ConfirmModal } from "./ConfirmModal";
+import { ReviewModal } from "./ReviewModal";
+import { DiffView } from "./DiffView";
 
 interface FavoritesSidebarProps {
   isOpen: boolean;
@@ -27,12 +31,15 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
   onClose,
   onViewFullText,
 }) => {
-  const { favorites, folders, remove, addFolder, removeFolder, move } =
+  const { favorites, folders, add, remove, addFolder, removeFolder, move } =
     useFavorites();
 
   const [activeFolderId, setActiveFolderId] = useState<number>(UNCLASSIFIED_ID);
   const [newFolderName, setNewFolderName] = useState("");
   const [isCreatingFolder, setIsCreatingFolder] = useState(false);
+  const [isReviewOpen, setIsReviewOpen] = useState(false);
+  // 展开了“对比原文”的收藏
+  const [diffingId, setDiffingId] = useState<string | null>(null);
 
   const [confirmState, setConfirmState] = useState<{
     isOpen: boolean;
@@ -70,9 +77,9 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
 
   const currentItems = useMemo(() => {
     if (activeFolderId === UNCLASSIFIED_ID) {
-      return favorites.filter((f) => f.folder_id === null);
+      return favorites.filter((f) => f.folder_ids.length === 0);
     }
-    return favorites.filter((f) => f.folder_id === activeFolderId);
+    return favorites.filter((f) => f.folder_ids.includes(activeFolderId));
   }, [activeFolderId, favorites]);
 
   const handleCreateFolder = async (e: React.FormEvent) => {
@@ -90,7 +97,7 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
       type: "deleteFolder",
       targetId: folderId,
       title: "删除文件夹",
-      message: `确定要删除文件夹 "${folderName}" 吗？\n警告！文件夹内的条目会被全部删除！`,
+      message: `确定要删除文件夹 "${folderName}" 吗？\n文件夹内的条目将移到未分类。`,
     });
   };
 
@@ -108,7 +115,7 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
     const { type, targetId } = confirmState;
 
     if (type === "deleteFolder" && typeof targetId === "number") {
-      await removeFolder(targetId);
+      await removeFolder(targetId, "move_to_uncategorized");
       if (activeFolderId === targetId) {
         setActiveFolderId(UNCLASSIFIED_ID);
       }
@@ -162,12 +169,21 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                 </p>
               </div>
             </div>
-            <button
-              onClick={onClose}
-              className="btn btn-circle btn-ghost btn-sm hover:bg-base-200"
-            >
-              <X size={22} />
-            </button>
+            <div className="flex items-center gap-1">
+              <button
+                onClick={() => setIsReviewOpen(true)}
+                className="btn btn-ghost btn-sm gap-1 text-base-content/70 hover:text-primary"
+                title="按记忆曲线复习收藏的条文"
+              >
+                <GraduationCap size={18} /> 复习
+              </button>
+              <button
+                onClick={onClose}
+                className="btn btn-circle btn-ghost btn-sm hover:bg-base-200"
+              >
+                <X size={22} />
+              </button>
+            </div>
           </header>
 
           {/* Body: Two Columns */}
@@ -233,7 +249,7 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                       <span>未分类</span>
                     </div>
                     <span className="badge badge-sm badge-ghost">
-                      {favorites.filter((f) => f.folder_id === null).length}
+                      {favorites.filter((f) => f.folder_ids.length === 0).length}
                     </span>
                   </a>
                 </li>
@@ -263,7 +279,7 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                       <div className="flex items-center gap-1">
                         <span className="badge badge-sm badge-ghost">
                           {
-                            favorites.filter((f) => f.folder_id === folder.id)
+                            favorites.filter((f) => f.folder_ids.includes(folder.id))
                               .length
                           }
                         </span>
@@ -324,6 +340,20 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                             <p className="text-xs text-base-content/60 line-clamp-2 leading-relaxed font-mono">
                               {item.content}
                             </p>
+                            {item.folder_ids.length > 1 && (
+                              <div className="flex flex-wrap gap-1 mt-2">
+                                {folders
+                                  .filter((f) => item.folder_ids.includes(f.id))
+                                  .map((f) => (
+                                    <span
+                                      key={f.id}
+                                      className="badge badge-ghost badge-xs gap-1"
+                                    >
+                                      <Folder size={10} /> {f.name}
+                                    </span>
+                                  ))}
+                              </div>
+                            )}
                           </div>
 
                           {/* Item Actions Dropdown */}
@@ -342,17 +372,48 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                               <li className="menu-title px-2 py-1 text-xs opacity-50">
                                 移动到...
                               </li>
-                              <li>
-                                <a onClick={() => move(item.law_id, null)}>
-                                  未分类
-                                </a>
-                              </li>
+                              {activeFolderId !== UNCLASSIFIED_ID && (
+                                <li>
+                                  <a
+                                    onClick={() =>
+                                      move(item.law_id, null, activeFolderId)
+                                    }
+                                  >
+                                    移出此文件夹
+                                  </a>
+                                </li>
+                              )}
                               {folders.map(
                                 (f) =>
-                                  f.id !== activeFolderId && (
+                                  !item.folder_ids.includes(f.id) && (
                                     <li key={f.id}>
                                       <a
-                                        onClick={() => move(item.law_id, f.id)}
+                                        onClick={() =>
+                                          move(
+                                            item.law_id,
+                                            f.id,
+                                            activeFolderId === UNCLASSIFIED_ID
+                                              ? null
+                                              : activeFolderId
+                                          )
+                                        }
+                                      >
+                                        {f.name}
+                                      </a>
+                                    </li>
+                                  )
+                              )}
+                              <li className="menu-title px-2 py-1 text-xs opacity-50">
+                                同时加入...
+                              </li>
+                              {folders.map(
+                                (f) =>
+                                  !item.folder_ids.includes(f.id) && (
+                                    <li key={`link-${f.id}`}>
+                                      <a
+                                        onClick={() =>
+                                          add(convertToChunk(item), f.id)
+                                        }
                                       >
                                         {f.name}
                                       </a>
@@ -372,7 +433,29 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
                           </div>
                         </div>
 
+                        {diffingId === item.law_id && item.original_content && (
+                          <div className="mt-2 p-2 rounded bg-base-200/50">
+                            <DiffView
+                              left={item.original_content}
+                              right={item.content}
+                            />
+                          </div>
+                        )}
+
                         <div className="card-actions justify-end mt-2 pt-2 border-t border-base-100/50">
+                          {item.original_content && (
+                            <button
+                              onClick={() =>
+                                setDiffingId((id) =>
+                                  id === item.law_id ? null : item.law_id
+                                )
+                              }
+                              className="btn btn-xs btn-ghost gap-1"
+                              title="与收藏时的原文对比"
+                            >
+                              <GitCompare size={14} /> 对比原文
+                            </button>
+                          )}
                           <button
                             onClick={() => {
                               onViewFullText(convertToChunk(item));
@@ -413,6 +496,8 @@ export const FavoritesSidebar: React.FC<FavoritesSidebarProps> = ({
         onConfirm={handleConfirm}
         onCancel={() => setConfirmState((prev) => ({ ...prev, isOpen: false }))}
       />
+
+      <ReviewModal isOpen={isReviewOpen} onClose={() => setIsReviewOpen(false)} />
     </>
   );
 };
@@ -75,7 +75,7 @@ export const FullTextModal: React.FC<FullTextModalProps> = ({
       setIsLoading(true);
       setError(null);
       try {
-        const response = await getFullText(law.source_file);
+        const response = await getFullText(law.source_file, law.source_name);
         setFullText(response.content);
       } catch (err) {
         setError("加载全文失败，请稍后再试。");
@@ -85,7 +85,7 @@ export const FullTextModal: React.FC<FullTextModalProps> = ({
       }
     };
     fetchFullText();
-  }, [law.source_file]);
+  }, [law.source_file, law.source_name]);
 
   useEffect(() => {
     if (fullText) {
@@ -209,7 +209,12 @@ export const FullTextModal: React.FC<FullTextModalProps> = ({
       targetLaw = lawNameRef;
     }
 
-    const content = await getArticleSnippet(targetLaw, artNum, law.law_name);
+    const content = await getArticleSnippet(
+      targetLaw,
+      artNum,
+      law.law_name,
+      law.source_name
+    );
     setPopoverState((prev) => ({ ...prev, content: content }));
   };
 
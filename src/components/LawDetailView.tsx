@@ -1,5 +1,21 @@
 import React, { useState, useEffect, useRef } from "react";
-import { getArticleSnippet, getFullText, LawChunk } from "../services/api";
+import {
+  getArticleSnippet,
+  getFullText,
+  LawChunk,
+  exportLawPdf,
+  formatError,
+  Annotation,
+  AnnotationColor,
+  addAnnotation,
+  getAnnotations,
+  updateAnnotation,
+  deleteAnnotation,
+  lookupTermDefinition,
+  TermDefinition,
+} from "../services/api";
+import { save } from "@tauri-apps/plugin-dialog";
+import { toast } from "react-hot-toast";
 import { AnimatePresence, motion } from "framer-motion";
 import {
   LoaderCircle,
@@ -11,6 +27,10 @@ import {
   ChevronDown,
   X,
   PenTool,
+  Printer,
+  Highlighter,
+  Trash2,
+  BookOpen,
 } from "lucide-react";
 import { CustomPopover } from "./CustomPopover";
 
@@ -48,6 +68,14 @@ const centerTitleClasses =
 const preambleClasses =
   "text-lg leading-8 text-base-content/70 mb-4 px-4 lg:px-10 font-serif indent-8 text-justify";
 
+const annotationClasses: Record<AnnotationColor, string> = {
+  yellow: "bg-yellow-200/70",
+  green: "bg-green-200/70",
+  blue: "bg-sky-200/70",
+  pink: "bg-pink-200/70",
+  purple: "bg-purple-200/70",
+};
+
 function useDebounce<T>(value: T, delay: number): T {
   const [debouncedValue, setDebouncedValue] = useState(value);
   useEffect(() => {
@@ -85,6 +113,12 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
   const hideTimeoutRef = useRef<number | null>(null);
   const snippetCache = useRef<Map<string, string>>(new Map());
 
+  const [annotations, setAnnotations] = useState<Annotation[]>([]);
+  const [editingAnnotation, setEditingAnnotation] = useState<Annotation | null>(
+    null
+  );
+  const [definition, setDefinition] = useState<TermDefinition | null>(null);
+
   const normalizeId = (id: string) => id.replace(/\s+/g, "");
 
   useEffect(() => {
@@ -93,7 +127,7 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
       setError(null);
       setFullText("");
       try {
-        const response = await getFullText(law.source_file);
+        const response = await getFullText(law.source_file, law.source_name);
         setFullText(response.content);
       } catch (err) {
         setError("加载全文失败，请稍后再试。");
@@ -103,7 +137,87 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
       }
     };
     fetchFullText();
-  }, [law.source_file]);
+  }, [law.source_file, law.source_name]);
+
+  // 全文加载后拉取批注，后端会先对照当前全文重新定位
+  useEffect(() => {
+    if (!fullText) return;
+    setEditingAnnotation(null);
+    getAnnotations(law.law_name)
+      .then((items) => {
+        setAnnotations(items);
+        const orphaned = items.filter((a) => a.orphaned).length;
+        if (orphaned > 0) {
+          toast(`有 ${orphaned} 条批注因法规文本变化无法定位`, { icon: "⚠️" });
+        }
+      })
+      .catch((err) => console.error("Failed to load annotations:", err));
+  }, [fullText, law.law_name]);
+
+  // 将当前选中的文字保存为高亮；选区需在同一段内，才能在全文中定位
+  const handleAddAnnotation = async () => {
+    const selected = window.getSelection()?.toString().trim() ?? "";
+    if (!selected) {
+      toast.error("请先选中要高亮的文字");
+      return;
+    }
+    const index = fullText.indexOf(selected);
+    if (index < 0) {
+      toast.error("选中内容跨越多个段落，请分段高亮");
+      return;
+    }
+    try {
+      const created = await addAnnotation(
+        law.law_name,
+        index,
+        index + selected.length
+      );
+      setAnnotations((prev) =>
+        [...prev, created].sort((a, b) => a.start_offset - b.start_offset)
+      );
+      window.getSelection()?.removeAllRanges();
+      setEditingAnnotation(created);
+    } catch (err) {
+      toast.error(`高亮失败：${formatError(err)}`);
+    }
+  };
+
+  // 查询选中术语的法定含义
+  const handleLookupDefinition = async () => {
+    const selected = window.getSelection()?.toString().trim() ?? "";
+    if (!selected) {
+      toast.error("请先选中要查询的术语");
+      return;
+    }
+    try {
+      setDefinition(await lookupTermDefinition(selected));
+    } catch (err) {
+      toast.error(formatError(err));
+    }
+  };
+
+  const handleUpdateAnnotation = async (
+    id: number,
+    changes: { color?: AnnotationColor; note?: string }
+  ) => {
+    try {
+      const updated = await updateAnnotation(id, changes);
+      setAnnotations((prev) => prev.map((a) => (a.id === id ? updated : a)));
+      setEditingAnnotation(updated);
+    } catch (err) {
+      toast.error(`保存批注失败：${formatError(err)}`);
+    }
+  };
+
+  const handleDeleteAnnotation = async (id: number) => {
+    try {
+      await deleteAnnotation(id);
+      setAnnotations((prev) => prev.filter((a) => a.id !== id));
+      setEditingAnnotation(null);
+    } catch (err) {
+      toast.error(`删除批注失败：${formatError(err)}`);
+    }
+  };
 
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -287,7 +401,12 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
       content: "正在查找条文...",
     }));
     try {
-      const content = await getArticleSnippet(targetLaw, artNum, law.law_name);
+      const content = await getArticleSnippet(
+        targetLaw,
+        artNum,
+        law.law_name,
+        law.source_name
+      );
       snippetCache.current.set(cacheKey, content);
       setPopoverState((prev) => (prev.visible ? { ...prev, content } : prev));
     } catch (e) {
@@ -391,7 +510,43 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
     return cleaned;
   };
 
+  // 按原文标出批注；渲染时各行已去掉首尾空白，无法直接使用偏移
+  const markAnnotations = (nodes: React.ReactNode[]): React.ReactNode[] => {
+    const byQuote = new Map(
+      annotations.filter((a) => !a.orphaned).map((a) => [a.quote, a])
+    );
+    if (byQuote.size === 0) return nodes;
+    const regex = new RegExp(
+      `(${[...byQuote.keys()]
+        .sort((a, b) => b.length - a.length)
+        .map((q) => q.replace(/[.*+?^${}()|[\]\\]/g, "\\$&"))
+        .join("|")})`
+    );
+
+    return nodes.flatMap((node, i) => {
+      if (typeof node !== "string") return node;
+      return node.split(regex).map((part, j) => {
+        const annotation = byQuote.get(part);
+        if (!annotation) return part;
+        return (
+          <mark
+            key={`a-${i}-${j}`}
+            className={`${annotationClasses[annotation.color]} text-base-content rounded-sm cursor-pointer`}
+            title={annotation.note ?? "点击编辑批注"}
+            onClick={(e) => {
+              e.stopPropagation();
+              setEditingAnnotation(annotation);
+            }}
+          >
+            {part}
+          </mark>
+        );
+      });
+    });
+  };
+
   const highlightContent = (nodes: React.ReactNode[]): React.ReactNode[] => {
+    nodes = markAnnotations(nodes);
     if (!debouncedQuery.trim()) return nodes;
     const regex = new RegExp(
       `(${debouncedQuery.replace(/[.*+?^${}()|[\]\\]/g, "\\$&")})`,
@@ -866,6 +1021,108 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
         )}
       </AnimatePresence>
 
+      {/* 术语释义 */}
+      <AnimatePresence>
+        {definition && (
+          <motion.div
+            initial={{ y: -20, opacity: 0 }}
+            animate={{ y: 0, opacity: 1 }}
+            exit={{ y: -20, opacity: 0 }}
+            className="absolute top-20 left-1/2 -translate-x-1/2 z-50 bg-base-100 shadow-xl border border-base-200 rounded-lg p-3 w-96 flex flex-col gap-2"
+          >
+            <div className="flex items-center gap-2">
+              <span className="font-bold text-sm">{definition.term}</span>
+              {definition.confidence !== "high" && (
+                <span className="badge badge-xs badge-warning">
+                  {definition.confidence === "medium" ? "可能的定义" : "仅供参考"}
+                </span>
+              )}
+              <div className="grow" />
+              <button
+                className="btn btn-ghost btn-xs btn-square"
+                onClick={() => setDefinition(null)}
+              >
+                <X size={14} />
+              </button>
+            </div>
+            <p className="text-sm leading-6 text-base-content/80 max-h-60 overflow-y-auto">
+              {definition.definition}
+            </p>
+            {definition.chunk && (
+              <button
+                className="btn btn-ghost btn-xs self-end"
+                onClick={() => {
+                  onOpenLink(definition.chunk!);
+                  setDefinition(null);
+                }}
+              >
+                《{definition.chunk.law_name}》{definition.chunk.article_number}
+              </button>
+            )}
+          </motion.div>
+        )}
+      </AnimatePresence>
+
+      {/* 批注编辑 */}
+      <AnimatePresence>
+        {editingAnnotation && (
+          <motion.div
+            initial={{ y: -20, opacity: 0 }}
+            animate={{ y: 0, opacity: 1 }}
+            exit={{ y: -20, opacity: 0 }}
+            className="absolute top-20 left-1/2 -translate-x-1/2 z-50 bg-base-100 shadow-xl border border-base-200 rounded-lg p-3 w-80 flex flex-col gap-2"
+          >
+            <div className="text-xs text-base-content/60 line-clamp-2">
+              “{editingAnnotation.quote}”
+            </div>
+            <div className="flex items-center gap-2">
+              {(Object.keys(annotationClasses) as AnnotationColor[]).map(
+                (color) => (
+                  <button
+                    key={color}
+                    className={`w-5 h-5 rounded-full ${annotationClasses[color]} ${
+                      editingAnnotation.color === color
+                        ? "ring-2 ring-primary ring-offset-1"
+                        : ""
+                    }`}
+                    onClick={() =>
+                      handleUpdateAnnotation(editingAnnotation.id, { color })
+                    }
+                  />
+                )
+              )}
+              <div className="grow" />
+              <button
+                className="btn btn-ghost btn-xs btn-square text-error"
+                onClick={() => handleDeleteAnnotation(editingAnnotation.id)}
+                title="删除批注"
+              >
+                <Trash2 size={14} />
+              </button>
+              <button
+                className="btn btn-ghost btn-xs btn-square"
+                onClick={() => setEditingAnnotation(null)}
+              >
+                <X size={14} />
+              </button>
+            </div>
+            <textarea
+              key={editingAnnotation.id}
+              className="textarea textarea-bordered textarea-sm w-full"
+              placeholder="添加笔记（失去焦点时保存）"
+              defaultValue={editingAnnotation.note ?? ""}
+              onBlur={(e) => {
+                if (e.target.value !== (editingAnnotation.note ?? "")) {
+                  handleUpdateAnnotation(editingAnnotation.id, {
+                    note: e.target.value,
+                  });
+                }
+              }}
+            />
+          </motion.div>
+        )}
+      </AnimatePresence>
+
       {/* 目录栏 */}
       {toc.length > 0 && (
         <div className="w-64 bg-base-200/50 border-r border-base-300 h-full overflow-y-auto p-4 hidden xl:block shrink-0">
@@ -922,6 +1179,49 @@ export const LawDetailView: React.FC<LawDetailViewProps> = ({
               <span className="hidden sm:inline text-xs">全文加入素材</span>
             </button>
 
+            <button
+              onMouseDown={(e) => e.preventDefault()}
+              onClick={handleAddAnnotation}
+              className="btn btn-ghost btn-sm gap-2 text-base-content/70 hover:text-primary"
+              title="高亮选中的文字"
+            >
+              <Highlighter size={16} />
+              <span className="hidden sm:inline text-xs">高亮</span>
+            </button>
+
+            <button
+              onMouseDown={(e) => e.preventDefault()}
+              onClick={handleLookupDefinition}
+              className="btn btn-ghost btn-sm gap-2 text-base-content/70 hover:text-primary"
+              title="查询选中术语的法定含义"
+            >
+              <BookOpen size={16} />
+              <span className="hidden sm:inline text-xs">释义</span>
+            </button>
+
+            <button
+              onClick={async () => {
+                const path = await save({
+                  title: "导出 PDF",
+                  defaultPath: `${law.law_name}.pdf`,
+                  filters: [{ name: "PDF", extensions: ["pdf"] }],
+                });
+                if (!path) return;
+                toast
+                  .promise(exportLawPdf(law.law_name, path), {
+                    loading: "正在生成 PDF...",
+                    success: (pages) => `已导出 PDF，共 ${pages} 页`,
+                    error: (err) => `导出失败：${formatError(err)}`,
+                  })
+                  .catch((err) => console.error(err));
+              }}
+              className="btn btn-ghost btn-sm gap-2 text-base-content/70 hover:text-primary"
+              title="导出为 PDF 以便打印"
+            >
+              <Printer size={16} />
+              <span className="hidden sm:inline text-xs">导出 PDF</span>
+            </button>
+
             {!showSearch && (
               <button
                 onClick={() => {
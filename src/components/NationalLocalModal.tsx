@@ -0,0 +1,159 @@
+// frontend/src/components/NationalLocalModal.tsx
+// 全国性规定与地方规定对照：两栏并排显示检索结果，启用 AI 对话时附差异比较
+
+import React, { useEffect, useState } from "react";
+import { LoaderCircle, Scale, X } from "lucide-react";
+import { toast } from "react-hot-toast";
+import {
+  LawChunk,
+  NationalLocalComparison,
+  formatError,
+  searchNationalVsLocal,
+} from "../services/api";
+
+interface NationalLocalModalProps {
+  isOpen: boolean;
+  query: string;
+  region: string;
+  onClose: () => void;
+  onOpenLaw: (law: LawChunk) => void;
+}
+
+export const NationalLocalModal: React.FC<NationalLocalModalProps> = ({
+  isOpen,
+  query,
+  region,
+  onClose,
+  onOpenLaw,
+}) => {
+  const [result, setResult] = useState<NationalLocalComparison | null>(null);
+  const [isLoading, setIsLoading] = useState(false);
+  // 鼠标停在比较点上时高亮其依据的条文
+  const [highlighted, setHighlighted] = useState<string[]>([]);
+
+  useEffect(() => {
+    if (!isOpen) return;
+    setResult(null);
+    setHighlighted([]);
+    setIsLoading(true);
+    searchNationalVsLocal(query, region)
+      .then(setResult)
+      .catch((err) => {
+        toast.error(formatError(err));
+        onClose();
+      })
+      .finally(() => setIsLoading(false));
+  }, [isOpen, query, region]);
+
+  if (!isOpen) return null;
+
+  const byId = new Map<string, LawChunk>();
+  result?.national.forEach((c) => byId.set(c.id, c));
+  result?.local.forEach((c) => byId.set(c.id, c));
+
+  const renderCell = (id: string | null) => {
+    const chunk = id ? byId.get(id) : undefined;
+    if (!chunk) return <div className="text-xs text-base-content/30 p-3">—</div>;
+    return (
+      <div
+        className={`border rounded-lg p-3 text-sm cursor-pointer hover:bg-base-200/50 transition-colors ${
+          highlighted.includes(chunk.id) ? "border-primary bg-primary/5" : "border-base-200"
+        }`}
+        onClick={() => onOpenLaw(chunk)}
+      >
+        <div className="font-medium mb-1">
+          《{chunk.law_name}》{chunk.article_number}
+        </div>
+        <p className="text-xs text-base-content/70 leading-5 line-clamp-4">{chunk.content}</p>
+      </div>
+    );
+  };
+
+  const citationsOf = (ids: string[]) =>
+    ids
+      .map((id) => byId.get(id))
+      .filter((c): c is LawChunk => !!c)
+      .map((c) => `《${c.law_name}》${c.article_number}`)
+      .join("、");
+
+  return (
+    <div className="modal modal-open z-60">
+      <div className="modal-backdrop" onClick={onClose}></div>
+      <div className="modal-box max-w-5xl bg-base-100 shadow-2xl border border-base-200 p-6 flex flex-col gap-4">
+        <div className="flex items-center justify-between">
+          <h3 className="font-bold text-lg flex items-center gap-2">
+            <Scale size={20} className="text-primary" /> 全国与{region}规定对照
+          </h3>
+          <button className="btn btn-ghost btn-sm btn-circle" onClick={onClose}>
+            <X size={18} />
+          </button>
+        </div>
+
+        {isLoading && (
+          <div className="flex items-center justify-center gap-2 py-12 text-base-content/60">
+            <LoaderCircle size={18} className="animate-spin" /> 正在检索…
+          </div>
+        )}
+
+        {result && (
+          <div className="max-h-[65vh] overflow-y-auto flex flex-col gap-4">
+            {result.degraded_reason && (
+              <div className="alert alert-warning text-xs py-2">
+                嵌入服务不可用，结果来自关键词匹配：{result.degraded_reason}
+              </div>
+            )}
+
+            {result.comparison && (
+              <div className="bg-base-200/40 rounded-lg p-4 text-sm flex flex-col gap-2">
+                <p className="font-medium">{result.comparison.summary}</p>
+                {result.comparison.points.map((p, index) => (
+                  <div
+                    key={index}
+                    className="border-t border-base-200 pt-2"
+                    onMouseEnter={() => setHighlighted([...p.national_ids, ...p.local_ids])}
+                    onMouseLeave={() => setHighlighted([])}
+                  >
+                    <div className="flex items-center gap-2">
+                      <span className="font-medium">{p.aspect}</span>
+                      {p.divergent && <span className="badge badge-xs badge-warning">有差异</span>}
+                    </div>
+                    <div className="grid grid-cols-2 gap-4 text-xs text-base-content/70 mt-1">
+                      <div>
+                        {p.national}
+                        {p.national_ids.length > 0 && (
+                          <span className="text-base-content/40">（{citationsOf(p.national_ids)}）</span>
+                        )}
+                      </div>
+                      <div>
+                        {p.local}
+                        {p.local_ids.length > 0 && (
+                          <span className="text-base-content/40">（{citationsOf(p.local_ids)}）</span>
+                        )}
+                      </div>
+                    </div>
+                  </div>
+                ))}
+              </div>
+            )}
+
+            <div className="grid grid-cols-2 gap-4 text-xs font-bold text-base-content/60">
+              <div>全国性规定（{result.national.length}）</div>
+              <div>
+                {result.region}地方规定（{result.local.length}）
+              </div>
+            </div>
+            {result.pairs.length === 0 && (
+              <p className="text-sm text-base-content/50 text-center py-6">未检索到相关条文</p>
+            )}
+            {result.pairs.map((pair, index) => (
+              <div key={index} className="grid grid-cols-2 gap-4">
+                {renderCell(pair.national_id)}
+                {renderCell(pair.local_id)}
+              </div>
+            ))}
+          </div>
+        )}
+      </div>
+    </div>
+  );
+};
@@ -0,0 +1,108 @@
+// frontend/src/components/QuickSearch.tsx
+// 全局快捷键呼出的快速查询小窗：输入引用或关键词，回车查询，点击结果复制引用
+import React, { useEffect, useRef, useState } from "react";
+import { getCurrentWindow } from "@tauri-apps/api/window";
+import { listen } from "@tauri-apps/api/event";
+import { LoaderCircle, Search } from "lucide-react";
+import { Toaster, toast } from "react-hot-toast";
+import {
+  quickLookup,
+  QuickLookupResult,
+  QuickLookupItem,
+  getChunkContent,
+  formatError,
+} from "../services/api";
+
+export const QuickSearch: React.FC = () => {
+  const [text, setText] = useState("");
+  const [result, setResult] = useState<QuickLookupResult | null>(null);
+  const [isLoading, setIsLoading] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+  const inputRef = useRef<HTMLInputElement>(null);
+
+  // 每次呼出时选中输入框，方便直接输入新的查询
+  useEffect(() => {
+    inputRef.current?.focus();
+    const unlisten = listen("quick-search-shown", () => {
+      inputRef.current?.select();
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const handleLookup = async () => {
+    if (!text.trim()) return;
+    setIsLoading(true);
+    setError(null);
+    try {
+      setResult(await quickLookup(text));
+    } catch (err) {
+      setResult(null);
+      setError(formatError(err));
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  const copyItem = async (item: QuickLookupItem) => {
+    const content = item.truncated
+      ? await getChunkContent(item.chunk_id)
+      : item.excerpt;
+    await navigator.clipboard.writeText(`${item.citation}：\n${content}`);
+    toast.success("已复制");
+  };
+
+  return (
+    <div
+      className="h-screen flex flex-col bg-base-100 border border-base-300 rounded-lg overflow-hidden"
+      onKeyDown={(e) => {
+        if (e.key === "Escape") getCurrentWindow().hide();
+      }}
+    >
+      <Toaster position="bottom-center" />
+      <div className="flex items-center gap-2 px-4 py-3 border-b border-base-200">
+        <Search size={18} className="opacity-50 shrink-0" />
+        <input
+          ref={inputRef}
+          className="grow bg-transparent outline-none text-base"
+          placeholder="输入引用（如 民法典第五百条）或关键词，回车查询"
+          value={text}
+          onChange={(e) => setText(e.target.value)}
+          onKeyDown={(e) => {
+            if (e.key === "Enter") handleLookup();
+          }}
+        />
+        {isLoading && <LoaderCircle size={18} className="animate-spin opacity-50" />}
+      </div>
+
+      <div className="grow overflow-y-auto p-3 space-y-2">
+        {error && <div className="text-error text-sm px-1">{error}</div>}
+        {result && !result.exact && result.items.length > 0 && (
+          <div className="text-xs text-base-content/50 px-1">
+            {result.degraded_reason
+              ? "未找到对应条文，嵌入服务暂不可用，以下为关键词匹配结果："
+              : "未找到对应条文，以下为相关结果："}
+          </div>
+        )}
+        {result?.items.map((item) => (
+          <div
+            key={item.chunk_id}
+            className="p-3 rounded-lg hover:bg-base-200 cursor-pointer transition-colors"
+            onClick={() => copyItem(item)}
+            title="点击复制引用"
+          >
+            <div className="font-bold text-sm mb-1">{item.citation}</div>
+            <div className="text-sm text-base-content/80 leading-6 whitespace-pre-line">
+              {item.excerpt}
+              {item.truncated && "……"}
+            </div>
+          </div>
+        ))}
+        {result && result.items.length === 0 && (
+          <div className="text-sm text-base-content/50 px-1">未找到相关法条</div>
+        )}
+      </div>
+    </div>
+  );
+};
@@ -0,0 +1,46 @@
+// frontend/src/components/RecentLaws.tsx
+// 首页：近 12 个月内发布或修订的法规
+
+import React, { useEffect, useState } from "react";
+import { CalendarClock } from "lucide-react";
+import { LawBrowseItem, getRecentLaws } from "../services/api";
+
+interface RecentLawsProps {
+  onOpen: (item: LawBrowseItem) => void;
+}
+
+export const RecentLaws: React.FC<RecentLawsProps> = ({ onOpen }) => {
+  const [items, setItems] = useState<LawBrowseItem[]>([]);
+
+  useEffect(() => {
+    getRecentLaws(12, 8)
+      .then(setItems)
+      .catch((err) => console.error("Failed to load recent laws:", err));
+  }, []);
+
+  if (items.length === 0) {
+    return null;
+  }
+
+  return (
+    <div className="text-left">
+      <div className="flex items-center gap-2 text-xs font-bold text-base-content/50 mb-2 px-1">
+        <CalendarClock size={14} /> 近一年更新的法规
+      </div>
+      <div className="grid grid-cols-1 sm:grid-cols-2 gap-2">
+        {items.map((item) => (
+          <button
+            key={item.law_name}
+            className="flex items-center justify-between gap-2 px-3 py-2 rounded-lg border border-base-200 bg-base-100 hover:border-primary/40 hover:bg-base-200/40 transition-colors text-left"
+            onClick={() => onOpen(item)}
+          >
+            <span className="text-sm truncate">{item.law_name}</span>
+            <span className="text-xs text-base-content/50 shrink-0 font-mono">
+              {item.publish_date}
+            </span>
+          </button>
+        ))}
+      </div>
+    </div>
+  );
+};
@@ -1,7 +1,16 @@
 // frontend/src/components/ResultCard.tsx
 
-import React from "react";
-import { LawChunk } from "../services/api";
+import React, { useState } from "react";
+import {
+  LawChunk,
+  resolveChunkContent,
+  makeDeepLink,
+  workspaceAdd,
+  formatError,
+  addFavorite,
+  suggestFolder,
+  FolderSuggestion,
+} from "../services/api";
 import { motion, Variants } from "framer-motion";
 import {
   Calendar,
@@ -11,10 +20,17 @@ import {
   MapPin,
   Copy,
   PenTool,
+  Link,
+  GitBranch,
+  Layers,
+  Database,
+  FolderPlus,
+  Sparkles,
 } from "lucide-react";
 import { highlightText } from "../utils/highlight";
 import { toast } from "react-hot-toast";
 import { useFavorites } from "../hooks/useFavorites";
+import { CrossRefPanel } from "./CrossRefPanel";
 
 interface ResultCardProps {
   law: LawChunk;
@@ -45,14 +61,31 @@ export const ResultCard: React.FC<ResultCardProps> = ({
   
   // 检查当前条目是否已收藏 (依赖 law.id)
   const favored = isFavorite(law.id);
+  const [showRefs, setShowRefs] = useState(false);
+  // 收藏后推荐的文件夹，用户点选后才加入
+  const [folderSuggestions, setFolderSuggestions] = useState<FolderSuggestion[]>([]);
 
   // 切换收藏状态
   const handleToggleFavorite = async (e: React.MouseEvent) => {
     e.stopPropagation(); // 防止触发卡片点击
     if (favored) {
       await remove(law.id);
-    } else {
-      await add(law);
+      setFolderSuggestions([]);
+    } else if (await add(law)) {
+      suggestFolder(law)
+        .then(setFolderSuggestions)
+        .catch((err) => console.error("Folder suggestion failed", err));
+    }
+  };
+
+  const fileIntoFolder = async (e: React.MouseEvent, s: FolderSuggestion) => {
+    e.stopPropagation();
+    try {
+      await addFavorite(law, s.folder_id);
+      toast.success(`已放入「${s.folder_name}」`);
+      setFolderSuggestions([]);
+    } catch (err) {
+      toast.error(formatError(err));
     }
   };
 
@@ -72,13 +105,35 @@ export const ResultCard: React.FC<ResultCardProps> = ({
     Math.min(100, Math.round((1 - law._distance / 2) * 100))
   );
 
-  const copyCitation = (e: React.MouseEvent) => {
+  const copyCitation = async (e: React.MouseEvent) => {
     e.stopPropagation();
-    const text = `《${law.law_name}》${law.article_number}：\n${law.content}`;
+    const content = await resolveChunkContent(law);
+    const text = `《${law.law_name}》${law.article_number}：\n${content}`;
     navigator.clipboard.writeText(text);
     toast.success("已复制引用格式");
   };
 
+  const addToWorkspace = async (e: React.MouseEvent) => {
+    e.stopPropagation();
+    try {
+      const count = await workspaceAdd(law);
+      toast.success(`已加入工作区（${count} 条）`);
+    } catch (err) {
+      toast.error(formatError(err));
+    }
+  };
+
+  const copyDeepLink = async (e: React.MouseEvent) => {
+    e.stopPropagation();
+    try {
+      navigator.clipboard.writeText(await makeDeepLink(law.id));
+      toast.success("已复制条文链接");
+    } catch (err) {
+      console.error(err);
+      toast.error("生成链接失败");
+    }
+  };
+
   // === 紧凑模式 (Compact Mode) ===
   if (density === "compact") {
     return (
@@ -114,6 +169,11 @@ export const ResultCard: React.FC<ResultCardProps> = ({
               <span className="badge badge-xs badge-ghost font-mono shrink-0">
                 {law.article_number}
               </span>
+              {law.source_name && (
+                <span className="badge badge-xs badge-outline text-secondary shrink-0">
+                  {law.source_name}
+                </span>
+              )}
             </div>
             <span className="text-xs text-base-content/60 truncate opacity-80 block">
               {highlightText(law.content, query)}
@@ -208,6 +268,11 @@ export const ResultCard: React.FC<ResultCardProps> = ({
               <MapPin size={12} /> <span>{law.region}</span>
             </div>
           )}
+          {law.source_name && (
+            <div className="badge badge-outline badge-sm gap-1.5 text-secondary">
+              <Database size={12} /> <span>{law.source_name}</span>
+            </div>
+          )}
           <div className="badge badge-ghost badge-sm gap-1.5 text-base-content/60">
             <Calendar size={12} /> <span>{formatDate(law.publish_date)}</span>
           </div>
@@ -237,6 +302,21 @@ export const ResultCard: React.FC<ResultCardProps> = ({
           <button onClick={copyCitation} className="btn btn-xs btn-ghost gap-1">
             <Copy size={14} /> 引用
           </button>
+
+          <button onClick={copyDeepLink} className="btn btn-xs btn-ghost gap-1">
+            <Link size={14} /> 链接
+          </button>
+
+          <button onClick={addToWorkspace} className="btn btn-xs btn-ghost gap-1">
+            <Layers size={14} /> 工作区
+          </button>
+
+          <button
+            onClick={() => setShowRefs((v) => !v)}
+            className={`btn btn-xs btn-ghost gap-1 ${showRefs ? "text-primary" : ""}`}
+          >
+            <GitBranch size={14} /> 引用关系
+          </button>
           
           <button
             onClick={() => onViewFullText(law)}
@@ -245,6 +325,38 @@ export const ResultCard: React.FC<ResultCardProps> = ({
             查看全文 <ChevronsRight size={14} />
           </button>
         </div>
+
+        {favored && folderSuggestions.length > 0 && (
+          <div className="flex flex-wrap items-center gap-2 mt-2 text-xs">
+            <span className="text-base-content/60">放入文件夹：</span>
+            {folderSuggestions.map((s) => (
+              <button
+                key={s.folder_id}
+                className="btn btn-xs btn-outline gap-1"
+                title={`${s.reason}（置信度 ${Math.round(s.confidence * 100)}%）`}
+                onClick={(e) => fileIntoFolder(e, s)}
+              >
+                {s.source === "ai" ? <Sparkles size={12} /> : <FolderPlus size={12} />}
+                {s.folder_name}
+              </button>
+            ))}
+            <button
+              className="btn btn-xs btn-ghost"
+              onClick={(e) => {
+                e.stopPropagation();
+                setFolderSuggestions([]);
+              }}
+            >
+              忽略
+            </button>
+          </div>
+        )}
+
+        {showRefs && (
+          <div className="mt-3">
+            <CrossRefPanel law={law} onOpen={onViewFullText} />
+          </div>
+        )}
       </div>
     </motion.div>
   );
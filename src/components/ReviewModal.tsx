@@ -0,0 +1,164 @@
+// frontend/src/components/ReviewModal.tsx
+// 收藏复习：先看隐去条号的内容回忆出处，翻面后按记忆程度评分
+
+import React, { useEffect, useState } from "react";
+import { GraduationCap, LoaderCircle, X } from "lucide-react";
+import { toast } from "react-hot-toast";
+import {
+  ReviewCard,
+  ReviewGrade,
+  getDueReviews,
+  submitReview,
+  enrollReviews,
+  formatError,
+} from "../services/api";
+
+interface ReviewModalProps {
+  isOpen: boolean;
+  onClose: () => void;
+}
+
+const GRADES: { grade: ReviewGrade; label: string; className: string }[] = [
+  { grade: 0, label: "忘记", className: "btn-error" },
+  { grade: 1, label: "困难", className: "btn-warning" },
+  { grade: 2, label: "记得", className: "btn-success" },
+  { grade: 3, label: "轻松", className: "btn-info" },
+];
+
+export const ReviewModal: React.FC<ReviewModalProps> = ({ isOpen, onClose }) => {
+  const [cards, setCards] = useState<ReviewCard[] | null>(null);
+  const [revealed, setRevealed] = useState(false);
+  const [reviewedCount, setReviewedCount] = useState(0);
+
+  const loadCards = async () => {
+    try {
+      setCards(await getDueReviews(50));
+      setRevealed(false);
+    } catch (err) {
+      toast.error(`加载复习卡片失败：${formatError(err)}`);
+      setCards([]);
+    }
+  };
+
+  useEffect(() => {
+    if (isOpen) {
+      setCards(null);
+      setReviewedCount(0);
+      loadCards();
+    }
+  }, [isOpen]);
+
+  if (!isOpen) return null;
+
+  const current = cards?.[0];
+
+  const handleGrade = async (grade: ReviewGrade) => {
+    if (!current) return;
+    try {
+      await submitReview(current.law_id, grade);
+      setReviewedCount((n) => n + 1);
+      setRevealed(false);
+      // 忘记的卡片稍后会再次到期，本轮结束时重新拉取
+      const rest = cards!.slice(1);
+      if (rest.length === 0) {
+        await loadCards();
+      } else {
+        setCards(rest);
+      }
+    } catch (err) {
+      toast.error(`提交失败：${formatError(err)}`);
+    }
+  };
+
+  const handleEnrollAll = async () => {
+    try {
+      const added = await enrollReviews();
+      toast.success(`已加入 ${added} 条收藏`);
+      await loadCards();
+    } catch (err) {
+      toast.error(formatError(err));
+    }
+  };
+
+  return (
+    <div className="modal modal-open z-60">
+      <div className="modal-backdrop" onClick={onClose}></div>
+      <div className="modal-box max-w-2xl bg-base-100 shadow-2xl border border-base-200 p-6">
+        <div className="flex items-center justify-between mb-4">
+          <h3 className="font-bold text-lg flex items-center gap-2">
+            <GraduationCap size={20} className="text-primary" /> 收藏复习
+          </h3>
+          <div className="flex items-center gap-3">
+            <span className="text-xs text-base-content/50">
+              已复习 {reviewedCount}
+              {cards && cards.length > 0 && ` · 待复习 ${cards.length}`}
+            </span>
+            <button className="btn btn-ghost btn-sm btn-circle" onClick={onClose}>
+              <X size={18} />
+            </button>
+          </div>
+        </div>
+
+        {!cards && (
+          <div className="flex justify-center py-16">
+            <LoaderCircle className="animate-spin text-primary" />
+          </div>
+        )}
+
+        {cards && !current && (
+          <div className="flex flex-col items-center gap-4 py-12 text-center">
+            <p className="text-base-content/70">
+              {reviewedCount > 0 ? "本轮复习完成 🎉" : "暂无到期的复习卡片"}
+            </p>
+            <button className="btn btn-sm btn-outline" onClick={handleEnrollAll}>
+              将全部收藏加入复习计划
+            </button>
+          </div>
+        )}
+
+        {current && (
+          <div className="flex flex-col gap-4">
+            <div
+              className="min-h-48 max-h-[50vh] overflow-y-auto p-5 rounded-lg bg-base-200/40 text-base leading-8 whitespace-pre-line cursor-pointer"
+              onClick={() => setRevealed(true)}
+              title={revealed ? undefined : "点击显示出处"}
+            >
+              {revealed ? current.content : current.masked_content}
+            </div>
+
+            {revealed ? (
+              <>
+                <div className="text-center font-bold">
+                  《{current.law_name}》{current.article_number}
+                </div>
+                {current.note && (
+                  <div className="text-xs text-base-content/60 text-center">
+                    笔记：{current.note}
+                  </div>
+                )}
+                <div className="grid grid-cols-4 gap-2">
+                  {GRADES.map(({ grade, label, className }) => (
+                    <button
+                      key={grade}
+                      className={`btn btn-sm ${className}`}
+                      onClick={() => handleGrade(grade)}
+                    >
+                      {label}
+                    </button>
+                  ))}
+                </div>
+              </>
+            ) : (
+              <button
+                className="btn btn-primary btn-sm"
+                onClick={() => setRevealed(true)}
+              >
+                显示出处
+              </button>
+            )}
+          </div>
+        )}
+      </div>
+    </div>
+  );
+};
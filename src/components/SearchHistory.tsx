@@ -1,10 +1,11 @@
 // frontend/src/components/SearchHistory.tsx
 import React from "react";
-import { History } from "lucide-react";
+import { History, BrainCircuit } from "lucide-react";
+import { SearchHistoryItem } from "../services/api";
 
 interface SearchHistoryProps {
-  history: string[];
-  onHistoryClick: (query: string) => void;
+  history: SearchHistoryItem[];
+  onHistoryClick: (item: SearchHistoryItem) => void;
   onClearHistory: () => void;
 }
 
@@ -30,13 +31,25 @@ export const SearchHistory: React.FC<SearchHistoryProps> = ({
       </div>
 
       <div className="flex flex-wrap gap-2">
-        {history.map((item, index) => (
+        {history.map((item) => (
           <button
-            key={index}
+            key={item.id}
             onClick={() => onHistoryClick(item)}
-            className="btn btn-xs btn-outline bg-base-100 hover:bg-base-200 border-base-300 text-base-content font-normal h-auto py-1 px-3"
+            title={
+              item.result_count === 0
+                ? "上次没有找到结果"
+                : item.search_type === "agent"
+                ? "深度思考"
+                : undefined
+            }
+            className={`btn btn-xs btn-outline bg-base-100 hover:bg-base-200 border-base-300 font-normal h-auto py-1 px-3 ${
+              item.result_count === 0
+                ? "text-base-content/40 border-dashed"
+                : "text-base-content"
+            }`}
           >
-            {item}
+            {item.search_type === "agent" && <BrainCircuit size={12} />}
+            {item.query}
           </button>
         ))}
       </div>
@@ -19,14 +19,37 @@ import {
   HardDrive,
   FolderOpen,
   Github,
+  Server,
+  Copy,
+  Search,
+  Gauge,
+  FileJson,
 } from "lucide-react";
 import {
   getSettings,
   saveSettings,
+  getEffectivePaths,
+  EffectivePaths,
+  DataSourceInfo,
+  listDataSources,
+  addDataSource,
+  setDataSourceEnabled,
+  removeDataSource,
   AppSettings,
   checkAiConnection,
   selectFolder,
+  setDataPath,
+  formatError,
+  getApiServerStatus,
+  ApiServerStatus,
+  runRetrievalBenchmark,
+  RetrievalBenchmarkReport,
+  BenchmarkProgress,
+  getMetricsSnapshot,
+  resetMetrics,
+  MetricsReport,
 } from "../services/api";
+import { open } from "@tauri-apps/plugin-dialog";
 import { getVersion } from "@tauri-apps/api/app";
 import { openUrl } from "@tauri-apps/plugin-opener";
 
@@ -85,7 +108,16 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
   const [activeTab, setActiveTab] = useState<TabKey>("general");
   const [isLoading, setIsLoading] = useState(false);
   const [isTesting, setIsTesting] = useState(false);
+  const [benchmarkProgress, setBenchmarkProgress] = useState<BenchmarkProgress | null>(null);
+  const [benchmarkReport, setBenchmarkReport] = useState<RetrievalBenchmarkReport | null>(null);
+  const [metrics, setMetrics] = useState<MetricsReport | null>(null);
   const [appVersion, setAppVersion] = useState("");
+  const [apiStatus, setApiStatus] = useState<ApiServerStatus | null>(null);
+  const [effectivePaths, setEffectivePaths] = useState<EffectivePaths | null>(
+    null
+  );
+  const [dataSources, setDataSources] = useState<DataSourceInfo[]>([]);
+  const [newSourceName, setNewSourceName] = useState("");
 
   useEffect(() => {
     getVersion().then(setAppVersion);
@@ -98,20 +130,41 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
         .then((data) => setConfig((prev) => ({ ...prev, ...data })))
         .catch(() => toast.error("加载设置失败"))
         .finally(() => setIsLoading(false));
+      getApiServerStatus()
+        .then(setApiStatus)
+        .catch(() => setApiStatus(null));
+      getEffectivePaths()
+        .then(setEffectivePaths)
+        .catch(() => setEffectivePaths(null));
+      listDataSources()
+        .then(setDataSources)
+        .catch(() => setDataSources([]));
     }
   }, [isOpen]);
 
+  // 数据源命令直接写入设置，同步到表单，避免保存时被旧的列表覆盖
+  const updateDataSources = async (op: () => Promise<DataSourceInfo[]>) => {
+    try {
+      setDataSources(await op());
+      const latest = await getSettings();
+      setConfig((prev) => ({ ...prev, data_sources: latest.data_sources }));
+    } catch (e) {
+      toast.error(formatError(e));
+    }
+  };
+
   const handleSave = async () => {
     try {
       if (config.chat_top_k > config.search_top_k) {
         toast.error("AI参考数量不能大于搜索返回总数");
         return;
       }
-      await saveSettings(config);
+      const warnings = await saveSettings(config);
       toast.success("设置已保存");
+      warnings.forEach((w) => toast(w.message, { icon: "⚠️" }));
       onClose();
-    } catch {
-      toast.error("保存失败");
+    } catch (e) {
+      toast.error(`保存失败：${formatError(e)}`);
     }
   };
 
@@ -133,6 +186,52 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
       .finally(() => setIsTesting(false));
   };
 
+  // 评测使用已保存的设置，修改模型后需先保存
+  const handleRunBenchmark = async (custom: boolean) => {
+    let path: string | null = null;
+    if (custom) {
+      const selected = await open({
+        multiple: false,
+        title: "选择评测用例文件",
+        filters: [{ name: "JSON", extensions: ["json"] }],
+      });
+      if (!selected) return;
+      path = selected as string;
+    }
+    setBenchmarkReport(null);
+    setBenchmarkProgress({ processed: 0, total: 0, current_query: "" });
+    try {
+      const report = await runRetrievalBenchmark(path, setBenchmarkProgress);
+      setBenchmarkReport(report);
+      if (report.report_path) toast.success(`评测摘要已保存到 ${report.report_path}`);
+    } catch (e) {
+      toast.error(`评测失败：${formatError(e)}`);
+    } finally {
+      setBenchmarkProgress(null);
+    }
+  };
+
+  const refreshMetrics = async () => {
+    try {
+      setMetrics(await getMetricsSnapshot());
+    } catch (e) {
+      toast.error(formatError(e));
+    }
+  };
+
+  // 复制为纯文本表格，便于粘贴到问题反馈
+  const copyMetrics = () => {
+    if (!metrics) return;
+    const lines = metrics.commands.map(
+      (m) =>
+        `${m.command}\t${m.count}\t${m.error_count}\t${m.p50_ms.toFixed(1)}\t${m.p95_ms.toFixed(1)}\t${m.max_ms.toFixed(1)}`
+    );
+    navigator.clipboard.writeText(
+      [`统计时长 ${metrics.since_secs} 秒`, "命令\t次数\t失败\tp50(ms)\tp95(ms)\t最大(ms)", ...lines].join("\n")
+    );
+    toast.success("已复制耗时统计");
+  };
+
   const handleOpenBrowser = async (url: string) => {
     try {
       await openUrl(url);
@@ -350,6 +449,88 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
                   警告：本项目目前基于 embeddinggemma:300m
                   模型制作，如果更换模型可能会导致结果不正确。
                 </div>
+
+                <fieldset className="fieldset bg-base-200/30 p-4 rounded-box border border-base-200">
+                  <legend className="fieldset-legend font-bold flex items-center gap-2">
+                    <Gauge size={16} /> 检索评测
+                  </legend>
+                  <p className="text-xs text-base-content/60">
+                    用一组常见法律问题检验当前（已保存的）模型能否检索到对应条文，仅在本地运行。
+                  </p>
+                  <div className="flex gap-2 mt-2">
+                    <button
+                      className="btn btn-sm btn-neutral gap-2"
+                      onClick={() => handleRunBenchmark(false)}
+                      disabled={benchmarkProgress !== null}
+                    >
+                      {benchmarkProgress ? (
+                        <Loader2 className="animate-spin" size={14} />
+                      ) : (
+                        <Gauge size={14} />
+                      )}
+                      运行内置评测
+                    </button>
+                    <button
+                      className="btn btn-sm btn-ghost gap-2"
+                      onClick={() => handleRunBenchmark(true)}
+                      disabled={benchmarkProgress !== null}
+                    >
+                      <FileJson size={14} /> 自定义用例
+                    </button>
+                  </div>
+                  {benchmarkProgress && benchmarkProgress.total > 0 && (
+                    <div className="mt-3 space-y-1">
+                      <progress
+                        className="progress progress-primary w-full"
+                        value={benchmarkProgress.processed}
+                        max={benchmarkProgress.total}
+                      />
+                      <div className="text-xs text-base-content/60 truncate">
+                        {benchmarkProgress.processed}/{benchmarkProgress.total}{" "}
+                        {benchmarkProgress.current_query}
+                      </div>
+                    </div>
+                  )}
+                  {benchmarkReport && (
+                    <div className="mt-3 space-y-2">
+                      <div className="stats stats-horizontal bg-base-100 border border-base-200 w-full">
+                        <div className="stat py-2">
+                          <div className="stat-title text-xs">hit@5</div>
+                          <div className="stat-value text-lg">
+                            {(benchmarkReport.hit_at_5 * 100).toFixed(1)}%
+                          </div>
+                        </div>
+                        <div className="stat py-2">
+                          <div className="stat-title text-xs">hit@10</div>
+                          <div className="stat-value text-lg">
+                            {(benchmarkReport.hit_at_10 * 100).toFixed(1)}%
+                          </div>
+                        </div>
+                        <div className="stat py-2">
+                          <div className="stat-title text-xs">MRR</div>
+                          <div className="stat-value text-lg">
+                            {benchmarkReport.mrr.toFixed(3)}
+                          </div>
+                        </div>
+                      </div>
+                      <div className="text-xs text-base-content/60">
+                        {benchmarkReport.embedding_model} · {benchmarkReport.total} 条用例 ·
+                        返回 {benchmarkReport.top_k} 条
+                        {benchmarkReport.top_k < 10 && "（少于 10 条，hit@10 偏低）"}
+                      </div>
+                      <div className="max-h-40 overflow-y-auto text-xs">
+                        {benchmarkReport.cases
+                          .filter((c) => c.rank === null)
+                          .map((c) => (
+                            <div key={c.query} className="truncate text-base-content/60">
+                              未命中：{c.query} → {c.expected}
+                              {c.error && `（${c.error}）`}
+                            </div>
+                          ))}
+                      </div>
+                    </div>
+                  )}
+                </fieldset>
               </div>
             )}
 
@@ -534,10 +715,14 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
                         onClick={async () => {
                           const path = await selectFolder();
                           if (path) {
-                            setConfig({ ...config, custom_data_path: path });
-                            toast.success(
-                              "路径已选择，请确保该目录下包含 content.db 和 law_db.lancedb"
-                            );
+                            try {
+                              await setDataPath(path);
+                              setConfig({ ...config, custom_data_path: path });
+                              getEffectivePaths().then(setEffectivePaths);
+                              toast.success("数据路径已切换");
+                            } catch (e) {
+                              toast.error(formatError(e));
+                            }
                           }
                         }}
                       >
@@ -546,15 +731,33 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
                       {config.custom_data_path && (
                         <button
                           className="btn btn-sm btn-ghost text-error"
-                          onClick={() =>
-                            setConfig({ ...config, custom_data_path: "" })
-                          }
+                          onClick={async () => {
+                            try {
+                              await setDataPath("");
+                              setConfig({ ...config, custom_data_path: null });
+                              getEffectivePaths().then(setEffectivePaths);
+                            } catch (e) {
+                              toast.error(formatError(e));
+                            }
+                          }}
                           title="恢复默认"
                         >
                           <X size={16} />
                         </button>
                       )}
                     </div>
+                    {effectivePaths && (
+                      <p className="text-[10px] font-mono text-base-content/60 break-all">
+                        当前生效：{effectivePaths.data_dir}
+                        {effectivePaths.custom_data_path &&
+                          !effectivePaths.custom_data_path_reachable && (
+                            <span className="text-error font-sans ml-1">
+                              （自定义目录 {effectivePaths.custom_data_path}{" "}
+                              无法访问，已使用默认数据）
+                            </span>
+                          )}
+                      </p>
+                    )}
                     <p className="text-[10px] text-base-content/50 leading-tight">
                       可以将数据库放在 NAS 或共享文件夹中供团队使用。
                       <br />
@@ -566,6 +769,312 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
                     </p>
                   </div>
                 </fieldset>
+
+                <fieldset className="fieldset bg-base-200/30 p-4 rounded-box border border-base-200">
+                  <legend className="fieldset-legend font-bold flex items-center gap-2">
+                    <Database size={14} /> 附加数据源
+                  </legend>
+                  <div className="flex flex-col gap-2 mt-1 w-full">
+                    {dataSources
+                      .filter((source) => !source.primary)
+                      .map((source) => (
+                        <div key={source.name} className="flex items-center gap-2">
+                          <input
+                            type="checkbox"
+                            className="toggle toggle-sm toggle-primary"
+                            checked={source.enabled}
+                            onChange={(e) =>
+                              updateDataSources(() =>
+                                setDataSourceEnabled(source.name, e.target.checked)
+                              )
+                            }
+                          />
+                          <span className="text-sm font-medium">{source.name}</span>
+                          <span
+                            className={`text-[10px] font-mono truncate flex-1 ${
+                              source.reachable ? "text-base-content/50" : "text-error"
+                            }`}
+                            title={source.path}
+                          >
+                            {source.reachable ? source.path : `无法访问：${source.path}`}
+                          </span>
+                          <button
+                            className="btn btn-xs btn-ghost text-error"
+                            onClick={() =>
+                              updateDataSources(() => removeDataSource(source.name))
+                            }
+                            title="移除（不删除文件）"
+                          >
+                            <X size={14} />
+                          </button>
+                        </div>
+                      ))}
+                    <div className="flex gap-2">
+                      <input
+                        type="text"
+                        className="input input-sm input-bordered flex-1 text-xs"
+                        placeholder="名称，如 合同范本库"
+                        value={newSourceName}
+                        onChange={(e) => setNewSourceName(e.target.value)}
+                      />
+                      <button
+                        className="btn btn-sm btn-secondary"
+                        disabled={!newSourceName.trim()}
+                        onClick={async () => {
+                          const path = await selectFolder();
+                          if (!path) return;
+                          await updateDataSources(() =>
+                            addDataSource(newSourceName.trim(), path)
+                          );
+                          setNewSourceName("");
+                        }}
+                      >
+                        <FolderOpen size={16} /> 添加
+                      </button>
+                    </div>
+                    <p className="text-[10px] text-base-content/50 leading-tight">
+                      目录需包含 content.db 与 law_db.lancedb，检索时与默认法规库一同查询，结果按相关度合并。
+                    </p>
+                  </div>
+                </fieldset>
+
+                <fieldset className="fieldset bg-base-200/30 p-4 rounded-box border border-base-200">
+                  <legend className="fieldset-legend font-bold flex items-center gap-2">
+                    <Search size={14} /> 快速查询
+                  </legend>
+                  <SettingInput
+                    label="全局快捷键（留空表示不启用）"
+                    value={config.quick_search_shortcut ?? ""}
+                    placeholder="CommandOrControl+Shift+L"
+                    onChange={(v: string) =>
+                      setConfig({ ...config, quick_search_shortcut: v })
+                    }
+                  />
+                  <p className="text-[10px] text-base-content/50 leading-tight">
+                    在任意程序中按下快捷键或点击托盘图标呼出查询小窗，输入“民法典第五百条”可直接查看条文。
+                  </p>
+                  <label className="label cursor-pointer justify-start gap-3 mt-2">
+                    <input
+                      type="checkbox"
+                      className="toggle toggle-sm toggle-primary"
+                      checked={!!config.enable_clipboard_watch}
+                      onChange={(e) =>
+                        setConfig({
+                          ...config,
+                          enable_clipboard_watch: e.target.checked,
+                        })
+                      }
+                    />
+                    <span className="label-text">识别剪贴板中的法条引用</span>
+                  </label>
+                  <p className="text-[10px] text-base-content/50 leading-tight">
+                    复制“《民法典》第五百条”等引用时自动弹出条文内容。剪贴板内容不会被保存；程序在后台且使用电池供电时暂停监听。
+                  </p>
+                  <label className="label cursor-pointer justify-start gap-3 mt-2">
+                    <input
+                      type="checkbox"
+                      className="toggle toggle-sm toggle-primary"
+                      checked={!!config.enable_review_mode}
+                      onChange={(e) =>
+                        setConfig({
+                          ...config,
+                          enable_review_mode: e.target.checked,
+                        })
+                      }
+                    />
+                    <span className="label-text">新收藏自动加入复习计划</span>
+                  </label>
+                  <label className="label cursor-pointer justify-start gap-3 mt-2">
+                    <input
+                      type="checkbox"
+                      className="toggle toggle-sm toggle-primary"
+                      checked={!!config.enable_query_rewrite_rules}
+                      onChange={(e) =>
+                        setConfig({
+                          ...config,
+                          enable_query_rewrite_rules: e.target.checked,
+                        })
+                      }
+                    />
+                    <span className="label-text">口语化查询改写</span>
+                  </label>
+                  <p className="text-[10px] text-base-content/50 leading-tight">
+                    检索前将“炒鱿鱼”“老板”等口语替换为法律术语，并去掉“请问”等无意义词。
+                  </p>
+                  <label className="label cursor-pointer justify-start gap-3 mt-2">
+                    <input
+                      type="checkbox"
+                      className="toggle toggle-sm toggle-primary"
+                      checked={!!config.low_memory_mode}
+                      onChange={(e) =>
+                        setConfig({
+                          ...config,
+                          low_memory_mode: e.target.checked,
+                        })
+                      }
+                    />
+                    <span className="label-text">低内存模式</span>
+                  </label>
+                  <p className="text-[10px] text-base-content/50 leading-tight">
+                    默认在搜索后预读结果中法规的全文，打开阅读视图更快；内存紧张时可关闭预读。
+                  </p>
+                  {!config.low_memory_mode && (
+                    <label className="label justify-start gap-3 mt-1">
+                      <span className="label-text">全文缓存上限</span>
+                      <input
+                        type="number"
+                        min={0}
+                        className="input input-bordered input-xs w-20"
+                        value={config.full_text_cache_mb ?? 32}
+                        onChange={(e) =>
+                          setConfig({
+                            ...config,
+                            full_text_cache_mb: Math.max(0, Number(e.target.value) || 0),
+                          })
+                        }
+                      />
+                      <span className="label-text">MB</span>
+                    </label>
+                  )}
+                </fieldset>
+
+                <fieldset className="fieldset bg-base-200/30 p-4 rounded-box border border-base-200">
+                  <legend className="fieldset-legend font-bold flex items-center gap-2">
+                    <Server size={14} /> 本地 API 服务
+                  </legend>
+
+                  <div className="flex flex-col gap-3 mt-1 w-full">
+                    <label className="label cursor-pointer justify-start gap-3">
+                      <input
+                        type="checkbox"
+                        className="toggle toggle-sm toggle-primary"
+                        checked={!!config.api_server_enabled}
+                        onChange={(e) =>
+                          setConfig({
+                            ...config,
+                            api_server_enabled: e.target.checked,
+                          })
+                        }
+                      />
+                      <span className="label-text">
+                        启用（仅监听 127.0.0.1）
+                        {apiStatus?.running && (
+                          <span className="text-success ml-2">
+                            运行中 :{apiStatus.port}
+                          </span>
+                        )}
+                      </span>
+                    </label>
+                    <SettingInput
+                      label="端口"
+                      type="number"
+                      value={config.api_server_port ?? 17860}
+                      onChange={(v: string) =>
+                        setConfig({ ...config, api_server_port: Number(v) })
+                      }
+                    />
+                    <div className="form-control">
+                      <label className="label">
+                        <span className="label-text font-medium flex items-center gap-2">
+                          <Key size={14} className="opacity-70" />
+                          访问令牌
+                        </span>
+                      </label>
+                      <div className="flex gap-2">
+                        <input
+                          type="text"
+                          className="input input-sm input-bordered flex-1 font-mono text-xs opacity-80"
+                          value={config.api_server_token || ""}
+                          placeholder="首次启动服务时自动生成"
+                          readOnly
+                        />
+                        <button
+                          className="btn btn-sm btn-ghost"
+                          disabled={!config.api_server_token}
+                          onClick={() => {
+                            navigator.clipboard.writeText(config.api_server_token);
+                            toast.success("已复制访问令牌");
+                          }}
+                          title="复制"
+                        >
+                          <Copy size={16} />
+                        </button>
+                      </div>
+                    </div>
+                    <SettingInput
+                      label="允许跨域的来源（逗号分隔，留空表示不允许）"
+                      value={(config.api_server_cors_origins ?? []).join(", ")}
+                      placeholder="http://localhost:3000"
+                      onChange={(v: string) =>
+                        setConfig({
+                          ...config,
+                          api_server_cors_origins: v
+                            .split(",")
+                            .map((o) => o.trim())
+                            .filter(Boolean),
+                        })
+                      }
+                    />
+                    <p className="text-[10px] text-base-content/50 leading-tight">
+                      提供 GET /search?q=...、/law/法规名/article/条号、/favorites
+                      接口，请求头需带 Authorization: Bearer 访问令牌。
+                    </p>
+                  </div>
+                </fieldset>
+
+                <fieldset className="fieldset bg-base-200/30 p-4 rounded-box border border-base-200">
+                  <legend className="fieldset-legend font-bold flex items-center gap-2">
+                    <Gauge size={14} /> 命令耗时统计
+                  </legend>
+                  <p className="text-[10px] text-base-content/50 leading-tight">
+                    仅保存在本机内存中，反馈问题时可复制附上。
+                  </p>
+                  <div className="flex gap-2 mt-1">
+                    <button className="btn btn-xs" onClick={refreshMetrics}>
+                      刷新
+                    </button>
+                    <button className="btn btn-xs btn-ghost" onClick={copyMetrics} disabled={!metrics}>
+                      <Copy size={12} /> 复制
+                    </button>
+                    <button
+                      className="btn btn-xs btn-ghost"
+                      onClick={async () => {
+                        await resetMetrics();
+                        refreshMetrics();
+                      }}
+                    >
+                      重置
+                    </button>
+                  </div>
+                  {metrics && (
+                    <div className="max-h-60 overflow-y-auto mt-2">
+                      <table className="table table-xs">
+                        <thead>
+                          <tr>
+                            <th>命令</th>
+                            <th>次数</th>
+                            <th>失败</th>
+                            <th>p50</th>
+                            <th>p95</th>
+                          </tr>
+                        </thead>
+                        <tbody>
+                          {metrics.commands.map((m) => (
+                            <tr key={m.command}>
+                              <td className="font-mono">{m.command}</td>
+                              <td>{m.count}</td>
+                              <td className={m.error_count > 0 ? "text-error" : ""}>
+                                {m.error_count}
+                              </td>
+                              <td>{m.p50_ms.toFixed(1)} ms</td>
+                              <td>{m.p95_ms.toFixed(1)} ms</td>
+                            </tr>
+                          ))}
+                        </tbody>
+                      </table>
+                    </div>
+                  )}
+                </fieldset>
               </div>
             )}
 
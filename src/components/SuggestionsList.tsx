@@ -52,6 +52,11 @@ export const SuggestionsList: React.FC<SuggestionsListProps> = ({
               <div className="flex items-center gap-3 min-w-0">
                 <FileText size={16} className="text-base-content/50 shrink-0" />
                 <span className="truncate font-medium">{suggestion.name}</span>
+                {suggestion.name_en && (
+                  <span className="truncate text-xs text-base-content/50">
+                    {suggestion.name_en}
+                  </span>
+                )}
               </div>
 
               <div className="flex items-center gap-2 shrink-0">
@@ -2,6 +2,8 @@
 
 import React, { useState, useRef, useEffect } from "react";
 import { Tab } from "../types";
+import { LawChunk } from "../services/api";
+import { WorkspaceButton } from "./WorkspaceButton";
 import { 
   X, Search, FileText, Plus, PenTool, Settings, Star, 
   ChevronDown} from "lucide-react";
@@ -17,6 +19,7 @@ interface TabBarProps {
   onOpenDrafting: () => void;
   onOpenSettings: () => void;
   onOpenFavorites: () => void;
+  onOpenLaw: (law: LawChunk) => void;
 }
 
 export const TabBar: React.FC<TabBarProps> = ({
@@ -29,6 +32,7 @@ export const TabBar: React.FC<TabBarProps> = ({
   onOpenDrafting,
   onOpenSettings,
   onOpenFavorites,
+  onOpenLaw,
 }) => {
   const [showTabList, setShowTabList] = useState(false);
   const listRef = useRef<HTMLDivElement>(null);
@@ -166,6 +170,8 @@ export const TabBar: React.FC<TabBarProps> = ({
             </button>
         </div>
 
+        <WorkspaceButton onOpen={onOpenLaw} />
+
         <div className="tooltip tooltip-bottom" data-tip="设置">
             <button onClick={onOpenSettings} className="btn btn-ghost btn-xs btn-square text-base-content/70">
                 <Settings size={14} />
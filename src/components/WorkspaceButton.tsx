@@ -0,0 +1,130 @@
+// frontend/src/components/WorkspaceButton.tsx
+// 工作区：跨多次检索暂存的条文，按钮上显示条数，展开后可移除或清空
+
+import React, { useEffect, useRef, useState } from "react";
+import { Layers, X } from "lucide-react";
+import { listen } from "@tauri-apps/api/event";
+import { toast } from "react-hot-toast";
+import {
+  LawChunk,
+  WorkspaceChangedEvent,
+  workspaceList,
+  workspaceRemove,
+  workspaceClear,
+  formatError,
+} from "../services/api";
+
+interface WorkspaceButtonProps {
+  onOpen: (law: LawChunk) => void;
+}
+
+export const WorkspaceButton: React.FC<WorkspaceButtonProps> = ({ onOpen }) => {
+  const [count, setCount] = useState(0);
+  const [items, setItems] = useState<LawChunk[] | null>(null);
+  const [open, setOpen] = useState(false);
+  const ref = useRef<HTMLDivElement>(null);
+
+  // 其他窗口或结果卡片改动工作区时同步计数
+  useEffect(() => {
+    workspaceList()
+      .then((list) => setCount(list.length))
+      .catch((err) => console.error("Failed to load workspace:", err));
+    const unlisten = listen<WorkspaceChangedEvent>("workspace-changed", (e) => {
+      setCount(e.payload.count);
+      setItems(null);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (open && items === null) {
+      workspaceList()
+        .then(setItems)
+        .catch((err) => toast.error(formatError(err)));
+    }
+  }, [open, items]);
+
+  useEffect(() => {
+    const handleClickOutside = (event: MouseEvent) => {
+      if (ref.current && !ref.current.contains(event.target as Node)) {
+        setOpen(false);
+      }
+    };
+    document.addEventListener("mousedown", handleClickOutside);
+    return () => document.removeEventListener("mousedown", handleClickOutside);
+  }, []);
+
+  return (
+    <div className="relative" ref={ref}>
+      <div className="tooltip tooltip-bottom" data-tip="工作区">
+        <button
+          onClick={() => setOpen((v) => !v)}
+          className="btn btn-ghost btn-xs btn-square text-base-content/70 relative"
+        >
+          <Layers size={14} />
+          {count > 0 && (
+            <span className="badge badge-primary badge-xs absolute -top-1 -right-1 px-1">
+              {count}
+            </span>
+          )}
+        </button>
+      </div>
+
+      {open && (
+        <div className="absolute right-0 top-full mt-2 w-72 max-h-96 overflow-y-auto bg-base-100 shadow-xl rounded-box border border-base-200 z-50 p-2">
+          <div className="flex items-center justify-between px-2 py-1 text-xs text-base-content/50">
+            <span>工作区（{count}）</span>
+            {count > 0 && (
+              <button
+                className="btn btn-ghost btn-xs text-error"
+                onClick={() =>
+                  workspaceClear().catch((err) => toast.error(formatError(err)))
+                }
+              >
+                清空
+              </button>
+            )}
+          </div>
+          {items === null ? (
+            <div className="px-2 py-3 text-xs text-base-content/40">加载中...</div>
+          ) : items.length === 0 ? (
+            <div className="px-2 py-3 text-xs text-base-content/40">
+              在搜索结果中点击“工作区”收集条文
+            </div>
+          ) : (
+            items.map((item) => (
+              <div
+                key={item.id}
+                className="flex items-center gap-1 px-2 py-1 rounded hover:bg-base-200 text-xs"
+              >
+                <button
+                  className="grow text-left truncate"
+                  title={item.content}
+                  onClick={() => {
+                    setOpen(false);
+                    onOpen(item);
+                  }}
+                >
+                  《{item.law_name}》{item.article_number}
+                </button>
+                <button
+                  className="btn btn-ghost btn-xs btn-square shrink-0"
+                  onClick={() =>
+                    workspaceRemove(item.id).catch((err) =>
+                      toast.error(formatError(err))
+                    )
+                  }
+                  title="移出工作区"
+                >
+                  <X size={12} />
+                </button>
+              </div>
+            ))
+          )}
+        </div>
+      )}
+    </div>
+  );
+};
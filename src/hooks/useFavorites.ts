@@ -1,6 +1,6 @@
 import { useState, useEffect, useCallback } from "react";
 import { 
-  UserFavorite, UserFolder, LawChunk,
+  UserFavorite, UserFolder, LawChunk, DeleteFolderMode,
   getFavorites, getFolders, addFavorite, removeFavorite, createFolder, deleteFolder, moveFavorite
 } from "../services/api";
 import { toast } from "react-hot-toast";
@@ -37,8 +37,12 @@ export function useFavorites() {
     await refresh();
   };
 
-  const removeFolder = async (id: number) => {
-    await deleteFolder(id);
+  const removeFolder = async (
+    id: number,
+    mode: DeleteFolderMode = "move_to_uncategorized",
+    targetFolderId: number | null = null
+  ) => {
+    await deleteFolder(id, mode, targetFolderId);
     await refresh();
   };
 
@@ -48,13 +52,19 @@ export function useFavorites() {
       await addFavorite(item, folderId); // 默认加入未分类或指定文件夹
       toast.success("已收藏");
       await refresh();
+      return true;
     } catch (e) {
       toast.error("收藏失败");
+      return false;
     }
   };
 
-  const move = async (lawId: string, folderId: number | null) => {
-    await moveFavorite(lawId, folderId);
+  const move = async (
+    lawId: string,
+    folderId: number | null,
+    fromFolderId: number | null = null
+  ) => {
+    await moveFavorite(lawId, folderId, fromFolderId);
     await refresh();
   };
 
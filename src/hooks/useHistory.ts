@@ -1,12 +1,17 @@
 import { useState, useEffect, useCallback } from "react";
-import { getHistory, addHistory, clearHistory } from "../services/api";
+import {
+  getHistoryItems,
+  addHistory,
+  clearHistory,
+  SearchHistoryItem,
+} from "../services/api";
 
 export function useHistory() {
-  const [history, setHistory] = useState<string[]>([]);
+  const [history, setHistory] = useState<SearchHistoryItem[]>([]);
 
   const refresh = useCallback(async () => {
     try {
-      const data = await getHistory();
+      const data = await getHistoryItems();
       setHistory(data);
     } catch (e) {
       console.error(e);
@@ -25,7 +30,7 @@ export function useHistory() {
 
   const clear = async () => {
     await clearHistory();
-    setHistory([]);
+    setHistory((items) => items.filter((item) => item.pinned));
   };
 
   return { history, add, clear, refresh };
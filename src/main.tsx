@@ -1,9 +1,13 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import { QuickSearch } from "./components/QuickSearch";
+
+// 快速查询小窗与主窗口共用同一入口，按地址区分
+const isQuickSearch = window.location.hash.startsWith("#/quick-search");
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    {isQuickSearch ? <QuickSearch /> : <App />}
   </React.StrictMode>,
 );
@@ -15,6 +15,10 @@ export interface LawChunk {
   source_file: string;
   _distance: number;
   region: string;
+  // 开启列表预览时内容被截断，完整内容用 getChunkContent 获取
+  content_truncated?: boolean;
+  // 所属的附加数据源（如合同范本库），默认法规库为空；全文、预览等需一并传回
+  source_name?: string | null;
 }
 
 export interface SearchResponse {
@@ -30,6 +34,56 @@ export interface LawNameSuggestion {
   name: string;
   region: string;
   category: string;
+  // 英文名，库中没有时为用户填写的译名
+  name_en?: string | null;
+  source_name?: string | null;
+}
+
+// 查询改写规则；to 为空表示检索时去掉该词。内置规则 id 为 null，添加同名规则即可覆盖
+export interface QueryRewriteRule {
+  id: number | null;
+  from: string;
+  to: string;
+  builtin: boolean;
+}
+
+export async function addQueryRewriteRule(
+  from: string,
+  to: string
+): Promise<QueryRewriteRule> {
+  return await invoke<QueryRewriteRule>("add_query_rewrite_rule", { from, to });
+}
+
+export async function listQueryRewriteRules(): Promise<QueryRewriteRule[]> {
+  return await invoke<QueryRewriteRule[]>("list_query_rewrite_rules");
+}
+
+export async function deleteQueryRewriteRule(id: number): Promise<void> {
+  return await invoke("delete_query_rewrite_rule", { id });
+}
+
+// 自动识别查询类型后的检索结果：引用返回条文，法规名返回法规，其余为语义检索结果
+export type ClassifiedSearch =
+  | { kind: "citation"; results: LawChunk[] }
+  | { kind: "law_name"; results: LawNameSuggestion[] }
+  | { kind: "semantic"; results: LawChunk[]; degraded_reason: string | null };
+
+export async function classifyAndSearch(
+  query: string,
+  contentMode?: ContentMode
+): Promise<ClassifiedSearch> {
+  return await invoke<ClassifiedSearch>("classify_and_search", {
+    query,
+    contentMode: contentMode ?? null,
+  });
+}
+
+// 保存法规英文译名，english 为空时删除
+export async function setLawNameTranslation(
+  lawName: string,
+  english: string
+): Promise<void> {
+  return await invoke("set_law_name_translation", { lawName, english });
 }
 
 export interface LawNameSearchResponse {
@@ -44,14 +98,29 @@ export interface UserFavorite {
   article_number: string;
   content: string;
   created_at: string;
-  tags?: string;
+  tags: string[];
+  // 所在的第一个文件夹，未分类为 null
   folder_id?: number | null;
+  // 一条收藏可以同时在多个文件夹中，为空表示未分类
+  folder_ids: number[];
+  // 本地法规库中没有该条文，内容来自导出文件
+  from_export: boolean;
+  // 用户笔记 (Markdown)
+  note?: string | null;
+  // 最近一次时效检查发现条文已修改或已删除
+  stale: boolean;
+  // 刷新内容前收藏时的原文
+  original_content?: string | null;
+  // 按 preview 返回且内容被截断时为 true，完整内容用 getFavoriteContent 获取
+  content_truncated?: boolean;
 }
 
 export interface UserFolder {
   id: number;
   name: string;
   created_at: string;
+  // 上级文件夹，顶层为 null
+  parent_id: number | null;
 }
 
 // Agent: 更新事件
@@ -66,10 +135,58 @@ export interface AgentUpdateEvent {
   thought?: string;
 }
 
+// Chat: 结构化上下文条文
+export interface ChatContextChunk {
+  id: string;
+  law_name: string;
+  article_number: string;
+  content: string;
+  distance?: number | null;
+}
+
+// Chat: 严格依据模式下检索结果均不相关 ({eventId}-ungrounded)
+export interface UngroundedEvent {
+  message: string;
+  suggestions: string[];
+}
+
+// Chat: 回答中 [n] 编号引用 ({eventId}-citations)
+export interface ChatReference {
+  number: number;
+  chunk_id?: string | null;
+  law_name?: string | null;
+  article_number?: string | null;
+  valid: boolean;
+}
+
+// Chat: 回答正文中的《XX法》第X条 (start/end 为字符串下标)
+export interface ChatCitation {
+  text: string;
+  start: number;
+  end: number;
+  law_name: string;
+  article_number: string;
+  exists: boolean;
+}
+
+export interface ChatCitationsEvent {
+  references: ChatReference[];
+  citations: ChatCitation[];
+}
+
+// Chat: 流结束事件 ({eventId}-done)
+export interface ChatDoneEvent {
+  finish_reason?: string | null;
+  total_chars: number;
+  usage?: Record<string, unknown> | null;
+  included_chunks: number;
+  dropped_chunks: number;
+}
+
 export interface AppSettings {
   search_top_k: number;
   display_density: "comfortable" | "compact";
-  custom_data_path?: string;
+  custom_data_path?: string | null;
 
   embedding_base_url: string;
   embedding_api_key: string;
@@ -82,6 +199,90 @@ export interface AppSettings {
   chat_top_k: number;
 
   max_agent_loops: number;
+
+  chat_temperature?: number | null;
+  chat_max_tokens?: number | null;
+  chat_top_p?: number | null;
+
+  custom_deep_prompt?: string | null;
+  custom_simple_prompt?: string | null;
+
+  chat_streaming_enabled: boolean;
+  chat_api_format: "openai" | "anthropic" | "ollama";
+  embedding_api_format: "openai" | "ollama";
+  embedding_provider: "api" | "local";
+  chat_context_char_budget: number;
+  chat_context_cache_size: number;
+  strict_grounding: boolean;
+
+  http_proxy?: string | null;
+  no_proxy_hosts?: string | null;
+  // 额外信任的 CA 证书 (PEM)；accept_invalid_certs 关闭证书校验，仅供排查
+  extra_ca_cert_path?: string | null;
+  accept_invalid_certs?: boolean;
+
+  embedding_timeout_secs: number;
+  chat_timeout_secs: number;
+
+  log_level: "error" | "warn" | "info" | "debug" | "trace";
+  log_query_text: boolean;
+  plaintext_api_keys: boolean;
+  active_profile?: string | null;
+  settings_version: number;
+  content_update_url?: string | null;
+  // 自定义引用模板，占位符 {law_name} {short_name} {article} {content}
+  citation_templates?: Partial<Record<CitationStyle, string>>;
+  history_limit: number;
+  // 缓存最近 30 次检索结果，重复搜索时不再请求嵌入服务
+  enable_search_cache: boolean;
+  // 本地使用统计，数据不会上传
+  enable_usage_stats: boolean;
+  // 启动时发送一次嵌入请求预热模型
+  warmup_on_start: boolean;
+  // 搜索结果内容预览字数，0 表示返回完整内容
+  search_preview_chars: number;
+  // 本地 HTTP API（仅 127.0.0.1），请求需带 Authorization: Bearer <token>
+  api_server_enabled: boolean;
+  api_server_port: number;
+  api_server_token: string;
+  // 允许跨域访问的来源，为空时不开放 CORS
+  api_server_cors_origins: string[];
+  // 呼出快速查询窗口的全局快捷键，为空时不注册
+  quick_search_shortcut: string;
+  // 监听剪贴板中的法条引用并提示条文
+  enable_clipboard_watch: boolean;
+  // 新收藏自动加入复习计划
+  enable_review_mode: boolean;
+  // 工作区最多收集的条文数
+  workspace_limit: number;
+  // 检索前把口语说法替换为法律用语
+  enable_query_rewrite_rules: boolean;
+  // 附加数据源，通过 addDataSource 等命令维护
+  data_sources?: DataSource[];
+  // 第三方数据集的列名映射：标准列名 -> 实际列名，如 { chunks: { law_name: "title" } }
+  content_schema_map?: ContentSchemaMap;
+  // 预读法规全文的内存缓存上限（MB），0 表示不缓存
+  full_text_cache_mb?: number;
+  // 低内存模式：不预读、不缓存全文
+  low_memory_mode?: boolean;
+}
+
+export interface ContentSchemaMap {
+  chunks?: Record<string, string>;
+  full_texts?: Record<string, string>;
+}
+
+export interface DataSource {
+  name: string;
+  path: string;
+  enabled: boolean;
+}
+
+// Chat: 单次调用的采样参数覆盖
+export interface ChatOptions {
+  temperature?: number;
+  max_tokens?: number;
+  top_p?: number;
 }
 
 export interface DraftMaterial {
@@ -99,24 +300,134 @@ export interface CustomTemplate {
   content: string;
 }
 
+// 后端命令返回的结构化错误
+export interface AppError {
+  code:
+    | "NETWORK"
+    | "EMBEDDING_API"
+    | "CHAT_API"
+    | "VECTOR_DB"
+    | "CONTENT_DB"
+    | "USER_DB"
+    | "VALIDATION"
+    | "NOT_FOUND";
+  message: string;
+  detail: string | null;
+}
+
+export function isAppError(err: unknown): err is AppError {
+  return typeof err === "object" && err !== null && "code" in err && "message" in err;
+}
+
+// 将命令错误转换为可展示的文本，兼容仍返回字符串的命令
+export function formatError(err: unknown): string {
+  if (isAppError(err)) {
+    return err.detail ? `${err.message}: ${err.detail}` : err.message;
+  }
+  return String(err);
+}
+
 // --- 核心搜索 ---
 
+// 每次搜索递增的请求编号；后端据此判断结果是否已被更新的搜索取代
+let searchRequestSeq = Date.now();
+
+// 检索结果的内容形式：full 为完整内容；preview 截到句末并设置 content_truncated，
+// 展开时调用 getChunkContent。不传时按设置中的 search_preview_chars
+export type ContentMode = "full" | "preview";
+
+// rewrittenQuery：开启查询改写时实际用于检索的文本，未改写时为 null
+// degradedReason：嵌入服务不可用、结果来自关键词匹配时的原因，正常检索为 null
+// cacheHit：结果来自检索缓存，未请求嵌入服务
+// stale：已被更新的搜索取代，results 为空，调用方应忽略
 export async function searchLaw(
   query: string,
-  filterRegion?: string
-): Promise<{ results: LawChunk[] }> {
+  filterRegion?: string,
+  contentMode?: ContentMode
+): Promise<{
+  results: LawChunk[];
+  stale: boolean;
+  rewrittenQuery: string | null;
+  degradedReason: string | null;
+  cacheHit: boolean;
+}> {
+  const requestId = ++searchRequestSeq;
   try {
-    const results = await invoke<LawChunk[]>("search_law", {
+    const response = await invoke<{
+      results: LawChunk[];
+      rewritten_query: string | null;
+      degraded: boolean;
+      degraded_reason: string | null;
+      cache_hit: boolean;
+      stale: boolean;
+    }>("search_law", {
       query,
       filterRegion: filterRegion || null,
+      requestId,
+      contentMode: contentMode ?? null,
     });
-    return { results };
+    return {
+      results: response.results,
+      stale: response.stale,
+      rewrittenQuery: response.rewritten_query,
+      degradedReason: response.degraded ? response.degraded_reason ?? "" : null,
+      cacheHit: response.cache_hit,
+    };
   } catch (error) {
     console.error("Search failed:", error);
     throw error;
   }
 }
 
+// 全国性规定与地方规定对照；pairs 按名次配对两栏结果，comparison 仅在启用 AI 对话时生成
+export interface ComparisonPoint {
+  aspect: string;
+  national: string;
+  local: string;
+  national_ids: string[];
+  local_ids: string[];
+  divergent: boolean;
+}
+
+export interface NationalLocalComparison {
+  query: string;
+  region: string;
+  national: LawChunk[];
+  local: LawChunk[];
+  pairs: { national_id: string | null; local_id: string | null }[];
+  comparison: { summary: string; points: ComparisonPoint[] } | null;
+  degraded_reason: string | null;
+}
+
+export async function searchNationalVsLocal(
+  query: string,
+  region: string,
+  contentMode?: ContentMode
+): Promise<NationalLocalComparison> {
+  return await invoke<NationalLocalComparison>("search_national_vs_local", {
+    query,
+    region,
+    contentMode: contentMode ?? null,
+  });
+}
+
+export async function getChunkContent(
+  chunkId: string,
+  sourceName?: string | null
+): Promise<string> {
+  return await invoke<string>("get_chunk_content", {
+    chunkId,
+    sourceName: sourceName ?? null,
+  });
+}
+
+// 结果只带预览时补全内容，用于复制、导出和对话上下文
+export async function resolveChunkContent(chunk: LawChunk): Promise<string> {
+  return chunk.content_truncated
+    ? await getChunkContent(chunk.id, chunk.source_name)
+    : chunk.content;
+}
+
 export async function searchLawByName(
   query: string,
   limit: number = 10
@@ -135,9 +446,17 @@ export async function searchLawByName(
 
 // --- AI 与 Agent ---
 
-export async function startAgentSearch(query: string, eventId: string): Promise<LawChunk[]> {
+export async function startAgentSearch(
+  query: string,
+  eventId: string,
+  contentMode?: ContentMode
+): Promise<LawChunk[]> {
   try {
-    return await invoke<LawChunk[]>("start_agent_search", { query, eventId });
+    return await invoke<LawChunk[]>("start_agent_search", {
+      query,
+      eventId,
+      contentMode: contentMode ?? null,
+    });
   } catch (error) {
     console.error("Agent search failed:", error);
     throw error;
@@ -148,38 +467,105 @@ export async function stopTask(eventId: string): Promise<void> {
   return await invoke("stop_task", { eventId });
 }
 
-export async function startChatStream(
-  query: string,
-  contextChunks: string[],
-  mode: "simple" | "deep" | "draft",
+// 订阅一次对话的全部事件，返回统一的取消订阅函数
+async function listenChatEvents(
+  eventId: string,
   onToken: (token: string) => void,
-  externalEventId?: string
+  onReasoning?: (text: string) => void,
+  onCitations?: (event: ChatCitationsEvent) => void
 ) {
-  const eventId = externalEventId || `chat-${Date.now()}-${Math.random().toString(36).substring(7)}`;
-
-  const unlisten = await listen<string>(eventId, (event) => {
+  const unlistenToken = await listen<string>(eventId, (event) => {
     onToken(event.payload);
   });
+  const unlistenReasoning = await listen<string>(`${eventId}-reasoning`, (event) => {
+    onReasoning?.(event.payload);
+  });
+  const unlistenCitations = await listen<ChatCitationsEvent>(`${eventId}-citations`, (event) => {
+    onCitations?.(event.payload);
+  });
+  const unlistenDone = await listen<ChatDoneEvent>(`${eventId}-done`, () => {
+    onToken("[DONE]");
+  });
+  const unlistenError = await listen<AppError | string>(`${eventId}-error`, (event) => {
+    onToken(`[Error: ${formatError(event.payload)}]`);
+  });
+
+  return () => {
+    unlistenToken();
+    unlistenReasoning();
+    unlistenCitations();
+    unlistenDone();
+    unlistenError();
+  };
+}
+
+function newChatEventId() {
+  return `chat-${Date.now()}-${Math.random().toString(36).substring(7)}`;
+}
+
+export async function startChatStream(
+  query: string,
+  contextChunks: ChatContextChunk[],
+  mode: "simple" | "deep" | "draft" | "custom",
+  onToken: (token: string) => void,
+  externalEventId?: string,
+  onReasoning?: (text: string) => void,
+  options?: ChatOptions,
+  onCitations?: (event: ChatCitationsEvent) => void
+) {
+  const eventId = externalEventId || newChatEventId();
+  const unlisten = await listenChatEvents(eventId, onToken, onReasoning, onCitations);
 
-  invoke("chat_stream", { query, contextChunks, mode, eventId }).catch(
+  invoke("chat_stream", {
+    query,
+    contextChunks,
+    mode,
+    eventId,
+    options: options || null,
+  }).catch(
     (err) => {
-      onToken(`[Error: ${err}]`);
+      onToken(`[Error: ${formatError(err)}]`);
     }
   );
 
   return unlisten;
 }
 
+// 复用上一次对话的上下文重新生成（无需重新检索）
+export async function regenerateChat(
+  eventId: string,
+  onToken: (token: string) => void,
+  styleHint?: string,
+  newEventId?: string,
+  onReasoning?: (text: string) => void,
+  onCitations?: (event: ChatCitationsEvent) => void
+) {
+  const targetEventId = newEventId || newChatEventId();
+  const unlisten = await listenChatEvents(targetEventId, onToken, onReasoning, onCitations);
+
+  invoke("regenerate_chat", {
+    eventId,
+    newEventId: targetEventId,
+    styleHint: styleHint || null,
+  }).catch((err) => {
+    onToken(`[Error: ${formatError(err)}]`);
+  });
+
+  return unlisten;
+}
+
 export async function checkAiConnection(
   baseUrl: string,
   apiKey: string,
-  model: string
+  model: string,
+  apiFormat?: "openai" | "anthropic" | "ollama"
 ): Promise<string> {
   try {
     const message = await invoke<string>("check_ai_connection", {
       baseUrl,
       apiKey,
       model,
+      apiFormat: apiFormat || null,
     });
     return message;
   } catch (error) {
@@ -187,142 +573,1069 @@ export async function checkAiConnection(
   }
 }
 
-// --- User Data (收藏与历史) ---
+// 切换数据目录（校验 content.db 与 law_db.lancedb），传空字符串恢复默认目录
+// 返回切换后实际生效的目录
+export async function setDataPath(path: string): Promise<string> {
+  return await invoke<string>("set_data_path", { path });
+}
 
-export async function getFavorites(): Promise<UserFavorite[]> {
-  return await invoke("get_favorites");
+// 数据源：primary 为默认数据目录（不能停用或删除），其余为附加数据源
+export interface DataSourceInfo {
+  name: string;
+  path: string;
+  enabled: boolean;
+  primary: boolean;
+  reachable: boolean;
 }
 
-export async function createFolder(name: string): Promise<void> {
-  return await invoke("create_folder", { name });
+export async function listDataSources(): Promise<DataSourceInfo[]> {
+  return await invoke<DataSourceInfo[]>("list_data_sources");
 }
 
-export async function getFolders(): Promise<UserFolder[]> {
-  return await invoke("get_folders");
+// 目录需包含 content.db 与 law_db.lancedb
+export async function addDataSource(
+  name: string,
+  path: string
+): Promise<DataSourceInfo[]> {
+  return await invoke<DataSourceInfo[]>("add_data_source", { name, path });
 }
 
-export async function deleteFolder(folderId: number): Promise<void> {
-  return await invoke("delete_folder", { folderId });
+export async function setDataSourceEnabled(
+  name: string,
+  enabled: boolean
+): Promise<DataSourceInfo[]> {
+  return await invoke<DataSourceInfo[]>("set_data_source_enabled", {
+    name,
+    enabled,
+  });
 }
 
-export async function addFavorite(
-  chunk: LawChunk,
-  folderId?: number | null
-): Promise<void> {
-  return await invoke("add_favorite", { chunk, folderId: folderId || null });
+export async function removeDataSource(name: string): Promise<DataSourceInfo[]> {
+  return await invoke<DataSourceInfo[]>("remove_data_source", { name });
 }
 
-export async function moveFavorite(
-  lawId: string,
-  folderId: number | null
-): Promise<void> {
-  return await invoke("move_favorite", { lawId, folderId });
+// 实际使用的路径；custom_data_path 为按 exe 目录（便携）或配置目录（标准）解析后的自定义目录
+export interface EffectivePaths {
+  data_dir: string;
+  is_default: boolean;
+  custom_data_path: string | null;
+  custom_data_path_reachable: boolean;
+  settings_path: string;
+  user_db_path: string;
+  log_dir: string;
 }
 
-export async function removeFavorite(lawId: string): Promise<void> {
-  return await invoke("remove_favorite", { lawId });
+export async function getEffectivePaths(): Promise<EffectivePaths> {
+  return await invoke<EffectivePaths>("get_effective_paths");
 }
 
-export async function checkIsFavorite(lawId: string): Promise<boolean> {
-  return await invoke("check_is_favorite", { lawId });
+// 嵌入服务连接失败（embedding-unavailable 事件），检索暂时改用关键词匹配
+export interface EmbeddingUnavailableEvent {
+  reason: string;
 }
 
-export async function addHistory(query: string): Promise<void> {
-  return await invoke("add_history", { query });
+export interface EmbeddingProbeResult {
+  dimension: number;
+  latency_ms: number;
 }
 
-export async function getHistory(): Promise<string[]> {
-  return await invoke("get_history");
+// 重新测试嵌入服务，连通后恢复语义检索
+export async function probeEmbeddingService(): Promise<EmbeddingProbeResult> {
+  return await invoke<EmbeddingProbeResult>("probe_embedding_service");
 }
 
-export async function clearHistory(): Promise<void> {
-  return await invoke("clear_history");
+// 自定义数据目录无法访问（data-dir-unreachable 事件），检索暂时使用默认数据
+export interface DataDirUnreachableEvent {
+  configured_path: string;
+  fallback_dir: string;
 }
 
-// --- 系统与配置 ---
+// 安装模式：portable（数据在程序目录旁）或 standard（系统 AppData）
+// pending_mode 与 mode 不同时需重启生效
+export interface InstallModeInfo {
+  mode: "portable" | "standard";
+  pending_mode: "portable" | "standard";
+  settings_path: string;
+  user_db_path: string;
+}
 
-export async function getFullText(
-  source_file: string
-): Promise<FullTextResponse> {
+export async function getInstallMode(): Promise<InstallModeInfo> {
+  return await invoke<InstallModeInfo>("get_install_mode");
+}
+
+// 复制设置与用户库到目标模式的位置；目标已有数据时需传 overwrite
+export async function convertInstallMode(
+  target: "portable" | "standard",
+  overwrite = false
+): Promise<InstallModeInfo> {
+  return await invoke<InstallModeInfo>("convert_install_mode", {
+    target,
+    overwrite,
+  });
+}
+
+// 法规数据更新
+export interface ContentUpdateInfo {
+  current_version: string | null;
+  latest_version: string;
+  size: number;
+  published_at: string | null;
+  notes: string | null;
+  has_update: boolean;
+}
+
+// content-update-progress 事件
+export interface ContentUpdateProgress {
+  stage: "download" | "verify" | "extract" | "install" | "done";
+  downloaded: number;
+  total: number;
+}
+
+export async function checkContentUpdates(): Promise<ContentUpdateInfo> {
+  return await invoke<ContentUpdateInfo>("check_content_updates");
+}
+
+// 下载、校验并替换 content.db 与 law_db.lancedb，返回新的数据版本
+export async function downloadContentUpdate(): Promise<string> {
+  return await invoke<string>("download_content_update");
+}
+
+// 导入自有法规文本 (.txt / .md)
+export interface LawImportMeta {
+  law_name?: string | null;
+  category: string;
+  region: string;
+  publish_date: string;
+}
+
+export interface ImportedLaw {
+  law_name: string;
+  category: string;
+  region: string;
+  publish_date: string;
+  article_count: number;
+  imported_at: string;
+}
+
+// law-import-progress 事件
+export interface LawImportProgress {
+  law_name: string;
+  stage: "parsed" | "embedding" | "saving" | "done";
+  total: number;
+  embedded: number;
+}
+
+export async function importLawDocument(
+  path: string,
+  metadata: LawImportMeta
+): Promise<ImportedLaw> {
+  return await invoke<ImportedLaw>("import_law_document", { path, metadata });
+}
+
+export async function listImportedLaws(): Promise<ImportedLaw[]> {
+  return await invoke<ImportedLaw[]>("list_imported_laws");
+}
+
+export async function deleteImportedLaw(lawName: string): Promise<void> {
+  return await invoke("delete_imported_law", { lawName });
+}
+
+// 向量库与 content.db 一致性检查；列表最多 1000 条，*_count 为实际总数
+export interface IntegrityReport {
+  vector_count: number;
+  chunk_count: number;
+  orphan_vector_count: number;
+  orphan_vectors: string[];
+  missing_vector_count: number;
+  chunks_missing_vectors: string[];
+  laws_without_chunks: string[];
+  repaired_vectors: number;
+}
+
+// repair 为 true 时删除孤立向量
+export async function verifyDataIntegrity(repair = false): Promise<IntegrityReport> {
+  return await invoke<IntegrityReport>("verify_data_integrity", { repair });
+}
+
+// vector-rebuild-progress 事件
+export interface VectorRebuildProgress {
+  processed: number;
+  total: number;
+  percent: number;
+  eta_secs: number | null;
+  current_law: string;
+}
+
+// 用当前嵌入模型重建向量库，返回写入的条文数；中断后再次调用会从断点继续
+export async function rebuildVectorIndex(): Promise<number> {
+  return await invoke<number>("rebuild_vector_index");
+}
+
+export async function cancelVectorRebuild(): Promise<void> {
+  return await invoke("cancel_vector_rebuild");
+}
+
+// 按分类/地区/名称分页浏览法规
+export interface LawBrowseItem {
+  law_name: string;
+  category: string;
+  region: string;
+  publish_date: string | null;
+  article_count: number;
+  is_favorite: boolean;
+}
+
+export interface LawBrowsePage {
+  total: number;
+  items: LawBrowseItem[];
+}
+
+export type LawBrowseSort = "name" | "publish_date" | "article_count";
+
+export async function browseLaws(params: {
+  category?: string | null;
+  region?: string | null;
+  nameFilter?: string | null;
+  sortBy?: LawBrowseSort;
+  offset: number;
+  limit: number;
+}): Promise<LawBrowsePage> {
+  return await invoke<LawBrowsePage>("browse_laws", {
+    category: params.category ?? null,
+    region: params.region ?? null,
+    nameFilter: params.nameFilter ?? null,
+    sortBy: params.sortBy ?? "name",
+    offset: params.offset,
+    limit: params.limit,
+  });
+}
+
+// 最近 months 个月发布或修订的法规，新的在前；months <= 0 时不限时间
+export async function getRecentLaws(
+  months: number = 12,
+  limit: number = 20
+): Promise<LawBrowseItem[]> {
+  return await invoke<LawBrowseItem[]>("get_recent_laws", { months, limit });
+}
+
+export async function getLawsByYear(year: number): Promise<LawBrowseItem[]> {
+  return await invoke<LawBrowseItem[]>("get_laws_by_year", { year });
+}
+
+// 法规库概览统计
+export interface StatGroup {
+  name: string;
+  law_count: number;
+  chunk_count: number;
+}
+
+export interface DbStats {
+  law_count: number;
+  chunk_count: number;
+  full_text_count: number;
+  earliest_publish_date: string | null;
+  latest_publish_date: string | null;
+  by_category: StatGroup[];
+  by_region: StatGroup[];
+  content_db_bytes: number;
+  vector_db_bytes: number;
+  favorite_count: number;
+  history_count: number;
+}
+
+// 统计结果会缓存，refresh 为 true 时重新统计
+export async function getDbStats(refresh = false): Promise<DbStats> {
+  return await invoke<DbStats>("get_db_stats", { refresh });
+}
+
+// 首次运行诊断：每项检查带状态、说明与对应的设置字段
+export interface DiagnosticCheck {
+  code: string;
+  status: "ok" | "warn" | "fail";
+  message: string;
+  field: keyof AppSettings | null;
+}
+
+export interface SetupDiagnostics {
+  install_mode: "portable" | "standard";
+  data_dir: string;
+  checks: DiagnosticCheck[];
+}
+
+export async function runSetupDiagnostics(): Promise<SetupDiagnostics> {
+  return await invoke<SetupDiagnostics>("run_setup_diagnostics");
+}
+
+// 测试代理连通性；不传 httpProxy 时使用已保存的设置
+export async function testProxy(httpProxy?: string, url?: string): Promise<string> {
   try {
-    const content = await invoke<string>("get_full_text", {
-      sourceFile: source_file,
+    return await invoke<string>("test_proxy", {
+      httpProxy: httpProxy ?? null,
+      url: url || null,
     });
-    return { source_file, content };
   } catch (error) {
-    console.error("Get full text failed:", error);
-    throw error;
+    throw new Error(String(error));
   }
 }
 
-export async function getSettings(): Promise<AppSettings> {
-  return await invoke<AppSettings>("get_settings");
+// --- 本地嵌入模型 ---
+
+export interface ModelDownloadProgress {
+  file: string;
+  file_index: number;
+  file_count: number;
+  downloaded: number;
+  total?: number | null;
 }
 
-export async function saveSettings(settings: any) {
-  return await invoke("save_settings", { newSettings: settings });
+export interface LocalModelStatus {
+  available: boolean;
+  path: string;
+  dimension?: number | null;
+  missing_files: string[];
 }
 
-export async function getArticleSnippet(
-  lawName: string | null,
-  articleNumber: string,
-  currentLaw: string
+export async function checkLocalModel(): Promise<LocalModelStatus> {
+  return await invoke<LocalModelStatus>("check_local_model");
+}
+
+// endpoint 可填 HuggingFace 镜像地址
+export async function downloadEmbeddingModel(
+  onProgress?: (progress: ModelDownloadProgress) => void,
+  endpoint?: string
 ): Promise<string> {
+  const unlisten = await listen<ModelDownloadProgress>("embedding-model-progress", (event) => {
+    onProgress?.(event.payload);
+  });
   try {
-    return await invoke<string>("get_article_snippet", {
-      lawNameQuery: lawName,
-      articleNumber: articleNumber,
-      currentLawName: currentLaw,
-    });
-  } catch (e) {
-    return "加载预览失败";
+    return await invoke<string>("download_embedding_model", { endpoint: endpoint || null });
+  } finally {
+    unlisten();
   }
 }
 
-export async function checkDbStatus(): Promise<boolean> {
-  try {
-    return await invoke<boolean>("check_db_status");
-  } catch (e) {
-    console.error("Failed to check db status", e);
-    return false;
-  }
+// --- 检索评测 ---
+
+export interface BenchmarkCaseResult {
+  query: string;
+  expected: string;
+  // 期望条文的名次（从 1 开始），未命中为 null
+  rank: number | null;
+  error: string | null;
 }
 
-// 文件夹选择器 (仅用于设置中更改数据路径)
-import { open } from "@tauri-apps/plugin-dialog";
-export async function selectFolder(): Promise<string | null> {
+export interface RetrievalBenchmarkReport {
+  embedding_provider: string;
+  embedding_model: string;
+  top_k: number;
+  total: number;
+  hit_at_5: number;
+  hit_at_10: number;
+  mrr: number;
+  elapsed_ms: number;
+  cases: BenchmarkCaseResult[];
+  report_path: string | null;
+}
+
+export interface BenchmarkProgress {
+  processed: number;
+  total: number;
+  current_query: string;
+}
+
+// queriesPath 为空时使用内置用例；自定义文件为 [{ query, law_name, article_number }] 格式的 JSON
+export async function runRetrievalBenchmark(
+  queriesPath?: string | null,
+  onProgress?: (progress: BenchmarkProgress) => void
+): Promise<RetrievalBenchmarkReport> {
+  const unlisten = await listen<BenchmarkProgress>("retrieval-benchmark-progress", (event) => {
+    onProgress?.(event.payload);
+  });
   try {
-    const selected = await open({
-      directory: true,
-      multiple: false,
-      title: "选择数据库所在的文件夹",
+    return await invoke<RetrievalBenchmarkReport>("run_retrieval_benchmark", {
+      queriesPath: queriesPath || null,
     });
-    return selected as string | null;
-  } catch (e) {
-    console.error("Select folder failed", e);
-    return null;
+  } finally {
+    unlisten();
   }
 }
 
-export async function stopChat(eventId: string): Promise<void> {
-  return await invoke("stop_chat", { eventId });
+// --- User Data (收藏与历史) ---
+
+// 传入 tag 时只返回带该标签的收藏
+export async function getFavorites(tag?: string | null): Promise<UserFavorite[]> {
+  const page = await getFavoritesPage({ tag });
+  return page.items;
 }
 
-// 素材库 API
-export async function addDraftMaterial(chunk: LawChunk): Promise<void> {
-  return await invoke("add_draft_material", { chunk });
+export interface FavoritesPage {
+  items: UserFavorite[];
+  total: number;
 }
 
-export async function getDraftMaterials(): Promise<DraftMaterial[]> {
-  return await invoke("get_draft_materials");
+// folderId 为 -1 时只返回未分类；sortBy 为 article_number 时同一法规内按条号数值排序，
+// manual 时按 reorderFavorite 调整的顺序
+export async function getFavoritesPage(params: {
+  tag?: string | null;
+  folderId?: number | null;
+  offset?: number;
+  limit?: number | null;
+  sortBy?: "created_at" | "law_name" | "article_number" | "manual";
+  contentMode?: ContentMode;
+}): Promise<FavoritesPage> {
+  return await invoke<FavoritesPage>("get_favorites", {
+    tag: params.tag || null,
+    folderId: params.folderId ?? null,
+    offset: params.offset ?? null,
+    limit: params.limit ?? null,
+    sortBy: params.sortBy ?? null,
+    contentMode: params.contentMode ?? null,
+  });
 }
 
-export async function removeDraftMaterial(lawId: string): Promise<void> {
-  return await invoke("remove_draft_material", { lawId });
+// 收藏只带预览时取完整内容（收藏中保存的文本）
+export async function getFavoriteContent(lawId: string): Promise<string> {
+  return await invoke<string>("get_favorite_content", { lawId });
 }
 
-export async function clearDraftMaterials(): Promise<void> {
-  return await invoke("clear_draft_materials");
+export async function createFolder(
+  name: string,
+  parentId: number | null = null
+): Promise<void> {
+  return await invoke("create_folder", { name, parentId });
+}
+
+// 调整文件夹层级；newParentId 为 null 时移到顶层
+export async function moveFolder(
+  folderId: number,
+  newParentId: number | null
+): Promise<void> {
+  return await invoke("move_folder", { folderId, newParentId });
+}
+
+export async function getFolders(): Promise<UserFolder[]> {
+  return await invoke("get_folders");
+}
+
+// 删除文件夹时收藏的去向，默认移到未分类
+export type DeleteFolderMode =
+  | "move_to_uncategorized"
+  | "move_to_folder"
+  | "delete_contents";
+
+export async function deleteFolder(
+  folderId: number,
+  mode: DeleteFolderMode = "move_to_uncategorized",
+  targetFolderId: number | null = null
+): Promise<void> {
+  return await invoke("delete_folder", { folderId, mode, targetFolderId });
+}
+
+export async function addFavorite(
+  chunk: LawChunk,
+  folderId?: number | null,
+  tags?: string[]
+): Promise<void> {
+  return await invoke("add_favorite", {
+    chunk,
+    folderId: folderId || null,
+    tags: tags ?? null,
+  });
+}
+
+// 收藏时推荐的文件夹，按置信度从高到低，最多 3 个；只是建议，需用户确认后再加入
+export interface FolderSuggestion {
+  folder_id: number;
+  folder_name: string;
+  confidence: number;
+  reason: string;
+  source: "rules" | "ai";
+}
+
+export async function suggestFolder(
+  chunk: LawChunk,
+  tags?: string[]
+): Promise<FolderSuggestion[]> {
+  return await invoke<FolderSuggestion[]>("suggest_folder", {
+    chunk,
+    tags: tags ?? null,
+  });
+}
+
+// 覆盖收藏的标签，返回整理后的标签
+export async function updateFavoriteTags(
+  lawId: string,
+  tags: string[]
+): Promise<string[]> {
+  return await invoke<string[]>("update_favorite_tags", { lawId, tags });
+}
+
+// 收藏时效检查，只返回已修改 (changed) 或已删除 (missing) 的收藏
+export interface FavoriteFreshness {
+  law_id: string;
+  law_name: string;
+  article_number: string;
+  status: "changed" | "missing";
+  current_content: string | null;
+}
+
+export async function checkFavoritesFreshness(): Promise<FavoriteFreshness[]> {
+  return await invoke<FavoriteFreshness[]>("check_favorites_freshness");
+}
+
+// 用当前法规库内容更新收藏，原文保留在 original_content；返回是否有更新
+export async function refreshFavorite(lawId: string): Promise<boolean> {
+  return await invoke<boolean>("refresh_favorite", { lawId });
+}
+
+export async function refreshAllFavorites(): Promise<number> {
+  return await invoke<number>("refresh_all_favorites");
+}
+
+// 设置收藏笔记，传空字符串清除
+export async function setFavoriteNote(lawId: string, note: string): Promise<void> {
+  return await invoke("set_favorite_note", { lawId, note });
+}
+
+// 在笔记、条文内容与法规名称中搜索收藏
+export async function searchFavorites(query: string): Promise<UserFavorite[]> {
+  return await invoke<UserFavorite[]>("search_favorites", { query });
+}
+
+export interface TagCount {
+  tag: string;
+  count: number;
+}
+
+export async function getAllTags(): Promise<TagCount[]> {
+  return await invoke<TagCount[]>("get_all_tags");
+}
+
+// 从 fromFolderId 移到 folderId；不传 fromFolderId 时移出所有文件夹，
+// folderId 为 null 时只移出
+export async function moveFavorite(
+  lawId: string,
+  folderId: number | null,
+  fromFolderId: number | null = null
+): Promise<void> {
+  return await invoke("move_favorite", { lawId, folderId, fromFolderId });
+}
+
+export async function getFavoriteFolders(lawId: string): Promise<UserFolder[]> {
+  return await invoke<UserFolder[]>("get_favorite_folders", { lawId });
+}
+
+// 按引用文本直接收藏，返回新收藏。
+// 本地库中没有该条时抛出 NOT_FOUND，detail 为 CitationNotFound 的 JSON
+export interface CitationNotFound {
+  law_name: string;
+  article_number: string;
+}
+
+export async function favoriteByCitation(
+  citation: string,
+  folderId: number | null = null
+): Promise<UserFavorite> {
+  return await invoke<UserFavorite>("favorite_by_citation", {
+    citation,
+    folderId,
+  });
+}
+
+// 把收藏排到同一文件夹内 afterLawId 之后；afterLawId 为空时排到最前。
+// 收藏在多个文件夹中时需传 folderId
+export async function reorderFavorite(
+  lawId: string,
+  afterLawId: string | null,
+  folderId: number | null = null
+): Promise<void> {
+  return await invoke("reorder_favorite", { lawId, afterLawId, folderId });
+}
+
+// 批量操作结果：skipped 为未生效的条文（已收藏 / 未收藏）
+export interface BulkResult {
+  affected: number;
+  skipped: string[];
+}
+
+export async function addFavoritesBulk(
+  chunks: LawChunk[],
+  folderId: number | null = null
+): Promise<BulkResult> {
+  return await invoke<BulkResult>("add_favorites_bulk", { chunks, folderId });
+}
+
+export async function moveFavoritesBulk(
+  lawIds: string[],
+  folderId: number | null,
+  fromFolderId: number | null = null
+): Promise<BulkResult> {
+  return await invoke<BulkResult>("move_favorites_bulk", {
+    lawIds,
+    folderId,
+    fromFolderId,
+  });
+}
+
+export async function removeFavoritesBulk(lawIds: string[]): Promise<BulkResult> {
+  return await invoke<BulkResult>("remove_favorites_bulk", { lawIds });
+}
+
+export async function removeFavorite(lawId: string): Promise<void> {
+  return await invoke("remove_favorite", { lawId });
+}
+
+export async function checkIsFavorite(lawId: string): Promise<boolean> {
+  return await invoke("check_is_favorite", { lawId });
+}
+
+// 整部法规收藏，与条文收藏相互独立
+export interface LawFavorite {
+  id: number;
+  law_name: string;
+  category: string;
+  region: string;
+  created_at: string;
+  folder_id: number | null;
+  note: string | null;
+}
+
+export async function addLawFavorite(params: {
+  lawName: string;
+  category?: string | null;
+  region?: string | null;
+  folderId?: number | null;
+  note?: string | null;
+}): Promise<void> {
+  return await invoke("add_law_favorite", {
+    lawName: params.lawName,
+    category: params.category ?? null,
+    region: params.region ?? null,
+    folderId: params.folderId ?? null,
+    note: params.note ?? null,
+  });
+}
+
+export async function removeLawFavorite(lawName: string): Promise<void> {
+  return await invoke("remove_law_favorite", { lawName });
+}
+
+export async function getLawFavorites(
+  folderId: number | null = null
+): Promise<LawFavorite[]> {
+  return await invoke<LawFavorite[]>("get_law_favorites", { folderId });
+}
+
+export async function checkIsLawFavorite(lawName: string): Promise<boolean> {
+  return await invoke("check_is_law_favorite", { lawName });
+}
+
+// 导出收藏为 JSON 文件，返回导出条数；folderId 为空时导出全部
+export async function exportFavorites(
+  path: string,
+  folderId: number | null = null
+): Promise<number> {
+  return await invoke<number>("export_favorites", { folderId, path });
+}
+
+// 把文件夹导出为 Markdown 或 Word 文档，返回写入的路径；folderId 为 -1 时导出未分类
+export async function exportFolder(
+  folderId: number,
+  format: "markdown" | "docx",
+  path: string
+): Promise<string> {
+  return await invoke<string>("export_folder", { folderId, format, path });
+}
+
+export interface FavoritesImportReport {
+  imported: number;
+  updated: number;
+  skipped: number;
+  conflicts: string[];
+  missing_locally: string[];
+}
+
+// merge：跳过已有条文；replace：用导入内容覆盖
+export async function importFavorites(
+  path: string,
+  strategy: "merge" | "replace" = "merge"
+): Promise<FavoritesImportReport> {
+  return await invoke<FavoritesImportReport>("import_favorites", {
+    path,
+    strategy,
+  });
+}
+
+// 搜索与深度思考完成后由后端自动记录历史，此接口仅为兼容保留
+export async function addHistory(query: string): Promise<void> {
+  return await invoke("add_history", { query });
+}
+
+// 旧接口：只返回查询文本
+export async function getHistory(): Promise<string[]> {
+  return await invoke("get_history_strings");
+}
+
+export interface SearchHistoryItem {
+  id: number;
+  query: string;
+  // Unix 时间戳 (秒)
+  timestamp: number;
+  // 旧版本写入的记录为 null
+  search_type: "search" | "agent" | null;
+  result_count: number | null;
+  // 置顶记录排在最前，且不受条数上限清理
+  pinned: boolean;
+  // 搜索次数，用于显示“搜索过 N 次”
+  use_count: number;
+}
+
+export async function getHistoryItems(): Promise<SearchHistoryItem[]> {
+  return await invoke<SearchHistoryItem[]>("get_history");
+}
+
+export async function deleteHistoryItem(id: number): Promise<void> {
+  return await invoke("delete_history_item", { id });
+}
+
+// keepPinned 为 true 时保留置顶记录
+export async function clearHistory(keepPinned = true): Promise<void> {
+  return await invoke("clear_history", { keepPinned });
+}
+
+// 按前缀查找历史，综合最近使用时间与搜索次数排序
+export async function searchHistory(
+  prefix: string,
+  limit = 10
+): Promise<SearchHistoryItem[]> {
+  return await invoke<SearchHistoryItem[]>("search_history", { prefix, limit });
+}
+
+// 启动预热结果，通过 warmup-complete 事件推送
+export interface WarmupReport {
+  steps: { name: string; millis: number; error: string | null }[];
+  total_ms: number;
+}
+
+// 使用统计
+export interface UsageStats {
+  searches_per_day: { day: string; count: number }[];
+  top_laws: { name: string; count: number }[];
+  top_queries: { name: string; count: number }[];
+}
+
+export async function getUsageStats(days = 30): Promise<UsageStats> {
+  return await invoke<UsageStats>("get_usage_stats", { days });
+}
+
+export async function clearUsageStats(): Promise<void> {
+  return await invoke("clear_usage_stats");
+}
+
+export async function pinHistoryItem(id: number, pinned: boolean): Promise<void> {
+  return await invoke("pin_history_item", { id, pinned });
+}
+
+// --- 系统与配置 ---
+
+export async function getFullText(
+  source_file: string,
+  sourceName?: string | null
+): Promise<FullTextResponse> {
+  try {
+    const content = await invoke<string>("get_full_text", {
+      sourceFile: source_file,
+      sourceName: sourceName ?? null,
+    });
+    return { source_file, content };
+  } catch (error) {
+    console.error("Get full text failed:", error);
+    throw error;
+  }
+}
+
+// 按原文顺序返回全部条文，供阅读视图定位到具体条文
+export async function getFullTextChunks(
+  source_file: string,
+  sourceName?: string | null
+): Promise<LawChunk[]> {
+  return await invoke<LawChunk[]>("get_full_text", {
+    sourceFile: source_file,
+    structured: true,
+    sourceName: sourceName ?? null,
+  });
+}
+
+// 法规目录：level 1 编 / 2 章 / 3 节
+export interface TocNode {
+  title: string;
+  level: number;
+  first_article: string | null;
+  chunk_id: string | null;
+  children: TocNode[];
+}
+
+export async function getLawToc(lawName: string): Promise<TocNode[]> {
+  return await invoke<TocNode[]>("get_law_toc", { lawName });
+}
+
+// 单部法规内的关键词检索；start/end 为全文中的字节偏移
+export interface FullTextMatch {
+  start: number;
+  end: number;
+  before: string;
+  matched: string;
+  after: string;
+  article_number: string | null;
+  chunk_id: string | null;
+}
+
+export interface FullTextSearchResult {
+  law_name: string;
+  matches: FullTextMatch[];
+  truncated: boolean;
+}
+
+export async function searchInFullText(
+  lawName: string,
+  keyword: string
+): Promise<FullTextSearchResult> {
+  return await invoke<FullTextSearchResult>("search_in_full_text", {
+    lawName,
+    keyword,
+  });
+}
+
+export async function getSettings(): Promise<AppSettings> {
+  return await invoke<AppSettings>("get_settings");
+}
+
+// 启动阶段的问题（如设置文件损坏已备份）
+export async function getStartupWarnings(): Promise<string[]> {
+  return await invoke<string[]>("get_startup_warnings");
+}
+
+// 设置校验结果：error 会阻止保存，warning 仅提示
+export interface ValidationIssue {
+  field: string;
+  severity: "error" | "warning";
+  message: string;
+}
+
+export async function validateSettings(settings: AppSettings): Promise<ValidationIssue[]> {
+  return await invoke<ValidationIssue[]>("validate_settings", { settings });
+}
+
+// 保存成功时返回警告列表
+export async function saveSettings(settings: any): Promise<ValidationIssue[]> {
+  return await invoke<ValidationIssue[]>("save_settings", { newSettings: settings });
+}
+
+// 只更新传入的字段，返回合并后的完整设置
+export async function updateSettingsPartial(
+  patch: Partial<AppSettings>
+): Promise<AppSettings> {
+  return await invoke<AppSettings>("update_settings_partial", { patch });
+}
+
+// 恢复默认设置；keepPaths 为 true 时保留自定义数据路径
+export async function resetSettings(keepPaths: boolean = true): Promise<AppSettings> {
+  return await invoke<AppSettings>("reset_settings", { keepPaths });
+}
+
+export async function exportSettings(path: string, redactKeys: boolean = true): Promise<void> {
+  return await invoke("export_settings", { path, redactKeys });
+}
+
+export interface SettingsImportReport {
+  applied: string[];
+  rejected: { field: string; reason: string }[];
+}
+
+// 逐字段导入，返回已应用与被拒绝的字段
+export async function importSettings(path: string): Promise<SettingsImportReport> {
+  return await invoke<SettingsImportReport>("import_settings", { path });
+}
+
+// settings-changed 事件：列出本次变更的设置字段
+export interface SettingsChangedEvent {
+  changed_fields: string[];
+}
+
+// 模型服务配置方案（嵌入 + 对话服务的地址、Key、模型）
+export interface SettingsProfile {
+  name: string;
+  embedding_provider: "api" | "local";
+  embedding_base_url: string;
+  embedding_api_key: string;
+  embedding_model: string;
+  embedding_api_format: "openai" | "ollama";
+  chat_base_url: string;
+  chat_api_key: string;
+  chat_model: string;
+  chat_api_format: "openai" | "anthropic" | "ollama";
+}
+
+export async function saveSettingsProfile(name: string): Promise<void> {
+  return await invoke("save_settings_profile", { name });
+}
+
+export async function listSettingsProfiles(): Promise<SettingsProfile[]> {
+  return await invoke<SettingsProfile[]>("list_settings_profiles");
+}
+
+// 应用后返回新的完整设置，变更的字段通过 settings-changed 事件广播
+export async function applySettingsProfile(name: string): Promise<AppSettings> {
+  return await invoke<AppSettings>("apply_settings_profile", { name });
+}
+
+export async function deleteSettingsProfile(name: string): Promise<void> {
+  return await invoke("delete_settings_profile", { name });
+}
+
+// 删除系统凭据管理器中保存的 API Key
+export async function clearStoredCredentials(): Promise<void> {
+  return await invoke("clear_stored_credentials");
+}
+
+export async function getArticleSnippet(
+  lawName: string | null,
+  articleNumber: string,
+  currentLaw: string,
+  sourceName?: string | null
+): Promise<string> {
+  try {
+    return await invoke<string>("get_article_snippet", {
+      lawNameQuery: lawName,
+      articleNumber: articleNumber,
+      currentLawName: currentLaw,
+      sourceName: sourceName ?? null,
+    });
+  } catch (e) {
+    return "加载预览失败";
+  }
+}
+
+// brief: 《民法典》第五百七十七条；full: 法规名 + 条号 + 引号包裹的全文；markdown: 引用块
+export type CitationStyle = "brief" | "full" | "markdown";
+
+// 生成引用文本，供复制到剪贴板
+export async function formatCitation(
+  chunkIds: string[],
+  style: CitationStyle
+): Promise<string> {
+  return await invoke<string>("format_citation", { chunkIds, style });
+}
+
+export interface DbStatus {
+  data_dir: string;
+  data_dir_exists: boolean;
+  lancedb_present: boolean;
+  table_openable: boolean;
+  row_count: number | null;
+  vector_error: string | null;
+  content_db_present: boolean;
+  chunks_count: number | null;
+  content_error: string | null;
+  schema_issues: string[];
+  ready: boolean;
+}
+
+export async function getDbStatusDetailed(): Promise<DbStatus> {
+  return await invoke<DbStatus>("get_db_status_detailed");
+}
+
+// 数据库不可用的具体原因，按排查顺序取第一项；可用时返回 null
+export function describeDbStatus(status: DbStatus): string | null {
+  if (status.ready) return null;
+  if (!status.data_dir_exists) return `数据目录不存在：${status.data_dir}`;
+  if (!status.content_db_present) return `数据目录中缺少 content.db：${status.data_dir}`;
+  if (!status.lancedb_present) return `数据目录中缺少向量索引 law_db.lancedb：${status.data_dir}`;
+  if (status.content_error) return `无法读取 content.db：${status.content_error}`;
+  if (!status.table_openable || status.vector_error)
+    return `无法打开向量索引：${status.vector_error ?? "未知错误"}`;
+  if (status.schema_issues.length > 0) return status.schema_issues.join("；");
+  if (!status.chunks_count) return "content.db 中没有条文数据";
+  if (!status.row_count) return "向量索引为空，请重建向量库";
+  return "数据库不可用";
+}
+
+export async function checkDbStatus(): Promise<boolean> {
+  try {
+    return await invoke<boolean>("check_db_status");
+  } catch (e) {
+    console.error("Failed to check db status", e);
+    return false;
+  }
+}
+
+// 日志：读取最近的日志行 / 在文件管理器中打开日志目录
+// 命令耗时统计，只保存在本机内存中
+export interface CommandMetricsSnapshot {
+  command: string;
+  count: number;
+  error_count: number;
+  p50_ms: number;
+  p95_ms: number;
+  max_ms: number;
+}
+
+export interface MetricsReport {
+  since_secs: number;
+  commands: CommandMetricsSnapshot[];
+}
+
+export async function getMetricsSnapshot(): Promise<MetricsReport> {
+  return await invoke<MetricsReport>("get_metrics_snapshot");
+}
+
+export async function resetMetrics(): Promise<void> {
+  return await invoke("reset_metrics");
+}
+
+export async function getRecentLogs(lines: number = 200): Promise<string[]> {
+  return await invoke<string[]>("get_recent_logs", { lines });
+}
+
+export async function openLogFolder(): Promise<void> {
+  return await invoke("open_log_folder");
+}
+
+// 文件夹选择器 (仅用于设置中更改数据路径)
+import { open } from "@tauri-apps/plugin-dialog";
+export async function selectFolder(): Promise<string | null> {
+  try {
+    const selected = await open({
+      directory: true,
+      multiple: false,
+      title: "选择数据库所在的文件夹",
+    });
+    return selected as string | null;
+  } catch (e) {
+    console.error("Select folder failed", e);
+    return null;
+  }
+}
+
+export async function stopChat(eventId: string): Promise<void> {
+  return await invoke("stop_chat", { eventId });
+}
+
+// 素材库 API
+export async function addDraftMaterial(chunk: LawChunk): Promise<void> {
+  return await invoke("add_draft_material", { chunk });
+}
+
+export async function getDraftMaterials(): Promise<DraftMaterial[]> {
+  return await invoke("get_draft_materials");
+}
+
+export async function removeDraftMaterial(lawId: string): Promise<void> {
+  return await invoke("remove_draft_material", { lawId });
+}
+
+export async function clearDraftMaterials(): Promise<void> {
+  return await invoke("clear_draft_materials");
 }
 
 // 模版 API
@@ -337,3 +1650,331 @@ export async function getTemplates(): Promise<CustomTemplate[]> {
 export async function deleteTemplate(id: number): Promise<void> {
   return await invoke("delete_template", { id });
 }
+
+// 本地 HTTP API
+export interface ApiServerStatus {
+  running: boolean;
+  port: number | null;
+  token: string;
+}
+
+export async function startApiServer(): Promise<ApiServerStatus> {
+  return await invoke<ApiServerStatus>("start_api_server");
+}
+
+export async function stopApiServer(): Promise<ApiServerStatus> {
+  return await invoke<ApiServerStatus>("stop_api_server");
+}
+
+export async function getApiServerStatus(): Promise<ApiServerStatus> {
+  return await invoke<ApiServerStatus>("get_api_server_status");
+}
+
+// lawvault:// 链接
+export type NavigateTarget =
+  | { kind: "article"; chunk: LawChunk }
+  | { kind: "law"; law: LawNameSuggestion }
+  | { kind: "search"; query: string };
+
+export interface DeepLinkError {
+  url: string;
+  message: string;
+}
+
+// 工作区：跨多次检索暂存的条文，重启后保留；改动后广播 workspace-changed
+export interface WorkspaceChangedEvent {
+  count: number;
+}
+
+export async function workspaceAdd(chunk: LawChunk): Promise<number> {
+  return await invoke<number>("workspace_add", { chunk });
+}
+
+export async function workspaceRemove(chunkId: string): Promise<number> {
+  return await invoke<number>("workspace_remove", { chunkId });
+}
+
+export async function workspaceList(): Promise<LawChunk[]> {
+  return await invoke<LawChunk[]>("workspace_list");
+}
+
+export async function workspaceClear(): Promise<void> {
+  return await invoke("workspace_clear");
+}
+
+// 工作区条文，可直接作为 chatStream 的 contextChunks
+export async function workspaceSendToChat(): Promise<ChatContextChunk[]> {
+  return await invoke<ChatContextChunk[]>("workspace_send_to_chat");
+}
+
+// 条文对比：逐字计算，相邻的同类改动已合并；单侧上限 20000 字
+export interface DiffHunk {
+  op: "equal" | "insert" | "delete";
+  text: string;
+}
+
+export async function diffTexts(left: string, right: string): Promise<DiffHunk[]> {
+  return await invoke<DiffHunk[]>("diff_texts", { left, right });
+}
+
+export async function diffChunks(leftId: string, rightId: string): Promise<DiffHunk[]> {
+  return await invoke<DiffHunk[]>("diff_chunks", { leftId, rightId });
+}
+
+// 引用核对：start/end 为 UTF-16 偏移，可直接对原文 slice
+export type CitationCheckStatus = "found" | "article_missing" | "law_not_found";
+
+export interface CitationCheck {
+  text: string;
+  start: number;
+  end: number;
+  law_name: string;
+  article_number: string;
+  status: CitationCheckStatus;
+  matched_law_name: string | null;
+  chunk: LawChunk | null;
+}
+
+export interface CitationCheckReport {
+  citations: CitationCheck[];
+  found: number;
+  article_missing: number;
+  law_not_found: number;
+}
+
+export async function checkCitationsInText(
+  text: string
+): Promise<CitationCheckReport> {
+  return await invoke<CitationCheckReport>("check_citations_in_text", { text });
+}
+
+// 术语释义；source 为 curated（释义表）/ pattern（条文定义句）/ vector（语义检索），
+// confidence 为 low 时给出的只是提到该术语的条文，不一定是定义
+export interface TermDefinition {
+  term: string;
+  definition: string;
+  source: "curated" | "pattern" | "vector";
+  confidence: "high" | "medium" | "low";
+  chunk: LawChunk | null;
+}
+
+export interface DefinedTerm {
+  term: string;
+  definition: string;
+  article_number: string;
+  chunk_id: string;
+}
+
+export async function lookupTermDefinition(term: string): Promise<TermDefinition> {
+  return await invoke<TermDefinition>("lookup_term_definition", { term });
+}
+
+export async function extractDefinedTerms(lawName: string): Promise<DefinedTerm[]> {
+  return await invoke<DefinedTerm[]>("extract_defined_terms", { lawName });
+}
+
+// 返回导入的条数
+export async function importTermDefinitions(path: string): Promise<number> {
+  return await invoke<number>("import_term_definitions", { path });
+}
+
+// 向量库与当前嵌入模型不一致（index-model-mismatch 事件），需重建向量库或换回原模型
+export interface IndexModelMismatch {
+  index_model: string;
+  configured_model: string;
+  built_at: string;
+}
+
+// 剪贴板中识别到的引用（citation-detected 事件）
+export interface CitationDetectedEvent {
+  citation: string;
+  chunk: LawChunk;
+}
+
+// 条文的分享链接，如 lawvault://law/民法典/第五百条
+export async function makeDeepLink(chunkId: string): Promise<string> {
+  return await invoke<string>("make_deep_link", { chunkId });
+}
+
+// 通过链接启动程序时的跳转目标，只能取一次
+export async function takePendingNavigation(): Promise<NavigateTarget | null> {
+  return await invoke<NavigateTarget | null>("take_pending_navigation");
+}
+
+// 搜索结果导出为表格，返回写入的行数
+export async function exportSearchResults(
+  chunks: LawChunk[],
+  format: "csv" | "xlsx",
+  path: string
+): Promise<number> {
+  return await invoke<number>("export_search_results", { chunks, format, path });
+}
+
+// 法规全文导出 PDF 的选项
+export interface PdfOptions {
+  font_size?: number;
+  include_publish_date?: boolean;
+  cover_line?: string | null;
+  // 中文字体文件，为空时自动查找数据目录 fonts/ 及系统字体
+  font_path?: string | null;
+}
+
+// 返回生成的页数
+export async function exportLawPdf(
+  lawName: string,
+  path: string,
+  options: PdfOptions = {}
+): Promise<number> {
+  return await invoke<number>("export_law_pdf", { lawName, path, options });
+}
+
+// 快速查询小窗
+export interface QuickLookupItem {
+  chunk_id: string;
+  citation: string;
+  law_name: string;
+  article_number: string;
+  excerpt: string;
+  truncated: boolean;
+}
+
+export interface QuickLookupResult {
+  // true 表示按引用精确命中，false 为语义检索结果
+  exact: boolean;
+  items: QuickLookupItem[];
+  // 嵌入服务不可用、结果来自关键词匹配时为原因
+  degraded_reason: string | null;
+}
+
+export async function quickLookup(text: string): Promise<QuickLookupResult> {
+  return await invoke<QuickLookupResult>("quick_lookup", { text });
+}
+
+// 全文批注；偏移为全文的 UTF-16 位置，即 JS 字符串下标
+export type AnnotationColor = "yellow" | "green" | "blue" | "pink" | "purple";
+
+export interface Annotation {
+  id: number;
+  law_name: string;
+  start_offset: number;
+  end_offset: number;
+  quote: string;
+  color: AnnotationColor;
+  note: string | null;
+  created_at: string;
+  // 法规更新后找不到原文的批注
+  orphaned: boolean;
+}
+
+export interface AnnotationReconcileReport {
+  checked: number;
+  relocated: number;
+  orphaned: number[];
+}
+
+export async function addAnnotation(
+  lawName: string,
+  startOffset: number,
+  endOffset: number,
+  color?: AnnotationColor,
+  note?: string
+): Promise<Annotation> {
+  return await invoke<Annotation>("add_annotation", {
+    lawName,
+    startOffset,
+    endOffset,
+    color,
+    note,
+  });
+}
+
+export async function getAnnotations(lawName: string): Promise<Annotation[]> {
+  return await invoke<Annotation[]>("get_annotations", { lawName });
+}
+
+// 未传的字段保持不变，note 传空字符串清除
+export async function updateAnnotation(
+  id: number,
+  changes: { color?: AnnotationColor; note?: string }
+): Promise<Annotation> {
+  return await invoke<Annotation>("update_annotation", { id, ...changes });
+}
+
+export async function deleteAnnotation(id: number): Promise<void> {
+  await invoke("delete_annotation", { id });
+}
+
+export async function reconcileAnnotations(
+  lawName: string
+): Promise<AnnotationReconcileReport> {
+  return await invoke<AnnotationReconcileReport>("reconcile_annotations", {
+    lawName,
+  });
+}
+
+// 条文互引索引（cross-ref-progress 事件）
+export interface CrossRefProgress {
+  processed: number;
+  total: number;
+  current_law: string;
+}
+
+export interface CrossRefIndexStats {
+  scanned_laws: number;
+  new_edges: number;
+  resolved_pending: number;
+  unresolved: number;
+}
+
+export async function buildCrossRefs(): Promise<CrossRefIndexStats> {
+  return await invoke<CrossRefIndexStats>("build_cross_refs");
+}
+
+// 本条引用的条文
+export async function getOutgoingRefs(chunkId: string): Promise<LawChunk[]> {
+  return await invoke<LawChunk[]>("get_outgoing_refs", { chunkId });
+}
+
+// 引用了本条的条文
+export async function getIncomingRefs(chunkId: string): Promise<LawChunk[]> {
+  return await invoke<LawChunk[]>("get_incoming_refs", { chunkId });
+}
+
+// 收藏复习
+export interface ReviewSchedule {
+  ease: number;
+  interval_days: number;
+  repetitions: number;
+  // 下次复习时间（Unix 秒）
+  due_at: number;
+}
+
+export interface ReviewCard {
+  law_id: string;
+  law_name: string;
+  article_number: string;
+  content: string;
+  // 条号替换为下划线的内容，翻面前展示
+  masked_content: string;
+  note: string | null;
+  schedule: ReviewSchedule;
+}
+
+// 0 = 忘记，1 = 困难，2 = 记得，3 = 轻松
+export type ReviewGrade = 0 | 1 | 2 | 3;
+
+export async function getDueReviews(limit?: number): Promise<ReviewCard[]> {
+  return await invoke<ReviewCard[]>("get_due_reviews", { limit });
+}
+
+export async function submitReview(
+  lawId: string,
+  grade: ReviewGrade
+): Promise<ReviewSchedule> {
+  return await invoke<ReviewSchedule>("submit_review", { lawId, grade });
+}
+
+// 不传 lawIds 时把全部收藏加入复习计划，返回新加入的数量
+export async function enrollReviews(lawIds?: string[]): Promise<number> {
+  return await invoke<number>("enroll_reviews", { lawIds });
+}